@@ -0,0 +1,464 @@
+//! Attachment staging
+//!
+//! Copies message attachments out of ~/Library/Messages/Attachments into the
+//! session's transcript directory so Claude can read them, and describes them
+//! for the injected prompt. HEIC photos are converted to JPEG with `sips`.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::messages::Attachment;
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, warn};
+
+/// An attachment after staging into a transcript directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagedAttachment {
+    pub name: String,
+    pub mime_type: String,
+    pub size: i64,
+    /// Local copy, or None if the source isn't on disk yet (iCloud download pending)
+    pub local_path: Option<PathBuf>,
+}
+
+/// Copies attachments into transcript directories
+pub struct AttachmentHandler {
+    sips: PathBuf,
+}
+
+impl AttachmentHandler {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            sips: config.sips.clone(),
+        }
+    }
+
+    /// Copy a message's attachments into `<transcript_dir>/attachments/<rowid>-<name>`
+    pub fn stage(
+        &self,
+        transcript_dir: &Path,
+        rowid: i64,
+        attachments: &[Attachment],
+    ) -> Result<Vec<StagedAttachment>> {
+        if attachments.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let dest_dir = transcript_dir.join("attachments");
+        fs::create_dir_all(&dest_dir)?;
+
+        let mut staged = Vec::with_capacity(attachments.len());
+        for attachment in attachments {
+            let dest = dest_dir.join(format!("{}-{}", rowid, sanitize_name(&attachment.name)));
+
+            let local_path = if dest.exists() {
+                // Already staged on an earlier pass
+                Some(dest)
+            } else {
+                match fs::copy(&attachment.path, &dest) {
+                    Ok(_) => {
+                        debug!("Staged attachment {} -> {}", attachment.path, dest.display());
+                        Some(dest)
+                    }
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        warn!("Attachment not downloaded yet: {}", attachment.path);
+                        None
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            };
+
+            let mut entry = StagedAttachment {
+                name: attachment.name.clone(),
+                mime_type: attachment.mime_type.clone(),
+                size: attachment.size,
+                local_path,
+            };
+
+            // Claude can't read HEIC; hand it a JPEG next to the original instead
+            if let Some(original) = entry.local_path.clone().filter(|p| is_heic(&entry.mime_type, p)) {
+                match self.convert_to_jpeg(&original) {
+                    Ok(jpeg) => {
+                        entry.size = fs::metadata(&jpeg).map(|m| m.len() as i64).unwrap_or(entry.size);
+                        entry.mime_type = "image/jpeg".to_string();
+                        entry.local_path = Some(jpeg);
+                    }
+                    Err(e) => warn!("HEIC conversion failed for {}, using original: {}", original.display(), e),
+                }
+            }
+
+            staged.push(entry);
+        }
+
+        Ok(staged)
+    }
+
+    /// Convert a HEIC image to a JPEG alongside it, returning the JPEG path
+    fn convert_to_jpeg(&self, source: &Path) -> Result<PathBuf> {
+        let jpeg = source.with_extension("jpg");
+        if jpeg.exists() {
+            return Ok(jpeg);
+        }
+
+        let output = Command::new(&self.sips)
+            .args(["-s", "format", "jpeg"])
+            .arg(source)
+            .arg("--out")
+            .arg(&jpeg)
+            .output()?;
+
+        if !output.status.success() || !jpeg.exists() {
+            return Err(Error::CommandFailed(format!(
+                "sips exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(jpeg)
+    }
+}
+
+/// Whether an attachment is a HEIC/HEIF image, by mime type or extension
+fn is_heic(mime_type: &str, path: &Path) -> bool {
+    let mime = mime_type.to_ascii_lowercase();
+    if mime == "image/heic" || mime == "image/heif" {
+        return true;
+    }
+    path.extension()
+        .map(|e| e.eq_ignore_ascii_case("heic") || e.eq_ignore_ascii_case("heif"))
+        .unwrap_or(false)
+}
+
+/// Bytes read from the start of a file to identify it
+const SNIFF_BYTES: usize = 512;
+
+/// Guess a mime type for an attachment Messages didn't label
+///
+/// Magic numbers first, then UTF-8 text, then the extension. Zip containers
+/// (docx, pkpass, ...) defer to the extension. A file that isn't on disk yet
+/// is guessed from its extension alone, or left "unknown".
+pub fn sniff_mime(path: &Path) -> String {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    let read = fs::File::open(path).and_then(|f| f.take(SNIFF_BYTES as u64).read_to_end(&mut head));
+    let by_extension = mime_from_extension(path);
+    if read.is_err() {
+        return by_extension.unwrap_or("unknown").to_string();
+    }
+
+    if let Some(mime) = mime_from_magic(&head) {
+        if mime == "application/zip" {
+            return by_extension.unwrap_or(mime).to_string();
+        }
+        return mime.to_string();
+    }
+    if looks_like_text(&head) {
+        return by_extension
+            .filter(|m| m.starts_with("text/") || *m == "application/json")
+            .unwrap_or("text/plain")
+            .to_string();
+    }
+    by_extension.unwrap_or("application/octet-stream").to_string()
+}
+
+fn mime_from_magic(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
+    }
+    if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if head.starts_with(b"%PDF-") {
+        return Some("application/pdf");
+    }
+    if head.starts_with(b"PK\x03\x04") {
+        return Some("application/zip");
+    }
+    // ISO base media: size, "ftyp", major brand
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return match &head[8..12] {
+            b"heic" | b"heix" | b"heim" | b"heis" => Some("image/heic"),
+            b"mif1" | b"msf1" | b"heif" => Some("image/heif"),
+            b"qt  " => Some("video/quicktime"),
+            b"M4A " => Some("audio/mp4"),
+            _ => Some("video/mp4"),
+        };
+    }
+    None
+}
+
+/// Valid UTF-8 without NULs, allowing a character cut off at the end of the sample
+fn looks_like_text(head: &[u8]) -> bool {
+    if head.is_empty() || head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() == SNIFF_BYTES,
+    }
+}
+
+fn mime_from_extension(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let mime = match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "heic" => "image/heic",
+        "heif" => "image/heif",
+        "gif" => "image/gif",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "vcf" => "text/vcard",
+        "json" => "application/json",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pkpass" => "application/vnd.apple.pkpass",
+        "mov" => "video/quicktime",
+        "mp4" => "video/mp4",
+        "m4a" => "audio/mp4",
+        "mp3" => "audio/mpeg",
+        "caf" => "audio/x-caf",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+/// Describe staged attachments for the wrapped prompt
+pub fn prompt_block(staged: &[StagedAttachment]) -> String {
+    let mut block = String::from("Attachments:");
+    for attachment in staged {
+        match &attachment.local_path {
+            Some(path) => block.push_str(&format!(
+                "\n- {} ({}, {})",
+                path.display(),
+                attachment.mime_type,
+                format_size(attachment.size)
+            )),
+            None => block.push_str(&format!(
+                "\n- {} ({}) \u{2014} attachment pending download",
+                attachment.name, attachment.mime_type
+            )),
+        }
+    }
+    block
+}
+
+/// Keep only the final path component so a transfer name can't escape the directory
+fn sanitize_name(name: &str) -> String {
+    let base = Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if base.is_empty() {
+        "attachment".to_string()
+    } else {
+        base
+    }
+}
+
+fn format_size(bytes: i64) -> String {
+    const KB: f64 = 1024.0;
+    const MB: f64 = KB * 1024.0;
+    let b = bytes as f64;
+    if b >= MB {
+        format!("{:.1} MB", b / MB)
+    } else if b >= KB {
+        format!("{:.1} KB", b / KB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn attachment(path: &Path, name: &str, mime: &str, size: i64) -> Attachment {
+        Attachment {
+            path: path.to_string_lossy().to_string(),
+            mime_type: mime.to_string(),
+            name: name.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_stage_copies_into_transcript_dir() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("IMG_0001.jpeg");
+        fs::write(&source, b"jpeg bytes").unwrap();
+        let transcript_dir = temp.path().join("transcripts/jane-doe");
+
+        let handler = AttachmentHandler::new(&Config::for_test(temp.path()));
+        let staged = handler
+            .stage(&transcript_dir, 42, &[attachment(&source, "IMG_0001.jpeg", "image/jpeg", 10)])
+            .unwrap();
+
+        let expected = transcript_dir.join("attachments/42-IMG_0001.jpeg");
+        assert_eq!(staged.len(), 1);
+        assert_eq!(staged[0].local_path.as_deref(), Some(expected.as_path()));
+        assert_eq!(fs::read(&expected).unwrap(), b"jpeg bytes");
+    }
+
+    #[test]
+    fn test_stage_missing_source_is_pending() {
+        let temp = TempDir::new().unwrap();
+        let missing = temp.path().join("not-downloaded.heic");
+        let transcript_dir = temp.path().join("transcripts/jane-doe");
+
+        let handler = AttachmentHandler::new(&Config::for_test(temp.path()));
+        let staged = handler
+            .stage(&transcript_dir, 7, &[attachment(&missing, "IMG_7.heic", "image/heic", 2048)])
+            .unwrap();
+
+        assert_eq!(staged.len(), 1);
+        assert!(staged[0].local_path.is_none());
+        assert!(prompt_block(&staged).contains("IMG_7.heic (image/heic) \u{2014} attachment pending download"));
+    }
+
+    /// Stand-in for sips that copies its input to the --out path
+    fn fake_sips(dir: &Path) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let script = dir.join("sips");
+        fs::write(&script, "#!/bin/sh\n# sips -s format jpeg <in> --out <out>\ncp \"$4\" \"$6\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+
+    #[test]
+    fn test_heic_converted_to_jpeg() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("IMG_0002.HEIC");
+        fs::write(&source, b"heic bytes").unwrap();
+        let transcript_dir = temp.path().join("transcripts/jane-doe");
+
+        let mut config = Config::for_test(temp.path());
+        config.sips = fake_sips(temp.path());
+        let handler = AttachmentHandler::new(&config);
+        // Mime type is sometimes missing; the extension is enough
+        let staged = handler
+            .stage(&transcript_dir, 9, &[attachment(&source, "IMG_0002.HEIC", "unknown", 10)])
+            .unwrap();
+
+        let jpeg = transcript_dir.join("attachments/9-IMG_0002.jpg");
+        assert_eq!(staged[0].local_path.as_deref(), Some(jpeg.as_path()));
+        assert_eq!(staged[0].mime_type, "image/jpeg");
+        assert!(jpeg.exists());
+        // Original is kept alongside
+        assert!(transcript_dir.join("attachments/9-IMG_0002.HEIC").exists());
+    }
+
+    #[test]
+    fn test_heic_conversion_failure_falls_back_to_original() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("IMG_0003.heic");
+        fs::write(&source, b"heic bytes").unwrap();
+        let transcript_dir = temp.path().join("transcripts/jane-doe");
+
+        // Config::for_test points sips at a path that doesn't exist
+        let handler = AttachmentHandler::new(&Config::for_test(temp.path()));
+        let staged = handler
+            .stage(&transcript_dir, 3, &[attachment(&source, "IMG_0003.heic", "image/heic", 10)])
+            .unwrap();
+
+        let original = transcript_dir.join("attachments/3-IMG_0003.heic");
+        assert_eq!(staged[0].local_path.as_deref(), Some(original.as_path()));
+        assert_eq!(staged[0].mime_type, "image/heic");
+    }
+
+    #[test]
+    fn test_is_heic() {
+        assert!(is_heic("image/heic", Path::new("a.bin")));
+        assert!(is_heic("image/HEIF", Path::new("a")));
+        assert!(is_heic("", Path::new("IMG_1.HEIC")));
+        assert!(!is_heic("image/jpeg", Path::new("IMG_1.jpeg")));
+    }
+
+    #[test]
+    fn test_sniff_mime_magic() {
+        let temp = TempDir::new().unwrap();
+        let cases: [(&str, &[u8], &str); 6] = [
+            ("photo", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "image/png"),
+            ("photo.bin", b"\xFF\xD8\xFF\xE0\0\x10JFIF", "image/jpeg"),
+            ("IMG_1", b"\0\0\0\x18ftypheic\0\0\0\0mif1heic", "image/heic"),
+            ("scan", b"%PDF-1.7\n%\xE2\xE3\xCF\xD3", "application/pdf"),
+            ("archive", b"PK\x03\x04\x14\0\0\0", "application/zip"),
+            // Magic wins over a wrong extension
+            ("misnamed.txt", b"%PDF-1.4\n", "application/pdf"),
+        ];
+        for (name, bytes, expected) in cases {
+            let path = temp.path().join(name);
+            fs::write(&path, bytes).unwrap();
+            assert_eq!(sniff_mime(&path), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_sniff_mime_text_and_fallbacks() {
+        let temp = TempDir::new().unwrap();
+        let write = |name: &str, bytes: &[u8]| {
+            let path = temp.path().join(name);
+            fs::write(&path, bytes).unwrap();
+            path
+        };
+
+        assert_eq!(sniff_mime(&write("notes", "caf\u{e9} at 8? \u{1f355}".as_bytes())), "text/plain");
+        assert_eq!(sniff_mime(&write("guests.csv", b"name,phone\nJane,+16175551234\n")), "text/csv");
+        // Zip containers keep their more specific type
+        assert_eq!(
+            sniff_mime(&write("Report.docx", b"PK\x03\x04\x14\0\x06\0")),
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        );
+        // Binary without magic falls back to the extension
+        assert_eq!(sniff_mime(&write("Voice.caf", b"caff\0\x01\0\0desc")), "audio/x-caf");
+        assert_eq!(sniff_mime(&write("blob", b"\x01\x02\0\xFE")), "application/octet-stream");
+
+        // A character split at the sample boundary is still text
+        let mut long = "a".repeat(SNIFF_BYTES - 1).into_bytes();
+        long.extend_from_slice("\u{e9}".as_bytes());
+        assert_eq!(sniff_mime(&write("long", &long)), "text/plain");
+
+        // Not downloaded yet: extension only
+        assert_eq!(sniff_mime(&temp.path().join("pending.heic")), "image/heic");
+        assert_eq!(sniff_mime(&temp.path().join("pending")), "unknown");
+    }
+
+    #[test]
+    fn test_sanitize_name_strips_directories() {
+        assert_eq!(sanitize_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_name("photo.jpg"), "photo.jpg");
+        assert_eq!(sanitize_name(""), "attachment");
+    }
+
+    #[test]
+    fn test_prompt_block() {
+        let staged = vec![
+            StagedAttachment {
+                name: "IMG_1.jpeg".to_string(),
+                mime_type: "image/jpeg".to_string(),
+                size: 1536,
+                local_path: Some(PathBuf::from("/t/attachments/5-IMG_1.jpeg")),
+            },
+            StagedAttachment {
+                name: "menu.pdf".to_string(),
+                mime_type: "application/pdf".to_string(),
+                size: 3 * 1024 * 1024,
+                local_path: Some(PathBuf::from("/t/attachments/5-menu.pdf")),
+            },
+        ];
+        assert_eq!(
+            prompt_block(&staged),
+            "Attachments:\n- /t/attachments/5-IMG_1.jpeg (image/jpeg, 1.5 KB)\n- /t/attachments/5-menu.pdf (application/pdf, 3.0 MB)"
+        );
+    }
+}
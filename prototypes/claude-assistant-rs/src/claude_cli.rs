@@ -0,0 +1,176 @@
+//! Which flags the installed Claude CLI takes
+//!
+//! Flags have been renamed and added across Claude Code releases, and a flag
+//! the CLI doesn't know makes it exit before the session is up. The version
+//! reported by `claude --version` picks a row of `FLAG_TABLE`, and the command
+//! builder spells each flag the way that row says.
+
+use crate::runner::CommandRunner;
+use std::fmt;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// A Claude CLI release, e.g. 1.0.58
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClaudeVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ClaudeVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// The version in `claude --version` output, e.g. "1.0.58 (Claude Code)"
+    pub fn parse(output: &str) -> Option<Self> {
+        let word = output.split_whitespace().next()?;
+        // Pre-release and build suffixes don't change the flags
+        let core = word.trim_start_matches('v').split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|part| part.parse::<u32>().ok());
+        let version = Self::new(parts.next()??, parts.next()??, parts.next().unwrap_or(Some(0))?);
+        parts.next().is_none().then_some(version)
+    }
+}
+
+impl fmt::Display for ClaudeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// How a Claude CLI release spells the flags sessions are started with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClaudeFlags {
+    pub skip_permissions: &'static str,
+    pub allowed_tools: &'static str,
+    /// None where the release can't add to the system prompt
+    pub append_system_prompt: Option<&'static str>,
+    pub resume: &'static str,
+}
+
+impl ClaudeFlags {
+    /// Flags of the newest release in the table, used when the version isn't known
+    pub const CURRENT: ClaudeFlags = ClaudeFlags {
+        skip_permissions: "--dangerously-skip-permissions",
+        allowed_tools: "--allowedTools",
+        append_system_prompt: Some("--append-system-prompt"),
+        resume: "--resume",
+    };
+
+    /// Flags for `version`, or the current ones (with a warning) for a version that's unknown or older than the table
+    pub fn for_version(version: Option<ClaudeVersion>) -> Self {
+        let Some(version) = version else {
+            warn!("Couldn't tell which Claude CLI version is installed, using the current flags");
+            return Self::CURRENT;
+        };
+        match FLAG_TABLE.iter().rev().find(|(since, _)| version >= *since) {
+            Some((_, flags)) => *flags,
+            None => {
+                warn!("Claude CLI {} is older than any known release, using the current flags", version);
+                Self::CURRENT
+            }
+        }
+    }
+}
+
+/// Flags by the release they first apply to, oldest first
+///
+/// When a release renames a flag, add a row here rather than changing an old one.
+const FLAG_TABLE: &[(ClaudeVersion, ClaudeFlags)] = &[
+    // No --append-system-prompt yet: tier prompts are left off rather than failing the start
+    (
+        ClaudeVersion::new(0, 2, 0),
+        ClaudeFlags { append_system_prompt: None, ..ClaudeFlags::CURRENT },
+    ),
+    (ClaudeVersion::new(1, 0, 0), ClaudeFlags::CURRENT),
+];
+
+/// The installed version, from `claude --version`
+pub fn probe_version(runner: &dyn CommandRunner, claude: &Path) -> Option<ClaudeVersion> {
+    let output = runner.run(claude, &["--version".to_string()]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    ClaudeVersion::parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Flags for the Claude CLI at `claude`, asking it its version
+pub fn detect_flags(runner: &dyn CommandRunner, claude: &Path) -> ClaudeFlags {
+    let version = probe_version(runner, claude);
+    if let Some(version) = version {
+        info!("Claude CLI at {} is version {}", claude.display(), version);
+    }
+    ClaudeFlags::for_version(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{FakeTmux, SystemRunner};
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(ClaudeVersion::parse("1.0.58 (Claude Code)\n"), Some(ClaudeVersion::new(1, 0, 58)));
+        assert_eq!(ClaudeVersion::parse("0.2.125"), Some(ClaudeVersion::new(0, 2, 125)));
+        assert_eq!(ClaudeVersion::parse("v2.1.0-beta.3"), Some(ClaudeVersion::new(2, 1, 0)));
+        assert_eq!(ClaudeVersion::parse("2.1"), Some(ClaudeVersion::new(2, 1, 0)));
+        assert_eq!(ClaudeVersion::parse("Claude Code"), None);
+        assert_eq!(ClaudeVersion::parse("1.0.58.4"), None);
+        assert_eq!(ClaudeVersion::parse(""), None);
+    }
+
+    #[test]
+    fn test_version_order() {
+        assert!(ClaudeVersion::new(0, 2, 125) < ClaudeVersion::new(1, 0, 0));
+        assert!(ClaudeVersion::new(1, 0, 9) < ClaudeVersion::new(1, 0, 10));
+        assert_eq!(ClaudeVersion::new(1, 0, 58).to_string(), "1.0.58");
+    }
+
+    /// Releases before --append-system-prompt
+    #[test]
+    fn test_flags_for_0_2() {
+        let flags = ClaudeFlags::for_version(Some(ClaudeVersion::new(0, 2, 125)));
+        assert_eq!(flags.append_system_prompt, None);
+        assert_eq!(flags.allowed_tools, "--allowedTools");
+        assert_eq!(flags.skip_permissions, "--dangerously-skip-permissions");
+    }
+
+    #[test]
+    fn test_flags_for_1_x() {
+        assert_eq!(ClaudeFlags::for_version(Some(ClaudeVersion::new(1, 0, 0))), ClaudeFlags::CURRENT);
+        assert_eq!(ClaudeFlags::for_version(Some(ClaudeVersion::new(1, 0, 58))), ClaudeFlags::CURRENT);
+    }
+
+    /// Releases newer than the table get its newest row
+    #[test]
+    fn test_flags_for_newer_release() {
+        assert_eq!(ClaudeFlags::for_version(Some(ClaudeVersion::new(9, 0, 0))), ClaudeFlags::CURRENT);
+    }
+
+    #[test]
+    fn test_flags_for_unknown_or_ancient_release() {
+        assert_eq!(ClaudeFlags::for_version(None), ClaudeFlags::CURRENT);
+        assert_eq!(ClaudeFlags::for_version(Some(ClaudeVersion::new(0, 1, 9))), ClaudeFlags::CURRENT);
+    }
+
+    #[test]
+    fn test_table_is_oldest_first() {
+        assert!(FLAG_TABLE.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_detect_flags() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let claude = temp.path().join("claude");
+        std::fs::write(&claude, "#!/bin/sh\necho '0.2.125 (Claude Code)'\n").unwrap();
+        std::fs::set_permissions(&claude, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        assert_eq!(probe_version(&SystemRunner, &claude), Some(ClaudeVersion::new(0, 2, 125)));
+        assert_eq!(detect_flags(&SystemRunner, &claude).append_system_prompt, None);
+
+        assert_eq!(probe_version(&SystemRunner, &temp.path().join("missing")), None);
+        assert_eq!(detect_flags(&SystemRunner, &temp.path().join("missing")), ClaudeFlags::CURRENT);
+        assert_eq!(probe_version(&FakeTmux::new(), &claude), Some(ClaudeVersion::new(1, 0, 58)));
+    }
+}
@@ -1,6 +1,89 @@
 //! Configuration and paths
 
-use std::path::PathBuf;
+use crate::error::{Error, Result};
+use crate::health::HealthPatterns;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Source of contact names and tiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContactsBackend {
+    /// `contacts list --json` from contacts-cli
+    #[default]
+    Cli,
+    /// Contacts.app's own SQLite databases, tier from a "tier:xyz" token in the note
+    AddressBook,
+}
+
+/// A blessed contact tier and how its sessions are run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TierConfig {
+    pub name: String,
+    /// Passed as `--allowedTools`; None leaves tools unrestricted
+    #[serde(default)]
+    pub allowed_tools: Option<String>,
+    /// Passed as `--append-system-prompt`
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Pass `--dangerously-skip-permissions`
+    #[serde(default)]
+    pub skip_permissions: bool,
+    /// Directory sessions run in instead of their transcript dir; `~/` is the home dir
+    #[serde(default)]
+    pub workdir: Option<String>,
+}
+
+impl TierConfig {
+    /// The default favorite tier, also used for sessions whose tier has no definition
+    pub fn restricted() -> Self {
+        Self {
+            name: "favorite".to_string(),
+            allowed_tools: Some("Read,WebSearch,WebFetch,Grep,Glob,Bash(osascript:*)".to_string()),
+            system_prompt: Some("You are chatting with a FAVORITES tier user with LIMITED privileges.".to_string()),
+            skip_permissions: true,
+            workdir: None,
+        }
+    }
+
+    /// The tier of sessions for senders let through by `quarantine --bless-once`
+    ///
+    /// No tool is pre-approved and permissions aren't skipped, so any tool use
+    /// waits on a prompt nobody answers.
+    pub fn quarantine() -> Self {
+        Self {
+            name: QUARANTINE_TIER.to_string(),
+            allowed_tools: Some(String::new()),
+            system_prompt: Some("You are chatting with an UNKNOWN sender. You have NO tools; reply in text only.".to_string()),
+            skip_permissions: false,
+            workdir: None,
+        }
+    }
+}
+
+/// The built-in tiers, highest priority first
+pub fn default_tiers() -> Vec<TierConfig> {
+    let full = |name: &str| TierConfig {
+        name: name.to_string(),
+        allowed_tools: None,
+        system_prompt: None,
+        skip_permissions: true,
+        workdir: None,
+    };
+    vec![
+        full("admin"),
+        full("wife"),
+        TierConfig {
+            system_prompt: Some(
+                "You are chatting with a FAMILY tier user. Read ~/.claude/skills/sms-assistant/family-rules.md FIRST."
+                    .to_string(),
+            ),
+            ..full("family")
+        },
+        TierConfig::restricted(),
+    ]
+}
 
 /// All configurable paths and constants
 #[derive(Debug, Clone)]
@@ -9,19 +92,157 @@ pub struct Config {
     pub messages_db: PathBuf,
     pub assistant_dir: PathBuf,
     pub state_dir: PathBuf,
+    /// Legacy single global last ROWID (migrated into cursors_file)
     pub state_file: PathBuf,
+    /// Per-chat ROWID high-water marks
+    pub cursors_file: PathBuf,
+    /// Messages from unblessed senders, one JSON object per line
+    pub quarantine_file: PathBuf,
+    /// Local tier assignments that win over the contacts source
+    pub tier_overrides_file: PathBuf,
+    /// Senders whose messages are always dropped
+    pub blocklist_file: PathBuf,
+    /// Group chats whose messages are processed from every participant
+    pub blessed_groups_file: PathBuf,
     pub registry_file: PathBuf,
+    /// Recent health events, for `health` and `status`
+    pub health_history_file: PathBuf,
+    /// When each contact reminder last fired
+    pub reminders_fired_file: PathBuf,
+    /// Reminders added with `reminders add`, by chat
+    pub reminders_file: PathBuf,
+    /// Optional JSON list of `TierConfig`s replacing the built-in tiers
+    pub tiers_file: PathBuf,
+    /// Optional TOML of fatal and API error patterns for health checks
+    pub health_patterns_file: PathBuf,
     pub logs_dir: PathBuf,
     pub skills_dir: PathBuf,
     pub transcripts_dir: PathBuf,
     pub tmux: PathBuf,
+    /// Socket of the daemon's own tmux server (`tmux -L`); empty uses the default server
+    pub tmux_socket_name: String,
     pub claude: PathBuf,
     pub contacts_cli: PathBuf,
+    /// Where contacts and their tiers come from
+    pub contacts_backend: ContactsBackend,
+    /// Contacts.app data, searched for AddressBook-v22.abcddb files
+    pub address_book_dir: PathBuf,
     pub send_sms: PathBuf,
+    /// Used to convert HEIC photos to JPEG
+    pub sips: PathBuf,
+    /// Used to read sessions' memory and CPU
+    pub ps: PathBuf,
     pub poll_interval_ms: u64,
     pub health_check_interval_secs: u64,
+    /// A tmux call still going after this long is killed, and a health sweep that hasn't heard about a session for this long stops waiting for it (0 waits forever)
+    pub health_check_timeout_secs: u64,
+    /// Restarts in a row a failing session gets before the health sweep gives up on it (0 never gives up)
+    pub restart_max_attempts: u32,
+    /// Health sweeps in a row a busy session's pane can stay unchanged before it's restarted as stuck (0 disables)
+    pub stuck_sweeps: u32,
+    /// A session showing API errors on this many of its last `api_error_window` health sweeps is restarted (0 disables)
+    pub api_error_sweeps: u32,
+    pub api_error_window: u32,
+    /// A session whose Claude and children together use more memory than this is restarted (0 disables)
+    pub max_session_rss_mb: u64,
+    /// Likewise for CPU, as `ps` reports it, where 100 is one core (0 disables)
+    pub max_session_cpu_pct: f64,
+    /// Sent to a session that has run out of context, instead of restarting it
+    pub compact_command: String,
+    /// How long a session sent `compact_command` has to recover before it's restarted
+    pub compact_grace_secs: u64,
+    /// How long to hold a session's messages at its usage limit, when Claude doesn't say when it resets
+    pub usage_limit_pause_mins: u64,
+    /// A chat whose session is restarted by the health sweep within this long of its last message is asked to resend it (0 disables)
+    pub recovery_notice_window_mins: u64,
+    /// A chat is sent at most one such notice this often
+    pub recovery_notice_cooldown_mins: u64,
+    /// Sessions nobody has messaged for this long are archived to free their Claude process (0 disables)
     pub idle_timeout_hours: f64,
+    /// Tiers whose sessions are never archived for being idle
+    pub idle_exempt_tiers: Vec<String>,
+    /// Local hour the daemon does its nightly housekeeping
     pub consolidation_hour: u32,
+    /// Nightly, registry entries with no tmux session and no message for this many days are dropped (0 disables)
+    pub registry_prune_days: u64,
+    /// Inject a short note for tapback reactions instead of dropping them
+    pub inject_tapbacks: bool,
+    /// Inject "X sent a sticker" for sticker/Digital Touch/handwriting messages instead of dropping them
+    pub summarize_non_text: bool,
+    /// Edits to messages older than this are not re-injected as corrections
+    pub edit_window_minutes: u64,
+    /// Query chat.db at least this often even if no file change was observed
+    pub fallback_poll_secs: u64,
+    /// Messages of history injected into a session when it is recreated (0 disables)
+    pub backfill_messages: usize,
+    /// Append replies sent outside Claude to the session's conversation.log
+    pub log_outbound: bool,
+    /// Also inject those replies into the session as a note
+    pub inject_outbound: bool,
+    /// The owner's own phone numbers and emails, used to detect @-mentions
+    pub my_handles: Vec<String>,
+    /// In group chats, only respond to messages that mention one of `my_handles`
+    pub group_mentions_only: bool,
+    /// Messages starting with one of these (any case) go to the chat's `-bg` session, prefix stripped
+    pub background_prefixes: Vec<String>,
+    /// Message from the top tier that interrupts its chat's session, e.g. "!stop" or "!stop then ..." (empty disables)
+    pub stop_command: String,
+    /// Nudge a session when an inbound message has gone this long without a reply (0 disables)
+    pub unreplied_nudge_hours: f64,
+    /// How often to look for unreplied messages
+    pub unreplied_check_interval_secs: u64,
+    /// Read the pane back after injecting to make sure the text arrived, retrying once
+    pub verify_injections: bool,
+    /// How long a new session has to show Claude's input box before it counts as failed
+    pub session_ready_timeout_secs: u64,
+    /// Scrollback archives kept per transcript dir when sessions are killed
+    pub pane_archive_keep: usize,
+    /// Sessions recreated per health sweep after tmux's server went away with all of them
+    pub server_recovery_batch: usize,
+    /// Seconds between starting each of those sessions
+    pub server_recovery_stagger_secs: u64,
+    /// Crash bundles kept per session under `logs/crashes`, saved when the health sweep restarts one (0 saves none)
+    pub crash_bundle_keep: usize,
+    /// How long a busy Claude gets to wrap up after being asked to, before its session is killed
+    pub kill_grace_secs: u64,
+    /// A session's output log is rotated once it grows past this
+    pub session_log_max_bytes: u64,
+    /// Rotated output logs kept per session
+    pub session_log_keep: usize,
+    /// Start and inject into each session on its own thread, rather than inline in the poll loop
+    pub parallel_sessions: bool,
+    /// How long tmux's answer to which sessions exist is reused (0 asks every time)
+    pub session_cache_ms: u64,
+    /// How long to wait for another process to finish with the session registry
+    pub registry_lock_ms: u64,
+    /// Merge changes something else makes to the session registry by each entry's `updated_at`; off, the daemon's copy wins
+    pub registry_merge: bool,
+    /// Print what would be created, injected, or killed instead of doing it
+    pub dry_run: bool,
+    /// A prompt waiting for a busy session is injected anyway after this long
+    pub queue_max_wait_secs: u64,
+    /// Inject everything that queued up for a busy session as one block instead of one at a time
+    pub coalesce_queued: bool,
+    /// Messages bigger than this are saved to the session's inbox/ and referenced instead of pasted
+    pub max_inject_bytes: usize,
+    /// On a fresh start, or when saved progress is older than this, replay messages from this far back
+    pub startup_backfill_minutes: u64,
+    /// Quarantine entries kept, oldest dropped first
+    pub quarantine_max_entries: usize,
+    /// Contacts are reloaded once the cache is older than this
+    pub contacts_cache_ttl_secs: u64,
+    /// Last successfully loaded contacts, used when the contacts source fails
+    pub contacts_snapshot_file: PathBuf,
+    /// A snapshot older than this is refused rather than trusted
+    pub contacts_snapshot_max_age_hours: u64,
+    /// ISO 3166 region that phone numbers without a country code are dialed from (e.g. "US", "GB")
+    pub default_region: String,
+    /// Blessed tiers in priority order
+    pub tiers: Vec<TierConfig>,
+    /// What health checks look for, from `health_patterns_file` and the built-in patterns
+    pub health_patterns: HealthPatterns,
+    /// Restart a session when its contact is promoted (demotions always restart)
+    pub restart_on_tier_upgrade: bool,
 }
 
 impl Default for Config {
@@ -33,20 +254,88 @@ impl Default for Config {
             messages_db: home.join("Library/Messages/chat.db"),
             state_dir: assistant_dir.join("state"),
             state_file: assistant_dir.join("state/last_rowid.txt"),
+            cursors_file: assistant_dir.join("state/chat_cursors.json"),
+            quarantine_file: assistant_dir.join("state/quarantine.jsonl"),
+            tier_overrides_file: assistant_dir.join("state/tier_overrides.json"),
+            blocklist_file: assistant_dir.join("state/blocklist.json"),
+            blessed_groups_file: assistant_dir.join("state/blessed_groups.json"),
             registry_file: assistant_dir.join("state/sessions.json"),
+            health_history_file: assistant_dir.join("state/health_history.json"),
+            reminders_fired_file: assistant_dir.join("state/reminders_fired.json"),
+            reminders_file: assistant_dir.join("state/reminders.json"),
+            contacts_snapshot_file: assistant_dir.join("state/contacts_cache.json"),
+            tiers_file: assistant_dir.join("config/tiers.json"),
+            health_patterns_file: assistant_dir.join("health_patterns.toml"),
             logs_dir: assistant_dir.join("logs"),
             skills_dir: home.join(".claude/skills"),
             transcripts_dir: home.join("transcripts"),
             tmux: PathBuf::from("/opt/homebrew/bin/tmux"),
+            tmux_socket_name: "claude-assistant".to_string(),
             claude: home.join(".local/bin/claude"),
             contacts_cli: home.join("code/contacts-cli/contacts"),
+            contacts_backend: ContactsBackend::default(),
+            address_book_dir: home.join("Library/Application Support/AddressBook"),
             send_sms: home.join("code/sms-cli/send-sms"),
+            sips: PathBuf::from("/usr/bin/sips"),
+            ps: PathBuf::from("/bin/ps"),
             assistant_dir,
             home,
             poll_interval_ms: 100,
             health_check_interval_secs: 300,
+            health_check_timeout_secs: 10,
+            restart_max_attempts: 5,
+            stuck_sweeps: 3,
+            api_error_sweeps: 4,
+            api_error_window: 6,
+            max_session_rss_mb: 6144,
+            max_session_cpu_pct: 0.0,
+            compact_command: "/compact".to_string(),
+            compact_grace_secs: 600,
+            usage_limit_pause_mins: 60,
+            recovery_notice_window_mins: 10,
+            recovery_notice_cooldown_mins: 60,
             idle_timeout_hours: 2.0,
+            idle_exempt_tiers: vec!["admin".to_string(), "wife".to_string()],
             consolidation_hour: 2,
+            registry_prune_days: 90,
+            inject_tapbacks: false,
+            summarize_non_text: true,
+            edit_window_minutes: 15,
+            fallback_poll_secs: 30,
+            backfill_messages: 20,
+            log_outbound: false,
+            inject_outbound: false,
+            my_handles: Vec::new(),
+            background_prefixes: vec!["bg:".to_string(), "task:".to_string()],
+            stop_command: "!stop".to_string(),
+            group_mentions_only: false,
+            unreplied_nudge_hours: 0.0,
+            unreplied_check_interval_secs: 900,
+            verify_injections: true,
+            session_ready_timeout_secs: 30,
+            pane_archive_keep: 20,
+            crash_bundle_keep: 10,
+            server_recovery_batch: 3,
+            server_recovery_stagger_secs: 5,
+            kill_grace_secs: 30,
+            session_log_max_bytes: 10 * 1024 * 1024,
+            session_log_keep: 3,
+            parallel_sessions: true,
+            session_cache_ms: 1000,
+            registry_lock_ms: 5000,
+            registry_merge: true,
+            dry_run: false,
+            queue_max_wait_secs: 600,
+            coalesce_queued: true,
+            max_inject_bytes: 8 * 1024,
+            startup_backfill_minutes: 60,
+            quarantine_max_entries: 1000,
+            contacts_cache_ttl_secs: 15 * 60,
+            contacts_snapshot_max_age_hours: 72,
+            default_region: "US".to_string(),
+            tiers: default_tiers(),
+            health_patterns: HealthPatterns::default(),
+            restart_on_tier_upgrade: false,
         }
     }
 }
@@ -60,33 +349,237 @@ impl Config {
             assistant_dir: temp_dir.join("claude-assistant"),
             state_dir: temp_dir.join("state"),
             state_file: temp_dir.join("state/last_rowid.txt"),
+            cursors_file: temp_dir.join("state/chat_cursors.json"),
+            quarantine_file: temp_dir.join("state/quarantine.jsonl"),
+            tier_overrides_file: temp_dir.join("state/tier_overrides.json"),
+            blocklist_file: temp_dir.join("state/blocklist.json"),
+            blessed_groups_file: temp_dir.join("state/blessed_groups.json"),
             registry_file: temp_dir.join("state/sessions.json"),
+            health_history_file: temp_dir.join("state/health_history.json"),
+            reminders_fired_file: temp_dir.join("state/reminders_fired.json"),
+            reminders_file: temp_dir.join("state/reminders.json"),
+            tiers_file: temp_dir.join("config/tiers.json"),
+            health_patterns_file: temp_dir.join("claude-assistant/health_patterns.toml"),
             logs_dir: temp_dir.join("logs"),
             skills_dir: temp_dir.join("skills"),
             transcripts_dir: temp_dir.join("transcripts"),
             tmux: PathBuf::from("/opt/homebrew/bin/tmux"),
+            tmux_socket_name: String::new(),
             claude: PathBuf::from("/usr/local/bin/claude"),
             contacts_cli: temp_dir.join("contacts"),
+            contacts_backend: ContactsBackend::default(),
+            address_book_dir: temp_dir.join("AddressBook"),
             send_sms: temp_dir.join("send-sms"),
+            sips: temp_dir.join("sips"),
+            ps: temp_dir.join("ps"),
             poll_interval_ms: 100,
             health_check_interval_secs: 300,
+            health_check_timeout_secs: 10,
+            restart_max_attempts: 5,
+            stuck_sweeps: 3,
+            api_error_sweeps: 4,
+            api_error_window: 6,
+            max_session_rss_mb: 6144,
+            max_session_cpu_pct: 0.0,
+            compact_command: "/compact".to_string(),
+            compact_grace_secs: 600,
+            usage_limit_pause_mins: 60,
+            recovery_notice_window_mins: 10,
+            recovery_notice_cooldown_mins: 60,
             idle_timeout_hours: 2.0,
+            idle_exempt_tiers: vec!["admin".to_string(), "wife".to_string()],
             consolidation_hour: 2,
+            registry_prune_days: 0,
+            inject_tapbacks: false,
+            summarize_non_text: true,
+            edit_window_minutes: 15,
+            fallback_poll_secs: 30,
+            backfill_messages: 20,
+            log_outbound: false,
+            inject_outbound: false,
+            my_handles: Vec::new(),
+            background_prefixes: vec!["bg:".to_string(), "task:".to_string()],
+            stop_command: "!stop".to_string(),
+            group_mentions_only: false,
+            unreplied_nudge_hours: 0.0,
+            unreplied_check_interval_secs: 900,
+            verify_injections: false,
+            session_ready_timeout_secs: 5,
+            pane_archive_keep: 20,
+            crash_bundle_keep: 10,
+            server_recovery_batch: 3,
+            server_recovery_stagger_secs: 0,
+            kill_grace_secs: 0,
+            session_log_max_bytes: 10 * 1024 * 1024,
+            session_log_keep: 3,
+            parallel_sessions: false,
+            session_cache_ms: 0,
+            registry_lock_ms: 2000,
+            registry_merge: true,
+            dry_run: false,
+            queue_max_wait_secs: 600,
+            coalesce_queued: true,
+            max_inject_bytes: 8 * 1024,
+            startup_backfill_minutes: 60,
+            quarantine_max_entries: 1000,
+            contacts_cache_ttl_secs: 15 * 60,
+            contacts_snapshot_file: temp_dir.join("state/contacts_cache.json"),
+            contacts_snapshot_max_age_hours: 72,
+            default_region: "US".to_string(),
+            tiers: default_tiers(),
+            health_patterns: HealthPatterns::default(),
+            restart_on_tier_upgrade: false,
+        }
+    }
+
+    /// Replace the built-in tiers with `tiers_file`, if it exists
+    pub fn load_tiers(&mut self) -> Result<()> {
+        if !self.tiers_file.exists() {
+            return Ok(());
         }
+        let content = std::fs::read_to_string(&self.tiers_file)?;
+        let tiers: Vec<TierConfig> = serde_json::from_str(&content)
+            .map_err(|e| Error::Config(format!("{}: {}", self.tiers_file.display(), e)))?;
+
+        let mut seen = HashSet::new();
+        for tier in &tiers {
+            let name = tier.name.as_str();
+            if name.is_empty() || name != name.trim().to_lowercase() {
+                return Err(Error::Config(format!("tier name '{}' must be lowercase with no spaces around it", name)));
+            }
+            if name == BLOCKED_TIER || name == QUARANTINE_TIER {
+                return Err(Error::Config(format!("'{}' is reserved and can't be a blessed tier", name)));
+            }
+            if !seen.insert(name) {
+                return Err(Error::Config(format!("tier '{}' is defined twice", name)));
+            }
+        }
+        self.tiers = tiers;
+        Ok(())
+    }
+
+    /// Add the patterns in `health_patterns_file`, if it exists, to the built-in ones (or replace them)
+    pub fn load_health_patterns(&mut self) -> Result<()> {
+        self.health_patterns = HealthPatterns::load(&self.health_patterns_file)?;
+        Ok(())
+    }
+
+    /// Definition of a blessed tier
+    pub fn tier(&self, name: &str) -> Option<&TierConfig> {
+        self.tiers.iter().find(|t| t.name == name)
+    }
+
+    pub fn is_blessed_tier(&self, tier: &str) -> bool {
+        self.tier(tier).is_some()
+    }
+
+    /// Blessed tier names in priority order
+    pub fn tier_names(&self) -> Vec<&str> {
+        self.tiers.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    /// The least trusted configured tier, for sessions no contact gives a tier to
+    ///
+    /// With no tiers configured at all that's the tool-less quarantine tier.
+    pub fn default_tier(&self) -> &str {
+        self.tiers.last().map_or(QUARANTINE_TIER, |t| t.name.as_str())
+    }
+
+    /// Position in priority order, lower is more trusted; unblessed tiers rank last
+    pub fn tier_rank(&self, tier: &str) -> usize {
+        self.tiers.iter().position(|t| t.name == tier).unwrap_or(self.tiers.len())
+    }
+
+    /// Check that the binaries the daemon runs are there and work, returning one message per problem
+    ///
+    /// A binary missing from its configured path is looked for on PATH, and
+    /// used from there if found. tmux and claude are also asked for their versions.
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut binaries = vec![
+            ("tmux", &mut self.tmux, Some("-V")),
+            ("claude", &mut self.claude, Some("--version")),
+            ("send-sms", &mut self.send_sms, None),
+        ];
+        if self.contacts_backend == ContactsBackend::Cli {
+            binaries.push(("contacts CLI", &mut self.contacts_cli, None));
+        }
+
+        for (label, path, version_flag) in binaries {
+            if let Err(problem) = check_executable(label, path) {
+                let found = path.file_name().and_then(|name| find_on_path(&name.to_string_lossy()));
+                match found {
+                    Some(found) => {
+                        tracing::warn!("{}; using {} from PATH", problem, found.display());
+                        *path = found;
+                    }
+                    None => {
+                        problems.push(format!("{}, and it isn't on PATH either", problem));
+                        continue;
+                    }
+                }
+            }
+            if let Some(flag) = version_flag {
+                if let Err(problem) = check_runs(label, path, flag) {
+                    problems.push(problem);
+                }
+            }
+        }
+        problems
     }
 }
 
+/// Whether `path` is a file anyone may execute, as a message naming `label` if not
+fn check_executable(label: &str, path: &Path) -> std::result::Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::metadata(path) {
+        Err(_) => Err(format!("{} not found at {}", label, path.display())),
+        Ok(meta) if !meta.is_file() => Err(format!("{} at {} isn't a file", label, path.display())),
+        Ok(meta) if meta.permissions().mode() & 0o111 == 0 => {
+            Err(format!("{} at {} isn't executable (chmod +x it)", label, path.display()))
+        }
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Whether `path flag` exits successfully
+fn check_runs(label: &str, path: &Path, flag: &str) -> std::result::Result<(), String> {
+    let failed = |why: String| format!("{} at {} doesn't run: `{} {}` {}", label, path.display(), path.display(), flag, why);
+    match Command::new(path).arg(flag).output() {
+        Err(e) => Err(failed(format!("failed: {}", e))),
+        Ok(output) if !output.status.success() => {
+            Err(failed(format!("exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim())))
+        }
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Where `which` finds `name`, if anywhere
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let output = Command::new("which").arg(name).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!path.is_empty()).then(|| PathBuf::from(path)).filter(|path| check_executable(name, path).is_ok())
+}
+
 /// macOS epoch offset (2001-01-01 to 1970-01-01 in seconds)
 pub const MACOS_EPOCH_OFFSET: i64 = 978307200;
 
-/// Contact tiers in priority order
-pub const BLESSED_TIERS: &[&str] = &["admin", "wife", "family", "favorite"];
+/// Tier override that drops someone regardless of any other tier
+pub const BLOCKED_TIER: &str = "blocked";
+
+/// Tier of sessions for quarantined senders let through once; see `TierConfig::quarantine`
+pub const QUARANTINE_TIER: &str = "unknown";
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::overly_complex_bool_expr)]
     fn test_default_config() {
         let config = Config::default();
         assert!(config.home.exists() || true); // May not exist in CI
@@ -100,6 +593,66 @@ mod tests {
         assert_eq!(config.home, temp);
     }
 
+    fn write_script(path: &Path, body: &str, mode: u32) {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::write(path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.tmux = temp.path().join("tmux");
+        config.claude = temp.path().join("claude");
+        write_script(&config.tmux, "exit 0", 0o755);
+        write_script(&config.claude, "exit 0", 0o755);
+        write_script(&config.send_sms, "exit 0", 0o755);
+        write_script(&config.contacts_cli, "exit 0", 0o755);
+        assert!(config.validate().is_empty());
+
+        // Not executable, and a broken claude
+        write_script(&config.send_sms, "exit 0", 0o644);
+        write_script(&config.claude, "echo 'node: not found' >&2; exit 127", 0o755);
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("claude at") && problems[0].contains("node: not found"));
+        assert!(problems[1].contains("send-sms at") && problems[1].contains("isn't executable"));
+    }
+
+    #[test]
+    fn test_validate_missing() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        // Names nothing on PATH has
+        config.tmux = temp.path().join("no-such-tmux");
+        config.claude = temp.path().join("no-such-claude");
+        config.send_sms = temp.path().join("no-such-send-sms");
+        config.contacts_cli = temp.path().join("no-such-contacts");
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].starts_with(&format!("tmux not found at {}", config.tmux.display())));
+        assert!(problems.iter().all(|p| p.ends_with("and it isn't on PATH either")));
+
+        // The contacts CLI isn't needed with the AddressBook backend
+        config.contacts_backend = ContactsBackend::AddressBook;
+        assert_eq!(config.validate().len(), 3);
+    }
+
+    /// A binary that's not where it's configured is picked up from PATH
+    #[test]
+    fn test_validate_finds_on_path() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.tmux = temp.path().join("elsewhere/true");
+
+        config.validate();
+        assert_ne!(config.tmux, temp.path().join("elsewhere/true"));
+        assert!(config.tmux.ends_with("true"));
+    }
+
     #[test]
     fn test_macos_epoch() {
         // Jan 1, 2001 00:00:00 UTC
@@ -108,10 +661,78 @@ mod tests {
 
     #[test]
     fn test_blessed_tiers() {
-        assert!(BLESSED_TIERS.contains(&"admin"));
-        assert!(BLESSED_TIERS.contains(&"wife"));
-        assert!(BLESSED_TIERS.contains(&"family"));
-        assert!(BLESSED_TIERS.contains(&"favorite"));
-        assert!(!BLESSED_TIERS.contains(&"unknown"));
+        let config = Config::for_test(&std::env::temp_dir());
+        assert_eq!(config.tier_names(), vec!["admin", "wife", "family", "favorite"]);
+        assert!(config.tier_rank("admin") < config.tier_rank("favorite"));
+        assert_eq!(config.tier_rank("unknown"), config.tier_rank("blocked"));
+        assert!(config.is_blessed_tier("admin"));
+        assert!(config.is_blessed_tier("favorite"));
+        assert!(!config.is_blessed_tier("unknown"));
+        assert!(config.tier("admin").unwrap().allowed_tools.is_none());
+        assert!(config.tier("favorite").unwrap().allowed_tools.is_some());
+        assert_eq!(config.default_tier(), "favorite");
+    }
+
+    #[test]
+    fn test_load_tiers() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+
+        // No file keeps the built-in tiers
+        config.load_tiers().unwrap();
+        assert_eq!(config.tiers, default_tiers());
+
+        std::fs::create_dir_all(temp.path().join("config")).unwrap();
+        std::fs::write(
+            &config.tiers_file,
+            r#"[
+                {"name": "admin", "skip_permissions": true},
+                {"name": "coworker", "allowed_tools": "Read,Grep", "system_prompt": "Work contact."}
+            ]"#,
+        )
+        .unwrap();
+        config.load_tiers().unwrap();
+        assert_eq!(config.tier_names(), vec!["admin", "coworker"]);
+        assert_eq!(config.default_tier(), "coworker");
+        assert!(!config.is_blessed_tier("family"));
+        let coworker = config.tier("coworker").unwrap();
+        assert_eq!(coworker.allowed_tools.as_deref(), Some("Read,Grep"));
+        assert!(!coworker.skip_permissions);
+    }
+
+    #[test]
+    fn test_load_health_patterns() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.load_health_patterns().unwrap();
+        assert_eq!(config.health_patterns.counts(), HealthPatterns::builtin().counts());
+
+        std::fs::create_dir_all(&config.assistant_dir).unwrap();
+        std::fs::write(&config.health_patterns_file, "[[fatal]]\nname = \"context_low\"\npattern = \"Context low\"\n").unwrap();
+        config.load_health_patterns().unwrap();
+        assert_eq!(config.health_patterns.counts().0, HealthPatterns::builtin().counts().0 + 1);
+
+        std::fs::write(&config.health_patterns_file, "api = [\"rate (limit\"]\n").unwrap();
+        let err = config.load_health_patterns().unwrap_err().to_string();
+        assert!(err.contains("health_patterns.toml"), "{}", err);
+        assert!(err.contains("rate (limit"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_tiers_rejects_bad_names() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        std::fs::create_dir_all(temp.path().join("config")).unwrap();
+
+        for bad in [
+            r#"[{"name": "Coworker"}]"#,
+            r#"[{"name": "blocked"}]"#,
+            r#"[{"name": "admin"}, {"name": "admin"}]"#,
+            r#"{"name": "admin"}"#,
+        ] {
+            std::fs::write(&config.tiers_file, bad).unwrap();
+            assert!(matches!(config.load_tiers(), Err(Error::Config(_))), "{}", bad);
+            assert_eq!(config.tiers, default_tiers());
+        }
     }
 }
@@ -1,24 +1,116 @@
 //! Contact management - lookup contacts and their tiers
+//!
+//! Contacts come from contacts-cli by default, or straight from Contacts.app's
+//! AddressBook databases (see `ContactsBackend`). Entries in
+//! `state/tier_overrides.json` are applied on top of either.
 
-use crate::config::{Config, BLESSED_TIERS};
+use crate::config::{Config, ContactsBackend, BLOCKED_TIER};
 use crate::error::{Error, Result};
-use std::collections::HashMap;
+use crate::registry::write_json_atomic;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+
+/// File name of a Contacts.app database (one at the top level, one per account under Sources/)
+const ADDRESS_BOOK_DB: &str = "AddressBook-v22.abcddb";
+
+/// Longest number treated as an SMS short code rather than a phone number
+const SHORT_CODE_MAX_DIGITS: usize = 6;
 
 /// Contact information
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Contact {
     pub name: String,
     pub phone: Option<String>,
     pub email: Option<String>,
     pub tier: String,
+    /// Free-form contact note (holds REMINDER:, SYSTEM_PROMPT:, ALLOWED_TOOLS: and WORKDIR: lines)
+    pub notes: Option<String>,
+    /// Appended to this contact's Claude session on top of the tier's prompt
+    pub system_prompt: Option<String>,
+    /// Replaces the tier's allowedTools for this contact's session
+    pub allowed_tools: Option<String>,
+    /// Used instead of the name when naming the contact's session
+    pub alias: Option<String>,
+    /// Directory the contact's session runs in, over the tier's
+    pub workdir: Option<String>,
+    /// The contacts source's own ID, shared by every handle of one person
+    pub id: Option<String>,
+}
+
+impl Contact {
+    /// Whether two entries are the same person, e.g. their phone and email handles
+    pub fn is_same_person(&self, other: &Contact) -> bool {
+        match (&self.id, &other.id) {
+            (Some(a), Some(b)) => a == b,
+            _ => self == other,
+        }
+    }
+}
+
+/// Where the daemon looks up who a sender is and whether to let them through
+///
+/// `ContactsManager` is the real source; `StaticContacts` holds a fixed set in
+/// memory so the daemon can be driven without a contacts CLI or database.
+pub trait ContactSource {
+    /// Lookup contact by phone OR email (for Messages.app identifiers)
+    fn lookup_identifier(&mut self, identifier: &str) -> Result<Option<Contact>>;
+
+    /// Get all contacts in a blessed tier, one per name
+    fn list_blessed(&mut self) -> Result<Vec<Contact>>;
+
+    /// Contacts whose name or alias matches a search, best match first
+    fn search(&mut self, query: &str) -> Result<Vec<Contact>>;
+
+    /// Force refresh, returning the number of cache entries
+    fn refresh(&mut self) -> Result<usize>;
+
+    /// Reload if the contacts may be out of date; returns whether they were reloaded
+    fn refresh_if_stale(&mut self) -> bool {
+        false
+    }
+
+    /// Changes whenever the contacts are rebuilt
+    fn generation(&self) -> u64;
+
+    /// Whether messages from this sender should be dropped regardless of tier
+    fn is_blocked(&mut self, identifier: &str) -> bool;
+
+    /// Whether every participant of this group chat is let through, blessed or not
+    fn is_blessed_group(&mut self, chat_id: &str) -> bool;
+
+    /// Check if a tier is one of the configured blessed tiers
+    fn is_blessed_tier(&self, tier: &str) -> bool;
 }
 
 /// Contact manager with caching
+///
+/// The cache is reloaded once older than `Config::contacts_cache_ttl_secs`, so
+/// tier changes are picked up without restarting the daemon.
 pub struct ContactsManager {
     config: Config,
     cache: HashMap<String, Contact>,
-    loaded: bool,
+    /// When the cache was last (re)loaded or a reload was attempted
+    loaded_at: Option<Instant>,
+    /// Bumped on every successful load, so callers can tell when contacts changed
+    generation: u64,
+    /// Modification time of the overrides file the cache was built with
+    overrides_modified: Option<SystemTime>,
+    /// When the snapshot being served was saved, if the source failed on the last load
+    snapshot_saved_at: Option<DateTime<Utc>>,
+    /// Reloaded whenever the file changes
+    blocklist: Blocklist,
+    /// Reloaded whenever the file changes
+    blessed_groups: BlessedGroups,
+    ttl: Duration,
+    /// Time source, replaceable in tests
+    now: Box<dyn Fn() -> Instant + Send + Sync>,
 }
 
 impl ContactsManager {
@@ -26,12 +118,92 @@ impl ContactsManager {
         Self {
             config: config.clone(),
             cache: HashMap::new(),
-            loaded: false,
+            loaded_at: None,
+            generation: 0,
+            overrides_modified: None,
+            snapshot_saved_at: None,
+            blocklist: Blocklist::empty(config),
+            blessed_groups: BlessedGroups::empty(config),
+            ttl: Duration::from_secs(config.contacts_cache_ttl_secs),
+            now: Box::new(Instant::now),
         }
     }
 
     /// Load all contacts into cache
+    ///
+    /// Each successful load is saved to `contacts_snapshot_file`. If the source
+    /// fails before anything is cached, that snapshot is used instead as long as
+    /// it's younger than `contacts_snapshot_max_age_hours`; otherwise the source's
+    /// error is returned.
     pub fn load(&mut self) -> Result<usize> {
+        let source = match self.config.contacts_backend {
+            ContactsBackend::Cli => self.load_cli(),
+            ContactsBackend::AddressBook => load_address_book(&self.config.address_book_dir, &self.config.default_region),
+        };
+        let mut contacts = match source {
+            Ok(contacts) => {
+                if let Err(e) = ContactsSnapshot::save(&self.config.contacts_snapshot_file, &contacts) {
+                    warn!("Failed to save contacts snapshot: {}", e);
+                }
+                self.snapshot_saved_at = None;
+                contacts
+            }
+            Err(e) => {
+                // With contacts already cached, the caller keeps serving those
+                let snapshot = if self.cache.is_empty() { self.fresh_snapshot() } else { None };
+                let Some(snapshot) = snapshot else {
+                    return Err(e);
+                };
+                error!(
+                    "Contacts source failed ({}); serving {} contacts from the snapshot saved {}",
+                    e,
+                    snapshot.contacts.len(),
+                    snapshot.saved_at
+                );
+                self.snapshot_saved_at = Some(snapshot.saved_at);
+                snapshot.contacts
+            }
+        };
+        for contact in &mut contacts {
+            contact.system_prompt = contact.notes.as_deref().and_then(system_prompt_from_note);
+            contact.allowed_tools = contact.notes.as_deref().and_then(allowed_tools_from_note);
+            contact.workdir = contact.notes.as_deref().and_then(workdir_from_note);
+        }
+        let overrides = TierOverrides::load(&self.config)?;
+        overrides.apply(&mut contacts);
+
+        self.cache = index_contacts(contacts);
+
+        self.loaded_at = Some((self.now)());
+        self.overrides_modified = overrides.modified;
+        self.generation += 1;
+        Ok(self.cache.len())
+    }
+
+    /// The saved snapshot, unless it's missing, unreadable, or too old
+    fn fresh_snapshot(&self) -> Option<ContactsSnapshot> {
+        let snapshot = match ContactsSnapshot::load(&self.config.contacts_snapshot_file) {
+            Ok(snapshot) => snapshot?,
+            Err(e) => {
+                warn!("Failed to read contacts snapshot: {}", e);
+                return None;
+            }
+        };
+        let max_age = chrono::Duration::hours(self.config.contacts_snapshot_max_age_hours as i64);
+        if Utc::now() - snapshot.saved_at > max_age {
+            warn!("Contacts snapshot from {} is too old to use", snapshot.saved_at);
+            return None;
+        }
+        Some(snapshot)
+    }
+
+    /// When the contacts in use were saved, if the source failed and they came from the snapshot
+    pub fn snapshot_in_use(&self) -> Option<DateTime<Utc>> {
+        self.snapshot_saved_at
+    }
+
+    /// Contacts from `contacts list --json`
+    fn load_cli(&self) -> Result<Vec<Contact>> {
         let output = Command::new(&self.config.contacts_cli)
             .arg("list")
             .arg("--json")
@@ -51,43 +223,33 @@ impl ContactsManager {
         let contacts: Vec<serde_json::Value> = serde_json::from_str(&stdout)
             .map_err(|e| Error::Parse(format!("contacts JSON: {}", e)))?;
 
-        self.cache.clear();
-
-        for c in contacts {
-            let name = c["name"].as_str().unwrap_or("").to_string();
-            let phone = c["phone"].as_str().map(|s| normalize_phone(s));
-            let email = c["email"].as_str().map(|s| s.to_lowercase());
-            let tier = c["tier"].as_str().unwrap_or("unknown").to_string();
-
-            let contact = Contact {
-                name: name.clone(),
-                phone: phone.clone(),
-                email: email.clone(),
-                tier,
-            };
-
-            // Index by phone
-            if let Some(ref p) = phone {
-                self.cache.insert(p.clone(), contact.clone());
-            }
-
-            // Index by email
-            if let Some(ref e) = email {
-                self.cache.insert(e.clone(), contact.clone());
-            }
-
-            // Index by name (lowercase)
-            self.cache.insert(name.to_lowercase(), contact);
-        }
-
-        self.loaded = true;
-        Ok(self.cache.len())
+        Ok(contacts
+            .into_iter()
+            .map(|c| Contact {
+                name: c["name"].as_str().unwrap_or("").to_string(),
+                phone: c["phone"].as_str().map(|p| normalize_phone(p, &self.config.default_region)),
+                email: c["email"].as_str().map(|s| s.to_lowercase()),
+                tier: c["tier"].as_str().unwrap_or("unknown").to_string(),
+                notes: c["notes"].as_str().filter(|n| !n.trim().is_empty()).map(str::to_string),
+                system_prompt: None,
+                allowed_tools: None,
+                alias: c["alias"].as_str().map(str::trim).filter(|a| !a.is_empty()).map(str::to_string),
+                workdir: None,
+                id: match &c["id"] {
+                    serde_json::Value::String(id) => Some(id.clone()),
+                    serde_json::Value::Number(id) => Some(id.to_string()),
+                    _ => None,
+                },
+            })
+            .collect())
     }
 
-    /// Ensure cache is loaded
+    /// Ensure cache is loaded, refreshing it if stale or the overrides were edited
     fn ensure_loaded(&mut self) -> Result<()> {
-        if !self.loaded {
+        if self.loaded_at.is_none() || modified_time(&self.config.tier_overrides_file) != self.overrides_modified {
             self.load()?;
+        } else {
+            self.refresh_if_stale();
         }
         Ok(())
     }
@@ -95,7 +257,7 @@ impl ContactsManager {
     /// Lookup contact by phone number
     pub fn lookup_phone(&mut self, phone: &str) -> Result<Option<Contact>> {
         self.ensure_loaded()?;
-        let normalized = normalize_phone(phone);
+        let normalized = normalize_phone(phone, &self.config.default_region);
         Ok(self.cache.get(&normalized).cloned())
     }
 
@@ -105,112 +267,1626 @@ impl ContactsManager {
         Ok(self.cache.get(&email.to_lowercase()).cloned())
     }
 
-    /// Lookup contact by phone OR email (for Messages.app identifiers)
-    pub fn lookup_identifier(&mut self, identifier: &str) -> Result<Option<Contact>> {
-        // Try phone first
-        if let Some(contact) = self.lookup_phone(identifier)? {
-            return Ok(Some(contact));
-        }
-        // Try email if it contains @
+    /// Lookup contact by name
+    pub fn lookup_name(&mut self, name: &str) -> Result<Option<Contact>> {
+        self.ensure_loaded()?;
+        Ok(self.cache.get(&name.to_lowercase()).cloned())
+    }
+}
+
+impl ContactSource for ContactsManager {
+    fn lookup_identifier(&mut self, identifier: &str) -> Result<Option<Contact>> {
+        // Apple ID emails would normalize to a bare "+" as a phone number
         if identifier.contains('@') {
             return self.lookup_email(identifier);
         }
-        Ok(None)
+        self.lookup_phone(identifier)
     }
 
-    /// Lookup contact by name
-    pub fn lookup_name(&mut self, name: &str) -> Result<Option<Contact>> {
+    fn list_blessed(&mut self) -> Result<Vec<Contact>> {
         self.ensure_loaded()?;
-        Ok(self.cache.get(&name.to_lowercase()).cloned())
+        Ok(blessed_contacts(&self.cache, &self.config))
     }
 
-    /// Get all blessed contacts (admin, wife, family, favorite)
-    pub fn list_blessed(&mut self) -> Result<Vec<Contact>> {
+    fn search(&mut self, query: &str) -> Result<Vec<Contact>> {
         self.ensure_loaded()?;
-        let blessed: Vec<Contact> = self
-            .cache
-            .values()
-            .filter(|c| BLESSED_TIERS.contains(&c.tier.as_str()))
-            .cloned()
-            .collect();
+        Ok(search_contacts(&self.cache, query))
+    }
+
+    fn refresh(&mut self) -> Result<usize> {
+        self.loaded_at = None;
+        self.load()
+    }
+
+    /// True if the identifier, or any handle or name of the contact behind it,
+    /// is on the blocklist, or the contact's tier is overridden to blocked.
+    fn is_blocked(&mut self, identifier: &str) -> bool {
+        self.blocklist.reload_if_changed(&self.config);
+        if self.blocklist.contains(identifier) {
+            return true;
+        }
+        match self.lookup_identifier(identifier) {
+            Ok(Some(contact)) => {
+                contact.tier == BLOCKED_TIER
+                    || [contact.phone.as_deref(), contact.email.as_deref(), Some(contact.name.as_str())]
+                        .into_iter()
+                        .flatten()
+                        .any(|key| self.blocklist.contains(key))
+            }
+            _ => false,
+        }
+    }
+
+    fn is_blessed_group(&mut self, chat_id: &str) -> bool {
+        self.blessed_groups.reload_if_changed(&self.config);
+        self.blessed_groups.contains(chat_id)
+    }
+
+    /// Reloaded once older than the TTL
+    ///
+    /// A failed reload keeps serving the old cache and waits another TTL before
+    /// retrying, rather than failing every lookup until the source recovers.
+    fn refresh_if_stale(&mut self) -> bool {
+        let now = (self.now)();
+        match self.loaded_at {
+            Some(at) if now.duration_since(at) < self.ttl => return false,
+            None => return false,
+            Some(_) => {}
+        }
+
+        match self.load() {
+            Ok(count) => {
+                info!("Refreshed contacts cache ({} entries)", count);
+                true
+            }
+            Err(e) => {
+                warn!("Contacts refresh failed, keeping cached contacts: {}", e);
+                self.loaded_at = Some(now);
+                false
+            }
+        }
+    }
+
+    /// Number of successful loads so far
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn is_blessed_tier(&self, tier: &str) -> bool {
+        self.config.is_blessed_tier(tier)
+    }
+}
+
+/// A fixed set of contacts held in memory, for tests
+///
+/// Lookups follow the same rules as `ContactsManager`; the block list and blessed
+/// groups are plain sets instead of files.
+#[cfg(any(test, feature = "test-support"))]
+pub struct StaticContacts {
+    config: Config,
+    cache: HashMap<String, Contact>,
+    blocked: BTreeSet<String>,
+    blessed_groups: BTreeSet<String>,
+    generation: u64,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl StaticContacts {
+    pub fn new(config: &Config, contacts: Vec<Contact>) -> Self {
+        Self {
+            config: config.clone(),
+            cache: index_contacts(contacts),
+            blocked: BTreeSet::new(),
+            blessed_groups: BTreeSet::new(),
+            generation: 1,
+        }
+    }
+
+    /// Replace every contact, as if the source had been edited and reloaded
+    pub fn set(&mut self, contacts: Vec<Contact>) {
+        self.cache = index_contacts(contacts);
+        self.generation += 1;
+    }
+
+    pub fn block(&mut self, identifier: &str) {
+        self.blocked.insert(override_key(identifier, &self.config.default_region));
+    }
+
+    pub fn bless_group(&mut self, chat_id: &str) {
+        self.blessed_groups.insert(chat_id.trim().to_lowercase());
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl ContactSource for StaticContacts {
+    fn lookup_identifier(&mut self, identifier: &str) -> Result<Option<Contact>> {
+        let key = if identifier.contains('@') {
+            identifier.to_lowercase()
+        } else {
+            normalize_phone(identifier, &self.config.default_region)
+        };
+        Ok(self.cache.get(&key).cloned())
+    }
+
+    fn list_blessed(&mut self) -> Result<Vec<Contact>> {
+        Ok(blessed_contacts(&self.cache, &self.config))
+    }
+
+    fn search(&mut self, query: &str) -> Result<Vec<Contact>> {
+        Ok(search_contacts(&self.cache, query))
+    }
+
+    fn refresh(&mut self) -> Result<usize> {
+        Ok(self.cache.len())
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn is_blocked(&mut self, identifier: &str) -> bool {
+        let region = self.config.default_region.clone();
+        if self.blocked.contains(&override_key(identifier, &region)) {
+            return true;
+        }
+        match self.lookup_identifier(identifier) {
+            Ok(Some(contact)) => {
+                contact.tier == BLOCKED_TIER
+                    || [contact.phone.as_deref(), contact.email.as_deref(), Some(contact.name.as_str())]
+                        .into_iter()
+                        .flatten()
+                        .any(|key| self.blocked.contains(&override_key(key, &region)))
+            }
+            _ => false,
+        }
+    }
 
-        // Dedupe by name
-        let mut seen = std::collections::HashSet::new();
-        let deduped: Vec<Contact> = blessed
+    fn is_blessed_group(&mut self, chat_id: &str) -> bool {
+        self.blessed_groups.contains(&chat_id.trim().to_lowercase())
+    }
+
+    fn is_blessed_tier(&self, tier: &str) -> bool {
+        self.config.is_blessed_tier(tier)
+    }
+}
+
+/// Index contacts by phone, email, and lowercase name
+fn index_contacts(contacts: Vec<Contact>) -> HashMap<String, Contact> {
+    let mut cache = HashMap::new();
+    for contact in contacts {
+        // Index by phone
+        if let Some(ref p) = contact.phone {
+            cache.insert(p.clone(), contact.clone());
+        }
+
+        // Index by email
+        if let Some(ref e) = contact.email {
+            cache.insert(e.clone(), contact.clone());
+        }
+
+        // Index by name (lowercase)
+        cache.insert(contact.name.to_lowercase(), contact);
+    }
+    cache
+}
+
+/// Blessed contacts in an index, deduped by name
+fn blessed_contacts(cache: &HashMap<String, Contact>, config: &Config) -> Vec<Contact> {
+    let mut seen = std::collections::HashSet::new();
+    cache
+        .values()
+        .filter(|c| config.is_blessed_tier(&c.tier))
+        .filter(|c| seen.insert(c.name.clone()))
+        .cloned()
+        .collect()
+}
+
+/// Contacts in an index matching a search, best match first, one per person
+fn search_contacts(cache: &HashMap<String, Contact>, query: &str) -> Vec<Contact> {
+    let mut matches: Vec<(u8, &Contact)> = cache
+        .values()
+        .filter_map(|c| {
+            let rank = std::iter::once(c.name.as_str())
+                .chain(c.alias.as_deref())
+                .filter_map(|name| name_match_rank(query, name))
+                .min()?;
+            Some((rank, c))
+        })
+        .collect();
+    // Phone handles first, so the entry kept for a person is the one texts go to
+    matches.sort_by(|(ra, a), (rb, b)| {
+        (ra, &a.name, a.phone.is_none(), &a.phone, &a.email).cmp(&(rb, &b.name, b.phone.is_none(), &b.phone, &b.email))
+    });
+    let mut found: Vec<Contact> = Vec::new();
+    for (_, contact) in matches {
+        if !found.iter().any(|c| c.is_same_person(contact)) {
+            found.push(contact.clone());
+        }
+    }
+    found
+}
+
+/// How well a name matches a search, ignoring case: 0 for the whole name, 1 for
+/// the first name, 2 for any other part of it, or None if it doesn't match
+pub fn name_match_rank(query: &str, name: &str) -> Option<u8> {
+    let query = query.trim().to_lowercase();
+    let name = name.trim().to_lowercase();
+    if query.is_empty() {
+        None
+    } else if name == query {
+        Some(0)
+    } else if name.split_whitespace().next() == Some(query.as_str()) {
+        Some(1)
+    } else if name.contains(&query) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Contacts as last read from the source, before overrides
+#[derive(Debug, Serialize, Deserialize)]
+struct ContactsSnapshot {
+    saved_at: DateTime<Utc>,
+    contacts: Vec<Contact>,
+}
+
+impl ContactsSnapshot {
+    fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    /// Save atomically, stamped with the current time
+    fn save(path: &Path, contacts: &[Contact]) -> Result<()> {
+        let snapshot = ContactsSnapshot {
+            saved_at: Utc::now(),
+            contacts: contacts.to_vec(),
+        };
+        write_json_atomic(path, &snapshot)
+    }
+}
+
+/// Local settings for one identifier
+///
+/// Stored as a bare tier string, or as an object for anything more:
+/// `{"tier": "family", "system_prompt": "...", "alias": "...", "allowed_tools": "...", "workdir": "..."}`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredOverride", into = "StoredOverride")]
+pub struct ContactOverride {
+    pub tier: Option<String>,
+    pub system_prompt: Option<String>,
+    pub alias: Option<String>,
+    /// Empty means the tier's tools, even over a note's ALLOWED_TOOLS line
+    pub allowed_tools: Option<String>,
+    /// Empty means the tier's workdir, even over a note's WORKDIR line
+    pub workdir: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredOverride {
+    Tier(String),
+    Full {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tier: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        system_prompt: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        alias: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        allowed_tools: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workdir: Option<String>,
+    },
+}
+
+impl From<StoredOverride> for ContactOverride {
+    fn from(stored: StoredOverride) -> Self {
+        match stored {
+            StoredOverride::Tier(tier) => Self {
+                tier: Some(tier),
+                ..Self::default()
+            },
+            StoredOverride::Full {
+                tier,
+                system_prompt,
+                alias,
+                allowed_tools,
+                workdir,
+            } => Self {
+                tier,
+                system_prompt,
+                alias,
+                allowed_tools,
+                workdir,
+            },
+        }
+    }
+}
+
+impl From<ContactOverride> for StoredOverride {
+    fn from(entry: ContactOverride) -> Self {
+        match entry {
+            ContactOverride {
+                tier: Some(tier),
+                system_prompt: None,
+                alias: None,
+                allowed_tools: None,
+                workdir: None,
+            } => StoredOverride::Tier(tier),
+            ContactOverride {
+                tier,
+                system_prompt,
+                alias,
+                allowed_tools,
+                workdir,
+            } => StoredOverride::Full {
+                tier,
+                system_prompt,
+                alias,
+                allowed_tools,
+                workdir,
+            },
+        }
+    }
+}
+
+/// Local tier assignments, keyed by normalized phone, lowercase email, or lowercase name
+///
+/// Applied on top of the contacts source. `BLOCKED_TIER` beats any other tier
+/// that matches the same person. Entries may also carry a system prompt, which
+/// wins over one from the contact's note.
+pub struct TierOverrides {
+    path: PathBuf,
+    region: String,
+    /// Configured blessed tier names, which `set` accepts along with blocked and unknown
+    blessed: Vec<String>,
+    entries: BTreeMap<String, ContactOverride>,
+    modified: Option<SystemTime>,
+}
+
+impl TierOverrides {
+    pub fn load(config: &Config) -> Result<Self> {
+        let path = config.tier_overrides_file.clone();
+        let modified = modified_time(&path);
+        let entries = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path,
+            region: config.default_region.clone(),
+            blessed: config.tier_names().into_iter().map(str::to_string).collect(),
+            entries,
+            modified,
+        })
+    }
+
+    /// Save atomically
+    pub fn save(&self) -> Result<()> {
+        write_json_atomic(&self.path, &self.entries)
+    }
+
+    /// Set a tier, returning the previous one
+    pub fn set(&mut self, identifier: &str, tier: &str) -> Result<Option<String>> {
+        let tier = tier.trim().to_lowercase();
+        if !self.blessed.contains(&tier) && tier != BLOCKED_TIER && tier != "unknown" {
+            return Err(Error::Config(format!(
+                "unknown tier '{}' (expected one of {}, {}, unknown)",
+                tier,
+                self.blessed.join(", "),
+                BLOCKED_TIER
+            )));
+        }
+        let entry = self.entries.entry(override_key(identifier, &self.region)).or_default();
+        Ok(entry.tier.replace(tier))
+    }
+
+    /// Remove a tier, keeping any other settings for the same identifier
+    pub fn remove(&mut self, identifier: &str) -> Option<String> {
+        let key = override_key(identifier, &self.region);
+        let entry = self.entries.get_mut(&key)?;
+        let tier = entry.tier.take();
+        if entry.system_prompt.is_none() && entry.alias.is_none() && entry.allowed_tools.is_none() && entry.workdir.is_none() {
+            self.entries.remove(&key);
+        }
+        tier
+    }
+
+    pub fn get(&self, identifier: &str) -> Option<&str> {
+        self.entries.get(&override_key(identifier, &self.region))?.tier.as_deref()
+    }
+
+    pub fn system_prompt(&self, identifier: &str) -> Option<&str> {
+        self.entries.get(&override_key(identifier, &self.region))?.system_prompt.as_deref()
+    }
+
+    /// All tier overrides, sorted by key
+    pub fn all(&self) -> BTreeMap<&str, &str> {
+        self.entries
+            .iter()
+            .filter_map(|(key, entry)| Some((key.as_str(), entry.tier.as_deref()?)))
+            .collect()
+    }
+
+    /// Tier for a contact: blocked if any of its keys is blocked, else phone/email before name
+    fn tier_for(&self, contact: &Contact) -> Option<&str> {
+        let keys = [contact.phone.as_deref(), contact.email.as_deref(), Some(contact.name.as_str())];
+        let matches: Vec<&str> = keys
             .into_iter()
-            .filter(|c| seen.insert(c.name.clone()))
+            .flatten()
+            .filter_map(|key| self.get(key))
             .collect();
+        if matches.contains(&BLOCKED_TIER) {
+            return Some(BLOCKED_TIER);
+        }
+        matches.first().copied()
+    }
 
-        Ok(deduped)
+    /// Override tiers and per-contact settings in place, and add contacts for overridden handles the source doesn't know
+    fn apply(&self, contacts: &mut Vec<Contact>) {
+        for contact in contacts.iter_mut() {
+            if let Some(tier) = self.tier_for(contact) {
+                contact.tier = tier.to_string();
+            }
+            let keys = [contact.phone.as_deref(), contact.email.as_deref(), Some(contact.name.as_str())];
+            let entries: Vec<&ContactOverride> = keys
+                .into_iter()
+                .flatten()
+                .filter_map(|key| self.entries.get(&override_key(key, &self.region)))
+                .collect();
+            if let Some(prompt) = entries.iter().find_map(|e| e.system_prompt.as_ref()) {
+                contact.system_prompt = Some(prompt.clone());
+            }
+            if let Some(alias) = entries.iter().find_map(|e| e.alias.as_ref()) {
+                contact.alias = Some(alias.clone());
+            }
+            if let Some(tools) = entries.iter().find_map(|e| e.allowed_tools.as_ref()) {
+                contact.allowed_tools = Some(tools.clone()).filter(|t| !t.trim().is_empty());
+            }
+            if let Some(workdir) = entries.iter().find_map(|e| e.workdir.as_ref()) {
+                contact.workdir = Some(workdir.clone()).filter(|w| !w.trim().is_empty());
+            }
+        }
+        for (key, entry) in &self.entries {
+            let Some(tier) = &entry.tier else { continue };
+            let known = contacts.iter().any(|c| {
+                c.phone.as_deref() == Some(key) || c.email.as_deref() == Some(key) || c.name.to_lowercase() == *key
+            });
+            if known {
+                continue;
+            }
+            let is_email = key.contains('@');
+            if !is_email && !looks_like_phone(key) {
+                continue;
+            }
+            contacts.push(Contact {
+                name: key.clone(),
+                phone: (!is_email).then(|| key.clone()),
+                email: is_email.then(|| key.clone()),
+                tier: tier.clone(),
+                notes: None,
+                system_prompt: entry.system_prompt.clone(),
+                allowed_tools: entry.allowed_tools.clone().filter(|t| !t.trim().is_empty()),
+                alias: entry.alias.clone(),
+                workdir: entry.workdir.clone().filter(|w| !w.trim().is_empty()),
+                id: None,
+            });
+        }
     }
+}
 
-    /// Force refresh the cache
-    pub fn refresh(&mut self) -> Result<usize> {
-        self.loaded = false;
-        self.load()
+/// What a `SetFile` holds: where it's saved and how its entries are compared
+pub trait SetKind {
+    /// Used in log messages
+    const WHAT: &'static str;
+    fn new(config: &Config) -> Self;
+    fn path(config: &Config) -> PathBuf;
+    fn key(&self, item: &str) -> String;
+}
+
+/// Senders, keyed like `TierOverrides`
+pub struct Senders {
+    region: String,
+}
+
+impl SetKind for Senders {
+    const WHAT: &'static str = "blocklist";
+
+    fn new(config: &Config) -> Self {
+        Self { region: config.default_region.clone() }
+    }
+
+    fn path(config: &Config) -> PathBuf {
+        config.blocklist_file.clone()
+    }
+
+    fn key(&self, item: &str) -> String {
+        override_key(item, &self.region)
+    }
+}
+
+/// Chat identifiers, compared case-insensitively
+pub struct Chats;
+
+impl SetKind for Chats {
+    const WHAT: &'static str = "blessed groups";
+
+    fn new(_config: &Config) -> Self {
+        Self
     }
 
-    /// Check if a tier is blessed
-    pub fn is_blessed_tier(tier: &str) -> bool {
-        BLESSED_TIERS.contains(&tier)
+    fn path(config: &Config) -> PathBuf {
+        config.blessed_groups_file.clone()
+    }
+
+    fn key(&self, item: &str) -> String {
+        item.trim().to_lowercase()
+    }
+}
+
+/// A set of identifiers saved as a JSON list
+pub struct SetFile<K: SetKind> {
+    path: PathBuf,
+    kind: K,
+    entries: BTreeSet<String>,
+    modified: Option<SystemTime>,
+}
+
+/// Senders to drop even if blessed
+pub type Blocklist = SetFile<Senders>;
+
+/// Group chats blessed as a whole
+pub type BlessedGroups = SetFile<Chats>;
+
+impl<K: SetKind> SetFile<K> {
+    fn empty(config: &Config) -> Self {
+        Self {
+            path: K::path(config),
+            kind: K::new(config),
+            entries: BTreeSet::new(),
+            modified: None,
+        }
+    }
+
+    pub fn load(config: &Config) -> Result<Self> {
+        let mut set = Self::empty(config);
+        set.modified = modified_time(&set.path);
+        if set.path.exists() {
+            set.entries = serde_json::from_str(&fs::read_to_string(&set.path)?)?;
+        }
+        Ok(set)
+    }
+
+    /// Load again if the file changed since, keeping what we have if that fails
+    fn reload_if_changed(&mut self, config: &Config) {
+        if modified_time(&self.path) == self.modified {
+            return;
+        }
+        match Self::load(config) {
+            Ok(set) => *self = set,
+            Err(e) => warn!("Failed to reload {}, keeping previous: {}", K::WHAT, e),
+        }
+    }
+
+    /// Save atomically
+    pub fn save(&self) -> Result<()> {
+        write_json_atomic(&self.path, &self.entries)
+    }
+
+    /// Returns false if already there
+    pub fn add(&mut self, item: &str) -> bool {
+        self.entries.insert(self.kind.key(item))
+    }
+
+    /// Returns false if it wasn't there
+    pub fn remove(&mut self, item: &str) -> bool {
+        self.entries.remove(&self.kind.key(item))
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.entries.contains(&self.kind.key(item))
+    }
+
+    pub fn all(&self) -> &BTreeSet<String> {
+        &self.entries
+    }
+}
+
+/// Normalize an identifier the way the cache is keyed
+pub fn override_key(identifier: &str, region: &str) -> String {
+    let identifier = identifier.trim();
+    if !identifier.contains('@') && looks_like_phone(identifier) {
+        normalize_phone(identifier, region)
+    } else {
+        identifier.to_lowercase()
+    }
+}
+
+/// Digits and phone punctuation only, with enough digits to be a number
+fn looks_like_phone(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit() || "+-(). ".contains(c))
+        && s.chars().filter(|c| c.is_ascii_digit()).count() >= 7
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Contacts from every AddressBook database under `dir`
+///
+/// A person with several numbers or addresses yields one Contact per handle,
+/// all sharing a name and tier.
+fn load_address_book(dir: &Path, region: &str) -> Result<Vec<Contact>> {
+    let mut databases = Vec::new();
+    find_address_books(dir, &mut databases);
+    if databases.is_empty() {
+        return Err(Error::Config(format!("no {} under {}", ADDRESS_BOOK_DB, dir.display())));
+    }
+
+    let mut contacts = Vec::new();
+    for db in databases {
+        contacts.extend(read_address_book(&db, region)?);
+    }
+    Ok(contacts)
+}
+
+/// Collect AddressBook databases: `dir` itself and `Sources/<account>/`
+fn find_address_books(dir: &Path, found: &mut Vec<PathBuf>) {
+    let top = dir.join(ADDRESS_BOOK_DB);
+    if top.is_file() {
+        found.push(top);
+    }
+    if let Ok(entries) = std::fs::read_dir(dir.join("Sources")) {
+        let mut sources: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().join(ADDRESS_BOOK_DB))
+            .filter(|p| p.is_file())
+            .collect();
+        sources.sort();
+        found.extend(sources);
+    }
+}
+
+fn read_address_book(path: &Path, region: &str) -> Result<Vec<Contact>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    // Z_PK -> (name, tier, note, nickname, unique ID)
+    type Person = (String, String, Option<String>, Option<String>, Option<String>);
+    let mut people: HashMap<i64, Person> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT ZABCDRECORD.Z_PK, ZABCDRECORD.ZFIRSTNAME, ZABCDRECORD.ZLASTNAME,
+                ZABCDRECORD.ZORGANIZATION, ZABCDNOTE.ZTEXT, ZABCDRECORD.ZNICKNAME, ZABCDRECORD.ZUNIQUEID
+         FROM ZABCDRECORD
+         LEFT JOIN ZABCDNOTE ON ZABCDNOTE.ZCONTACT = ZABCDRECORD.Z_PK",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, Option<String>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+        ))
+    })?;
+    for row in rows {
+        let (pk, first, last, organization, note, nickname, unique_id) = row?;
+        let name = [first, last]
+            .into_iter()
+            .flatten()
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let name = match (name.is_empty(), organization) {
+            (false, _) => name,
+            (true, Some(org)) if !org.trim().is_empty() => org.trim().to_string(),
+            _ => continue,
+        };
+        let tier = note.as_deref().and_then(tier_from_note).unwrap_or_else(|| "unknown".to_string());
+        let note = note.filter(|n| !n.trim().is_empty());
+        let nickname = nickname.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+        people.insert(pk, (name, tier, note, nickname, unique_id));
+    }
+
+    let mut contacts = Vec::new();
+    let handles = [
+        ("SELECT ZOWNER, ZFULLNUMBER FROM ZABCDPHONENUMBER ORDER BY Z_PK", true),
+        ("SELECT ZOWNER, ZADDRESS FROM ZABCDEMAILADDRESS ORDER BY Z_PK", false),
+    ];
+    for (sql, is_phone) in handles {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<String>>(1)?)))?;
+        for row in rows {
+            let (Some(owner), Some(value)) = row? else { continue };
+            let Some((name, tier, note, nickname, unique_id)) = people.get(&owner) else { continue };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            contacts.push(Contact {
+                name: name.clone(),
+                phone: is_phone.then(|| normalize_phone(value, region)),
+                email: (!is_phone).then(|| value.to_lowercase()),
+                tier: tier.clone(),
+                notes: note.clone(),
+                system_prompt: None,
+                allowed_tools: None,
+                alias: nickname.clone(),
+                workdir: None,
+                id: unique_id.clone(),
+            });
+        }
+    }
+    Ok(contacts)
+}
+
+/// The tier named by a "tier:xyz" token in a contact note
+fn tier_from_note(note: &str) -> Option<String> {
+    note.split_whitespace().find_map(|word| {
+        let (key, value) = word.split_once(':')?;
+        if !key.eq_ignore_ascii_case("tier") {
+            return None;
+        }
+        let tier: String = value
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+            .collect::<String>()
+            .to_lowercase();
+        (!tier.is_empty()).then_some(tier)
+    })
+}
+
+/// The prompt from a "SYSTEM_PROMPT: ..." line in a contact note
+///
+/// Following lines that start with whitespace continue it, so a longer prompt
+/// can be wrapped; they're joined with newlines.
+pub fn system_prompt_from_note(note: &str) -> Option<String> {
+    let mut lines = note.lines();
+    let first = lines.by_ref().find_map(|line| line.strip_prefix("SYSTEM_PROMPT:"))?;
+    let mut parts = vec![first.trim()];
+    parts.extend(
+        lines
+            .take_while(|line| line.starts_with([' ', '\t']) && !line.trim().is_empty())
+            .map(str::trim),
+    );
+    let prompt = parts.into_iter().filter(|p| !p.is_empty()).collect::<Vec<_>>().join("\n");
+    (!prompt.is_empty()).then_some(prompt)
+}
+
+/// The tools from an "ALLOWED_TOOLS: ..." line in a contact note, e.g. "Read,Grep,Bash"
+pub fn allowed_tools_from_note(note: &str) -> Option<String> {
+    note.lines()
+        .find_map(|line| line.strip_prefix("ALLOWED_TOOLS:"))
+        .map(str::trim)
+        .filter(|tools| !tools.is_empty())
+        .map(str::to_string)
+}
+
+/// The directory from a "WORKDIR: ..." line in a contact note, e.g. "~/family-stuff"
+pub fn workdir_from_note(note: &str) -> Option<String> {
+    note.lines()
+        .find_map(|line| line.strip_prefix("WORKDIR:"))
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .map(str::to_string)
+}
+
+/// A Messages handle or chat identifier, by kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identifier {
+    /// E.164 phone number
+    Phone(String),
+    /// Apple ID email, lowercased
+    Email(String),
+    /// SMS short code ("22395") or alphanumeric sender ID ("AMAZON"), never a person
+    ShortCode(String),
+    /// Group chat ID ("chat123456789" or a hex GUID), lowercased
+    GroupUuid(String),
+}
+
+impl Identifier {
+    pub fn parse(raw: &str, region: &str) -> Self {
+        let raw = raw.trim();
+
+        // Apple ID email handles pass through (case-insensitive)
+        if raw.contains('@') {
+            return Identifier::Email(raw.to_lowercase());
+        }
+
+        // Group IDs: "chat" plus digits, or a GUID (20+ hex chars)
+        let is_chat_number = raw
+            .strip_prefix("chat")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+        if is_chat_number || (raw.len() >= 20 && raw.chars().all(|c| c.is_ascii_hexdigit())) {
+            return Identifier::GroupUuid(raw.to_lowercase());
+        }
+
+        // Sender IDs with letters, or nothing dialable at all, are kept as given
+        if raw.chars().any(char::is_alphabetic) || !raw.chars().any(|c| c.is_ascii_digit()) {
+            return Identifier::ShortCode(raw.to_string());
+        }
+
+        let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+        if !raw.starts_with('+') && digits.len() <= SHORT_CODE_MAX_DIGITS {
+            // Bare digits, which is how Messages stores them
+            return Identifier::ShortCode(digits);
+        }
+        Identifier::Phone(e164(raw, &digits, region))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Identifier::Phone(s) | Identifier::Email(s) | Identifier::ShortCode(s) | Identifier::GroupUuid(s) => s,
+        }
+    }
+
+    pub fn into_string(self) -> String {
+        match self {
+            Identifier::Phone(s) | Identifier::Email(s) | Identifier::ShortCode(s) | Identifier::GroupUuid(s) => s,
+        }
+    }
+
+    pub fn is_short_code(&self) -> bool {
+        matches!(self, Identifier::ShortCode(_))
     }
 }
 
 /// Normalize phone number to E.164 format
-pub fn normalize_phone(phone: &str) -> String {
-    // Remove all non-digit characters except leading +
-    let has_plus = phone.starts_with('+');
+///
+/// Numbers without a country code are read as dialed from `region` (an ISO 3166
+/// code like "GB"), so "07911 123456" becomes "+447911123456" for a UK owner.
+/// Short codes stay bare digits, which is how Messages stores them, and sender
+/// IDs with letters ("AMAZON") are returned unchanged. Anything that doesn't
+/// parse as a valid number falls back to assuming NANP lengths.
+pub fn normalize_phone(phone: &str, region: &str) -> String {
+    let phone = phone.trim();
+    if phone.chars().any(char::is_alphabetic) || !phone.chars().any(|c| c.is_ascii_digit()) {
+        return phone.to_string();
+    }
+
     let digits: String = phone.chars().filter(|c| c.is_ascii_digit()).collect();
+    if !phone.starts_with('+') && digits.len() <= SHORT_CODE_MAX_DIGITS {
+        return digits;
+    }
+    e164(phone, &digits, region)
+}
+
+/// A number with more digits than a short code, in E.164
+fn e164(phone: &str, digits: &str, region: &str) -> String {
+    let country = region.trim().to_uppercase().parse::<phonenumber::country::Id>().ok();
+    if let Ok(number) = phonenumber::parse(country, phone) {
+        if phonenumber::is_valid(&number) {
+            return number.format().mode(phonenumber::Mode::E164).to_string();
+        }
+    }
 
-    if has_plus {
+    if phone.starts_with('+') || digits.len() != 10 {
+        // 11-digit US numbers already carry the leading 1
         format!("+{}", digits)
-    } else if digits.len() == 10 {
+    } else {
         // Assume US number
         format!("+1{}", digits)
-    } else if digits.len() == 11 && digits.starts_with('1') {
-        format!("+{}", digits)
-    } else {
-        format!("+{}", digits)
     }
 }
 
+/// Normalize a Messages chat identifier: emails and group IDs lowercased, phones to
+/// E.164, short codes and sender IDs as given
+pub fn normalize_chat_id(chat_id: &str, region: &str) -> String {
+    Identifier::parse(chat_id, region).into_string()
+}
+
+/// Stand in for the contacts CLI with a script that prints `json`
+///
+/// The JSON is kept in `contacts.json` beside the script, whose path is
+/// returned so a test can change what the next refresh sees.
+#[cfg(any(test, feature = "test-support"))]
+pub fn fake_contacts_cli(config: &Config, json: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let contacts_json = config.contacts_cli.with_file_name("contacts.json");
+    fs::write(&contacts_json, json).expect("couldn't write contacts.json");
+    fs::write(&config.contacts_cli, format!("#!/bin/sh\ncat '{}'\n", contacts_json.display())).expect("couldn't write the contacts CLI");
+    fs::set_permissions(&config.contacts_cli, fs::Permissions::from_mode(0o755)).expect("couldn't make the contacts CLI executable");
+    contacts_json
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_normalize_phone_e164() {
-        assert_eq!(normalize_phone("+16175551234"), "+16175551234");
+        assert_eq!(normalize_phone("+16175551234", "US"), "+16175551234");
     }
 
     #[test]
     fn test_normalize_phone_with_spaces() {
-        assert_eq!(normalize_phone("+1 617 555 1234"), "+16175551234");
+        assert_eq!(normalize_phone("+1 617 555 1234", "US"), "+16175551234");
     }
 
     #[test]
     fn test_normalize_phone_with_dashes() {
-        assert_eq!(normalize_phone("617-555-1234"), "+16175551234");
+        assert_eq!(normalize_phone("617-555-1234", "US"), "+16175551234");
     }
 
     #[test]
     fn test_normalize_phone_10_digit() {
-        assert_eq!(normalize_phone("6175551234"), "+16175551234");
+        assert_eq!(normalize_phone("6175551234", "US"), "+16175551234");
     }
 
     #[test]
     fn test_normalize_phone_11_digit() {
-        assert_eq!(normalize_phone("16175551234"), "+16175551234");
+        assert_eq!(normalize_phone("16175551234", "US"), "+16175551234");
+    }
+
+    #[test]
+    fn test_normalize_phone_gb_local() {
+        assert_eq!(normalize_phone("07911 123456", "GB"), "+447911123456");
+        assert_eq!(normalize_phone("+44 7911 123456", "GB"), "+447911123456");
+        assert_eq!(normalize_phone("020 7946 0958", "gb"), "+442079460958");
+        // A country code wins over the region
+        assert_eq!(normalize_phone("+1 617 555 1234", "GB"), "+16175551234");
+    }
+
+    #[test]
+    fn test_normalize_phone_de_local() {
+        assert_eq!(normalize_phone("0151 23456789", "DE"), "+4915123456789");
+        assert_eq!(normalize_phone("030 901820", "DE"), "+4930901820");
+        assert_eq!(normalize_phone("+49 30 901820", "US"), "+4930901820");
+    }
+
+    #[test]
+    fn test_normalize_phone_short_codes() {
+        assert_eq!(normalize_phone("262966", "US"), "262966");
+        assert_eq!(normalize_phone("88088", "GB"), "88088");
+        assert_eq!(normalize_phone("22 22 22", "DE"), "222222");
+    }
+
+    #[test]
+    fn test_normalize_phone_fallback() {
+        // Unknown region and unparseable numbers keep the old heuristic
+        assert_eq!(normalize_phone("617-555-1234", "XX"), "+16175551234");
+        assert_eq!(normalize_phone("+999 1234 5678", "US"), "+99912345678");
+    }
+
+    #[test]
+    fn test_lookup_uses_default_region() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.default_region = "GB".to_string();
+        fake_contacts_cli(&config, r#"[{"name": "Nigel Smith", "phone": "07911 123456", "tier": "family"}]"#);
+        let mut manager = ContactsManager::new(&config);
+
+        let contact = manager.lookup_identifier("+447911123456").unwrap().unwrap();
+        assert_eq!(contact.name, "Nigel Smith");
+        assert_eq!(contact.phone.as_deref(), Some("+447911123456"));
+    }
+
+    /// ContactsManager backed by a stand-in contacts CLI that prints `json`
+    fn manager_with_contacts(dir: &std::path::Path, json: &str) -> ContactsManager {
+        let config = Config::for_test(dir);
+        fake_contacts_cli(&config, json);
+        ContactsManager::new(&config)
+    }
+
+    #[test]
+    fn test_lookup_identifier_phone_and_email() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[
+                {"name": "John Doe", "phone": "617-555-1234", "tier": "admin"},
+                {"name": "Jane Doe", "phone": "", "email": "Jane.Doe@iCloud.com", "tier": "wife"}
+            ]"#,
+        );
+
+        let john = contacts.lookup_identifier("+16175551234").unwrap().unwrap();
+        assert_eq!(john.name, "John Doe");
+        let jane = contacts.lookup_identifier("jane.doe@icloud.com").unwrap().unwrap();
+        assert_eq!(jane.name, "Jane Doe");
+        assert_eq!(jane.tier, "wife");
+        assert!(contacts.lookup_identifier("nobody@example.com").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_search() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[
+                {"name": "John Doe", "phone": "617-555-1234", "email": "john@example.com", "tier": "admin"},
+                {"name": "Jane Doe", "phone": "617-555-5678", "tier": "wife"},
+                {"name": "Johnny Appleseed", "phone": "617-555-0000", "tier": "unknown"}
+            ]"#,
+        );
+        let names = |found: Vec<Contact>| found.into_iter().map(|c| c.name).collect::<Vec<_>>();
+
+        // One entry per person even though each is cached under several keys
+        assert_eq!(names(contacts.search("jane").unwrap()), vec!["Jane Doe"]);
+        assert_eq!(names(contacts.search("DOE").unwrap()), vec!["Jane Doe", "John Doe"]);
+        // A first name beats a longer name that merely contains it
+        assert_eq!(names(contacts.search("john").unwrap()), vec!["John Doe", "Johnny Appleseed"]);
+        assert_eq!(names(contacts.search(" john doe ").unwrap()), vec!["John Doe"]);
+        assert!(contacts.search("zed").unwrap().is_empty());
+        assert!(contacts.search("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_name_match_rank() {
+        assert_eq!(name_match_rank("jane doe", "Jane Doe"), Some(0));
+        assert_eq!(name_match_rank("Jane", "Jane Doe"), Some(1));
+        assert_eq!(name_match_rank("ski", "Ski Trip 2026"), Some(1));
+        assert_eq!(name_match_rank("trip", "Ski Trip 2026"), Some(2));
+        assert_eq!(name_match_rank("bob", "Jane Doe"), None);
+        assert_eq!(name_match_rank("  ", "Jane Doe"), None);
+    }
+
+    /// (first, last, note, phones, emails)
+    type Person<'a> = (&'a str, &'a str, Option<&'a str>, &'a [&'a str], &'a [&'a str]);
+
+    /// Minimal Contacts.app database with the tables we read
+    fn create_address_book(path: &Path, people: &[Person]) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ZABCDRECORD (Z_PK INTEGER PRIMARY KEY, ZFIRSTNAME TEXT, ZLASTNAME TEXT, ZORGANIZATION TEXT,
+                                       ZNICKNAME TEXT, ZUNIQUEID TEXT);
+             CREATE TABLE ZABCDPHONENUMBER (Z_PK INTEGER PRIMARY KEY, ZOWNER INTEGER, ZFULLNUMBER TEXT, ZLABEL TEXT);
+             CREATE TABLE ZABCDEMAILADDRESS (Z_PK INTEGER PRIMARY KEY, ZOWNER INTEGER, ZADDRESS TEXT, ZLABEL TEXT);
+             CREATE TABLE ZABCDNOTE (Z_PK INTEGER PRIMARY KEY, ZCONTACT INTEGER, ZTEXT TEXT);",
+        )
+        .unwrap();
+        for (first, last, note, phones, emails) in people {
+            conn.execute(
+                "INSERT INTO ZABCDRECORD (ZFIRSTNAME, ZLASTNAME) VALUES (?1, NULLIF(?2, ''))",
+                [first, last],
+            )
+            .unwrap();
+            let owner = conn.last_insert_rowid();
+            conn.execute(
+                "UPDATE ZABCDRECORD SET ZUNIQUEID = ?1 || ':ABPerson' WHERE Z_PK = ?2",
+                rusqlite::params![format!("{}-{}", first, last), owner],
+            )
+            .unwrap();
+            if let Some(note) = note {
+                conn.execute("INSERT INTO ZABCDNOTE (ZCONTACT, ZTEXT) VALUES (?1, ?2)", rusqlite::params![owner, note])
+                    .unwrap();
+            }
+            for phone in *phones {
+                conn.execute(
+                    "INSERT INTO ZABCDPHONENUMBER (ZOWNER, ZFULLNUMBER, ZLABEL) VALUES (?1, ?2, '_$!<Mobile>!$_')",
+                    rusqlite::params![owner, phone],
+                )
+                .unwrap();
+            }
+            for email in *emails {
+                conn.execute(
+                    "INSERT INTO ZABCDEMAILADDRESS (ZOWNER, ZADDRESS) VALUES (?1, ?2)",
+                    rusqlite::params![owner, email],
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_address_book_backend() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.contacts_backend = ContactsBackend::AddressBook;
+        create_address_book(
+            &config.address_book_dir.join("Sources/4F1C-ICLOUD").join(ADDRESS_BOOK_DB),
+            &[
+                ("John", "Doe", Some("Met at work.\ntier:admin"), &["(617) 555-1234", "617.555.9999"], &[]),
+                ("Jane", "Doe", Some("TIER:Family"), &[], &["Jane.Doe@iCloud.com"]),
+                ("Sam", "", None, &["+44 20 7946 0958"], &[]),
+            ],
+        );
+        Connection::open(config.address_book_dir.join("Sources/4F1C-ICLOUD").join(ADDRESS_BOOK_DB))
+            .unwrap()
+            .execute("UPDATE ZABCDRECORD SET ZNICKNAME = 'Janey' WHERE ZFIRSTNAME = 'Jane'", [])
+            .unwrap();
+        let mut contacts = ContactsManager::new(&config);
+
+        let john = contacts.lookup_identifier("+16175551234").unwrap().unwrap();
+        assert_eq!(john.name, "John Doe");
+        assert_eq!(john.tier, "admin");
+        assert_eq!(contacts.lookup_phone("6175559999").unwrap().unwrap().name, "John Doe");
+        let jane = contacts.lookup_identifier("jane.doe@icloud.com").unwrap().unwrap();
+        assert_eq!(jane.tier, "family");
+        assert_eq!(jane.notes.as_deref(), Some("TIER:Family"));
+        assert_eq!(jane.alias.as_deref(), Some("Janey"));
+        assert_eq!(john.alias, None);
+        // One person's handles share an ID
+        assert!(john.is_same_person(&contacts.lookup_phone("6175559999").unwrap().unwrap()));
+        assert!(!john.is_same_person(&jane));
+        let sam = contacts.lookup_identifier("+442079460958").unwrap().unwrap();
+        assert_eq!(sam.name, "Sam");
+        assert_eq!(sam.tier, "unknown");
+
+        let blessed: Vec<String> = contacts.list_blessed().unwrap().into_iter().map(|c| c.name).collect();
+        assert_eq!(blessed.len(), 2);
+        assert!(blessed.contains(&"John Doe".to_string()));
+    }
+
+    #[test]
+    fn test_address_book_matches_cli_normalization() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut cli = manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "John Doe", "phone": "1 (617) 555-1234", "email": "JOHN@Example.com", "tier": "admin"}]"#,
+        );
+        let mut config = Config::for_test(temp.path());
+        config.contacts_backend = ContactsBackend::AddressBook;
+        create_address_book(
+            &config.address_book_dir.join(ADDRESS_BOOK_DB),
+            &[("John", "Doe", Some("tier:admin"), &["1 (617) 555-1234"], &["JOHN@Example.com"])],
+        );
+        let mut book = ContactsManager::new(&config);
+
+        for handle in ["+16175551234", "617-555-1234", "john@example.com"] {
+            assert_eq!(
+                cli.lookup_identifier(handle).unwrap().map(|c| (c.name, c.tier)),
+                book.lookup_identifier(handle).unwrap().map(|c| (c.name, c.tier)),
+                "{}",
+                handle
+            );
+        }
+    }
+
+    #[test]
+    fn test_address_book_missing() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.contacts_backend = ContactsBackend::AddressBook;
+        let err = ContactsManager::new(&config).load().unwrap_err();
+        assert!(matches!(err, Error::Config(_)));
+    }
+
+    #[test]
+    fn test_tier_from_note() {
+        assert_eq!(tier_from_note("tier:favorite"), Some("favorite".to_string()));
+        assert_eq!(tier_from_note("College roommate. Tier:Family, call on Sundays"), Some("family".to_string()));
+        assert_eq!(tier_from_note("no tier here"), None);
+        assert_eq!(tier_from_note("tier:"), None);
+    }
+
+    /// Point a manager at a clock that only moves when the returned handle is advanced
+    fn fake_clock(contacts: &mut ContactsManager) -> std::sync::Arc<std::sync::Mutex<Instant>> {
+        let now = std::sync::Arc::new(std::sync::Mutex::new(Instant::now()));
+        let handle = now.clone();
+        contacts.now = Box::new(move || *handle.lock().unwrap());
+        now
+    }
+
+    #[test]
+    fn test_stale_cache_refreshes() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "John Doe", "phone": "+16175551234", "tier": "favorite"}]"#,
+        );
+        let clock = fake_clock(&mut contacts);
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "favorite");
+
+        // Promoted while the daemon is running
+        std::fs::write(
+            temp.path().join("contacts.json"),
+            r#"[{"name": "John Doe", "phone": "+16175551234", "tier": "family"}]"#,
+        )
+        .unwrap();
+        *clock.lock().unwrap() += Duration::from_secs(60);
+        assert!(!contacts.refresh_if_stale());
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "favorite");
+
+        // Lookups refresh transparently once past the TTL
+        *clock.lock().unwrap() += contacts.ttl;
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "family");
+        assert!(!contacts.refresh_if_stale());
+    }
+
+    #[test]
+    fn test_failed_refresh_keeps_stale_cache() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "John Doe", "phone": "+16175551234", "tier": "admin"}]"#,
+        );
+        let clock = fake_clock(&mut contacts);
+        contacts.load().unwrap();
+
+        // The CLI breaks
+        std::fs::write(temp.path().join("contacts.json"), "not json").unwrap();
+        *clock.lock().unwrap() += contacts.ttl + Duration::from_secs(1);
+        assert!(!contacts.refresh_if_stale());
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "admin");
+
+        // Not retried until another TTL has passed, then recovers
+        std::fs::write(
+            temp.path().join("contacts.json"),
+            r#"[{"name": "John Doe", "phone": "+16175551234", "tier": "wife"}]"#,
+        )
+        .unwrap();
+        assert!(!contacts.refresh_if_stale());
+        *clock.lock().unwrap() += contacts.ttl;
+        assert!(contacts.refresh_if_stale());
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "wife");
+    }
+
+    #[test]
+    fn test_load_saves_snapshot() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "John Doe", "phone": "617-555-1234", "tier": "admin"}]"#,
+        );
+        contacts.load().unwrap();
+        assert!(contacts.snapshot_in_use().is_none());
+
+        let snapshot = ContactsSnapshot::load(&temp.path().join("state/contacts_cache.json"))
+            .unwrap()
+            .unwrap();
+        assert!(Utc::now() - snapshot.saved_at < chrono::Duration::minutes(1));
+        assert_eq!(snapshot.contacts.len(), 1);
+        assert_eq!(snapshot.contacts[0].phone.as_deref(), Some("+16175551234"));
+    }
+
+    #[test]
+    fn test_failed_load_serves_fresh_snapshot() {
+        let temp = tempfile::TempDir::new().unwrap();
+        manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "John Doe", "phone": "+16175551234", "tier": "admin"}]"#,
+        )
+        .load()
+        .unwrap();
+        std::fs::write(
+            temp.path().join("state/tier_overrides.json"),
+            r#"{"+16175551234": "family"}"#,
+        )
+        .unwrap();
+
+        // After a restart the CLI has lost its permissions
+        std::fs::write(temp.path().join("contacts.json"), "not json").unwrap();
+        let mut contacts = ContactsManager::new(&Config::for_test(temp.path()));
+        contacts.load().unwrap();
+        assert!(contacts.snapshot_in_use().is_some());
+        // Overrides still apply on top of the snapshot
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "family");
+    }
+
+    #[test]
+    fn test_failed_load_refuses_expired_snapshot() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(temp.path(), "not json");
+        assert!(contacts.load().is_err());
+
+        let old = ContactsSnapshot {
+            saved_at: Utc::now() - chrono::Duration::hours(73),
+            contacts: vec![Contact {
+                name: "John Doe".to_string(),
+                phone: Some("+16175551234".to_string()),
+                email: None,
+                tier: "admin".to_string(),
+                notes: None,
+                system_prompt: None,
+                allowed_tools: None,
+                alias: None,
+                workdir: None,
+                id: None,
+            }],
+        };
+        std::fs::create_dir_all(temp.path().join("state")).unwrap();
+        std::fs::write(
+            temp.path().join("state/contacts_cache.json"),
+            serde_json::to_string(&old).unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(contacts.load(), Err(Error::Parse(_))));
+        assert!(contacts.lookup_phone("+16175551234").is_err());
+    }
+
+    #[test]
+    fn test_tier_override_precedence() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[
+                {"name": "John Doe", "phone": "617-555-1234", "tier": "favorite"},
+                {"name": "Jane Doe", "email": "jane@example.com", "tier": "admin"}
+            ]"#,
+        );
+        let config = Config::for_test(temp.path());
+        let mut overrides = TierOverrides::load(&config).unwrap();
+        overrides.set("(617) 555-1234", "family").unwrap();
+        overrides.set("john doe", "admin").unwrap();
+        overrides.set("+1 617 555 0000", "favorite").unwrap();
+        assert!(overrides.set("+16175559999", "bestie").is_err());
+        overrides.save().unwrap();
+
+        // Phone override beats both the CLI tier and the name override
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "family");
+        assert_eq!(contacts.lookup_email("jane@example.com").unwrap().unwrap().tier, "admin");
+        // Someone not in Contacts at all
+        let stranger = contacts.lookup_phone("6175550000").unwrap().unwrap();
+        assert_eq!(stranger.tier, "favorite");
+        assert_eq!(stranger.name, "+16175550000");
+    }
+
+    #[test]
+    fn test_blocked_override_beats_blessed() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "Cousin Eddie", "phone": "+16175551234", "tier": "family"}]"#,
+        );
+        let mut overrides = TierOverrides::load(&Config::for_test(temp.path())).unwrap();
+        overrides.set("+16175551234", "admin").unwrap();
+        overrides.set("Cousin Eddie", BLOCKED_TIER).unwrap();
+        overrides.save().unwrap();
+
+        let eddie = contacts.lookup_phone("+16175551234").unwrap().unwrap();
+        assert_eq!(eddie.tier, BLOCKED_TIER);
+        assert!(!contacts.is_blessed_tier(&eddie.tier));
+        assert!(contacts.list_blessed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tier_overrides_persist_and_reload() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "John Doe", "phone": "+16175551234", "tier": "favorite"}]"#,
+        );
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "favorite");
+
+        let mut overrides = TierOverrides::load(&config).unwrap();
+        assert_eq!(overrides.set("+16175551234", "Wife").unwrap(), None);
+        overrides.save().unwrap();
+
+        let reloaded = TierOverrides::load(&config).unwrap();
+        assert_eq!(reloaded.get("617-555-1234"), Some("wife"));
+        assert_eq!(reloaded.all().len(), 1);
+        // A running manager notices the edit without waiting for its TTL
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "wife");
+
+        let mut overrides = reloaded;
+        assert_eq!(overrides.remove("+16175551234").as_deref(), Some("wife"));
+        overrides.save().unwrap();
+        assert!(TierOverrides::load(&config).unwrap().all().is_empty());
+    }
+
+    #[test]
+    fn test_blocklist() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[
+                {"name": "Cousin Eddie", "phone": "+16175551234", "email": "eddie@example.com", "tier": "family"},
+                {"name": "Jane Doe", "phone": "+16175559876", "tier": "wife"}
+            ]"#,
+        );
+        assert!(!contacts.is_blocked("+16175551234"));
+
+        // Blocking by name covers every handle on the card
+        let mut blocklist = Blocklist::load(&config).unwrap();
+        assert!(blocklist.add("Cousin Eddie"));
+        assert!(!blocklist.add("cousin eddie"));
+        assert!(blocklist.add("(555) 010-9999"));
+        blocklist.save().unwrap();
+
+        assert!(contacts.is_blocked("+16175551234"));
+        assert!(contacts.is_blocked("Eddie@Example.com"));
+        assert!(contacts.is_blocked("+15550109999"));
+        assert!(!contacts.is_blocked("+16175559876"));
+        // Still a family contact; blocking is checked separately from tiers
+        assert_eq!(contacts.lookup_phone("+16175551234").unwrap().unwrap().tier, "family");
+
+        let mut blocklist = Blocklist::load(&config).unwrap();
+        assert_eq!(blocklist.all().len(), 2);
+        assert!(blocklist.remove("cousin eddie"));
+        assert!(!blocklist.remove("cousin eddie"));
+        blocklist.save().unwrap();
+        assert!(!contacts.is_blocked("+16175551234"));
+
+        // A blocked tier override counts too
+        let mut overrides = TierOverrides::load(&config).unwrap();
+        overrides.set("+16175559876", BLOCKED_TIER).unwrap();
+        overrides.save().unwrap();
+        assert!(contacts.is_blocked("+16175559876"));
+    }
+
+    #[test]
+    fn test_system_prompt_from_note() {
+        assert_eq!(system_prompt_from_note("Likes tea"), None);
+        assert_eq!(
+            system_prompt_from_note("Mom\nSYSTEM_PROMPT: Use big text.\nREMINDER: 0 9 * * * | Call"),
+            Some("Use big text.".to_string())
+        );
+        // Indented lines continue the prompt
+        assert_eq!(
+            system_prompt_from_note("SYSTEM_PROMPT: Use big text.\n  No jargon.\n\tNever run shell commands.\nOther note"),
+            Some("Use big text.\nNo jargon.\nNever run shell commands.".to_string())
+        );
+        assert_eq!(
+            system_prompt_from_note("SYSTEM_PROMPT:\n  Starts on the next line"),
+            Some("Starts on the next line".to_string())
+        );
+        assert_eq!(system_prompt_from_note("SYSTEM_PROMPT:   \nnot indented"), None);
+    }
+
+    #[test]
+    fn test_system_prompt_from_notes_and_overrides() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[
+                {"name": "Mom", "phone": "+16175551234", "tier": "family",
+                 "notes": "SYSTEM_PROMPT: Use big text.\n  No jargon."},
+                {"name": "Dad", "phone": "+16175559876", "tier": "family"}
+            ]"#,
+        );
+        let mom = contacts.lookup_phone("+16175551234").unwrap().unwrap();
+        assert_eq!(mom.system_prompt.as_deref(), Some("Use big text.\nNo jargon."));
+        assert_eq!(contacts.lookup_phone("+16175559876").unwrap().unwrap().system_prompt, None);
+
+        // The overrides file takes a bare tier or an object with a prompt
+        let config = Config::for_test(temp.path());
+        std::fs::create_dir_all(temp.path().join("state")).unwrap();
+        std::fs::write(
+            &config.tier_overrides_file,
+            r#"{
+                "+16175551234": {"system_prompt": "Keep it short."},
+                "dad": {"tier": "admin", "system_prompt": "He's an engineer."},
+                "+16175550000": "favorite"
+            }"#,
+        )
+        .unwrap();
+        contacts.refresh().unwrap();
+        let mom = contacts.lookup_phone("+16175551234").unwrap().unwrap();
+        assert_eq!(mom.system_prompt.as_deref(), Some("Keep it short."));
+        assert_eq!(mom.tier, "family");
+        let dad = contacts.lookup_phone("+16175559876").unwrap().unwrap();
+        assert_eq!((dad.tier.as_str(), dad.system_prompt.as_deref()), ("admin", Some("He's an engineer.")));
+
+        // Tier edits keep the prompt, and tier-only entries stay bare strings
+        let mut overrides = TierOverrides::load(&config).unwrap();
+        assert_eq!(overrides.remove("dad").as_deref(), Some("admin"));
+        assert_eq!(overrides.remove("+16175551234"), None);
+        assert_eq!(overrides.system_prompt("Dad"), Some("He's an engineer."));
+        assert_eq!(overrides.all().into_iter().collect::<Vec<_>>(), vec![("+16175550000", "favorite")]);
+        overrides.save().unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&config.tier_overrides_file).unwrap()).unwrap();
+        assert_eq!(saved["+16175550000"], "favorite");
+        assert_eq!(saved["dad"], serde_json::json!({"system_prompt": "He's an engineer."}));
+    }
+
+    #[test]
+    fn test_allowed_tools_from_notes_and_overrides() {
+        assert_eq!(allowed_tools_from_note("Brother\nALLOWED_TOOLS: Read,Grep,Bash\n"), Some("Read,Grep,Bash".to_string()));
+        assert_eq!(allowed_tools_from_note("ALLOWED_TOOLS:   "), None);
+        assert_eq!(allowed_tools_from_note("Likes tea"), None);
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[
+                {"name": "Bro", "phone": "+16175551234", "tier": "family", "notes": "ALLOWED_TOOLS: Read,Bash"},
+                {"name": "Sis", "phone": "+16175559876", "tier": "family", "notes": "ALLOWED_TOOLS: Read,Bash"},
+                {"name": "Mom", "phone": "+16175550000", "tier": "family"}
+            ]"#,
+        );
+        assert_eq!(
+            contacts.lookup_phone("+16175551234").unwrap().unwrap().allowed_tools.as_deref(),
+            Some("Read,Bash")
+        );
+        assert_eq!(contacts.lookup_phone("+16175550000").unwrap().unwrap().allowed_tools, None);
+
+        // An override wins over the note; an empty one falls back to the tier
+        let config = Config::for_test(temp.path());
+        std::fs::create_dir_all(temp.path().join("state")).unwrap();
+        std::fs::write(
+            &config.tier_overrides_file,
+            r#"{
+                "+16175551234": {"allowed_tools": "Read,Grep,Bash"},
+                "sis": {"allowed_tools": ""},
+                "mom": {"tier": "favorite", "allowed_tools": "Read"}
+            }"#,
+        )
+        .unwrap();
+        contacts.refresh().unwrap();
+        let tools = |contacts: &mut ContactsManager, phone: &str| contacts.lookup_phone(phone).unwrap().unwrap().allowed_tools;
+        assert_eq!(tools(&mut contacts, "+16175551234").as_deref(), Some("Read,Grep,Bash"));
+        assert_eq!(tools(&mut contacts, "+16175559876"), None);
+        assert_eq!(tools(&mut contacts, "+16175550000").as_deref(), Some("Read"));
+
+        // Removing the tier keeps the tools
+        let mut overrides = TierOverrides::load(&config).unwrap();
+        assert_eq!(overrides.remove("mom").as_deref(), Some("favorite"));
+        overrides.save().unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&config.tier_overrides_file).unwrap()).unwrap();
+        assert_eq!(saved["mom"], serde_json::json!({"allowed_tools": "Read"}));
+    }
+
+    #[test]
+    fn test_workdir_from_notes_and_overrides() {
+        assert_eq!(workdir_from_note("Wife\nWORKDIR: ~/family-stuff\n"), Some("~/family-stuff".to_string()));
+        assert_eq!(workdir_from_note("WORKDIR:  "), None);
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[
+                {"name": "Wife", "phone": "+16175551234", "tier": "wife", "notes": "WORKDIR: ~/family-stuff"},
+                {"name": "Mom", "phone": "+16175550000", "tier": "family", "notes": "WORKDIR: ~/mom"}
+            ]"#,
+        );
+        let workdir = |contacts: &mut ContactsManager, phone: &str| contacts.lookup_phone(phone).unwrap().unwrap().workdir;
+        assert_eq!(workdir(&mut contacts, "+16175551234").as_deref(), Some("~/family-stuff"));
+
+        // An override wins over the note; an empty one falls back to the tier
+        let config = Config::for_test(temp.path());
+        std::fs::create_dir_all(temp.path().join("state")).unwrap();
+        std::fs::write(&config.tier_overrides_file, r#"{"+16175551234": {"workdir": "~/shared"}, "mom": {"workdir": ""}}"#)
+            .unwrap();
+        contacts.refresh().unwrap();
+        assert_eq!(workdir(&mut contacts, "+16175551234").as_deref(), Some("~/shared"));
+        assert_eq!(workdir(&mut contacts, "+16175550000"), None);
+    }
+
+    #[test]
+    fn test_identifier_classes() {
+        assert_eq!(Identifier::parse("(617) 555-1234", "US"), Identifier::Phone("+16175551234".to_string()));
+        assert_eq!(Identifier::parse("+44 7911 123456", "US"), Identifier::Phone("+447911123456".to_string()));
+        assert_eq!(Identifier::parse(" Jane@iCloud.com", "US"), Identifier::Email("jane@icloud.com".to_string()));
+        assert_eq!(Identifier::parse("22395", "US"), Identifier::ShortCode("22395".to_string()));
+        assert_eq!(Identifier::parse("AMAZON", "US"), Identifier::ShortCode("AMAZON".to_string()));
+        assert_eq!(Identifier::parse("Chase-Alerts", "GB"), Identifier::ShortCode("Chase-Alerts".to_string()));
+        assert_eq!(Identifier::parse("chat123456789", "US"), Identifier::GroupUuid("chat123456789".to_string()));
+        assert_eq!(
+            Identifier::parse("ABC123DEF456789012345", "US"),
+            Identifier::GroupUuid("abc123def456789012345".to_string())
+        );
+        assert!(Identifier::parse("+", "US").is_short_code());
+        assert!(!Identifier::parse("6175551234", "US").is_short_code());
+        assert_eq!(Identifier::parse("6175551234", "US").as_str(), "+16175551234");
+    }
+
+    #[test]
+    fn test_normalize_alphanumeric_senders() {
+        // Previously "+", colliding with every other letters-only sender
+        assert_eq!(normalize_phone("AMAZON", "US"), "AMAZON");
+        assert_eq!(normalize_phone("", "US"), "");
+        assert_eq!(normalize_chat_id("AMAZON", "US"), "AMAZON");
+        assert_eq!(normalize_chat_id("22395", "US"), "22395");
+    }
+
+    #[test]
+    fn test_override_key() {
+        assert_eq!(override_key("(617) 555-1234", "US"), "+16175551234");
+        assert_eq!(override_key(" Jane.Doe@iCloud.com ", "US"), "jane.doe@icloud.com");
+        assert_eq!(override_key("Cousin Eddie", "US"), "cousin eddie");
     }
 
     #[test]
     fn test_is_blessed_tier() {
-        assert!(ContactsManager::is_blessed_tier("admin"));
-        assert!(ContactsManager::is_blessed_tier("wife"));
-        assert!(ContactsManager::is_blessed_tier("family"));
-        assert!(ContactsManager::is_blessed_tier("favorite"));
-        assert!(!ContactsManager::is_blessed_tier("unknown"));
-        assert!(!ContactsManager::is_blessed_tier(""));
+        let contacts = ContactsManager::new(&Config::for_test(&std::env::temp_dir()));
+        assert!(contacts.is_blessed_tier("admin"));
+        assert!(contacts.is_blessed_tier("wife"));
+        assert!(contacts.is_blessed_tier("family"));
+        assert!(contacts.is_blessed_tier("favorite"));
+        assert!(!contacts.is_blessed_tier("unknown"));
+        assert!(!contacts.is_blessed_tier(""));
     }
 
     #[test]
@@ -220,14 +1896,87 @@ mod tests {
             phone: Some("+16175551234".to_string()),
             email: Some("test@example.com".to_string()),
             tier: "admin".to_string(),
+            notes: None,
+            system_prompt: None,
+            allowed_tools: None,
+            alias: None,
+            workdir: None,
+            id: None,
         };
         let c2 = c1.clone();
         assert_eq!(c1, c2);
     }
 
     #[test]
-    fn test_blessed_tiers_constant() {
-        assert_eq!(BLESSED_TIERS.len(), 4);
-        assert!(BLESSED_TIERS.iter().all(|t| !t.is_empty()));
+    fn test_custom_tier_is_blessed() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = manager_with_contacts(
+            temp.path(),
+            r#"[{"name": "Pat Lee", "phone": "+16175551234", "tier": "coworker"}]"#,
+        );
+        assert!(contacts.list_blessed().unwrap().is_empty());
+
+        let mut config = Config::for_test(temp.path());
+        config.tiers.push(crate::config::TierConfig {
+            name: "coworker".to_string(),
+            ..crate::config::TierConfig::restricted()
+        });
+        contacts.config = config.clone();
+        assert!(contacts.is_blessed_tier("coworker"));
+        assert_eq!(contacts.list_blessed().unwrap()[0].name, "Pat Lee");
+
+        let mut overrides = TierOverrides::load(&config).unwrap();
+        assert!(overrides.set("+16175559876", "coworker").is_ok());
+        assert!(TierOverrides::load(&Config::for_test(temp.path()))
+            .unwrap()
+            .set("+16175559876", "coworker")
+            .is_err());
+    }
+
+    #[test]
+    fn test_static_contacts() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let contact = |name: &str, phone: &str, email: Option<&str>, tier: &str| Contact {
+            name: name.to_string(),
+            phone: Some(phone.to_string()),
+            email: email.map(str::to_string),
+            tier: tier.to_string(),
+            notes: None,
+            system_prompt: None,
+            allowed_tools: None,
+            alias: None,
+            workdir: None,
+            id: None,
+        };
+        let mut contacts = StaticContacts::new(
+            &config,
+            vec![
+                contact("Jane Doe", "+16175551234", Some("jane@icloud.com"), "family"),
+                contact("Sam Accountant", "+16175550000", None, "unknown"),
+            ],
+        );
+
+        // Same normalization as the real source
+        assert_eq!(contacts.lookup_identifier("617-555-1234").unwrap().unwrap().name, "Jane Doe");
+        assert_eq!(contacts.lookup_identifier("Jane@iCloud.com").unwrap().unwrap().name, "Jane Doe");
+        assert!(contacts.lookup_identifier("+16175559999").unwrap().is_none());
+
+        let blessed = contacts.list_blessed().unwrap();
+        assert_eq!(blessed.len(), 1);
+        assert_eq!(blessed[0].name, "Jane Doe");
+
+        // Blocking one handle blocks the person
+        assert!(!contacts.is_blocked("jane@icloud.com"));
+        contacts.block("6175551234");
+        assert!(contacts.is_blocked("jane@icloud.com"));
+
+        contacts.bless_group("Chat123");
+        assert!(contacts.is_blessed_group("chat123"));
+
+        let generation = contacts.generation();
+        contacts.set(Vec::new());
+        assert_ne!(contacts.generation(), generation);
+        assert!(contacts.list_blessed().unwrap().is_empty());
     }
 }
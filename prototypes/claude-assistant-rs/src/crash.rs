@@ -0,0 +1,265 @@
+//! Crash reports, saved before the health sweep restarts a session
+//!
+//! Restarting a session throws away the evidence of what went wrong with it.
+//! Just before, the sweep saves a bundle under `logs/crashes/<session>-<time>/`:
+//! why it was restarted and its registry entry (`report.json`), its whole
+//! scrollback (`pane.txt`), its Claude's process tree (`processes.txt`), and
+//! the end of the daemon's log (`daemon.log`). The newest few are kept per
+//! session, for `crashes list` and `crashes show`.
+
+use crate::error::{Error, Result};
+use crate::process::{format_ps, parse_ps, ProcessRow};
+use crate::registry::SessionData;
+use crate::session::SessionManager;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Subdirectory of the logs dir holding crash bundles
+pub const CRASH_DIR: &str = "crashes";
+
+/// Lines of the daemon's log kept in a bundle
+pub const DAEMON_LOG_LINES: usize = 100;
+
+/// How a bundle's directory name ends, after the session name
+const BUNDLE_TIME_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+const REPORT_FILE: &str = "report.json";
+const PANE_FILE: &str = "pane.txt";
+const PROCESSES_FILE: &str = "processes.txt";
+const DAEMON_LOG_FILE: &str = "daemon.log";
+
+/// Where crash bundles are kept under `logs_dir`
+pub fn crashes_dir(logs_dir: &Path) -> PathBuf {
+    logs_dir.join(CRASH_DIR)
+}
+
+/// Why and when a session was restarted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub session: String,
+    pub at: DateTime<Utc>,
+    /// What the health check found, e.g. "stuck"
+    pub reason: String,
+    /// Its registry entry at the time
+    pub registry: Option<SessionData>,
+}
+
+/// Everything saved about a session before it was restarted
+///
+/// Whatever couldn't be gathered, e.g. the pane of a session that's already
+/// gone, is left out rather than failing the whole bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrashBundle {
+    pub report: CrashReport,
+    pub pane: Option<String>,
+    pub processes: Option<Vec<ProcessRow>>,
+    pub daemon_log: Option<String>,
+}
+
+impl CrashBundle {
+    /// Gather what there is to know about a session about to be restarted
+    pub fn collect(
+        session_mgr: &SessionManager,
+        session_name: &str,
+        reason: &str,
+        registry: Option<SessionData>,
+        daemon_log: &Path,
+    ) -> Self {
+        Self {
+            report: CrashReport { session: session_name.to_string(), at: Utc::now(), reason: reason.to_string(), registry },
+            pane: session_mgr.capture_scrollback(session_name).ok(),
+            processes: session_mgr.session_processes(session_name),
+            daemon_log: tail_lines(daemon_log, DAEMON_LOG_LINES),
+        }
+    }
+
+    /// Save under `crashes_dir`, then drop all but the session's newest `keep`
+    pub fn save(&self, crashes_dir: &Path, keep: usize) -> Result<PathBuf> {
+        let dir = crashes_dir.join(bundle_name(&self.report.session, self.report.at));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(REPORT_FILE), serde_json::to_string_pretty(&self.report)?)?;
+        if let Some(pane) = &self.pane {
+            fs::write(dir.join(PANE_FILE), pane)?;
+        }
+        if let Some(processes) = &self.processes {
+            fs::write(dir.join(PROCESSES_FILE), format_ps(processes))?;
+        }
+        if let Some(log) = &self.daemon_log {
+            fs::write(dir.join(DAEMON_LOG_FILE), log)?;
+        }
+        prune(crashes_dir, &self.report.session, keep)?;
+        Ok(dir)
+    }
+
+    /// Read a saved bundle
+    pub fn load(dir: &Path) -> Result<Self> {
+        let report = fs::read_to_string(dir.join(REPORT_FILE))
+            .map_err(|_| Error::Parse(format!("{} isn't a crash bundle", dir.display())))?;
+        let read = |file: &str| fs::read_to_string(dir.join(file)).ok();
+        Ok(Self {
+            report: serde_json::from_str(&report)?,
+            pane: read(PANE_FILE),
+            processes: read(PROCESSES_FILE).map(|text| parse_ps(&text)),
+            daemon_log: read(DAEMON_LOG_FILE),
+        })
+    }
+}
+
+/// A bundle's directory name: the session, then when it was saved
+fn bundle_name(session_name: &str, at: DateTime<Utc>) -> String {
+    format!("{}-{}", session_name, at.format(BUNDLE_TIME_FORMAT))
+}
+
+/// The session and time a bundle's directory name is made of
+///
+/// Session names have hyphens too, so the time is taken off the end.
+fn parse_bundle_name(name: &str) -> Option<(&str, DateTime<Utc>)> {
+    let (rest, time) = name.rsplit_once('-')?;
+    let (session_name, date) = rest.rsplit_once('-')?;
+    let at = NaiveDateTime::parse_from_str(&format!("{}-{}", date, time), BUNDLE_TIME_FORMAT).ok()?;
+    Some((session_name, at.and_utc()))
+}
+
+/// Saved bundles, newest first, as (directory, session, time)
+pub fn list(crashes_dir: &Path) -> Result<Vec<(PathBuf, String, DateTime<Utc>)>> {
+    if !crashes_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut bundles: Vec<(PathBuf, String, DateTime<Utc>)> = fs::read_dir(crashes_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            let (session_name, at) = parse_bundle_name(path.file_name()?.to_str()?)?;
+            Some((path.clone(), session_name.to_string(), at))
+        })
+        .collect();
+    bundles.sort_by_key(|bundle| std::cmp::Reverse(bundle.2));
+    Ok(bundles)
+}
+
+/// Remove all but a session's newest `keep` bundles, returning how many went
+pub fn prune(crashes_dir: &Path, session_name: &str, keep: usize) -> Result<usize> {
+    let stale: Vec<PathBuf> = list(crashes_dir)?
+        .into_iter()
+        .filter(|(_, session, _)| session == session_name)
+        .skip(keep)
+        .map(|(path, _, _)| path)
+        .collect();
+    for path in &stale {
+        fs::remove_dir_all(path)?;
+    }
+    Ok(stale.len())
+}
+
+/// The last `lines` lines of a file, or None if it can't be read
+fn tail_lines(path: &Path, lines: usize) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let all: Vec<&str> = content.lines().collect();
+    let start = all.len().saturating_sub(lines);
+    Some(all[start..].iter().map(|line| format!("{}\n", line)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::registry::SessionRegistry;
+    use crate::runner::FakeTmux;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn bundle(session_name: &str, at: &str) -> CrashBundle {
+        CrashBundle {
+            report: CrashReport {
+                session: session_name.to_string(),
+                at: DateTime::parse_from_rfc3339(at).unwrap().with_timezone(&Utc),
+                reason: "stuck".to_string(),
+                registry: None,
+            },
+            pane: Some("esc to interrupt\n".to_string()),
+            processes: None,
+            daemon_log: None,
+        }
+    }
+
+    #[test]
+    fn test_collect_and_load() {
+        let temp = TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", "Error: Claude session crashed\n$ ");
+        let session_mgr = SessionManager::with_runner(&config, fake.clone());
+        let mut registry = SessionRegistry::new(&config);
+        let entry = registry.register("+16175551111", "pat-smith", "/tmp/t", "individual", None, None, None, None).unwrap();
+        fs::create_dir_all(&config.logs_dir).unwrap();
+        let log = config.logs_dir.join("manager.log");
+        let lines: String = (1..=150).map(|n| format!("line {}\n", n)).collect();
+        fs::write(&log, lines).unwrap();
+
+        let collected = CrashBundle::collect(&session_mgr, "pat-smith", "fatal_error:crashed", Some(entry.clone()), &log);
+        assert_eq!(collected.pane.as_deref(), Some("Error: Claude session crashed\n$ "));
+        assert!(fake.calls_to("capture-pane").iter().any(|call| call.ends_with(&["-S".to_string(), "-".to_string()])));
+        let daemon_log = collected.daemon_log.as_deref().unwrap();
+        assert_eq!(daemon_log.lines().count(), DAEMON_LOG_LINES);
+        assert!(daemon_log.starts_with("line 51\n") && daemon_log.ends_with("line 150\n"));
+        // The fake tmux has no pids to look up
+        assert_eq!(collected.processes, None);
+
+        let dir = collected.save(&crashes_dir(&config.logs_dir), 5).unwrap();
+        assert!(dir.file_name().unwrap().to_str().unwrap().starts_with("pat-smith-"));
+        assert!(!dir.join(PROCESSES_FILE).exists());
+        let loaded = CrashBundle::load(&dir).unwrap();
+        assert_eq!(loaded, collected);
+        assert_eq!(loaded.report.registry.unwrap().chat_id, "+16175551111");
+
+        // A session already gone still gets its reason and the log kept
+        let gone = CrashBundle::collect(&session_mgr, "lee-jones", "session_missing", None, &log);
+        assert_eq!((gone.pane, gone.processes), (None, None));
+        assert!(gone.daemon_log.is_some());
+        assert!(CrashBundle::load(temp.path()).is_err());
+    }
+
+    #[test]
+    fn test_processes_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let mut saved = bundle("pat-smith", "2025-03-04T08:00:00Z");
+        saved.processes = Some(vec![
+            ProcessRow { pid: 500, ppid: 410, rss_kb: 3100, cpu_pct: 0.0 },
+            ProcessRow { pid: 501, ppid: 500, rss_kb: 6291456, cpu_pct: 97.5 },
+        ]);
+        let dir = saved.save(temp.path(), 5).unwrap();
+        assert_eq!(CrashBundle::load(&dir).unwrap().processes, saved.processes);
+    }
+
+    #[test]
+    fn test_bundle_name() {
+        let at = DateTime::parse_from_rfc3339("2025-03-04T08:15:30.250Z").unwrap().with_timezone(&Utc);
+        let name = bundle_name("pat-smith-bg", at);
+        assert_eq!(name, "pat-smith-bg-20250304-081530.250");
+        assert_eq!(parse_bundle_name(&name), Some(("pat-smith-bg", at)));
+        assert_eq!(parse_bundle_name("pat-smith"), None);
+        assert_eq!(parse_bundle_name("notes"), None);
+    }
+
+    #[test]
+    fn test_prune_keeps_newest_per_session() {
+        let temp = TempDir::new().unwrap();
+        for minute in 0..4 {
+            bundle("pat-smith", &format!("2025-03-04T08:0{}:00Z", minute)).save(temp.path(), 2).unwrap();
+        }
+        bundle("al-admin", "2025-03-04T07:00:00Z").save(temp.path(), 2).unwrap();
+        // Not a bundle, so left alone
+        fs::create_dir_all(temp.path().join("notes")).unwrap();
+
+        let bundles = list(temp.path()).unwrap();
+        let names: Vec<&str> = bundles.iter().map(|(path, _, _)| path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["pat-smith-20250304-080300.000", "pat-smith-20250304-080200.000", "al-admin-20250304-070000.000"]);
+        assert_eq!(prune(temp.path(), "pat-smith", 1).unwrap(), 1);
+        assert_eq!(prune(temp.path(), "pat-smith", 1).unwrap(), 0);
+        assert_eq!(list(temp.path()).unwrap().len(), 2);
+        assert!(temp.path().join("notes").exists());
+        assert!(list(&temp.path().join("missing")).unwrap().is_empty());
+    }
+}
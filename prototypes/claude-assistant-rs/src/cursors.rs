@@ -0,0 +1,332 @@
+//! Per-chat ROWID high-water marks
+//!
+//! chat.db is still polled globally from `floor`, but each chat remembers the
+//! last ROWID it processed. A crash after one chat's batch is persisted but
+//! before the next replays nothing for the first chat and skips nothing for
+//! the second.
+//!
+//! ROWIDs aren't stable across an iCloud Messages resync, so the GUIDs of
+//! recently processed messages are remembered too.
+//!
+//! The poll loop also writes a heartbeat, so an idle daemon whose cursors
+//! haven't moved in hours isn't taken for one that was down.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::registry::{write_atomic, write_json_atomic};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// Processed message GUIDs remembered, oldest evicted first
+const RECENT_GUID_CAPACITY: usize = 5000;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CursorState {
+    /// Every row at or below this has been processed in every chat
+    floor: i64,
+    /// Per-chat progress above the floor
+    chats: HashMap<String, i64>,
+    /// GUIDs of recently processed messages, oldest first
+    #[serde(default)]
+    recent_guids: VecDeque<String>,
+}
+
+/// Persistent per-chat_id high-water marks
+pub struct ChatCursors {
+    path: PathBuf,
+    legacy_path: PathBuf,
+    /// When the poll loop last ran, as an RFC 3339 timestamp
+    heartbeat_path: PathBuf,
+    state: CursorState,
+    /// Lookup index over `state.recent_guids`
+    guid_index: HashSet<String>,
+}
+
+impl ChatCursors {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            path: config.cursors_file.clone(),
+            legacy_path: config.state_file.clone(),
+            heartbeat_path: config.state_dir.join("heartbeat"),
+            state: CursorState::default(),
+            guid_index: HashSet::new(),
+        }
+    }
+
+    /// Load cursors from disk, migrating a single-value `last_rowid.txt` if that's all there is
+    ///
+    /// Returns false when no state exists at all, so the caller can pick a starting point.
+    pub fn load(&mut self) -> Result<bool> {
+        if self.path.exists() {
+            let content = fs::read_to_string(&self.path)?;
+            self.state = serde_json::from_str(&content)?;
+            self.guid_index = self.state.recent_guids.iter().cloned().collect();
+            return Ok(true);
+        }
+
+        if self.legacy_path.exists() {
+            let floor = fs::read_to_string(&self.legacy_path)?
+                .trim()
+                .parse()
+                .map_err(|e| Error::Parse(format!("{}: {}", self.legacy_path.display(), e)))?;
+            self.state = CursorState {
+                floor,
+                ..Default::default()
+            };
+            self.save()?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Whether the daemon was last seen more than `max_age` ago
+    ///
+    /// Seen means the last heartbeat or the last cursor save, whichever is later.
+    /// Missing state counts as stale.
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.is_stale_at(SystemTime::now(), max_age)
+    }
+
+    fn is_stale_at(&self, now: SystemTime, max_age: Duration) -> bool {
+        let saved = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        let beat = fs::read_to_string(&self.heartbeat_path)
+            .ok()
+            .and_then(|s| DateTime::parse_from_rfc3339(s.trim()).ok())
+            .map(SystemTime::from);
+        match saved.max(beat) {
+            Some(seen) => now.duration_since(seen).map(|age| age > max_age).unwrap_or(false),
+            None => true,
+        }
+    }
+
+    /// Record that the poll loop is running
+    pub fn beat(&self) -> Result<()> {
+        self.beat_at(Utc::now())
+    }
+
+    fn beat_at(&self, now: DateTime<Utc>) -> Result<()> {
+        write_atomic(&self.heartbeat_path, now.to_rfc3339().as_bytes())
+    }
+
+    /// Save cursors to disk atomically
+    pub fn save(&self) -> Result<()> {
+        write_json_atomic(&self.path, &self.state)
+    }
+
+    /// ROWID to poll chat.db from
+    pub fn floor(&self) -> i64 {
+        self.state.floor
+    }
+
+    /// Whether a chat has already processed this row
+    pub fn is_seen(&self, chat_id: &str, rowid: i64) -> bool {
+        let mark = self.state.chats.get(chat_id).copied().unwrap_or(0);
+        rowid <= self.state.floor.max(mark)
+    }
+
+    /// Record a row as processed for a chat (in memory; call `save` to persist)
+    pub fn advance(&mut self, chat_id: &str, rowid: i64) {
+        let mark = self.state.chats.entry(chat_id.to_string()).or_insert(0);
+        *mark = (*mark).max(rowid);
+    }
+
+    /// Whether a message with this GUID was already processed, whatever its ROWID is now
+    pub fn has_guid(&self, guid: &str) -> bool {
+        self.guid_index.contains(guid)
+    }
+
+    /// Remember a processed message's GUID (in memory; call `save` to persist)
+    pub fn record_guid(&mut self, guid: &str) {
+        if guid.is_empty() || !self.guid_index.insert(guid.to_string()) {
+            return;
+        }
+        self.state.recent_guids.push_back(guid.to_string());
+        while self.state.recent_guids.len() > RECENT_GUID_CAPACITY {
+            if let Some(evicted) = self.state.recent_guids.pop_front() {
+                self.guid_index.remove(&evicted);
+            }
+        }
+    }
+
+    /// Reset if chat.db's newest ROWID is below the floor, as after a resync renumbers rows
+    ///
+    /// Otherwise every poll would ask for rows above a floor nothing will ever reach.
+    /// Remembered GUIDs are kept so re-numbered old messages still aren't replayed.
+    /// Returns true if a reset happened.
+    pub fn reconcile(&mut self, max_rowid: i64) -> bool {
+        if max_rowid >= self.state.floor {
+            return false;
+        }
+        warn!(
+            "chat.db max ROWID {} is below processed floor {}; ROWIDs were reset, restarting from {}",
+            max_rowid, self.state.floor, max_rowid
+        );
+        self.state.floor = max_rowid;
+        self.state.chats.clear();
+        true
+    }
+
+    /// Raise the floor once a whole poll batch is done, dropping marks it covers
+    pub fn set_floor(&mut self, rowid: i64) {
+        if rowid <= self.state.floor {
+            return;
+        }
+        self.state.floor = rowid;
+        self.state.chats.retain(|_, mark| *mark > rowid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_crash_between_chats() {
+        let temp = TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+
+        let mut cursors = ChatCursors::new(&config);
+        assert!(!cursors.load().unwrap());
+        cursors.set_floor(100);
+        cursors.save().unwrap();
+
+        // Batch of rows 101-104 across two chats; chat A finishes and persists...
+        cursors.advance("+16175551234", 101);
+        cursors.advance("+16175551234", 103);
+        cursors.save().unwrap();
+        // ...then chat B's row 102 is processed but we crash before persisting
+        cursors.advance("chat123456789", 102);
+        drop(cursors);
+
+        let mut recovered = ChatCursors::new(&config);
+        assert!(recovered.load().unwrap());
+        assert_eq!(recovered.floor(), 100);
+        // Chat A doesn't replay
+        assert!(recovered.is_seen("+16175551234", 101));
+        assert!(recovered.is_seen("+16175551234", 103));
+        // Chat B doesn't skip
+        assert!(!recovered.is_seen("chat123456789", 102));
+        assert!(!recovered.is_seen("chat123456789", 104));
+    }
+
+    #[test]
+    fn test_set_floor_prunes_marks() {
+        let temp = TempDir::new().unwrap();
+        let mut cursors = ChatCursors::new(&Config::for_test(temp.path()));
+        cursors.advance("a", 5);
+        cursors.advance("b", 12);
+        cursors.set_floor(10);
+
+        assert_eq!(cursors.floor(), 10);
+        assert!(cursors.is_seen("a", 9));
+        assert!(cursors.is_seen("unknown", 10));
+        assert!(!cursors.is_seen("a", 11));
+        assert!(cursors.is_seen("b", 12));
+        assert_eq!(cursors.state.chats.len(), 1);
+
+        // Floor never moves backwards
+        cursors.set_floor(3);
+        assert_eq!(cursors.floor(), 10);
+    }
+
+    #[test]
+    fn test_resync_replay_skipped_by_guid() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut cursors = ChatCursors::new(&config);
+        cursors.set_floor(100);
+        for (rowid, guid) in [(101, "G-101"), (102, "G-102")] {
+            cursors.advance("+16175551234", rowid);
+            cursors.record_guid(guid);
+        }
+        cursors.set_floor(102);
+        cursors.save().unwrap();
+
+        // After a resync the same messages come back with higher ROWIDs
+        let mut reloaded = ChatCursors::new(&config);
+        reloaded.load().unwrap();
+        assert!(!reloaded.is_seen("+16175551234", 250));
+        assert!(reloaded.has_guid("G-101"));
+        assert!(reloaded.has_guid("G-102"));
+        assert!(!reloaded.has_guid("G-251"));
+    }
+
+    #[test]
+    fn test_recent_guids_bounded() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut cursors = ChatCursors::new(&Config::for_test(temp.path()));
+        for i in 0..RECENT_GUID_CAPACITY + 10 {
+            cursors.record_guid(&format!("G-{}", i));
+        }
+        cursors.record_guid("G-20");
+        assert_eq!(cursors.state.recent_guids.len(), RECENT_GUID_CAPACITY);
+        assert!(!cursors.has_guid("G-9"));
+        assert!(cursors.has_guid("G-10"));
+        assert!(cursors.has_guid(&format!("G-{}", RECENT_GUID_CAPACITY + 9)));
+    }
+
+    #[test]
+    fn test_reconcile_rowids_went_backwards() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut cursors = ChatCursors::new(&Config::for_test(temp.path()));
+        cursors.set_floor(5000);
+        cursors.advance("+16175551234", 5003);
+        cursors.record_guid("G-5003");
+
+        assert!(!cursors.reconcile(5003));
+        assert_eq!(cursors.floor(), 5000);
+
+        // Resync renumbered everything down to 1200 rows
+        assert!(cursors.reconcile(1200));
+        assert_eq!(cursors.floor(), 1200);
+        assert!(!cursors.is_seen("+16175551234", 1201));
+        assert!(cursors.has_guid("G-5003"));
+    }
+
+    #[test]
+    fn test_stale_state_detection() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut cursors = ChatCursors::new(&Config::for_test(temp.path()));
+        let window = Duration::from_secs(60 * 60);
+        assert!(cursors.is_stale(window));
+
+        cursors.set_floor(42);
+        cursors.save().unwrap();
+        assert!(!cursors.is_stale(window));
+
+        let later = SystemTime::now() + Duration::from_secs(2 * 60 * 60);
+        assert!(cursors.is_stale_at(later, window));
+        assert!(!cursors.is_stale_at(later, Duration::from_secs(3 * 60 * 60)));
+
+        // Idle, with no messages to move the cursors, but still polling
+        cursors.beat_at(DateTime::from(later) - chrono::Duration::minutes(1)).unwrap();
+        assert!(!cursors.is_stale_at(later, window));
+        assert!(cursors.is_stale_at(later + 2 * window, window));
+    }
+
+    #[test]
+    fn test_migrates_legacy_last_rowid() {
+        let temp = TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        fs::create_dir_all(config.state_file.parent().unwrap()).unwrap();
+        fs::write(&config.state_file, "4242\n").unwrap();
+
+        let mut cursors = ChatCursors::new(&config);
+        assert!(cursors.load().unwrap());
+        assert_eq!(cursors.floor(), 4242);
+        assert!(config.cursors_file.exists());
+
+        // Later loads read the new file even if the legacy one changes
+        fs::write(&config.state_file, "1").unwrap();
+        let mut reloaded = ChatCursors::new(&config);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.floor(), 4242);
+    }
+}
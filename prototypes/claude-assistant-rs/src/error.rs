@@ -16,6 +16,9 @@ pub enum Error {
     #[error("Session not found: {0}")]
     SessionNotFound(String),
 
+    #[error("Session name {0} already belongs to chat {1}")]
+    SessionNameTaken(String, String),
+
     #[error("Contact not found: {0}")]
     ContactNotFound(String),
 
@@ -25,6 +28,9 @@ pub enum Error {
     #[error("Tmux error: {0}")]
     Tmux(String),
 
+    #[error("Injected text never showed up in session {0}")]
+    InjectionNotConfirmed(String),
+
     #[error("Command failed: {0}")]
     CommandFailed(String),
 
@@ -35,6 +41,20 @@ pub enum Error {
     Config(String),
 }
 
+impl Error {
+    /// Whether the error is a momentary condition worth retrying (chat.db busy/locked)
+    /// rather than something that needs attention (database missing, bad schema)
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Sqlite(rusqlite::Error::SqliteFailure(e, _)) => matches!(
+                e.code,
+                rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+            ),
+            _ => false,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[cfg(test)]
@@ -47,6 +67,22 @@ mod tests {
         assert!(err.to_string().contains("test-session"));
     }
 
+    #[test]
+    fn test_error_is_transient() {
+        let busy = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database is locked".to_string()),
+        );
+        assert!(Error::Sqlite(busy).is_transient());
+
+        let cant_open = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+            None,
+        );
+        assert!(!Error::Sqlite(cant_open).is_transient());
+        assert!(!Error::Parse("x".to_string()).is_transient());
+    }
+
     #[test]
     fn test_error_from_io() {
         let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
@@ -1,10 +1,21 @@
 //! Health checking for tmux sessions
 //!
 //! Detects crashes, API errors, and unhealthy session states using regex patterns.
+//! `RestartTracker` backs off restarting a session that keeps failing, and
+//! gives up on it after a few tries. `StuckDetector` catches a session that
+//! matches nothing but has shown the same busy screen for several sweeps, and
+//! `ErrorTracker` one that keeps hitting API errors sweep after sweep.
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use chrono::{DateTime, Local, NaiveTime};
 use once_cell::sync::Lazy;
 use regex::{Regex, RegexSet};
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 /// Result of a health check
 #[derive(Debug, Clone, PartialEq)]
@@ -20,6 +31,18 @@ pub enum UnhealthyReason {
     ApiErrorsPersistent,
     FatalError(String),
     ClaudeNotRunning,
+    /// Busy, with the pane unchanged for several sweeps
+    Stuck,
+    /// Claude and its children using more memory or CPU than allowed
+    ResourceLimit { rss_mb: u64, cpu_pct: f64 },
+    /// The conversation has filled Claude's context; compacting it may be enough
+    ContextExhausted,
+    /// The account is out of usage until its limit resets; a restart won't help
+    UsageLimited,
+    /// Claude is logged out, at its login or onboarding screen; only a person can fix it
+    AuthRequired,
+    /// tmux's server isn't running, so neither is any session
+    NoServer,
 }
 
 impl std::fmt::Display for UnhealthyReason {
@@ -29,13 +52,21 @@ impl std::fmt::Display for UnhealthyReason {
             UnhealthyReason::ApiErrorsPersistent => write!(f, "api_errors_persistent"),
             UnhealthyReason::FatalError(pattern) => write!(f, "fatal_error:{}", pattern),
             UnhealthyReason::ClaudeNotRunning => write!(f, "claude_not_running"),
+            UnhealthyReason::Stuck => write!(f, "stuck"),
+            UnhealthyReason::ResourceLimit { rss_mb, cpu_pct } => {
+                write!(f, "resource_limit:{}MB,{:.0}%cpu", rss_mb, cpu_pct)
+            }
+            UnhealthyReason::ContextExhausted => write!(f, "context_exhausted"),
+            UnhealthyReason::UsageLimited => write!(f, "usage_limited"),
+            UnhealthyReason::AuthRequired => write!(f, "auth_required"),
+            UnhealthyReason::NoServer => write!(f, "no_server"),
         }
     }
 }
 
 /// API error patterns that may be transient
 static API_ERROR_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
-    RegexSet::new(&[
+    RegexSet::new([
         r"API Error[:\s]\(?(\d{3})",
         r"overloaded_error",
         r"rate_limit_error",
@@ -85,24 +116,392 @@ static FATAL_PATTERNS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
     ]
 });
 
+/// Warnings in the status line under Claude's input box when a conversation is nearly out of context
+static CONTEXT_STATUS_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([r"(?i)context low\b", r"(?i)run /compact to"]).expect("Invalid context regex")
+});
+
+/// Errors Claude replies with when a conversation has run out of context
+static CONTEXT_ERROR_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"(?i)^\s*⎿\s+(error:\s*)?prompt is too long",
+        r"(?i)^\s*⎿\s+(error:\s*)?conversation too long",
+    ])
+    .expect("Invalid context regex")
+});
+
+/// Banner lines Claude shows when the account has used up its allowance
+///
+/// Anchored to the `⎿` or `⏺` Claude starts the line with, so a contact
+/// asking about their phone plan's usage limit doesn't match.
+static USAGE_LIMIT_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"(?i)^\s*[⎿⏺]\s+claude (ai )?usage limit reached",
+        r"(?i)^\s*⎿\s+you('ve| have) (hit|reached) your (\w+ )?(usage )?limit",
+        r"(?i)^\s*⎿\s+[\w-]+ limit reached\b.*\bresets?\b",
+    ])
+    .expect("Invalid usage limit regex")
+});
+
+/// Claude's login screen and its onboarding, which take the whole pane
+static LOGIN_SCREEN_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"(?i)select login method",
+        r"(?i)claude account with subscription",
+        r"(?i)anthropic console account",
+        r"(?i)paste code here if prompted",
+        r"(?i)choose the text style",
+    ])
+    .expect("Invalid auth regex")
+});
+
+/// Errors Claude replies with once its login has lapsed
+static AUTH_ERROR_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"(?i)^\s*⎿\s+.*·\s*please run /login",
+        r"(?i)^\s*⎿\s+api error: 401\b.*oauth token (has )?(expired|been revoked)",
+    ])
+    .expect("Invalid auth regex")
+});
+
+/// A line of conversation: an echoed prompt or Claude's reply
+static CONVERSATION: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(>|⏺)\s").expect("Invalid conversation regex"));
+
+/// When a usage limit resets, e.g. "resets 3pm" or "reset at 15:30"
+static USAGE_RESET_TIME: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\bresets?\s+(?:at\s+)?(\d{1,2})(?::(\d{2}))?\s*([ap]m)?\b").expect("Invalid usage reset regex")
+});
+
+/// Older releases give the reset as unix seconds: "Claude AI usage limit reached|1718900000"
+static USAGE_RESET_EPOCH: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)limit reached\|(\d{9,11})\b").expect("Invalid usage reset regex"));
+
+/// A line Claude echoes a prompt on, above its reply
+static PROMPT_ECHO: Lazy<Regex> = Lazy::new(|| Regex::new(r"^>\s").expect("Invalid prompt echo regex"));
+
+/// The bottom edge of Claude's input box; its status line is below
+static INPUT_BOX_BOTTOM: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*╰─").expect("Invalid input box regex"));
+
+/// Footer and spinner lines Claude shows only while it's working
+static BUSY_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"(?i)esc to interrupt",
+        r"^\s*[·✢✳✶✻✽*]\s+\S+…",
+    ])
+    .expect("Invalid busy regex")
+});
+
+/// Parts of Claude's UI that only show once its input box is up
+static READY_PATTERNS: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"Welcome to Claude",
+        r"\? for shortcuts",
+        r"(?m)^\s*│\s*>",
+        r"(?i)bypass permissions on",
+    ])
+    .expect("Invalid ready regex")
+});
+
+/// The parts of a busy line that tick over while Claude is hung as much as while it works:
+/// the spinner glyph, the seconds elapsed, and the token count
+static BUSY_COUNTERS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*[·✢✳✶✻✽*]\s+|\b\d+(\.\d+)?[smh]\b|[↑↓]?\s*[\d.,]+k?\s+tokens\b").expect("Invalid busy counter regex")
+});
+
+/// Lines at the bottom of the pane where the busy footer appears
+const BUSY_FOOTER_LINES: usize = 10;
+
 /// Shell prompt patterns (session ended, claude not running)
 static SHELL_PROMPTS: &[char] = &['$', '%', '>', '#'];
 
-/// Check if session content indicates unhealthy state
-pub fn check_session_content(content: &str) -> HealthStatus {
-    // Check for API errors (only unhealthy if persistent)
-    let api_error_count = API_ERROR_PATTERNS.matches(content).iter().count();
-    if api_error_count >= 3 {
-        return HealthStatus::Unhealthy(UnhealthyReason::ApiErrorsPersistent);
+/// Where in a capture `check_session_content_with_context` looks for errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthCheckOptions {
+    /// Only the bottom this many lines, where a real crash would show
+    pub tail_lines: usize,
+    /// Skip anything above Claude's input box, which is still up under its own responses
+    pub skip_above_input: bool,
+}
+
+impl Default for HealthCheckOptions {
+    fn default() -> Self {
+        Self { tail_lines: 15, skip_above_input: true }
     }
+}
+
+/// Fatal and API error patterns a health check looks for
+///
+/// The built-in ones can be added to or replaced by `health_patterns.toml`
+/// in the assistant dir, as Claude Code's error messages change:
+///
+/// ```toml
+/// # Use only the patterns below, not the built-in ones too
+/// replace = false
+/// api = ["rate limit reached"]
+///
+/// [[fatal]]
+/// name = "context_low"
+/// pattern = "Context low"
+/// ```
+#[derive(Debug, Clone)]
+pub struct HealthPatterns {
+    /// Each with the name reported when it matches
+    fatal: Vec<(Regex, String)>,
+    api: RegexSet,
+}
 
-    // Check for fatal errors
-    for (pattern, name) in FATAL_PATTERNS.iter() {
-        if pattern.is_match(content) {
-            return HealthStatus::Unhealthy(UnhealthyReason::FatalError(name.to_string()));
+/// One pattern that matched, and the first line it matched on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternMatch {
+    /// "fatal" or "api"
+    pub kind: &'static str,
+    /// The fatal pattern's name, or the API pattern itself
+    pub name: String,
+    pub line: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatternsFile {
+    #[serde(default)]
+    replace: bool,
+    #[serde(default)]
+    api: Vec<String>,
+    #[serde(default)]
+    fatal: Vec<NamedPattern>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NamedPattern {
+    name: String,
+    pattern: String,
+}
+
+static BUILTIN_PATTERNS: Lazy<HealthPatterns> = Lazy::new(HealthPatterns::builtin);
+
+impl Default for HealthPatterns {
+    fn default() -> Self {
+        BUILTIN_PATTERNS.clone()
+    }
+}
+
+impl HealthPatterns {
+    /// The patterns compiled in
+    pub fn builtin() -> Self {
+        Self {
+            fatal: FATAL_PATTERNS.iter().map(|(regex, name)| (regex.clone(), name.to_string())).collect(),
+            api: API_ERROR_PATTERNS.clone(),
+        }
+    }
+
+    /// The built-in patterns with those in `path` added, or in their place if it says so
+    ///
+    /// A missing file leaves the built-in ones; a bad regex in it is an error naming the pattern.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::builtin());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Self::from_toml(&content).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Patterns from the contents of a `health_patterns.toml`
+    pub fn from_toml(content: &str) -> std::result::Result<Self, String> {
+        let file: PatternsFile = toml::from_str(content).map_err(|e| e.to_string())?;
+        let builtin = Self::builtin();
+        let (mut fatal, mut api) = if file.replace {
+            (Vec::new(), Vec::new())
+        } else {
+            (builtin.fatal, builtin.api.patterns().to_vec())
+        };
+
+        for named in file.fatal {
+            let regex = Regex::new(&named.pattern)
+                .map_err(|e| format!("fatal pattern '{}' ({}) isn't a valid regex: {}", named.name, named.pattern, e))?;
+            fatal.push((regex, named.name));
         }
+        for pattern in &file.api {
+            Regex::new(pattern).map_err(|e| format!("API pattern '{}' isn't a valid regex: {}", pattern, e))?;
+        }
+        api.extend(file.api);
+        let api = RegexSet::new(&api).map_err(|e| format!("API patterns: {}", e))?;
+        Ok(Self { fatal, api })
+    }
+
+    /// How many fatal and API patterns there are
+    pub fn counts(&self) -> (usize, usize) {
+        (self.fatal.len(), self.api.len())
     }
 
+    /// Check if session content indicates unhealthy state, looking anywhere in it
+    pub fn check(&self, content: &str) -> HealthStatus {
+        if let Some(reason) = self.error_in(content) {
+            return HealthStatus::Unhealthy(reason);
+        }
+        claude_status(content)
+    }
+
+    /// Check session content, looking for errors only where a crash would actually show
+    ///
+    /// A crash leaves its error at the bottom of the pane, below Claude's input
+    /// box if it was up. An error with the input box still under it is part of
+    /// the conversation, e.g. Claude explaining a panic, so isn't counted.
+    ///
+    /// Being logged out, hitting a usage limit, and running out of context are
+    /// told apart first, even if a pattern would call them fatal, as a restart
+    /// doesn't help.
+    pub fn check_with_context(&self, content: &str, opts: &HealthCheckOptions) -> HealthStatus {
+        if auth_required(content, opts) {
+            return HealthStatus::Unhealthy(UnhealthyReason::AuthRequired);
+        }
+        if usage_limit_banner(content, opts).is_some() {
+            return HealthStatus::Unhealthy(UnhealthyReason::UsageLimited);
+        }
+        let lines: Vec<&str> = content.trim_end().lines().collect();
+        let mut tail = tail_of(&lines, opts.tail_lines);
+        // Only where Claude says so itself, not in the conversation, which may well mention it
+        let status = tail.iter().rposition(|line| INPUT_BOX_BOTTOM.is_match(line)).map_or(&[][..], |bottom| &tail[bottom + 1..]);
+        if status.iter().any(|line| CONTEXT_STATUS_PATTERNS.is_match(line))
+            || latest_reply_lines(content, opts).iter().any(|line| CONTEXT_ERROR_PATTERNS.is_match(line))
+        {
+            return HealthStatus::Unhealthy(UnhealthyReason::ContextExhausted);
+        }
+        if opts.skip_above_input {
+            if let Some(input) = tail.iter().rposition(|line| READY_PATTERNS.is_match(line)) {
+                tail = &tail[input + 1..];
+            }
+        }
+        if let Some(reason) = self.error_in(&tail.join("\n")) {
+            return HealthStatus::Unhealthy(reason);
+        }
+        claude_status(content)
+    }
+
+    /// Whether the bottom of the pane shows an API error
+    ///
+    /// Claude reports these and retries with its input box still up, so unlike
+    /// crashes they're looked for above it too.
+    pub fn has_api_error(&self, content: &str, opts: &HealthCheckOptions) -> bool {
+        let lines: Vec<&str> = content.trim_end().lines().collect();
+        self.api.is_match(&tail_of(&lines, opts.tail_lines).join("\n"))
+    }
+
+    /// Every pattern that matches somewhere in `content`
+    pub fn matches(&self, content: &str) -> Vec<PatternMatch> {
+        let first_line = |regex: &Regex| content.lines().find(|line| regex.is_match(line)).map(str::to_string);
+        let mut found = Vec::new();
+        for (regex, name) in &self.fatal {
+            if regex.is_match(content) {
+                let line = first_line(regex).unwrap_or_default();
+                found.push(PatternMatch { kind: "fatal", name: name.clone(), line });
+            }
+        }
+        for i in self.api.matches(content).iter() {
+            let pattern = &self.api.patterns()[i];
+            let line = Regex::new(pattern).ok().and_then(|regex| first_line(&regex)).unwrap_or_default();
+            found.push(PatternMatch { kind: "api", name: pattern.clone(), line });
+        }
+        found
+    }
+
+    /// A persistent API error or fatal error in `text`
+    fn error_in(&self, text: &str) -> Option<UnhealthyReason> {
+        // Check for API errors (only unhealthy if persistent)
+        let api_error_count = self.api.matches(text).iter().count();
+        if api_error_count >= 3 {
+            return Some(UnhealthyReason::ApiErrorsPersistent);
+        }
+
+        // Check for fatal errors
+        self.fatal
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(text))
+            .map(|(_, name)| UnhealthyReason::FatalError(name.clone()))
+    }
+}
+
+/// Check if session content indicates unhealthy state, with the built-in patterns
+///
+/// Looks for errors anywhere in `content`, so a conversation that mentions
+/// one reads as unhealthy; the health sweep uses
+/// `check_session_content_with_context`.
+pub fn check_session_content(content: &str) -> HealthStatus {
+    BUILTIN_PATTERNS.check(content)
+}
+
+/// Check session content with the built-in patterns, looking only where a crash would show
+pub fn check_session_content_with_context(content: &str, opts: &HealthCheckOptions) -> HealthStatus {
+    BUILTIN_PATTERNS.check_with_context(content, opts)
+}
+
+/// Whether the bottom of the pane shows an API error, with the built-in patterns
+pub fn has_api_error(content: &str, opts: &HealthCheckOptions) -> bool {
+    BUILTIN_PATTERNS.has_api_error(content, opts)
+}
+
+/// Claude's reply to the latest prompt, if it's a usage limit banner
+///
+/// The reply is everything from the last echoed prompt down, so once another
+/// prompt goes in, an old banner above it no longer counts. Only a line of
+/// the reply is matched, never the prompt, and the text returned tells one
+/// banner from the next.
+pub fn usage_limit_banner(content: &str, opts: &HealthCheckOptions) -> Option<String> {
+    let latest = latest_reply_lines(content, opts);
+    latest.iter().any(|line| USAGE_LIMIT_PATTERNS.is_match(line)).then(|| latest.join("\n"))
+}
+
+/// Whether Claude is at its login or onboarding screen, or replied that its login lapsed
+///
+/// The screens count only with no conversation on the pane, and the errors
+/// only as Claude's reply, so a contact texting "please run /login" doesn't.
+fn auth_required(content: &str, opts: &HealthCheckOptions) -> bool {
+    let lines: Vec<&str> = content.trim_end().lines().collect();
+    let tail = tail_of(&lines, opts.tail_lines);
+    let screen = !tail.iter().any(|line| CONVERSATION.is_match(line))
+        && tail.iter().any(|line| LOGIN_SCREEN_PATTERNS.is_match(line));
+    screen || latest_reply_lines(content, opts).iter().any(|line| AUTH_ERROR_PATTERNS.is_match(line))
+}
+
+/// The tail of the pane from the last echoed prompt down, or all of it if there's none
+fn latest_reply_lines<'a>(content: &'a str, opts: &HealthCheckOptions) -> Vec<&'a str> {
+    let mut lines: Vec<&str> = content.trim_end().lines().collect();
+    lines.drain(..lines.len().saturating_sub(opts.tail_lines));
+    lines.drain(..lines.iter().rposition(|line| PROMPT_ECHO.is_match(line)).unwrap_or(0));
+    lines
+}
+
+/// When the usage limit in a banner resets, if it says
+///
+/// A time of day is the next one after `now`, in local time.
+pub fn usage_limit_reset(banner: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    if let Some(captures) = USAGE_RESET_EPOCH.captures(banner) {
+        let secs: i64 = captures[1].parse().ok()?;
+        return Some(DateTime::from_timestamp(secs, 0)?.with_timezone(&Local));
+    }
+    let captures = USAGE_RESET_TIME.captures(banner)?;
+    let mut hour: u32 = captures[1].parse().ok()?;
+    let minute: u32 = captures.get(2).map_or(Some(0), |m| m.as_str().parse().ok())?;
+    match captures.get(3).map(|m| m.as_str().to_lowercase()).as_deref() {
+        Some("am") if hour == 12 => hour = 0,
+        Some("pm") if hour < 12 => hour += 12,
+        _ => {}
+    }
+    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+    let today = now.date_naive().and_time(time).and_local_timezone(Local).earliest()?;
+    if today > now {
+        Some(today)
+    } else {
+        (now.date_naive().succ_opt()?).and_time(time).and_local_timezone(Local).earliest()
+    }
+}
+
+fn tail_of<'a>(lines: &'a [&'a str], count: usize) -> &'a [&'a str] {
+    &lines[lines.len().saturating_sub(count)..]
+}
+
+/// Healthy unless the pane is back at a shell prompt
+fn claude_status(content: &str) -> HealthStatus {
     // Check if claude is still running (shell prompt without claude activity)
     let content_stripped = content.trim();
     let ends_with_prompt = SHELL_PROMPTS
@@ -116,15 +515,610 @@ pub fn check_session_content(content: &str) -> HealthStatus {
     HealthStatus::Healthy
 }
 
+/// Whether Claude has started far enough to take input
+pub fn is_ready_content(content: &str) -> bool {
+    READY_PATTERNS.is_match(content)
+}
+
+/// Whether the bottom of the pane shows Claude still working on something
+pub fn is_busy_content(content: &str) -> bool {
+    content
+        .lines()
+        .rev()
+        .filter(|line| !line.trim().is_empty())
+        .take(BUSY_FOOTER_LINES)
+        .any(|line| BUSY_PATTERNS.is_match(line))
+}
+
 /// Quick check if content has any concerning patterns
 pub fn has_concerning_patterns(content: &str) -> bool {
     API_ERROR_PATTERNS.is_match(content)
         || FATAL_PATTERNS.iter().any(|(p, _)| p.is_match(content))
 }
 
+/// Waits before each further restart of a session that keeps failing; the last repeats
+pub const RESTART_BACKOFF: [Duration; 3] = [Duration::from_secs(300), Duration::from_secs(900), Duration::from_secs(3600)];
+
+/// What to do about a session that failed its health check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartDecision {
+    /// Restart it, the given attempt in a row
+    Restart(u32),
+    /// Too soon after the last attempt
+    Wait,
+    /// It failed this many restarts in a row; stop trying
+    GiveUp(u32),
+}
+
+/// Consecutive restarts of each session, so a broken one isn't restarted forever
+pub struct RestartTracker {
+    max_attempts: u32,
+    /// Attempts in a row and when the last one was
+    attempts: HashMap<String, (u32, Instant)>,
+}
+
+impl RestartTracker {
+    /// Give up on a session after `max_attempts` restarts in a row (0 never gives up)
+    pub fn new(max_attempts: u32) -> Self {
+        Self { max_attempts, attempts: HashMap::new() }
+    }
+
+    /// Note a failed health check at `now` and decide what to do about it
+    pub fn unhealthy(&mut self, session_name: &str, now: Instant) -> RestartDecision {
+        let Some(&(count, last)) = self.attempts.get(session_name) else {
+            self.attempts.insert(session_name.to_string(), (1, now));
+            return RestartDecision::Restart(1);
+        };
+        let wait = RESTART_BACKOFF[(count as usize - 1).min(RESTART_BACKOFF.len() - 1)];
+        if now.saturating_duration_since(last) < wait {
+            return RestartDecision::Wait;
+        }
+        if self.max_attempts > 0 && count >= self.max_attempts {
+            self.attempts.remove(session_name);
+            return RestartDecision::GiveUp(count);
+        }
+        self.attempts.insert(session_name.to_string(), (count + 1, now));
+        RestartDecision::Restart(count + 1)
+    }
+
+    /// A passed health check ends a run of failures
+    pub fn healthy(&mut self, session_name: &str) {
+        self.attempts.remove(session_name);
+    }
+}
+
+/// API errors each session showed over its recent sweeps
+///
+/// One capture rarely shows several kinds of API error at once, but a
+/// session failing the same request every minute shows one on sweep after
+/// sweep. Errors on `threshold` of the last `window` sweeps make it
+/// persistent. Clean sweeps push old errors out of the window, and sweeps
+/// from longer ago than the window spans are forgotten.
+pub struct ErrorTracker {
+    threshold: u32,
+    window: u32,
+    /// How long `window` sweeps take
+    max_age: Duration,
+    /// When each recent sweep was and whether it saw an error, oldest first
+    sweeps: HashMap<String, VecDeque<(Instant, bool)>>,
+}
+
+impl ErrorTracker {
+    /// Errors on `threshold` of the last `window` sweeps, `interval` apart, are persistent (0 never is)
+    pub fn new(threshold: u32, window: u32, interval: Duration) -> Self {
+        Self {
+            threshold,
+            window: window.max(threshold),
+            max_age: interval * window.max(threshold),
+            sweeps: HashMap::new(),
+        }
+    }
+
+    /// Note whether a sweep at `now` saw an API error, returning whether they've become persistent
+    pub fn record(&mut self, session_name: &str, now: Instant, error: bool) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        let sweeps = self.sweeps.entry(session_name.to_string()).or_default();
+        sweeps.push_back((now, error));
+        while sweeps.len() > self.window as usize
+            || sweeps.front().is_some_and(|&(at, _)| now.saturating_duration_since(at) > self.max_age)
+        {
+            sweeps.pop_front();
+        }
+
+        let errors = sweeps.iter().filter(|&&(_, error)| error).count();
+        if errors == 0 {
+            self.sweeps.remove(session_name);
+        } else if errors >= self.threshold as usize {
+            self.sweeps.remove(session_name);
+            return true;
+        }
+        false
+    }
+
+    /// Start over on a session, e.g. once it's been restarted
+    pub fn forget(&mut self, session_name: &str) {
+        self.sweeps.remove(session_name);
+    }
+}
+
+/// Spots sessions whose busy pane stops changing
+///
+/// Claude's output moves while it works, so a busy pane showing the same
+/// thing across `sweeps` health sweeps in a row means it has hung. Its
+/// spinner line keeps counting seconds and tokens either way, so those are
+/// left out of the comparison. A session waiting at its input box is idle,
+/// not stuck, however long it sits.
+pub struct StuckDetector {
+    sweeps: u32,
+    /// Hash of each busy pane last seen, and how many sweeps in a row it's been seen
+    panes: HashMap<String, (u64, u32)>,
+}
+
+impl StuckDetector {
+    /// Call a session stuck after the same busy pane on `sweeps` sweeps in a row (0 never does)
+    pub fn new(sweeps: u32) -> Self {
+        Self { sweeps, panes: HashMap::new() }
+    }
+
+    /// Note what a session's pane shows this sweep, returning whether it's stuck
+    pub fn observe(&mut self, session_name: &str, content: &str) -> bool {
+        if self.sweeps == 0 || !is_busy_content(content) {
+            self.panes.remove(session_name);
+            return false;
+        }
+        let mut hasher = DefaultHasher::new();
+        for line in content.lines() {
+            if BUSY_PATTERNS.is_match(line) {
+                BUSY_COUNTERS.replace_all(line, "").hash(&mut hasher);
+            } else {
+                line.hash(&mut hasher);
+            }
+        }
+        let hash = hasher.finish();
+
+        let seen = match self.panes.get(session_name) {
+            Some(&(last, count)) if last == hash => count + 1,
+            _ => 1,
+        };
+        if seen >= self.sweeps {
+            self.panes.remove(session_name);
+            return true;
+        }
+        self.panes.insert(session_name.to_string(), (hash, seen));
+        false
+    }
+
+    /// Start over on a session, e.g. once it's been restarted
+    pub fn forget(&mut self, session_name: &str) {
+        self.panes.remove(session_name);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_restart_backoff() {
+        let mut tracker = RestartTracker::new(5);
+        let start = Instant::now();
+        let at = |mins: u64| start + Duration::from_secs(mins * 60);
+
+        assert_eq!(tracker.unhealthy("jane-doe", at(0)), RestartDecision::Restart(1));
+        assert_eq!(tracker.unhealthy("jane-doe", at(4)), RestartDecision::Wait);
+        assert_eq!(tracker.unhealthy("jane-doe", at(5)), RestartDecision::Restart(2));
+        assert_eq!(tracker.unhealthy("jane-doe", at(15)), RestartDecision::Wait);
+        assert_eq!(tracker.unhealthy("jane-doe", at(20)), RestartDecision::Restart(3));
+        assert_eq!(tracker.unhealthy("jane-doe", at(60)), RestartDecision::Wait);
+        assert_eq!(tracker.unhealthy("jane-doe", at(80)), RestartDecision::Restart(4));
+        assert_eq!(tracker.unhealthy("jane-doe", at(140)), RestartDecision::Restart(5));
+        // Given the same last wait as the others, then dropped
+        assert_eq!(tracker.unhealthy("jane-doe", at(150)), RestartDecision::Wait);
+        assert_eq!(tracker.unhealthy("jane-doe", at(200)), RestartDecision::GiveUp(5));
+        // Counted from scratch if it comes back
+        assert_eq!(tracker.unhealthy("jane-doe", at(201)), RestartDecision::Restart(1));
+        // Sessions are tracked apart
+        assert_eq!(tracker.unhealthy("john-doe", at(202)), RestartDecision::Restart(1));
+    }
+
+    #[test]
+    fn test_restart_tracker_reset_and_unlimited() {
+        let start = Instant::now();
+        let mut tracker = RestartTracker::new(2);
+        assert_eq!(tracker.unhealthy("jane-doe", start), RestartDecision::Restart(1));
+        tracker.healthy("jane-doe");
+        assert_eq!(tracker.unhealthy("jane-doe", start), RestartDecision::Restart(1));
+
+        let mut tracker = RestartTracker::new(0);
+        for attempt in 1..=20 {
+            let now = start + Duration::from_secs(3600 * attempt as u64);
+            assert_eq!(tracker.unhealthy("jane-doe", now), RestartDecision::Restart(attempt));
+        }
+    }
+
+    /// Claude answering a question about a panic, its input box still up
+    const QUOTED_PANIC_PANE: &str = "> why does this crash?\n⏺ The log shows:\n\n    panic: index out of range [5] with length 3\n\n  The slice is shorter than the loop assumes.\n╭──────────────────╮\n│ >                │\n╰──────────────────╯\n  ? for shortcuts\n";
+
+    #[test]
+    fn test_quoted_error_above_input_healthy() {
+        let opts = HealthCheckOptions::default();
+        assert_eq!(check_session_content_with_context(QUOTED_PANIC_PANE, &opts), HealthStatus::Healthy);
+        // The old check is fooled
+        assert!(matches!(check_session_content(QUOTED_PANIC_PANE), HealthStatus::Unhealthy(_)));
+        // ...as is the new one looking at the whole tail
+        let everything = HealthCheckOptions { skip_above_input: false, ..opts };
+        assert_eq!(
+            check_session_content_with_context(QUOTED_PANIC_PANE, &everything),
+            HealthStatus::Unhealthy(UnhealthyReason::FatalError("panic".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_error_below_input_unhealthy() {
+        let crashed = format!("{}panic: runtime error: index out of range\nClaude exited\n$ ", QUOTED_PANIC_PANE);
+        assert_eq!(
+            check_session_content_with_context(&crashed, &HealthCheckOptions::default()),
+            HealthStatus::Unhealthy(UnhealthyReason::FatalError("panic".to_string()))
+        );
+
+        // No input box at all, e.g. Claude died while starting
+        let crashed = "Error: invalid API key\nClaude session crashed\n$ ";
+        assert_eq!(
+            check_session_content_with_context(crashed, &HealthCheckOptions::default()),
+            HealthStatus::Unhealthy(UnhealthyReason::FatalError("crashed".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_error_above_tail_ignored() {
+        let mut pane = "Segmentation fault (core dumped)\n".to_string();
+        for i in 0..20 {
+            pane.push_str(&format!("line {} of a long claude answer\n", i));
+        }
+        let opts = HealthCheckOptions { tail_lines: 15, skip_above_input: false };
+        assert_eq!(check_session_content_with_context(&pane, &opts), HealthStatus::Healthy);
+        let opts = HealthCheckOptions { tail_lines: 25, ..opts };
+        assert!(matches!(check_session_content_with_context(&pane, &opts), HealthStatus::Unhealthy(_)));
+    }
+
+    #[test]
+    fn test_shell_prompt_with_context() {
+        assert_eq!(
+            check_session_content_with_context("$ exit\n$ ", &HealthCheckOptions::default()),
+            HealthStatus::Unhealthy(UnhealthyReason::ClaudeNotRunning)
+        );
+    }
+
+    #[test]
+    fn test_has_api_error() {
+        let opts = HealthCheckOptions::default();
+        let retrying = "> hi\n  ⎿ API Error (529 {\"type\":\"overloaded_error\"}) · Retrying in 8 seconds…\n╭──╮\n│ > │\n╰──╯\n";
+        assert!(has_api_error(retrying, &opts));
+        assert!(!has_api_error(QUOTED_PANIC_PANE, &opts));
+        let scrolled = format!("API Error (529)\n{}", "more output\n".repeat(20));
+        assert!(!has_api_error(&scrolled, &opts));
+    }
+
+    /// Sweeps 5 minutes apart
+    fn sweep(tracker: &mut ErrorTracker, start: Instant, sweeps: &[bool], from: u64) -> Vec<bool> {
+        sweeps
+            .iter()
+            .enumerate()
+            .map(|(i, &error)| tracker.record("jane-doe", start + Duration::from_secs(300 * (from + i as u64)), error))
+            .collect()
+    }
+
+    #[test]
+    fn test_api_errors_persistent_over_sweeps() {
+        let start = Instant::now();
+        let mut tracker = ErrorTracker::new(3, 5, Duration::from_secs(300));
+        assert_eq!(sweep(&mut tracker, start, &[true, true, false, true], 0), [false, false, false, true]);
+        // Counted afresh once reported
+        assert_eq!(sweep(&mut tracker, start, &[true, true], 4), [false, false]);
+        // Other sessions are tracked apart
+        assert!(!tracker.record("john-doe", start, true));
+    }
+
+    #[test]
+    fn test_clean_sweeps_decay_api_errors() {
+        let start = Instant::now();
+        let mut tracker = ErrorTracker::new(3, 5, Duration::from_secs(300));
+        // Errors now and then never pile up to 3 in any 5 sweeps
+        let sweeps = [true, false, false, false, true, false, false, false, true, false, true];
+        assert!(sweep(&mut tracker, start, &sweeps, 0).iter().all(|&persistent| !persistent));
+
+        tracker.forget("jane-doe");
+        assert_eq!(sweep(&mut tracker, start, &[true, true], 20), [false, false]);
+        // Errors from before a long gap (the daemon stopped, say) are forgotten
+        assert_eq!(sweep(&mut tracker, start, &[true], 40), [false]);
+    }
+
+    #[test]
+    fn test_error_tracker_disabled() {
+        let mut tracker = ErrorTracker::new(0, 5, Duration::from_secs(300));
+        assert!(sweep(&mut tracker, Instant::now(), &[true; 10], 0).iter().all(|&persistent| !persistent));
+    }
+
+    #[test]
+    fn test_patterns_file_adds_to_builtin() {
+        let patterns = HealthPatterns::from_toml(
+            r#"
+            api = ["rate limit reached"]
+
+            [[fatal]]
+            name = "context_low"
+            pattern = "Context low"
+            "#,
+        )
+        .unwrap();
+        let (fatal, api) = HealthPatterns::builtin().counts();
+        assert_eq!(patterns.counts(), (fatal + 1, api + 1));
+        assert_eq!(
+            patterns.check("Context low (2% remaining)"),
+            HealthStatus::Unhealthy(UnhealthyReason::FatalError("context_low".to_string()))
+        );
+        // Built-in ones still apply
+        assert_eq!(
+            patterns.check("Segmentation fault"),
+            HealthStatus::Unhealthy(UnhealthyReason::FatalError("segfault".to_string()))
+        );
+        assert!(patterns.has_api_error("Claude: rate limit reached, try later", &HealthCheckOptions::default()));
+        assert!(!has_api_error("Claude: rate limit reached, try later", &HealthCheckOptions::default()));
+    }
+
+    #[test]
+    fn test_patterns_file_replaces_builtin() {
+        let patterns = HealthPatterns::from_toml(
+            "replace = true\napi = [\"overloaded\"]\n[[fatal]]\nname = \"oops\"\npattern = \"(?i)oops\"\n",
+        )
+        .unwrap();
+        assert_eq!(patterns.counts(), (1, 1));
+        assert_eq!(patterns.check("Segmentation fault"), HealthStatus::Healthy);
+        assert_eq!(patterns.check("OOPS"), HealthStatus::Unhealthy(UnhealthyReason::FatalError("oops".to_string())));
+
+        let empty = HealthPatterns::from_toml("").unwrap();
+        assert_eq!(empty.counts(), HealthPatterns::builtin().counts());
+    }
+
+    #[test]
+    fn test_patterns_file_invalid() {
+        let err = HealthPatterns::from_toml("[[fatal]]\nname = \"broken\"\npattern = \"crash(ed\"\n").err().unwrap();
+        assert!(err.contains("fatal pattern 'broken' (crash(ed)"), "{}", err);
+        let err = HealthPatterns::from_toml("api = [\"[529\"]\n").err().unwrap();
+        assert!(err.contains("API pattern '[529'"), "{}", err);
+        // Typos aren't ignored
+        assert!(HealthPatterns::from_toml("[[fatal]]\nname = \"x\"\nregex = \"x\"\n").is_err());
+        assert!(HealthPatterns::from_toml("apis = []\n").is_err());
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("health_patterns.toml");
+        assert_eq!(HealthPatterns::load(&path).unwrap().counts(), HealthPatterns::builtin().counts());
+        std::fs::write(&path, "api = [\"(\"]\n").unwrap();
+        let err = HealthPatterns::load(&path).unwrap_err().to_string();
+        assert!(err.contains("health_patterns.toml") && err.contains("API pattern '('"), "{}", err);
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        let pane = "ok\nAPI Error (529 overloaded_error)\npanic: boom\n";
+        let found = HealthPatterns::builtin().matches(pane);
+        let names: Vec<(&str, &str)> = found.iter().map(|m| (m.kind, m.name.as_str())).collect();
+        assert_eq!(names, [("fatal", "panic"), ("api", r"API Error[:\s]\(?(\d{3})"), ("api", "overloaded_error")]);
+        assert_eq!(found[0].line, "panic: boom");
+        assert_eq!(found[1].line, "API Error (529 overloaded_error)");
+    }
+
+    /// Banners from sessions that ran out of context
+    const CONTEXT_BANNERS: &[&str] = &[
+        "╭──────────────────╮\n│ >                │\n╰──────────────────╯\n  ? for shortcuts          Context low (2% remaining) · Run /compact to compact & continue\n",
+        "> summarize the thread\n  ⎿ Prompt is too long\n╭──────────────────╮\n│ >                │\n╰──────────────────╯\n",
+        "> and one more thing\n  ⎿ Error: Conversation too long. Press esc twice to go up a few messages and try again.\n╭──╮\n│ > │\n╰──╯\n",
+    ];
+
+    #[test]
+    fn test_context_exhausted() {
+        let opts = HealthCheckOptions::default();
+        for banner in CONTEXT_BANNERS {
+            let status = check_session_content_with_context(banner, &opts);
+            assert_eq!(status, HealthStatus::Unhealthy(UnhealthyReason::ContextExhausted), "{}", banner);
+        }
+        // Plenty left
+        let pane = "╭──╮\n│ > │\n╰──╯\n  Context left until auto-compact: 38% · ? for shortcuts\n";
+        assert_eq!(check_session_content_with_context(pane, &opts), HealthStatus::Healthy);
+        // Scrolled out of the tail
+        let pane = format!("Context low (2% remaining)\n{}", "more output\n".repeat(20));
+        assert_eq!(check_session_content_with_context(&pane, &opts), HealthStatus::Healthy);
+    }
+
+    /// A contact mentioning the errors, and Claude answering, isn't running out of context
+    #[test]
+    fn test_context_phrases_in_conversation_healthy() {
+        let opts = HealthCheckOptions::default();
+        let pane = "> ---SMS FROM Jane Doe (family)---\n  my essay says prompt is too long, and the conversation too long. context low? run /compact to fix?\n  ---END SMS---\n⏺ That sounds like the essay tool's limit, not yours. Prompt is too long means it wants under 2,000 words.\n╭──────────────────╮\n│ >                │\n╰──────────────────╯\n  ? for shortcuts\n";
+        assert_eq!(check_session_content_with_context(pane, &opts), HealthStatus::Healthy);
+    }
+
+    /// A patterns file calling the banner fatal doesn't get it restarted
+    #[test]
+    fn test_context_exhausted_before_fatal() {
+        let patterns = HealthPatterns::from_toml("[[fatal]]\nname = \"context_low\"\npattern = \"Context low\"\n").unwrap();
+        assert_eq!(
+            patterns.check_with_context(CONTEXT_BANNERS[0], &HealthCheckOptions::default()),
+            HealthStatus::Unhealthy(UnhealthyReason::ContextExhausted)
+        );
+    }
+
+    /// Banners from an account out of usage
+    const USAGE_BANNERS: &[&str] = &[
+        "> what's on my calendar?\n  ⎿ You've reached your usage limit, resets at 3pm\n╭──╮\n│ > │\n╰──╯\n",
+        "> hi\n⏺ Claude usage limit reached. Your limit will reset at 5:30pm (America/New_York).\n\n    • /upgrade to increase your usage limit.\n",
+        "> hi\n  ⎿ Claude AI usage limit reached|1718900000\n$ ",
+        "> hi\n  ⎿ 5-hour limit reached ∙ resets 15:00\n     /upgrade to increase your usage limit.\n",
+    ];
+
+    #[test]
+    fn test_usage_limited() {
+        let opts = HealthCheckOptions::default();
+        for banner in USAGE_BANNERS {
+            let status = check_session_content_with_context(banner, &opts);
+            assert_eq!(status, HealthStatus::Unhealthy(UnhealthyReason::UsageLimited), "{}", banner);
+        }
+        // A prompt since then means the banner is old news
+        let pane = format!("{}> try again\n⏺ Sure, here it is.\n╭──╮\n│ > │\n╰──╯\n", USAGE_BANNERS[0]);
+        assert_eq!(usage_limit_banner(&pane, &opts), None);
+        assert_eq!(check_session_content_with_context(&pane, &opts), HealthStatus::Healthy);
+        // Tells banners apart
+        assert_ne!(usage_limit_banner(USAGE_BANNERS[0], &opts), usage_limit_banner(USAGE_BANNERS[1], &opts));
+    }
+
+    /// A contact asking about a usage limit, and Claude answering, isn't out of usage
+    #[test]
+    fn test_usage_limit_in_conversation_healthy() {
+        let opts = HealthCheckOptions::default();
+        let pane = "> ---SMS FROM Jane Doe (family)---\n  what is the usage limit on our verizon plan? I think we hit our limit last month\n  ---END SMS---\n⏺ Your Verizon plan's usage limit is 50GB; you've reached your limit twice this year. It resets on the 1st.\n╭──────────────────╮\n│ >                │\n╰──────────────────╯\n  ? for shortcuts\n";
+        assert_eq!(usage_limit_banner(pane, &opts), None);
+        assert_eq!(check_session_content_with_context(pane, &opts), HealthStatus::Healthy);
+    }
+
+    /// Captured login and onboarding screens, and a session whose login lapsed mid-conversation
+    const LOGIN_SCREENS: &[&str] = &[
+        " Claude Code can be used with your Claude subscription or billed based on API usage through your Console account.
+
+ Select login method:
+
+ ❯ 1. Claude account with subscription · Pro, Max, Team, or Enterprise
+
+   2. Anthropic Console account · API usage billing
+",
+        " Browser didn't open? Use the url below to sign in:
+
+https://claude.ai/oauth/authorize?code=true&client_id=9d1c250a
+
+ Paste code here if prompted >
+",
+        " Let's get started.
+
+ Choose the text style that looks best with your terminal:
+ To change this later, run /theme
+
+ ❯ 1. Dark mode ✔
+   2. Light mode
+",
+        "> what's the weather?
+  ⎿ API Error: 401 {\"type\":\"error\",\"error\":{\"type\":\"authentication_error\",\"message\":\"OAuth token has expired.\"}} · Please run /login
+╭──────────────────╮
+│ >                │
+╰──────────────────╯
+",
+        "> hi
+  ⎿ Invalid API key · Please run /login
+╭──╮
+│ > │
+╰──╯
+",
+    ];
+
+    #[test]
+    fn test_auth_required() {
+        let opts = HealthCheckOptions::default();
+        for screen in LOGIN_SCREENS {
+            let status = check_session_content_with_context(screen, &opts);
+            assert_eq!(status, HealthStatus::Unhealthy(UnhealthyReason::AuthRequired), "{}", screen);
+        }
+        // Logging in again and carrying on clears it
+        let pane = format!("{}> try again\n⏺ It's sunny.\n╭──╮\n│ > │\n╰──╯\n", LOGIN_SCREENS[4]);
+        assert_eq!(check_session_content_with_context(&pane, &opts), HealthStatus::Healthy);
+        // As does the welcome banner every session starts with
+        let welcome = format!("✻ Welcome to Claude Code!\n\n  /help for help, /status for your current setup\n\n{}", crate::runner::FAKE_READY_PANE);
+        assert_eq!(check_session_content_with_context(&welcome, &opts), HealthStatus::Healthy);
+    }
+
+    /// A contact writing about logging in, and Claude answering, isn't a lapsed login
+    #[test]
+    fn test_login_talk_in_conversation_healthy() {
+        let opts = HealthCheckOptions::default();
+        let pane = "> ---SMS FROM Jane Doe (family)---\n  the app says please run /login and select login method: Anthropic Console account or Claude account with subscription?\n  my OAuth token has expired\n  ---END SMS---\n⏺ Pick \"Claude account with subscription\", then please run /login again if it asks.\n╭──────────────────╮\n│ >                │\n╰──────────────────╯\n  ? for shortcuts\n";
+        assert_eq!(check_session_content_with_context(pane, &opts), HealthStatus::Healthy);
+    }
+
+    #[test]
+    fn test_usage_limit_reset() {
+        let now = Local.with_ymd_and_hms(2025, 3, 4, 13, 20, 0).unwrap();
+        let at = |h, m| Local.with_ymd_and_hms(2025, 3, 4, h, m, 0).unwrap();
+        assert_eq!(usage_limit_reset(USAGE_BANNERS[0], now), Some(at(15, 0)));
+        assert_eq!(usage_limit_reset(USAGE_BANNERS[1], now), Some(at(17, 30)));
+        assert_eq!(usage_limit_reset(USAGE_BANNERS[3], now), Some(at(15, 0)));
+        assert_eq!(usage_limit_reset(USAGE_BANNERS[2], now).unwrap().timestamp(), 1718900000);
+        // Already past today, so tomorrow's
+        let tomorrow = Local.with_ymd_and_hms(2025, 3, 5, 9, 0, 0).unwrap();
+        assert_eq!(usage_limit_reset("limit resets 9am", now), Some(tomorrow));
+        assert_eq!(usage_limit_reset("limit resets at 12am", now).unwrap().date_naive(), tomorrow.date_naive());
+        assert_eq!(usage_limit_reset("You've hit your limit", now), None);
+        assert_eq!(usage_limit_reset("resets at 25:00", now), None);
+    }
+
+    const BUSY_PANE: &str = "> fix the build\n⏺ Running cargo test…\n✻ Compiling… (212s · esc to interrupt)\n";
+    const IDLE_PANE: &str = "⏺ Done.\n╭──────────────────╮\n│ >                │\n╰──────────────────╯\n";
+
+    #[test]
+    fn test_stuck_after_unchanged_busy_sweeps() {
+        let mut detector = StuckDetector::new(3);
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+        assert!(detector.observe("jane-doe", BUSY_PANE));
+        // Counted afresh after being reported
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+    }
+
+    /// A hung tool call's spinner keeps counting, but nothing else moves
+    #[test]
+    fn test_ticking_spinner_still_stuck() {
+        let mut detector = StuckDetector::new(3);
+        let pane = |glyph: &str, secs: u32, tokens: &str| {
+            format!("> fix the build\n⏺ Running cargo test…\n{} Compiling… ({}s · ↑ {} tokens · esc to interrupt)\n", glyph, secs, tokens)
+        };
+        assert!(!detector.observe("jane-doe", &pane("✻", 212, "3.1k")));
+        assert!(!detector.observe("jane-doe", &pane("✶", 518, "3.4k")));
+        assert!(detector.observe("jane-doe", &pane("·", 824, "3,950")));
+
+        // New output is still progress
+        assert!(!detector.observe("jane-doe", &pane("✻", 900, "4k")));
+        let moved = pane("✻", 1200, "4k").replace("cargo test…", "cargo test…\n  ⎿ 12 passed");
+        assert!(!detector.observe("jane-doe", &moved));
+    }
+
+    /// Sitting at the input box for hours is idle, not stuck
+    #[test]
+    fn test_idle_pane_not_stuck() {
+        let mut detector = StuckDetector::new(3);
+        for _ in 0..10 {
+            assert!(!detector.observe("jane-doe", IDLE_PANE));
+        }
+        // An idle sweep breaks a busy run
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+        assert!(!detector.observe("jane-doe", IDLE_PANE));
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+    }
+
+    #[test]
+    fn test_stuck_detector_forget_and_disabled() {
+        let mut detector = StuckDetector::new(2);
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+        assert!(!detector.observe("john-doe", BUSY_PANE));
+        detector.forget("jane-doe");
+        assert!(!detector.observe("jane-doe", BUSY_PANE));
+        // Sessions are tracked apart
+        assert!(detector.observe("john-doe", BUSY_PANE));
+
+        let mut detector = StuckDetector::new(0);
+        for _ in 0..10 {
+            assert!(!detector.observe("jane-doe", BUSY_PANE));
+        }
+    }
 
     #[test]
     fn test_healthy_session() {
@@ -266,6 +1260,39 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_busy_footer() {
+        let working = "> what's the weather?\n\n✻ Pondering… (12s · ↑ 1.2k tokens · esc to interrupt)\n\n> \n";
+        assert!(is_busy_content(working));
+        assert!(is_busy_content("● Reading the file\n✶ Searching…\n"));
+
+        let idle = "> what's the weather?\n\n● It's sunny.\n\n> \n  ? for shortcuts\n";
+        assert!(!is_busy_content(idle));
+
+        // An old footer that has scrolled up doesn't count
+        let scrolled = format!("✻ Pondering… (esc to interrupt)\n{}", "● done\n".repeat(20));
+        assert!(!is_busy_content(&scrolled));
+    }
+
+    #[test]
+    fn test_ready_content() {
+        // Still loading: the shell has run claude but nothing is drawn yet
+        assert!(!is_ready_content(""));
+        assert!(!is_ready_content("jsmith@mac ~/transcripts/jane-doe $ claude --dangerously-skip-permissions\n"));
+        // A folder trust dialog still needs answering
+        assert!(!is_ready_content("Do you trust the files in this folder?\n\n❯ 1. Yes, proceed\n  2. No, exit\n"));
+
+        let banner = "╭───────────────────────────────────────╮\n\
+                      │ ✻ Welcome to Claude Code!             │\n\
+                      ╰───────────────────────────────────────╯\n";
+        assert!(is_ready_content(banner));
+        let input_box = "╭──────────────────────╮\n│ > Try \"fix lint\"     │\n╰──────────────────────╯\n";
+        assert!(is_ready_content(input_box));
+        let footer = "────────────────\n> \n────────────────\n  ⏵⏵ bypass permissions on (shift+tab to cycle)\n";
+        assert!(is_ready_content(footer));
+        assert!(is_ready_content("> \n  ? for shortcuts\n"));
+    }
+
     // Performance test
     #[test]
     fn test_health_check_performance() {
@@ -0,0 +1,336 @@
+//! Recent health events, kept across daemon restarts
+//!
+//! The daemon notes each change in a session's health and what it did about
+//! it: restarted, compacted, paused, or quarantined it. `restart-session` and
+//! `restart-sessions` note their restarts too. The newest `HISTORY_CAPACITY`
+//! events are kept, in memory and in `health_history.json`, so `health` and
+//! `status` can show which sessions flapped overnight and why.
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::registry::write_json_atomic;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Events kept, oldest dropped first
+pub const HISTORY_CAPACITY: usize = 200;
+
+/// How a session stood after a health sweep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthState {
+    Healthy,
+    Unhealthy,
+    /// At its usage limit, holding its messages
+    Paused,
+    /// Failed too many restarts, left alone until `restart-session`
+    Quarantined,
+    /// Restarted from the command line, not yet swept since
+    Restarting,
+}
+
+impl fmt::Display for HealthState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HealthState::Healthy => "healthy",
+            HealthState::Unhealthy => "unhealthy",
+            HealthState::Paused => "paused",
+            HealthState::Quarantined => "quarantined",
+            HealthState::Restarting => "restarting",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What was done about a session's health
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionTaken {
+    Restart,
+    /// Left alone, as it was restarted too recently
+    Backoff,
+    Compact,
+    Pause,
+    Resume,
+    Quarantine,
+    /// `restart-session` or `restart-sessions`
+    ManualRestart,
+}
+
+impl fmt::Display for ActionTaken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ActionTaken::Restart => "restart",
+            ActionTaken::Backoff => "backoff",
+            ActionTaken::Compact => "compact",
+            ActionTaken::Pause => "pause",
+            ActionTaken::Resume => "resume",
+            ActionTaken::Quarantine => "quarantine",
+            ActionTaken::ManualRestart => "manual_restart",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One change in a session's health
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthEvent {
+    pub session: String,
+    pub at: DateTime<Utc>,
+    /// None if nothing was known about the session before
+    pub from: Option<HealthState>,
+    pub to: HealthState,
+    /// Why, e.g. "crashed" or "stuck"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<ActionTaken>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HistoryFile {
+    /// Oldest first
+    events: Vec<HealthEvent>,
+}
+
+/// The newest health events, oldest first
+pub struct HealthHistory {
+    path: PathBuf,
+    capacity: usize,
+    events: VecDeque<HealthEvent>,
+}
+
+impl HealthHistory {
+    pub fn new(config: &Config) -> Self {
+        Self::with_capacity(config, HISTORY_CAPACITY)
+    }
+
+    pub fn with_capacity(config: &Config, capacity: usize) -> Self {
+        Self { path: config.health_history_file.clone(), capacity, events: VecDeque::new() }
+    }
+
+    /// Load saved events; a missing file is an empty history
+    pub fn load(&mut self) -> Result<()> {
+        self.events = self.read()?.into();
+        self.trim();
+        Ok(())
+    }
+
+    /// Save atomically, first merging in events saved since by anyone else
+    ///
+    /// `restart-session` saves its events straight to the file while the
+    /// daemon runs, so the daemon's copy has to take them in rather than write
+    /// over them.
+    pub fn save(&mut self) -> Result<()> {
+        let mut merged = self.read()?;
+        merged.retain(|event| !self.events.contains(event));
+        merged.extend(self.events.drain(..));
+        // Stable, so events at the same moment stay in the order they were noted
+        merged.sort_by_key(|event| event.at);
+        self.events = merged.into();
+        self.trim();
+
+        let file = HistoryFile { events: self.events.iter().cloned().collect() };
+        write_json_atomic(&self.path, &file)
+    }
+
+    /// Note an event, dropping the oldest if full
+    pub fn record(&mut self, event: HealthEvent) {
+        self.events.push_back(event);
+        self.trim();
+    }
+
+    /// Note a session now being `to`, if that's news or something was done about it
+    ///
+    /// Returns whether it was noted. A session first seen healthy isn't news.
+    pub fn transition(
+        &mut self,
+        session: &str,
+        at: DateTime<Utc>,
+        to: HealthState,
+        reason: Option<String>,
+        action: Option<ActionTaken>,
+    ) -> bool {
+        let from = self.last_state(session);
+        let unchanged = from == Some(to) || (from.is_none() && to == HealthState::Healthy);
+        if unchanged && action.is_none() {
+            return false;
+        }
+        self.record(HealthEvent { session: session.to_string(), at, from, to, reason, action });
+        true
+    }
+
+    /// How a session stood at its latest event
+    pub fn last_state(&self, session: &str) -> Option<HealthState> {
+        self.events.iter().rev().find(|event| event.session == session).map(|event| event.to)
+    }
+
+    /// Every event, oldest first
+    pub fn events(&self) -> impl Iterator<Item = &HealthEvent> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    fn read(&self) -> Result<Vec<HealthEvent>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let file: HistoryFile = serde_json::from_str(&fs::read_to_string(&self.path)?)?;
+        Ok(file.events)
+    }
+
+    fn trim(&mut self) {
+        while self.events.len() > self.capacity {
+            self.events.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn event(session: &str, minute: i64, to: HealthState, action: Option<ActionTaken>) -> HealthEvent {
+        let start = DateTime::parse_from_rfc3339("2025-03-04T08:00:00Z").unwrap().with_timezone(&Utc);
+        HealthEvent {
+            session: session.to_string(),
+            at: start + chrono::Duration::minutes(minute),
+            from: None,
+            to,
+            reason: None,
+            action,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_drops_oldest() {
+        let temp = TempDir::new().unwrap();
+        let mut history = HealthHistory::with_capacity(&Config::for_test(temp.path()), 3);
+        for minute in 0..5 {
+            history.record(event("pat-smith", minute, HealthState::Unhealthy, Some(ActionTaken::Restart)));
+        }
+        assert_eq!(history.len(), 3);
+        let minutes: Vec<i64> = history.events().map(|e| e.at.timestamp() / 60 % 60).collect();
+        assert_eq!(minutes, [2, 3, 4]);
+    }
+
+    #[test]
+    fn test_transition_notes_changes_and_actions() {
+        let temp = TempDir::new().unwrap();
+        let mut history = HealthHistory::new(&Config::for_test(temp.path()));
+        let at = Utc::now();
+        let unhealthy = |history: &mut HealthHistory, action| {
+            history.transition("pat-smith", at, HealthState::Unhealthy, Some("crashed".to_string()), action)
+        };
+
+        // Healthy from the start isn't news
+        assert!(!history.transition("pat-smith", at, HealthState::Healthy, None, None));
+        assert!(unhealthy(&mut history, Some(ActionTaken::Restart)));
+        // Still unhealthy: only noted when something is done about it
+        assert!(unhealthy(&mut history, Some(ActionTaken::Backoff)));
+        assert!(!unhealthy(&mut history, None));
+        assert!(history.transition("pat-smith", at, HealthState::Healthy, None, None));
+        assert!(!history.transition("pat-smith", at, HealthState::Healthy, None, None));
+
+        let events: Vec<&HealthEvent> = history.events().collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!((events[0].from, events[0].to), (None, HealthState::Unhealthy));
+        assert_eq!(events[0].reason.as_deref(), Some("crashed"));
+        assert_eq!(events[1].action, Some(ActionTaken::Backoff));
+        assert_eq!((events[2].from, events[2].to), (Some(HealthState::Unhealthy), HealthState::Healthy));
+        assert_eq!(history.last_state("pat-smith"), Some(HealthState::Healthy));
+        assert_eq!(history.last_state("al-admin"), None);
+    }
+
+    #[test]
+    fn test_persistence_format() {
+        let temp = TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut history = HealthHistory::new(&config);
+        history.load().unwrap();
+        assert!(history.is_empty());
+        history.record(HealthEvent {
+            from: Some(HealthState::Healthy),
+            reason: Some("stuck".to_string()),
+            ..event("pat-smith", 0, HealthState::Unhealthy, Some(ActionTaken::Restart))
+        });
+        history.record(event("al-admin", 1, HealthState::Restarting, Some(ActionTaken::ManualRestart)));
+        history.save().unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&config.health_history_file).unwrap()).unwrap();
+        assert_eq!(
+            saved["events"][0],
+            serde_json::json!({
+                "session": "pat-smith",
+                "at": "2025-03-04T08:00:00Z",
+                "from": "healthy",
+                "to": "unhealthy",
+                "reason": "stuck",
+                "action": "restart",
+            })
+        );
+        // Nothing to say is left out
+        assert_eq!(
+            saved["events"][1],
+            serde_json::json!({
+                "session": "al-admin",
+                "at": "2025-03-04T08:01:00Z",
+                "from": null,
+                "to": "restarting",
+                "action": "manual_restart",
+            })
+        );
+
+        let mut loaded = HealthHistory::new(&config);
+        loaded.load().unwrap();
+        assert_eq!(loaded.events().collect::<Vec<_>>(), history.events().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_save_merges_events_saved_elsewhere() {
+        let temp = TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut daemon = HealthHistory::with_capacity(&config, 3);
+        daemon.record(event("pat-smith", 0, HealthState::Unhealthy, Some(ActionTaken::Restart)));
+        daemon.save().unwrap();
+
+        // restart-session, while the daemon runs
+        let mut cli = HealthHistory::new(&config);
+        cli.load().unwrap();
+        cli.record(event("al-admin", 5, HealthState::Restarting, Some(ActionTaken::ManualRestart)));
+        cli.save().unwrap();
+
+        daemon.record(event("pat-smith", 10, HealthState::Healthy, None));
+        daemon.record(event("pat-smith", 15, HealthState::Unhealthy, Some(ActionTaken::Restart)));
+        daemon.save().unwrap();
+        let sessions: Vec<(&str, i64)> =
+            daemon.events().map(|e| (e.session.as_str(), e.at.timestamp() / 60 % 60)).collect();
+        // In order, each once, and no more than the daemon keeps
+        assert_eq!(sessions, [("al-admin", 5), ("pat-smith", 10), ("pat-smith", 15)]);
+        let mut loaded = HealthHistory::new(&config);
+        loaded.load().unwrap();
+        assert_eq!(loaded.len(), 3);
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_file() {
+        let temp = TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        fs::create_dir_all(&config.state_dir).unwrap();
+        fs::write(&config.health_history_file, "{ \"events\": [").unwrap();
+        assert!(HealthHistory::new(&config).load().is_err());
+    }
+}
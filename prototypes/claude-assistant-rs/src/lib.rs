@@ -4,10 +4,24 @@
 //! from blessed contacts (admin, wife, family, favorite tiers).
 
 pub mod messages;
+pub mod attachments;
+pub mod outbound;
+pub mod notifier;
 pub mod contacts;
+pub mod claude_cli;
 pub mod session;
 pub mod registry;
+pub mod cursors;
+pub mod quarantine;
+pub mod queue;
+pub mod runner;
+pub mod workers;
+pub mod sweep;
+pub mod response;
 pub mod health;
+pub mod health_history;
+pub mod crash;
+pub mod process;
 pub mod reminder;
 pub mod config;
 pub mod error;
@@ -2,21 +2,46 @@
 //!
 //! CLI and daemon for managing SMS-based Claude sessions via tmux.
 
-use chrono::Utc;
+use chrono::{DateTime, Timelike, Utc};
 use clap::{Parser, Subcommand};
-use claude_assistant_rs::config::Config;
-use claude_assistant_rs::contacts::ContactsManager;
-use claude_assistant_rs::health::HealthStatus;
-use claude_assistant_rs::messages::MessagesReader;
-use claude_assistant_rs::registry::SessionRegistry;
-use claude_assistant_rs::reminder::ReminderManager;
-use claude_assistant_rs::session::SessionManager;
-use claude_assistant_rs::Result;
+use claude_assistant_rs::attachments::{self, AttachmentHandler, StagedAttachment};
+use claude_assistant_rs::config::{Config, QUARANTINE_TIER};
+use claude_assistant_rs::contacts::{
+    name_match_rank, normalize_chat_id, BlessedGroups, Blocklist, Contact, ContactSource, ContactsManager, Identifier, TierOverrides,
+};
+use claude_assistant_rs::crash::{self, CrashBundle, CrashReport};
+use claude_assistant_rs::cursors::ChatCursors;
+use claude_assistant_rs::health::{
+    usage_limit_banner, usage_limit_reset, ErrorTracker, HealthCheckOptions, HealthPatterns, HealthStatus,
+    RestartDecision, RestartTracker, StuckDetector, UnhealthyReason,
+};
+use claude_assistant_rs::health_history::{ActionTaken, HealthEvent, HealthHistory, HealthState};
+use claude_assistant_rs::notifier::{Notifier, RememberingNotifier, SmsNotifier};
+use claude_assistant_rs::outbound::{self, OutboundAuthor};
+use claude_assistant_rs::process::format_ps;
+use claude_assistant_rs::quarantine::{Quarantine, QuarantineEntry};
+use claude_assistant_rs::queue::{InjectionQueue, QueuedPrompt};
+use claude_assistant_rs::messages::{
+    conversation_context, GroupEvent, Message, MessageKind, MessageService, MessagesReader, RecentMessages,
+    TapbackKind,
+};
+use claude_assistant_rs::registry::{read_registry, ImportMode, RegistryExport, SessionData, SessionEvent, SessionRegistry};
+use claude_assistant_rs::reminder::{ReminderManager, ReminderStore, Upcoming};
+use claude_assistant_rs::sweep::{spawn_sweep, Probe};
+use claude_assistant_rs::workers::SessionWorkers;
+use claude_assistant_rs::session::{
+    conversation_since, latest_conversation, tmux_command, KillMode, SessionInfo, SessionManager, PANE_ARCHIVE_DIR,
+};
+use claude_assistant_rs::{Error, Result};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
@@ -27,12 +52,24 @@ use tracing_subscriber::EnvFilter;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Print the sessions that would be created and the text that would be injected, without doing it
+    #[arg(long, global = true)]
+    dry_run: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Start the daemon
-    Start,
+    Start {
+        /// Start from the newest message instead of replaying recent ones
+        #[arg(long)]
+        no_backfill: bool,
+
+        /// Write over changes made to the session registry outside the daemon instead of merging them
+        #[arg(long)]
+        no_merge: bool,
+    },
 
     /// Stop the daemon
     Stop,
@@ -40,8 +77,12 @@ enum Commands {
     /// Restart the daemon
     Restart,
 
-    /// Show daemon status
-    Status,
+    /// Show daemon status and how busy each session has been
+    Status {
+        /// Print the daemon's PID and the registry's sessions as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Tail the log file
     Logs {
@@ -52,11 +93,15 @@ enum Commands {
         /// Don't follow the log
         #[arg(long = "no-follow")]
         no_follow: bool,
+
+        /// Tail what a session has printed instead (session name, or a contact or group name)
+        #[arg(long)]
+        session: Option<String>,
     },
 
     /// Attach to a tmux session
     Attach {
-        /// Session name (omit to list sessions)
+        /// Session name, or a contact or group name (omit to list sessions)
         session: Option<String>,
     },
 
@@ -65,25 +110,48 @@ enum Commands {
 
     /// Kill a specific tmux session
     KillSession {
-        /// Session name
+        /// Session name, or a contact or group name
         session: String,
+        /// Kill at once, without asking a busy Claude to wrap up first
+        #[arg(long)]
+        now: bool,
     },
 
     /// Kill all tmux sessions
     KillSessions,
 
+    /// Press Escape in a session to stop what Claude is doing
+    Interrupt {
+        /// Session name, or a contact or group name
+        session: String,
+
+        /// Message to send once it has stopped
+        message: Option<String>,
+    },
+
     /// Restart a specific session
     RestartSession {
         /// Session name
         session: String,
+
+        /// Start a new conversation instead of resuming the last one
+        #[arg(long)]
+        fresh: bool,
     },
 
     /// Restart all sessions
     RestartSessions,
 
+    /// Move sessions left on the default tmux server onto the daemon's socket
+    AdoptSessions {
+        /// Just kill them instead of restarting them on the socket
+        #[arg(long)]
+        kill: bool,
+    },
+
     /// Inject a prompt into a session
     InjectPrompt {
-        /// Chat ID (phone number or group UUID)
+        /// Chat ID (phone number or group UUID), or a contact or group name
         chat_id: String,
 
         /// Prompt text (or use --file)
@@ -117,6 +185,92 @@ enum Commands {
         /// GUID of message being replied to
         #[arg(long)]
         reply_to: Option<String>,
+
+        /// Service to show in the SMS header (imessage, sms, rcs)
+        #[arg(long)]
+        service: Option<MessageService>,
+
+        /// Wait for Claude to finish and print its response
+        #[arg(long)]
+        wait: bool,
+
+        /// Seconds to wait with --wait before giving up
+        #[arg(long, default_value = "120", requires = "wait")]
+        timeout: u64,
+    },
+
+    /// List messages from unblessed senders, or let one through
+    Quarantine {
+        /// Number of entries to show
+        #[arg(short = 'n', long, default_value = "20")]
+        lines: usize,
+
+        /// Inject this quarantined message (by ROWID) into a session once
+        #[arg(long, value_name = "ROWID")]
+        bless_once: Option<i64>,
+    },
+
+    /// Drop all messages from someone, even a blessed contact (lists blocked senders if none given)
+    Block {
+        /// Phone number, email, or contact name
+        identifier: Option<String>,
+    },
+
+    /// Remove someone from the blocklist
+    Unblock {
+        /// Phone number, email, or contact name
+        identifier: String,
+    },
+
+    /// Process a group chat's messages from every participant, not just blessed ones (lists blessed groups if none given)
+    BlessGroup {
+        /// Group chat identifier (see `status` or quarantine entries)
+        chat_id: Option<String>,
+    },
+
+    /// Go back to only processing a group's messages from blessed contacts
+    UnblessGroup {
+        /// Group chat identifier
+        chat_id: String,
+    },
+
+    /// Manage local tier overrides (state/tier_overrides.json)
+    Tier {
+        #[command(subcommand)]
+        action: TierAction,
+    },
+
+    /// Inspect, fix, or check the session registry (state/sessions.json)
+    Registry {
+        #[command(subcommand)]
+        action: RegistryAction,
+    },
+
+    /// Show recent health events, or try out the health check's error patterns (health_patterns.toml)
+    #[command(args_conflicts_with_subcommands = true)]
+    Health {
+        /// Only this session's events
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(subcommand)]
+        action: Option<HealthAction>,
+    },
+
+    /// Browse the crash reports saved when the health sweep restarts a session (logs/crashes)
+    Crashes {
+        #[command(subcommand)]
+        action: CrashesAction,
+    },
+
+    /// See when reminders are due, and add or remove your own without editing contact notes
+    Reminders {
+        #[command(subcommand)]
+        action: RemindersAction,
     },
 
     /// Install LaunchAgent for auto-start
@@ -127,7 +281,163 @@ enum Commands {
 
     /// Run the daemon (internal)
     #[command(hide = true)]
-    Run,
+    Run {
+        /// Start from the newest message instead of replaying recent ones
+        #[arg(long)]
+        no_backfill: bool,
+
+        /// Write over changes made to the session registry outside the daemon instead of merging them
+        #[arg(long)]
+        no_merge: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TierAction {
+    /// Override someone's tier (admin, wife, family, favorite, unknown, blocked)
+    Set {
+        /// Phone number, email, or contact name
+        identifier: String,
+        tier: String,
+    },
+
+    /// List overrides
+    List,
+
+    /// Remove an override
+    Rm {
+        /// Phone number, email, or contact name
+        identifier: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum HealthAction {
+    /// Show which patterns match a pane capture, and what the health check would make of it
+    #[command(group = clap::ArgGroup::new("input").required(true))]
+    TestPatterns {
+        /// File holding a pane capture
+        #[arg(group = "input")]
+        file: Option<PathBuf>,
+        /// Capture this session's pane instead (session name, or a contact or group name)
+        #[arg(long, group = "input")]
+        session: Option<String>,
+        /// Patterns file to try instead of the loaded one
+        #[arg(long)]
+        patterns: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CrashesAction {
+    /// List crash reports, newest first
+    List {
+        /// Only this session's
+        #[arg(long)]
+        session: Option<String>,
+    },
+
+    /// Print a crash report: its reason, registry entry, processes, the daemon's log, and the session's scrollback
+    Show {
+        /// The report's name from `crashes list`, or a session for its newest
+        report: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RemindersAction {
+    /// List reminders, from contact notes and added here, with their schedules and when each is next due
+    List {
+        /// Only this chat's: phone number, email, group ID, or contact name
+        chat_id: Option<String>,
+    },
+
+    /// Show the reminder due soonest
+    Next,
+
+    /// Add a reminder, kept in the daemon's reminders file rather than contact notes
+    Add {
+        /// Phone number, email, group ID, or contact name
+        chat_id: String,
+        /// When, as a cron schedule in UTC, e.g. "0 9 * * 1-5" for 9:00 on weekdays
+        cron: String,
+        /// What to send the chat's session
+        prompt: String,
+    },
+
+    /// Remove a reminder added with `reminders add`
+    Rm {
+        /// Phone number, email, group ID, or contact name
+        chat_id: String,
+        /// Its number in `reminders list`
+        index: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegistryAction {
+    /// List sessions, one tab-separated line each: chat ID, session, tier, type, last message in, last message out
+    List,
+
+    /// Print one session's entry as JSON
+    Show {
+        /// Chat ID or session name
+        target: String,
+    },
+
+    /// Remove a chat's entry (its tmux session is left running)
+    Rm {
+        /// Chat ID, or `<chat ID>-bg` for a background session
+        chat_id: String,
+        /// Don't ask first
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+
+    /// Change a chat's entry; a new tier applies when its session next starts
+    #[command(group = clap::ArgGroup::new("changes").required(true).multiple(true))]
+    Set {
+        /// Chat ID, or `<chat ID>-bg` for a background session
+        chat_id: String,
+        #[arg(long, group = "changes")]
+        tier: Option<String>,
+        #[arg(long, group = "changes")]
+        contact_name: Option<String>,
+        /// Group name; empty to clear
+        #[arg(long, group = "changes")]
+        display_name: Option<String>,
+    },
+
+    /// Drop entries whose tmux session is gone and whose chat has been quiet for a while (see --dry-run)
+    Prune {
+        /// Days since the chat's last message
+        #[arg(long, default_value_t = 90)]
+        days: u64,
+        /// Keep entries whose transcript dir is still there
+        #[arg(long)]
+        transcripts_gone: bool,
+    },
+
+    /// Write the whole registry as JSON, to move it to another machine
+    Export {
+        /// File to write instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Read a registry export, keeping whichever copy of each entry is newer (or see --replace)
+    Import {
+        file: PathBuf,
+        /// Keep current entries, taking imported ones only where newer (the default)
+        #[arg(long, conflicts_with = "replace")]
+        merge: bool,
+        /// Drop all current entries for the imported ones
+        #[arg(long)]
+        replace: bool,
+    },
+
+    /// Check that the registry and each backup parse
+    Verify,
 }
 
 fn main() -> Result<()> {
@@ -142,20 +452,34 @@ fn main() -> Result<()> {
         .with_target(false)
         .init();
 
-    let config = Config::default();
+    let mut config = Config::default();
+    config.load_tiers()?;
+    config.load_health_patterns()?;
+    if cli.dry_run
+        && !matches!(cli.command, Commands::InjectPrompt { .. } | Commands::Registry { action: RegistryAction::Prune { .. } })
+    {
+        eprintln!("Error: --dry-run only works with inject-prompt and registry prune");
+        std::process::exit(1);
+    }
+    config.dry_run = cli.dry_run;
+    if let Commands::Start { no_merge: true, .. } | Commands::Run { no_merge: true, .. } = cli.command {
+        config.registry_merge = false;
+    }
 
     match cli.command {
-        Commands::Start => cmd_start(&config),
+        Commands::Start { no_backfill, .. } => preflight(&mut config).and_then(|_| cmd_start(&config, no_backfill)),
         Commands::Stop => cmd_stop(&config),
-        Commands::Restart => cmd_restart(&config),
-        Commands::Status => cmd_status(&config),
-        Commands::Logs { lines, no_follow } => cmd_logs(&config, lines, !no_follow),
+        Commands::Restart => preflight(&mut config).and_then(|_| cmd_restart(&config)),
+        Commands::Status { json } => cmd_status(&config, json),
+        Commands::Logs { lines, no_follow, session } => cmd_logs(&config, lines, !no_follow, session.as_deref()),
         Commands::Attach { session } => cmd_attach(&config, session),
         Commands::Monitor => cmd_monitor(&config),
-        Commands::KillSession { session } => cmd_kill_session(&config, &session),
+        Commands::KillSession { session, now } => cmd_kill_session(&config, &session, now),
         Commands::KillSessions => cmd_kill_sessions(&config),
-        Commands::RestartSession { session } => cmd_restart_session(&config, &session),
+        Commands::Interrupt { session, message } => cmd_interrupt(&config, &session, message.as_deref()),
+        Commands::RestartSession { session, fresh } => cmd_restart_session(&config, &session, fresh),
         Commands::RestartSessions => cmd_restart_sessions(&config),
+        Commands::AdoptSessions { kill } => cmd_adopt_sessions(&config, kill),
         Commands::InjectPrompt {
             chat_id,
             prompt,
@@ -166,21 +490,40 @@ fn main() -> Result<()> {
             no_create,
             skip_health,
             reply_to,
+            service,
+            wait,
+            timeout,
         } => cmd_inject_prompt(
             &config,
+            &SessionManager::new(&config),
+            &mut ContactsManager::new(&config),
             &chat_id,
             &prompt,
-            bg,
-            sms,
-            admin,
-            file.as_deref(),
-            no_create,
-            skip_health,
-            reply_to.as_deref(),
+            &InjectOptions {
+                bg,
+                sms,
+                admin,
+                file: file.as_deref(),
+                no_create,
+                skip_health,
+                reply_to: reply_to.as_deref(),
+                service: service.unwrap_or_default(),
+                wait: wait.then(|| Duration::from_secs(timeout)),
+            },
         ),
+        Commands::Quarantine { lines, bless_once } => cmd_quarantine(&config, lines, bless_once),
+        Commands::Block { identifier } => cmd_block(&config, identifier.as_deref()),
+        Commands::Unblock { identifier } => cmd_unblock(&config, &identifier),
+        Commands::BlessGroup { chat_id } => cmd_bless_group(&config, chat_id.as_deref()),
+        Commands::UnblessGroup { chat_id } => cmd_unbless_group(&config, &chat_id),
+        Commands::Tier { action } => cmd_tier(&config, action),
+        Commands::Registry { action } => cmd_registry(&config, action),
+        Commands::Health { session, json, action } => cmd_health(&config, session.as_deref(), json, action),
+        Commands::Crashes { action } => cmd_crashes(&config, action),
+        Commands::Reminders { action } => cmd_reminders(&config, &mut ContactsManager::new(&config), action),
         Commands::Install => cmd_install(&config),
         Commands::Uninstall => cmd_uninstall(&config),
-        Commands::Run => cmd_run(&config),
+        Commands::Run { no_backfill, .. } => preflight(&mut config).and_then(|_| cmd_run(&config, no_backfill)),
     }
 }
 
@@ -215,7 +558,31 @@ fn is_running(config: &Config) -> bool {
     get_pid(config).is_some()
 }
 
-fn cmd_start(config: &Config) -> Result<()> {
+/// Load the registry for a CLI command, once a running daemon has saved what it's holding back
+fn load_registry(config: &Config, registry: &mut SessionRegistry) -> Result<usize> {
+    if get_pid(config).is_some_and(|pid| pid != std::process::id()) {
+        registry.request_flush()?;
+    }
+    registry.load()
+}
+
+/// Check the binaries the daemon runs, printing each problem, before starting it
+fn preflight(config: &mut Config) -> Result<()> {
+    let problems = config.validate();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("Error: {}", problem);
+    }
+    Err(Error::Config(format!(
+        "{} missing or broken binar{}; install them or fix their paths in the config",
+        problems.len(),
+        if problems.len() == 1 { "y" } else { "ies" }
+    )))
+}
+
+fn cmd_start(config: &Config, no_backfill: bool) -> Result<()> {
     if is_running(config) {
         println!("Daemon already running (PID {})", get_pid(config).unwrap());
         return Ok(());
@@ -235,8 +602,15 @@ fn cmd_start(config: &Config) -> Result<()> {
     let exe = std::env::current_exe()?;
 
     // Start the daemon
-    let child = Command::new(&exe)
-        .arg("run")
+    let mut cmd = Command::new(&exe);
+    cmd.arg("run");
+    if no_backfill {
+        cmd.arg("--no-backfill");
+    }
+    if !config.registry_merge {
+        cmd.arg("--no-merge");
+    }
+    let child = cmd
         .stdout(Stdio::from(log.try_clone()?))
         .stderr(Stdio::from(log))
         .spawn()?;
@@ -267,8 +641,8 @@ fn cmd_stop(config: &Config) -> Result<()> {
         .args(["-TERM", &pid.to_string()])
         .status();
 
-    // Wait for it to die
-    for _ in 0..10 {
+    // Wait for it to finish its sessions' queued work and exit
+    for _ in 0..60 {
         std::thread::sleep(Duration::from_millis(500));
         let status = Command::new("kill")
             .args(["-0", &pid.to_string()])
@@ -301,10 +675,20 @@ fn cmd_restart(config: &Config) -> Result<()> {
         cmd_stop(config)?;
         std::thread::sleep(Duration::from_secs(1));
     }
-    cmd_start(config)
+    cmd_start(config, false)
 }
 
-fn cmd_status(config: &Config) -> Result<()> {
+fn cmd_status(config: &Config, json: bool) -> Result<()> {
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
+    let mut sessions: Vec<&SessionData> = registry.all().values().collect();
+    sessions.sort_by(|a, b| a.session_name.cmp(&b.session_name));
+    if json {
+        let status = serde_json::json!({ "pid": get_pid(config), "sessions": sessions });
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
     if let Some(pid) = get_pid(config) {
         // Get uptime
         let result = Command::new("ps")
@@ -333,11 +717,73 @@ fn cmd_status(config: &Config) -> Result<()> {
         println!("Daemon not running");
     }
 
+    if !sessions.is_empty() {
+        println!();
+        for line in status_table(&sessions, Utc::now()) {
+            println!("{}", line);
+        }
+        let mut history = HealthHistory::new(config);
+        match history.load() {
+            Ok(()) => {
+                let summary = health_summary(&history, &sessions, Utc::now());
+                if !summary.is_empty() {
+                    println!("\nHealth:");
+                    for line in summary {
+                        println!("  {}", line);
+                    }
+                }
+            }
+            Err(e) => println!("\nHealth history unreadable: {}", e),
+        }
+    }
+
+    let problems = config.clone().validate();
+    if !problems.is_empty() {
+        println!("\nProblems:");
+        for problem in problems {
+            println!("  {}", problem);
+        }
+    }
+
     Ok(())
 }
 
-fn cmd_logs(config: &Config, lines: u32, follow: bool) -> Result<()> {
-    let log_file = config.logs_dir.join("manager.log");
+/// A row per session: name, tier, messages today, last message each way, restarts
+fn status_table(sessions: &[&SessionData], now: DateTime<Utc>) -> Vec<String> {
+    let today = now.with_timezone(&chrono::Local).date_naive();
+    let width = sessions.iter().map(|d| d.session_name.len()).chain([7]).max().unwrap_or(7);
+    let mut lines = vec![format!(
+        "{:<width$}  {:<10} {:>5}  {:<15} {:<15} {:>8} {:>8}",
+        "SESSION", "TIER", "TODAY", "LAST IN", "LAST OUT", "RESTARTS", "COMPACTS"
+    )];
+    let ago = |at: Option<DateTime<Utc>>| match at {
+        Some(at) => format!("{} ago", format_ago(now - at)),
+        None => "-".to_string(),
+    };
+    for data in sessions {
+        let counters = &data.counters;
+        lines.push(format!(
+            "{:<width$}  {:<10} {:>5}  {:<15} {:<15} {:>8} {:>8}",
+            data.session_name,
+            data.tier.as_deref().unwrap_or("-"),
+            counters.inbound_on(today),
+            ago(data.last_message_time),
+            ago(data.last_outbound_time),
+            counters.restarts,
+            counters.compactions,
+        ));
+    }
+    lines
+}
+
+fn cmd_logs(config: &Config, lines: u32, follow: bool, session: Option<&str>) -> Result<()> {
+    let log_file = match session {
+        Some(session) => {
+            let session_mgr = SessionManager::new(config);
+            session_mgr.session_log_path(&session_for_arg(config, &session_mgr, session)?)
+        }
+        None => config.logs_dir.join("manager.log"),
+    };
     if !log_file.exists() {
         println!("Log file not found: {}", log_file.display());
         return Ok(());
@@ -359,7 +805,8 @@ fn cmd_attach(config: &Config, session: Option<String>) -> Result<()> {
     match session {
         Some(name) => {
             // Attach to session
-            let status = Command::new(&config.tmux)
+            let name = session_for_arg(config, &session_mgr, &name)?;
+            let status = tmux_command(&config.tmux, &config.tmux_socket_name)
                 .args(["attach", "-t", &format!("={}", name)])
                 .status()?;
             std::process::exit(status.code().unwrap_or(1));
@@ -369,9 +816,10 @@ fn cmd_attach(config: &Config, session: Option<String>) -> Result<()> {
             match session_mgr.list_sessions() {
                 Ok(sessions) if !sessions.is_empty() => {
                     println!("Available sessions:");
-                    for session in sessions {
+                    for session in &sessions {
                         println!("  claude-assistant-rs attach {}", session);
                     }
+                    println!("\nOr attach directly with: {}", session_mgr.attach_hint(&sessions[0]));
                 }
                 _ => println!("No sessions running"),
             }
@@ -393,26 +841,37 @@ fn cmd_monitor(config: &Config) -> Result<()> {
     }
 
     // Kill existing monitor session
-    let _ = Command::new(&config.tmux)
+    let _ = tmux_command(&config.tmux, &config.tmux_socket_name)
         .args(["kill-session", "-t", "monitor"])
         .output();
 
     // Create monitor script for each session
+    let tmux = if config.tmux_socket_name.is_empty() {
+        config.tmux.display().to_string()
+    } else {
+        format!("{} -L {}", config.tmux.display(), config.tmux_socket_name)
+    };
+    // Each pane follows its session's window name, which is retitled when a contact is renamed
     let make_script = |session: &str| -> String {
         format!(
             r#"while true; do
+title=$({tmux} display-message -p -t ={session}: '#{{?automatic-rename,,#{{window_name}}}}' 2>/dev/null)
+[ -n "$title" ] && {tmux} select-pane -t "$TMUX_PANE" -T "$title"
 clear
-{} capture-pane -t {} -p 2>/dev/null | tail -30
+{tmux} capture-pane -t {session} -p 2>/dev/null | tail -30
 sleep 1
-done"#,
-            config.tmux.display(),
-            session
+done"#
         )
     };
+    let mut registry = SessionRegistry::new(config);
+    let _ = load_registry(config, &mut registry);
+    let title = |session: &str| -> String {
+        registry.get_by_session_name(session).map(session_title).unwrap_or(session).to_string()
+    };
 
     // Create monitor session with first pane
     let first = &sessions[0];
-    Command::new(&config.tmux)
+    tmux_command(&config.tmux, &config.tmux_socket_name)
         .args([
             "new-session", "-d", "-s", "monitor",
             "/bin/bash", "-c", &make_script(first),
@@ -422,15 +881,15 @@ done"#,
     std::thread::sleep(Duration::from_millis(300));
 
     // Set pane title for first pane
-    Command::new(&config.tmux)
-        .args(["select-pane", "-t", "monitor:0.0", "-T", first])
+    tmux_command(&config.tmux, &config.tmux_socket_name)
+        .args(["select-pane", "-t", "monitor:0.0", "-T", &title(first)])
         .status()?;
 
     // Split panes for remaining sessions
     for (i, session) in sessions[1..].iter().enumerate() {
         let split_flag = if (i + 1) % 2 == 1 { "-v" } else { "-h" };
 
-        Command::new(&config.tmux)
+        tmux_command(&config.tmux, &config.tmux_socket_name)
             .args([
                 "split-window", "-t", "monitor", split_flag,
                 "/bin/bash", "-c", &make_script(session),
@@ -438,12 +897,12 @@ done"#,
             .status()?;
 
         // Set pane title
-        Command::new(&config.tmux)
-            .args(["select-pane", "-t", &format!("monitor:0.{}", i + 1), "-T", session])
+        tmux_command(&config.tmux, &config.tmux_socket_name)
+            .args(["select-pane", "-t", &format!("monitor:0.{}", i + 1), "-T", &title(session)])
             .status()?;
 
         // Rebalance layout
-        Command::new(&config.tmux)
+        tmux_command(&config.tmux, &config.tmux_socket_name)
             .args(["select-layout", "-t", "monitor", "tiled"])
             .status()?;
 
@@ -451,15 +910,15 @@ done"#,
     }
 
     // Enable pane titles
-    Command::new(&config.tmux)
+    tmux_command(&config.tmux, &config.tmux_socket_name)
         .args(["set-option", "-t", "monitor", "pane-border-status", "top"])
         .status()?;
-    Command::new(&config.tmux)
+    tmux_command(&config.tmux, &config.tmux_socket_name)
         .args(["set-option", "-t", "monitor", "pane-border-format", " #{pane_title} "])
         .status()?;
 
     // Final layout
-    Command::new(&config.tmux)
+    tmux_command(&config.tmux, &config.tmux_socket_name)
         .args(["select-layout", "-t", "monitor", "tiled"])
         .status()?;
 
@@ -467,27 +926,53 @@ done"#,
     println!("Attaching... (Ctrl+b d to detach)");
 
     // Attach
-    let status = Command::new(&config.tmux)
+    let status = tmux_command(&config.tmux, &config.tmux_socket_name)
         .args(["attach", "-t", "monitor"])
         .status()?;
     std::process::exit(status.code().unwrap_or(0));
 }
 
-fn cmd_kill_session(config: &Config, session: &str) -> Result<()> {
+/// Where a session's transcript lives, per the registry or derived from its name
+fn session_transcript_dir(config: &Config, registry: &SessionRegistry, session: &str) -> PathBuf {
+    registry
+        .get_by_session_name(session)
+        .map(|d| PathBuf::from(&d.transcript_dir))
+        .unwrap_or_else(|| config.transcripts_dir.join(session))
+}
+
+fn cmd_kill_session(config: &Config, session: &str, now: bool) -> Result<()> {
     let session_mgr = SessionManager::new(config);
+    let session = &session_for_arg(config, &session_mgr, session)?;
 
     if !session_mgr.session_exists(session) {
         println!("Session not found: {}", session);
         return Ok(());
     }
 
-    session_mgr.kill_session(session)?;
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
+    let mode = if now { KillMode::Now } else { KillMode::Graceful };
+    session_mgr.archive_and_kill(session, &session_transcript_dir(config, &registry, session), mode)?;
     println!("Killed session: {}", session);
     println!("Session will be recreated on next incoming message");
 
     Ok(())
 }
 
+fn cmd_interrupt(config: &Config, session: &str, message: Option<&str>) -> Result<()> {
+    let session_mgr = SessionManager::new(config);
+    let session = &session_for_arg(config, &session_mgr, session)?;
+
+    if !session_mgr.session_exists(session) {
+        println!("Session not found: {}", session);
+        return Ok(());
+    }
+
+    session_mgr.interrupt(session, message)?;
+    println!("Interrupted session: {}", session);
+    Ok(())
+}
+
 fn cmd_kill_sessions(config: &Config) -> Result<()> {
     let session_mgr = SessionManager::new(config);
     let sessions = session_mgr.list_sessions()?;
@@ -497,8 +982,10 @@ fn cmd_kill_sessions(config: &Config) -> Result<()> {
         return Ok(());
     }
 
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
     for session in &sessions {
-        session_mgr.kill_session(session)?;
+        session_mgr.archive_and_kill(session, &session_transcript_dir(config, &registry, session), KillMode::Graceful)?;
         println!("Killed: {}", session);
     }
 
@@ -508,18 +995,20 @@ fn cmd_kill_sessions(config: &Config) -> Result<()> {
     Ok(())
 }
 
-fn cmd_restart_session(config: &Config, session: &str) -> Result<()> {
+fn cmd_restart_session(config: &Config, session: &str, fresh: bool) -> Result<()> {
     let session_mgr = SessionManager::new(config);
     let mut registry = SessionRegistry::new(config);
-    registry.load()?;
+    load_registry(config, &mut registry)?;
 
     // Look up session in registry
     let session_data = registry.get_by_session_name(session);
-    let (contact_name, tier, _chat_id) = if let Some(data) = session_data {
+    let key = session_data.map(registry_key);
+    let (contact_name, tier, chat_id, conversation) = if let Some(data) = session_data {
         (
             data.contact_name.clone().unwrap_or_else(|| session.replace('-', " ")),
-            data.tier.clone().unwrap_or_else(|| "favorite".to_string()),
+            data.tier.clone().unwrap_or_else(|| config.default_tier().to_string()),
             data.chat_id.clone(),
+            data.claude_session_id.clone(),
         )
     } else {
         // Try to derive from session name
@@ -536,29 +1025,75 @@ fn cmd_restart_session(config: &Config, session: &str) -> Result<()> {
             .join(" ");
 
         println!("Session not in registry, using derived name: {}", contact_name);
-        (contact_name, "favorite".to_string(), session.to_string())
+        (contact_name, config.default_tier().to_string(), session.to_string(), None)
     };
 
     let transcript_dir = config.transcripts_dir.join(session);
+    let conversation = restart_conversation(&mut registry, key.as_deref(), &transcript_dir, conversation, fresh)?;
+    note_manual_restarts(config, &[session]);
+    if let Some(key) = &key {
+        if registry.set_quarantined(key, false)? {
+            println!("Lifted the quarantine on {}", session);
+        }
+    }
 
     // Kill if exists
     if session_mgr.session_exists(session) {
-        session_mgr.kill_session(session)?;
+        session_mgr.archive_and_kill(session, &transcript_dir, KillMode::Graceful)?;
         println!("Killed session: {}", session);
         std::thread::sleep(Duration::from_secs(1));
     }
 
     // Recreate
-    session_mgr.create_session(session, &transcript_dir, &tier)?;
+    let mut contacts = ContactsManager::new(config);
+    let contact = session_contact(&mut contacts, &chat_id);
+    let info = SessionInfo::new(&chat_id, &tier, &contact_name);
+    let resumed =
+        session_mgr.resume_session(session, &transcript_dir, &info, contact.as_ref(), conversation.as_deref())?;
     println!("Created session: {} (tier: {}, contact: {})", session, tier, contact_name);
 
+    if resumed {
+        println!("Resumed conversation {}", conversation.unwrap_or_default());
+    } else {
+        // Give the fresh session the recent conversation
+        inject_backfill(config, &session_mgr, &MessagesReader::new(config), &mut contacts, session, &chat_id);
+    }
+
     Ok(())
 }
 
+/// The registry key of a session: its chat ID, or for a background session the chat's background key
+fn registry_key(data: &SessionData) -> String {
+    match data.session_type.as_str() {
+        "background" => SessionRegistry::background_key(&data.chat_id),
+        _ => data.chat_id.clone(),
+    }
+}
+
+/// The conversation a restarted session resumes: the newest on disk, else the registered one
+///
+/// A fresh restart resumes nothing, and the registered conversation is
+/// forgotten so a later health restart doesn't go back to it.
+fn restart_conversation(
+    registry: &mut SessionRegistry,
+    key: Option<&str>,
+    transcript_dir: &Path,
+    registered: Option<String>,
+    fresh: bool,
+) -> Result<Option<String>> {
+    if !fresh {
+        return Ok(latest_conversation(transcript_dir).or(registered));
+    }
+    if let Some(key) = key {
+        registry.clear_claude_session(key)?;
+    }
+    Ok(None)
+}
+
 fn cmd_restart_sessions(config: &Config) -> Result<()> {
     let session_mgr = SessionManager::new(config);
     let mut registry = SessionRegistry::new(config);
-    registry.load()?;
+    load_registry(config, &mut registry)?;
 
     let sessions = session_mgr.list_sessions()?;
     if sessions.is_empty() {
@@ -566,22 +1101,31 @@ fn cmd_restart_sessions(config: &Config) -> Result<()> {
         return Ok(());
     }
 
+    let mut contacts = ContactsManager::new(config);
     let mut restarted = 0;
+    note_manual_restarts(config, &sessions.iter().map(String::as_str).collect::<Vec<_>>());
     for session in &sessions {
         // Kill
-        session_mgr.kill_session(session)?;
+        let transcript_dir = config.transcripts_dir.join(session);
+        session_mgr.archive_and_kill(session, &transcript_dir, KillMode::Graceful)?;
         println!("Killed: {}", session);
         std::thread::sleep(Duration::from_millis(500));
 
         // Get tier from registry
-        let tier = registry
-            .get_by_session_name(session)
+        let data = registry.get_by_session_name(session);
+        let tier = data
             .and_then(|d| d.tier.clone())
-            .unwrap_or_else(|| "favorite".to_string());
-
-        let transcript_dir = config.transcripts_dir.join(session);
-        session_mgr.create_session(session, &transcript_dir, &tier)?;
-        println!("Recreated: {} (tier: {})", session, tier);
+            .unwrap_or_else(|| config.default_tier().to_string());
+        let contact = data.and_then(|d| session_contact(&mut contacts, &d.chat_id));
+        let info = match data {
+            Some(data) => session_info(data, &tier),
+            None => SessionInfo::new(session, &tier, session),
+        };
+
+        let conversation = latest_conversation(&transcript_dir).or_else(|| data.and_then(|d| d.claude_session_id.clone()));
+        let resumed =
+            session_mgr.resume_session(session, &transcript_dir, &info, contact.as_ref(), conversation.as_deref())?;
+        println!("Recreated: {} (tier: {}{})", session, tier, if resumed { ", resumed" } else { "" });
         restarted += 1;
     }
 
@@ -589,20 +1133,170 @@ fn cmd_restart_sessions(config: &Config) -> Result<()> {
     Ok(())
 }
 
-fn cmd_inject_prompt(
+/// Sessions on the default tmux server, where they ran before the daemon had its own socket
+fn default_server(config: &Config) -> SessionManager {
+    let mut default_server = config.clone();
+    default_server.tmux_socket_name.clear();
+    SessionManager::new(&default_server)
+}
+
+/// Registered sessions still running on the default tmux server, from before the daemon had its own socket
+fn legacy_sessions(config: &Config, legacy: &SessionManager, registry: &SessionRegistry) -> Vec<String> {
+    if config.tmux_socket_name.is_empty() {
+        return Vec::new();
+    }
+    let mut sessions = legacy.list_sessions().unwrap_or_default();
+    sessions.retain(|name| registry.get_by_session_name(name).is_some());
+    sessions
+}
+
+/// Register running sessions the registry has lost track of, returning their names
+///
+/// Who a session is for comes from the `session-info.json` in its transcript
+/// dir, or failing that the blessed contact it's named after. Sessions that
+/// can't be placed are left alone, so the registry never guesses.
+fn adopt_orphans(
     config: &Config,
-    chat_id: &str,
-    prompt: &str,
+    session_mgr: &SessionManager,
+    registry: &mut SessionRegistry,
+    contacts: &mut dyn ContactSource,
+) -> Vec<String> {
+    let sessions = match session_mgr.list_sessions() {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            warn!("Failed to list sessions to adopt: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut adopted = Vec::new();
+    for session_name in sessions {
+        if session_name == "monitor" || registry.get_by_session_name(&session_name).is_some() {
+            continue;
+        }
+        let (base, session_type) = match session_name.strip_suffix("-bg") {
+            Some(base) => (base, "background"),
+            None if session_name.starts_with("group-") => (session_name.as_str(), "group"),
+            None => (session_name.as_str(), "individual"),
+        };
+        let transcript_dir = config.transcripts_dir.join(base);
+
+        let owner = match SessionInfo::load(&transcript_dir) {
+            Some(info) => Some((info.chat_id, info.contact, info.tier)),
+            // Groups aren't named after anyone
+            None if session_type == "group" => None,
+            None => contact_for_session(contacts, base),
+        };
+        let Some((chat_id, contact_name, tier)) = owner else {
+            warn!("Can't tell who session {} is for, leaving it out of the registry", session_name);
+            continue;
+        };
+        let key = if session_type == "background" { SessionRegistry::background_key(&chat_id) } else { chat_id.clone() };
+        if let Some(existing) = registry.get(&key) {
+            warn!("Session {} is for {}, which already has {}; not adopting it", session_name, chat_id, existing.session_name);
+            continue;
+        }
+
+        match registry.register(
+            &chat_id,
+            &session_name,
+            transcript_dir.to_str().unwrap_or(""),
+            session_type,
+            Some(contact_name),
+            None,
+            Some(tier),
+            None,
+        ) {
+            Ok(_) => {
+                info!("Adopted session {} for {}", session_name, chat_id);
+                adopted.push(session_name);
+            }
+            Err(e) => warn!("Failed to adopt session {}: {}", session_name, e),
+        }
+    }
+    adopted
+}
+
+/// The blessed contact a session is named after, as (chat_id, name, tier)
+fn contact_for_session(contacts: &mut dyn ContactSource, session_name: &str) -> Option<(String, String, String)> {
+    let blessed = match contacts.list_blessed() {
+        Ok(blessed) => blessed,
+        Err(e) => {
+            warn!("Failed to list contacts to match session {}: {}", session_name, e);
+            return None;
+        }
+    };
+    let mut matches = blessed.into_iter().filter(|contact| {
+        let name = contact.alias.as_deref().unwrap_or(&contact.name);
+        SessionManager::session_name_for_contact(name) == session_name
+    });
+    let contact = matches.next()?;
+    if matches.next().is_some() {
+        warn!("More than one contact could own session {}", session_name);
+        return None;
+    }
+    let chat_id = contact.phone.clone().or_else(|| contact.email.clone())?;
+    Some((chat_id, contact.name, contact.tier))
+}
+
+fn cmd_adopt_sessions(config: &Config, kill: bool) -> Result<()> {
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
+
+    let legacy = default_server(config);
+    let sessions = legacy_sessions(config, &legacy, &registry);
+    if sessions.is_empty() {
+        println!("No sessions to adopt");
+        return Ok(());
+    }
+
+    for session in &sessions {
+        legacy.archive_and_kill(session, &session_transcript_dir(config, &registry, session), KillMode::Graceful)?;
+        println!("Killed {} on the default tmux server", session);
+        if !kill {
+            cmd_restart_session(config, session, false)?;
+        }
+    }
+    Ok(())
+}
+
+/// How `inject-prompt` delivers a prompt; the default types it as is into the main session
+#[derive(Default)]
+struct InjectOptions<'a> {
+    /// Into the background session instead
     bg: bool,
+    /// Wrapped like an incoming text
     sms: bool,
+    /// Wrapped in ADMIN OVERRIDE tags
     admin: bool,
-    file: Option<&Path>,
+    /// Read the prompt from here instead
+    file: Option<&'a Path>,
+    /// Fail rather than start a session that isn't running
     no_create: bool,
     skip_health: bool,
-    reply_to: Option<&str>,
+    /// GUID of the message being replied to, with `sms`
+    reply_to: Option<&'a str>,
+    /// Shown in the header, with `sms`
+    service: MessageService,
+    /// Wait this long for the response and print it
+    wait: Option<Duration>,
+}
+
+fn cmd_inject_prompt(
+    config: &Config,
+    session_mgr: &SessionManager,
+    contacts: &mut dyn ContactSource,
+    chat_id: &str,
+    prompt: &str,
+    options: &InjectOptions,
 ) -> Result<()> {
-    // Normalize chat_id
-    let chat_id = normalize_chat_id(chat_id);
+    let InjectOptions { bg, sms, admin, file, no_create, skip_health, reply_to, service, wait } = *options;
+    // Load registry
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
+
+    // Normalize chat_id, or find the chat a name refers to
+    let chat_id = chat_for_arg(&registry, contacts, chat_id, &config.default_region);
 
     // Get prompt from file or args
     let prompt = if let Some(path) = file {
@@ -616,32 +1310,15 @@ fn cmd_inject_prompt(
         std::process::exit(1);
     }
 
-    // Load registry
-    let mut registry = SessionRegistry::new(config);
-    registry.load()?;
-
     // Look up session info
-    let session_data = registry.get(&chat_id).cloned();
-    let (session_name, contact_name, tier) = if let Some(data) = session_data {
-        (
-            data.session_name.clone(),
-            data.contact_name.clone().unwrap_or_else(|| data.session_name.replace('-', " ")),
-            data.tier.clone().unwrap_or_else(|| "favorite".to_string()),
-        )
-    } else {
-        // Try to look up from contacts
-        let mut contacts = ContactsManager::new(config);
-        if let Ok(Some(contact)) = contacts.lookup_phone(&chat_id) {
-            let session_name = SessionManager::session_name_for_contact(&contact.name);
-            (session_name, contact.name, contact.tier)
-        } else {
+    let (session_name, contact_name, tier) = match resolve_inject_target(config, &registry, contacts, &chat_id) {
+        Some(target) => target,
+        None => {
             eprintln!("Error: Contact not found for {}", chat_id);
             std::process::exit(5);
         }
     };
 
-    let session_mgr = SessionManager::new(config);
-
     // Determine target session
     let target = if bg {
         format!("{}-bg", session_name)
@@ -657,18 +1334,22 @@ fn cmd_inject_prompt(
         }
 
         // Create session
-        println!("Creating session {}...", target);
+        if !config.dry_run {
+            println!("Creating session {}...", target);
+        }
         let transcript_dir = config.transcripts_dir.join(&session_name);
-        session_mgr.create_session(&target, &transcript_dir, &tier)?;
+        let contact = session_contact(contacts, &chat_id);
+        session_mgr.create_session(&target, &transcript_dir, &SessionInfo::new(&chat_id, &tier, &contact_name), contact.as_ref())?;
     } else if !skip_health {
         // Check health
         match session_mgr.check_health(&target) {
             HealthStatus::Unhealthy(reason) => {
                 println!("Session {} unhealthy ({:?}), restarting...", target, reason);
-                session_mgr.kill_session(&target)?;
-                std::thread::sleep(Duration::from_secs(1));
                 let transcript_dir = config.transcripts_dir.join(&session_name);
-                session_mgr.create_session(&target, &transcript_dir, &tier)?;
+                session_mgr.archive_and_kill(&target, &transcript_dir, KillMode::for_unhealthy(&reason))?;
+                std::thread::sleep(Duration::from_secs(1));
+                let contact = session_contact(contacts, &chat_id);
+                session_mgr.create_session(&target, &transcript_dir, &SessionInfo::new(&chat_id, &tier, &contact_name), contact.as_ref())?;
             }
             HealthStatus::Healthy => {}
         }
@@ -677,26 +1358,825 @@ fn cmd_inject_prompt(
     // Wrap prompt
     let mut final_prompt = prompt;
     if sms {
-        final_prompt = wrap_sms(&final_prompt, &contact_name, &tier, &chat_id, reply_to);
+        let group = registry.get(&chat_id).filter(|d| d.session_type == "group").map(|d| GroupContext {
+            name: d.display_name.as_deref(),
+            participants: None,
+        });
+        let reply_context = reply_to.map(|guid| {
+            lookup_reply_context(&MessagesReader::new(config), contacts, guid)
+        });
+        final_prompt = wrap_sms(
+            &final_prompt,
+            &contact_name,
+            &tier,
+            &chat_id,
+            &SmsOptions { service, reply_context: reply_context.as_deref(), group, ..Default::default() },
+        );
     }
     if admin {
         final_prompt = wrap_admin(&final_prompt);
     }
 
-    // Inject
+    if config.dry_run {
+        // Says what it would have injected; nothing to record or wait for
+        return session_mgr.inject_text(&target, &final_prompt);
+    }
+
+    // Inject, keeping the pane as it was to find the response in afterwards
+    let snapshot = match wait {
+        Some(_) => Some(session_mgr.response_snapshot(&target)?),
+        None => None,
+    };
     session_mgr.inject_text(&target, &final_prompt)?;
 
     // Update registry
     if registry.get(&chat_id).is_some() {
         registry.update_last_message(&chat_id)?;
+        registry.flush()?;
     }
 
-    println!("Injected into {}", target);
+    let (Some(timeout), Some(snapshot)) = (wait, snapshot) else {
+        println!("Injected into {}", target);
+        return Ok(());
+    };
+    let (response, finished) = session_mgr.wait_for_response(&target, &snapshot, timeout)?;
+    println!("{}", response);
+    if !finished {
+        eprintln!("Timed out after {}s waiting for {} to finish", timeout.as_secs(), target);
+        std::process::exit(EXIT_TIMEOUT);
+    }
     Ok(())
 }
 
-fn cmd_install(config: &Config) -> Result<()> {
-    let plist_dst = dirs::home_dir()
+fn cmd_quarantine(config: &Config, lines: usize, bless_once: Option<i64>) -> Result<()> {
+    let rowid = match bless_once {
+        Some(rowid) => rowid,
+        None => {
+            let entries = Quarantine::new(config).recent(lines)?;
+            if entries.is_empty() {
+                println!("Quarantine is empty");
+            }
+            for entry in &entries {
+                println!("{}", format_quarantine_entry(entry));
+            }
+            return Ok(());
+        }
+    };
+
+    match bless_quarantined(config, &SessionManager::new(config), rowid)? {
+        Some(session_name) => {
+            println!("Injected message {} into {}", rowid, session_name);
+            Ok(())
+        }
+        None => {
+            eprintln!("Error: No quarantined message with ROWID {}", rowid);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cmd_block(config: &Config, identifier: Option<&str>) -> Result<()> {
+    let mut blocklist = Blocklist::load(config)?;
+    match identifier {
+        Some(identifier) => {
+            if blocklist.add(identifier) {
+                blocklist.save()?;
+                println!("Blocked {}", identifier);
+            } else {
+                println!("{} is already blocked", identifier);
+            }
+        }
+        None => {
+            if blocklist.all().is_empty() {
+                println!("Nobody is blocked");
+            }
+            for entry in blocklist.all() {
+                println!("{}", entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_unblock(config: &Config, identifier: &str) -> Result<()> {
+    let mut blocklist = Blocklist::load(config)?;
+    if blocklist.remove(identifier) {
+        blocklist.save()?;
+        println!("Unblocked {}", identifier);
+    } else {
+        println!("{} was not blocked", identifier);
+    }
+    Ok(())
+}
+
+fn cmd_bless_group(config: &Config, chat_id: Option<&str>) -> Result<()> {
+    let mut groups = BlessedGroups::load(config)?;
+    match chat_id {
+        Some(chat_id) => {
+            if groups.add(chat_id) {
+                groups.save()?;
+                println!("Blessed group {}", chat_id);
+            } else {
+                println!("Group {} is already blessed", chat_id);
+            }
+        }
+        None => {
+            if groups.all().is_empty() {
+                println!("No blessed groups");
+            }
+            for entry in groups.all() {
+                println!("{}", entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_unbless_group(config: &Config, chat_id: &str) -> Result<()> {
+    let mut groups = BlessedGroups::load(config)?;
+    if groups.remove(chat_id) {
+        groups.save()?;
+        println!("Unblessed group {}", chat_id);
+    } else {
+        println!("Group {} was not blessed", chat_id);
+    }
+    Ok(())
+}
+
+fn cmd_tier(config: &Config, action: TierAction) -> Result<()> {
+    let mut overrides = TierOverrides::load(config)?;
+    match action {
+        TierAction::Set { identifier, tier } => {
+            let previous = overrides.set(&identifier, &tier)?;
+            overrides.save()?;
+            match previous {
+                Some(previous) => println!("{}: {} -> {}", identifier, previous, tier.to_lowercase()),
+                None => println!("{}: {}", identifier, tier.to_lowercase()),
+            }
+        }
+        TierAction::List => {
+            if overrides.all().is_empty() {
+                println!("No tier overrides");
+            }
+            for (identifier, tier) in overrides.all() {
+                println!("{:<32} {}", identifier, tier);
+            }
+        }
+        TierAction::Rm { identifier } => match overrides.remove(&identifier) {
+            Some(tier) => {
+                overrides.save()?;
+                println!("Removed {} ({})", identifier, tier);
+            }
+            None => {
+                eprintln!("Error: No override for {}", identifier);
+                std::process::exit(1);
+            }
+        },
+    }
+    Ok(())
+}
+
+fn cmd_health(config: &Config, session: Option<&str>, json: bool, action: Option<HealthAction>) -> Result<()> {
+    let Some(action) = action else {
+        let mut history = HealthHistory::new(config);
+        history.load()?;
+        let events: Vec<&HealthEvent> = history.events().filter(|e| session.is_none_or(|s| e.session == s)).collect();
+        if json {
+            println!("{}", serde_json::to_string_pretty(&events)?);
+            return Ok(());
+        }
+        if events.is_empty() {
+            println!("No health events{}", session.map(|s| format!(" for {}", s)).unwrap_or_default());
+        }
+        for line in history_lines(&events) {
+            println!("{}", line);
+        }
+        return Ok(());
+    };
+    match action {
+        HealthAction::TestPatterns { file, session, patterns } => {
+            let patterns = match patterns {
+                Some(path) if !path.exists() => return Err(Error::Config(format!("{} doesn't exist", path.display()))),
+                Some(path) => HealthPatterns::load(&path)?,
+                None => config.health_patterns.clone(),
+            };
+            let content = match (file, session) {
+                (Some(file), _) => fs::read_to_string(file)?,
+                (None, Some(session)) => {
+                    let session_mgr = SessionManager::new(config);
+                    let session = session_for_arg(config, &session_mgr, &session)?;
+                    // As much as the health sweep looks at
+                    session_mgr.capture_pane(&session, 30)?
+                }
+                (None, None) => unreachable!("clap requires a file or --session"),
+            };
+            for line in patterns_report(&patterns, &content) {
+                println!("{}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_crashes(config: &Config, action: CrashesAction) -> Result<()> {
+    let dir = crash::crashes_dir(&config.logs_dir);
+    match action {
+        CrashesAction::List { session } => {
+            let reports: Vec<(String, CrashReport)> = crash::list(&dir)?
+                .into_iter()
+                .filter(|(_, name, _)| session.as_ref().is_none_or(|s| s == name))
+                .filter_map(|(path, _, _)| {
+                    let report = CrashBundle::load(&path).ok()?.report;
+                    Some((path.file_name()?.to_string_lossy().to_string(), report))
+                })
+                .collect();
+            if reports.is_empty() {
+                println!("No crash reports{}", session.map(|s| format!(" for {}", s)).unwrap_or_default());
+            }
+            for line in crash_lines(&reports) {
+                println!("{}", line);
+            }
+        }
+        CrashesAction::Show { report } => {
+            let named = dir.join(&report);
+            let path = if !report.contains('/') && named.is_dir() {
+                named
+            } else {
+                // A session: its newest
+                crash::list(&dir)?
+                    .into_iter()
+                    .find(|(_, session, _)| *session == report)
+                    .map(|(path, _, _)| path)
+                    .ok_or(Error::SessionNotFound(report))?
+            };
+            print!("{}", crash_report_text(&path, &CrashBundle::load(&path)?)?);
+        }
+    }
+    Ok(())
+}
+
+/// A line per crash report: when, which session, why, and the report's name for `crashes show`
+fn crash_lines(reports: &[(String, CrashReport)]) -> Vec<String> {
+    let width = reports.iter().map(|(_, r)| r.session.len()).chain([7]).max().unwrap_or(7);
+    reports
+        .iter()
+        .map(|(name, report)| {
+            format!(
+                "{}  {:<width$}  {}  {}",
+                report.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+                report.session,
+                report.reason,
+                name
+            )
+        })
+        .collect()
+}
+
+/// A crash report in full, each part under a heading; parts that weren't saved say so
+fn crash_report_text(path: &Path, bundle: &CrashBundle) -> Result<String> {
+    let report = &bundle.report;
+    let mut text = format!(
+        "Crash report {}\nSession: {}\nAt: {}\nReason: {}\n",
+        path.display(),
+        report.session,
+        report.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+        report.reason
+    );
+    let registry = report.registry.as_ref().map(serde_json::to_string_pretty).transpose()?;
+    let processes = bundle.processes.as_deref().map(format_ps);
+    let parts = [
+        ("Registry entry", registry),
+        ("Processes", processes),
+        ("Daemon log", bundle.daemon_log.clone()),
+        ("Scrollback", bundle.pane.clone()),
+    ];
+    for (heading, part) in parts {
+        text.push_str(&format!("\n== {} ==\n", heading));
+        match part {
+            Some(part) => {
+                text.push_str(&part);
+                if !part.ends_with('\n') {
+                    text.push('\n');
+                }
+            }
+            None => text.push_str("(not saved)\n"),
+        }
+    }
+    Ok(text)
+}
+
+fn cmd_reminders(config: &Config, contacts: &mut dyn ContactSource, action: RemindersAction) -> Result<()> {
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
+    let mut store = ReminderStore::load(config)?;
+    let now = Utc::now();
+    match action {
+        RemindersAction::List { chat_id } => {
+            let chat_id = chat_id.map(|arg| chat_for_arg(&registry, contacts, &arg, &config.default_region));
+            let reminders = load_reminders(config, contacts, &registry, &store, now)?;
+            let mut listed: Vec<Upcoming<'_>> = reminders
+                .upcoming()
+                .into_iter()
+                .filter(|u| chat_id.as_ref().is_none_or(|c| c == u.chat_id))
+                .collect();
+            listed.sort_by_key(|u| (u.chat_id, u.index));
+            if listed.is_empty() {
+                println!("No reminders{}", chat_id.map(|c| format!(" for {}", c)).unwrap_or_default());
+            }
+            for line in reminder_lines(&listed, now) {
+                println!("{}", line);
+            }
+        }
+        RemindersAction::Next => {
+            let reminders = load_reminders(config, contacts, &registry, &store, now)?;
+            match reminders.upcoming().into_iter().find(|u| u.next.is_some()) {
+                Some(next) => println!("{}", reminder_lines(&[next], now)[0]),
+                None => println!("No reminders due"),
+            }
+        }
+        RemindersAction::Add { chat_id, cron, prompt } => {
+            let chat_id = chat_for_arg(&registry, contacts, &chat_id, &config.default_region);
+            let position = store.add(&chat_id, &cron, &prompt)?;
+            store.save()?;
+            let reminders = load_reminders(config, contacts, &registry, &store, now)?;
+            let added = reminders.upcoming().into_iter().find(|u| u.chat_id == chat_id && u.reminder.added == Some(position));
+            if let Some(added) = added {
+                println!("Added {}", reminder_lines(&[added], now)[0]);
+            }
+        }
+        RemindersAction::Rm { chat_id, index } => {
+            let chat_id = chat_for_arg(&registry, contacts, &chat_id, &config.default_region);
+            let reminders = load_reminders(config, contacts, &registry, &store, now)?;
+            let added = reminders
+                .get(&chat_id)
+                .and_then(|chat| chat.get(index.checked_sub(1)?))
+                .map(|reminder| reminder.added);
+            match added {
+                Some(Some(position)) => {
+                    if let Some(removed) = store.remove(&chat_id, position) {
+                        store.save()?;
+                        println!("Removed reminder #{} for {}: {} | {}", index, chat_id, removed.cron, removed.prompt);
+                    }
+                }
+                Some(None) => {
+                    eprintln!("Error: Reminder #{} for {} is from their contact notes; edit the notes to change it", index, chat_id);
+                    std::process::exit(1);
+                }
+                None => {
+                    eprintln!("Error: No reminder #{} for {}", index, chat_id);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reminders as the daemon has them: from blessed contacts' notes and the reminders file,
+/// counting from when each last fired. Read only; nothing is saved.
+fn load_reminders(
+    config: &Config,
+    contacts: &mut dyn ContactSource,
+    registry: &SessionRegistry,
+    store: &ReminderStore,
+    now: DateTime<Utc>,
+) -> Result<ReminderManager> {
+    let mut reminders = ReminderManager::with_config(config);
+    reminders.load()?;
+    reminders.sync_contacts(&contacts.list_blessed()?, store.reminders(), registry, now);
+    Ok(reminders)
+}
+
+/// A line per reminder: its chat and number, schedule, when it's next due, where it's from, and its prompt
+fn reminder_lines(reminders: &[Upcoming<'_>], now: DateTime<Utc>) -> Vec<String> {
+    let chat_width = reminders.iter().map(|u| u.chat_id.len()).max().unwrap_or(0);
+    let cron_width = reminders.iter().map(|u| u.reminder.cron_expr.len()).max().unwrap_or(0);
+    reminders
+        .iter()
+        .map(|u| {
+            let next = match u.next {
+                Some(next) if next <= now => "due now".to_string(),
+                Some(next) => next.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string(),
+                None => "never".to_string(),
+            };
+            format!(
+                "{:<chat_width$}  #{:<2} {:<cron_width$}  {:<16}  {:<5}  {}",
+                u.chat_id,
+                u.index + 1,
+                u.reminder.cron_expr,
+                next,
+                if u.reminder.added.is_some() { "added" } else { "notes" },
+                u.reminder.prompt
+            )
+        })
+        .collect()
+}
+
+/// A line per health event, oldest first: when, which session, how it changed, and what was done
+fn history_lines(events: &[&HealthEvent]) -> Vec<String> {
+    let width = events.iter().map(|e| e.session.len()).chain([7]).max().unwrap_or(7);
+    events
+        .iter()
+        .map(|event| {
+            let mut line = format!(
+                "{}  {:<width$}  {} -> {}",
+                event.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+                event.session,
+                event.from.map_or("-".to_string(), |from| from.to_string()),
+                event.to
+            );
+            if let Some(reason) = &event.reason {
+                line.push_str(&format!(" ({})", reason));
+            }
+            if let Some(action) = event.action {
+                line.push_str(&format!(", {}", action));
+            }
+            line
+        })
+        .collect()
+}
+
+/// A line per session with health events: how it stands and how often it was restarted in the last day
+fn health_summary(history: &HealthHistory, sessions: &[&SessionData], now: DateTime<Utc>) -> Vec<String> {
+    let width = sessions.iter().map(|d| d.session_name.len()).chain([7]).max().unwrap_or(7);
+    let plural = |n: usize, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+    sessions
+        .iter()
+        .filter_map(|data| {
+            let events: Vec<&HealthEvent> = history.events().filter(|e| e.session == data.session_name).collect();
+            let last = events.last()?;
+            let day: Vec<&&HealthEvent> = events.iter().filter(|e| now - e.at < chrono::Duration::days(1)).collect();
+            let restarts = day
+                .iter()
+                .filter(|e| matches!(e.action, Some(ActionTaken::Restart | ActionTaken::ManualRestart)))
+                .count();
+            let reason = last.reason.as_ref().map(|r| format!(" ({})", r)).unwrap_or_default();
+            Some(format!(
+                "{:<width$}  {}{} for {}, {} and {} in the last day",
+                data.session_name,
+                last.to,
+                reason,
+                format_ago(now - last.at),
+                plural(day.len(), "event"),
+                plural(restarts, "restart")
+            ))
+        })
+        .collect()
+}
+
+/// Note restarts from the command line in the health history
+fn note_manual_restarts(config: &Config, sessions: &[&str]) {
+    let mut history = HealthHistory::new(config);
+    if let Err(e) = history.load() {
+        warn!("Failed to load health history: {}", e);
+        return;
+    }
+    for session in sessions {
+        history.transition(session, Utc::now(), HealthState::Restarting, None, Some(ActionTaken::ManualRestart));
+    }
+    if let Err(e) = history.save() {
+        warn!("Failed to save health history: {}", e);
+    }
+}
+
+/// Which patterns match a pane capture, then what the health checks make of it
+fn patterns_report(patterns: &HealthPatterns, content: &str) -> Vec<String> {
+    let (fatal, api) = patterns.counts();
+    let mut lines = vec![format!("{} fatal and {} API patterns", fatal, api)];
+    let matches = patterns.matches(content);
+    if matches.is_empty() {
+        lines.push("Nothing matched".to_string());
+    }
+    for found in matches {
+        lines.push(format!("{:<6} {:<24} {}", found.kind, found.name, found.line.trim()));
+    }
+    let verdict = |status: HealthStatus| match status {
+        HealthStatus::Healthy => "healthy".to_string(),
+        HealthStatus::Unhealthy(reason) => format!("unhealthy ({})", reason),
+    };
+    lines.push(format!("Health sweep: {}", verdict(patterns.check_with_context(content, &HealthCheckOptions::default()))));
+    lines.push(format!("Whole capture: {}", verdict(patterns.check(content))));
+    lines
+}
+
+fn cmd_registry(config: &Config, action: RegistryAction) -> Result<()> {
+    let mut registry = SessionRegistry::new(config);
+    match action {
+        RegistryAction::List => {
+            load_registry(config, &mut registry)?;
+            for line in registry_list(&registry) {
+                println!("{}", line);
+            }
+        }
+        RegistryAction::Show { target } => {
+            load_registry(config, &mut registry)?;
+            println!("{}", serde_json::to_string_pretty(registry_entry(&registry, &target)?)?);
+        }
+        RegistryAction::Rm { chat_id, yes } => {
+            load_registry(config, &mut registry)?;
+            let session_name = match registry.get(&chat_id) {
+                Some(data) => data.session_name.clone(),
+                None => return Err(Error::SessionNotFound(chat_id)),
+            };
+            if !yes && !confirm(&format!("Remove {} (session {}) from the registry?", chat_id, session_name)) {
+                println!("Left {} alone", chat_id);
+                return Ok(());
+            }
+            if registry.remove(&chat_id)?.is_some() {
+                println!("Removed {} ({})", chat_id, session_name);
+            }
+        }
+        RegistryAction::Set { chat_id, tier, contact_name, display_name } => {
+            load_registry(config, &mut registry)?;
+            for change in registry_set(config, &mut registry, &chat_id, tier, contact_name, display_name)? {
+                println!("{}", change);
+            }
+        }
+        RegistryAction::Prune { days, transcripts_gone } => {
+            load_registry(config, &mut registry)?;
+            let session_mgr = SessionManager::new(config);
+            let older_than = Duration::from_secs(days * 24 * 3600);
+            if config.dry_run {
+                let prunable = registry.prunable(&session_mgr, older_than, transcripts_gone);
+                for data in &prunable {
+                    println!("Would prune {}", prune_line(data));
+                }
+                println!("{} entries would be pruned", prunable.len());
+            } else {
+                let pruned = registry.prune(&session_mgr, older_than, transcripts_gone)?;
+                for data in &pruned {
+                    println!("Pruned {}", prune_line(data));
+                }
+                println!("{} entries pruned", pruned.len());
+            }
+        }
+        RegistryAction::Export { output } => {
+            load_registry(config, &mut registry)?;
+            let json = serde_json::to_string_pretty(&registry.export(&home_dir()?))?;
+            match output {
+                Some(path) => {
+                    fs::write(&path, json + "\n")?;
+                    println!("Exported {} sessions to {}", registry.len(), path.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        RegistryAction::Import { file, merge: _, replace } => {
+            let export: RegistryExport = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            let mode = if replace { ImportMode::Replace } else { ImportMode::Merge };
+            let summary = registry.import(config, export, mode, &home_dir()?)?;
+            println!(
+                "Imported {}: {} added, {} updated, {} kept as newer here, {} removed",
+                file.display(),
+                summary.added,
+                summary.updated,
+                summary.kept,
+                summary.removed
+            );
+        }
+        RegistryAction::Verify => {
+            let (report, primary_ok) = verify_registry(&registry);
+            for line in report {
+                println!("{}", line);
+            }
+            if !primary_ok {
+                eprintln!("Error: The registry is corrupt; the daemon will restore the newest good backup when it starts");
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `registry list` lines, sorted by chat ID
+fn registry_list(registry: &SessionRegistry) -> Vec<String> {
+    let mut entries: Vec<(&String, &SessionData)> = registry.all().iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let time = |at: Option<DateTime<Utc>>| match at {
+        Some(at) => at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        None => "-".to_string(),
+    };
+    entries
+        .into_iter()
+        .map(|(key, data)| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                key,
+                data.session_name,
+                data.tier.as_deref().unwrap_or("-"),
+                data.session_type,
+                time(data.last_message_time),
+                time(data.last_outbound_time)
+            )
+        })
+        .collect()
+}
+
+fn home_dir() -> Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| Error::Config("can't tell where the home directory is".to_string()))
+}
+
+/// A pruned entry: session, chat ID, and when it was last messaged
+fn prune_line(data: &SessionData) -> String {
+    let last = match data.last_message_time {
+        Some(at) => at.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string(),
+        None => "never messaged".to_string(),
+    };
+    format!("{} ({}, {})", data.session_name, data.chat_id, last)
+}
+
+/// The entry for a chat ID (or background key), else for a session name
+fn registry_entry<'a>(registry: &'a SessionRegistry, target: &str) -> Result<&'a SessionData> {
+    registry
+        .get(target)
+        .or_else(|| registry.get_by_session_name(target))
+        .ok_or_else(|| Error::SessionNotFound(target.to_string()))
+}
+
+/// Apply `registry set`, returning a line per field that changed
+fn registry_set(
+    config: &Config,
+    registry: &mut SessionRegistry,
+    chat_id: &str,
+    tier: Option<String>,
+    contact_name: Option<String>,
+    display_name: Option<String>,
+) -> Result<Vec<String>> {
+    if registry.get(chat_id).is_none() {
+        return Err(Error::SessionNotFound(chat_id.to_string()));
+    }
+    let mut changes = Vec::new();
+    if let Some(tier) = tier {
+        let tier = tier.trim().to_lowercase();
+        let tiers = config.tier_names();
+        if !tiers.contains(&tier.as_str()) && tier != QUARANTINE_TIER {
+            return Err(Error::Config(format!(
+                "unknown tier '{}' (expected one of {}, {})",
+                tier,
+                tiers.join(", "),
+                QUARANTINE_TIER
+            )));
+        }
+        if registry.update_tier(chat_id, &tier)? {
+            changes.push(format!("{}: tier {}", chat_id, tier));
+        }
+    }
+    if let Some(name) = contact_name {
+        if registry.update_contact_name(chat_id, &name)? {
+            changes.push(format!("{}: contact name {}", chat_id, name));
+        }
+    }
+    if let Some(name) = display_name {
+        let name = (!name.is_empty()).then_some(name);
+        if registry.update_display_name(chat_id, name.clone())? {
+            changes.push(format!("{}: display name {}", chat_id, name.as_deref().unwrap_or("cleared")));
+        }
+    }
+    if changes.is_empty() {
+        changes.push(format!("{}: unchanged", chat_id));
+    }
+    Ok(changes)
+}
+
+/// Ask a yes/no question on the terminal; anything but yes is no
+fn confirm(question: &str) -> bool {
+    use std::io::Write;
+    print!("{} [y/N] ", question);
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// A line per registry file, and whether the registry itself (not a backup) is usable
+fn verify_registry(registry: &SessionRegistry) -> (Vec<String>, bool) {
+    let mut primary_ok = true;
+    let report = registry
+        .files()
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let status = if !path.exists() {
+                "missing".to_string()
+            } else {
+                match read_registry(path) {
+                    Ok(sessions) => format!("OK ({} sessions)", sessions.len()),
+                    Err(e) => {
+                        primary_ok &= i > 0;
+                        format!("invalid: {}", e)
+                    }
+                }
+            };
+            format!("{:<48} {}", path.display(), status)
+        })
+        .collect();
+    (report, primary_ok)
+}
+
+/// One line of `quarantine` output
+fn format_quarantine_entry(entry: &QuarantineEntry) -> String {
+    let mut line = format!(
+        "{:>8}  {}  {}",
+        entry.rowid,
+        entry.timestamp.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+        entry.sender
+    );
+    if entry.chat_id != entry.sender {
+        line.push_str(&format!(" in {}", entry.chat_id));
+    }
+    if entry.is_spam {
+        line.push_str(" [spam]");
+    }
+    if entry.blocked {
+        line.push_str(" [blocked]");
+    }
+    line.push_str(&format!(": {}", entry.preview));
+    line
+}
+
+/// Inject a quarantined message into its sender's session and drop it from quarantine
+///
+/// Returns the session name, or None if the ROWID isn't quarantined.
+fn bless_quarantined(config: &Config, session_mgr: &SessionManager, rowid: i64) -> Result<Option<String>> {
+    let quarantine = Quarantine::new(config);
+    let entry = match quarantine.get(rowid)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    // Prefer the full message; the log only keeps a preview
+    let messages = MessagesReader::new(config);
+    let msg = match messages.get_message_by_rowid(rowid) {
+        Ok(msg) => msg,
+        Err(e) => {
+            warn!("Couldn't read message {} from chat.db, using saved preview: {}", rowid, e);
+            None
+        }
+    };
+
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
+    let mut contacts = ContactsManager::new(config);
+    let contact_name = contacts
+        .lookup_identifier(&entry.sender)
+        .ok()
+        .flatten()
+        .map(|c| c.name)
+        .unwrap_or_else(|| entry.sender.clone());
+    let session_name = match msg.as_ref().filter(|m| m.is_group) {
+        Some(m) => registry.claim_session_name(
+            &SessionManager::session_name_for_group(&entry.chat_id, m.group_name.as_deref()),
+            &entry.chat_id,
+        ),
+        None => individual_session_name(&registry, &mut contacts, &entry.chat_id, &contact_name),
+    };
+
+    let transcript_dir = session_mgr.transcript_dir(&session_name);
+    if !session_mgr.session_exists(&session_name) {
+        ensure_transcript_dir(&transcript_dir)?;
+        let contact = session_contact(&mut contacts, &entry.chat_id);
+        let info = SessionInfo::new(&entry.chat_id, QUARANTINE_TIER, &contact_name);
+        session_mgr.create_session(&session_name, &transcript_dir, &info, contact.as_ref())?;
+        registry.register(
+            &entry.chat_id,
+            &session_name,
+            transcript_dir.to_str().unwrap_or(""),
+            if entry.chat_id != entry.sender { "group" } else { "individual" },
+            Some(contact_name.clone()),
+            msg.as_ref().and_then(|m| m.group_name.clone()),
+            Some(QUARANTINE_TIER.to_string()),
+            None,
+        )?;
+    }
+
+    let prompt = match &msg {
+        Some(m) => {
+            let staged = AttachmentHandler::new(config)
+                .stage(&transcript_dir, rowid, &m.attachments)
+                .unwrap_or_else(|e| {
+                    warn!("Failed to stage attachments for message {}: {}", rowid, e);
+                    Vec::new()
+                });
+            compose_prompt(&m.body_text(), &staged)
+        }
+        None => entry.preview.clone(),
+    };
+    let options = SmsOptions {
+        service: msg.as_ref().map(|m| m.service).unwrap_or_default(),
+        subject: msg.as_ref().and_then(|m| m.subject.as_deref()),
+        group: msg.as_ref().filter(|m| m.is_group).map(|m| GroupContext { name: m.group_name.as_deref(), participants: None }),
+        ..Default::default()
+    };
+    let wrapped = wrap_sms(&prompt, &contact_name, QUARANTINE_TIER, &entry.chat_id, &options);
+    session_mgr.inject_message(&session_name, &wrapped, &transcript_dir, rowid)?;
+    quarantine.remove(rowid)?;
+    Ok(Some(session_name))
+}
+
+fn cmd_install(config: &Config) -> Result<()> {
+    let plist_dst = dirs::home_dir()
         .unwrap()
         .join("Library/LaunchAgents/com.jsmith.claude-assistant-rs.plist");
 
@@ -765,34 +2245,43 @@ fn cmd_uninstall(_config: &Config) -> Result<()> {
 // Daemon Loop
 // ============================================================================
 
-fn cmd_run(config: &Config) -> Result<()> {
+fn cmd_run(config: &Config, no_backfill: bool) -> Result<()> {
     info!("Claude Assistant daemon starting (Rust)");
 
-    // Initialize components
-    let session_mgr = SessionManager::new(config);
-    let mut registry = SessionRegistry::new(config);
-    registry.load()?;
-    info!("Loaded {} sessions from registry", registry.len());
-
     let mut contacts = ContactsManager::new(config);
     contacts.load()?;
     info!("Loaded contacts");
+    if let Some(saved_at) = contacts.snapshot_in_use() {
+        notify_admin(
+            config,
+            &SmsNotifier::new(config),
+            &mut contacts,
+            &format!(
+                "Claude Assistant: the contacts source failed at startup, so contacts saved {} are in use. Check the contacts CLI's permissions.",
+                saved_at.with_timezone(&chrono::Local).format("%b %-d %-I:%M %p")
+            ),
+        );
+    }
 
-    let messages = MessagesReader::new(config);
-    let mut reminders = ReminderManager::new();
-
-    // Load last processed ROWID
-    let state_file = config.state_dir.join("last_rowid.txt");
-    let mut last_rowid: i64 = if state_file.exists() {
-        fs::read_to_string(&state_file)?
-            .trim()
-            .parse()
-            .unwrap_or(0)
-    } else {
-        // Start from current max
-        messages.get_max_rowid()?
-    };
-    info!("Starting from ROWID {}", last_rowid);
+    let mut daemon = Daemon::new(config, Box::new(contacts), no_backfill)?;
+    let adopted = adopt_orphans(config, &daemon.session_mgr, &mut daemon.registry, daemon.contacts.as_mut());
+    if !adopted.is_empty() {
+        info!("Adopted {} running sessions missing from the registry: {}", adopted.len(), adopted.join(", "));
+    }
+    let legacy = legacy_sessions(config, &default_server(config), &daemon.registry);
+    if !legacy.is_empty() {
+        warn!(
+            "Sessions still on the default tmux server: {}. Run `claude-assistant-rs adopt-sessions` to move them to the '{}' socket, or `adopt-sessions --kill` to drop them",
+            legacy.join(", "),
+            config.tmux_socket_name
+        );
+    }
+    let mut reminders = ReminderManager::with_config(config);
+    if let Err(e) = reminders.load() {
+        warn!("Failed to load reminder fire times, counting from now: {}", e);
+    }
+    let mut reminders_synced = None;
+    sync_reminders(config, &mut reminders, daemon.contacts.as_mut(), &daemon.registry, &mut reminders_synced);
 
     // Health check interval
     let mut last_health_check = std::time::Instant::now();
@@ -802,278 +2291,5181 @@ fn cmd_run(config: &Config) -> Result<()> {
     let mut last_reminder_check = std::time::Instant::now();
     let reminder_check_interval = Duration::from_secs(60); // 1 minute
 
-    // Main loop
-    loop {
-        // Poll for new messages
-        match messages.poll(last_rowid) {
-            Ok(new_messages) => {
-                for msg in new_messages {
-                    // Skip messages from self
-                    if msg.is_from_me {
-                        last_rowid = last_rowid.max(msg.rowid);
-                        continue;
-                    }
+    // Unreplied-message nudges, once per message
+    let mut last_unreplied_check = std::time::Instant::now();
+    let unreplied_check_interval = Duration::from_secs(config.unreplied_check_interval_secs);
 
-                    // Get chat_id
-                    let chat_id = &msg.chat_id;
-
-                    // Look up sender
-                    let sender_info: Option<(String, String)> = if msg.is_group {
-                        // For groups, check if any member is blessed
-                        if let Ok(Some(contact)) = contacts.lookup_phone(&msg.sender) {
-                            if ContactsManager::is_blessed_tier(&contact.tier) {
-                                Some((contact.name.clone(), contact.tier.clone()))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        // Individual: sender is chat_id
-                        if let Ok(Some(contact)) = contacts.lookup_phone(chat_id) {
-                            if ContactsManager::is_blessed_tier(&contact.tier) {
-                                Some((contact.name.clone(), contact.tier.clone()))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    };
-
-                    // Skip if not blessed
-                    let (contact_name, tier) = match sender_info {
-                        Some((name, t)) => (name, t),
-                        None => {
-                            debug!("Ignoring message from unknown/unblessed: {}", chat_id);
-                            last_rowid = last_rowid.max(msg.rowid);
-                            continue;
-                        }
-                    };
-
-                    info!(
-                        "New message from {} ({}) in chat {}: {}",
-                        contact_name,
-                        tier,
-                        chat_id,
-                        msg.text.chars().take(50).collect::<String>()
-                    );
+    // Change detection: poll once at startup, then whenever the WAL changes
+    let mut db_changed = true;
+    let mut last_poll = std::time::Instant::now();
+    let fallback_poll_interval = Duration::from_secs(config.fallback_poll_secs);
 
-                    // Get or create session
-                    let session_name = if msg.is_group {
-                        SessionManager::session_name_for_group(chat_id, msg.group_name.as_deref())
-                    } else {
-                        SessionManager::session_name_for_contact(&contact_name)
-                    };
-
-                    // Ensure session exists
-                    if !session_mgr.session_exists(&session_name) {
-                        info!("Creating session: {}", session_name);
-                        let transcript_dir = config.transcripts_dir.join(&session_name);
-                        ensure_transcript_dir(&transcript_dir)?;
-
-                        if let Err(e) = session_mgr.create_session(&session_name, &transcript_dir, &tier) {
-                            error!("Failed to create session {}: {}", session_name, e);
-                            last_rowid = last_rowid.max(msg.rowid);
-                            continue;
-                        }
+    // Nightly housekeeping, at the consolidation hour
+    let mut last_housekeeping: Option<chrono::NaiveDate> = None;
 
-                        // Register in registry
-                        let _ = registry.register(
-                            chat_id,
-                            &session_name,
-                            transcript_dir.to_str().unwrap_or(""),
-                            if msg.is_group { "group" } else { "individual" },
-                            Some(contact_name.clone()),
-                            msg.group_name.clone(),
-                            Some(tier.clone()),
-                            None, // participants
-                        );
-                    }
+    // Last message times and counters, saved with the next registry change or at least this often,
+    // and changes made outside the daemon picked up as often
+    let mut last_registry_flush = std::time::Instant::now();
+    let registry_flush_interval = Duration::from_secs(5);
 
-                    // Wrap and inject message
-                    let wrapped = wrap_sms(&msg.text, &contact_name, &tier, chat_id, None);
-                    if let Err(e) = session_mgr.inject_text(&session_name, &wrapped) {
-                        error!("Failed to inject message into {}: {}", session_name, e);
-                    } else {
-                        // Update last message time
-                        let _ = registry.update_last_message(chat_id);
-                    }
+    // Heartbeat, so a restart after a quiet spell isn't taken for one after downtime
+    let mut last_heartbeat = std::time::Instant::now();
+    let heartbeat_interval = Duration::from_secs(60);
+    if let Err(e) = daemon.cursors.beat() {
+        warn!("Failed to write the heartbeat: {}", e);
+    }
 
-                    last_rowid = last_rowid.max(msg.rowid);
-                }
+    let shutdown = shutdown_flag();
 
-                // Save last ROWID
-                if let Err(e) = fs::write(&state_file, last_rowid.to_string()) {
-                    warn!("Failed to save last ROWID: {}", e);
-                }
-            }
-            Err(e) => {
-                error!("Failed to poll messages: {}", e);
+    // Main loop
+    loop {
+        if shutdown.load(Ordering::SeqCst) {
+            info!("Shutting down, finishing queued session work first");
+            daemon.shutdown();
+            flush_reminders(&mut reminders);
+            info!("Daemon stopped");
+            return Ok(());
+        }
+        daemon.apply_outcomes();
+
+        // Query only when the watcher saw a change, with a periodic safety-net poll
+        if db_changed || last_poll.elapsed() >= fallback_poll_interval {
+            last_poll = std::time::Instant::now();
+            if let Err(e) = daemon.poll() {
+                // Nothing saves what's held back on the way out but this
+                error!("Poll failed, shutting down: {}", e);
+                daemon.shutdown();
+                flush_reminders(&mut reminders);
+                return Err(e);
             }
         }
+        if !daemon.queue.is_empty() {
+            daemon.flush_queue(std::time::Instant::now());
+        }
 
-        // Health checks
+        // Health checks, run off the loop and acted on as they come in
         if last_health_check.elapsed() >= health_check_interval {
-            debug!("Running health checks...");
-
-            for (chat_id, data) in registry.all().clone() {
-                let session_name = &data.session_name;
-
-                match session_mgr.check_health(session_name) {
-                    HealthStatus::Unhealthy(reason) => {
-                        warn!("Session {} unhealthy: {:?}", session_name, reason);
+            daemon.reap_idle(Utc::now());
+            daemon.start_sweep(std::time::Instant::now());
+            daemon.workers.retire_idle(std::time::Instant::now());
+            last_health_check = std::time::Instant::now();
+        }
+        daemon.finish_sweep(false);
 
-                        // Restart
-                        let _ = session_mgr.kill_session(session_name);
-                        std::thread::sleep(Duration::from_secs(1));
+        let local = chrono::Local::now();
+        if local.hour() == config.consolidation_hour && last_housekeeping != Some(local.date_naive()) {
+            last_housekeeping = Some(local.date_naive());
+            daemon.prune_registry();
+        }
 
-                        let transcript_dir = PathBuf::from(&data.transcript_dir);
-                        let tier = data.tier.as_deref().unwrap_or("favorite");
+        // Catch sessions whose contact changed tier in a contacts refresh
+        daemon.apply_tier_changes();
 
-                        if let Err(e) = session_mgr.create_session(session_name, &transcript_dir, tier) {
-                            error!("Failed to restart session {}: {}", session_name, e);
-                        } else {
-                            info!("Restarted unhealthy session: {}", session_name);
-                        }
-                    }
-                    HealthStatus::Healthy => {
-                        debug!("Session {} healthy", session_name);
-                    }
-                }
+        if last_heartbeat.elapsed() >= heartbeat_interval {
+            if let Err(e) = daemon.cursors.beat() {
+                warn!("Failed to write the heartbeat: {}", e);
             }
+            last_heartbeat = std::time::Instant::now();
+        }
 
-            last_health_check = std::time::Instant::now();
+        // A CLI command is waiting to read the registry
+        if let Err(e) = daemon.registry.flush_if_requested() {
+            warn!("Failed to save the registry for a command: {}", e);
+        }
+        if last_registry_flush.elapsed() >= registry_flush_interval {
+            // Pick up hand edits, so what's read in between isn't stale
+            if let Err(e) = daemon.registry.refresh() {
+                warn!("Failed to reload the registry: {}", e);
+            }
+            if let Err(e) = daemon.registry.flush() {
+                warn!("Failed to save the registry: {}", e);
+            }
+            last_registry_flush = std::time::Instant::now();
         }
 
         // Reminder checks
         if last_reminder_check.elapsed() >= reminder_check_interval {
+            // Pick up edited notes after a contacts refresh
+            sync_reminders(config, &mut reminders, daemon.contacts.as_mut(), &daemon.registry, &mut reminders_synced);
             let now = Utc::now();
-            for (chat_id, prompt) in reminders.check_due(now) {
+            for (chat_id, prompt) in due_reminders(&mut reminders, daemon.contacts.as_mut(), now) {
                 info!("Reminder due for {}: {}", chat_id, prompt);
 
-                if let Some(data) = registry.get(&chat_id) {
-                    if let Err(e) = session_mgr.inject_text(&data.session_name, &prompt) {
-                        error!("Failed to inject reminder into {}: {}", data.session_name, e);
-                    }
+                if let Some(session_name) = daemon.registry.get(&chat_id).map(|d| d.session_name.clone()) {
+                    daemon.inject_later(&session_name, prompt, "inject reminder into");
                 }
             }
+            flush_reminders(&mut reminders);
 
             last_reminder_check = std::time::Instant::now();
         }
 
-        // Sleep before next poll
-        std::thread::sleep(Duration::from_secs(1));
+        // Unreplied checks
+        if config.unreplied_nudge_hours > 0.0 && last_unreplied_check.elapsed() >= unreplied_check_interval {
+            let now = Utc::now();
+            let threshold = chrono::Duration::seconds((config.unreplied_nudge_hours * 3600.0) as i64);
+            daemon.nudge_unreplied(now - threshold, now);
+            last_unreplied_check = std::time::Instant::now();
+        }
+
+        // Wait for chat.db to change (bounded so health checks and reminders keep running)
+        db_changed = daemon.messages.wait_for_changes(Duration::from_secs(1));
+    }
+}
+
+/// Set once SIGTERM or SIGINT arrives, so the loop can finish up and exit
+fn shutdown_flag() -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let set = Arc::clone(&flag);
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            warn!("Can't watch for shutdown signals, queued work may be lost on stop: {}", e);
+            return flag;
+        }
+    };
+    std::thread::spawn(move || {
+        runtime.block_on(async {
+            use tokio::signal::unix::{signal, SignalKind};
+            let (mut term, mut int) = match (signal(SignalKind::terminate()), signal(SignalKind::interrupt())) {
+                (Ok(term), Ok(int)) => (term, int),
+                (Err(e), _) | (_, Err(e)) => {
+                    warn!("Can't watch for shutdown signals, queued work may be lost on stop: {}", e);
+                    return;
+                }
+            };
+            tokio::select! {
+                _ = term.recv() => {}
+                _ = int.recv() => {}
+            }
+            set.store(true, Ordering::SeqCst);
+        })
+    });
+    flag
+}
+
+/// A message that reached its session
+struct Delivered {
+    /// Registry key whose last message time to update
+    key: String,
+    /// The message and the text injected for it, kept for unsends
+    recent: Option<(Message, String)>,
+}
+
+/// What a health sweep's restart of a session is reported as
+const RESTART_JOB: &str = "restart session";
+
+/// How a job on a session's worker went, reported back to the daemon loop
+struct Outcome {
+    session: String,
+    /// What was being done, for the log if it failed, e.g. "inject reminder into"
+    what: &'static str,
+    result: Result<Option<Delivered>>,
+}
+
+/// Everything the daemon loop carries from one poll to the next
+struct Daemon<'a> {
+    config: &'a Config,
+    session_mgr: Arc<SessionManager>,
+    /// Start, restart, and inject into each session in order, without holding up the others
+    workers: SessionWorkers,
+    outcome_sender: Sender<Outcome>,
+    outcomes: Receiver<Outcome>,
+    registry: SessionRegistry,
+    contacts: Box<dyn ContactSource>,
+    messages: MessagesReader,
+    attachment_handler: AttachmentHandler,
+    quarantine: Quarantine,
+    cursors: ChatCursors,
+    recent: RecentMessages,
+    /// Only edits/unsends made after this are reported
+    last_modified_seen: DateTime<Utc>,
+    edit_window: chrono::Duration,
+    /// Contacts generation the sessions' tiers were last checked against
+    tiers_synced: Option<u64>,
+    /// Prompts waiting for busy sessions
+    queue: InjectionQueue,
+    /// Restarts in a row of sessions failing their health checks
+    restarts: RestartTracker,
+    /// Busy panes that haven't changed across sweeps
+    stuck: StuckDetector,
+    /// API errors seen over recent sweeps
+    api_errors: ErrorTracker,
+    /// Sessions sent `/compact` for running out of context, and when
+    compacting: HashMap<String, std::time::Instant>,
+    /// Sessions at their usage limit, holding their messages
+    paused: HashMap<String, Paused>,
+    /// Recent health changes, saved after each sweep
+    health_history: HealthHistory,
+    /// The health sweep under way, if any
+    sweep: Option<Sweep>,
+    /// Texts chats: alerts, and notices about their sessions
+    notifier: RememberingNotifier,
+    /// Registry keys and reasons of sessions the health sweep is restarting, by session name
+    restarting: HashMap<String, (String, String)>,
+    /// When each chat was last asked to resend a message lost to a restart
+    recovery_notices: HashMap<String, DateTime<Utc>>,
+    /// Registry keys of sessions lost with tmux's server and not yet recreated
+    recreating: HashSet<String>,
+    /// GUID of the unreplied message each chat was last nudged about
+    nudged: HashMap<String, String>,
+}
+
+/// A health sweep whose checks are still coming in
+struct Sweep {
+    /// When it started, for restart backoff and the sweep-by-sweep trackers
+    now: std::time::Instant,
+    results: Receiver<(String, Probe)>,
+    /// Registry keys of the sessions not checked yet, by session name
+    waiting: HashMap<String, String>,
+    /// When a check last came in
+    last_heard: std::time::Instant,
+    /// Sessions found logged out, for a single alert at the end
+    logged_out: Vec<String>,
+    /// Registry keys of sessions gone with tmux's server, to recreate together at the end
+    lost: Vec<String>,
+}
+
+/// A session at its usage limit
+struct Paused {
+    /// When the limit resets and its messages go in again
+    until: DateTime<Utc>,
+    /// The banner that paused it, so it doesn't pause it again once it's over
+    banner: String,
+}
+
+impl<'a> Daemon<'a> {
+    /// Load the registry and per-chat progress, choosing where to start reading
+    fn new(config: &'a Config, contacts: Box<dyn ContactSource>, no_backfill: bool) -> Result<Self> {
+        let mut registry = SessionRegistry::new(config);
+        registry.load()?;
+        info!("Loaded {} sessions from registry", registry.len());
+
+        let messages = MessagesReader::new(config);
+
+        // Load per-chat progress (migrates the old last_rowid.txt)
+        let mut cursors = ChatCursors::new(config);
+        let backfill = Duration::from_secs(config.startup_backfill_minutes * 60);
+        let stale = config.startup_backfill_minutes > 0 && cursors.is_stale(backfill);
+        let loaded = cursors.load()?;
+        if loaded && cursors.reconcile(messages.get_max_rowid()?) {
+            cursors.save()?;
+        }
+        if !loaded || stale {
+            let start = if no_backfill || config.startup_backfill_minutes == 0 {
+                messages.get_max_rowid()?
+            } else {
+                messages.rowid_before(Utc::now() - chrono::Duration::minutes(config.startup_backfill_minutes as i64))?
+            };
+            // Never go back past progress we already have
+            if !loaded || start > cursors.floor() {
+                cursors.set_floor(start);
+            }
+            cursors.save()?;
+            let replay = messages.count_since(cursors.floor())?;
+            if replay > 0 {
+                info!(
+                    "{} saved state: replaying {} message(s) (backfill window {} min)",
+                    if loaded { "Stale" } else { "No" },
+                    replay,
+                    config.startup_backfill_minutes
+                );
+            }
+        }
+        info!("Starting from ROWID {}", cursors.floor());
+
+        let mut health_history = HealthHistory::new(config);
+        if let Err(e) = health_history.load() {
+            warn!("Failed to load health history, starting a new one: {}", e);
+        }
+
+        let (outcome_sender, outcomes) = mpsc::channel();
+        Ok(Self {
+            config,
+            session_mgr: Arc::new(SessionManager::new(config)),
+            workers: SessionWorkers::new(config),
+            outcome_sender,
+            outcomes,
+            registry,
+            contacts,
+            messages,
+            attachment_handler: AttachmentHandler::new(config),
+            quarantine: Quarantine::new(config),
+            cursors,
+            recent: RecentMessages::new(200),
+            last_modified_seen: Utc::now(),
+            edit_window: chrono::Duration::minutes(config.edit_window_minutes as i64),
+            tiers_synced: None,
+            queue: InjectionQueue::new(config),
+            restarts: RestartTracker::new(config.restart_max_attempts),
+            stuck: StuckDetector::new(config.stuck_sweeps),
+            compacting: HashMap::new(),
+            paused: HashMap::new(),
+            health_history,
+            sweep: None,
+            notifier: RememberingNotifier::new(Arc::new(SmsNotifier::new(config)), 200),
+            restarting: HashMap::new(),
+            recovery_notices: HashMap::new(),
+            recreating: HashSet::new(),
+            nudged: HashMap::new(),
+            api_errors: ErrorTracker::new(
+                config.api_error_sweeps,
+                config.api_error_window,
+                Duration::from_secs(config.health_check_interval_secs),
+            ),
+        })
+    }
+
+    /// Handle new messages, then edits and unsends of earlier ones
+    fn poll(&mut self) -> Result<()> {
+        match self.messages.poll(self.cursors.floor()) {
+            Ok(new_messages) => {
+                let batch_max = new_messages.iter().map(|m| m.rowid).max();
+
+                for (batch_chat_id, chat_messages) in group_by_chat(new_messages) {
+                    for msg in chat_messages {
+                        // Already handled before a restart, or replayed under a new ROWID by a resync
+                        if self.cursors.is_seen(&batch_chat_id, msg.rowid) || self.cursors.has_guid(&msg.guid) {
+                            continue;
+                        }
+                        self.cursors.advance(&batch_chat_id, msg.rowid);
+                        self.cursors.record_guid(&msg.guid);
+
+                        self.process_message(&msg)?;
+                    }
+
+                    // Persist after each chat so a crash can't replay or skip other chats
+                    if let Err(e) = self.cursors.save() {
+                        warn!("Failed to save chat cursors: {}", e);
+                    }
+                }
+
+                if let Some(max) = batch_max {
+                    self.cursors.set_floor(max);
+                    if let Err(e) = self.cursors.save() {
+                        warn!("Failed to save chat cursors: {}", e);
+                    }
+                } else if let Ok(max) = self.messages.get_max_rowid() {
+                    // Nothing new: make sure that's not because ROWIDs went backwards
+                    if self.cursors.reconcile(max) {
+                        if let Err(e) = self.cursors.save() {
+                            warn!("Failed to save chat cursors: {}", e);
+                        }
+                    }
+                }
+            }
+            Err(e) if e.is_transient() => {
+                warn!("chat.db busy, will retry next cycle: {}", e);
+            }
+            Err(e) => {
+                error!("Failed to poll messages: {}", e);
+            }
+        }
+
+        // Edits and unsends rewrite already-processed rows, so look for them separately
+        self.check_modified();
+        self.apply_outcomes();
+        Ok(())
+    }
+
+    /// Run `job` on a session's worker, behind anything already submitted for it
+    fn on_session<F>(&mut self, session_name: &str, what: &'static str, job: F)
+    where
+        F: FnOnce(&SessionManager) -> Result<Option<Delivered>> + Send + 'static,
+    {
+        let session_mgr = Arc::clone(&self.session_mgr);
+        let outcomes = self.outcome_sender.clone();
+        let session = session_name.to_string();
+        let submitted = self.workers.submit(
+            session_name,
+            Box::new(move || {
+                let result = job(&session_mgr);
+                let _ = outcomes.send(Outcome { session, what, result });
+            }),
+        );
+        // Reported like a failed job, so whatever waits on it is cleared up
+        if let Err(e) = submitted {
+            let _ = self.outcome_sender.send(Outcome { session: session_name.to_string(), what, result: Err(e) });
+        }
+    }
+
+    /// Type text into a session once its worker gets to it
+    fn inject_later(&mut self, session_name: &str, text: String, what: &'static str) {
+        let session = session_name.to_string();
+        self.on_session(session_name, what, move |session_mgr| session_mgr.inject_text(&session, &text).map(|_| None));
+    }
+
+    /// Nudge sessions about messages left unreplied since before `older_than`, once per message
+    ///
+    /// Only a chat's latest nudge is remembered, and it's forgotten once the
+    /// chat has nothing waiting or its session is archived.
+    fn nudge_unreplied(&mut self, older_than: DateTime<Utc>, now: DateTime<Utc>) {
+        let chats: HashMap<String, String> = self
+            .registry
+            .all()
+            .values()
+            .filter(|d| d.session_type != "background" && !d.archived)
+            .map(|d| (d.chat_id.clone(), d.session_name.clone()))
+            .collect();
+        self.nudged.retain(|chat_id, _| chats.contains_key(chat_id));
+        for (chat_id, session_name) in chats {
+            let msg = match self.messages.get_unreplied(&chat_id, older_than) {
+                Ok(Some(msg)) => msg,
+                Ok(None) => {
+                    self.nudged.remove(&chat_id);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to check unreplied messages for {}: {}", chat_id, e);
+                    continue;
+                }
+            };
+            if self.nudged.get(&chat_id) == Some(&msg.guid) {
+                continue;
+            }
+            self.nudged.insert(chat_id.clone(), msg.guid.clone());
+            let name = participant_names(self.contacts.as_mut(), std::slice::from_ref(&msg.sender)).remove(0);
+            let nudge = unreplied_nudge(&name, &msg, now);
+            info!("Unreplied message in {}: {}", chat_id, nudge);
+            self.inject_later(&session_name, nudge, "inject nudge into");
+        }
+    }
+
+    /// Record what the session workers have finished since last time
+    fn apply_outcomes(&mut self) {
+        while let Ok(outcome) = self.outcomes.try_recv() {
+            if outcome.what == RESTART_JOB {
+                let restarted = self.restarting.remove(&outcome.session);
+                if let (Some((key, reason)), Ok(_)) = (restarted, &outcome.result) {
+                    self.notify_recovered(&key, &reason, Utc::now());
+                }
+            }
+            match outcome.result {
+                Ok(Some(delivered)) => {
+                    self.registry.count(&delivered.key, SessionEvent::Injection);
+                    let _ = self.registry.update_last_message(&delivered.key);
+                    if let Some((msg, text)) = delivered.recent {
+                        self.recent.record(&msg, &text);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to {} {}: {}", outcome.what, outcome.session, e);
+                    self.alert_unconfirmed(&e);
+                }
+            }
+        }
+    }
+
+    /// Drop registry entries for chats long gone quiet whose sessions are gone
+    fn prune_registry(&mut self) {
+        if self.config.registry_prune_days == 0 {
+            return;
+        }
+        let older_than = Duration::from_secs(self.config.registry_prune_days * 24 * 3600);
+        match self.registry.prune(&self.session_mgr, older_than, false) {
+            Ok(pruned) if pruned.is_empty() => {}
+            Ok(pruned) => {
+                let names: Vec<&str> = pruned.iter().map(|d| d.session_name.as_str()).collect();
+                info!("Pruned {} stale registry entries: {}", pruned.len(), names.join(", "));
+            }
+            Err(e) => warn!("Failed to prune the registry: {}", e),
+        }
+    }
+
+    /// Finish all queued session work, then save progress
+    ///
+    /// Prompts still waiting for busy sessions go in anyway: their messages
+    /// are already marked handled, so they'd otherwise be lost.
+    fn shutdown(&mut self) {
+        let now = std::time::Instant::now();
+        for session_name in self.queue.sessions() {
+            while let Some(prompt) = self.queue.take_ready(&session_name, false, now) {
+                self.inject_queued(&session_name, prompt);
+            }
+        }
+        self.workers.drain();
+        self.apply_outcomes();
+        if let Err(e) = self.registry.flush() {
+            warn!("Failed to save the registry: {}", e);
+        }
+        if let Err(e) = self.cursors.save() {
+            warn!("Failed to save chat cursors: {}", e);
+        }
+    }
+
+    /// Route one new message to its session, creating the session if needed
+    fn process_message(&mut self, msg: &Message) -> Result<()> {
+        let config = self.config;
+
+        // Renames and membership changes, from anyone including us
+        if let MessageKind::GroupEvent { event } = &msg.kind {
+            handle_group_event(&self.session_mgr, &mut self.registry, &self.messages, self.contacts.as_mut(), msg, event);
+            return Ok(());
+        }
+
+        // Messages from self never trigger Claude, but may be noted in its session
+        if msg.is_from_me {
+            self.registry.update_last_outbound(&msg.chat_id, msg.timestamp);
+            if config.log_outbound || config.inject_outbound {
+                track_outbound(config, &self.session_mgr, &self.registry, &self.notifier, msg);
+            }
+            return Ok(());
+        }
+
+        // Get chat_id
+        let chat_id = &msg.chat_id;
+
+        // Delivery notices and 2FA codes: no contact to look up, nobody to answer
+        if is_short_code(config, msg) {
+            debug!("Skipping message {} from short code {}", msg.rowid, msg.sender);
+            return Ok(());
+        }
+
+        // Blocked senders are dropped; in a group only their own messages are
+        if drop_blocked(self.contacts.as_mut(), &self.quarantine, msg) {
+            return Ok(());
+        }
+
+        // Skip if not blessed
+        let (contact_name, tier) = match resolve_sender(self.config, self.contacts.as_mut(), &self.registry, msg) {
+            Some((name, t)) => (name, t),
+            None => {
+                // Keep a record of real messages; reactions and unsends aren't worth one
+                if matches!(msg.kind, MessageKind::Text | MessageKind::NonText { .. }) {
+                    debug!("Quarantining message {} from unblessed sender in {}", msg.rowid, chat_id);
+                    if let Err(e) = self.quarantine.append(msg) {
+                        warn!("Failed to quarantine message {}: {}", msg.rowid, e);
+                    }
+                }
+                return Ok(());
+            }
+        };
+
+        if needs_mention(config, msg) {
+            debug!("Skipping group message {} in chat {}: not mentioned", msg.rowid, chat_id);
+            return Ok(());
+        }
+
+        // "!stop" from the top tier interrupts the chat's session instead of reaching it
+        if let Some(follow_up) = stop_command(config, &msg.kind, &msg.body_text()) {
+            if is_top_tier(config, &tier) {
+                self.interrupt_chat(chat_id, &contact_name, follow_up);
+                return Ok(());
+            }
+            warn!("Ignoring stop command from {} ({}) in chat {}: not the top tier", contact_name, tier, chat_id);
+        }
+
+        // Tapbacks: drop, or replace the raw "Loved ..." text with a short note
+        let text = match &msg.kind {
+            MessageKind::Tapback { kind, removed, .. } => {
+                if !config.inject_tapbacks {
+                    debug!("Skipping tapback from {} in chat {}", contact_name, chat_id);
+                    return Ok(());
+                }
+                describe_tapback(&contact_name, *kind, *removed)
+            }
+            MessageKind::Retracted => match retraction_note(&self.recent, msg, &contact_name) {
+                Some(note) => note,
+                None => {
+                    // Unsent before we ever injected it: nothing to take back
+                    debug!("Skipping retracted message {} in chat {}", msg.rowid, chat_id);
+                    return Ok(());
+                }
+            },
+            MessageKind::NonText { kind } => {
+                if !config.summarize_non_text {
+                    debug!("Skipping {} from {} in chat {}", kind.describe(), contact_name, chat_id);
+                    return Ok(());
+                }
+                format!("{} sent {}", contact_name, kind.describe())
+            }
+            MessageKind::Text => msg.body_text(),
+            // Handled before the sender checks
+            MessageKind::GroupEvent { .. } => return Ok(()),
+        };
+
+        // "bg: ..." goes to the chat's background session so it doesn't hold up the conversation
+        let (text, background) = match background_task(config, &msg.kind, &text) {
+            Some(task) => (task.to_string(), true),
+            None => (text, false),
+        };
+
+        info!(
+            "New message from {} ({}) in chat {}: {}",
+            contact_name,
+            tier,
+            chat_id,
+            text.chars().take(50).collect::<String>()
+        );
+
+        // Get or create session
+        let session_name = if msg.is_group {
+            // Keep the original session even if the group has since been renamed
+            match self.registry.get(chat_id) {
+                Some(data) => data.session_name.clone(),
+                None => self
+                    .registry
+                    .claim_session_name(&SessionManager::session_name_for_group(chat_id, msg.group_name.as_deref()), chat_id),
+            }
+        } else {
+            individual_session_name(&self.registry, self.contacts.as_mut(), chat_id, &contact_name)
+        };
+        // Never another chat's session, which a stale contact name or alias can still point at
+        let chat_type = if msg.is_group { "group" } else { "individual" };
+        let session_name = match self.registry.check_name(chat_id, &session_name, chat_type, Some(&contact_name)) {
+            Ok(()) => session_name,
+            Err(Error::SessionNameTaken(name, owner)) => {
+                let distinct = self.registry.claim_session_name(&name, chat_id);
+                warn!("Session {} belongs to {}, using {} for {}", name, owner, distinct, chat_id);
+                self.registry.check_name(chat_id, &distinct, chat_type, Some(&contact_name))?;
+                distinct
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Ensure session exists; a background session shares its chat's transcript dir
+        let transcript_dir = config.transcripts_dir.join(&session_name);
+        let (session_name, session_type) = if background {
+            (format!("{}-bg", session_name), "background")
+        } else {
+            (session_name, if msg.is_group { "group" } else { "individual" })
+        };
+        let key = if background { SessionRegistry::background_key(chat_id) } else { chat_id.clone() };
+        // Listed in the first message of a new group session
+        let mut new_members = None;
+        // Started by the session's worker, just before the message goes in
+        let mut create = None;
+        // Already being started if its worker is still busy
+        if !self.session_mgr.session_exists(&session_name) && !self.workers.is_busy(&session_name) {
+            info!("Creating session: {}", session_name);
+            ensure_transcript_dir(&transcript_dir)?;
+
+            let contact = session_contact(self.contacts.as_mut(), chat_id);
+            let info = SessionInfo {
+                group: msg.is_group.then(|| msg.group_name.clone().unwrap_or_else(|| session_name.clone())),
+                ..SessionInfo::new(chat_id, &tier, &contact_name)
+            };
+            create = Some((info, contact));
+
+            // Register in registry
+            let participants = if msg.is_group {
+                group_participants(&self.messages, self.contacts.as_mut(), chat_id)
+            } else {
+                None
+            };
+            new_members = participants.clone();
+            if let Err(e) = self.registry.register(
+                chat_id,
+                &session_name,
+                transcript_dir.to_str().unwrap_or(""),
+                session_type,
+                Some(contact_name.clone()),
+                msg.group_name.clone(),
+                Some(tier.clone()),
+                participants,
+            ) {
+                // The message must not go on into a session another chat owns
+                if matches!(e, Error::SessionNameTaken(..)) {
+                    return Err(e);
+                }
+                warn!("Failed to register session {} for {}: {}", session_name, chat_id, e);
+            }
+        } else if msg.is_group {
+            // Catch up on a rename whose event row we never saw
+            if let Err(e) = self.registry.update_display_name(chat_id, msg.group_name.clone()) {
+                warn!("Failed to update display name for {}: {}", chat_id, e);
+            }
+            // Pick up people joining or leaving the group
+            if let Some(participants) = group_participants(&self.messages, self.contacts.as_mut(), chat_id) {
+                match self.registry.update_participants(chat_id, participants) {
+                    Ok(true) => info!("Group {} membership changed", chat_id),
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to update participants for {}: {}", chat_id, e),
+                }
+            }
+        }
+        self.registry.count(&key, SessionEvent::Inbound(msg.timestamp));
+
+        // Copy attachments (including voice audio) where Claude can read them
+        let mut staged = Vec::new();
+        if msg.kind == MessageKind::Text {
+            match self.attachment_handler.stage(&transcript_dir, msg.rowid, &msg.attachments) {
+                Ok(s) => staged = s,
+                Err(e) => warn!("Failed to stage attachments for message {}: {}", msg.rowid, e),
+            }
+        }
+        let prompt = compose_prompt(&text, &staged);
+
+        // Wrap and inject message
+        let reply_context = msg
+            .thread_originator_guid
+            .as_deref()
+            .map(|guid| lookup_reply_context(&self.messages, self.contacts.as_mut(), guid));
+        let options = SmsOptions {
+            service: msg.service,
+            subject: msg.subject.as_deref(),
+            reply_context: reply_context.as_deref(),
+            mentioned: msg.mentions_me,
+            group: msg.is_group.then_some(GroupContext { name: msg.group_name.as_deref(), participants: new_members.as_deref() }),
+        };
+        let wrapped = wrap_sms(&prompt, &contact_name, &tier, chat_id, &options);
+        // Wait while Claude is working or out of usage, and behind anything already waiting
+        if create.is_none()
+            && (self.is_paused(&session_name)
+                || self.queue.is_waiting(&session_name)
+                || self.session_mgr.is_busy(&session_name))
+        {
+            info!("Session {} is busy, queueing message {}", session_name, msg.rowid);
+            self.queue.push(
+                &session_name,
+                QueuedPrompt {
+                    text: wrapped,
+                    rowid: msg.rowid,
+                    transcript_dir: transcript_dir.clone(),
+                    queued_at: std::time::Instant::now(),
+                },
+            );
+            return Ok(());
+        }
+
+        let delivered = Delivered {
+            key,
+            recent: (msg.kind == MessageKind::Text).then(|| (msg.clone(), text)),
+        };
+        let (session, rowid) = (session_name.clone(), msg.rowid);
+        self.on_session(&session_name, "inject message into", move |session_mgr| {
+            if let Some((info, contact)) = create {
+                session_mgr.create_session(&session, &transcript_dir, &info, contact.as_ref())?;
+            }
+            session_mgr.inject_message(&session, &wrapped, &transcript_dir, rowid)?;
+            Ok(Some(delivered))
+        });
+        Ok(())
+    }
+
+    /// Pass edits and unsends on to sessions that saw the original message
+    fn check_modified(&mut self) {
+        let modified = match self.messages.get_modified_messages(self.cursors.floor(), self.last_modified_seen) {
+            Ok(modified) => modified,
+            Err(e) => {
+                error!("Failed to check for modified messages: {}", e);
+                return;
+            }
+        };
+        for msg in modified {
+            for changed_at in [msg.date_edited, msg.date_retracted].into_iter().flatten() {
+                self.last_modified_seen = self.last_modified_seen.max(changed_at);
+            }
+            if msg.is_from_me || is_short_code(self.config, &msg) || self.contacts.is_blocked(&msg.sender) {
+                continue;
+            }
+
+            let (contact_name, tier) = match resolve_sender(self.config, self.contacts.as_mut(), &self.registry, &msg) {
+                Some(sender) => sender,
+                None => continue,
+            };
+
+            // Only sessions that already saw the original need the correction
+            let (session_name, transcript_dir) = match self.registry.get(&msg.chat_id) {
+                Some(data) => (data.session_name.clone(), PathBuf::from(&data.transcript_dir)),
+                None => continue,
+            };
+
+            let note = if msg.kind == MessageKind::Retracted {
+                match retraction_note(&self.recent, &msg, &contact_name) {
+                    Some(note) => note,
+                    None => continue,
+                }
+            } else if Utc::now() - msg.timestamp <= self.edit_window {
+                format!("Correction from {}: {}", contact_name, msg.text)
+            } else {
+                continue;
+            };
+
+            info!("Message {} from {} in chat {} was modified", msg.rowid, contact_name, msg.chat_id);
+            let group = msg.is_group.then_some(GroupContext { name: msg.group_name.as_deref(), participants: None });
+            let wrapped = wrap_sms(
+                &note,
+                &contact_name,
+                &tier,
+                &msg.chat_id,
+                &SmsOptions { service: msg.service, group, ..Default::default() },
+            );
+            let (session, rowid) = (session_name.clone(), msg.rowid);
+            self.on_session(&session_name, "inject update into", move |session_mgr| {
+                session_mgr.inject_message(&session, &wrapped, &transcript_dir, rowid).map(|_| None)
+            });
+        }
+    }
+
+    /// Bring sessions in line with their contacts' tiers after contacts change
+    ///
+    /// The registry always takes the new tier. A running session is restarted
+    /// with the new permissions straight away on a demotion, but on a promotion
+    /// only with `restart_on_tier_upgrade`; otherwise it picks them up the next
+    /// time it's recreated. Sessions of chats no longer let through at all,
+    /// because the contact was removed, blocked, or moved out of the blessed
+    /// tiers, or a group lost its last blessed member, are archived.
+    fn apply_tier_changes(&mut self) {
+        let generation = self.contacts.generation();
+        if self.tiers_synced == Some(generation) {
+            return;
+        }
+        self.tiers_synced = Some(generation);
+
+        let mut sessions: Vec<(String, SessionData)> =
+            self.registry.all().iter().map(|(key, d)| (key.clone(), d.clone())).collect();
+        sessions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        // An empty or unreadable contact list says nothing about who's gone
+        let trust_missing = match self.contacts.list_blessed() {
+            Ok(blessed) => !blessed.is_empty(),
+            Err(e) => {
+                warn!("Failed to list contacts, keeping sessions of contacts not found: {}", e);
+                false
+            }
+        };
+
+        for (key, mut data) in sessions {
+            // A group's background session stands or falls with the group
+            let group = data.session_type == "group" || self.registry.get(&data.chat_id).is_some_and(|d| d.session_type == "group");
+            if group {
+                match self.group_let_through(&data.chat_id) {
+                    Ok(false) => self.retire(&key, &data, "nobody in it is blessed any more"),
+                    Ok(true) => {}
+                    Err(e) => {
+                        warn!("Failed to recheck group {}, trying again next poll: {}", data.chat_id, e);
+                        self.tiers_synced = None;
+                    }
+                }
+                continue;
+            }
+            let contact = match self.contacts.lookup_identifier(&data.chat_id) {
+                Ok(Some(contact)) if self.contacts.is_blocked(&data.chat_id) => {
+                    self.retire(&key, &data, &format!("{} is blocked", contact.name));
+                    continue;
+                }
+                Ok(Some(contact)) if !self.contacts.is_blessed_tier(&contact.tier) => {
+                    self.retire(&key, &data, &format!("{} moved to the {} tier", contact.name, contact.tier));
+                    continue;
+                }
+                Ok(Some(contact)) => contact,
+                Ok(None) if trust_missing => {
+                    self.retire(&key, &data, "the contact is gone");
+                    continue;
+                }
+                Ok(None) => {
+                    warn!("No contact found for {}, but the contact list is empty; keeping {}", data.chat_id, data.session_name);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to recheck the tier of {}, trying again next poll: {}", data.chat_id, e);
+                    self.tiers_synced = None;
+                    continue;
+                }
+            };
+            if !contact.name.is_empty() && data.contact_name.as_deref() != Some(contact.name.as_str()) {
+                self.rename_contact(&key, &data, &contact.name);
+                data.contact_name = Some(contact.name.clone());
+            }
+            let old = data.tier.as_deref().unwrap_or(self.config.default_tier());
+            if contact.tier == old {
+                continue;
+            }
+            let upgrade = self.config.tier_rank(&contact.tier) < self.config.tier_rank(old);
+            info!(
+                "{} tier changed from {} to {} for session {}",
+                data.chat_id, old, contact.tier, data.session_name
+            );
+            if let Err(e) = self.registry.update_tier(&key, &contact.tier) {
+                warn!("Failed to record new tier for {}: {}", data.chat_id, e);
+            }
+
+            // One its worker is still starting comes up at the old tier
+            if !self.session_mgr.session_exists(&data.session_name) && !self.workers.is_busy(&data.session_name) {
+                continue;
+            }
+            if upgrade && !self.config.restart_on_tier_upgrade {
+                info!("Session {} keeps its {} permissions until it restarts", data.session_name, old);
+                continue;
+            }
+
+            let transcript_dir = PathBuf::from(&data.transcript_dir);
+            let info = session_info(&data, &contact.tier);
+            let backfill = if data.session_type != "background" {
+                backfill_context(self.config, &self.messages, self.contacts.as_mut(), &data.chat_id)
+            } else {
+                None
+            };
+            let note = format!(
+                "[Permissions changed: this contact moved from the {} tier to {}, and the session was restarted with the new permissions]",
+                old, contact.tier
+            );
+            let (session, conversation) = (data.session_name.clone(), data.claude_session_id.clone());
+            self.on_session(&data.session_name, "apply the new tier to", move |session_mgr| {
+                let resumed = session_mgr.restart_session(
+                    &session,
+                    &transcript_dir,
+                    &info,
+                    Some(&contact),
+                    conversation.as_deref(),
+                    KillMode::Graceful,
+                )?;
+                info!("Restarted session {} at tier {}", session, contact.tier);
+                if let Some(backfill) = backfill.filter(|_| !resumed) {
+                    info!("Backfilling history into {}", session);
+                    if let Err(e) = session_mgr.inject_text(&session, &backfill) {
+                        error!("Failed to inject history into {}: {}", session, e);
+                    }
+                }
+                session_mgr.inject_text(&session, &note)?;
+                Ok(None)
+            });
+        }
+    }
+
+    /// Whether a group chat is still let through: it's blessed, or someone blessed is in it
+    ///
+    /// A chat whose participants can't be read is given the benefit of the doubt.
+    fn group_let_through(&mut self, chat_id: &str) -> Result<bool> {
+        if self.contacts.is_blessed_group(chat_id) {
+            return Ok(true);
+        }
+        let handles = self.messages.get_chat_participants(chat_id)?;
+        if handles.is_empty() {
+            return Ok(true);
+        }
+        for handle in &handles {
+            if let Some(contact) = self.contacts.lookup_identifier(handle)? {
+                if self.contacts.is_blessed_tier(&contact.tier) && !self.contacts.is_blocked(handle) {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Archive the session of a chat that isn't let through any more
+    ///
+    /// Its registry entry stays, so if the chat is blessed again its next
+    /// message starts the session afresh at its new tier.
+    fn retire(&mut self, key: &str, data: &SessionData, why: &str) {
+        if data.archived {
+            return;
+        }
+        let session_name = &data.session_name;
+        info!("Archiving session {}: {}", session_name, why);
+        if let Err(e) = self.registry.set_archived(key, true) {
+            warn!("Failed to mark {} archived: {}", session_name, e);
+        }
+        // Behind anything its worker is still doing, such as starting it
+        let (session, transcript_dir) = (session_name.clone(), PathBuf::from(&data.transcript_dir));
+        self.on_session(session_name, "archive", move |session_mgr| {
+            if session_mgr.session_exists(&session) {
+                session_mgr.archive_and_kill(&session, &transcript_dir, KillMode::Graceful)?;
+            }
+            Ok(None)
+        });
+    }
+
+    /// Record a contact's new name and retitle their running session, which keeps its tmux name
+    fn rename_contact(&mut self, key: &str, data: &SessionData, name: &str) {
+        info!(
+            "{} renamed from {} to {} for session {}",
+            data.chat_id,
+            data.contact_name.as_deref().unwrap_or("nobody"),
+            name,
+            data.session_name
+        );
+        if let Err(e) = self.registry.update_contact_name(key, name) {
+            warn!("Failed to record new name for {}: {}", data.chat_id, e);
+        }
+        if !self.session_mgr.session_exists(&data.session_name) {
+            return;
+        }
+        let (session, title) = (data.session_name.clone(), name.to_string());
+        self.on_session(&data.session_name, "retitle", move |session_mgr| {
+            session_mgr.set_title(&session, &title).map(|_| None)
+        });
+    }
+
+    /// Inject queued prompts into sessions that are free, or have kept them waiting too long
+    fn flush_queue(&mut self, now: std::time::Instant) {
+        for session_name in self.queue.sessions() {
+            // Still working through what it was handed last time, or out of usage
+            if self.workers.is_busy(&session_name) || self.is_paused(&session_name) {
+                continue;
+            }
+            let busy = self.session_mgr.is_busy(&session_name);
+            let Some(prompt) = self.queue.take_ready(&session_name, busy, now) else {
+                continue;
+            };
+            if busy {
+                warn!("Session {} still busy, injecting a prompt that has waited too long", session_name);
+            }
+            self.inject_queued(&session_name, prompt);
+        }
+        self.apply_outcomes();
+    }
+
+    fn inject_queued(&mut self, session_name: &str, prompt: QueuedPrompt) {
+        let session = session_name.to_string();
+        let key = self.registry.get_by_session_name(session_name).map(|data| match data.session_type.as_str() {
+            "background" => SessionRegistry::background_key(&data.chat_id),
+            _ => data.chat_id.clone(),
+        });
+        self.on_session(session_name, "inject queued message into", move |session_mgr| {
+            session_mgr.inject_message(&session, &prompt.text, &prompt.transcript_dir, prompt.rowid)?;
+            Ok(key.map(|key| Delivered { key, recent: None }))
+        });
+    }
+
+    /// Interrupt a chat's session at its owner's request, passing on anything they said after the command
+    fn interrupt_chat(&mut self, chat_id: &str, contact_name: &str, follow_up: &str) {
+        let Some(session_name) = self.registry.get(chat_id).map(|d| d.session_name.clone()) else {
+            warn!("Stop command from {} in chat {}, which has no session", contact_name, chat_id);
+            return;
+        };
+        let follow_up = (!follow_up.is_empty()).then_some(follow_up);
+        match self.session_mgr.interrupt(&session_name, follow_up) {
+            Ok(()) => info!("{} interrupted session {} by SMS", contact_name, session_name),
+            Err(e) => error!("Failed to interrupt {} for {}: {}", session_name, contact_name, e),
+        }
+    }
+
+    /// Tell the admins when a message may never have reached its session
+    fn alert_unconfirmed(&mut self, e: &Error) {
+        if let Error::InjectionNotConfirmed(session_name) = e {
+            notify_admin(
+                self.config,
+                &self.notifier,
+                self.contacts.as_mut(),
+                &format!("Claude Assistant: a message sent to {} never showed up in its session and may have been lost.", session_name),
+            );
+        }
+    }
+
+    /// Archive sessions nobody has messaged in `idle_timeout_hours`
+    ///
+    /// The scrollback is archived in the transcript dir before the session is
+    /// killed. The registry entry stays, marked archived, so the next
+    /// message recreates the session under the same name.
+    fn reap_idle(&mut self, now: DateTime<Utc>) {
+        if self.config.idle_timeout_hours <= 0.0 {
+            return;
+        }
+        let timeout = chrono::Duration::seconds((self.config.idle_timeout_hours * 3600.0) as i64);
+
+        let mut idle: Vec<(String, SessionData)> = self
+            .registry
+            .all()
+            .iter()
+            .filter(|(_, d)| !d.archived)
+            .filter(|(_, d)| now - d.last_message_time.unwrap_or(d.updated_at) >= timeout)
+            .map(|(key, d)| (key.clone(), d.clone()))
+            .collect();
+        // By the tier the contact has now, which the registry only catches up with on the next tier sync
+        idle.retain(|(_, d)| {
+            let tier = self.current_tier(d);
+            !self.config.idle_exempt_tiers.iter().any(|t| Some(t.as_str()) == tier.as_deref())
+        });
+        idle.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (key, data) in idle {
+            let session_name = &data.session_name;
+            if self.session_mgr.session_exists(session_name) {
+                if let Err(e) = self.session_mgr.archive_and_kill(session_name, Path::new(&data.transcript_dir), KillMode::Graceful) {
+                    error!("Failed to kill idle session {}: {}", session_name, e);
+                    continue;
+                }
+            }
+            info!("Archived idle session {}", session_name);
+            if let Err(e) = self.registry.set_archived(&key, true) {
+                warn!("Failed to mark {} archived: {}", session_name, e);
+            }
+        }
+    }
+
+    /// A session's tier as its contact has it now
+    ///
+    /// Groups, chats without a contact, and failed lookups get the tier the registry has.
+    fn current_tier(&mut self, data: &SessionData) -> Option<String> {
+        match self.contacts.lookup_identifier(&data.chat_id) {
+            Ok(Some(contact)) if data.session_type == "individual" => Some(contact.tier),
+            _ => data.tier.clone(),
+        }
+    }
+
+    /// Refresh stale contacts and start checking every session's health
+    ///
+    /// The checks run on threads of their own; `finish_sweep` acts on what
+    /// they find. A sweep still going when the next is due is left to finish.
+    fn start_sweep(&mut self, now: std::time::Instant) {
+        if let Some(sweep) = &self.sweep {
+            warn!("Health sweep still waiting on {} session(s), not starting another", sweep.waiting.len());
+            return;
+        }
+        debug!("Running health checks...");
+
+        self.contacts.refresh_if_stale();
+
+        let race = self.messages.race_stats();
+        if race.requeried > 0 {
+            info!(
+                requeried = race.requeried,
+                resolved = race.resolved,
+                deferred = race.deferred,
+                gave_up = race.gave_up,
+                "[RACE_TELEMETRY] chat_message_join race totals"
+            );
+        }
+
+        match self.session_mgr.rotate_session_logs() {
+            Ok(0) => {}
+            Ok(n) => info!("Rotated {} session logs", n),
+            Err(e) => warn!("Failed to rotate session logs: {}", e),
+        }
+
+        // Sessions mid-start or mid-restart would look unhealthy
+        let waiting: HashMap<String, String> = self
+            .registry
+            .all()
+            .iter()
+            .filter(|(_, data)| !data.archived && !data.quarantined && !self.workers.is_busy(&data.session_name))
+            .map(|(key, data)| (data.session_name.clone(), key.clone()))
+            .collect();
+        let check_usage = self.config.max_session_rss_mb > 0 || self.config.max_session_cpu_pct > 0.0;
+        let results = spawn_sweep(Arc::clone(&self.session_mgr), waiting.keys().cloned().collect(), check_usage);
+        self.sweep = Some(Sweep {
+            now,
+            results,
+            waiting,
+            last_heard: std::time::Instant::now(),
+            logged_out: Vec::new(),
+            lost: Vec::new(),
+        });
+    }
+
+    /// Act on the health checks that are in, and wrap up the sweep once they all are
+    ///
+    /// With `wait`, blocks until then. A session whose check hasn't come in
+    /// for `health_check_timeout_secs` is given up on until the next sweep.
+    fn finish_sweep(&mut self, wait: bool) {
+        let Some(mut sweep) = self.sweep.take() else {
+            return;
+        };
+        let timeout = Duration::from_secs(self.config.health_check_timeout_secs);
+        let stalled = |sweep: &Sweep| !timeout.is_zero() && sweep.last_heard.elapsed() >= timeout;
+        while !sweep.waiting.is_empty() {
+            let received = match (wait, timeout.is_zero()) {
+                (true, true) => sweep.results.recv().ok(),
+                (true, false) => sweep.results.recv_timeout(timeout.saturating_sub(sweep.last_heard.elapsed())).ok(),
+                (false, _) => sweep.results.try_recv().ok(),
+            };
+            match received {
+                Some((session_name, probe)) => {
+                    if let Some(key) = sweep.waiting.remove(&session_name) {
+                        self.apply_probe(&mut sweep, &key, probe);
+                    }
+                    // Time spent restarting isn't the checks' to answer for
+                    sweep.last_heard = std::time::Instant::now();
+                }
+                None if wait || stalled(&sweep) => {
+                    let mut stuck: Vec<&str> = sweep.waiting.keys().map(String::as_str).collect();
+                    stuck.sort();
+                    warn!("Health checks of {} didn't finish, leaving them for the next sweep", stuck.join(", "));
+                    break;
+                }
+                None => {
+                    self.sweep = Some(sweep);
+                    return;
+                }
+            }
+        }
+        // One alert for them all; they're quarantined, so it isn't sent again
+        if !sweep.logged_out.is_empty() {
+            sweep.logged_out.sort();
+            notify_admin(self.config, &self.notifier, self.contacts.as_mut(), &auth_alert(&sweep.logged_out));
+        }
+        self.recreate_lost(sweep.lost);
+        self.apply_outcomes();
+        if let Err(e) = self.health_history.save() {
+            warn!("Failed to save health history: {}", e);
+        }
+    }
+
+    /// Run a whole health sweep, waiting for it to finish
+    #[cfg(test)]
+    fn check_health(&mut self, now: std::time::Instant) {
+        self.start_sweep(now);
+        self.finish_sweep(true);
+    }
+
+    /// Restart a session that has crashed or hung, going by what its health check found
+    ///
+    /// A session that keeps failing is restarted less and less often, and
+    /// after `restart_max_attempts` in a row it's quarantined: left alone, with
+    /// the admins told, until `restart-session`.
+    fn apply_probe(&mut self, sweep: &mut Sweep, key: &str, probe: Probe) {
+        let now = sweep.now;
+        // Gone, retired, or being restarted since the sweep started
+        let Some(data) = self.registry.all().get(key).cloned() else {
+            return;
+        };
+        if data.archived || data.quarantined || self.workers.is_busy(&data.session_name) {
+            return;
+        }
+        let session_name = &data.session_name;
+        let transcript_dir = PathBuf::from(&data.transcript_dir);
+
+        let mut status = probe.status;
+        let (max_rss_mb, max_cpu_pct) = (self.config.max_session_rss_mb, self.config.max_session_cpu_pct);
+        if let Some(usage) = probe.usage.filter(|usage| usage.over(max_rss_mb, max_cpu_pct)) {
+            status = HealthStatus::Unhealthy(UnhealthyReason::ResourceLimit {
+                rss_mb: usage.rss_mb,
+                cpu_pct: usage.cpu_pct,
+            });
+        }
+        if status == HealthStatus::Healthy {
+            let api_error = self.config.health_patterns.has_api_error(&probe.pane, &HealthCheckOptions::default());
+            if self.stuck.observe(session_name, &probe.pane) {
+                status = HealthStatus::Unhealthy(UnhealthyReason::Stuck);
+            } else if self.api_errors.record(session_name, now, api_error) {
+                status = HealthStatus::Unhealthy(UnhealthyReason::ApiErrorsPersistent);
+            }
+        }
+        match status {
+            // Restarting won't get it more usage
+            HealthStatus::Unhealthy(UnhealthyReason::UsageLimited) => self.pause(&data, &probe.pane),
+            // Nor log it back in; left for someone to attach and do it
+            HealthStatus::Unhealthy(UnhealthyReason::AuthRequired) => {
+                error!("Session {} is logged out of Claude, quarantining it", session_name);
+                if let Err(e) = self.registry.set_quarantined(key, true) {
+                    warn!("Failed to quarantine {}: {}", session_name, e);
+                }
+                self.health_history.transition(
+                    session_name,
+                    Utc::now(),
+                    HealthState::Quarantined,
+                    Some(UnhealthyReason::AuthRequired.to_string()),
+                    Some(ActionTaken::Quarantine),
+                );
+                sweep.logged_out.push(session_name.clone());
+            }
+            // Gone with tmux's server, so recreated with the others rather than restarted on its own
+            HealthStatus::Unhealthy(UnhealthyReason::NoServer) => sweep.lost.push(key.to_string()),
+            HealthStatus::Unhealthy(UnhealthyReason::SessionMissing) if self.recreating.contains(key) => {
+                sweep.lost.push(key.to_string())
+            }
+            HealthStatus::Unhealthy(reason) => {
+                warn!("Session {} unhealthy: {:?}", session_name, reason);
+                if reason == UnhealthyReason::ContextExhausted && self.compact(key, session_name, now) {
+                    return;
+                }
+                self.stuck.forget(session_name);
+                self.api_errors.forget(session_name);
+                let decision = self.restarts.unhealthy(session_name, now);
+                let action = match decision {
+                    RestartDecision::Restart(_) => Some(ActionTaken::Restart),
+                    // Each sweep it waits out isn't news
+                    RestartDecision::Wait => {
+                        let news = self.health_history.last_state(session_name) != Some(HealthState::Unhealthy);
+                        news.then_some(ActionTaken::Backoff)
+                    }
+                    RestartDecision::GiveUp(_) => None,
+                };
+                if action.is_some() {
+                    self.health_history.transition(
+                        session_name,
+                        Utc::now(),
+                        HealthState::Unhealthy,
+                        Some(reason.to_string()),
+                        action,
+                    );
+                }
+                match decision {
+                    RestartDecision::Restart(1) => {}
+                    RestartDecision::Restart(attempt) => {
+                        info!("Restarting {} again, attempt {} in a row", session_name, attempt)
+                    }
+                    RestartDecision::Wait => {
+                        debug!("Backing off restarting {}", session_name);
+                        return;
+                    }
+                    RestartDecision::GiveUp(attempts) => {
+                        self.give_up_on(key, &data, attempts);
+                        return;
+                    }
+                }
+                self.restart(key, &data, reason, Duration::ZERO);
+            }
+            HealthStatus::Healthy => {
+                debug!("Session {} healthy", session_name);
+                self.recreating.remove(key);
+                self.restarts.healthy(session_name);
+                self.compacting.remove(session_name);
+                let resumed = self.paused.remove(session_name);
+                if resumed.as_ref().is_some_and(|paused| Utc::now() < paused.until) {
+                    info!("Session {} is past its usage limit early, resuming it", session_name);
+                }
+                let action = resumed.map(|_| ActionTaken::Resume);
+                self.health_history.transition(session_name, Utc::now(), HealthState::Healthy, None, action);
+                // Note the conversation it's on, to resume after a restart; one older than
+                // the session is a previous session's, left behind by a fresh start
+                let started = probe.started.unwrap_or(std::time::UNIX_EPOCH);
+                if let Some(conversation) = conversation_since(&transcript_dir, started) {
+                    if let Err(e) = self.registry.update_claude_session(key, &conversation) {
+                        warn!("Failed to record conversation for {}: {}", session_name, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Restart a session on its worker, `delay` from now, saving a crash report first
+    fn restart(&mut self, key: &str, data: &SessionData, reason: UnhealthyReason, delay: Duration) {
+        let session_name = &data.session_name;
+        let transcript_dir = PathBuf::from(&data.transcript_dir);
+        self.registry.count(key, SessionEvent::Restart);
+
+        let tier = data.tier.as_deref().unwrap_or(self.config.default_tier());
+        let contact = session_contact(self.contacts.as_mut(), &data.chat_id);
+        let info = session_info(data, tier);
+        // Background sessions run tasks, not the conversation, so get no history
+        let backfill = if data.session_type != "background" {
+            backfill_context(self.config, &self.messages, self.contacts.as_mut(), &data.chat_id)
+        } else {
+            None
+        };
+
+        // Restart, picking up where the conversation left off if possible
+        self.restarting.insert(session_name.clone(), (key.to_string(), reason.to_string()));
+        let (session, conversation) = (session_name.clone(), data.claude_session_id.clone());
+        // Nothing of a session is left to report on once the server has gone
+        let keep = if reason == UnhealthyReason::NoServer { 0 } else { self.config.crash_bundle_keep };
+        let crashes_dir = crash::crashes_dir(&self.config.logs_dir);
+        let daemon_log = self.config.logs_dir.join("manager.log");
+        let (entry, why) = (data.clone(), reason.to_string());
+        self.on_session(session_name, RESTART_JOB, move |session_mgr| {
+            std::thread::sleep(delay);
+            // Before the evidence goes with it
+            if keep > 0 {
+                let bundle = CrashBundle::collect(session_mgr, &session, &why, Some(entry), &daemon_log);
+                match bundle.save(&crashes_dir, keep) {
+                    Ok(dir) => info!("Saved a crash report for {} to {}", session, dir.display()),
+                    Err(e) => warn!("Failed to save a crash report for {}: {}", session, e),
+                }
+            }
+            let _ = session_mgr.archive_and_kill(&session, &transcript_dir, KillMode::for_unhealthy(&reason));
+            std::thread::sleep(Duration::from_secs(1));
+
+            if session_mgr.resume_session(&session, &transcript_dir, &info, contact.as_ref(), conversation.as_deref())? {
+                info!("Restarted unhealthy session {}, resuming its conversation", session);
+                return Ok(None);
+            }
+            info!("Restarted unhealthy session: {}", session);
+            if let Some(backfill) = backfill {
+                info!("Backfilling history into {}", session);
+                session_mgr.inject_text(&session, &backfill)?;
+            }
+            Ok(None)
+        });
+    }
+
+    /// Recreate sessions lost with tmux's server, a few a sweep and one after another
+    ///
+    /// Every session goes at once with the server, as after a reboot that
+    /// started the daemon before tmux's socket dir was there. Starting them all
+    /// together overwhelmed the machine and half failed, so this takes the
+    /// chats messaged most recently first, `server_recovery_batch` a sweep,
+    /// `server_recovery_stagger_secs` apart. The rest wait for the next sweep,
+    /// whatever tmux says about them by then.
+    fn recreate_lost(&mut self, mut lost: Vec<String>) {
+        if lost.is_empty() {
+            return;
+        }
+        if lost.iter().any(|key| !self.recreating.contains(key)) {
+            error!("tmux's server isn't running, so {} session(s) are gone; recreating them a few at a time", lost.len());
+        }
+        self.recreating.extend(lost.iter().cloned());
+        let last_inbound = |key: &String| self.registry.all().get(key).and_then(|data| data.counters.last_inbound);
+        lost.sort_by_key(|key| std::cmp::Reverse(last_inbound(key)));
+
+        let batch = self.config.server_recovery_batch.max(1);
+        let stagger = Duration::from_secs(self.config.server_recovery_stagger_secs);
+        for (n, key) in lost.iter().take(batch).enumerate() {
+            self.recreating.remove(key);
+            let Some(data) = self.registry.all().get(key).cloned() else {
+                continue;
+            };
+            info!("Recreating {} ({} of {} lost)", data.session_name, n + 1, lost.len());
+            self.health_history.transition(
+                &data.session_name,
+                Utc::now(),
+                HealthState::Unhealthy,
+                Some(UnhealthyReason::NoServer.to_string()),
+                Some(ActionTaken::Restart),
+            );
+            self.restart(key, &data, UnhealthyReason::NoServer, stagger * n as u32);
+        }
+        if lost.len() > batch {
+            info!("{} lost session(s) left for the next sweep", lost.len() - batch);
+        }
+    }
+
+    /// Send `compact_command` to a session out of context, or give it time to take
+    ///
+    /// Returns false once it has had `compact_grace_secs` and is still out of
+    /// context, so should be restarted after all.
+    fn compact(&mut self, key: &str, session_name: &str, now: std::time::Instant) -> bool {
+        let grace = Duration::from_secs(self.config.compact_grace_secs);
+        match self.compacting.get(session_name) {
+            None => {
+                info!("Session {} is out of context, sending {}", session_name, self.config.compact_command);
+                self.registry.count(key, SessionEvent::Compaction);
+                self.health_history.transition(
+                    session_name,
+                    Utc::now(),
+                    HealthState::Unhealthy,
+                    Some(UnhealthyReason::ContextExhausted.to_string()),
+                    Some(ActionTaken::Compact),
+                );
+                self.compacting.insert(session_name.to_string(), now);
+                self.inject_later(session_name, self.config.compact_command.clone(), "compact");
+                true
+            }
+            Some(&sent) if now.saturating_duration_since(sent) < grace => {
+                debug!("Waiting for {} to compact", session_name);
+                true
+            }
+            Some(_) => {
+                warn!("Session {} is still out of context after {:?}, restarting it", session_name, grace);
+                self.compacting.remove(session_name);
+                false
+            }
+        }
+    }
+
+    /// Whether a session is holding its messages until its usage limit resets
+    fn is_paused(&self, session_name: &str) -> bool {
+        self.paused.get(session_name).is_some_and(|paused| Utc::now() < paused.until)
+    }
+
+    /// Hold a session's messages until its usage limit resets, and tell its chat once why replies stopped
+    ///
+    /// The banner is still on screen after the reset, until another prompt
+    /// goes in, so the one that paused the session doesn't pause it again.
+    fn pause(&mut self, data: &SessionData, pane: &str) {
+        let session_name = &data.session_name;
+        let Some(banner) = usage_limit_banner(pane, &HealthCheckOptions::default()) else {
+            return;
+        };
+        if self.paused.get(session_name).is_some_and(|paused| paused.banner == banner) {
+            debug!("Session {} still shows its usage limit", session_name);
+            return;
+        }
+        let now = Utc::now();
+        let until = usage_limit_reset(&banner, now.with_timezone(&chrono::Local))
+            .map(|reset| reset.with_timezone(&Utc))
+            .filter(|reset| *reset > now)
+            .unwrap_or(now + chrono::Duration::minutes(self.config.usage_limit_pause_mins as i64));
+        let resume = until.with_timezone(&chrono::Local).format("%-I:%M %p");
+        warn!("Session {} hit its usage limit, holding its messages until {}", session_name, resume);
+        self.paused.insert(session_name.clone(), Paused { until, banner });
+        self.health_history.transition(
+            session_name,
+            now,
+            HealthState::Paused,
+            Some(UnhealthyReason::UsageLimited.to_string()),
+            Some(ActionTaken::Pause),
+        );
+        let text = format!(
+            "Claude Assistant is temporarily out of capacity. Your messages are saved and will be answered after {}.",
+            resume
+        );
+        if let Err(e) = self.notifier.send(&data.chat_id, &text) {
+            warn!("Failed to tell {} that {} is paused: {}", data.chat_id, session_name, e);
+        }
+    }
+
+    /// Ask a chat to resend a message its session's restart may have lost
+    ///
+    /// Only if it messaged in the last `recovery_notice_window_mins`, and not
+    /// again within `recovery_notice_cooldown_mins`, so a session that keeps
+    /// failing doesn't keep texting it.
+    fn notify_recovered(&mut self, key: &str, reason: &str, now: DateTime<Utc>) {
+        let Some(data) = self.registry.all().get(key).cloned() else {
+            return;
+        };
+        let last_notice = self.recovery_notices.get(&data.chat_id).copied();
+        if !recovery_notice_due(self.config, data.counters.last_inbound, last_notice, now) {
+            return;
+        }
+        let text = recovery_notice(self.config, data.tier.as_deref().unwrap_or(self.config.default_tier()), &data.session_name, reason);
+        match self.notifier.send(&data.chat_id, &text) {
+            Ok(()) => {
+                info!("Asked {} to resend its last message to {}", data.chat_id, data.session_name);
+                self.recovery_notices.insert(data.chat_id.clone(), now);
+            }
+            Err(e) => warn!("Failed to tell {} that {} was restarted: {}", data.chat_id, data.session_name, e),
+        }
+    }
+
+    /// Stop restarting a session that keeps failing, and tell the admins what its screen last showed
+    fn give_up_on(&mut self, key: &str, data: &SessionData, attempts: u32) {
+        let session_name = &data.session_name;
+        error!("Session {} failed {} restarts in a row, quarantining it", session_name, attempts);
+        if let Err(e) = self.registry.set_quarantined(key, true) {
+            warn!("Failed to quarantine {}: {}", session_name, e);
+        }
+        self.health_history.transition(
+            session_name,
+            Utc::now(),
+            HealthState::Quarantined,
+            Some(format!("failed {} restarts", attempts)),
+            Some(ActionTaken::Quarantine),
+        );
+        let mut text = format!(
+            "Claude Assistant: {} failed {} restarts in a row and won't be restarted until you run `claude-assistant-rs restart-session {}`.",
+            session_name, attempts, session_name
+        );
+        if let Some((path, screen)) = last_screen(&self.session_mgr, session_name, Path::new(&data.transcript_dir)) {
+            text.push_str(&format!("\n\nIts screen ({}) ended:\n{}", path.display(), screen));
+        }
+        notify_admin(self.config, &self.notifier, self.contacts.as_mut(), &text);
+    }
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Lines of a failed session's screen sent to the admins
+const LAST_SCREEN_LINES: usize = 15;
+
+/// Where a session's screen was saved and how it ended
+///
+/// A running session's pane is archived now; otherwise it's the archive saved
+/// when it was last killed.
+fn last_screen(session_mgr: &SessionManager, session_name: &str, transcript_dir: &Path) -> Option<(PathBuf, String)> {
+    let path = match session_mgr.archive_pane(session_name, transcript_dir) {
+        Ok(path) => path,
+        Err(_) => fs::read_dir(transcript_dir.join(PANE_ARCHIVE_DIR))
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(&format!("-{}.txt", session_name))))
+            .max()?,
+    };
+    let content = fs::read_to_string(&path).ok()?;
+    let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+    let screen = lines[lines.len().saturating_sub(LAST_SCREEN_LINES)..].join("\n");
+    Some((path, screen))
+}
+
+/// What to tell the admins when sessions are logged out of Claude
+///
+/// Logging in from one session logs them all in, so only the first needs attaching to.
+fn auth_alert(sessions: &[String]) -> String {
+    let first = &sessions[0];
+    let restart = match sessions {
+        [_] => format!("`claude-assistant-rs restart-session {}`", first),
+        _ => format!("`claude-assistant-rs restart-session` for each of {}", sessions.join(", ")),
+    };
+    format!(
+        "Claude Assistant: Claude needs re-authentication — attach to {} (`claude-assistant-rs attach {}`) and log in, then run {}.",
+        first, first, restart
+    )
+}
+
+/// Whether a chat last messaged recently enough, and was last asked to resend long enough ago, to ask again
+fn recovery_notice_due(
+    config: &Config,
+    last_inbound: Option<DateTime<Utc>>,
+    last_notice: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    let window = chrono::Duration::minutes(config.recovery_notice_window_mins as i64);
+    let cooldown = chrono::Duration::minutes(config.recovery_notice_cooldown_mins as i64);
+    let recent = last_inbound.is_some_and(|at| now - at <= window);
+    let cooled = last_notice.is_none_or(|at| now - at >= cooldown);
+    config.recovery_notice_window_mins > 0 && recent && cooled
+}
+
+/// What a chat is told after its session was restarted; the top tier hears why
+fn recovery_notice(config: &Config, tier: &str, session_name: &str, reason: &str) -> String {
+    if is_top_tier(config, tier) {
+        format!(
+            "Claude Assistant: {} was restarted ({}). If your last message went unanswered, please send it again.",
+            session_name, reason
+        )
+    } else {
+        "Had a hiccup and restarted — could you resend your last message?".to_string()
+    }
+}
+
+/// The task in a message addressed to the background session, e.g. "bg: find flights to Denver"
+fn background_task<'a>(config: &Config, kind: &MessageKind, text: &'a str) -> Option<&'a str> {
+    if *kind != MessageKind::Text {
+        return None;
+    }
+    let text = text.trim_start();
+    config.background_prefixes.iter().filter(|p| !p.is_empty()).find_map(|prefix| {
+        let head = text.get(..prefix.len())?;
+        if !head.eq_ignore_ascii_case(prefix) {
+            return None;
+        }
+        let task = text[prefix.len()..].trim();
+        (!task.is_empty()).then_some(task)
+    })
+}
+
+/// What follows the stop command in a message that starts with it, e.g. "" for "!stop"
+fn stop_command<'a>(config: &Config, kind: &MessageKind, text: &'a str) -> Option<&'a str> {
+    if *kind != MessageKind::Text || config.stop_command.is_empty() {
+        return None;
+    }
+    let text = text.trim();
+    let head = text.get(..config.stop_command.len())?;
+    if !head.eq_ignore_ascii_case(&config.stop_command) {
+        return None;
+    }
+    // "!stopwatch" is just a message
+    let rest = &text[head.len()..];
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.trim())
+}
+
+/// Whether a tier is the first, most trusted one
+fn is_top_tier(config: &Config, tier: &str) -> bool {
+    config.tiers.first().is_some_and(|top| top.name == tier)
+}
+
+/// Whether a message comes from an SMS short code or alphanumeric sender ID rather than a person
+fn is_short_code(config: &Config, msg: &Message) -> bool {
+    Identifier::parse(&msg.sender, &config.default_region).is_short_code()
+}
+
+/// Text everyone in the top tier
+fn notify_admin(config: &Config, notifier: &dyn Notifier, contacts: &mut dyn ContactSource, text: &str) {
+    let Some(top) = config.tiers.first() else {
+        return;
+    };
+    let admins = match contacts.list_blessed() {
+        Ok(blessed) => blessed.into_iter().filter(|c| c.tier == top.name),
+        Err(e) => {
+            warn!("Failed to look up {} contacts to notify: {}", top.name, e);
+            return;
+        }
+    };
+    for admin in admins {
+        let Some(handle) = admin.phone.or(admin.email) else {
+            continue;
+        };
+        match notifier.send(&handle, text) {
+            Ok(()) => info!("Notified {} ({})", admin.name, handle),
+            Err(e) => warn!("Failed to notify {}: {}", admin.name, e),
+        }
+    }
+}
+
+/// Drop a message from a blocked sender, keeping a quarantine record of it
+fn drop_blocked(contacts: &mut dyn ContactSource, quarantine: &Quarantine, msg: &Message) -> bool {
+    if !contacts.is_blocked(&msg.sender) {
+        return false;
+    }
+    info!("Dropping message {} from blocked sender {} in {}", msg.rowid, msg.sender, msg.chat_id);
+    let entry = QuarantineEntry {
+        blocked: true,
+        ..QuarantineEntry::from_message(msg)
+    };
+    if let Err(e) = quarantine.append_entry(&entry) {
+        warn!("Failed to quarantine message {}: {}", msg.rowid, e);
+    }
+    true
+}
+
+/// Re-register reminders from blessed contacts' notes and the reminders file if the
+/// contacts cache was rebuilt or the file written since `synced` (the contacts
+/// generation and file time last synced from)
+fn sync_reminders(
+    config: &Config,
+    reminders: &mut ReminderManager,
+    contacts: &mut dyn ContactSource,
+    registry: &SessionRegistry,
+    synced: &mut Option<(u64, Option<SystemTime>)>,
+) {
+    let store = match ReminderStore::load(config) {
+        Ok(store) => store,
+        Err(e) => {
+            warn!("Failed to load the reminders file: {}", e);
+            return;
+        }
+    };
+    let current = (contacts.generation(), store.modified());
+    if *synced == Some(current) {
+        return;
+    }
+    match contacts.list_blessed() {
+        Ok(blessed) => {
+            let chats = reminders.sync_contacts(&blessed, store.reminders(), registry, Utc::now());
+            info!("Reminders registered for {} chat(s) ({} total)", chats, reminders.count());
+            *synced = Some(current);
+        }
+        Err(e) => warn!("Failed to load contacts for reminders: {}", e),
+    }
+}
+
+/// Save when reminders last fired, if that changed
+fn flush_reminders(reminders: &mut ReminderManager) {
+    if let Err(e) = reminders.flush() {
+        warn!("Failed to save reminder fire times: {}", e);
+    }
+}
+
+/// Reminders due now, except for chats with a blocked sender
+fn due_reminders(
+    reminders: &mut ReminderManager,
+    contacts: &mut dyn ContactSource,
+    now: DateTime<Utc>,
+) -> Vec<(String, String)> {
+    reminders
+        .check_due(now)
+        .into_iter()
+        .filter(|(chat_id, _)| {
+            let blocked = contacts.is_blocked(chat_id);
+            if blocked {
+                info!("Skipping reminder for blocked chat {}", chat_id);
+            }
+            !blocked
+        })
+        .collect()
+}
+
+/// Environment and session-info.json for restarting a registered session at a tier
+fn session_info(data: &SessionData, tier: &str) -> SessionInfo {
+    let contact = data.contact_name.as_deref().or(data.display_name.as_deref()).unwrap_or(&data.session_name);
+    SessionInfo {
+        group: (data.session_type == "group").then(|| session_title(data).to_string()),
+        ..SessionInfo::new(&data.chat_id, tier, contact)
+    }
+}
+
+/// What a registered session's window and pane are titled: its group's name, or its contact's
+fn session_title(data: &SessionData) -> &str {
+    let name = if data.session_type == "group" { &data.display_name } else { &data.contact_name };
+    name.as_deref().unwrap_or(&data.session_name)
+}
+
+/// The contact a 1:1 chat is with, whose own settings adjust their session's tier
+fn session_contact(contacts: &mut dyn ContactSource, chat_id: &str) -> Option<Contact> {
+    contacts.lookup_identifier(chat_id).ok().flatten()
+}
+
+/// Resolve who a message is from and the tier its session runs at, or None if it isn't let through
+///
+/// In a blessed group every participant is let through, named by their contact
+/// name or else their raw handle. A group session an unblessed participant
+/// starts runs at the configured default tier.
+fn resolve_sender(
+    config: &Config,
+    contacts: &mut dyn ContactSource,
+    registry: &SessionRegistry,
+    msg: &Message,
+) -> Option<(String, String)> {
+    if let Some(sender) = resolve_blessed_sender(contacts, msg) {
+        return Some(sender);
+    }
+    if !msg.is_group || !contacts.is_blessed_group(&msg.chat_id) {
+        return None;
+    }
+    let name = match contacts.lookup_identifier(&msg.sender) {
+        Ok(Some(contact)) if !contact.name.is_empty() => contact.name,
+        _ => msg.sender.clone(),
+    };
+    let tier = registry
+        .get(&msg.chat_id)
+        .and_then(|d| d.tier.clone())
+        .unwrap_or_else(|| config.default_tier().to_string());
+    Some((name, tier))
+}
+
+/// Resolve the blessed contact behind a message, returning (name, tier)
+///
+/// For groups the individual sender must be blessed; for 1:1 chats the chat_id is the sender.
+/// Senders may be phone numbers or Apple ID emails.
+fn resolve_blessed_sender(contacts: &mut dyn ContactSource, msg: &Message) -> Option<(String, String)> {
+    let identifier = if msg.is_group { &msg.sender } else { &msg.chat_id };
+    match contacts.lookup_identifier(identifier) {
+        Ok(Some(contact)) if contacts.is_blessed_tier(&contact.tier) => {
+            let name = if contact.name.is_empty() { identifier.clone() } else { contact.name };
+            Some((name, contact.tier))
+        }
+        _ => None,
+    }
+}
+
+/// Gentle reminder that a message has sat without a reply
+fn unreplied_nudge(name: &str, msg: &Message, now: DateTime<Utc>) -> String {
+    let snippet: String = msg.body_text().chars().take(100).collect();
+    match msg.date_read {
+        Some(read) => format!(
+            "[Reminder: you read {}'s message {} ago and haven't replied: \"{}\"]",
+            name,
+            format_ago(now - read),
+            snippet
+        ),
+        None => format!(
+            "[Reminder: {} messaged {} ago and hasn't had a reply: \"{}\"]",
+            name,
+            format_ago(now - msg.timestamp),
+            snippet
+        ),
+    }
+}
+
+/// "3 hours", "1 hour", "45 minutes"
+fn format_ago(elapsed: chrono::Duration) -> String {
+    let plural = |n: i64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+    match elapsed.num_hours() {
+        0 => plural(elapsed.num_minutes().max(1), "minute"),
+        hours if hours < 48 => plural(hours, "hour"),
+        _ => plural(elapsed.num_days(), "day"),
+    }
+}
+
+/// Fold a group rename or membership change into the registry and the group's session
+///
+/// Only groups that already have a session are tracked. The tmux session keeps
+/// its original name; only the recorded display name changes.
+fn handle_group_event(
+    session_mgr: &SessionManager,
+    registry: &mut SessionRegistry,
+    messages: &MessagesReader,
+    contacts: &mut dyn ContactSource,
+    msg: &Message,
+    event: &GroupEvent,
+) {
+    let Some(session_name) = registry.get(&msg.chat_id).map(|d| d.session_name.clone()) else {
+        debug!("Ignoring group event in untracked chat {}", msg.chat_id);
+        return;
+    };
+
+    let updated = match event {
+        GroupEvent::Renamed { name } => registry.update_display_name(&msg.chat_id, name.clone()),
+        GroupEvent::ParticipantAdded { .. } | GroupEvent::ParticipantRemoved { .. } | GroupEvent::Left => {
+            match group_participants(messages, contacts, &msg.chat_id) {
+                Some(participants) => registry.update_participants(&msg.chat_id, participants),
+                None => Ok(false),
+            }
+        }
+        GroupEvent::PhotoChanged => Ok(false),
+    };
+    match updated {
+        Ok(true) if matches!(event, GroupEvent::Renamed { .. }) && session_mgr.session_exists(&session_name) => {
+            let title = registry.get(&msg.chat_id).map(session_title).unwrap_or(&session_name);
+            if let Err(e) = session_mgr.set_title(&session_name, title) {
+                warn!("Failed to retitle {}: {}", session_name, e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to update registry for group {}: {}", msg.chat_id, e),
+    }
+
+    let actor = if msg.is_from_me {
+        "You".to_string()
+    } else {
+        participant_names(contacts, std::slice::from_ref(&msg.sender)).remove(0)
+    };
+    let note = group_event_note(&actor, event, |handle| {
+        participant_names(contacts, &[handle.to_string()]).remove(0)
+    });
+    info!("Group {}: {}", msg.chat_id, note);
+    if let Err(e) = session_mgr.inject_text(&session_name, &format!("[Group update: {}]", note)) {
+        warn!("Failed to inject group update into {}: {}", session_name, e);
+    }
+}
+
+/// Compact description of a group event, e.g. "Group renamed to 'Ski Trip 2025'"
+fn group_event_note(actor: &str, event: &GroupEvent, mut name_for: impl FnMut(&str) -> String) -> String {
+    match event {
+        GroupEvent::Renamed { name: Some(name) } => format!("Group renamed to '{}'", name),
+        GroupEvent::Renamed { name: None } => "Group name removed".to_string(),
+        GroupEvent::ParticipantAdded { handle } => format!("{} was added", name_for(handle)),
+        GroupEvent::ParticipantRemoved { handle } => format!("{} was removed", name_for(handle)),
+        GroupEvent::Left => format!("{} left the group", actor),
+        GroupEvent::PhotoChanged => format!("{} changed the group photo", actor),
+    }
+}
+
+/// Log (and optionally inject) a reply sent from one of the owner's devices
+fn track_outbound(
+    config: &Config,
+    session_mgr: &SessionManager,
+    registry: &SessionRegistry,
+    notifier: &RememberingNotifier,
+    msg: &Message,
+) {
+    let Some(data) = registry.get(&msg.chat_id) else {
+        return;
+    };
+    let text = msg.body_text();
+
+    // The daemon's notices and Claude's own send-sms calls land in chat.db as from-me too; don't feed them back
+    let author = if notifier.sent_recently(&msg.chat_id, &text)
+        || session_mgr
+            .capture_pane(&data.session_name, 200)
+            .is_ok_and(|pane| outbound::sent_by_session(&pane, &text))
+    {
+        OutboundAuthor::Assistant
+    } else {
+        OutboundAuthor::Owner
+    };
+
+    if config.log_outbound {
+        let transcript_dir = PathBuf::from(&data.transcript_dir);
+        if let Err(e) = outbound::append_conversation_log(&transcript_dir, msg, author) {
+            warn!("Failed to append to conversation log for {}: {}", data.session_name, e);
+        }
+    }
+
+    if config.inject_outbound && author == OutboundAuthor::Owner {
+        debug!("Noting direct reply in {}", data.session_name);
+        if let Err(e) = session_mgr.inject_text(&data.session_name, &outbound::outbound_note(msg)) {
+            error!("Failed to inject outbound note into {}: {}", data.session_name, e);
+        }
+    }
+}
+
+/// Inject recent chat history into a session that was just recreated
+fn inject_backfill(
+    config: &Config,
+    session_mgr: &SessionManager,
+    messages: &MessagesReader,
+    contacts: &mut dyn ContactSource,
+    session_name: &str,
+    chat_id: &str,
+) {
+    if let Some(context) = backfill_context(config, messages, contacts, chat_id) {
+        info!("Backfilling history into {}", session_name);
+        if let Err(e) = session_mgr.inject_text(session_name, &context) {
+            error!("Failed to inject history into {}: {}", session_name, e);
+        }
+    }
+}
+
+/// Recent history of a chat, ready to inject into a fresh session
+fn backfill_context(
+    config: &Config,
+    messages: &MessagesReader,
+    contacts: &mut dyn ContactSource,
+    chat_id: &str,
+) -> Option<String> {
+    if config.backfill_messages == 0 {
+        return None;
+    }
+    let history = match messages.get_recent_messages(chat_id, config.backfill_messages) {
+        Ok(history) => history,
+        Err(e) => {
+            warn!("Failed to load history for {}: {}", chat_id, e);
+            return None;
+        }
+    };
+    conversation_context(&history, |msg| {
+        if msg.is_from_me {
+            return "Me".to_string();
+        }
+        match contacts.lookup_identifier(&msg.sender) {
+            Ok(Some(contact)) if !contact.name.is_empty() => contact.name,
+            _ => msg.sender.clone(),
+        }
+    })
+}
+
+/// Split a poll batch by chat, keeping chats in order of first appearance
+fn group_by_chat(messages: Vec<Message>) -> Vec<(String, Vec<Message>)> {
+    let mut groups: Vec<(String, Vec<Message>)> = Vec::new();
+    for msg in messages {
+        match groups.iter_mut().find(|(chat_id, _)| *chat_id == msg.chat_id) {
+            Some((_, batch)) => batch.push(msg),
+            None => groups.push((msg.chat_id.clone(), vec![msg])),
+        }
+    }
+    groups
+}
+
+/// Names of a group's members, falling back to the raw handle for unknown contacts
+fn group_participants(
+    messages: &MessagesReader,
+    contacts: &mut dyn ContactSource,
+    chat_id: &str,
+) -> Option<Vec<String>> {
+    match messages.get_chat_participants(chat_id) {
+        Ok(handles) => Some(participant_names(contacts, &handles)),
+        Err(e) => {
+            warn!("Failed to read participants for {}: {}", chat_id, e);
+            None
+        }
+    }
+}
+
+fn participant_names(contacts: &mut dyn ContactSource, handles: &[String]) -> Vec<String> {
+    handles
+        .iter()
+        .map(|handle| match contacts.lookup_identifier(handle) {
+            Ok(Some(contact)) if !contact.name.is_empty() => contact.name,
+            _ => handle.clone(),
+        })
+        .collect()
+}
+
+/// Exit code when a name given on the command line matches more than one chat
+const EXIT_AMBIGUOUS: i32 = 6;
+
+/// Exit code when `inject-prompt --wait` gives up before Claude finishes
+const EXIT_TIMEOUT: i32 = 7;
+
+/// What a chat ID or name given on the command line refers to
+#[derive(Debug, PartialEq)]
+enum ChatMatch {
+    Unique(String),
+    /// Chat ID and description of each equally good match
+    Ambiguous(Vec<(String, String)>),
+    None,
+}
+
+/// Find the chat a command line argument refers to
+///
+/// Phone numbers, emails, and group IDs are used as they are. Anything else is
+/// searched for among contact names and aliases and the registry's contact and
+/// group names, keeping only the best kind of match: the whole name, then a
+/// first name, then any part of a name.
+fn find_chat(registry: &SessionRegistry, contacts: &mut dyn ContactSource, query: &str, region: &str) -> ChatMatch {
+    let is_name = matches!(Identifier::parse(query, region), Identifier::ShortCode(_))
+        && query.chars().any(char::is_alphabetic);
+    if !is_name {
+        return ChatMatch::Unique(normalize_chat_id(query, region));
+    }
+
+    // (rank, chat ID, description)
+    let mut candidates: Vec<(u8, String, String)> = Vec::new();
+    for data in registry.all().values().filter(|d| d.session_type != "background") {
+        let rank = [data.display_name.as_deref(), data.contact_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|name| name_match_rank(query, name))
+            .min();
+        if let Some(rank) = rank {
+            let name = data.display_name.as_ref().or(data.contact_name.as_ref()).unwrap_or(&data.session_name);
+            candidates.push((rank, data.chat_id.clone(), format!("{} (session {})", name, data.session_name)));
+        }
+    }
+    for contact in contacts.search(query).unwrap_or_default() {
+        let handles = [contact.phone.as_ref(), contact.email.as_ref()];
+        // A contact with a session is already a candidate under that chat
+        if handles.iter().flatten().any(|h| candidates.iter().any(|(_, chat_id, _)| chat_id == *h)) {
+            continue;
+        }
+        let rank = std::iter::once(contact.name.as_str())
+            .chain(contact.alias.as_deref())
+            .filter_map(|name| name_match_rank(query, name))
+            .min();
+        if let (Some(rank), Some(handle)) = (rank, handles.into_iter().flatten().next()) {
+            candidates.push((rank, handle.clone(), format!("{} ({})", contact.name, contact.tier)));
+        }
+    }
+
+    let Some(best) = candidates.iter().map(|(rank, _, _)| *rank).min() else {
+        return ChatMatch::None;
+    };
+    let mut best: Vec<(String, String)> = candidates
+        .into_iter()
+        .filter(|(rank, _, _)| *rank == best)
+        .map(|(_, chat_id, description)| (chat_id, description))
+        .collect();
+    best.sort();
+    if best.len() == 1 {
+        ChatMatch::Unique(best.remove(0).0)
+    } else {
+        ChatMatch::Ambiguous(best)
+    }
+}
+
+/// The chat a command line argument refers to, exiting if it could be more than one or none
+fn chat_for_arg(registry: &SessionRegistry, contacts: &mut dyn ContactSource, arg: &str, region: &str) -> String {
+    match find_chat(registry, contacts, arg, region) {
+        ChatMatch::Unique(chat_id) => chat_id,
+        ChatMatch::Ambiguous(candidates) => exit_ambiguous(arg, &candidates),
+        ChatMatch::None => {
+            eprintln!("Error: No contact or chat named {}", arg);
+            std::process::exit(5);
+        }
+    }
+}
+
+/// List the chats a name could mean and exit with `EXIT_AMBIGUOUS`
+fn exit_ambiguous(query: &str, candidates: &[(String, String)]) -> ! {
+    eprintln!("Error: {} matches more than one chat:", query);
+    for (chat_id, description) in candidates {
+        eprintln!("  {}  {}", chat_id, description);
+    }
+    std::process::exit(EXIT_AMBIGUOUS);
+}
+
+/// Session named on the command line: a running session as is, else the
+/// session of the chat the argument resolves to
+fn session_for_arg(config: &Config, session_mgr: &SessionManager, arg: &str) -> Result<String> {
+    if session_mgr.session_exists(arg) {
+        return Ok(arg.to_string());
+    }
+    let mut registry = SessionRegistry::new(config);
+    load_registry(config, &mut registry)?;
+    let mut contacts = ContactsManager::new(config);
+    Ok(match find_chat(&registry, &mut contacts, arg, &config.default_region) {
+        ChatMatch::Unique(chat_id) => resolve_inject_target(config, &registry, &mut contacts, &chat_id)
+            .map(|(session_name, _, _)| session_name)
+            .unwrap_or_else(|| arg.to_string()),
+        ChatMatch::Ambiguous(candidates) => exit_ambiguous(arg, &candidates),
+        ChatMatch::None => arg.to_string(),
+    })
+}
+
+/// Session, contact name, and tier for inject-prompt: registry first, then contacts
+fn resolve_inject_target(
+    config: &Config,
+    registry: &SessionRegistry,
+    contacts: &mut dyn ContactSource,
+    chat_id: &str,
+) -> Option<(String, String, String)> {
+    if let Some(data) = registry.get(chat_id) {
+        return Some((
+            data.session_name.clone(),
+            data.contact_name.clone().unwrap_or_else(|| data.session_name.replace('-', " ")),
+            data.tier.clone().unwrap_or_else(|| config.default_tier().to_string()),
+        ));
+    }
+
+    let contact = contacts.lookup_identifier(chat_id).ok()??;
+    let session_name = individual_session_name(registry, contacts, chat_id, &contact.name);
+    Some((session_name, contact.name, contact.tier))
+}
+
+/// Session name for a 1:1 chat
+///
+/// A registered chat keeps its session. Otherwise the name comes from the
+/// contact's alias, else their name; if a different person's chat already owns
+/// that, the last 4 digits of this chat's number (or its email's user name) are
+/// appended so the two conversations stay apart, plus a hash of the chat ID if
+/// even that is taken.
+fn individual_session_name(
+    registry: &SessionRegistry,
+    contacts: &mut dyn ContactSource,
+    chat_id: &str,
+    contact_name: &str,
+) -> String {
+    if let Some(data) = registry.get(chat_id) {
+        return data.session_name.clone();
+    }
+
+    let contact = contacts.lookup_identifier(chat_id).ok().flatten();
+    let base = SessionManager::session_name_for_contact(
+        contact.as_ref().and_then(|c| c.alias.as_deref()).unwrap_or(contact_name),
+    );
+    let Some(owner) = registry.session_owner(&base) else {
+        return base;
+    };
+    let same_person = match (&contact, contacts.lookup_identifier(owner).ok().flatten()) {
+        (Some(contact), Some(owner)) => contact.is_same_person(&owner),
+        _ => false,
+    };
+    if same_person {
+        return base;
+    }
+
+    let digits: String = chat_id.chars().filter(|c| c.is_ascii_digit()).collect();
+    let suffix = if !chat_id.contains('@') && digits.len() >= 4 {
+        digits[digits.len() - 4..].to_string()
+    } else {
+        let user = chat_id.split('@').next().unwrap_or(chat_id);
+        user.chars().filter(|c| c.is_ascii_alphanumeric()).take(8).collect::<String>().to_lowercase()
+    };
+    let name = registry.claim_session_name(&format!("{}-{}", base, suffix), chat_id);
+    info!("Session {} belongs to {}, using {} for {}", base, owner, name, chat_id);
+    name
+}
+
+/// Fetch the message being replied to and describe it for the wrapped prompt
+fn lookup_reply_context(messages: &MessagesReader, contacts: &mut dyn ContactSource, guid: &str) -> String {
+    let original = match messages.get_message_by_guid(guid) {
+        Ok(Some(msg)) => msg,
+        Ok(None) => return format_reply_context(None),
+        Err(e) => {
+            warn!("Failed to look up replied-to message {}: {}", guid, e);
+            return format_reply_context(None);
+        }
+    };
+
+    let name = if original.is_from_me {
+        "you".to_string()
+    } else {
+        match contacts.lookup_identifier(&original.sender) {
+            Ok(Some(contact)) if !contact.name.is_empty() => contact.name,
+            _ => original.sender.clone(),
+        }
+    };
+    format_reply_context(Some((&name, &original.body_text())))
+}
+
+/// "In reply to <name>: <snippet>", or a note when the original is gone (old or deleted)
+fn format_reply_context(original: Option<(&str, &str)>) -> String {
+    match original {
+        Some((name, text)) => {
+            let mut snippet: String = text.chars().take(200).collect();
+            if text.chars().count() > 200 {
+                snippet.push('\u{2026}');
+            }
+            format!("In reply to {}: {}", name, snippet)
+        }
+        None => "In reply to an earlier message that is no longer available".to_string(),
+    }
+}
+
+/// The group chat a wrapped message was written in
+struct GroupContext<'a> {
+    /// Display name, if the group has one
+    name: Option<&'a str>,
+    /// Members to list, for the first message of a new session
+    participants: Option<&'a [String]>,
+}
+
+/// What else the header of a wrapped message says; the default is a plain 1:1 text
+#[derive(Default)]
+struct SmsOptions<'a> {
+    service: MessageService,
+    subject: Option<&'a str>,
+    /// From `format_reply_context`
+    reply_context: Option<&'a str>,
+    /// The message mentions us
+    mentioned: bool,
+    group: Option<GroupContext<'a>>,
+}
+
+/// A message wrapped for Claude, from `sender` (a name, or the raw handle of an
+/// unknown participant) with `tier` being what let it through
+fn wrap_sms(prompt: &str, sender: &str, tier: &str, chat_id: &str, options: &SmsOptions) -> String {
+    let SmsOptions { service, subject, reply_context, mentioned, ref group } = *options;
+    let group = group.as_ref();
+    let from = match group {
+        Some(group) => format!("{} in {}", sender, group.name.unwrap_or("an unnamed group")),
+        None => sender.to_string(),
+    };
+    let members = group
+        .and_then(|group| group.participants)
+        .map(|participants| format!("\nGroup members: {}", participants.join(", ")))
+        .unwrap_or_default();
+    let subject = subject
+        .map(|subject| format!("\nSubject: {}", subject))
+        .unwrap_or_default();
+    let reply_context = reply_context
+        .map(|context| format!("\n{}", context))
+        .unwrap_or_default();
+    let mut via = match service {
+        MessageService::Unknown => String::new(),
+        known => format!(" via {}", known),
+    };
+    if mentioned {
+        via.push_str(" (you were mentioned)");
+    }
+
+    let footer = match group {
+        Some(_) => format!(
+            "You are in a group text session. Reply to the whole group with ~/code/sms-cli/send-sms to {}, not to {} alone",
+            chat_id, sender
+        ),
+        None => "You are in a text message session. Reply with ~/code/sms-cli/send-sms; who you're talking to is in session-info.json"
+            .to_string(),
+    };
+
+    format!(
+        r#"
+---SMS FROM {} ({}){}---
+Chat ID: {}{}{}{}
+{}
+---END SMS---
+**Important:** {}
+"#,
+        from, tier, via, chat_id, members, subject, reply_context, prompt, footer
+    )
+}
+
+/// Group message ignored because `group_mentions_only` is set and it doesn't mention us
+fn needs_mention(config: &Config, msg: &Message) -> bool {
+    msg.is_group && config.group_mentions_only && !msg.mentions_me
+}
+
+/// Message text followed by the list of staged attachments, if any
+fn compose_prompt(text: &str, staged: &[StagedAttachment]) -> String {
+    if staged.is_empty() {
+        return text.to_string();
+    }
+    let block = attachments::prompt_block(staged);
+    if text.is_empty() {
+        block
+    } else {
+        format!("{}\n{}", text, block)
+    }
+}
+
+/// Short note describing a tapback, injected in place of Apple's "Loved ..." text
+fn describe_tapback(contact_name: &str, kind: TapbackKind, removed: bool) -> String {
+    if removed {
+        format!("{} removed their {} reaction from an earlier message", contact_name, kind.emoji())
+    } else {
+        format!("{} reacted {} to your earlier message", contact_name, kind.emoji())
+    }
+}
+
+/// Note telling Claude to disregard an unsent message, if we ever injected the original
+fn retraction_note(recent: &RecentMessages, msg: &Message, contact_name: &str) -> Option<String> {
+    let original = recent.get(&msg.guid)?;
+    let snippet: String = original.text.chars().take(80).collect();
+    Some(format!(
+        "{} unsent a message (\"{}\") \u{2014} disregard it.",
+        contact_name, snippet
+    ))
+}
+
+fn wrap_admin(prompt: &str) -> String {
+    format!(
+        r#"
+---ADMIN OVERRIDE---
+From: Jane Doe (admin)
+{}
+---END ADMIN OVERRIDE---
+"#,
+        prompt
+    )
+}
+
+fn ensure_transcript_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    // Symlink .claude for skills
+    let claude_symlink = dir.join(".claude");
+    if !claude_symlink.exists() {
+        if let Some(home) = dirs::home_dir() {
+            let _ = symlink(home.join(".claude"), &claude_symlink);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claude_assistant_rs::contacts::{fake_contacts_cli, StaticContacts};
+    use claude_assistant_rs::notifier::RecordingNotifier;
+    use claude_assistant_rs::runner::{FakeTmux, FAKE_READY_PANE};
+
+    #[test]
+    fn test_normalize_chat_id_phone() {
+        assert_eq!(normalize_chat_id("+16175551234", "US"), "+16175551234");
+        assert_eq!(normalize_chat_id("6175551234", "US"), "+16175551234");
+        assert_eq!(normalize_chat_id("16175551234", "US"), "+16175551234");
+        assert_eq!(normalize_chat_id("617-555-1234", "US"), "+16175551234");
+    }
+
+    #[test]
+    fn test_normalize_chat_id_international() {
+        assert_eq!(normalize_chat_id("07911 123456", "GB"), "+447911123456");
+        assert_eq!(normalize_chat_id("0151 23456789", "DE"), "+4915123456789");
+        assert_eq!(normalize_chat_id("262966", "US"), "262966");
+    }
+
+    #[test]
+    fn test_normalize_chat_id_email() {
+        assert_eq!(normalize_chat_id("Jane.Doe@iCloud.com", "US"), "jane.doe@icloud.com");
+        assert_eq!(normalize_chat_id("jane@example.com", "US"), "jane@example.com");
+    }
+
+    /// ContactsManager backed by a stand-in contacts CLI
+    fn fake_contacts(dir: &Path, json: &str) -> ContactsManager {
+        let config = Config::for_test(dir);
+        fake_contacts_cli(&config, json);
+        ContactsManager::new(&config)
+    }
+
+    #[test]
+    fn test_resolve_inject_target_mixed_registry() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register(
+                "+16175551234",
+                "john-doe",
+                "/t/john-doe",
+                "individual",
+                Some("John Doe".to_string()),
+                None,
+                Some("admin".to_string()),
+                None,
+            )
+            .unwrap();
+        registry
+            .register(
+                "jane.doe@icloud.com",
+                "jane-doe",
+                "/t/jane-doe",
+                "individual",
+                Some("Jane Doe".to_string()),
+                None,
+                Some("wife".to_string()),
+                None,
+            )
+            .unwrap();
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "Sam Roe", "email": "sam@example.com", "tier": "family"}]"#,
+        );
+
+        let chat_id = normalize_chat_id("Jane.Doe@iCloud.com", "US");
+        let (session, name, tier) = resolve_inject_target(&config, &registry, &mut contacts, &chat_id).unwrap();
+        assert_eq!((session.as_str(), name.as_str(), tier.as_str()), ("jane-doe", "Jane Doe", "wife"));
+
+        let chat_id = normalize_chat_id("617-555-1234", "US");
+        let (session, _, _) = resolve_inject_target(&config, &registry, &mut contacts, &chat_id).unwrap();
+        assert_eq!(session, "john-doe");
+
+        // Not registered yet: falls back to contacts by email
+        let (session, name, tier) = resolve_inject_target(&config, &registry, &mut contacts, "sam@example.com").unwrap();
+        assert_eq!((session.as_str(), name.as_str(), tier.as_str()), ("sam-roe", "Sam Roe", "family"));
+
+        assert!(resolve_inject_target(&config, &registry, &mut contacts, "stranger@example.com").is_none());
+    }
+
+    #[test]
+    fn test_find_chat_by_name() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register(
+                "+16175551234",
+                "john-doe",
+                "/t/john-doe",
+                "individual",
+                Some("John Doe".to_string()),
+                None,
+                Some("admin".to_string()),
+                None,
+            )
+            .unwrap();
+        registry
+            .register(
+                "chat123456",
+                "group-ski-trip",
+                "/t/group-ski-trip",
+                "group",
+                None,
+                Some("Ski Trip".to_string()),
+                None,
+                Some(vec!["+16175551234".to_string()]),
+            )
+            .unwrap();
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[
+                {"name": "John Doe", "phone": "617-555-1234", "tier": "admin"},
+                {"name": "Jane Doe", "email": "jane@icloud.com", "tier": "wife"},
+                {"name": "Johnny Appleseed", "phone": "617-555-0000", "tier": "family"}
+            ]"#,
+        );
+
+        // Unique: a group's display name, a contact without a session, and a
+        // first name that beats a longer name containing it
+        assert_eq!(find_chat(&registry, &mut contacts, "ski trip", "US"), ChatMatch::Unique("chat123456".to_string()));
+        assert_eq!(find_chat(&registry, &mut contacts, "jane", "US"), ChatMatch::Unique("jane@icloud.com".to_string()));
+        assert_eq!(find_chat(&registry, &mut contacts, "John", "US"), ChatMatch::Unique("+16175551234".to_string()));
+
+        // Identifiers skip the search
+        assert_eq!(
+            find_chat(&registry, &mut contacts, "617-555-9999", "US"),
+            ChatMatch::Unique("+16175559999".to_string())
+        );
+
+        // Ambiguous: John has a session, Jane doesn't, and each appears once
+        assert_eq!(
+            find_chat(&registry, &mut contacts, "doe", "US"),
+            ChatMatch::Ambiguous(vec![
+                ("+16175551234".to_string(), "John Doe (session john-doe)".to_string()),
+                ("jane@icloud.com".to_string(), "Jane Doe (wife)".to_string()),
+            ])
+        );
+
+        assert_eq!(find_chat(&registry, &mut contacts, "nobody", "US"), ChatMatch::None);
+    }
+
+    #[test]
+    fn test_resolve_blessed_sender_email() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "Jane Doe", "email": "jane.doe@icloud.com", "tier": "wife"},
+                {"name": "Pat Poe", "email": "pat@example.com", "tier": "unknown"}]"#,
+        );
+        let msg = Message {
+            chat_id: "jane.doe@icloud.com".to_string(),
+            sender: "jane.doe@icloud.com".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_blessed_sender(&mut contacts, &msg),
+            Some(("Jane Doe".to_string(), "wife".to_string()))
+        );
+        let stranger = Message { chat_id: "pat@example.com".to_string(), ..msg };
+        assert_eq!(resolve_blessed_sender(&mut contacts, &stranger), None);
+    }
+
+    #[test]
+    fn test_individual_session_name_collision() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "Alex Chen", "phone": "+16175551234", "email": "alex@work.com", "tier": "family"},
+                {"name": "Alex Chen", "phone": "+14155552816", "tier": "favorite"},
+                {"name": "Alex Chen", "email": "a.chen@icloud.com", "tier": "favorite"}]"#,
+        );
+        let mut registry = SessionRegistry::new(&config);
+        assert_eq!(individual_session_name(&registry, &mut contacts, "+16175551234", "Alex Chen"), "alex-chen");
+        registry
+            .register("+16175551234", "alex-chen", "/t/alex-chen", "individual", Some("Alex Chen".to_string()), None, None, None)
+            .unwrap();
+
+        // A different Alex Chen doesn't get merged into the first one's session
+        assert_eq!(individual_session_name(&registry, &mut contacts, "+14155552816", "Alex Chen"), "alex-chen-2816");
+        assert_eq!(individual_session_name(&registry, &mut contacts, "a.chen@icloud.com", "Alex Chen"), "alex-chen-achen");
+        // The same person's other handle shares it
+        assert_eq!(individual_session_name(&registry, &mut contacts, "alex@work.com", "Alex Chen"), "alex-chen");
+
+        // Registered chats keep their session
+        registry
+            .register("+14155552816", "alex-chen-2816", "/t/alex-chen-2816", "individual", None, None, None, None)
+            .unwrap();
+        assert_eq!(individual_session_name(&registry, &mut contacts, "+14155552816", "Alex Chen"), "alex-chen-2816");
+        assert_eq!(registry.get_by_session_name("alex-chen").unwrap().chat_id, "+16175551234");
+
+        // Another number ending in the same digits can't share that one either
+        let name = individual_session_name(&registry, &mut contacts, "+12125552816", "Alex Chen");
+        assert!(name.starts_with("alex-chen-2816-"));
+        assert_eq!(name.len(), "alex-chen-2816-".len() + 6);
+    }
+
+    #[test]
+    fn test_individual_session_name_alias() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "Margaret O'Brien-Smith", "phone": "+16175551234", "alias": "Mom", "tier": "family"},
+                {"name": "Alex Chen", "phone": "+14155552816", "alias": "Alex Work", "tier": "favorite"},
+                {"name": "Sam Roe", "phone": "+16175559876", "tier": "family"}]"#,
+        );
+        let registry = SessionRegistry::new(&config);
+        assert_eq!(individual_session_name(&registry, &mut contacts, "+16175551234", "Margaret O'Brien-Smith"), "mom");
+        assert_eq!(individual_session_name(&registry, &mut contacts, "+16175559876", "Sam Roe"), "sam-roe");
+
+        // An alias in the overrides file beats the contacts source's
+        std::fs::create_dir_all(temp.path().join("state")).unwrap();
+        fs::write(&config.tier_overrides_file, r#"{"+14155552816": {"alias": "Alex C"}}"#).unwrap();
+        assert_eq!(individual_session_name(&registry, &mut contacts, "+14155552816", "Alex Chen"), "alex-c");
+    }
+
+    fn group_message(sender: &str) -> Message {
+        Message {
+            chat_id: "chat123456789".to_string(),
+            sender: sender.to_string(),
+            is_group: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_sender_blessed_group() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "Aunt May", "phone": "+16175551234", "tier": "family"},
+                {"name": "Cousin Eddie", "phone": "+16175559876", "tier": "unknown"}]"#,
+        );
+        let mut registry = SessionRegistry::new(&config);
+
+        // Unblessed group: only blessed senders get through
+        assert_eq!(
+            resolve_sender(&config, &mut contacts, &registry, &group_message("+16175551234")),
+            Some(("Aunt May".to_string(), "family".to_string()))
+        );
+        assert_eq!(resolve_sender(&config, &mut contacts, &registry, &group_message("+16175559876")), None);
+        assert_eq!(resolve_sender(&config, &mut contacts, &registry, &group_message("+16175550000")), None);
+
+        let mut groups = BlessedGroups::load(&config).unwrap();
+        assert!(groups.add("CHAT123456789"));
+        groups.save().unwrap();
+
+        // Blessed group: everyone, by contact name or raw handle
+        assert_eq!(
+            resolve_sender(&config, &mut contacts, &registry, &group_message("+16175551234")),
+            Some(("Aunt May".to_string(), "family".to_string()))
+        );
+        assert_eq!(
+            resolve_sender(&config, &mut contacts, &registry, &group_message("+16175559876")),
+            Some(("Cousin Eddie".to_string(), "favorite".to_string()))
+        );
+        assert_eq!(
+            resolve_sender(&config, &mut contacts, &registry, &group_message("+16175550000")),
+            Some(("+16175550000".to_string(), "favorite".to_string()))
+        );
+        // At the least trusted tier configured
+        let mut custom = config.clone();
+        custom.tiers.truncate(3);
+        assert_eq!(
+            resolve_sender(&custom, &mut contacts, &registry, &group_message("+16175550000")),
+            Some(("+16175550000".to_string(), "family".to_string()))
+        );
+
+        // An existing group session keeps its tier
+        registry
+            .register("chat123456789", "family-chat", "/t/family-chat", "group", None, None, Some("family".to_string()), None)
+            .unwrap();
+        assert_eq!(
+            resolve_sender(&config, &mut contacts, &registry, &group_message("+16175550000")),
+            Some(("+16175550000".to_string(), "family".to_string()))
+        );
+
+        // Only groups are blessed as a whole
+        let direct = Message { is_group: false, chat_id: "+16175550000".to_string(), ..group_message("+16175550000") };
+        assert_eq!(resolve_sender(&config, &mut contacts, &registry, &direct), None);
+    }
+
+    #[test]
+    fn test_blocked_sender_in_blessed_group() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = fake_contacts(temp.path(), "[]");
+        let quarantine = Quarantine::new(&config);
+        let mut groups = BlessedGroups::load(&config).unwrap();
+        groups.add("chat123456789");
+        groups.save().unwrap();
+        let mut blocklist = Blocklist::load(&config).unwrap();
+        blocklist.add("+16175550000");
+        blocklist.save().unwrap();
+
+        assert!(drop_blocked(&mut contacts, &quarantine, &group_message("+16175550000")));
+        assert!(!drop_blocked(&mut contacts, &quarantine, &group_message("+16175551111")));
+
+        // Unblessing goes back to sender-only handling
+        cmd_unbless_group(&config, "chat123456789").unwrap();
+        let registry = SessionRegistry::new(&config);
+        assert_eq!(resolve_sender(&config, &mut contacts, &registry, &group_message("+16175551111")), None);
+    }
+
+    #[test]
+    fn test_participant_names() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "John Doe", "phone": "+16175551234", "tier": "admin"},
+                {"name": "Jane Doe", "email": "jane.doe@icloud.com", "tier": "wife"}]"#,
+        );
+        let handles = vec![
+            "+16175551234".to_string(),
+            "+16175559876".to_string(),
+            "jane.doe@icloud.com".to_string(),
+        ];
+        assert_eq!(
+            participant_names(&mut contacts, &handles),
+            vec!["John Doe", "+16175559876", "Jane Doe"]
+        );
+    }
+
+    #[test]
+    fn test_group_by_chat() {
+        let msg = |rowid: i64, chat_id: &str| Message {
+            rowid,
+            chat_id: chat_id.to_string(),
+            ..Default::default()
+        };
+        let groups = group_by_chat(vec![msg(1, "a"), msg(2, "b"), msg(3, "a"), msg(4, "c")]);
+        let summary: Vec<(&str, Vec<i64>)> = groups
+            .iter()
+            .map(|(chat, batch)| (chat.as_str(), batch.iter().map(|m| m.rowid).collect()))
+            .collect();
+        assert_eq!(summary, vec![("a", vec![1, 3]), ("b", vec![2]), ("c", vec![4])]);
+    }
+
+    #[test]
+    fn test_normalize_chat_id_group() {
+        assert_eq!(
+            normalize_chat_id("ABC123DEF456789012345", "US"),
+            "abc123def456789012345"
+        );
+    }
+
+    #[test]
+    fn test_wrap_sms() {
+        let wrapped = wrap_sms("Hello", "John Doe", "admin", "+16175551234", &SmsOptions::default());
+        assert!(wrapped.contains("John Doe"));
+        assert!(wrapped.contains("admin"));
+        assert!(wrapped.contains("+16175551234"));
+        assert!(wrapped.contains("Hello"));
+        assert!(wrapped.contains("---SMS FROM John Doe (admin)---"));
+        assert!(wrapped.contains("session-info.json"));
+        assert_eq!(wrapped.matches("+16175551234").count(), 1);
+    }
+
+    #[test]
+    fn test_wrap_sms_service_header() {
+        let wrapped = wrap_sms(
+            "Hi",
+            "Jane Doe",
+            "family",
+            "+16175551234",
+            &SmsOptions { service: MessageService::Sms, ..Default::default() },
+        );
+        assert!(wrapped.contains("---SMS FROM Jane Doe (family) via SMS---"));
+        let wrapped = wrap_sms(
+            "Hi",
+            "Jane Doe",
+            "family",
+            "+16175551234",
+            &SmsOptions { service: MessageService::IMessage, ..Default::default() },
+        );
+        assert!(wrapped.contains("---SMS FROM Jane Doe (family) via iMessage---"));
+    }
+
+    #[test]
+    fn test_wrap_sms_subject() {
+        let wrapped = wrap_sms(
+            "see attached",
+            "Jane Doe",
+            "family",
+            "+16175551234",
+            &SmsOptions { service: MessageService::Sms, subject: Some("Quarterly report"), ..Default::default() },
+        );
+        assert!(wrapped.contains("Chat ID: +16175551234\nSubject: Quarterly report\nsee attached"));
+        let wrapped = wrap_sms(
+            "hi",
+            "Jane Doe",
+            "family",
+            "+16175551234",
+            &SmsOptions { service: MessageService::Sms, ..Default::default() },
+        );
+        assert!(!wrapped.contains("Subject:"));
+    }
+
+    #[test]
+    fn test_wrap_sms_group() {
+        let members = vec!["Jane Doe".to_string(), "John Doe".to_string(), "+16175559999".to_string()];
+        let group = GroupContext { name: Some("Book Club"), participants: Some(&members) };
+        let wrapped = wrap_sms(
+            "Who's hosting?",
+            "John Doe",
+            "family",
+            "chat123",
+            &SmsOptions { service: MessageService::IMessage, group: Some(group), ..Default::default() },
+        );
+        assert!(wrapped.contains("---SMS FROM John Doe in Book Club (family) via iMessage---"));
+        assert!(wrapped.contains("Chat ID: chat123\nGroup members: Jane Doe, John Doe, +16175559999\nWho's hosting?"));
+        // Replies go to the group, not whoever wrote this
+        assert!(wrapped.contains("send-sms to chat123, not to John Doe alone"));
+
+        // Members are only listed when the session is new
+        let group = GroupContext { name: Some("Book Club"), participants: None };
+        let wrapped = wrap_sms(
+            "hi",
+            "John Doe",
+            "family",
+            "chat123",
+            &SmsOptions { service: MessageService::IMessage, group: Some(group), ..Default::default() },
+        );
+        assert!(!wrapped.contains("Group members"));
+    }
+
+    /// A participant not in contacts shows up by handle
+    #[test]
+    fn test_wrap_sms_group_unknown_participant() {
+        let group = GroupContext { name: None, participants: None };
+        let wrapped = wrap_sms(
+            "hey all",
+            "+16175559999",
+            "family",
+            "chat123",
+            &SmsOptions { service: MessageService::Sms, group: Some(group), ..Default::default() },
+        );
+        assert!(wrapped.contains("---SMS FROM +16175559999 in an unnamed group (family) via SMS---"));
+        assert!(wrapped.contains("send-sms to chat123, not to +16175559999 alone"));
+    }
+
+    #[test]
+    fn test_wrap_sms_mentioned() {
+        let wrapped = wrap_sms(
+            "@Jane you in?",
+            "John Doe",
+            "family",
+            "chat123",
+            &SmsOptions { service: MessageService::IMessage, mentioned: true, ..Default::default() },
+        );
+        assert!(wrapped.contains("---SMS FROM John Doe (family) via iMessage (you were mentioned)---"));
+    }
+
+    #[test]
+    fn test_needs_mention() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        let mut msg = Message {
+            is_group: true,
+            ..Default::default()
+        };
+        assert!(!needs_mention(&config, &msg));
+
+        config.group_mentions_only = true;
+        assert!(needs_mention(&config, &msg));
+        msg.mentions_me = true;
+        assert!(!needs_mention(&config, &msg));
+
+        // 1:1 chats are never filtered
+        msg.is_group = false;
+        msg.mentions_me = false;
+        assert!(!needs_mention(&config, &msg));
+    }
+
+    #[test]
+    fn test_dry_run_flag() {
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "inject-prompt", "+1", "hi", "--dry-run"]).unwrap();
+        assert!(cli.dry_run);
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "--dry-run", "inject-prompt", "+1", "hi"]).unwrap();
+        assert!(cli.dry_run);
+        assert!(!Cli::try_parse_from(["claude-assistant-rs", "inject-prompt", "+1", "hi"]).unwrap().dry_run);
+    }
+
+    #[test]
+    fn test_inject_prompt_service_flag() {
+        let cli = Cli::try_parse_from([
+            "claude-assistant-rs",
+            "inject-prompt",
+            "+16175551234",
+            "hi",
+            "--sms",
+            "--service",
+            "rcs",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::InjectPrompt { service, .. } => assert_eq!(service, Some(MessageService::Rcs)),
+            _ => panic!("expected inject-prompt"),
+        }
+        assert!(Cli::try_parse_from(["claude-assistant-rs", "inject-prompt", "+1", "hi", "--service", "fax"]).is_err());
+
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "inject-prompt", "+1", "hi", "--wait", "--timeout", "30"]).unwrap();
+        assert!(matches!(cli.command, Commands::InjectPrompt { wait: true, timeout: 30, .. }));
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "inject-prompt", "+1", "hi"]).unwrap();
+        assert!(matches!(cli.command, Commands::InjectPrompt { wait: false, timeout: 120, .. }));
+        assert!(Cli::try_parse_from(["claude-assistant-rs", "inject-prompt", "+1", "hi", "--timeout", "30"]).is_err());
+    }
+
+    #[test]
+    fn test_logs_session_flag() {
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "logs", "--session", "jane-doe", "--no-follow"]).unwrap();
+        assert!(matches!(cli.command, Commands::Logs { session: Some(ref s), no_follow: true, .. } if s == "jane-doe"));
+    }
+
+    #[test]
+    fn test_kill_session_now_flag() {
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "kill-session", "jane-doe", "--now"]).unwrap();
+        assert!(matches!(cli.command, Commands::KillSession { now: true, .. }));
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "kill-session", "jane-doe"]).unwrap();
+        assert!(matches!(cli.command, Commands::KillSession { now: false, .. }));
+    }
+
+    #[test]
+    fn test_registry_verify() {
+        assert!(matches!(
+            Cli::try_parse_from(["claude-assistant-rs", "registry", "verify"]).unwrap().command,
+            Commands::Registry { action: RegistryAction::Verify }
+        ));
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register("+16175551234", "jane-doe", "/tmp/t", "individual", None, None, Some("family".to_string()), None)
+            .unwrap();
+        registry
+            .register("+16175555678", "john-doe", "/tmp/t", "individual", None, None, Some("family".to_string()), None)
+            .unwrap();
+        let (report, ok) = verify_registry(&registry);
+        assert!(ok);
+        assert!(report[0].ends_with("OK (2 sessions)"));
+        assert!(report[1].ends_with("OK (1 sessions)"));
+        assert!(report[2].ends_with("missing"));
+
+        // A corrupt backup is reported; a corrupt registry fails the check
+        fs::write(&registry.files()[1], "{").unwrap();
+        let (report, ok) = verify_registry(&registry);
+        assert!(ok && report[1].contains("invalid: "));
+        fs::write(&registry.files()[0], "").unwrap();
+        assert!(!verify_registry(&registry).1);
+    }
+
+    #[test]
+    fn test_registry_subcommands() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["claude-assistant-rs", "registry"], args].concat()).map(|cli| cli.command);
+        assert!(matches!(parse(&["list"]).unwrap(), Commands::Registry { action: RegistryAction::List }));
+        assert!(matches!(parse(&["rm", "+16175551234", "--yes"]).unwrap(), Commands::Registry { action: RegistryAction::Rm { yes: true, .. } }));
+        match parse(&["set", "chat123", "--display-name", "Book Club", "--tier", "family"]).unwrap() {
+            Commands::Registry { action: RegistryAction::Set { chat_id, tier, contact_name, display_name } } => {
+                assert_eq!(chat_id, "chat123");
+                assert_eq!(tier.as_deref(), Some("family"));
+                assert_eq!(contact_name, None);
+                assert_eq!(display_name.as_deref(), Some("Book Club"));
+            }
+            _ => panic!("expected registry set"),
+        }
+        // Nothing to set
+        assert!(parse(&["set", "chat123"]).is_err());
+        assert!(matches!(
+            parse(&["prune"]).unwrap(),
+            Commands::Registry { action: RegistryAction::Prune { days: 90, transcripts_gone: false } }
+        ));
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "registry", "prune", "--days", "30", "--dry-run"]).unwrap();
+        assert!(cli.dry_run);
+        assert!(matches!(cli.command, Commands::Registry { action: RegistryAction::Prune { days: 30, .. } }));
+    }
+
+    #[test]
+    fn test_health_test_patterns() {
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "health", "test-patterns", "pane.txt"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Health { action: Some(HealthAction::TestPatterns { file: Some(_), session: None, patterns: None }), .. }
+        ));
+        let args = ["claude-assistant-rs", "health", "test-patterns", "--session", "jane-doe", "--patterns", "p.toml"];
+        assert!(Cli::try_parse_from(args).is_ok());
+        // One or the other
+        assert!(Cli::try_parse_from(["claude-assistant-rs", "health", "test-patterns"]).is_err());
+        assert!(Cli::try_parse_from(["claude-assistant-rs", "health", "test-patterns", "pane.txt", "--session", "jane-doe"]).is_err());
+
+        let patterns = HealthPatterns::from_toml("[[fatal]]\nname = \"context_low\"\npattern = \"Context low\"\n").unwrap();
+        let pane = "⏺ panic: index out of range is the bug\n╭──╮\n│ > │\n╰──╯\nContext low (3% remaining)\n";
+        let report = patterns_report(&patterns, pane);
+        assert_eq!(report[1], format!("fatal  {:<24} ⏺ panic: index out of range is the bug", "panic"));
+        assert_eq!(report[2], format!("fatal  {:<24} Context low (3% remaining)", "context_low"));
+        // Running out of context is told apart from the fatal patterns
+        assert_eq!(report[3], "Health sweep: unhealthy (context_exhausted)");
+        assert_eq!(report[4], "Whole capture: unhealthy (fatal_error:panic)");
+        assert_eq!(patterns_report(&patterns, "all good")[1..], ["Nothing matched", "Health sweep: healthy", "Whole capture: healthy"]);
+    }
+
+    #[test]
+    fn test_health_history_command() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["claude-assistant-rs", "health"], args].concat()).map(|cli| cli.command);
+        assert!(matches!(parse(&[]).unwrap(), Commands::Health { session: None, json: false, action: None }));
+        match parse(&["--session", "pat-smith", "--json"]).unwrap() {
+            Commands::Health { session, json, action: None } => assert_eq!((session.as_deref(), json), (Some("pat-smith"), true)),
+            _ => panic!("expected health"),
+        }
+        assert!(parse(&["--json", "test-patterns", "pane.txt"]).is_err());
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let now = Utc::now();
+        let mut history = HealthHistory::new(&config);
+        let hours_ago = |h: i64| now - chrono::Duration::hours(h);
+        history.transition("pat-smith", hours_ago(30), HealthState::Unhealthy, Some("crashed".to_string()), Some(ActionTaken::Restart));
+        history.transition("pat-smith", hours_ago(29), HealthState::Healthy, None, None);
+        history.transition("pat-smith", hours_ago(3), HealthState::Unhealthy, Some("stuck".to_string()), Some(ActionTaken::Restart));
+        history.transition("pat-smith", hours_ago(2), HealthState::Restarting, None, Some(ActionTaken::ManualRestart));
+
+        let events: Vec<&HealthEvent> = history.events().collect();
+        let lines = history_lines(&events);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].ends_with("pat-smith  - -> unhealthy (crashed), restart"));
+        assert!(lines[1].ends_with("pat-smith  unhealthy -> healthy"));
+        assert!(lines[3].ends_with("pat-smith  unhealthy -> restarting, manual_restart"));
+
+        let mut registry = SessionRegistry::new(&config);
+        let pat = registry.register("+16175551111", "pat-smith", "/tmp/t", "individual", None, None, None, None).unwrap();
+        let al = registry.register("+16175552222", "al-admin", "/tmp/t", "individual", None, None, None, None).unwrap();
+        // Nothing to say about a session with no events
+        let summary = health_summary(&history, &[&pat, &al], now);
+        assert_eq!(summary, ["pat-smith  restarting for 2 hours, 2 events and 2 restarts in the last day"]);
+    }
+
+    #[test]
+    fn test_registry_list_show_set_rm() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register("+16175551234", "jane-doe", "/tmp/t", "individual", Some("Jane Doe".to_string()), None, Some("family".to_string()), None)
+            .unwrap();
+        registry.update_last_message("+16175551234").unwrap();
+        registry
+            .register("chat123", "book-club", "/tmp/t", "group", None, Some("Book Club".to_string()), None, None)
+            .unwrap();
+
+        let lines = registry_list(&registry);
+        assert_eq!(lines.len(), 2);
+        let jane: Vec<&str> = lines[0].split('\t').collect();
+        assert_eq!(jane[..4], ["+16175551234", "jane-doe", "family", "individual"]);
+        assert!(jane[4].parse::<DateTime<Utc>>().is_ok(), "{}", jane[4]);
+        assert_eq!(jane[5], "-");
+        assert_eq!(lines[1], "chat123\tbook-club\t-\tgroup\t-\t-");
+
+        assert_eq!(registry_entry(&registry, "chat123").unwrap().session_name, "book-club");
+        assert_eq!(registry_entry(&registry, "jane-doe").unwrap().chat_id, "+16175551234");
+        assert!(matches!(registry_entry(&registry, "nobody"), Err(Error::SessionNotFound(_))));
+
+        let changes = registry_set(&config, &mut registry, "chat123", Some("Family".to_string()), None, Some(String::new())).unwrap();
+        assert_eq!(changes, ["chat123: tier family", "chat123: display name cleared"]);
+        let changes = registry_set(&config, &mut registry, "chat123", Some("family".to_string()), None, None).unwrap();
+        assert_eq!(changes, ["chat123: unchanged"]);
+        assert!(registry_set(&config, &mut registry, "chat123", Some("vip".to_string()), None, None).is_err());
+        assert!(registry_set(&config, &mut registry, "chat999", None, Some("Nobody".to_string()), None).is_err());
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved["chat123"].tier.as_deref(), Some("family"));
+        assert_eq!(saved["chat123"].display_name, None);
+
+        cmd_registry(&config, RegistryAction::Rm { chat_id: "chat123".to_string(), yes: true }).unwrap();
+        assert!(cmd_registry(&config, RegistryAction::Rm { chat_id: "chat123".to_string(), yes: true }).is_err());
+        assert_eq!(read_registry(&config.registry_file).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_registry_export_import() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["claude-assistant-rs", "registry"], args].concat()).map(|cli| cli.command);
+        assert!(matches!(
+            parse(&["import", "sessions.export.json"]).unwrap(),
+            Commands::Registry { action: RegistryAction::Import { merge: false, replace: false, .. } }
+        ));
+        assert!(parse(&["import", "sessions.export.json", "--merge", "--replace"]).is_err());
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let old = Config::for_test(&temp.path().join("old"));
+        let mut registry = SessionRegistry::new(&old);
+        registry
+            .register("+16175551234", "jane-doe", "/tmp/t", "individual", None, None, Some("family".to_string()), None)
+            .unwrap();
+        let file = temp.path().join("export.json");
+        cmd_registry(&old, RegistryAction::Export { output: Some(file.clone()) }).unwrap();
+
+        let new = Config::for_test(&temp.path().join("new"));
+        cmd_registry(&new, RegistryAction::Import { file: file.clone(), merge: false, replace: true }).unwrap();
+        assert_eq!(read_registry(&new.registry_file).unwrap()["+16175551234"].session_name, "jane-doe");
+    }
+
+    #[test]
+    fn test_status_table() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut registry = SessionRegistry::new(&Config::for_test(temp.path()));
+        let mut register = |chat_id: &str, session_name: &str, tier: &str| {
+            registry
+                .register(chat_id, session_name, "/tmp/t", "individual", None, None, Some(tier.to_string()), None)
+                .unwrap()
+        };
+        let now = Utc::now();
+        let mut jane = register("+16175551234", "jane-doe", "family");
+        jane.counters.inbound = 9;
+        jane.counters.inbound_today = 3;
+        jane.counters.day = Some(now.with_timezone(&chrono::Local).date_naive());
+        jane.last_message_time = Some(now - chrono::Duration::hours(3));
+        jane.last_outbound_time = Some(now - chrono::Duration::minutes(5));
+        jane.counters.restarts = 2;
+        jane.counters.compactions = 1;
+        let mut group = register("chat123", "book-club-with-a-long-name", "favorite");
+        group.counters.inbound_today = 4;
+
+        let lines = status_table(&[&jane, &group], now);
+        assert_eq!(lines.len(), 3);
+        let row: Vec<&str> = lines[1].split("  ").map(str::trim).filter(|c| !c.is_empty()).collect();
+        assert_eq!(row, ["jane-doe", "family", "3", "3 hours ago", "5 minutes ago", "2", "1"]);
+        // Yesterday's count isn't today's
+        let row: Vec<&str> = lines[2].split_whitespace().collect();
+        assert_eq!(row, ["book-club-with-a-long-name", "favorite", "0", "-", "-", "0", "0"]);
+        assert!(lines[0].starts_with("SESSION "));
+    }
+
+    #[test]
+    fn test_status_json_flag() {
+        assert!(matches!(
+            Cli::try_parse_from(["claude-assistant-rs", "status", "--json"]).unwrap().command,
+            Commands::Status { json: true }
+        ));
+        assert!(matches!(Cli::try_parse_from(["claude-assistant-rs", "status"]).unwrap().command, Commands::Status { json: false }));
+    }
+
+    #[test]
+    fn test_tier_subcommands() {
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "tier", "set", "+16175551234", "family"]).unwrap();
+        match cli.command {
+            Commands::Tier { action: TierAction::Set { identifier, tier } } => {
+                assert_eq!(identifier, "+16175551234");
+                assert_eq!(tier, "family");
+            }
+            _ => panic!("expected tier set"),
+        }
+        assert!(matches!(
+            Cli::try_parse_from(["claude-assistant-rs", "tier", "list"]).unwrap().command,
+            Commands::Tier { action: TierAction::List }
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["claude-assistant-rs", "tier", "rm", "jane@example.com"]).unwrap().command,
+            Commands::Tier { action: TierAction::Rm { .. } }
+        ));
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        cmd_tier(&config, TierAction::Set { identifier: "617-555-1234".to_string(), tier: "Blocked".to_string() }).unwrap();
+        assert_eq!(TierOverrides::load(&config).unwrap().get("+16175551234"), Some("blocked"));
+        assert!(cmd_tier(&config, TierAction::Set { identifier: "x@y.com".to_string(), tier: "vip".to_string() }).is_err());
+    }
+
+    #[test]
+    fn test_no_backfill_flag() {
+        for sub in ["start", "run"] {
+            let cli = Cli::try_parse_from(["claude-assistant-rs", sub, "--no-backfill"]).unwrap();
+            match cli.command {
+                Commands::Start { no_backfill, .. } | Commands::Run { no_backfill, .. } => assert!(no_backfill),
+                _ => panic!("expected {}", sub),
+            }
+        }
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "start"]).unwrap();
+        assert!(matches!(cli.command, Commands::Start { no_backfill: false, no_merge: false }));
+    }
+
+    #[test]
+    fn test_no_merge_flag() {
+        for sub in ["start", "run"] {
+            let cli = Cli::try_parse_from(["claude-assistant-rs", sub, "--no-merge"]).unwrap();
+            match cli.command {
+                Commands::Start { no_merge, no_backfill } | Commands::Run { no_merge, no_backfill } => {
+                    assert!(no_merge && !no_backfill)
+                }
+                _ => panic!("expected {}", sub),
+            }
+        }
+    }
+
+    #[test]
+    fn test_restart_session_fresh_flag() {
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "restart-session", "jane-doe", "--fresh"]).unwrap();
+        assert!(matches!(cli.command, Commands::RestartSession { fresh: true, .. }));
+        let cli = Cli::try_parse_from(["claude-assistant-rs", "restart-session", "jane-doe"]).unwrap();
+        assert!(matches!(cli.command, Commands::RestartSession { fresh: false, .. }));
+    }
+
+    /// Only registered sessions on the default server need adopting
+    #[test]
+    fn test_legacy_sessions() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        // The default server has a registered session and one of the user's own
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+        fake.add_session("monitor", "");
+        let legacy = SessionManager::with_runner(&config, fake);
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register("+15555550100", "jane-doe", "/tmp/jane-doe", "individual", None, None, None, None)
+            .unwrap();
+
+        assert!(legacy_sessions(&config, &legacy, &registry).is_empty());
+        config.tmux_socket_name = "claude-assistant".to_string();
+        assert_eq!(legacy_sessions(&config, &legacy, &registry), vec!["jane-doe"]);
+    }
+
+    #[test]
+    fn test_bless_quarantined() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let fake = Arc::new(FakeTmux::new());
+        let session_mgr = SessionManager::with_runner(&config, fake.clone());
+        fake_contacts(temp.path(), r#"[{"name": "Sam Accountant", "phone": "+16175550000", "tier": "none"}]"#);
+
+        let quarantine = Quarantine::new(&config);
+        quarantine
+            .append(&Message {
+                rowid: 77,
+                text: "Your return is ready to sign".to_string(),
+                sender: "+16175550000".to_string(),
+                chat_id: "+16175550000".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // chat.db isn't there, so the saved preview is injected
+        let session = bless_quarantined(&config, &session_mgr, 77).unwrap();
+        assert_eq!(session.as_deref(), Some("sam-accountant"));
+        let log = tmux_log(&fake);
+        assert!(log.contains("send-keys -t sam-accountant -l --"));
+        assert!(log.contains("---SMS FROM Sam Accountant (unknown)---"));
+        assert!(log.contains("Your return is ready to sign"));
+
+        // Only once
+        assert!(quarantine.get(77).unwrap().is_none());
+        assert_eq!(bless_quarantined(&config, &session_mgr, 77).unwrap(), None);
+    }
+
+    #[test]
+    fn test_drop_blocked_sender() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "Cousin Eddie", "phone": "+16175551234", "tier": "family"}]"#,
+        );
+        let quarantine = Quarantine::new(&config);
+        let mut blocklist = Blocklist::load(&config).unwrap();
+        blocklist.add("Cousin Eddie");
+        blocklist.save().unwrap();
+
+        let msg = Message {
+            rowid: 5,
+            text: "FWD: FWD: you won't believe this".to_string(),
+            sender: "+16175551234".to_string(),
+            chat_id: "+16175551234".to_string(),
+            ..Default::default()
+        };
+        assert!(drop_blocked(&mut contacts, &quarantine, &msg));
+        let entry = quarantine.get(5).unwrap().unwrap();
+        assert!(entry.blocked);
+        assert!(format_quarantine_entry(&entry).contains("[blocked]"));
+    }
+
+    #[test]
+    fn test_blocked_group_member_only_drops_their_messages() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[
+                {"name": "Cousin Eddie", "phone": "+16175551234", "tier": "family"},
+                {"name": "Jane Doe", "phone": "+16175559876", "tier": "family"}
+            ]"#,
+        );
+        let quarantine = Quarantine::new(&config);
+        let mut blocklist = Blocklist::load(&config).unwrap();
+        blocklist.add("+16175551234");
+        blocklist.save().unwrap();
+
+        let group_msg = |rowid: i64, sender: &str| Message {
+            rowid,
+            text: "hi all".to_string(),
+            sender: sender.to_string(),
+            chat_id: "chat123456".to_string(),
+            is_group: true,
+            ..Default::default()
+        };
+        assert!(drop_blocked(&mut contacts, &quarantine, &group_msg(1, "+16175551234")));
+        assert!(!drop_blocked(&mut contacts, &quarantine, &group_msg(2, "+16175559876")));
+        assert_eq!(quarantine.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_blocked_chat_reminders_suppressed() {
+        use chrono::TimeZone;
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = fake_contacts(temp.path(), "[]");
+        let mut reminders = ReminderManager::new();
+        reminders.register("+16175551234", "REMINDER: * * * * * | Ping blocked");
+        reminders.register("+16175559876", "REMINDER: * * * * * | Ping allowed");
+        let mut blocklist = Blocklist::load(&config).unwrap();
+        blocklist.add("+16175551234");
+        blocklist.save().unwrap();
+
+        let now = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        let due = due_reminders(&mut reminders, &mut contacts, now);
+        assert_eq!(due, vec![("+16175559876".to_string(), "Ping allowed".to_string())]);
+    }
+
+    #[test]
+    fn test_format_quarantine_entry() {
+        let entry = QuarantineEntry {
+            timestamp: Utc::now(),
+            rowid: 42,
+            sender: "+16175550000".to_string(),
+            chat_id: "chat123".to_string(),
+            preview: "Claim your prize".to_string(),
+            is_spam: true,
+            blocked: false,
+        };
+        let line = format_quarantine_entry(&entry);
+        assert!(line.trim_start().starts_with("42  "));
+        assert!(line.ends_with("+16175550000 in chat123 [spam]: Claim your prize"));
+    }
+
+    #[test]
+    fn test_wrap_voice_message() {
+        let msg = Message {
+            text: "\u{fffc}".to_string(),
+            is_audio_message: true,
+            audio_transcription: Some("Running late, start without me".to_string()),
+            ..Default::default()
+        };
+        let staged = vec![StagedAttachment {
+            name: "Audio Message.caf".to_string(),
+            mime_type: "audio/x-caf".to_string(),
+            size: 2048,
+            local_path: Some(PathBuf::from("/t/attachments/12-Audio Message.caf")),
+        }];
+
+        let prompt = compose_prompt(&msg.body_text(), &staged);
+        let wrapped = wrap_sms(&prompt, "John Doe", "admin", "+16175551234", &SmsOptions::default());
+        assert!(wrapped.contains("Voice message (transcribed): Running late, start without me\nAttachments:"));
+        assert!(wrapped.contains("/t/attachments/12-Audio Message.caf (audio/x-caf, 2.0 KB)"));
+
+        let untranscribed = Message { audio_transcription: None, ..msg };
+        let wrapped = wrap_sms(
+            &compose_prompt(&untranscribed.body_text(), &staged),
+            "John Doe",
+            "admin",
+            "+16175551234",
+            &SmsOptions::default(),
+        );
+        assert!(wrapped.contains("Voice message received (no transcription available)"));
+        assert!(wrapped.contains("12-Audio Message.caf"));
+    }
+
+    #[test]
+    fn test_compose_prompt_attachments_only() {
+        let staged = vec![StagedAttachment {
+            name: "IMG_1.jpeg".to_string(),
+            mime_type: "image/jpeg".to_string(),
+            size: 10,
+            local_path: None,
+        }];
+        assert_eq!(compose_prompt("hi", &[]), "hi");
+        assert!(compose_prompt("", &staged).starts_with("Attachments:"));
+    }
+
+    #[test]
+    fn test_format_reply_context() {
+        assert_eq!(
+            format_reply_context(Some(("Jane Doe", "what time is the game?"))),
+            "In reply to Jane Doe: what time is the game?"
+        );
+
+        let long = "x".repeat(250);
+        let context = format_reply_context(Some(("you", &long)));
+        assert_eq!(context, format!("In reply to you: {}\u{2026}", "x".repeat(200)));
+
+        assert_eq!(
+            format_reply_context(None),
+            "In reply to an earlier message that is no longer available"
+        );
+    }
+
+    #[test]
+    fn test_wrap_sms_with_reply_context() {
+        // Daemon path: thread_originator_guid resolved to the original message
+        let context = format_reply_context(Some(("Jane Doe", "want to get dinner?")));
+        let wrapped = wrap_sms(
+            "yes!",
+            "John Doe",
+            "admin",
+            "+16175551234",
+            &SmsOptions { service: MessageService::IMessage, reply_context: Some(&context), ..Default::default() },
+        );
+        assert!(wrapped.contains("Chat ID: +16175551234\nIn reply to Jane Doe: want to get dinner?\nyes!"));
+        assert!(!wrapped.contains("not yet implemented"));
+
+        // CLI path: --reply-to GUID that no longer exists in chat.db
+        let temp = tempfile::TempDir::new().unwrap();
+        let reader = MessagesReader::new(&Config::for_test(temp.path()));
+        let mut contacts = fake_contacts(temp.path(), "[]");
+        let context = lookup_reply_context(&reader, &mut contacts, "MISSING-GUID");
+        let wrapped = wrap_sms(
+            "ok",
+            "John Doe",
+            "admin",
+            "+16175551234",
+            &SmsOptions { service: MessageService::IMessage, reply_context: Some(&context), ..Default::default() },
+        );
+        assert!(wrapped.contains("In reply to an earlier message that is no longer available\nok"));
+    }
+
+    #[test]
+    fn test_unreplied_nudge() {
+        use chrono::TimeZone;
+        let now = Utc.with_ymd_and_hms(2026, 3, 1, 21, 0, 0).unwrap();
+        let mut msg = Message {
+            text: "are we still on for tomorrow?".to_string(),
+            timestamp: Utc.with_ymd_and_hms(2026, 3, 1, 17, 30, 0).unwrap(),
+            date_read: Some(Utc.with_ymd_and_hms(2026, 3, 1, 18, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            unreplied_nudge("Jane Doe", &msg, now),
+            "[Reminder: you read Jane Doe's message 3 hours ago and haven't replied: \"are we still on for tomorrow?\"]"
+        );
+
+        msg.date_read = None;
+        assert_eq!(
+            unreplied_nudge("Jane Doe", &msg, now),
+            "[Reminder: Jane Doe messaged 3 hours ago and hasn't had a reply: \"are we still on for tomorrow?\"]"
+        );
+    }
+
+    #[test]
+    fn test_format_ago() {
+        assert_eq!(format_ago(chrono::Duration::seconds(20)), "1 minute");
+        assert_eq!(format_ago(chrono::Duration::minutes(45)), "45 minutes");
+        assert_eq!(format_ago(chrono::Duration::minutes(61)), "1 hour");
+        assert_eq!(format_ago(chrono::Duration::hours(30)), "30 hours");
+        assert_eq!(format_ago(chrono::Duration::days(3)), "3 days");
     }
-}
 
-// ============================================================================
-// Helper Functions
-// ============================================================================
+    #[test]
+    fn test_group_event_note() {
+        let name_for = |handle: &str| if handle == "+16175550000" { "Bob".to_string() } else { handle.to_string() };
+        assert_eq!(
+            group_event_note("Jane", &GroupEvent::Renamed { name: Some("Ski Trip 2025".to_string()) }, name_for),
+            "Group renamed to 'Ski Trip 2025'"
+        );
+        assert_eq!(
+            group_event_note(
+                "Jane",
+                &GroupEvent::ParticipantAdded {
+                    handle: "+16175550000".to_string()
+                },
+                name_for
+            ),
+            "Bob was added"
+        );
+        assert_eq!(
+            group_event_note(
+                "Jane",
+                &GroupEvent::ParticipantRemoved {
+                    handle: "+16175559999".to_string()
+                },
+                name_for
+            ),
+            "+16175559999 was removed"
+        );
+        assert_eq!(group_event_note("Jane", &GroupEvent::Left, name_for), "Jane left the group");
+        assert_eq!(group_event_note("You", &GroupEvent::Renamed { name: None }, name_for), "Group name removed");
+    }
+
+    #[test]
+    fn test_group_rename_keeps_session_name() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register(
+                "chat123456789",
+                "group-ski_trip",
+                "/t/group-ski_trip",
+                "group",
+                None,
+                Some("Ski Trip".to_string()),
+                Some("family".to_string()),
+                None,
+            )
+            .unwrap();
+        let mut contacts = fake_contacts(
+            temp.path(),
+            r#"[{"name": "Jane Doe", "phone": "+16175551234", "tier": "family"}]"#,
+        );
+        let msg = Message {
+            chat_id: "chat123456789".to_string(),
+            sender: "+16175551234".to_string(),
+            is_group: true,
+            kind: MessageKind::GroupEvent {
+                event: GroupEvent::Renamed {
+                    name: Some("Ski Trip 2025".to_string()),
+                },
+            },
+            ..Default::default()
+        };
+        let MessageKind::GroupEvent { event } = &msg.kind else { unreachable!() };
+
+        // No tmux here, so the injection fails; the registry is still updated
+        handle_group_event(
+            &SessionManager::new(&config),
+            &mut registry,
+            &MessagesReader::new(&config),
+            &mut contacts,
+            &msg,
+            event,
+        );
 
-fn normalize_chat_id(chat_id: &str) -> String {
-    // Check if it looks like a group UUID (20+ hex chars)
-    if chat_id.len() >= 20 && chat_id.chars().all(|c| c.is_ascii_hexdigit()) {
-        return chat_id.to_lowercase();
+        let data = registry.get("chat123456789").unwrap();
+        assert_eq!(data.display_name.as_deref(), Some("Ski Trip 2025"));
+        assert_eq!(data.session_name, "group-ski_trip");
     }
 
-    // Phone number - normalize to E.164
-    let digits: String = chat_id
-        .chars()
-        .filter(|c| c.is_ascii_digit() || *c == '+')
-        .collect();
+    #[test]
+    fn test_describe_tapback() {
+        assert_eq!(
+            describe_tapback("John", TapbackKind::Love, false),
+            "John reacted \u{2764}\u{fe0f} to your earlier message"
+        );
+        let removed = describe_tapback("John", TapbackKind::Like, true);
+        assert!(removed.contains("removed"));
+        assert!(removed.contains(TapbackKind::Like.emoji()));
+    }
 
-    if digits.starts_with('+') {
-        digits
-    } else if digits.len() == 10 {
-        format!("+1{}", digits)
-    } else if digits.len() == 11 && digits.starts_with('1') {
-        format!("+{}", digits)
-    } else {
-        format!("+{}", digits)
+    fn retracted(guid: &str) -> Message {
+        Message {
+            rowid: 7,
+            guid: guid.to_string(),
+            chat_id: "+16175551234".to_string(),
+            kind: MessageKind::Retracted,
+            ..Default::default()
+        }
     }
-}
 
-fn wrap_sms(
-    prompt: &str,
-    contact_name: &str,
-    tier: &str,
-    chat_id: &str,
-    reply_to: Option<&str>,
-) -> String {
-    // TODO: Add reply chain context when reply_to is provided
-    let reply_context = if reply_to.is_some() {
-        "\n[Reply context not yet implemented in Rust version]"
-    } else {
-        ""
-    };
+    #[test]
+    fn test_retraction_after_injection() {
+        let mut recent = RecentMessages::new(10);
+        let original = Message {
+            rowid: 7,
+            guid: "G-1".to_string(),
+            chat_id: "+16175551234".to_string(),
+            text: "meet at 5".to_string(),
+            ..Default::default()
+        };
+        recent.record(&original, &original.text);
+
+        let note = retraction_note(&recent, &retracted("G-1"), "Jane").unwrap();
+        assert!(note.starts_with("Jane unsent a message"));
+        assert!(note.contains("meet at 5"));
+        assert!(note.contains("disregard"));
+    }
 
-    format!(
-        r#"
----SMS FROM {} ({})---
-Chat ID: {}{}
-{}
----END SMS---
-**Important:** You are in a text message session. Communicate back to the user with ~/code/sms-cli/send-sms "{}" "message"
-"#,
-        contact_name, tier, chat_id, reply_context, prompt, chat_id
-    )
-}
+    #[test]
+    fn test_retraction_before_injection() {
+        // Unsent before the daemon saw it: nothing to retract
+        let recent = RecentMessages::new(10);
+        assert!(retraction_note(&recent, &retracted("G-2"), "Jane").is_none());
+    }
 
-fn wrap_admin(prompt: &str) -> String {
-    format!(
-        r#"
----ADMIN OVERRIDE---
-From: Jane Doe (admin)
-{}
----END ADMIN OVERRIDE---
-"#,
-        prompt
-    )
-}
+    /// A contact with just a name, a phone number or email, and a tier
+    fn contact(name: &str, handle: &str, tier: &str) -> Contact {
+        let is_email = handle.contains('@');
+        Contact {
+            name: name.to_string(),
+            phone: (!is_email).then(|| handle.to_string()),
+            email: is_email.then(|| handle.to_string()),
+            tier: tier.to_string(),
+            notes: None,
+            system_prompt: None,
+            allowed_tools: None,
+            alias: None,
+            workdir: None,
+            id: None,
+        }
+    }
 
-fn ensure_transcript_dir(dir: &Path) -> Result<()> {
-    fs::create_dir_all(dir)?;
+    /// chat.db with the tables the reader queries and one 1:1 chat per handle
+    ///
+    /// Chat n is with the nth handle, so both share a ROWID.
+    struct ChatDb {
+        conn: rusqlite::Connection,
+    }
 
-    // Symlink .claude for skills
-    let claude_symlink = dir.join(".claude");
-    if !claude_symlink.exists() {
-        if let Some(home) = dirs::home_dir() {
-            let _ = symlink(home.join(".claude"), &claude_symlink);
+    impl ChatDb {
+        fn create(path: &Path, handles: &[&str]) -> Self {
+            let conn = rusqlite::Connection::open(path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE handle (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, id TEXT, service TEXT);
+                 CREATE TABLE chat (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, chat_identifier TEXT, style INTEGER, display_name TEXT);
+                 CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+                 CREATE TABLE chat_handle_join (chat_id INTEGER, handle_id INTEGER);
+                 CREATE TABLE message (
+                     ROWID INTEGER PRIMARY KEY AUTOINCREMENT, guid TEXT, text TEXT, handle_id INTEGER DEFAULT 0,
+                     date INTEGER DEFAULT 0, attributedBody BLOB, cache_has_attachments INTEGER DEFAULT 0,
+                     is_audio_message INTEGER DEFAULT 0, is_from_me INTEGER DEFAULT 0, thread_originator_guid TEXT,
+                     associated_message_type INTEGER DEFAULT 0, associated_message_guid TEXT, date_edited INTEGER DEFAULT 0,
+                     message_summary_info BLOB, date_retracted INTEGER DEFAULT 0, balloon_bundle_id TEXT, service TEXT,
+                     item_type INTEGER DEFAULT 0, group_action_type INTEGER DEFAULT 0, group_title TEXT,
+                     other_handle INTEGER DEFAULT 0, date_delivered INTEGER DEFAULT 0, date_read INTEGER DEFAULT 0,
+                     subject TEXT, is_spam INTEGER DEFAULT 0
+                 );
+                 CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, filename TEXT, mime_type TEXT, transfer_name TEXT, total_bytes INTEGER);
+                 CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);",
+            )
+            .unwrap();
+            for handle in handles {
+                conn.execute("INSERT INTO handle (id, service) VALUES (?1, 'iMessage')", [handle]).unwrap();
+                conn.execute("INSERT INTO chat (chat_identifier, style) VALUES (?1, 45)", [handle]).unwrap();
+                conn.execute(
+                    "INSERT INTO chat_handle_join (chat_id, handle_id) VALUES (last_insert_rowid(), last_insert_rowid())",
+                    [],
+                )
+                .unwrap();
+            }
+            Self { conn }
+        }
+
+        /// A message from the other person in `chat`, returning its ROWID
+        fn insert_message(&self, chat: i64, guid: &str, text: &str) -> i64 {
+            self.insert_row(chat, guid, text, false, 1)
+        }
+
+        /// A message sent from this Mac in `chat`, returning its ROWID
+        fn insert_sent(&self, chat: i64, guid: &str, text: &str) -> i64 {
+            self.insert_row(chat, guid, text, true, 1)
+        }
+
+        /// `date` is in nanoseconds after 2001-01-01, the chat.db epoch
+        fn insert_row(&self, chat: i64, guid: &str, text: &str, from_me: bool, date: i64) -> i64 {
+            self.conn
+                .execute(
+                    "INSERT INTO message (guid, text, handle_id, date, is_from_me) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![guid, text, chat, date, from_me],
+                )
+                .unwrap();
+            let rowid = self.conn.last_insert_rowid();
+            self.conn
+                .execute("INSERT INTO chat_message_join (chat_id, message_id) VALUES (?1, ?2)", [chat, rowid])
+                .unwrap();
+            rowid
         }
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_daemon_routes_blessed_and_quarantines_unknown() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        // Chat 1 is Jane, chat 2 a stranger, chat 3 a sender ID
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234", "+16175550000", "AMAZON"]);
+        let contacts = StaticContacts::new(
+            &config,
+            vec![Contact {
+                system_prompt: Some("Jane prefers short answers.".to_string()),
+                ..contact("Jane Doe", "+16175551234", "family")
+            }],
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Already there at startup, so not replayed
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        chat_db.insert_message(1, "G-1", "dinner at 7?");
+        chat_db.insert_message(2, "G-2", "Your package is waiting");
+        chat_db.insert_message(3, "G-3", "Your order has shipped");
+        daemon.poll().unwrap();
+
+        let session = SessionManager::session_name_for_contact("Jane Doe");
+        let data = daemon.registry.get("+16175551234").unwrap();
+        assert_eq!(data.session_name, session);
+        assert_eq!(data.tier.as_deref(), Some("family"));
+        assert!(daemon.registry.get("+16175550000").is_none());
+
+        let log = tmux_log(&fake);
+        let created: Vec<&str> = log.lines().filter(|l| l.starts_with("new-session")).collect();
+        assert_eq!(created.len(), 1);
+        assert!(created[0].contains(&format!("-s {} ", session)));
+        // The tier and contact prompts are joined by a blank line, so this spans log lines
+        assert!(log.contains("Jane prefers short answers."));
+        assert!(log.contains(&format!("send-keys -t {} -l", session)));
+        assert!(log.contains("dinner at 7?"));
+        assert!(!log.contains("Your package is waiting"));
+        assert!(!log.contains("old news"));
+        assert!(!log.contains("Your order has shipped"));
+
+        let quarantined = daemon.quarantine.entries().unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].sender, "+16175550000");
+
+        // Both messages are done with; a second poll does nothing
+        daemon.poll().unwrap();
+        let again = tmux_log(&fake);
+        assert_eq!(again.lines().filter(|l| l.starts_with("send-keys -t") && l.contains(" -l ")).count(), 1);
+    }
 
+    /// Two contacts whose names make the same session name, and whom the
+    /// contacts source can't tell apart, still get a session each
     #[test]
-    fn test_normalize_chat_id_phone() {
-        assert_eq!(normalize_chat_id("+16175551234"), "+16175551234");
-        assert_eq!(normalize_chat_id("6175551234"), "+16175551234");
-        assert_eq!(normalize_chat_id("16175551234"), "+16175551234");
-        assert_eq!(normalize_chat_id("617-555-1234"), "+16175551234");
+    fn test_daemon_colliding_names_get_own_sessions() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234", "+16175550000"]);
+        let duplicate = |name: &str, phone: &str| Contact { id: Some("duplicated-card".to_string()), ..contact(name, phone, "family") };
+        let contacts = StaticContacts::new(&config, vec![duplicate("Jane Doe", "+16175551234"), duplicate("JANE DOE", "+16175550000")]);
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        chat_db.insert_message(1, "G-1", "from the first jane");
+        daemon.poll().unwrap();
+        chat_db.insert_message(2, "G-2", "from the second jane");
+        daemon.poll().unwrap();
+
+        let first = daemon.registry.get("+16175551234").unwrap().session_name.clone();
+        let second = daemon.registry.get("+16175550000").unwrap().session_name.clone();
+        assert_eq!(first, "jane-doe");
+        assert_ne!(second, first);
+        assert!(second.starts_with("jane-doe-"));
+
+        let log = tmux_log(&fake);
+        // The wrapped message spans log lines, so split on the command instead
+        let typed_into = |text: &str| log.split("send-keys -t ").find(|keys| keys.contains(text)).unwrap().to_string();
+        assert!(typed_into("from the first jane").starts_with(&format!("{} -l", first)));
+        assert!(typed_into("from the second jane").starts_with(&format!("{} -l", second)));
     }
 
     #[test]
-    fn test_normalize_chat_id_group() {
+    fn test_background_task() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        let text = MessageKind::Text;
+
+        assert_eq!(background_task(&config, &text, "bg: find flights to Denver"), Some("find flights to Denver"));
+        assert_eq!(background_task(&config, &text, "  TASK:summarize the thread "), Some("summarize the thread"));
+        assert_eq!(background_task(&config, &text, "bg:   "), None);
+        assert_eq!(background_task(&config, &text, "big: news"), None);
+        assert_eq!(background_task(&config, &text, "no bg: prefix here"), None);
+        assert_eq!(background_task(&config, &MessageKind::Retracted, "bg: gone"), None);
+
+        config.background_prefixes = vec!["later:".to_string(), String::new()];
+        assert_eq!(background_task(&config, &text, "bg: find flights"), None);
+        assert_eq!(background_task(&config, &text, "Later: find flights"), Some("find flights"));
+    }
+
+    #[test]
+    fn test_stop_command() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        let text = MessageKind::Text;
+
+        assert_eq!(stop_command(&config, &text, "!stop"), Some(""));
+        assert_eq!(stop_command(&config, &text, "  !STOP  "), Some(""));
+        assert_eq!(stop_command(&config, &text, "!stop then just book the 6pm"), Some("then just book the 6pm"));
+        assert_eq!(stop_command(&config, &text, "!stopwatch broke"), None);
+        assert_eq!(stop_command(&config, &text, "please !stop"), None);
+        assert_eq!(stop_command(&config, &MessageKind::Retracted, "!stop"), None);
+
+        assert!(is_top_tier(&config, "admin"));
+        assert!(!is_top_tier(&config, "family"));
+
+        config.stop_command = String::new();
+        assert_eq!(stop_command(&config, &text, "!stop"), None);
+    }
+
+    /// Only the top tier can interrupt a session by SMS; anyone else's "!stop" is just a message
+    #[test]
+    fn test_daemon_stop_command_admin_only() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175550001", "+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Al Admin", "+16175550001", "admin"), contact("Jane Doe", "+16175551234", "family")]);
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        chat_db.insert_message(1, "G-1", "look up flights");
+        chat_db.insert_message(2, "G-2", "what's for dinner");
+        daemon.poll().unwrap();
+        chat_db.insert_message(1, "G-3", "!stop then just book the 6pm");
+        chat_db.insert_message(2, "G-4", "!stop");
+        daemon.poll().unwrap();
+
+        let log = tmux_log(&fake);
+        assert!(log.contains("send-keys -t al-admin Escape"));
+        assert!(log.contains("send-keys -t al-admin -l -- then just book the 6pm"));
+        assert!(!log.contains("!stop then"));
+        assert!(!log.contains("send-keys -t jane-doe Escape"));
+        assert_eq!(log.matches("send-keys -t jane-doe -l").count(), 2);
+        assert!(log.contains("!stop"));
+    }
+
+    #[test]
+    fn test_daemon_routes_background_task() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        chat_db.insert_message(1, "G-1", "BG: find flights to Denver");
+        daemon.poll().unwrap();
+
+        let session = SessionManager::session_name_for_contact("Jane Doe");
+        let bg = format!("{}-bg", session);
+        let data = daemon.registry.get_background("+16175551234").unwrap();
+        assert_eq!((data.session_name.as_str(), data.session_type.as_str()), (bg.as_str(), "background"));
+        assert_eq!(data.tier.as_deref(), Some("family"));
+        // The conversation's session isn't needed for a task
+        assert!(daemon.registry.get("+16175551234").is_none());
+
+        let log = tmux_log(&fake);
+        assert!(log.contains(&format!("new-session -d -s {} ", bg)));
+        assert!(log.contains(&format!("send-keys -t {} -l", bg)));
+        assert!(log.contains("find flights to Denver"));
+        assert!(!log.contains("BG:"));
+
+        // Anything else still goes to the conversation
+        chat_db.insert_message(1, "G-2", "dinner at 7?");
+        daemon.poll().unwrap();
+        assert_eq!(daemon.registry.get("+16175551234").unwrap().session_name, session);
+        assert!(tmux_log(&fake).contains(&format!("send-keys -t {} -l", session)));
+    }
+
+    #[test]
+    fn test_daemon_queues_while_busy() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        let fake = Arc::new(FakeTmux::new());
+        let injections = || fake.calls_to("send-keys").iter().filter(|call| call.contains(&"-l".to_string())).count();
+        chat_db.insert_message(1, "G-0", "old news");
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        chat_db.insert_message(1, "G-1", "first");
+        daemon.poll().unwrap();
+        assert_eq!(injections(), 1);
+
+        // Claude is still working on the first
+        fake.set_pane("jane-doe", &format!("{}esc to interrupt\n", FAKE_READY_PANE));
+        chat_db.insert_message(1, "G-2", "second");
+        chat_db.insert_message(1, "G-3", "third");
+        daemon.poll().unwrap();
+        daemon.flush_queue(std::time::Instant::now());
+        assert_eq!(injections(), 1);
+        assert_eq!(daemon.queue.len(), 2);
+
+        // Both go in together once it's done
+        fake.set_pane("jane-doe", FAKE_READY_PANE);
+        daemon.flush_queue(std::time::Instant::now());
+        assert_eq!(injections(), 2);
+        let log = tmux_log(&fake);
+        assert!(log.find("second").unwrap() < log.find("third").unwrap());
+        assert!(daemon.queue.is_empty());
+    }
+
+    #[test]
+    fn test_daemon_pauses_session_at_usage_limit() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let texts = record_texts(&mut daemon);
+        let notices = || texts.sent().len();
+
+        chat_db.insert_message(1, "G-1", "first");
+        daemon.poll().unwrap();
+        let limited = format!("> first\n  ⎿ You've reached your usage limit, resets at 3pm\n{}", FAKE_READY_PANE);
+        fake.set_pane("jane-doe", &limited);
+
+        // Paused rather than restarted, and Jane is told why
+        daemon.check_health(std::time::Instant::now());
+        assert!(daemon.is_paused("jane-doe"));
+        assert!(fake.calls_to("kill-session").is_empty());
+        let notice = &texts.sent_to("+16175551234")[0];
+        assert!(notice.starts_with("Claude Assistant is temporarily out of capacity."));
+        assert!(notice.contains("after 3:00 PM"));
+
+        // Held, with no more notices however long it lasts
+        chat_db.insert_message(1, "G-2", "second");
+        chat_db.insert_message(1, "G-3", "third");
+        daemon.poll().unwrap();
+        daemon.flush_queue(std::time::Instant::now());
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(daemon.queue.len(), 2);
+        assert!(!fake.pane("jane-doe").unwrap().contains("second"));
+        assert_eq!(notices(), 1);
+
+        // Once it resets, the same banner still on screen doesn't pause it again
+        daemon.paused.get_mut("jane-doe").unwrap().until = Utc::now() - chrono::Duration::minutes(1);
+        daemon.check_health(std::time::Instant::now());
+        assert!(!daemon.is_paused("jane-doe"));
+        daemon.flush_queue(std::time::Instant::now());
+        let pane = fake.pane("jane-doe").unwrap();
+        assert!(pane.find("second").unwrap() < pane.find("third").unwrap());
+        assert!(daemon.queue.is_empty());
+        assert_eq!(notices(), 1);
+
+        // Replies again, so it's forgotten; a later limit is a new pause with its own notice
+        daemon.check_health(std::time::Instant::now());
+        assert!(daemon.paused.is_empty());
+        fake.set_pane("jane-doe", &format!("> fourth\n  ⎿ Claude usage limit reached. Your limit will reset at 5pm\n{}", FAKE_READY_PANE));
+        daemon.check_health(std::time::Instant::now());
+        assert!(daemon.is_paused("jane-doe"));
+        assert_eq!(notices(), 2);
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+    }
+
+    #[test]
+    fn test_adopt_orphans() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let fake = Arc::new(FakeTmux::new());
+        for session in ["jane-doe", "jane-doe-bg", "john-doe", "group-book_club", "mystery", "known", "monitor"] {
+            fake.add_session(session, FAKE_READY_PANE);
+        }
+        let session_mgr = SessionManager::with_runner(&config, fake);
+        // Jane's transcript dir says who it's for; John's session is only named after him
+        let jane_dir = config.transcripts_dir.join("jane-doe");
+        fs::create_dir_all(&jane_dir).unwrap();
+        SessionInfo::new("+16175551234", "family", "Jane Doe").save(&jane_dir).unwrap();
+        let mut contacts = StaticContacts::new(&config, vec![contact("John Doe", "+16175550000", "favorite")]);
+        let mut registry = SessionRegistry::new(&config);
+        registry.register("+16175559999", "known", "/t/known", "individual", None, None, None, None).unwrap();
+
+        let mut adopted = adopt_orphans(&config, &session_mgr, &mut registry, &mut contacts);
+        adopted.sort();
+        assert_eq!(adopted, ["jane-doe", "jane-doe-bg", "john-doe"]);
+
+        let jane = registry.get("+16175551234").unwrap();
+        assert_eq!((jane.session_name.as_str(), jane.session_type.as_str()), ("jane-doe", "individual"));
+        assert_eq!((jane.contact_name.as_deref(), jane.tier.as_deref()), (Some("Jane Doe"), Some("family")));
+        assert_eq!(jane.transcript_dir, jane_dir.to_str().unwrap());
+        let background = registry.get_background("+16175551234").unwrap();
+        assert_eq!((background.session_name.as_str(), background.transcript_dir.as_str()), ("jane-doe-bg", jane.transcript_dir.as_str()));
+        let john = registry.get("+16175550000").unwrap();
+        assert_eq!((john.session_name.as_str(), john.tier.as_deref()), ("john-doe", Some("favorite")));
+        // Nothing to go on for the group or the stranger
+        assert!(registry.get_by_session_name("group-book_club").is_none());
+        assert!(registry.get_by_session_name("mystery").is_none());
+
+        // Persisted, so a second pass has nothing to do
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert!(adopt_orphans(&config, &session_mgr, &mut reloaded, &mut contacts).is_empty());
+    }
+
+    /// A slow session start for one contact doesn't hold up another, and each
+    /// contact's messages still go in in order
+    #[test]
+    fn test_daemon_sessions_in_parallel() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        config.parallel_sessions = true;
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234", "+16175550000"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family"), contact("John Doe", "+16175550000", "family")]);
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        // Jane's session takes a second to start
+        fake.delay_start("jane-doe", Duration::from_secs(1));
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        chat_db.insert_message(1, "G-1", "first from jane");
+        chat_db.insert_message(1, "G-2", "second from jane");
+        chat_db.insert_message(2, "G-3", "hi from john");
+        let start = std::time::Instant::now();
+        daemon.poll().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(900));
+
+        std::thread::sleep(Duration::from_millis(500));
+        let log = tmux_log(&fake);
+        assert!(log.contains("hi from john"));
+        assert!(!log.contains("first from jane"));
+
+        daemon.shutdown();
+        let log = tmux_log(&fake);
+        let created = log.find("new-session -d -s jane-doe").unwrap();
+        let first = log.find("first from jane").unwrap();
+        assert!(created < first && first < log.find("second from jane").unwrap());
+        assert!(daemon.registry.get("+16175551234").unwrap().last_message_time.is_some());
+        assert!(daemon.workers.is_empty());
+    }
+
+    #[test]
+    fn test_health_check_covers_background_sessions() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(temp.path(), &[("+16175551234", "Jane Doe", "family", "family")]);
+        let mut registry = SessionRegistry::new(&config);
+        registry.load().unwrap();
+        registry
+            .register(
+                "+16175551234",
+                "jane-doe-bg",
+                "/tmp",
+                "background",
+                Some("Jane Doe".to_string()),
+                None,
+                Some("family".to_string()),
+                None,
+            )
+            .unwrap();
+        let fake = running(&["jane-doe"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        // The main session is running; the background one has died
+        daemon.check_health(std::time::Instant::now());
+
+        let log = tmux_log(&fake);
+        assert!(log.contains("new-session -d -s jane-doe-bg "));
+        assert!(!log.contains("new-session -d -s jane-doe "));
+    }
+
+    /// Registry sessions at their old tiers and contacts at their new ones
+    ///
+    /// A new tier of "" leaves the contact out, as if they'd been deleted.
+    fn tier_change_fixture(dir: &Path, sessions: &[(&str, &str, &str, &str)]) -> (Config, StaticContacts) {
+        let mut config = Config::for_test(dir);
+        config.backfill_messages = 0;
+        let chat_db = ChatDb::create(&config.messages_db, &[]);
+        chat_db.conn.execute("INSERT INTO message (guid, text) VALUES ('G-0', 'hi')", []).unwrap();
+
+        let mut registry = SessionRegistry::new(&config);
+        let mut contacts = Vec::new();
+        for (phone, name, old, new) in sessions {
+            let session = SessionManager::session_name_for_contact(name);
+            let transcript_dir = config.transcripts_dir.join(&session);
+            registry
+                .register(
+                    phone,
+                    &session,
+                    transcript_dir.to_str().unwrap(),
+                    "individual",
+                    Some(name.to_string()),
+                    None,
+                    Some(old.to_string()),
+                    None,
+                )
+                .unwrap();
+            if new.is_empty() {
+                continue;
+            }
+            contacts.push(contact(name, phone, new));
+        }
+        let contacts = StaticContacts::new(&config, contacts);
+        (config, contacts)
+    }
+
+    /// A FakeTmux with `sessions` running, ready for messages
+    fn running(sessions: &[&str]) -> Arc<FakeTmux> {
+        let fake = Arc::new(FakeTmux::new());
+        for session in sessions {
+            fake.add_session(session, FAKE_READY_PANE);
+        }
+        fake
+    }
+
+    /// Every call to the fake, one per line, as a tmux log would show them
+    fn tmux_log(fake: &FakeTmux) -> String {
+        fake.calls().iter().map(|call| format!("{}\n", call.join(" "))).collect()
+    }
+
+    /// Keep the daemon's texts instead of sending them
+    fn record_texts(daemon: &mut Daemon) -> Arc<RecordingNotifier> {
+        let texts = Arc::new(RecordingNotifier::new());
+        daemon.notifier = RememberingNotifier::new(texts.clone(), 200);
+        texts
+    }
+
+    #[test]
+    fn test_tier_downgrade_restarts_and_upgrade_waits() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[
+                ("+16175551111", "Promoted Pat", "favorite", "family"),
+                ("+16175552222", "Demoted Dana", "family", "favorite"),
+                ("+16175553333", "Steady Sam", "family", "family"),
+            ],
+        );
+        let fake = running(&["promoted-pat", "demoted-dana", "steady-sam"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        daemon.apply_tier_changes();
+
+        // Both changes are recorded
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().tier.as_deref(), Some("family"));
+        assert_eq!(daemon.registry.get("+16175552222").unwrap().tier.as_deref(), Some("favorite"));
+        assert_eq!(daemon.registry.get("+16175553333").unwrap().tier.as_deref(), Some("family"));
+
+        // Only the demotion restarts without restart_on_tier_upgrade
+        let log = tmux_log(&fake);
+        let restarted: Vec<&str> = log.lines().filter(|l| l.starts_with("new-session")).collect();
+        assert_eq!(restarted.len(), 1);
+        assert!(restarted[0].contains("-s demoted-dana "));
+        assert!(restarted[0].contains("--allowedTools"));
+        assert!(log.contains("kill-session -t =demoted-dana"));
+        assert!(log.contains("moved from the family tier to favorite"));
+        for untouched in ["promoted-pat", "steady-sam"] {
+            assert!(!log.contains(&format!("kill-session -t ={}", untouched)));
+            assert!(!log.contains(&format!("send-keys -t {}", untouched)));
+        }
+
+        // Nothing to do until contacts change again
+        let calls = fake.calls().len();
+        daemon.apply_tier_changes();
+        assert_eq!(fake.calls().len(), calls);
+    }
+
+    #[test]
+    fn test_tier_upgrade_restarts_with_flag() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut config, contacts) =
+            tier_change_fixture(temp.path(), &[("+16175551111", "Promoted Pat", "favorite", "family")]);
+        config.restart_on_tier_upgrade = true;
+        let fake = running(&["promoted-pat"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        daemon.apply_tier_changes();
+
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().tier.as_deref(), Some("family"));
+        let log = tmux_log(&fake);
+        let restarted: Vec<&str> = log.lines().filter(|l| l.starts_with("new-session")).collect();
+        assert_eq!(restarted.len(), 1);
+        assert!(restarted[0].contains("-s promoted-pat "));
+        assert!(!restarted[0].contains("--allowedTools"));
+        assert!(log.contains("moved from the favorite tier to family"));
+    }
+
+    /// A tier change's restart runs on the session's worker, not the poll loop
+    #[test]
+    fn test_tier_change_restarts_on_worker() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut config, contacts) = tier_change_fixture(temp.path(), &[("+16175552222", "Demoted Dana", "family", "favorite")]);
+        config.parallel_sessions = true;
+        let fake = running(&["demoted-dana"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        fake.delay_start("demoted-dana", Duration::from_secs(1));
+
+        let start = std::time::Instant::now();
+        daemon.apply_tier_changes();
+        assert!(start.elapsed() < Duration::from_millis(900));
+        assert!(daemon.workers.is_busy("demoted-dana"));
+
+        daemon.workers.drain();
+        daemon.apply_outcomes();
+        let log = tmux_log(&fake);
+        assert!(log.contains("new-session -d -s demoted-dana "));
+        assert!(log.contains("moved from the family tier to favorite"));
+    }
+
+    /// Removed, blocked, and no-longer-blessed contacts lose their sessions rather than restarting them
+    #[test]
+    fn test_tier_change_archives_sessions_no_longer_let_through() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, mut contacts) = tier_change_fixture(
+            temp.path(),
+            &[
+                ("+16175551111", "Removed Rita", "family", ""),
+                ("+16175552222", "Blocked Bo", "family", "family"),
+                ("+16175553333", "Unknown Uma", "family", "unknown"),
+                ("+16175554444", "Steady Sam", "family", "family"),
+            ],
+        );
+        contacts.block("+16175552222");
+        let fake = running(&["removed-rita", "blocked-bo", "unknown-uma", "steady-sam"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        daemon.apply_tier_changes();
+        daemon.workers.drain();
+
+        let log = tmux_log(&fake);
+        assert!(!log.contains("new-session"));
+        for gone in ["+16175551111", "+16175552222", "+16175553333"] {
+            let data = daemon.registry.get(gone).unwrap();
+            assert!(data.archived, "{} should be archived", data.session_name);
+            assert_eq!(data.tier.as_deref(), Some("family"));
+            assert!(log.contains(&format!("kill-session -t ={}", data.session_name)));
+            assert!(!log.contains(&format!("send-keys -t {}", data.session_name)));
+        }
+        assert!(!daemon.registry.get("+16175554444").unwrap().archived);
+        assert!(!log.contains("kill-session -t =steady-sam"));
+    }
+
+    /// An empty contact list, as from a failed export, retires nobody
+    #[test]
+    fn test_tier_change_keeps_sessions_when_contacts_empty() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[("+16175551111", "Removed Rita", "family", ""), ("+16175552222", "Removed Ray", "family", "")],
+        );
+        let fake = running(&["removed-rita", "removed-ray"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        daemon.apply_tier_changes();
+        daemon.workers.drain();
+
+        for kept in ["+16175551111", "+16175552222"] {
+            assert!(!daemon.registry.get(kept).unwrap().archived);
+        }
+        assert!(!tmux_log(&fake).contains("kill-session"));
+    }
+
+    /// A group is archived once it's neither blessed nor has anyone blessed in it
+    #[test]
+    fn test_tier_change_rechecks_groups() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, mut contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Pat Smith", "family", "family")]);
+        let conn = rusqlite::Connection::open(&config.messages_db).unwrap();
+        conn.execute_batch(
+            "INSERT INTO handle (id, service) VALUES ('+16175551111', 'iMessage'), ('+16175559999', 'iMessage');
+             INSERT INTO chat (chat_identifier, style) VALUES ('chat100', 43), ('chat200', 43);
+             INSERT INTO chat_handle_join (chat_id, handle_id) VALUES (1, 1), (1, 2), (2, 2);",
+        )
+        .unwrap();
+        contacts.bless_group("chat300");
+        let fake = running(&["pat-smith"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        for (chat_id, session) in [("chat100", "pats-group"), ("chat200", "strangers"), ("chat300", "blessed-group")] {
+            let transcript_dir = config.transcripts_dir.join(session);
+            daemon
+                .registry
+                .register(chat_id, session, transcript_dir.to_str().unwrap(), "group", None, None, Some("family".to_string()), None)
+                .unwrap();
+            fake.add_session(session, FAKE_READY_PANE);
+        }
+        daemon.apply_tier_changes();
+
+        assert!(!daemon.registry.get("chat100").unwrap().archived);
+        assert!(daemon.registry.get("chat200").unwrap().archived);
+        assert!(!daemon.registry.get("chat300").unwrap().archived);
+        let log = tmux_log(&fake);
+        assert!(log.contains("kill-session -t =strangers"));
+        assert!(!log.contains("kill-session -t =pats-group"));
+        assert!(!log.contains("kill-session -t =blessed-group"));
+    }
+
+    /// A renamed contact's session is retitled, not restarted
+    #[test]
+    fn test_contact_rename_retitles_session() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Patricia Smith", "family", "family")]);
+        let fake = running(&["patricia-smith"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        daemon.registry.update_contact_name("+16175551111", "Pat Smith").unwrap();
+        daemon.apply_tier_changes();
+
+        let data = daemon.registry.get("+16175551111").unwrap();
+        assert_eq!(data.contact_name.as_deref(), Some("Patricia Smith"));
+        assert_eq!(data.session_name, "patricia-smith");
+        let log = tmux_log(&fake);
+        assert!(log.contains("rename-window -t =patricia-smith: Patricia Smith"));
+        assert!(log.contains("select-pane -t =patricia-smith: -T Patricia Smith"));
+        assert!(!log.contains("kill-session"));
+        assert!(!log.contains("new-session"));
+    }
+
+    #[test]
+    fn test_health_check_records_and_resumes_conversation() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Pat Smith", "family", "family")]);
+        let transcript_dir = config.transcripts_dir.join("pat-smith");
+        let project: String =
+            transcript_dir.to_string_lossy().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect();
+        let projects = transcript_dir.join(".claude/projects").join(project);
+        fs::create_dir_all(&projects).unwrap();
+        fs::write(projects.join("conv-1.jsonl"), "{}").unwrap();
+        let fake = running(&["pat-smith"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        // A healthy session's conversation is noted
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().claude_session_id.as_deref(), Some("conv-1"));
+        assert!(!tmux_log(&fake).contains("new-session"));
+
+        // and picked up again when it has to be restarted
+        fake.end_session("pat-smith");
+        daemon.check_health(std::time::Instant::now());
+        let log = tmux_log(&fake);
+        assert!(log.contains("new-session -d -s pat-smith "));
+        assert!(log.contains("--resume conv-1"));
+    }
+
+    /// After a fresh restart the old conversation is neither resumed nor noted again
+    #[test]
+    fn test_fresh_restart_forgets_conversation() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Pat Smith", "family", "family")]);
+        let transcript_dir = config.transcripts_dir.join("pat-smith");
+        let project: String =
+            transcript_dir.to_string_lossy().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect();
+        let projects = transcript_dir.join(".claude/projects").join(project);
+        fs::create_dir_all(&projects).unwrap();
+        let old = fs::File::create(projects.join("conv-1.jsonl")).unwrap();
+        old.set_modified(std::time::SystemTime::now() - Duration::from_secs(3600)).unwrap();
+
+        let mut registry = SessionRegistry::new(&config);
+        registry.load().unwrap();
+        registry.update_claude_session("+16175551111", "conv-0").unwrap();
+        let resumed = restart_conversation(&mut registry, Some("+16175551111"), &transcript_dir, Some("conv-0".to_string()), false);
+        assert_eq!(resumed.unwrap().as_deref(), Some("conv-1"));
+        assert!(restart_conversation(&mut registry, Some("+16175551111"), &transcript_dir, Some("conv-0".to_string()), true)
+            .unwrap()
+            .is_none());
+        assert!(read_registry(&config.registry_file).unwrap()["+16175551111"].claude_session_id.is_none());
+
+        // The fresh session started just now, after conv-1 was last written
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", FAKE_READY_PANE);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        daemon.check_health(std::time::Instant::now());
+        assert!(daemon.registry.get("+16175551111").unwrap().claude_session_id.is_none());
+
+        fs::File::create(projects.join("conv-2.jsonl")).unwrap();
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().claude_session_id.as_deref(), Some("conv-2"));
+        assert!(fake.calls_to("new-session").is_empty());
+    }
+
+    /// A message goes into a new session, and waits while Claude is busy, against FakeTmux
+    #[test]
+    fn test_daemon_injects_into_fake_tmux() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        config.verify_injections = true;
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let typed = || fake.calls_to("send-keys").iter().filter(|call| call.contains(&"-l".to_string())).count();
+
+        chat_db.insert_message(1, "G-1", "what's for dinner?");
+        daemon.poll().unwrap();
+        assert_eq!(fake.sessions(), ["jane-doe"]);
+        assert_eq!(fake.title("jane-doe").as_deref(), Some("Jane Doe"));
+        let pane = fake.pane("jane-doe").unwrap();
+        assert!(pane.contains("FROM Jane Doe (family)") && pane.contains("what's for dinner?"), "{}", pane);
+        assert_eq!(typed(), 1);
+
+        // Claude is working on it
+        fake.set_pane("jane-doe", &format!("{}✻ Cooking… (esc to interrupt)\n", pane));
+        chat_db.insert_message(1, "G-2", "never mind");
+        daemon.poll().unwrap();
+        daemon.flush_queue(std::time::Instant::now());
+        assert_eq!(typed(), 1);
+        assert_eq!(daemon.queue.len(), 1);
+
+        fake.set_pane("jane-doe", FAKE_READY_PANE);
+        daemon.flush_queue(std::time::Instant::now());
+        assert_eq!(typed(), 2);
+        assert!(fake.pane("jane-doe").unwrap().contains("never mind"));
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+
+        // Both messages counted, the queued one too
+        daemon.apply_outcomes();
+        daemon.registry.flush().unwrap();
+        let counters = &read_registry(&config.registry_file).unwrap()["+16175551234"].counters;
+        assert_eq!((counters.inbound, counters.injections, counters.restarts), (2, 2, 0));
+    }
+
+    /// A message is nudged about once, and a chat is forgotten once answered or archived
+    #[test]
+    fn test_daemon_nudges_unreplied_once() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        chat_db.insert_row(1, "G-1", "are you coming?", false, 1);
+        let fake = running(&["jane-doe"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let transcript_dir = config.transcripts_dir.join("jane-doe");
+        daemon
+            .registry
+            .register("+16175551234", "jane-doe", transcript_dir.to_str().unwrap(), "individual", None, None, Some("family".to_string()), None)
+            .unwrap();
+        let nudges = || fake.calls_to("send-keys").iter().filter(|call| call.last().is_some_and(|a| a.starts_with("[Reminder:"))).count();
+        let now = Utc::now();
+
+        daemon.nudge_unreplied(now, now);
+        daemon.nudge_unreplied(now, now);
+        assert_eq!(nudges(), 1);
+        assert_eq!(daemon.nudged.len(), 1);
+
+        chat_db.insert_row(1, "G-2", "yes!", true, 2);
+        daemon.nudge_unreplied(now, now);
+        assert!(daemon.nudged.is_empty());
+
+        chat_db.insert_row(1, "G-3", "bring snacks", false, 3);
+        daemon.nudge_unreplied(now, now);
+        assert_eq!(nudges(), 2);
+        daemon.registry.set_archived("+16175551234", true).unwrap();
+        daemon.nudge_unreplied(now, now);
+        assert!(daemon.nudged.is_empty());
+        assert_eq!(nudges(), 2);
+    }
+
+    /// Our own messages in a chat note when it was last answered, once the chat has a session
+    #[test]
+    fn test_daemon_records_last_outbound() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        // Dates are seconds after 2001-01-01, the chat.db epoch
+        let insert = |guid: &str, from_me: bool, secs: i64| chat_db.insert_row(1, guid, "hi", from_me, secs * 1_000_000_000);
+        let at = |secs: i64| Some(DateTime::from_timestamp(978_307_200 + secs, 0).unwrap());
+        insert("G-0", false, 5);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, Arc::new(FakeTmux::new())));
+
+        // Nothing to note before the chat has a session
+        insert("G-1", true, 10);
+        daemon.poll().unwrap();
+        assert!(daemon.registry.get("+16175551234").is_none());
+
+        insert("G-2", false, 20);
+        insert("G-3", true, 30);
+        insert("G-4", false, 40);
+        daemon.poll().unwrap();
+        let data = daemon.registry.get("+16175551234").unwrap();
+        assert_eq!(data.last_outbound_time, at(30));
+        assert_eq!(data.counters.inbound, 2);
+
+        // A reply whose chat isn't joined yet waits for it, like an inbound message
+        let late = insert("G-5", true, 50);
+        chat_db.conn.execute("DELETE FROM chat_message_join WHERE message_id = ?1", [late]).unwrap();
+        daemon.poll().unwrap();
+        assert_eq!(daemon.registry.get("+16175551234").unwrap().last_outbound_time, at(30));
+        chat_db.conn.execute("INSERT INTO chat_message_join (chat_id, message_id) VALUES (1, ?1)", [late]).unwrap();
+        daemon.poll().unwrap();
+        assert_eq!(daemon.registry.get("+16175551234").unwrap().last_outbound_time, at(50));
+
+        daemon.registry.flush().unwrap();
+        let saved = &read_registry(&config.registry_file).unwrap()["+16175551234"];
+        assert_eq!(saved.last_outbound_time, at(50));
+        assert!(saved.last_message_time.is_some());
+    }
+
+    /// The owner's direct replies are noted in the session, the daemon's own texts aren't
+    #[test]
+    fn test_daemon_notes_owner_replies_not_its_own() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("chat.db");
+        config.inject_outbound = true;
+        let chat_db = ChatDb::create(&config.messages_db, &["+16175551234"]);
+        let contacts = StaticContacts::new(&config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        chat_db.insert_message(1, "G-0", "old news");
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        record_texts(&mut daemon);
+
+        chat_db.insert_message(1, "G-1", "are you free at 7?");
+        daemon.poll().unwrap();
+        // The daemon's notice comes back through chat.db as from-me, like the owner's reply
+        let notice = "Had a hiccup and restarted — could you resend your last message?";
+        daemon.notifier.send("+16175551234", notice).unwrap();
+        chat_db.insert_sent(1, "G-2", notice);
+        chat_db.insert_sent(1, "G-3", "yes, see you then");
+        daemon.poll().unwrap();
+
+        let pane = fake.pane("jane-doe").unwrap();
+        assert!(pane.contains("[You replied directly: yes, see you then]"));
+        assert!(!pane.contains("hiccup"));
+    }
+
+    /// Everything --dry-run printed for one inject-prompt, and the fake tmux it ran against
+    fn dry_run_inject(config: &Config, fake: &Arc<FakeTmux>, bg: bool, sms: bool, admin: bool) -> String {
+        let out = Arc::new(std::sync::Mutex::new(Vec::<u8>::new()));
+        let session_mgr = SessionManager::with_runner(config, fake.clone()).dry_run_into(out.clone());
+        let mut contacts = StaticContacts::new(config, vec![contact("Jane Doe", "+16175551234", "family")]);
+        cmd_inject_prompt(
+            config,
+            &session_mgr,
+            &mut contacts,
+            "+16175551234",
+            "what's for dinner?",
+            &InjectOptions { bg, sms, admin, wait: Some(Duration::from_secs(30)), ..Default::default() },
+        )
+        .unwrap();
+        let out = out.lock().unwrap();
+        String::from_utf8(out.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_inject_prompt_dry_run() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.dry_run = true;
+        let mut registry = SessionRegistry::new(&config);
+        let transcript_dir = config.transcripts_dir.join("jane-doe");
+        registry
+            .register(
+                "+16175551234",
+                "jane-doe",
+                transcript_dir.to_str().unwrap(),
+                "individual",
+                Some("Jane Doe".to_string()),
+                None,
+                Some("family".to_string()),
+                None,
+            )
+            .unwrap();
+        let saved = fs::read(&config.registry_file).unwrap();
+        let fake = Arc::new(FakeTmux::new());
+
+        // Plain, into a session that would have to be created
+        let out = dry_run_inject(&config, &fake, false, false, false);
+        assert!(out.starts_with("Would create session jane-doe with:\n"), "{}", out);
+        assert!(out.contains(" new-session -d -s jane-doe -e CLAUDE_ASSISTANT_CHAT_ID=+16175551234 "));
+        assert!(out.contains("--append-system-prompt"));
+        assert!(out.ends_with("Would inject into jane-doe:\nwhat's for dinner?\n"), "{}", out);
+
+        let out = dry_run_inject(&config, &fake, false, true, false);
+        assert!(out.contains("Would inject into jane-doe:\n"));
+        assert!(out.contains("FROM Jane Doe (family)"), "{}", out);
+
+        let out = dry_run_inject(&config, &fake, false, false, true);
+        assert!(out.contains("---ADMIN OVERRIDE---\nFrom: Jane Doe (admin)\nwhat's for dinner?\n"), "{}", out);
+
+        // A running background session is only injected into
+        fake.add_session("jane-doe-bg", FAKE_READY_PANE);
+        let out = dry_run_inject(&config, &fake, true, true, true);
+        assert!(out.starts_with("Would inject into jane-doe-bg:\n"), "{}", out);
+        assert!(out.contains("---ADMIN OVERRIDE---") && out.contains("FROM Jane Doe (family)"));
+
+        // Nothing was touched
+        let calls: Vec<String> = fake.calls().iter().map(|call| call[0].clone()).collect();
+        assert!(calls.iter().all(|call| call == "has-session" || call == "capture-pane"), "{:?}", calls);
+        assert_eq!(fake.sessions(), ["jane-doe-bg"]);
+        assert_eq!(fs::read(&config.registry_file).unwrap(), saved);
+        assert!(!transcript_dir.exists());
+    }
+
+    /// A crashed session is archived, killed, and started again, against FakeTmux
+    #[test]
+    fn test_daemon_restarts_crashed_session_in_fake_tmux() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Pat Smith", "family", "family")]);
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", "Claude session crashed\n$ ");
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(fake.calls_to("kill-session").len(), 1);
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+        assert_eq!(fake.pane("pat-smith").as_deref(), Some(FAKE_READY_PANE));
+        let archives = fs::read_dir(config.transcripts_dir.join("pat-smith/pane-archives")).unwrap().count();
+        assert_eq!(archives, 1);
+
+        // Healthy now, so left alone
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().counters.restarts, 1);
+    }
+
+    /// A restarted session with no conversation to resume is caught up on the chat instead
+    #[test]
+    fn test_daemon_backfills_restarted_session() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut config, contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Pat Smith", "family", "family")]);
+        config.backfill_messages = 5;
+        let conn = rusqlite::Connection::open(&config.messages_db).unwrap();
+        conn.execute_batch(
+            "INSERT INTO handle (id, service) VALUES ('+16175551111', 'iMessage');
+             INSERT INTO chat (chat_identifier, style) VALUES ('+16175551111', 45);
+             INSERT INTO message (guid, text, handle_id, date, is_from_me) VALUES
+                 ('G-1', 'can you book the 6pm table?', 1, 1, 0), ('G-2', 'Booked for 6pm', 1, 2, 1);
+             INSERT INTO chat_message_join (chat_id, message_id) VALUES (1, 2), (1, 3);",
+        )
+        .unwrap();
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", "Claude session crashed\n$ ");
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+        let pane = fake.pane("pat-smith").unwrap();
+        assert!(pane.contains("---Recent conversation context (oldest first)---"));
+        let (asked, booked) = (pane.find("Pat Smith: can you book the 6pm table?").unwrap(), pane.find("Me: Booked for 6pm").unwrap());
+        assert!(asked < booked);
+    }
+
+    #[test]
+    fn test_daemon_backs_off_then_quarantines_crash_loop() {
+        use std::time::{Duration, Instant};
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[("+16175551111", "Pat Smith", "family", "family"), ("+16175552222", "Al Admin", "admin", "admin")],
+        );
+        config.session_ready_timeout_secs = 1;
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", "Claude session crashed\n$ ");
+        fake.add_session("al-admin", FAKE_READY_PANE);
+        // Every restart comes up crashed again
+        fake.set_startup_pane("Error: invalid API key\nClaude session crashed\n$ ");
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let texts = record_texts(&mut daemon);
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+
+        daemon.check_health(start);
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+        // Too soon for the second attempt
+        daemon.check_health(minutes(1));
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+        // 5m, then 15m, then an hour apart
+        for (at, restarts) in [(5, 2), (20, 3), (30, 3), (80, 4), (140, 5)] {
+            daemon.check_health(minutes(at));
+            assert_eq!(fake.calls_to("new-session").len(), restarts, "at {}m", at);
+        }
+        assert!(texts.sent().is_empty());
+
+        // The sixth failure in a row gives up
+        daemon.check_health(minutes(200));
+        assert_eq!(fake.calls_to("new-session").len(), 5);
+        let pat = daemon.registry.get("+16175551111").unwrap();
+        assert!(pat.quarantined);
+        assert_eq!(pat.counters.restarts, 5);
+        let alerts = texts.sent_to("+16175552222");
+        assert_eq!(texts.sent().len(), 1);
+        let log = &alerts[0];
+        assert!(log.contains("pat-smith failed 5 restarts in a row"));
+        assert!(log.contains("restart-session pat-smith"));
+        assert!(log.contains("Error: invalid API key"));
+
+        // Left alone from now on
+        daemon.check_health(minutes(1000));
+        assert_eq!(fake.calls_to("new-session").len(), 5);
+        assert_eq!(texts.sent().len(), 1);
+    }
+
+    #[test]
+    fn test_daemon_quarantines_logged_out_sessions_and_alerts_once() {
+        use std::time::{Duration, Instant};
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[
+                ("+16175551111", "Pat Smith", "family", "family"),
+                ("+16175552222", "Al Admin", "admin", "admin"),
+                ("+16175553333", "Lee Jones", "family", "family"),
+            ],
+        );
+        let login = " Select login method:\n\n ❯ 1. Claude account with subscription · Pro, Max, Team, or Enterprise\n\n   2. Anthropic Console account · API usage billing\n";
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", login);
+        fake.add_session("lee-jones", "> hi\n  ⎿ Invalid API key · Please run /login\n╭──╮\n│ > │\n╰──╯\n");
+        fake.add_session("al-admin", FAKE_READY_PANE);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let texts = record_texts(&mut daemon);
+        let start = Instant::now();
+
+        // Quarantined rather than restarted onto the same screen, with one alert for both
+        daemon.check_health(start);
+        assert!(fake.calls_to("kill-session").is_empty());
+        assert!(fake.calls_to("new-session").is_empty());
+        assert!(daemon.registry.get("+16175551111").unwrap().quarantined);
+        assert!(daemon.registry.get("+16175553333").unwrap().quarantined);
+        assert!(!daemon.registry.get("+16175552222").unwrap().quarantined);
+        assert_eq!(texts.sent().len(), 1);
+        let log = &texts.sent_to("+16175552222")[0];
+        assert!(log.starts_with("Claude Assistant: Claude needs re-authentication — attach to lee-jones"));
+        assert!(log.contains("restart-session` for each of lee-jones, pat-smith"));
+
+        // Not again, sweep after sweep
+        daemon.check_health(start + Duration::from_secs(300));
+        daemon.check_health(start + Duration::from_secs(600));
+        assert_eq!(texts.sent().len(), 1);
+        assert!(fake.calls_to("new-session").is_empty());
+    }
+
+    #[test]
+    fn test_auth_alert() {
         assert_eq!(
-            normalize_chat_id("ABC123DEF456789012345"),
-            "abc123def456789012345"
+            auth_alert(&["pat-smith".to_string()]),
+            "Claude Assistant: Claude needs re-authentication — attach to pat-smith (`claude-assistant-rs attach pat-smith`) and log in, then run `claude-assistant-rs restart-session pat-smith`."
         );
     }
 
+    /// Chats that messaged just before their session was restarted are asked to resend, once
     #[test]
-    fn test_wrap_sms() {
-        let wrapped = wrap_sms("Hello", "John Doe", "admin", "+16175551234", None);
-        assert!(wrapped.contains("John Doe"));
-        assert!(wrapped.contains("admin"));
-        assert!(wrapped.contains("+16175551234"));
-        assert!(wrapped.contains("Hello"));
+    fn test_daemon_asks_recent_chats_to_resend_after_restart() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[
+                ("+16175551111", "Pat Smith", "family", "family"),
+                ("+16175552222", "Al Admin", "admin", "admin"),
+                ("+16175553333", "Lee Jones", "family", "family"),
+            ],
+        );
+        let fake = Arc::new(FakeTmux::new());
+        for session in ["pat-smith", "al-admin", "lee-jones"] {
+            fake.add_session(session, "Claude session crashed\n$ ");
+        }
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let notifier = record_texts(&mut daemon);
+        let now = Utc::now();
+        daemon.registry.count("+16175551111", SessionEvent::Inbound(now - chrono::Duration::minutes(2)));
+        daemon.registry.count("+16175552222", SessionEvent::Inbound(now - chrono::Duration::minutes(2)));
+        // Lee last wrote yesterday, so has nothing to resend
+        daemon.registry.count("+16175553333", SessionEvent::Inbound(now - chrono::Duration::hours(20)));
+
+        daemon.check_health(std::time::Instant::now());
+        daemon.workers.drain();
+        daemon.apply_outcomes();
+        assert_eq!(fake.calls_to("new-session").len(), 3);
+        assert_eq!(
+            notifier.sent_to("+16175551111"),
+            ["Had a hiccup and restarted — could you resend your last message?"]
+        );
+        assert_eq!(
+            notifier.sent_to("+16175552222"),
+            ["Claude Assistant: al-admin was restarted (fatal_error:crashed). If your last message went unanswered, please send it again."]
+        );
+        assert!(notifier.sent_to("+16175553333").is_empty());
+        assert!(daemon.restarting.is_empty());
+
+        // Not again within the hour
+        daemon.notify_recovered("+16175551111", "crashed", now + chrono::Duration::minutes(5));
+        assert_eq!(notifier.sent().len(), 2);
+    }
+
+    #[test]
+    fn test_recovery_notice_due() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        let now = Utc::now();
+        let ago = |mins: i64| Some(now - chrono::Duration::minutes(mins));
+
+        assert!(recovery_notice_due(&config, ago(3), None, now));
+        // Nothing recent to resend
+        assert!(!recovery_notice_due(&config, ago(30), None, now));
+        assert!(!recovery_notice_due(&config, None, None, now));
+        // At most once an hour
+        assert!(!recovery_notice_due(&config, ago(3), ago(59), now));
+        assert!(recovery_notice_due(&config, ago(3), ago(60), now));
+
+        config.recovery_notice_window_mins = 0;
+        assert!(!recovery_notice_due(&config, Some(now), None, now));
+    }
+
+    /// With tmux's server gone, sessions come back a few a sweep, most recently messaged first
+    #[test]
+    fn test_daemon_recreates_sessions_lost_with_server() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[
+                ("+16175551111", "Pat Smith", "family", "family"),
+                ("+16175552222", "Al Admin", "admin", "admin"),
+                ("+16175553333", "Lee Jones", "family", "family"),
+                ("+16175554444", "Kim Park", "family", "family"),
+            ],
+        );
+        // No sessions, so no server
+        let fake = Arc::new(FakeTmux::new());
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let now = Utc::now();
+        for (key, hours) in [("+16175551111", 3), ("+16175552222", 1), ("+16175553333", 2), ("+16175554444", 4)] {
+            daemon.registry.count(key, SessionEvent::Inbound(now - chrono::Duration::hours(hours)));
+        }
+        let created = |fake: &FakeTmux| -> Vec<String> {
+            let calls = fake.calls_to("new-session");
+            calls.iter().map(|call| call[call.iter().position(|a| a == "-s").unwrap() + 1].clone()).collect()
+        };
+
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(created(&fake), ["al-admin", "lee-jones", "pat-smith"]);
+        assert_eq!(daemon.recreating.iter().collect::<Vec<_>>(), ["+16175554444"]);
+        // Not held against the sessions themselves
+        assert_eq!(daemon.restarts.unhealthy("al-admin", std::time::Instant::now()), RestartDecision::Restart(1));
+        let history: Vec<_> = daemon.health_history.events().map(|e| e.reason.clone()).collect();
+        assert!(history.iter().all(|reason| reason.as_deref() == Some("no_server")));
+        assert!(!crash::crashes_dir(&config.logs_dir).exists());
+
+        // The server is back, but the last of them is still one of the lost
+        daemon.check_health(std::time::Instant::now());
+        assert_eq!(created(&fake), ["al-admin", "lee-jones", "pat-smith", "kim-park"]);
+        assert!(daemon.recreating.is_empty());
+        assert!(!crash::crashes_dir(&config.logs_dir).exists());
+    }
+
+    /// A session whose tmux calls hang holds up neither polling nor the other sessions' restarts
+    #[test]
+    fn test_daemon_sweeps_past_hung_session() {
+        use std::time::{Duration, Instant};
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[("+16175551111", "Pat Smith", "family", "family"), ("+16175552222", "Al Admin", "admin", "admin")],
+        );
+        config.health_check_timeout_secs = 1;
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", "Claude session crashed\n$ ");
+        fake.add_session("al-admin", FAKE_READY_PANE);
+        let release = fake.hang("al-admin");
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        let start = Instant::now();
+        daemon.start_sweep(start);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        // The loop carries on: polling, and acting on the checks that are in
+        while fake.calls_to("new-session").is_empty() && start.elapsed() < Duration::from_secs(5) {
+            daemon.poll().unwrap();
+            daemon.finish_sweep(false);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().counters.restarts, 1);
+        assert!(daemon.sweep.as_ref().is_some_and(|sweep| sweep.waiting.contains_key("al-admin")));
+        // Another sweep isn't piled on top
+        daemon.start_sweep(Instant::now());
+        assert_eq!(daemon.sweep.as_ref().unwrap().waiting.len(), 1);
+
+        // Given up on once it has been quiet too long, and left alone rather than restarted
+        std::thread::sleep(Duration::from_millis(1100));
+        daemon.finish_sweep(false);
+        assert!(daemon.sweep.is_none());
+        assert_eq!(daemon.registry.get("+16175552222").unwrap().counters.restarts, 0);
+        drop(release);
+
+        // Checked as usual next time
+        daemon.check_health(Instant::now());
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+    }
+
+    /// A restart that comes up healthy starts the count over
+    #[test]
+    fn test_daemon_healthy_session_resets_restarts() {
+        use std::time::{Duration, Instant};
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Pat Smith", "family", "family")]);
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", "Claude session crashed\n$ ");
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let start = Instant::now();
+
+        daemon.check_health(start);
+        daemon.check_health(start + Duration::from_secs(10));
+        fake.set_pane("pat-smith", "Claude session crashed\n$ ");
+        // Restarted straight away rather than after the 5 minute backoff
+        daemon.check_health(start + Duration::from_secs(20));
+        assert_eq!(fake.calls_to("new-session").len(), 2);
+
+        // Each flap is in the saved history
+        let mut history = HealthHistory::new(&config);
+        history.load().unwrap();
+        let flaps: Vec<_> = history.events().map(|e| (e.from, e.to, e.action)).collect();
+        assert_eq!(
+            flaps,
+            [
+                (None, HealthState::Unhealthy, Some(ActionTaken::Restart)),
+                (Some(HealthState::Unhealthy), HealthState::Healthy, None),
+                (Some(HealthState::Healthy), HealthState::Unhealthy, Some(ActionTaken::Restart)),
+            ]
+        );
+        assert!(history.events().all(|e| e.session == "pat-smith"));
+
+        // With a crash report saved before each restart
+        let crashes = crash::list(&crash::crashes_dir(&config.logs_dir)).unwrap();
+        assert_eq!(crashes.len(), 2);
+        let bundle = CrashBundle::load(&crashes[0].0).unwrap();
+        assert_eq!(bundle.report.reason, "fatal_error:crashed");
+        assert_eq!(bundle.pane.as_deref(), Some("Claude session crashed\n$ "));
+        assert_eq!(bundle.report.registry.unwrap().session_name, "pat-smith");
+    }
+
+    #[test]
+    fn test_crashes_command() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["claude-assistant-rs", "crashes"], args].concat()).map(|cli| cli.command);
+        match parse(&["list", "--session", "pat-smith"]).unwrap() {
+            Commands::Crashes { action: CrashesAction::List { session } } => assert_eq!(session.as_deref(), Some("pat-smith")),
+            _ => panic!("expected crashes list"),
+        }
+        assert!(matches!(parse(&["show", "pat-smith"]).unwrap(), Commands::Crashes { action: CrashesAction::Show { .. } }));
+        assert!(parse(&["show"]).is_err());
+
+        let at = DateTime::parse_from_rfc3339("2025-03-04T08:00:00Z").unwrap().with_timezone(&Utc);
+        let report = CrashReport { session: "pat-smith".to_string(), at, reason: "stuck".to_string(), registry: None };
+        let lines = crash_lines(&[("pat-smith-20250304-080000.000".to_string(), report.clone())]);
+        assert!(lines[0].ends_with("pat-smith  stuck  pat-smith-20250304-080000.000"));
+
+        let bundle = CrashBundle {
+            report,
+            pane: Some("✻ Compiling… (212s · esc to interrupt)".to_string()),
+            processes: None,
+            daemon_log: Some("WARN Session pat-smith unhealthy: Stuck\n".to_string()),
+        };
+        let text = crash_report_text(Path::new("/tmp/pat-smith-20250304-080000.000"), &bundle).unwrap();
+        assert!(text.starts_with("Crash report /tmp/pat-smith-20250304-080000.000\nSession: pat-smith\n"));
+        assert!(text.contains("Reason: stuck\n"));
+        assert!(text.contains("== Processes ==\n(not saved)\n"));
+        assert!(text.contains("== Daemon log ==\nWARN Session pat-smith unhealthy: Stuck\n"));
+        assert!(text.ends_with("== Scrollback ==\n✻ Compiling… (212s · esc to interrupt)\n"));
+    }
+
+    #[test]
+    fn test_reminders_command() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["claude-assistant-rs", "reminders"], args].concat()).map(|cli| cli.command);
+        match parse(&["add", "John Doe", "30 17 * * *", "Stretch"]).unwrap() {
+            Commands::Reminders { action: RemindersAction::Add { chat_id, cron, prompt } } => {
+                assert_eq!((chat_id.as_str(), cron.as_str(), prompt.as_str()), ("John Doe", "30 17 * * *", "Stretch"))
+            }
+            _ => panic!("expected reminders add"),
+        }
+        assert!(matches!(parse(&["list"]).unwrap(), Commands::Reminders { action: RemindersAction::List { chat_id: None } }));
+        assert!(matches!(parse(&["rm", "+16175551234", "2"]).unwrap(), Commands::Reminders { action: RemindersAction::Rm { index: 2, .. } }));
+        assert!(parse(&["rm", "+16175551234", "two"]).is_err());
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let john = Contact {
+            notes: Some("REMINDER: 0 9 * * * | Morning".to_string()),
+            ..contact("John Doe", "+16175551234", "family")
+        };
+        let mut contacts = StaticContacts::new(&config, vec![john]);
+        let add = |contacts: &mut StaticContacts, cron: &str| {
+            let action = RemindersAction::Add { chat_id: "John Doe".to_string(), cron: cron.to_string(), prompt: "Stretch".to_string() };
+            cmd_reminders(&config, contacts, action)
+        };
+
+        add(&mut contacts, "30 17 * * *").unwrap();
+        assert!(add(&mut contacts, "every day at noon").is_err());
+        let store = ReminderStore::load(&config).unwrap();
+        let added = &store.reminders()["+16175551234"];
+        assert_eq!(added.iter().map(|r| (r.cron.as_str(), r.prompt.as_str())).collect::<Vec<_>>(), [("30 17 * * *", "Stretch")]);
+
+        // Listed after the notes' one, and numbered to match
+        let now = Utc::now();
+        let registry = SessionRegistry::new(&config);
+        let reminders = load_reminders(&config, &mut contacts, &registry, &store, now).unwrap();
+        let mut listed = reminders.upcoming();
+        listed.sort_by_key(|u| (u.chat_id, u.index));
+        let lines = reminder_lines(&listed, now);
+        assert!(lines[0].starts_with("+16175551234  #1  0 9 * * *    ") && lines[0].ends_with("  notes  Morning"));
+        assert!(lines[1].starts_with("+16175551234  #2  30 17 * * *  ") && lines[1].ends_with("  added  Stretch"));
+
+        let action = RemindersAction::Rm { chat_id: "+16175551234".to_string(), index: 2 };
+        cmd_reminders(&config, &mut contacts, action).unwrap();
+        assert!(ReminderStore::load(&config).unwrap().reminders().is_empty());
+    }
+
+    #[test]
+    fn test_daemon_restarts_stuck_session() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[("+16175551111", "Pat Smith", "family", "family"), ("+16175552222", "Al Admin", "admin", "admin")],
+        );
+        let fake = Arc::new(FakeTmux::new());
+        let hung = "> fix the build\n✻ Compiling… (212s · esc to interrupt)\n";
+        fake.add_session("pat-smith", hung);
+        // Idle at its input box just as long
+        fake.add_session("al-admin", FAKE_READY_PANE);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        daemon.check_health(std::time::Instant::now());
+        daemon.check_health(std::time::Instant::now());
+        assert!(fake.calls_to("new-session").is_empty());
+        daemon.check_health(std::time::Instant::now());
+        let created = fake.calls_to("new-session");
+        assert_eq!(created.len(), 1);
+        assert!(created[0].contains(&"pat-smith".to_string()));
+        assert_eq!(fake.pane("pat-smith").as_deref(), Some(FAKE_READY_PANE));
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().counters.restarts, 1);
+    }
+
+    #[test]
+    fn test_daemon_restarts_session_with_api_errors_sweep_after_sweep() {
+        use std::time::{Duration, Instant};
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(temp.path(), &[("+16175551111", "Pat Smith", "family", "family")]);
+        let fake = Arc::new(FakeTmux::new());
+        let failing = format!("> hi\n  ⎿ API Error (529 overloaded) · Retrying in 8 seconds…\n{}", FAKE_READY_PANE);
+        fake.add_session("pat-smith", &failing);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let start = Instant::now();
+        let sweep = |daemon: &mut Daemon, n: u64| daemon.check_health(start + Duration::from_secs(300 * n));
+
+        // One error on its own is fine
+        sweep(&mut daemon, 0);
+        fake.set_pane("pat-smith", FAKE_READY_PANE);
+        sweep(&mut daemon, 1);
+        fake.set_pane("pat-smith", &failing);
+        for n in 2..4 {
+            sweep(&mut daemon, n);
+        }
+        assert!(fake.calls_to("new-session").is_empty());
+        // The fourth in six sweeps
+        sweep(&mut daemon, 4);
+        assert_eq!(fake.calls_to("new-session").len(), 1);
+        assert_eq!(fake.pane("pat-smith").as_deref(), Some(FAKE_READY_PANE));
+    }
+
+    #[test]
+    fn test_daemon_restarts_session_over_memory_limit() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[("+16175551111", "Pat Smith", "family", "family"), ("+16175552222", "Al Admin", "admin", "admin")],
+        );
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("pat-smith", FAKE_READY_PANE);
+        fake.add_session("al-admin", FAKE_READY_PANE);
+        fake.set_pid("pat-smith", 500);
+        fake.set_pid("al-admin", 600);
+        // Pat's Claude has grown to 8GB; Al's is fine
+        fake.set_processes("  500 410 3100 0.0\n  501 500 8388608 97.5\n  502 501 81920 1.2\n  600 410 3100 0.0\n  601 600 409600 2.0\n");
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        daemon.check_health(std::time::Instant::now());
+        let killed = fake.calls_to("kill-session");
+        assert_eq!(killed.len(), 1);
+        assert_eq!(killed[0][2], "=pat-smith");
+        let created = fake.calls_to("new-session");
+        assert_eq!(created.len(), 1);
+        assert!(created[0].contains(&"pat-smith".to_string()));
+        assert_eq!(fake.sessions(), ["al-admin", "pat-smith"]);
+        // Its screen was saved first
+        assert_eq!(fs::read_dir(config.transcripts_dir.join("pat-smith/pane-archives")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_daemon_compacts_session_out_of_context() {
+        use std::time::{Duration, Instant};
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[("+16175551111", "Pat Smith", "family", "family"), ("+16175552222", "Al Admin", "admin", "admin")],
+        );
+        let fake = Arc::new(FakeTmux::new());
+        let exhausted = format!("{}  Context low (2% remaining) · Run /compact to compact & continue\n", FAKE_READY_PANE);
+        fake.add_session("pat-smith", &exhausted);
+        fake.add_session("al-admin", &exhausted);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+
+        // Compacted rather than restarted
+        daemon.check_health(start);
+        assert!(fake.pane("pat-smith").unwrap().contains("> /compact\n"));
+        assert!(fake.pane("al-admin").unwrap().contains("> /compact\n"));
+        assert!(fake.calls_to("new-session").is_empty());
+        assert_eq!(daemon.registry.get("+16175551111").unwrap().counters.compactions, 1);
+
+        // Al's worked; Pat's is still out of context, but within its grace
+        fake.set_pane("al-admin", FAKE_READY_PANE);
+        daemon.check_health(minutes(5));
+        assert!(fake.calls_to("new-session").is_empty());
+
+        // Still wedged once the grace is up, so restarted
+        daemon.check_health(minutes(10));
+        let created = fake.calls_to("new-session");
+        assert_eq!(created.len(), 1);
+        assert!(created[0].contains(&"pat-smith".to_string()));
+        let pat = daemon.registry.get("+16175551111").unwrap();
+        assert_eq!((pat.counters.compactions, pat.counters.restarts), (1, 1));
+
+        // Al running out again later gets another compact
+        fake.set_pane("al-admin", &exhausted);
+        daemon.check_health(minutes(60));
+        assert_eq!(daemon.registry.get("+16175552222").unwrap().counters.compactions, 2);
+    }
+
+    #[test]
+    fn test_reap_idle_archives_sessions() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (config, contacts) = tier_change_fixture(
+            temp.path(),
+            &[
+                ("+16175551111", "Pat Smith", "family", "family"),
+                ("+16175552222", "Al Admin", "admin", "admin"),
+                ("+16175553333", "Promoted Pam", "family", "admin"),
+                ("+16175554444", "Demoted Dan", "admin", "family"),
+            ],
+        );
+        let fake = running(&["pat-smith", "al-admin", "promoted-pam", "demoted-dan"]);
+        let mut daemon = Daemon::new(&config, Box::new(contacts), true).unwrap();
+        daemon.session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+        fake.set_pane("pat-smith", &format!("pane of pat-smith\n{}", FAKE_READY_PANE));
+        let now = Utc::now();
+
+        // Within the 2 hour timeout
+        daemon.reap_idle(now + chrono::Duration::hours(1));
+        assert!(!tmux_log(&fake).contains("kill-session"));
+
+        daemon.reap_idle(now + chrono::Duration::hours(3));
+        let archives: Vec<_> = fs::read_dir(config.transcripts_dir.join("pat-smith/pane-archives")).unwrap().collect();
+        assert_eq!(archives.len(), 1);
+        let pane = fs::read_to_string(archives[0].as_ref().unwrap().path()).unwrap();
+        assert!(pane.starts_with("pane of pat-smith\n"));
+        assert!(tmux_log(&fake).contains("kill-session -t =pat-smith"));
+        assert!(daemon.registry.get("+16175551111").unwrap().archived);
+        // Admins are exempt, by the tier their contact has now
+        assert!(!daemon.registry.get("+16175552222").unwrap().archived);
+        assert!(!daemon.registry.get("+16175553333").unwrap().archived);
+        assert!(daemon.registry.get("+16175554444").unwrap().archived);
+        assert_eq!(fake.sessions(), ["al-admin", "promoted-pam"]);
+
+        // Archived sessions are left alone until a message brings them back
+        daemon.check_health(std::time::Instant::now());
+        daemon.reap_idle(now + chrono::Duration::hours(6));
+        let log = tmux_log(&fake);
+        assert!(!log.contains("new-session"));
+        assert_eq!(log.matches("kill-session").count(), 2);
+    }
+
+    #[test]
+    fn test_notify_admin_texts_top_tier() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let mut contacts = StaticContacts::new(
+            &config,
+            vec![
+                contact("Jane Doe", "jane@icloud.com", "admin"),
+                contact("Sam Smith", "+16175550000", "family"),
+            ],
+        );
+        let texts = RecordingNotifier::new();
+        notify_admin(&config, &texts, &mut contacts, "contacts are stale");
+        assert_eq!(texts.sent(), [("jane@icloud.com".to_string(), "contacts are stale".to_string())]);
     }
 
     #[test]
@@ -0,0 +1,3073 @@
+//! Messages.app database reader
+//!
+//! Reads messages from ~/Library/Messages/chat.db and parses attributedBody blobs.
+
+pub mod typedstream;
+
+use crate::attachments;
+use crate::config::{Config, MACOS_EPOCH_OFFSET};
+use crate::error::{Error, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{Connection, OpenFlags};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, VecDeque};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// A message from Messages.app
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    pub rowid: i64,
+    pub guid: String,
+    pub timestamp: DateTime<Utc>,
+    pub sender: String,        // Phone of the sender (for groups) or chat_id (for 1:1)
+    pub text: String,          // Message text (empty if no text)
+    pub chat_id: String,       // Chat identifier (phone for 1:1, UUID for groups)
+    pub is_from_me: bool,
+    pub is_group: bool,
+    pub group_name: Option<String>,
+    pub attachments: Vec<Attachment>,
+    pub is_audio_message: bool,
+    pub audio_transcription: Option<String>,
+    pub thread_originator_guid: Option<String>,
+    pub kind: MessageKind,
+    pub edited: bool,
+    pub edit_history: Vec<String>, // Every version of the text, part by part, oldest first (empty if never edited)
+    pub date_edited: Option<DateTime<Utc>>,
+    pub date_retracted: Option<DateTime<Utc>>,
+    pub service: MessageService,
+    /// Handles @-mentioned in the message, in order
+    pub mentions: Vec<String>,
+    /// One of the mentions is one of `Config::my_handles`
+    pub mentions_me: bool,
+    pub date_delivered: Option<DateTime<Utc>>,
+    pub date_read: Option<DateTime<Utc>>,
+    /// Subject line from MMS or the email gateway (never empty)
+    pub subject: Option<String>,
+    /// Filtered into Messages' junk folder
+    pub is_spam: bool,
+}
+
+/// Transport a message arrived over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageService {
+    IMessage,
+    /// Green bubble: keep replies short, no rich content
+    Sms,
+    Rcs,
+    #[default]
+    Unknown,
+}
+
+impl MessageService {
+    /// Parse the `service` column of message/handle ("iMessage", "SMS", "RCS")
+    pub fn from_db(service: &str) -> Self {
+        match service.to_ascii_lowercase().as_str() {
+            "imessage" => MessageService::IMessage,
+            "sms" => MessageService::Sms,
+            "rcs" => MessageService::Rcs,
+            _ => MessageService::Unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for MessageService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MessageService::IMessage => "iMessage",
+            MessageService::Sms => "SMS",
+            MessageService::Rcs => "RCS",
+            MessageService::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for MessageService {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match MessageService::from_db(s) {
+            MessageService::Unknown if !s.eq_ignore_ascii_case("unknown") => {
+                Err(format!("unknown service '{}' (expected imessage, sms, or rcs)", s))
+            }
+            service => Ok(service),
+        }
+    }
+}
+
+impl Message {
+    /// Text to inject for this message
+    ///
+    /// Voice messages carry only a placeholder in `text`, so their transcription
+    /// (or a note that there isn't one) stands in for it.
+    pub fn body_text(&self) -> String {
+        if !self.is_audio_message {
+            return self.text.clone();
+        }
+        match &self.audio_transcription {
+            Some(transcription) => format!("Voice message (transcribed): {}", transcription),
+            None => "Voice message received (no transcription available)".to_string(),
+        }
+    }
+}
+
+/// What kind of message a row represents
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum MessageKind {
+    /// Ordinary text and/or attachments
+    #[default]
+    Text,
+    /// The sender unsent this message; its text is gone
+    Retracted,
+    /// A tapback reaction to another message (associated_message_type 2000-2005, 3000-3005)
+    Tapback {
+        kind: TapbackKind,
+        target_guid: String,
+        removed: bool,
+    },
+    /// Stickers, Digital Touch, and handwriting: attachments with nothing Claude can read
+    NonText { kind: NonTextKind },
+    /// A system row recording a change to a group chat (item_type != 0)
+    GroupEvent { event: GroupEvent },
+}
+
+/// Group chat changes Messages.app records as system rows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupEvent {
+    /// New group name, or None if the name was cleared
+    Renamed { name: Option<String> },
+    ParticipantAdded { handle: String },
+    ParticipantRemoved { handle: String },
+    /// The sender left the group
+    Left,
+    PhotoChanged,
+}
+
+impl GroupEvent {
+    /// What the sender did, with participants as raw handles ("added +16175551234")
+    pub fn describe(&self) -> String {
+        match self {
+            GroupEvent::Renamed { name: Some(name) } => format!("renamed the group to '{}'", name),
+            GroupEvent::Renamed { name: None } => "removed the group name".to_string(),
+            GroupEvent::ParticipantAdded { handle } => format!("added {}", handle),
+            GroupEvent::ParticipantRemoved { handle } => format!("removed {}", handle),
+            GroupEvent::Left => "left the group".to_string(),
+            GroupEvent::PhotoChanged => "changed the group photo".to_string(),
+        }
+    }
+}
+
+/// Kinds of message that carry only a plugin payload, not text or media
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonTextKind {
+    Sticker,
+    DigitalTouch,
+    Handwriting,
+}
+
+impl NonTextKind {
+    /// Short description for summaries ("Jane sent a sticker")
+    pub fn describe(&self) -> &'static str {
+        match self {
+            NonTextKind::Sticker => "a sticker",
+            NonTextKind::DigitalTouch => "a Digital Touch message",
+            NonTextKind::Handwriting => "a handwritten message",
+        }
+    }
+}
+
+/// Tapback reaction types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapbackKind {
+    Love,
+    Like,
+    Dislike,
+    Laugh,
+    Emphasize,
+    Question,
+}
+
+impl TapbackKind {
+    /// Emoji used when describing the reaction to Claude
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            TapbackKind::Love => "\u{2764}\u{fe0f}",
+            TapbackKind::Like => "\u{1f44d}",
+            TapbackKind::Dislike => "\u{1f44e}",
+            TapbackKind::Laugh => "\u{1f602}",
+            TapbackKind::Emphasize => "\u{203c}\u{fe0f}",
+            TapbackKind::Question => "\u{2753}",
+        }
+    }
+}
+
+/// An attachment from a message
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub path: String,
+    pub mime_type: String,
+    pub name: String,
+    pub size: i64,
+}
+
+/// How long SQLite itself waits on a locked database before reporting SQLITE_BUSY
+const BUSY_TIMEOUT_MS: u64 = 100;
+
+/// Backoff between our own retries once SQLite gives up
+const BUSY_RETRY_DELAYS_MS: [u64; 3] = [50, 150, 400];
+
+/// Wait before trying a failed chat.db watch again, doubling with each failure
+const WATCH_RETRY_FIRST: Duration = Duration::from_secs(5);
+/// Longest wait between attempts to watch chat.db
+const WATCH_RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// Backoff while waiting for a new message's chat_message_join row (375ms total)
+const CHAT_JOIN_BACKOFF_MS: [u64; 4] = [25, 50, 100, 200];
+
+/// Polls a message can be held back for a missing chat_message_join before it's let through
+const MAX_CHAT_JOIN_DEFERRALS: u32 = 5;
+
+/// Message ROWIDs per attachment lookup
+const ATTACHMENT_BATCH_SIZE: usize = 500;
+
+/// Columns selected for a single message row, before interpretation
+struct RawMessageRow {
+    rowid: i64,
+    guid: String,
+    date: i64,
+    phone: Option<String>,
+    text: Option<String>,
+    attributed_body: Option<Vec<u8>>,
+    has_attachments: bool,
+    is_audio: bool,
+    is_from_me: bool,
+    chat_style: Option<i32>,
+    display_name: Option<String>,
+    chat_identifier: Option<String>,
+    thread_guid: Option<String>,
+    associated_type: i64,
+    associated_guid: Option<String>,
+    date_edited: i64,
+    summary_info: Option<Vec<u8>>,
+    date_retracted: i64,
+    balloon_bundle_id: Option<String>,
+    service: Option<String>,
+    item_type: i64,
+    group_action_type: i64,
+    group_title: Option<String>,
+    other_handle: Option<String>,
+    date_delivered: i64,
+    date_read: i64,
+    subject: Option<String>,
+    is_spam: bool,
+}
+
+/// Reader for Messages.app database
+pub struct MessagesReader {
+    db_path: std::path::PathBuf,
+    poll_interval: Duration,
+    watcher: Option<WalWatcher>,
+    /// After a failed watch: when to try again, and how long to wait if that fails too
+    watch_retry: Option<(Instant, Duration)>,
+    my_handles: Vec<String>,
+    /// Shared by every query; opened on first use and reopened if it goes stale
+    conn: Mutex<Option<CachedConnection>>,
+    /// Number of times chat.db has been opened
+    opens: AtomicUsize,
+    clock: Box<dyn Clock>,
+    /// Times each currently-deferred ROWID has been held back
+    deferrals: Mutex<HashMap<i64, u32>>,
+    race: RaceCounters,
+}
+
+/// chat.style, chat.display_name, chat.chat_identifier
+type ChatColumns = (Option<i32>, Option<String>, Option<String>);
+
+/// Time source for the chat_message_join backoff, replaceable in tests
+trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+#[derive(Default)]
+struct RaceCounters {
+    requeried: AtomicU64,
+    resolved: AtomicU64,
+    deferred: AtomicU64,
+    gave_up: AtomicU64,
+}
+
+/// Outcomes of the chat_message_join race since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RaceStats {
+    /// Rows first seen without a chat and re-queried
+    pub requeried: u64,
+    /// Re-queries that found the chat during backoff
+    pub resolved: u64,
+    /// Times a row was held back for the next poll
+    pub deferred: u64,
+    /// Rows let through without chat info after too many deferrals
+    pub gave_up: u64,
+}
+
+/// Open chat.db handle plus the file identity it was opened against
+struct CachedConnection {
+    conn: Connection,
+    file_id: Option<(u64, u64)>,
+}
+
+/// (device, inode) of a file, to notice when it's replaced rather than modified
+fn file_id(path: &Path) -> Option<(u64, u64)> {
+    std::fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+/// Filesystem watch on the directory holding chat.db and its -wal/-shm files
+///
+/// The directory is watched rather than the files themselves because checkpointing
+/// can truncate or recreate chat.db-wal, which would orphan a per-file watch.
+struct WalWatcher {
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<notify::Event>>,
+}
+
+impl MessagesReader {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            db_path: config.messages_db.clone(),
+            poll_interval: Duration::from_millis(config.poll_interval_ms),
+            watcher: None,
+            watch_retry: None,
+            my_handles: config.my_handles.clone(),
+            conn: Mutex::new(None),
+            opens: AtomicUsize::new(0),
+            clock: Box::new(SystemClock),
+            deferrals: Mutex::new(HashMap::new()),
+            race: RaceCounters::default(),
+        }
+    }
+
+    /// Block until chat.db (or its WAL) changes, or the timeout elapses
+    ///
+    /// Returns true if the database may have changed and should be queried. If the
+    /// watch can't be established, sleeps `poll_interval_ms` and returns true so the
+    /// caller degrades to plain polling, trying the watch again after a backoff
+    /// that doubles from `WATCH_RETRY_FIRST` up to `WATCH_RETRY_MAX`.
+    pub fn wait_for_changes(&mut self, timeout: Duration) -> bool {
+        let now = self.clock.now();
+        if self.watcher.is_none() && self.watch_retry.is_none_or(|(at, _)| now >= at) {
+            match self.start_watcher() {
+                Ok(watcher) => {
+                    info!("Watching {} for changes", self.db_path.display());
+                    self.watcher = Some(watcher);
+                    self.watch_retry = None;
+                }
+                Err(e) => {
+                    let backoff = self.watch_retry.map_or(WATCH_RETRY_FIRST, |(_, backoff)| backoff);
+                    warn!(
+                        "File watching unavailable, polling and trying again in {}s: {}",
+                        backoff.as_secs(),
+                        e
+                    );
+                    self.watch_retry = Some((now + backoff, (backoff * 2).min(WATCH_RETRY_MAX)));
+                }
+            }
+        }
+
+        let watcher = match &self.watcher {
+            Some(w) => w,
+            None => {
+                std::thread::sleep(self.poll_interval);
+                return true;
+            }
+        };
+
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            match watcher.rx.recv_timeout(remaining) {
+                Ok(Ok(event)) if self.is_db_event(&event) => {
+                    // Coalesce the burst of events a single write produces
+                    while watcher.rx.try_recv().is_ok() {}
+                    return true;
+                }
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => {
+                    debug!("Watch error: {}", e);
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => return false,
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("File watcher stopped, will re-establish");
+                    self.watcher = None;
+                    return true;
+                }
+            }
+        }
+    }
+
+    fn start_watcher(&self) -> notify::Result<WalWatcher> {
+        let dir = self
+            .db_path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(WalWatcher {
+            _watcher: watcher,
+            rx,
+        })
+    }
+
+    /// Whether an event touches chat.db, chat.db-wal, or chat.db-shm
+    fn is_db_event(&self, event: &notify::Event) -> bool {
+        let db_name = match self.db_path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return true,
+        };
+        event.paths.iter().any(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().starts_with(&db_name))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Open a new database connection (read-only to avoid lock contention)
+    fn open_db(&self) -> Result<Connection> {
+        let conn = Connection::open_with_flags(
+            &self.db_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MS))?;
+        Ok(conn)
+    }
+
+    /// Run queries on the cached connection, opening it first if needed
+    ///
+    /// If chat.db was replaced on disk (e.g. restored by an iCloud sync) the old
+    /// handle would keep reading the unlinked file, so it's reopened. A query that
+    /// fails for any non-busy SQLite reason is retried once on a fresh connection.
+    fn with_conn<T>(&self, mut query: impl FnMut(&Connection) -> Result<T>) -> Result<T> {
+        let mut cached = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+
+        let current_id = file_id(&self.db_path);
+        if cached.as_ref().is_some_and(|c| c.file_id != current_id) {
+            info!("chat.db was replaced, reopening");
+            *cached = None;
+        }
+
+        let mut reopened = false;
+        loop {
+            if cached.is_none() {
+                let conn = self.open_db()?;
+                self.opens.fetch_add(1, Ordering::Relaxed);
+                *cached = Some(CachedConnection {
+                    conn,
+                    file_id: file_id(&self.db_path),
+                });
+            }
+            let conn = &cached.as_ref().expect("connection just opened").conn;
+
+            match self.with_busy_retry(|| query(conn)) {
+                Err(e) if !reopened && matches!(e, Error::Sqlite(_)) && !e.is_transient() => {
+                    warn!("chat.db query failed, reopening connection: {}", e);
+                    *cached = None;
+                    reopened = true;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run a query, retrying with backoff while Messages.app holds the write lock
+    fn with_busy_retry<T>(&self, mut query: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match query() {
+                Err(e) if e.is_transient() && attempt < BUSY_RETRY_DELAYS_MS.len() => {
+                    let delay = BUSY_RETRY_DELAYS_MS[attempt];
+                    debug!(attempt = attempt + 1, delay_ms = delay, "chat.db busy, retrying: {}", e);
+                    std::thread::sleep(Duration::from_millis(delay));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Get messages newer than the given ROWID (poll for new messages)
+    pub fn poll(&self, since_rowid: i64) -> Result<Vec<Message>> {
+        self.get_new_messages(since_rowid)
+    }
+
+    /// Get the maximum ROWID (for starting the daemon)
+    pub fn get_max_rowid(&self) -> Result<i64> {
+        self.get_latest_rowid()
+    }
+
+    /// Get messages newer than the given ROWID
+    ///
+    /// A message whose chat_message_join row still hasn't appeared after the
+    /// backoff is deferred: it and everything after it are left for the next
+    /// poll rather than processed with the wrong chat. The backoff is only
+    /// waited out once per message; on later polls a deferred message that's
+    /// still missing its row is deferred again straight away. After
+    /// `MAX_CHAT_JOIN_DEFERRALS` polls it's let through as a 1:1 message.
+    pub fn get_new_messages(&self, since_rowid: i64) -> Result<Vec<Message>> {
+        let mut unresolved = Vec::new();
+        let mut messages = self.with_conn(|conn| {
+            unresolved.clear();
+            self.query_messages(
+                conn,
+                "message.ROWID > ?1 ORDER BY message.date ASC",
+                &[&since_rowid],
+                Some(&mut unresolved),
+            )
+        })?;
+
+        if let Some(cutoff) = self.defer_unresolved(&unresolved) {
+            messages.retain(|m| m.rowid < cutoff);
+        }
+        Ok(messages)
+    }
+
+    /// Wait for a message's chat_message_join row with bounded backoff
+    ///
+    /// Returns the chat columns once the join appears, or None if it's still missing.
+    fn await_chat_join(&self, conn: &Connection, rowid: i64) -> Option<ChatColumns> {
+        let race_start = self.clock.now();
+        self.race.requeried.fetch_add(1, Ordering::Relaxed);
+        info!(rowid = rowid, "[RACE_TELEMETRY] chat_style=NULL on initial query, backing off");
+
+        for delay_ms in CHAT_JOIN_BACKOFF_MS {
+            self.clock.sleep(Duration::from_millis(delay_ms));
+            let requery_result: rusqlite::Result<ChatColumns> = conn.query_row(
+                r#"
+                SELECT chat.style, chat.display_name, chat.chat_identifier
+                FROM message
+                LEFT JOIN chat_message_join ON message.ROWID = chat_message_join.message_id
+                LEFT JOIN chat ON chat_message_join.chat_id = chat.ROWID
+                WHERE message.ROWID = ?1
+                "#,
+                [rowid],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            );
+            let race_elapsed_ms = (self.clock.now() - race_start).as_millis();
+            match requery_result {
+                Ok((Some(style), name, identifier)) => {
+                    self.race.resolved.fetch_add(1, Ordering::Relaxed);
+                    info!(rowid = rowid, elapsed_ms = race_elapsed_ms, chat_style = style, chat_identifier = ?identifier, "[RACE_TELEMETRY] SUCCESS after re-query");
+                    return Some((Some(style), name, identifier));
+                }
+                Ok(_) => {
+                    debug!(rowid = rowid, elapsed_ms = race_elapsed_ms, "[RACE_TELEMETRY] still NULL, backing off");
+                }
+                Err(e) => {
+                    warn!(rowid = rowid, elapsed_ms = race_elapsed_ms, error = ?e, "[RACE_TELEMETRY] NO_ROW after re-query - message may have been deleted");
+                    return None;
+                }
+            }
+        }
+
+        warn!(
+            rowid = rowid,
+            elapsed_ms = (self.clock.now() - race_start).as_millis(),
+            "[RACE_TELEMETRY] STILL_NULL after backoff - join row may not exist yet"
+        );
+        None
+    }
+
+    /// Decide which unresolved rows to hold back, returning the lowest deferred ROWID
+    fn defer_unresolved(&self, unresolved: &[i64]) -> Option<i64> {
+        let mut deferrals = self.deferrals.lock().unwrap_or_else(|e| e.into_inner());
+        // Rows that resolved (or were never deferred) drop out of the table
+        let previous = std::mem::take(&mut *deferrals);
+
+        let mut cutoff: Option<i64> = None;
+        for &rowid in unresolved {
+            let count = previous.get(&rowid).copied().unwrap_or(0) + 1;
+            if count > MAX_CHAT_JOIN_DEFERRALS {
+                self.race.gave_up.fetch_add(1, Ordering::Relaxed);
+                warn!(rowid = rowid, deferrals = count - 1, "[RACE_TELEMETRY] GAVE_UP - processing without chat info");
+                continue;
+            }
+            self.race.deferred.fetch_add(1, Ordering::Relaxed);
+            info!(rowid = rowid, deferral = count, "[RACE_TELEMETRY] DEFERRED to next poll");
+            deferrals.insert(rowid, count);
+            cutoff = Some(cutoff.map_or(rowid, |c| c.min(rowid)));
+        }
+        cutoff
+    }
+
+    /// Counts of chat_message_join race outcomes since startup
+    pub fn race_stats(&self) -> RaceStats {
+        RaceStats {
+            requeried: self.race.requeried.load(Ordering::Relaxed),
+            resolved: self.race.resolved.load(Ordering::Relaxed),
+            deferred: self.race.deferred.load(Ordering::Relaxed),
+            gave_up: self.race.gave_up.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get already-processed messages (ROWID <= up_to_rowid) edited or unsent after the given time
+    ///
+    /// Edits and retractions rewrite the existing row in place, so they never show up
+    /// in `get_new_messages`.
+    pub fn get_modified_messages(
+        &self,
+        up_to_rowid: i64,
+        modified_after: DateTime<Utc>,
+    ) -> Result<Vec<Message>> {
+        let modified_after = datetime_to_macos(modified_after);
+        self.with_conn(|conn| {
+            self.query_messages(
+                conn,
+                "message.ROWID <= ?1 AND (message.date_edited > ?2 OR message.date_retracted > ?2)
+                 ORDER BY MAX(message.date_edited, message.date_retracted) ASC",
+                &[&up_to_rowid, &modified_after],
+                None,
+            )
+        })
+    }
+
+    /// Run the shared message SELECT with the given WHERE/ORDER BY tail
+    ///
+    /// With `unresolved`, rows missing their chat_message_join are given time to
+    /// catch up, and any still missing are recorded there (and returned with 1:1
+    /// fallback chat info). Without it, rows are taken as they are.
+    fn query_messages(
+        &self,
+        conn: &Connection,
+        filter: &str,
+        params: &[&dyn rusqlite::ToSql],
+        mut unresolved: Option<&mut Vec<i64>>,
+    ) -> Result<Vec<Message>> {
+        let sql = format!(
+            r#"
+            SELECT
+                message.ROWID,
+                message.guid,
+                message.date,
+                handle.id as phone,
+                message.text,
+                message.attributedBody,
+                message.cache_has_attachments,
+                message.is_audio_message,
+                message.is_from_me,
+                chat.style,
+                chat.display_name,
+                chat.chat_identifier,
+                message.thread_originator_guid,
+                message.associated_message_type,
+                message.associated_message_guid,
+                message.date_edited,
+                message.message_summary_info,
+                message.date_retracted,
+                message.balloon_bundle_id,
+                COALESCE(message.service, handle.service),
+                message.item_type,
+                message.group_action_type,
+                message.group_title,
+                other_handle.id,
+                message.date_delivered,
+                message.date_read,
+                message.subject,
+                message.is_spam
+            FROM message
+            LEFT JOIN handle ON message.handle_id = handle.ROWID
+            LEFT JOIN handle AS other_handle ON message.other_handle = other_handle.ROWID
+            LEFT JOIN chat_message_join ON message.ROWID = chat_message_join.message_id
+            LEFT JOIN chat ON chat_message_join.chat_id = chat.ROWID
+            WHERE {}
+            "#,
+            filter
+        );
+        let mut stmt = conn.prepare(&sql)?;
+
+        let mut messages = Vec::new();
+        let mut attachment_rowids = Vec::new();
+
+        // Collect before interpreting: while the statement is open its read transaction
+        // pins a WAL snapshot, so a race re-query would never see the missing join row
+        let rows = stmt.query_map(params, |row| {
+            Ok(RawMessageRow {
+                rowid: row.get(0)?,
+                guid: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                date: row.get(2)?,
+                phone: row.get(3)?,
+                text: row.get(4)?,
+                attributed_body: row.get(5)?,
+                has_attachments: row.get::<_, i32>(6)? != 0,
+                is_audio: row.get::<_, i32>(7)? != 0,
+                is_from_me: row.get::<_, i32>(8)? != 0,
+                chat_style: row.get(9)?,
+                display_name: row.get(10)?,
+                chat_identifier: row.get(11)?,
+                thread_guid: row.get(12)?,
+                associated_type: row.get::<_, Option<i64>>(13)?.unwrap_or(0),
+                associated_guid: row.get(14)?,
+                date_edited: row.get::<_, Option<i64>>(15)?.unwrap_or(0),
+                summary_info: row.get(16)?,
+                date_retracted: row.get::<_, Option<i64>>(17)?.unwrap_or(0),
+                balloon_bundle_id: row.get(18)?,
+                service: row.get(19)?,
+                item_type: row.get::<_, Option<i64>>(20)?.unwrap_or(0),
+                group_action_type: row.get::<_, Option<i64>>(21)?.unwrap_or(0),
+                group_title: row.get(22)?,
+                other_handle: row.get(23)?,
+                date_delivered: row.get::<_, Option<i64>>(24)?.unwrap_or(0),
+                date_read: row.get::<_, Option<i64>>(25)?.unwrap_or(0),
+                subject: row.get(26)?,
+                is_spam: row.get::<_, Option<i32>>(27)?.unwrap_or(0) != 0,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+        drop(stmt);
+
+        for row in rows {
+            let RawMessageRow {
+                rowid,
+                guid,
+                date,
+                phone,
+                text,
+                attributed_body,
+                has_attachments,
+                is_audio,
+                is_from_me,
+                chat_style,
+                display_name,
+                chat_identifier,
+                thread_guid,
+                associated_type,
+                associated_guid,
+                date_edited,
+                summary_info,
+                date_retracted,
+                balloon_bundle_id,
+                service,
+                item_type,
+                group_action_type,
+                group_title,
+                other_handle,
+                date_delivered,
+                date_read,
+                subject,
+                is_spam,
+            } = row;
+
+            // Skip if no phone (what we send to groups has no handle, but still counts)
+            let phone = match phone {
+                Some(p) => p,
+                None if is_from_me => String::new(),
+                None => continue,
+            };
+
+            // Race condition fix: if chat_style is None, the chat_message_join row might not
+            // have been written yet. Back off and re-query this specific message.
+            let (chat_style, display_name, chat_identifier) = match (chat_style, unresolved.as_deref_mut()) {
+                (None, Some(unresolved)) if self.deferrals.lock().unwrap_or_else(|e| e.into_inner()).contains_key(&rowid) => {
+                    debug!(rowid = rowid, "[RACE_TELEMETRY] still NULL on a deferred row, not backing off again");
+                    unresolved.push(rowid);
+                    (chat_style, display_name, chat_identifier)
+                }
+                (None, Some(unresolved)) => match self.await_chat_join(conn, rowid) {
+                    Some(chat) => chat,
+                    None => {
+                        unresolved.push(rowid);
+                        (chat_style, display_name, chat_identifier)
+                    }
+                },
+                _ => (chat_style, display_name, chat_identifier),
+            };
+
+            // Tapbacks carry their own "Loved \u{201c}...\u{201d}" text; classify before parsing.
+            // Unsent messages keep their row but lose the text.
+            let retracted = date_retracted > 0
+                || summary_info.as_deref().is_some_and(is_retracted_summary);
+            let kind = if item_type != 0 {
+                // Other system rows (kept audio, FaceTime, ...) have nothing to inject
+                match classify_group_event(item_type, group_action_type, group_title, other_handle) {
+                    Some(event) => MessageKind::GroupEvent { event },
+                    None => continue,
+                }
+            } else if retracted {
+                MessageKind::Retracted
+            } else if let Some(kind) = classify_non_text(balloon_bundle_id.as_deref(), associated_type) {
+                MessageKind::NonText { kind }
+            } else {
+                classify_associated(associated_type, associated_guid.as_deref())
+            };
+
+            // Parse attributed body if text is None
+            let (msg_text, audio_transcription) = match (&text, &attributed_body) {
+                (Some(t), _) if !t.is_empty() && t != "\u{fffc}" => (Some(t.clone()), None),
+                (_, Some(blob)) => {
+                    let (parsed_text, audio) = parse_attributed_body(blob);
+                    (parsed_text, audio)
+                }
+                _ => (None, None),
+            };
+
+            // Mentions live in the attributed runs even when the plain text column is set
+            let mentions = attributed_body.as_deref().map(parse_mentions).unwrap_or_default();
+            let mentions_me = mentions
+                .iter()
+                .any(|m| self.my_handles.iter().any(|h| same_handle(m, h)));
+
+            // Skip if no text and no attachments
+            if kind == MessageKind::Text && msg_text.is_none() && !has_attachments {
+                continue;
+            }
+
+            // Edit history lives in the message_summary_info plist
+            let edit_history = summary_info
+                .as_deref()
+                .map(parse_edit_history)
+                .unwrap_or_default();
+
+            // Attachments are filled in afterwards with a single batched query
+            if has_attachments {
+                attachment_rowids.push(rowid);
+            }
+
+            // Detect group chat (style 43 = group, 45 = 1:1)
+            let is_group = chat_style == Some(43);
+
+            let timestamp = macos_to_datetime(date);
+
+            // Determine chat_id (phone for 1:1, UUID for groups)
+            let chat_id = chat_identifier.clone().unwrap_or_else(|| phone.clone());
+
+            messages.push(Message {
+                rowid,
+                guid,
+                timestamp,
+                sender: phone.clone(),
+                text: msg_text.unwrap_or_default(),
+                chat_id,
+                is_from_me,
+                is_group,
+                group_name: if is_group { display_name } else { None },
+                attachments: Vec::new(),
+                is_audio_message: is_audio,
+                audio_transcription,
+                thread_originator_guid: thread_guid,
+                kind,
+                edited: date_edited > 0,
+                edit_history,
+                date_edited: (date_edited > 0).then(|| macos_to_datetime(date_edited)),
+                date_retracted: (date_retracted > 0).then(|| macos_to_datetime(date_retracted)),
+                service: service.as_deref().map(MessageService::from_db).unwrap_or_default(),
+                mentions,
+                mentions_me,
+                date_delivered: (date_delivered > 0).then(|| macos_to_datetime(date_delivered)),
+                date_read: (date_read > 0).then(|| macos_to_datetime(date_read)),
+                subject: subject.map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+                is_spam,
+            });
+        }
+
+        if !attachment_rowids.is_empty() {
+            let mut by_message = self.get_attachments(conn, &attachment_rowids)?;
+            for msg in &mut messages {
+                if let Some(attachments) = by_message.remove(&msg.rowid) {
+                    msg.attachments = attachments;
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Last `limit` messages in a chat, both directions, oldest first
+    pub fn get_recent_messages(&self, chat_id: &str, limit: usize) -> Result<Vec<Message>> {
+        let limit = limit as i64;
+        let mut recent = self.with_conn(|conn| {
+            self.query_messages(
+                conn,
+                "chat.chat_identifier = ?1 ORDER BY message.date DESC LIMIT ?2",
+                &[&chat_id, &limit],
+                None,
+            )
+        })?;
+        recent.reverse();
+        Ok(recent)
+    }
+
+    /// Newest inbound message in a chat, sent before `older_than`, with no outbound message after it
+    ///
+    /// Tapbacks and system rows don't count as messages needing a reply.
+    pub fn get_unreplied(&self, chat_id: &str, older_than: DateTime<Utc>) -> Result<Option<Message>> {
+        let older_than = datetime_to_macos(older_than);
+        let mut found = self.with_conn(|conn| {
+            self.query_messages(
+                conn,
+                "chat.chat_identifier = ?1
+                 AND message.is_from_me = 0
+                 AND message.item_type = 0
+                 AND message.associated_message_type = 0
+                 AND message.date <= ?2
+                 AND NOT EXISTS (
+                     SELECT 1 FROM message AS reply
+                     JOIN chat_message_join AS reply_join ON reply.ROWID = reply_join.message_id
+                     WHERE reply_join.chat_id = chat.ROWID
+                       AND reply.is_from_me = 1
+                       AND reply.date > message.date
+                 )
+                 ORDER BY message.date DESC LIMIT 1",
+                &[&chat_id, &older_than],
+                None,
+            )
+        })?;
+        Ok(found.pop())
+    }
+
+    /// Look up a single message by GUID (e.g. the original of a reply thread)
+    pub fn get_message_by_guid(&self, guid: &str) -> Result<Option<Message>> {
+        let mut found = self.with_conn(|conn| self.query_messages(conn, "message.guid = ?1", &[&guid], None))?;
+        Ok(found.pop())
+    }
+
+    /// Look up a single message by ROWID
+    pub fn get_message_by_rowid(&self, rowid: i64) -> Result<Option<Message>> {
+        let mut found = self.with_conn(|conn| self.query_messages(conn, "message.ROWID = ?1", &[&rowid], None))?;
+        Ok(found.pop())
+    }
+
+    /// Handle IDs (phones/emails) of everyone in a chat, excluding ourselves
+    pub fn get_chat_participants(&self, chat_identifier: &str) -> Result<Vec<String>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT DISTINCT handle.id
+                FROM chat
+                JOIN chat_handle_join ON chat.ROWID = chat_handle_join.chat_id
+                JOIN handle ON chat_handle_join.handle_id = handle.ROWID
+                WHERE chat.chat_identifier = ?1
+                ORDER BY handle.id
+                "#,
+            )?;
+            let participants = stmt
+                .query_map([chat_identifier], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(participants)
+        })
+    }
+
+    /// Get the most recent message ROWID
+    pub fn get_latest_rowid(&self) -> Result<i64> {
+        self.with_conn(|conn| {
+            let rowid: i64 = conn.query_row("SELECT MAX(ROWID) FROM message", [], |row| row.get(0))?;
+            Ok(rowid)
+        })
+    }
+
+    /// ROWID to poll from so that every message sent at or after `since` is picked up
+    ///
+    /// That's one below the first such row, or the current max if nothing is that recent.
+    pub fn rowid_before(&self, since: DateTime<Utc>) -> Result<i64> {
+        let since = datetime_to_macos(since);
+        self.with_conn(|conn| {
+            let rowid: i64 = conn.query_row(
+                "SELECT COALESCE(
+                     (SELECT MIN(ROWID) - 1 FROM message WHERE date >= ?1),
+                     (SELECT COALESCE(MAX(ROWID), 0) FROM message)
+                 )",
+                [since],
+                |row| row.get(0),
+            )?;
+            Ok(rowid)
+        })
+    }
+
+    /// Number of messages above a ROWID
+    pub fn count_since(&self, rowid: i64) -> Result<i64> {
+        self.with_conn(|conn| {
+            let count: i64 =
+                conn.query_row("SELECT COUNT(*) FROM message WHERE ROWID > ?1", [rowid], |row| row.get(0))?;
+            Ok(count)
+        })
+    }
+
+    /// Get attachments for a batch of messages, keyed by message ROWID
+    fn get_attachments(
+        &self,
+        conn: &Connection,
+        message_rowids: &[i64],
+    ) -> Result<HashMap<i64, Vec<Attachment>>> {
+        let mut by_message: HashMap<i64, Vec<Attachment>> = HashMap::new();
+
+        // Stay well under SQLite's bound-parameter limit
+        for chunk in message_rowids.chunks(ATTACHMENT_BATCH_SIZE) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let sql = format!(
+                r#"
+                SELECT
+                    message_attachment_join.message_id,
+                    attachment.filename,
+                    attachment.mime_type,
+                    attachment.transfer_name,
+                    attachment.total_bytes
+                FROM attachment
+                JOIN message_attachment_join ON attachment.ROWID = message_attachment_join.attachment_id
+                WHERE message_attachment_join.message_id IN ({})
+                ORDER BY message_attachment_join.message_id, attachment.ROWID
+                "#,
+                placeholders
+            );
+            let mut stmt = conn.prepare(&sql)?;
+
+            let rows = stmt
+                .query_map(rusqlite::params_from_iter(chunk), |row| {
+                    let message_id: i64 = row.get(0)?;
+                    let filename: Option<String> = row.get(1)?;
+                    let mime_type: Option<String> = row.get(2)?;
+                    let transfer_name: Option<String> = row.get(3)?;
+                    let total_bytes: Option<i64> = row.get(4)?;
+
+                    Ok((message_id, filename, mime_type, transfer_name, total_bytes))
+                })?
+                .filter_map(|r| r.ok());
+
+            for (message_id, filename, mime_type, transfer_name, total_bytes) in rows {
+                if let Some(attachment) = build_attachment(filename, mime_type, transfer_name, total_bytes) {
+                    by_message.entry(message_id).or_default().push(attachment);
+                }
+            }
+        }
+
+        Ok(by_message)
+    }
+}
+
+/// Turn an attachment row into an Attachment, skipping rows without a file
+fn build_attachment(
+    filename: Option<String>,
+    mime_type: Option<String>,
+    transfer_name: Option<String>,
+    total_bytes: Option<i64>,
+) -> Option<Attachment> {
+    let path = filename?;
+    // Expand ~ to home dir
+    let expanded = if path.starts_with("~/") {
+        dirs::home_dir()
+            .map(|h| h.join(&path[2..]).to_string_lossy().to_string())
+            .unwrap_or(path.clone())
+    } else {
+        path.clone()
+    };
+
+    Some(Attachment {
+        mime_type: mime_type
+            .filter(|m| !m.is_empty())
+            .unwrap_or_else(|| attachments::sniff_mime(Path::new(&expanded))),
+        name: transfer_name.unwrap_or_else(|| {
+            Path::new(&path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        }),
+        size: total_bytes.unwrap_or(0),
+        path: expanded,
+    })
+}
+
+/// Classify a row by its associated_message_type / associated_message_guid
+///
+/// 2000-2005 add a tapback, 3000-3005 remove one. The GUID is prefixed with the
+/// message part it targets ("p:0/", "bp:"), which is stripped.
+pub fn classify_associated(associated_type: i64, associated_guid: Option<&str>) -> MessageKind {
+    let (base, removed) = match associated_type {
+        2000..=2005 => (associated_type - 2000, false),
+        3000..=3005 => (associated_type - 3000, true),
+        _ => return MessageKind::Text,
+    };
+
+    let kind = match base {
+        0 => TapbackKind::Love,
+        1 => TapbackKind::Like,
+        2 => TapbackKind::Dislike,
+        3 => TapbackKind::Laugh,
+        4 => TapbackKind::Emphasize,
+        _ => TapbackKind::Question,
+    };
+
+    let raw_guid = associated_guid.unwrap_or("");
+    let target_guid = match raw_guid.find('/') {
+        Some(pos) => &raw_guid[pos + 1..],
+        None => raw_guid.strip_prefix("bp:").unwrap_or(raw_guid),
+    };
+
+    MessageKind::Tapback {
+        kind,
+        target_guid: target_guid.to_string(),
+        removed,
+    }
+}
+
+/// "Recent conversation context" block for a freshly (re)created session
+///
+/// `name_for` labels each message's sender. Returns None when there's no history.
+pub fn conversation_context(
+    history: &[Message],
+    mut name_for: impl FnMut(&Message) -> String,
+) -> Option<String> {
+    if history.is_empty() {
+        return None;
+    }
+    let mut block = String::from("---Recent conversation context (oldest first)---");
+    for msg in history {
+        let text = match &msg.kind {
+            MessageKind::Text => msg.body_text(),
+            MessageKind::Retracted => "(unsent a message)".to_string(),
+            MessageKind::NonText { kind } => format!("(sent {})", kind.describe()),
+            MessageKind::Tapback { kind, .. } => format!("(reacted {})", kind.emoji()),
+            MessageKind::GroupEvent { event } => format!("({})", event.describe()),
+        };
+        let attachments = if msg.attachments.is_empty() {
+            String::new()
+        } else {
+            format!(" [{} attachment(s)]", msg.attachments.len())
+        };
+        block.push_str(&format!(
+            "\n[{}] {}: {}{}",
+            msg.timestamp.format("%Y-%m-%d %H:%M"),
+            name_for(msg),
+            text,
+            attachments
+        ));
+    }
+    block.push_str("\n---END CONTEXT---");
+    Some(block)
+}
+
+/// Recognize sticker, Digital Touch, and handwriting messages
+///
+/// These are identified by the balloon plugin that renders them, except stickers
+/// peeled onto an existing bubble, which use associated_message_type 1000.
+/// URL previews and other plugins that carry real text are left alone.
+pub fn classify_non_text(balloon_bundle_id: Option<&str>, associated_type: i64) -> Option<NonTextKind> {
+    if associated_type == 1000 {
+        return Some(NonTextKind::Sticker);
+    }
+    let bundle = balloon_bundle_id?;
+    if bundle.contains("DigitalTouchBalloonProvider") {
+        Some(NonTextKind::DigitalTouch)
+    } else if bundle.contains("HandwritingProvider") {
+        Some(NonTextKind::Handwriting)
+    } else if bundle.contains("Sticker") {
+        Some(NonTextKind::Sticker)
+    } else {
+        None
+    }
+}
+
+/// Interpret a system row's item_type / group_action_type
+///
+/// item_type 1 is a membership change (action 0 added, 1 removed, naming
+/// `other_handle`), 2 a rename to `group_title`, and 3 the sender leaving
+/// (action 0) or changing the group photo (action 1).
+pub fn classify_group_event(
+    item_type: i64,
+    group_action_type: i64,
+    group_title: Option<String>,
+    other_handle: Option<String>,
+) -> Option<GroupEvent> {
+    match (item_type, group_action_type) {
+        (1, 0) => other_handle.map(|handle| GroupEvent::ParticipantAdded { handle }),
+        (1, 1) => other_handle.map(|handle| GroupEvent::ParticipantRemoved { handle }),
+        (2, _) => Some(GroupEvent::Renamed {
+            name: group_title.filter(|t| !t.trim().is_empty()),
+        }),
+        (3, 0) => Some(GroupEvent::Left),
+        (3, 1) => Some(GroupEvent::PhotoChanged),
+        _ => None,
+    }
+}
+
+/// Check whether a message_summary_info plist records retracted (unsent) parts
+pub fn is_retracted_summary(data: &[u8]) -> bool {
+    plist::from_bytes::<plist::Value>(data)
+        .ok()
+        .and_then(|v| v.as_dictionary().and_then(|d| d.get("rp")).cloned())
+        .and_then(|rp| rp.as_array().map(|parts| !parts.is_empty()))
+        .unwrap_or(false)
+}
+
+/// Bounded record of recently injected messages, keyed by GUID
+///
+/// Lets the daemon tie a later retraction back to something Claude has already seen.
+pub struct RecentMessages {
+    capacity: usize,
+    order: VecDeque<String>,
+    entries: HashMap<String, RecentMessage>,
+}
+
+/// What we remember about an injected message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentMessage {
+    pub rowid: i64,
+    pub chat_id: String,
+    pub text: String,
+}
+
+impl RecentMessages {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Remember an injected message, evicting the oldest when full
+    pub fn record(&mut self, msg: &Message, text: &str) {
+        if msg.guid.is_empty() {
+            return;
+        }
+        if self.entries.contains_key(&msg.guid) {
+            self.order.retain(|g| g != &msg.guid);
+        }
+        self.order.push_back(msg.guid.clone());
+        self.entries.insert(
+            msg.guid.clone(),
+            RecentMessage {
+                rowid: msg.rowid,
+                chat_id: msg.chat_id.clone(),
+                text: text.to_string(),
+            },
+        );
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    /// Look up a previously injected message by GUID
+    pub fn get(&self, guid: &str) -> Option<&RecentMessage> {
+        self.entries.get(guid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Convert macOS nanosecond timestamp to DateTime<Utc>
+fn macos_to_datetime(ts: i64) -> DateTime<Utc> {
+    let unix_ts = ts.div_euclid(1_000_000_000) + MACOS_EPOCH_OFFSET;
+    let nanos = ts.rem_euclid(1_000_000_000) as u32;
+    Utc.timestamp_opt(unix_ts, nanos).unwrap()
+}
+
+/// Convert DateTime<Utc> to a macOS nanosecond timestamp (inverse of `macos_to_datetime`)
+fn datetime_to_macos(dt: DateTime<Utc>) -> i64 {
+    (dt.timestamp() - MACOS_EPOCH_OFFSET) * 1_000_000_000 + dt.timestamp_subsec_nanos() as i64
+}
+
+/// Extract every version of an edited message from its message_summary_info plist
+///
+/// The plist has an "ec" (edited content) dictionary keyed by message part index,
+/// each holding an array of `{ d: date, t: <attributedBody typedstream> }` entries.
+/// Returns the texts part by part in part order, each part's oldest first; parts
+/// without text, like attachments, add nothing. Empty if the message was never edited.
+pub fn parse_edit_history(data: &[u8]) -> Vec<String> {
+    let value = match plist::from_bytes::<plist::Value>(data) {
+        Ok(v) => v,
+        Err(_) => return Vec::new(),
+    };
+
+    let parts = match value
+        .as_dictionary()
+        .and_then(|d| d.get("ec"))
+        .and_then(|ec| ec.as_dictionary())
+    {
+        Some(parts) => parts,
+        None => return Vec::new(),
+    };
+
+    let mut keys: Vec<&String> = parts.keys().collect();
+    keys.sort_by_key(|k| k.parse::<u32>().unwrap_or(u32::MAX));
+
+    let mut history = Vec::new();
+    for key in keys {
+        let entries = match parts.get(key).and_then(|v| v.as_array()) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        for entry in entries {
+            let blob = entry
+                .as_dictionary()
+                .and_then(|d| d.get("t"))
+                .and_then(|t| t.as_data());
+            if let Some((Some(text), _)) = blob.map(parse_attributed_body) {
+                history.push(text);
+            }
+        }
+    }
+
+    history
+}
+
+/// Parse NSAttributedString from attributedBody blob
+/// Returns (message_text, audio_transcription)
+///
+/// Decodes the typedstream properly when possible and falls back to scanning
+/// for known markers if the archive doesn't decode.
+pub fn parse_attributed_body(data: &[u8]) -> (Option<String>, Option<String>) {
+    match typedstream::decode_attributed_string(data) {
+        Ok(decoded) => {
+            // U+FFFC marks where attachments sit inline; it isn't message text
+            let text = decoded.text.replace('\u{fffc}', "");
+            let text = Some(text.trim().to_string()).filter(|t| !t.is_empty());
+            let audio = match decoded.attribute("IMAudioTranscription") {
+                Some(typedstream::AttributeValue::String(t)) => Some(t.clone()),
+                _ => None,
+            };
+            (text, audio)
+        }
+        Err(e) => {
+            debug!("typedstream decode failed, using heuristics: {}", e);
+            let text = extract_message_text(data);
+            let audio = extract_audio_transcription(data);
+            (text, audio)
+        }
+    }
+}
+
+/// Handles @-mentioned in an attributedBody blob, in order of appearance
+pub fn parse_mentions(data: &[u8]) -> Vec<String> {
+    let Ok(decoded) = typedstream::decode_attributed_string(data) else {
+        return Vec::new();
+    };
+    let mut mentions: Vec<String> = Vec::new();
+    for run in &decoded.runs {
+        for (key, value) in &run.attributes {
+            if let ("__kIMMentionConfirmedMention", typedstream::AttributeValue::String(handle)) =
+                (key.as_str(), value)
+            {
+                if !mentions.contains(handle) {
+                    mentions.push(handle.clone());
+                }
+            }
+        }
+    }
+    mentions
+}
+
+/// Whether two handles refer to the same person
+///
+/// Emails compare case-insensitively; phone numbers compare by their last ten
+/// digits so "+1 (617) 555-1234" matches "6175551234".
+pub fn same_handle(a: &str, b: &str) -> bool {
+    if a.contains('@') || b.contains('@') {
+        return a.trim().eq_ignore_ascii_case(b.trim());
+    }
+    let digits = |s: &str| s.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+    let (a, b) = (digits(a), digits(b));
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    let tail = |s: &str| s[s.len().saturating_sub(10)..].to_string();
+    tail(&a) == tail(&b)
+}
+
+/// Extract main message text from blob
+fn extract_message_text(data: &[u8]) -> Option<String> {
+    let markers: &[&[u8]] = &[b"NSString", b"NSMutableString"];
+
+    for marker in markers {
+        if let Some(pos) = find_subsequence(data, marker) {
+            let after_marker = &data[pos + marker.len()..];
+            if let Some(text) = extract_text_after_marker(after_marker) {
+                return Some(text);
+            }
+        }
+    }
+
+    // Fallback: try plist parsing
+    parse_via_plist(data)
+}
+
+/// How far past the "IMAudioTranscription" key its string value may start
+///
+/// The value follows within a few dozen bytes; without a bound a multi-MB blob
+/// with no transcription gets scanned to the end.
+const AUDIO_SEARCH_WINDOW: usize = 1024;
+
+/// Extract audio transcription (Apple's speech-to-text for voice messages)
+fn extract_audio_transcription(data: &[u8]) -> Option<String> {
+    let marker = b"IMAudioTranscription";
+
+    if let Some(pos) = find_subsequence(data, marker) {
+        let after_marker = &data[pos + marker.len()..];
+        let end = after_marker.len().saturating_sub(10).min(AUDIO_SEARCH_WINDOW);
+
+        for i in 0..end {
+            let slice = &after_marker[i..];
+
+            // 2-byte length encoding (0x81 prefix)
+            if slice.len() > 4 && slice[0] == 0x81 {
+                let len = u16::from_le_bytes([slice[1], slice[2]]) as usize;
+                if len > 10 && len < 5000 && slice.len() > 3 + len {
+                    let text_bytes = &slice[3..3 + len];
+                    if let Ok(text) = std::str::from_utf8(text_bytes) {
+                        let cleaned = text.trim();
+                        if !cleaned.is_empty() && cleaned.chars().any(|c| c.is_alphabetic()) {
+                            return Some(cleaned.to_string());
+                        }
+                    }
+                }
+            }
+
+            // 1-byte length for shorter transcriptions
+            if slice.len() > 2 {
+                let len = slice[0] as usize;
+                if len > 10 && len < 128 && slice.len() > 1 + len {
+                    let text_bytes = &slice[1..1 + len];
+                    if let Ok(text) = std::str::from_utf8(text_bytes) {
+                        let cleaned = text.trim();
+                        if !cleaned.is_empty() && cleaned.chars().any(|c| c.is_alphabetic()) {
+                            return Some(cleaned.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn extract_text_after_marker(data: &[u8]) -> Option<String> {
+    let limit = data.len().saturating_sub(10);
+
+    // Only positions holding the '+' (NSString) type byte can start a string
+    for i in memchr::memchr_iter(0x2B, &data[..limit]) {
+        let slice = &data[i..];
+
+        // Format 1: 0x2B <1-byte length> <text>
+        if slice.len() > 2 {
+            let len = slice[1] as usize;
+            if len > 0 && len < 128 && slice.len() > 2 + len {
+                let text_bytes = &slice[2..2 + len];
+                if let Ok(text) = std::str::from_utf8(text_bytes) {
+                    if is_valid_message_text(text) {
+                        return Some(text.to_string());
+                    }
+                }
+            }
+        }
+
+        // Format 2: 0x2B 0x81 <2-byte length LE> <text>
+        if slice.len() > 4 && slice[1] == 0x81 {
+            let len = u16::from_le_bytes([slice[2], slice[3]]) as usize;
+            if len > 0 && slice.len() > 4 + len {
+                let text_bytes = &slice[4..4 + len];
+                if let Ok(text) = std::str::from_utf8(text_bytes) {
+                    if is_valid_message_text(text) {
+                        return Some(text.to_string());
+                    }
+                }
+            }
+        }
+
+        // Format 3: 0x2B 0x82 <4-byte length LE> <text>
+        if slice.len() > 6 && slice[1] == 0x82 {
+            let len = u32::from_le_bytes([slice[2], slice[3], slice[4], slice[5]]) as usize;
+            if len > 0 && len < 100_000 && slice.len() > 6 + len {
+                let text_bytes = &slice[6..6 + len];
+                if let Ok(text) = std::str::from_utf8(text_bytes) {
+                    if is_valid_message_text(text) {
+                        return Some(text.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    memchr::memmem::find(haystack, needle)
+}
+
+fn is_valid_message_text(text: &str) -> bool {
+    !text.is_empty() && text.len() > 1 && text.chars().any(|c| c.is_alphabetic())
+}
+
+fn parse_via_plist(data: &[u8]) -> Option<String> {
+    match plist::from_bytes::<plist::Value>(data) {
+        Ok(value) => extract_string_from_plist(&value),
+        Err(_) => None,
+    }
+}
+
+fn extract_string_from_plist(value: &plist::Value) -> Option<String> {
+    match value {
+        plist::Value::String(s) => Some(s.clone()),
+        plist::Value::Dictionary(dict) => {
+            if let Some(plist::Value::Array(objects)) = dict.get("$objects") {
+                for obj in objects {
+                    if let plist::Value::String(s) = obj {
+                        if is_valid_message_text(s) {
+                            return Some(s.clone());
+                        }
+                    }
+                    if let plist::Value::Dictionary(inner) = obj {
+                        if let Some(plist::Value::String(s)) = inner.get("NS.string") {
+                            return Some(s.clone());
+                        }
+                    }
+                }
+            }
+            None
+        }
+        plist::Value::Array(arr) => {
+            for item in arr {
+                if let Some(s) = extract_string_from_plist(item) {
+                    return Some(s);
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+    use std::sync::Arc;
+
+    // Test blob: "i think we can drop haiku..."
+    const TEST_BLOB_SIMPLE: &str = "040B73747265616D747970656481E803840140848484124E5341747472696275746564537472696E67008484084E534F626A656374008592848484084E53537472696E67019484012B6669207468696E6B2077652063616E2064726F70206861696B7520736F207765206A7573742075736520746D75782072696768743F20616E64207468656E20666F72204E534174747269627574656453747269696E6720706C656173652070726F746F7479706586840269490166928484840C4E5344696374696F6E617279009484016901928496961D5F5F6B494D4D657373616765506172744174747269627574654E616D658692848484084E534E756D626572008484074E5356616C7565009484012A84999900868686";
+
+    // Test blob: long text (165 chars)
+    const TEST_BLOB_LONG: &str = "040B73747265616D747970656481E803840140848484124E5341747472696275746564537472696E67008484084E534F626A656374008592848484084E53537472696E67019484012B81A5007765206861766520746F207265777269746520697420616C6C2E20706C656173652064657269736B2065766572797468696E67206279206C61756E6368696E67207375626167656E7420666F72206561636820636F6D706F6E656E7420616E6420676F6F676C6520666F7220727573742076657273696F6E732E207468656E20657374696D61746520706572666F726D616E636520696E637265617365206F76657220707986840269490181A500928484840C4E5344696374696F6E617279009484016901928496961D5F5F6B494D4D657373616765506172744174747269627574654E616D658692848484084E534E756D626572008484074E5356616C7565009484012A84999900868686";
+
+    // Test blob: URL with link attributes
+    const TEST_BLOB_URL: &str = "040B73747265616D747970656481E803840140848484194E534D757461626C6541747472696275746564537472696E67008484124E5341747472696275746564537472696E67008484084E534F626A6563740085928484840F4E534D757461626C65537472696E67018484084E53537472696E67019584012B2368747470733A2F2F6769746875622E636F6D2F6F6272612F7375706572706F7765727386840269490123928484840C4E5344696374696F6E61727900958401690592849898265F5F6B494D4261736557726974696E67446972656374696F6E4174747269627574654E616D658692848484084E534E756D626572008484074E5356616C7565009584012A848401719FFF8692849898205F5F6B494D4C696E6B4973526963684C696E6B4174747269627574654E616D658692849D9E84840163A0018692849898165F5F6B494D4C696E6B4174747269627574654E616D658692848484054E5355524C0095A000928498982368747470733A2F2F6769746875622E636F6D2F6F6272612F7375706572706F776572738686928498981D5F5F6B494D4D657373616765506172744174747269627574654E616D658692849D9E9F9F0086928498981E5F5F6B494D4461746144657465637465644174747269627574654E616D658692848484064E534461746100959B81350284065B353635635D62706C6973743030D4010203040506070C582476657273696F6E592461726368697665725424746F7058246F626A6563747312000186A05F100F4E534B657965644172636869766572D208090A0B5776657273696F6E5964642D726573756C74800B8001AC0D0E1C2425262C2D2E32353955246E756C6CD70F101112131415161718191A1B1A524D535624636C6173735241525154515052535252564E8006800A8002800710018008D41D1E1F10202122235F10124E532E72616E676576616C2E6C656E6774685F10144E532E72616E676576616C2E6C6F636174696F6E5A4E532E7370656369616C800380041004800510231000D22728292A5A24636C6173736E616D655824636C6173736573574E5356616C7565A2292B584E534F626A6563745F102368747470733A2F2F6769746875622E636F6D2F6F6272612F7375706572706F77657273574874747055524CD22F1030315A4E532E6F626A65637473A08009D227283334574E534172726179A2332BD2272836375F100F44445363616E6E6572526573756C74A2382B5F100F44445363616E6E6572526573756C74100100080011001A00240029003200370049004E005600600062006400710077008600890090009300950097009A009D009F00A100A300A500A700A900B200C700DE00E900EB00ED00EF00F100F300F500FA0105010E0116011901220148015001550160016101630168017001730178018A018D019F0000000000000201000000000000003A000000000000000000000000000001A1868686";
+
+    // Test blob: Audio message with transcription
+    const TEST_BLOB_AUDIO: &str = "040B73747265616D747970656481E803840140848484124E5341747472696275746564537472696E67008484084E534F626A656374008592848484084E53537472696E67019484012B03EFBFBC86840269490101928484840C4E5344696374696F6E61727900948401690492849696225F5F6B494D46696C655472616E73666572475549444174747269627574654E616D6586928496962961745F305F38463932454445322D373631372D343939312D423939432D383834313134334341463138869284969614494D417564696F5472616E736372697074696F6E869284969681C2024F6E636520796F7527726520646F6E6520646F696E6720746861742C207768617420492077616E7420796F7520746F20646F20697320726561642074686520726F6F7420636C6F74204D4420746F2067657420612073656E736520666F7220616C6C206F6620746865207468696E6773207468617420617265206F6E207468697320636F6D707574657220616E64207468656E20492077616E7420796F7520746F20666F722065616368206F66207468652066757475726573206C6973746564206F7574207468657265206C61756E6368206120737562206167656E7420746F20646F20726573656172636820746861742073686F756C64206265206174206C6561737420612070616765206F722074776F206F662065786163746C7920686F7720697420776F726B73206F6E2074686973206D616368696E6520736372756262696E6720616C6C206F662074686520706572736F6E616C2064657461696C73206E616D65732074686174206B696E64206F66207468696E67206A757374206B6565702069742E2049206C6F7665206F6E652067656E6572616C2077726974696E67206120626967207265706F727420746861742073686F756C64206265206C696B6520313020746F203135207061676573206B696E64206F66207468696E67207468656E20636F6E76657274207468617420746F20612050444620616E64207468656E2070617374652069742068657265206F6E636520796F7520646F2074686174207468656E20636F6E7665727420746861742050444620666F72206F75722054657861732073706565636820616E64206174746163682E2054686520617564696F20746F2074686973207468726561642061732077656C6C2C20736F20646F2074686174206F6E636520796F7527726520646F6E652077697468207468697320576861746576657220796F7527726520646F696E67207269676874206E6F778692849696265F5F6B494D4261736557726974696E67446972656374696F6E4174747269627574654E616D658692848484084E534E756D626572008484074E5356616C7565009484012A848401719DFF86928496961D5F5F6B494D4D657373616765506172744174747269627574654E616D658692849F9CA19D00868686";
+
+    // Test blob: emoji only, including a ZWJ family and a skin-tone modifier
+    const TEST_BLOB_EMOJI: &str = "040B73747265616D747970656481E803840140848484124E5341747472696275746564537472696E67008484084E534F626A656374008592848484084E53537472696E67019484012B44F09F8E89F09F8E89F09F8E82F09FA5B320E29DA4EFB88FE29DA4EFB88F20F09F91A8E2808DF09F91A9E2808DF09F91A7F09F918DF09F8FBDF09F8DB0F09F8DBEF09FA58286840269490120928484840C4E5344696374696F6E617279009484016901928496961D5F5F6B494D4D657373616765506172744174747269627574654E616D658692848484084E534E756D626572008484074E5356616C7565009484012A84999900868686";
+
+    // Test blob: "Hey Jane, ..." with an inline mention run; the trailing run reuses the first run's attributes
+    const TEST_BLOB_MENTION: &str = "040B73747265616D747970656481E803840140848484124E5341747472696275746564537472696E67008484084E534F626A656374008592848484084E53537472696E67019484012B2C486579204A616E652C2061726520796F75207374696C6C20636F6D696E6720746F6E696768743F20F09F8D9586840269490104928484840C4E5344696374696F6E617279009484016901928496961D5F5F6B494D4D657373616765506172744174747269627574654E616D658692848484084E534E756D626572008484074E5356616C7565009484012A8499990086869701049284989902928496961D5F5F6B494D4D657373616765506172744174747269627574654E616D658692849B9C9D990086928496961C5F5F6B494D4D656E74696F6E436F6E6669726D65644D656E74696F6E86928496960C2B31363137353535393837368686970122929786";
+
+    #[test]
+    fn test_parse_simple_text() {
+        let data = hex::decode(TEST_BLOB_SIMPLE).unwrap();
+        let (text, audio) = parse_attributed_body(&data);
+        assert!(text.is_some());
+        assert!(text.unwrap().contains("i think we can drop haiku"));
+        assert!(audio.is_none());
+    }
+
+    #[test]
+    fn test_parse_long_text() {
+        let data = hex::decode(TEST_BLOB_LONG).unwrap();
+        let (text, audio) = parse_attributed_body(&data);
+        assert!(text.is_some());
+        let t = text.unwrap();
+        assert!(t.contains("we have to rewrite it all"));
+        assert_eq!(t.len(), 165);
+        assert!(audio.is_none());
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let data = hex::decode(TEST_BLOB_URL).unwrap();
+        let (text, audio) = parse_attributed_body(&data);
+        assert!(text.is_some());
+        assert!(text.unwrap().contains("github.com/obra/superpowers"));
+        assert!(audio.is_none());
+    }
+
+    #[test]
+    fn test_parse_audio_transcription() {
+        let data = hex::decode(TEST_BLOB_AUDIO).unwrap();
+        let (_text, audio) = parse_attributed_body(&data);
+        // Audio messages have placeholder text
+        assert!(audio.is_some());
+        let a = audio.unwrap();
+        assert!(a.contains("Once you're done doing that"));
+        assert!(a.len() > 100); // Should be a long transcription
+    }
+
+    #[test]
+    fn test_parse_emoji_only() {
+        let data = hex::decode(TEST_BLOB_EMOJI).unwrap();
+        let (text, audio) = parse_attributed_body(&data);
+        assert_eq!(
+            text.as_deref(),
+            Some("\u{1F389}\u{1F389}\u{1F382}\u{1F973} \u{2764}\u{FE0F}\u{2764}\u{FE0F} \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{1F44D}\u{1F3FD}\u{1F370}\u{1F37E}\u{1F942}")
+        );
+        assert!(audio.is_none());
+        // The marker heuristics reject text without letters
+        assert!(extract_message_text(&data).is_none());
+    }
+
+    #[test]
+    fn test_parse_inline_mention() {
+        let data = hex::decode(TEST_BLOB_MENTION).unwrap();
+        let (text, _) = parse_attributed_body(&data);
+        assert_eq!(text.as_deref(), Some("Hey Jane, are you still coming tonight? \u{1F355}"));
+
+        let decoded = typedstream::decode_attributed_string(&data).unwrap();
+        let runs = decoded.run_texts();
+        let texts: Vec<&str> = runs.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(texts, ["Hey ", "Jane", ", are you still coming tonight? \u{1F355}"]);
+        assert_eq!(
+            decoded.attribute("__kIMMentionConfirmedMention"),
+            Some(&typedstream::AttributeValue::String("+16175559876".to_string()))
+        );
+        // Back-referenced dictionary decodes to the same attributes as the first run
+        assert_eq!(runs[2].1.attributes, runs[0].1.attributes);
+    }
+
+    #[test]
+    fn test_parse_mentions() {
+        let data = hex::decode(TEST_BLOB_MENTION).unwrap();
+        assert_eq!(parse_mentions(&data), ["+16175559876"]);
+        assert!(parse_mentions(&hex::decode(TEST_BLOB_SIMPLE).unwrap()).is_empty());
+        assert!(parse_mentions(&[0x00, 0x01]).is_empty());
+    }
+
+    #[test]
+    fn test_same_handle() {
+        assert!(same_handle("+16175559876", "(617) 555-9876"));
+        assert!(same_handle("+1 617 555 9876", "16175559876"));
+        assert!(same_handle("Me@iCloud.com", "me@icloud.com"));
+        assert!(!same_handle("+16175559876", "+16175551234"));
+        assert!(!same_handle("me@icloud.com", "+16175559876"));
+        assert!(!same_handle("", ""));
+    }
+
+    #[test]
+    fn test_mentions_me_from_db() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut reader, conn) = create_fixture_db(temp.path());
+        let rowid = insert_message(&conn, "M-1", "Hey Jane, are you still coming tonight? \u{1F355}", None);
+        conn.execute(
+            "UPDATE message SET attributedBody = ?1 WHERE ROWID = ?2",
+            rusqlite::params![hex::decode(TEST_BLOB_MENTION).unwrap(), rowid],
+        )
+        .unwrap();
+
+        let msg = &reader.get_new_messages(0).unwrap()[0];
+        assert_eq!(msg.mentions, ["+16175559876"]);
+        assert!(!msg.mentions_me);
+
+        reader.my_handles = vec!["jane@example.com".to_string(), "(617) 555-9876".to_string()];
+        let msg = &reader.get_new_messages(0).unwrap()[0];
+        assert!(msg.mentions_me);
+    }
+
+    #[test]
+    fn test_parse_attachment_placeholder_only() {
+        // The audio blob's text is just U+FFFC, which isn't message text
+        let data = hex::decode(TEST_BLOB_AUDIO).unwrap();
+        let (text, _) = parse_attributed_body(&data);
+        assert!(text.is_none());
+    }
+
+    #[test]
+    fn test_voice_message_body_from_db() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+
+        let rowid = insert_message(&conn, "V-1", "\u{fffc}", None);
+        conn.execute(
+            "UPDATE message SET attributedBody = ?1, is_audio_message = 1, cache_has_attachments = 1 WHERE ROWID = ?2",
+            rusqlite::params![hex::decode(TEST_BLOB_AUDIO).unwrap(), rowid],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO attachment (filename, mime_type, transfer_name, total_bytes)
+             VALUES ('/tmp/Audio Message.caf', 'audio/x-caf', 'Audio Message.caf', 52000)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO message_attachment_join (message_id, attachment_id) VALUES (?1, ?2)",
+            [rowid, conn.last_insert_rowid()],
+        )
+        .unwrap();
+
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 1);
+        let msg = &messages[0];
+        assert!(msg.is_audio_message);
+        assert_eq!(msg.attachments[0].path, "/tmp/Audio Message.caf");
+        assert!(msg
+            .body_text()
+            .starts_with("Voice message (transcribed): Once you're done doing that"));
+    }
+
+    #[test]
+    fn test_voice_message_without_transcription() {
+        let msg = Message {
+            text: "\u{fffc}".to_string(),
+            is_audio_message: true,
+            ..Default::default()
+        };
+        assert_eq!(msg.body_text(), "Voice message received (no transcription available)");
+
+        let plain = Message {
+            text: "hi".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(plain.body_text(), "hi");
+    }
+
+    #[test]
+    fn test_parse_empty_blob() {
+        let (text, audio) = parse_attributed_body(&[]);
+        assert!(text.is_none());
+        assert!(audio.is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid_blob() {
+        let data = vec![0x00, 0x01, 0x02, 0x03];
+        let (text, audio) = parse_attributed_body(&data);
+        assert!(text.is_none());
+        assert!(audio.is_none());
+    }
+
+    #[test]
+    fn test_macos_timestamp_conversion() {
+        // Test a known timestamp
+        let macos_ts: i64 = 0; // Jan 1, 2001 00:00:00 in macOS time
+        let dt = macos_to_datetime(macos_ts);
+        assert_eq!(dt.year(), 2001);
+        assert_eq!(dt.month(), 1);
+        assert_eq!(dt.day(), 1);
+    }
+
+    /// 2MB of noise with no '+' bytes, salted with truncated string headers every 4KB
+    fn large_noise_blob() -> Vec<u8> {
+        let mut state: u32 = 0x1234_5678;
+        let mut blob = b"\x04\x0bnot-a-typedstream".to_vec();
+        blob.extend_from_slice(b"IMAudioTranscription");
+        blob.extend_from_slice(b"NSString");
+        while blob.len() < 2 * 1024 * 1024 {
+            if blob.len().is_multiple_of(4096) {
+                // Claims a 64KB string that runs into invalid UTF-8
+                blob.extend_from_slice(&[0x2B, 0x82, 0x00, 0x00, 0x01, 0x00, 0xFF]);
+                continue;
+            }
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let byte = (state >> 24) as u8;
+            blob.push(if byte == 0x2B { 0x2C } else { byte });
+        }
+        blob
+    }
+
+    #[test]
+    fn test_large_blob_parse_time() {
+        let mut blob = large_noise_blob();
+        blob.extend_from_slice(b"\x2b\x0dFound at last\x86\x84\x02iI\x01\x0c\x86\x86\x86\x86\x86");
+
+        let started = Instant::now();
+        let (text, audio) = parse_attributed_body(&blob);
+        let elapsed = started.elapsed();
+
+        assert_eq!(text.as_deref(), Some("Found at last"));
+        assert_eq!(audio, None);
+        assert!(elapsed < Duration::from_millis(500), "parsing 2MB took {:?}", elapsed);
+    }
+
+    #[test]
+    fn test_audio_transcription_search_is_bounded() {
+        let mut blob = b"IMAudioTranscription".to_vec();
+        blob.extend(std::iter::repeat_n(0u8, AUDIO_SEARCH_WINDOW + 100));
+        blob.push(20);
+        blob.extend_from_slice(b"Too far from the key");
+        blob.extend(std::iter::repeat_n(0u8, 16));
+        assert_eq!(extract_audio_transcription(&blob), None);
+
+        let mut near = b"IMAudioTranscription".to_vec();
+        near.extend_from_slice(&[0x86, 0x92, 0x84, 0x96, 0x96, 20]);
+        near.extend_from_slice(b"Close enough to find");
+        near.extend(std::iter::repeat_n(0u8, 16));
+        assert_eq!(extract_audio_transcription(&near).as_deref(), Some("Close enough to find"));
+    }
+
+    #[test]
+    fn test_find_subsequence() {
+        assert_eq!(find_subsequence(b"hello world", b"world"), Some(6));
+        assert_eq!(find_subsequence(b"hello world", b"xxx"), None);
+        assert_eq!(find_subsequence(b"NSString", b"NSString"), Some(0));
+    }
+
+    #[test]
+    fn test_is_valid_message_text() {
+        assert!(is_valid_message_text("hello"));
+        assert!(is_valid_message_text("hello world"));
+        assert!(!is_valid_message_text(""));
+        assert!(!is_valid_message_text("a")); // Too short
+        assert!(!is_valid_message_text("123")); // No letters
+    }
+
+    // Benchmark test (run with --release for meaningful results)
+    #[test]
+    fn test_parsing_performance() {
+        let data = hex::decode(TEST_BLOB_LONG).unwrap();
+        let start = std::time::Instant::now();
+        for _ in 0..1000 {
+            let _ = parse_attributed_body(&data);
+        }
+        let elapsed = start.elapsed();
+        // Should complete 1000 iterations in under 100ms
+        assert!(elapsed.as_millis() < 100, "Parsing too slow: {:?}", elapsed);
+    }
+
+    /// Build a minimal chat.db with the tables and columns the reader touches
+    fn create_fixture_db(dir: &Path) -> (MessagesReader, Connection) {
+        let db_path = dir.join("chat.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE handle (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, id TEXT, service TEXT);
+            CREATE TABLE chat (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, chat_identifier TEXT, style INTEGER, display_name TEXT);
+            CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+            CREATE TABLE chat_handle_join (chat_id INTEGER, handle_id INTEGER);
+            CREATE TABLE message (
+                ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+                guid TEXT,
+                text TEXT,
+                handle_id INTEGER DEFAULT 0,
+                date INTEGER DEFAULT 0,
+                attributedBody BLOB,
+                cache_has_attachments INTEGER DEFAULT 0,
+                is_audio_message INTEGER DEFAULT 0,
+                is_from_me INTEGER DEFAULT 0,
+                thread_originator_guid TEXT,
+                associated_message_type INTEGER DEFAULT 0,
+                associated_message_guid TEXT,
+                date_edited INTEGER DEFAULT 0,
+                message_summary_info BLOB,
+                date_retracted INTEGER DEFAULT 0,
+                balloon_bundle_id TEXT,
+                service TEXT,
+                item_type INTEGER DEFAULT 0,
+                group_action_type INTEGER DEFAULT 0,
+                group_title TEXT,
+                other_handle INTEGER DEFAULT 0,
+                date_delivered INTEGER DEFAULT 0,
+                date_read INTEGER DEFAULT 0,
+                subject TEXT,
+                is_spam INTEGER DEFAULT 0
+            );
+            CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, filename TEXT, mime_type TEXT, transfer_name TEXT, total_bytes INTEGER);
+            CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+            INSERT INTO handle (id, service) VALUES ('+16175551234', 'iMessage');
+            INSERT INTO chat (chat_identifier, style, display_name) VALUES ('+16175551234', 45, NULL);
+            "#,
+        )
+        .unwrap();
+
+        let mut config = Config::for_test(dir);
+        config.messages_db = db_path;
+        (MessagesReader::new(&config), conn)
+    }
+
+    /// Insert a 1:1 message from the fixture handle, returning its ROWID
+    fn insert_message(conn: &Connection, guid: &str, text: &str, associated: Option<(i64, &str)>) -> i64 {
+        let (assoc_type, assoc_guid) = associated.unwrap_or((0, ""));
+        conn.execute(
+            "INSERT INTO message (guid, text, handle_id, date, associated_message_type, associated_message_guid)
+             VALUES (?1, ?2, 1, (SELECT COUNT(*) FROM message), ?3, NULLIF(?4, ''))",
+            rusqlite::params![guid, text, assoc_type, assoc_guid],
+        )
+        .unwrap();
+        let rowid = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO chat_message_join (chat_id, message_id) VALUES (1, ?1)",
+            [rowid],
+        )
+        .unwrap();
+        rowid
+    }
+
+    #[test]
+    fn test_classify_associated_tapbacks() {
+        assert_eq!(
+            classify_associated(2000, Some("p:0/ABC-123")),
+            MessageKind::Tapback {
+                kind: TapbackKind::Love,
+                target_guid: "ABC-123".to_string(),
+                removed: false,
+            }
+        );
+        assert_eq!(
+            classify_associated(3003, Some("bp:ABC-123")),
+            MessageKind::Tapback {
+                kind: TapbackKind::Laugh,
+                target_guid: "ABC-123".to_string(),
+                removed: true,
+            }
+        );
+        assert_eq!(classify_associated(0, None), MessageKind::Text);
+        assert_eq!(classify_associated(1000, Some("p:0/X")), MessageKind::Text);
+    }
+
+    #[test]
+    fn test_tapbacks_classified_from_db() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+
+        insert_message(&conn, "ORIG-1", "want to get dinner?", None);
+        insert_message(&conn, "TB-1", "Loved \u{201c}want to get dinner?\u{201d}", Some((2000, "p:0/ORIG-1")));
+        insert_message(&conn, "TB-2", "Removed a heart from \u{201c}want to get dinner?\u{201d}", Some((3000, "p:0/ORIG-1")));
+        insert_message(&conn, "TB-3", "Questioned \u{201c}want to get dinner?\u{201d}", Some((2005, "p:0/ORIG-1")));
+
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].kind, MessageKind::Text);
+        assert_eq!(messages[0].text, "want to get dinner?");
+        assert_eq!(
+            messages[1].kind,
+            MessageKind::Tapback {
+                kind: TapbackKind::Love,
+                target_guid: "ORIG-1".to_string(),
+                removed: false,
+            }
+        );
+        assert!(matches!(
+            messages[2].kind,
+            MessageKind::Tapback { kind: TapbackKind::Love, removed: true, .. }
+        ));
+        assert!(matches!(
+            messages[3].kind,
+            MessageKind::Tapback { kind: TapbackKind::Question, removed: false, .. }
+        ));
+    }
+
+    /// Build a message_summary_info plist the way Messages.app stores edit history
+    fn edit_summary_blob(versions: &[&str]) -> Vec<u8> {
+        edit_summary_blob_parts(&[("0", versions)])
+    }
+
+    /// `edit_summary_blob` for a message of several parts, each with its own versions
+    fn edit_summary_blob_parts(parts_versions: &[(&str, &[&str])]) -> Vec<u8> {
+        let mut parts = plist::Dictionary::new();
+        for (part, versions) in parts_versions {
+            let entries: Vec<plist::Value> = versions
+                .iter()
+                .enumerate()
+                .map(|(i, hex_blob)| {
+                    let mut entry = plist::Dictionary::new();
+                    entry.insert("d".to_string(), plist::Value::Real(700_000_000.0 + i as f64));
+                    entry.insert("t".to_string(), plist::Value::Data(hex::decode(hex_blob).unwrap()));
+                    plist::Value::Dictionary(entry)
+                })
+                .collect();
+            parts.insert(part.to_string(), plist::Value::Array(entries));
+        }
+        let mut root = plist::Dictionary::new();
+        root.insert("ec".to_string(), plist::Value::Dictionary(parts));
+        root.insert("ep".to_string(), plist::Value::Array(vec![plist::Value::Integer(0.into())]));
+
+        let mut out = Vec::new();
+        plist::Value::Dictionary(root).to_writer_binary(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_edit_history() {
+        let blob = edit_summary_blob(&[TEST_BLOB_SIMPLE, TEST_BLOB_LONG]);
+        let history = parse_edit_history(&blob);
+        assert_eq!(history.len(), 2);
+        assert!(history[0].contains("i think we can drop haiku"));
+        assert!(history[1].contains("we have to rewrite it all"));
+    }
+
+    /// Every part's versions count, in part order however the plist keys are ordered
+    #[test]
+    fn test_parse_edit_history_several_parts() {
+        let blob = edit_summary_blob_parts(&[("2", &[TEST_BLOB_SIMPLE]), ("0", &[TEST_BLOB_LONG, TEST_BLOB_SIMPLE]), ("1", &[])]);
+        let history = parse_edit_history(&blob);
+        assert_eq!(history.len(), 3);
+        assert!(history[0].contains("we have to rewrite it all"));
+        assert!(history[1].contains("i think we can drop haiku"));
+        assert!(history[2].contains("i think we can drop haiku"));
+    }
+
+    #[test]
+    fn test_parse_edit_history_unedited() {
+        assert!(parse_edit_history(&[]).is_empty());
+        assert!(parse_edit_history(b"not a plist").is_empty());
+
+        // A summary plist without edited content (e.g. only retraction info)
+        let mut root = plist::Dictionary::new();
+        root.insert("rp".to_string(), plist::Value::Array(Vec::new()));
+        let mut out = Vec::new();
+        plist::Value::Dictionary(root).to_writer_binary(&mut out).unwrap();
+        assert!(parse_edit_history(&out).is_empty());
+    }
+
+    #[test]
+    fn test_macos_datetime_round_trip() {
+        let dt = Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 15).unwrap()
+            + chrono::Duration::nanoseconds(123_456_789);
+        assert_eq!(macos_to_datetime(datetime_to_macos(dt)), dt);
+    }
+
+    #[test]
+    fn test_get_modified_messages_edits() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+
+        let edited_rowid = insert_message(&conn, "E-1", "we have to rewrite it all", None);
+        insert_message(&conn, "E-2", "untouched", None);
+
+        let edited_at = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        conn.execute(
+            "UPDATE message SET date_edited = ?1, message_summary_info = ?2 WHERE ROWID = ?3",
+            rusqlite::params![
+                datetime_to_macos(edited_at),
+                edit_summary_blob(&[TEST_BLOB_SIMPLE, TEST_BLOB_LONG]),
+                edited_rowid
+            ],
+        )
+        .unwrap();
+
+        // Edit after the cutoff is reported with its history
+        let before = edited_at - chrono::Duration::seconds(1);
+        let edited = reader.get_modified_messages(10, before).unwrap();
+        assert_eq!(edited.len(), 1);
+        assert!(edited[0].edited);
+        assert_eq!(edited[0].date_edited, Some(edited_at));
+        assert_eq!(edited[0].edit_history.len(), 2);
+        assert_eq!(edited[0].text, "we have to rewrite it all");
+
+        // Cutoff at the edit time itself: already seen
+        assert!(reader.get_modified_messages(10, edited_at).unwrap().is_empty());
+
+        // Rows beyond the processed high-water mark come through the normal poll instead
+        assert!(reader.get_modified_messages(0, before).unwrap().is_empty());
+
+        // Unedited messages report no history
+        let all = reader.get_new_messages(0).unwrap();
+        assert!(!all[1].edited);
+        assert!(all[1].edit_history.is_empty());
+    }
+
+    /// Mark a fixture row as unsent the way Messages.app does: text cleared, retraction recorded
+    fn retract_message(conn: &Connection, rowid: i64, at: DateTime<Utc>) {
+        let mut root = plist::Dictionary::new();
+        root.insert("rp".to_string(), plist::Value::Array(vec![plist::Value::Integer(0.into())]));
+        let mut summary = Vec::new();
+        plist::Value::Dictionary(root).to_writer_binary(&mut summary).unwrap();
+        conn.execute(
+            "UPDATE message SET text = NULL, attributedBody = NULL, date_retracted = ?1,
+             message_summary_info = ?2 WHERE ROWID = ?3",
+            rusqlite::params![datetime_to_macos(at), summary, rowid],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_retracted_after_processing() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+
+        let rowid = insert_message(&conn, "R-1", "oops wrong chat", None);
+        let first = reader.get_new_messages(0).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].guid, "R-1");
+        assert_eq!(first[0].kind, MessageKind::Text);
+
+        let unsent_at = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        retract_message(&conn, rowid, unsent_at);
+
+        let modified = reader
+            .get_modified_messages(rowid, unsent_at - chrono::Duration::seconds(5))
+            .unwrap();
+        assert_eq!(modified.len(), 1);
+        assert_eq!(modified[0].kind, MessageKind::Retracted);
+        assert_eq!(modified[0].guid, "R-1");
+        assert_eq!(modified[0].date_retracted, Some(unsent_at));
+    }
+
+    #[test]
+    fn test_retracted_before_processing() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+
+        // Sent and unsent before we ever polled: still reported (not silently skipped
+        // for lacking text) so the daemon can decide it never needs a note
+        let rowid = insert_message(&conn, "R-2", "never mind", None);
+        retract_message(&conn, rowid, Utc::now());
+
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].kind, MessageKind::Retracted);
+        assert!(messages[0].text.is_empty());
+    }
+
+    #[test]
+    fn test_is_retracted_summary() {
+        let mut root = plist::Dictionary::new();
+        root.insert("rp".to_string(), plist::Value::Array(vec![plist::Value::Integer(0.into())]));
+        let mut blob = Vec::new();
+        plist::Value::Dictionary(root).to_writer_binary(&mut blob).unwrap();
+        assert!(is_retracted_summary(&blob));
+
+        assert!(!is_retracted_summary(&edit_summary_blob(&[TEST_BLOB_SIMPLE])));
+        assert!(!is_retracted_summary(b"garbage"));
+    }
+
+    #[test]
+    fn test_recent_messages_bounded() {
+        let mut recent = RecentMessages::new(2);
+        for (rowid, guid) in [(1, "A"), (2, "B"), (3, "C")] {
+            let msg = Message {
+                rowid,
+                guid: guid.to_string(),
+                chat_id: "+16175551234".to_string(),
+                ..Default::default()
+            };
+            recent.record(&msg, "hi");
+        }
+        assert_eq!(recent.len(), 2);
+        assert!(recent.get("A").is_none());
+        assert_eq!(recent.get("C").unwrap().rowid, 3);
+
+        // Messages without a GUID can't be correlated and aren't stored
+        recent.record(&Message::default(), "no guid");
+        assert_eq!(recent.len(), 2);
+    }
+
+    #[test]
+    fn test_wait_for_changes_sees_wal_write() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut reader, _conn) = create_fixture_db(temp.path());
+
+        // Establish the watch with an idle wait first
+        assert!(!reader.wait_for_changes(Duration::from_millis(50)));
+
+        let wal = temp.path().join("chat.db-wal");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            std::fs::write(wal, b"frame").unwrap();
+        });
+        assert!(reader.wait_for_changes(Duration::from_secs(5)));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_changes_ignores_unrelated_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut reader, _conn) = create_fixture_db(temp.path());
+        assert!(!reader.wait_for_changes(Duration::from_millis(50)));
+
+        std::fs::write(temp.path().join("other.txt"), b"noise").unwrap();
+        assert!(!reader.wait_for_changes(Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn test_wait_for_changes_falls_back_to_polling() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("missing-dir/chat.db");
+        config.poll_interval_ms = 10;
+        let mut reader = MessagesReader::new(&config);
+
+        // Can't watch a directory that doesn't exist: degrade to sleeping the poll interval
+        let start = std::time::Instant::now();
+        assert!(reader.wait_for_changes(Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// A watch that failed is tried again, less and less often, until it works
+    #[test]
+    fn test_watch_retried_with_backoff() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.messages_db = temp.path().join("later/chat.db");
+        config.poll_interval_ms = 10;
+        let mut reader = MessagesReader::new(&config);
+        let start = Instant::now();
+        reader.clock = Box::new(FakeClock { start, slept: Arc::new(Mutex::new(Vec::new())), on_sleep: Box::new(|_| {}) });
+
+        assert!(reader.wait_for_changes(Duration::from_millis(50)));
+        assert_eq!(reader.watch_retry, Some((start + WATCH_RETRY_FIRST, WATCH_RETRY_FIRST * 2)));
+        // Not yet due, so no attempt to push the backoff further
+        assert!(reader.wait_for_changes(Duration::from_millis(50)));
+        assert_eq!(reader.watch_retry, Some((start + WATCH_RETRY_FIRST, WATCH_RETRY_FIRST * 2)));
+
+        // Due, and failing again
+        reader.watch_retry = Some((start, WATCH_RETRY_FIRST * 2));
+        assert!(reader.wait_for_changes(Duration::from_millis(50)));
+        assert!(reader.watcher.is_none());
+        assert_eq!(reader.watch_retry, Some((start + WATCH_RETRY_FIRST * 2, WATCH_RETRY_FIRST * 4)));
+
+        // Never longer than the cap
+        reader.watch_retry = Some((start, WATCH_RETRY_MAX));
+        reader.wait_for_changes(Duration::from_millis(50));
+        assert_eq!(reader.watch_retry, Some((start + WATCH_RETRY_MAX, WATCH_RETRY_MAX)));
+
+        // Once the directory is there, the next retry takes
+        std::fs::create_dir_all(temp.path().join("later")).unwrap();
+        reader.watch_retry = Some((start, WATCH_RETRY_MAX));
+        assert!(!reader.wait_for_changes(Duration::from_millis(50)));
+        assert!(reader.watcher.is_some());
+        assert_eq!(reader.watch_retry, None);
+    }
+
+    #[test]
+    fn test_busy_retry_gives_up_with_transient_error() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "L-1", "locked out", None);
+
+        // Hold the write lock for the whole attempt (rollback journal blocks readers)
+        conn.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let start = std::time::Instant::now();
+        let err = reader.get_new_messages(0).unwrap_err();
+        assert!(err.is_transient(), "expected transient error, got {:?}", err);
+        // All three backoff delays were applied
+        assert!(start.elapsed() >= Duration::from_millis(600));
+        conn.execute_batch("ROLLBACK").unwrap();
+    }
+
+    #[test]
+    fn test_busy_retry_recovers_when_lock_released() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "L-2", "eventually readable", None);
+        drop(conn);
+
+        let db_path = temp.path().join("chat.db");
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let holder = std::thread::spawn(move || {
+            let conn = Connection::open(db_path).unwrap();
+            conn.execute_batch("BEGIN EXCLUSIVE").unwrap();
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(250));
+            conn.execute_batch("COMMIT").unwrap();
+        });
+        locked_rx.recv().unwrap();
+
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 1);
+        holder.join().unwrap();
+    }
+
+    #[test]
+    fn test_connection_reused_across_queries() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "C-1", "first", None);
+
+        for _ in 0..5 {
+            reader.poll(0).unwrap();
+        }
+        reader.get_max_rowid().unwrap();
+        reader.get_chat_participants("+16175551234").unwrap();
+        insert_message(&conn, "C-2", "second", None);
+        assert_eq!(reader.poll(0).unwrap().len(), 2);
+
+        assert_eq!(reader.opens.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_connection_reopened_when_db_replaced() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "R-1", "before sync", None);
+        assert_eq!(reader.poll(0).unwrap().len(), 1);
+
+        // Build a replacement db and move it over chat.db, as a restore would
+        let staging = temp.path().join("staging");
+        std::fs::create_dir(&staging).unwrap();
+        let (_, replacement) = create_fixture_db(&staging);
+        insert_message(&replacement, "R-2", "after sync", None);
+        insert_message(&replacement, "R-3", "after sync", None);
+        drop(replacement);
+        std::fs::rename(staging.join("chat.db"), temp.path().join("chat.db")).unwrap();
+
+        let guids: Vec<String> = reader.poll(0).unwrap().into_iter().map(|m| m.guid).collect();
+        assert_eq!(guids, ["R-2", "R-3"]);
+        assert_eq!(reader.opens.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_connection_opened_once_db_appears() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let reader = MessagesReader::new(&Config::for_test(temp.path()));
+        assert!(reader.get_max_rowid().is_err());
+        assert_eq!(reader.opens.load(Ordering::Relaxed), 0);
+
+        let (_, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "A-1", "hello", None);
+        assert_eq!(reader.get_max_rowid().unwrap(), 1);
+        assert_eq!(reader.opens.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_missing_db_is_not_transient() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let reader = MessagesReader::new(&Config::for_test(temp.path()));
+        let err = reader.get_new_messages(0).unwrap_err();
+        assert!(!err.is_transient());
+    }
+
+    #[test]
+    fn test_attachments_batched_onto_right_messages() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+
+        let photos = insert_message(&conn, "A-1", "", None);
+        let plain = insert_message(&conn, "A-2", "no attachments here", None);
+        let docs = insert_message(&conn, "A-3", "see attached", None);
+        let attach = |message_id: i64, filename: &str, mime: &str| {
+            conn.execute(
+                "INSERT INTO attachment (filename, mime_type, transfer_name, total_bytes) VALUES (?1, ?2, NULL, 10)",
+                rusqlite::params![filename, mime],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO message_attachment_join (message_id, attachment_id) VALUES (?1, ?2)",
+                [message_id, conn.last_insert_rowid()],
+            )
+            .unwrap();
+        };
+        for i in 0..3 {
+            attach(photos, &format!("/tmp/IMG_{}.heic", i), "image/heic");
+        }
+        attach(docs, "/tmp/report.pdf", "application/pdf");
+        attach(docs, "/tmp/notes.txt", "text/plain");
+        conn.execute(
+            "UPDATE message SET cache_has_attachments = 1 WHERE ROWID IN (?1, ?2)",
+            [photos, docs],
+        )
+        .unwrap();
+
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 3);
+
+        let by_rowid = |rowid: i64| messages.iter().find(|m| m.rowid == rowid).unwrap();
+        let photo_names: Vec<_> = by_rowid(photos).attachments.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(photo_names, vec!["IMG_0.heic", "IMG_1.heic", "IMG_2.heic"]);
+        assert!(by_rowid(plain).attachments.is_empty());
+        let doc_types: Vec<_> = by_rowid(docs).attachments.iter().map(|a| a.mime_type.as_str()).collect();
+        assert_eq!(doc_types, vec!["application/pdf", "text/plain"]);
+    }
+
+    #[test]
+    fn test_null_attachment_mime_is_sniffed() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        let airdropped = temp.path().join("Boarding Pass");
+        std::fs::write(&airdropped, b"%PDF-1.7\n").unwrap();
+
+        let rowid = insert_message(&conn, "N-1", "", None);
+        conn.execute(
+            "INSERT INTO attachment (filename, mime_type, transfer_name, total_bytes) VALUES (?1, NULL, NULL, 9)",
+            [airdropped.to_string_lossy()],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO message_attachment_join (message_id, attachment_id) VALUES (?1, ?2)",
+            [rowid, conn.last_insert_rowid()],
+        )
+        .unwrap();
+        conn.execute("UPDATE message SET cache_has_attachments = 1 WHERE ROWID = ?1", [rowid]).unwrap();
+
+        let msg = reader.get_message_by_guid("N-1").unwrap().unwrap();
+        assert_eq!(msg.attachments[0].mime_type, "application/pdf");
+    }
+
+    #[test]
+    fn test_mixed_inbound_outbound() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "IN-1", "are you coming tonight?", None);
+        let out_text = insert_message(&conn, "OUT-1", "yes, leaving now", None);
+        let out_photo = insert_message(&conn, "OUT-2", "\u{fffc}", None);
+        insert_message(&conn, "IN-2", "great", None);
+        conn.execute(
+            "UPDATE message SET is_from_me = 1 WHERE ROWID IN (?1, ?2)",
+            [out_text, out_photo],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO attachment (filename, mime_type, transfer_name, total_bytes)
+             VALUES ('/tmp/IMG_5.jpeg', 'image/jpeg', 'IMG_5.jpeg', 100)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO message_attachment_join (message_id, attachment_id) VALUES (?1, ?2)",
+            [out_photo, conn.last_insert_rowid()],
+        )
+        .unwrap();
+        conn.execute("UPDATE message SET cache_has_attachments = 1 WHERE ROWID = ?1", [out_photo])
+            .unwrap();
+
+        let messages = reader.get_new_messages(0).unwrap();
+        let flags: Vec<(&str, bool)> = messages.iter().map(|m| (m.guid.as_str(), m.is_from_me)).collect();
+        assert_eq!(
+            flags,
+            vec![("IN-1", false), ("OUT-1", true), ("OUT-2", true), ("IN-2", false)]
+        );
+        // Outbound messages carry the same chat_id as the conversation they belong to
+        assert!(messages.iter().all(|m| m.chat_id == "+16175551234"));
+        assert_eq!(messages[2].attachments[0].name, "IMG_5.jpeg");
+        assert_eq!(messages[2].text, "");
+    }
+
+    #[test]
+    fn test_get_recent_messages() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        for i in 1..=5 {
+            insert_message(&conn, &format!("H-{}", i), &format!("message {}", i), None);
+        }
+        let mine = insert_message(&conn, "H-6", "reply from me", None);
+        conn.execute("UPDATE message SET is_from_me = 1 WHERE ROWID = ?1", [mine]).unwrap();
+        // Another chat's history stays out
+        conn.execute("INSERT INTO chat (chat_identifier, style) VALUES ('chat-other', 45)", []).unwrap();
+        let other = insert_message(&conn, "X-1", "elsewhere", None);
+        conn.execute("UPDATE chat_message_join SET chat_id = 2 WHERE message_id = ?1", [other]).unwrap();
+
+        let history = reader.get_recent_messages("+16175551234", 3).unwrap();
+        let texts: Vec<&str> = history.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["message 4", "message 5", "reply from me"]);
+        assert!(history[2].is_from_me);
+    }
+
+    #[test]
+    fn test_restart_context_includes_history() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "C-1", "can you book dinner for friday?", None);
+        let reply = insert_message(&conn, "C-2", "Booked Luigi's at 7", None);
+        conn.execute("UPDATE message SET is_from_me = 1 WHERE ROWID = ?1", [reply]).unwrap();
+        insert_message(&conn, "C-3", "Loved \u{201c}Booked Luigi's at 7\u{201d}", Some((2000, "p:0/C-2")));
+
+        let history = reader.get_recent_messages("+16175551234", 20).unwrap();
+        let context = conversation_context(&history, |m| {
+            if m.is_from_me { "Me".to_string() } else { "Jane Doe".to_string() }
+        })
+        .unwrap();
+
+        let lines: Vec<&str> = context.lines().collect();
+        assert_eq!(lines[0], "---Recent conversation context (oldest first)---");
+        assert!(lines[1].ends_with("Jane Doe: can you book dinner for friday?"));
+        assert!(lines[2].ends_with("Me: Booked Luigi's at 7"));
+        assert!(lines[3].ends_with("Jane Doe: (reacted \u{2764}\u{fe0f})"));
+        assert_eq!(lines[4], "---END CONTEXT---");
+
+        assert!(conversation_context(&[], |_| String::new()).is_none());
+    }
+
+    #[test]
+    fn test_get_message_by_guid() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        insert_message(&conn, "ORIG-9", "what time is the game?", None);
+        let reply = insert_message(&conn, "REPLY-9", "7pm I think", None);
+        conn.execute(
+            "UPDATE message SET thread_originator_guid = 'ORIG-9' WHERE ROWID = ?1",
+            [reply],
+        )
+        .unwrap();
+
+        let original = reader.get_message_by_guid("ORIG-9").unwrap().unwrap();
+        assert_eq!(original.text, "what time is the game?");
+        assert_eq!(original.sender, "+16175551234");
+
+        let replies = reader.get_new_messages(0).unwrap();
+        assert_eq!(replies[1].thread_originator_guid.as_deref(), Some("ORIG-9"));
+
+        assert!(reader.get_message_by_guid("DELETED-1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_chat_participants() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        conn.execute_batch(
+            r#"
+            INSERT INTO handle (id, service) VALUES ('+16175559876', 'iMessage');
+            INSERT INTO handle (id, service) VALUES ('jane.doe@icloud.com', 'iMessage');
+            INSERT INTO chat (chat_identifier, style, display_name) VALUES ('chat123456789', 43, 'Family');
+            INSERT INTO chat_handle_join (chat_id, handle_id) VALUES (2, 1), (2, 2), (2, 3);
+            INSERT INTO chat_handle_join (chat_id, handle_id) VALUES (1, 1);
+            "#,
+        )
+        .unwrap();
+
+        let participants = reader.get_chat_participants("chat123456789").unwrap();
+        assert_eq!(participants, vec!["+16175551234", "+16175559876", "jane.doe@icloud.com"]);
+        assert_eq!(reader.get_chat_participants("+16175551234").unwrap(), vec!["+16175551234"]);
+        assert!(reader.get_chat_participants("chat-unknown").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_classify_non_text() {
+        assert_eq!(
+            classify_non_text(Some("com.apple.DigitalTouchBalloonProvider"), 0),
+            Some(NonTextKind::DigitalTouch)
+        );
+        assert_eq!(
+            classify_non_text(Some("com.apple.Handwriting.HandwritingProvider"), 0),
+            Some(NonTextKind::Handwriting)
+        );
+        assert_eq!(
+            classify_non_text(
+                Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.Stickers.UserGenerated.MessagesExtension"),
+                0
+            ),
+            Some(NonTextKind::Sticker)
+        );
+        assert_eq!(classify_non_text(None, 1000), Some(NonTextKind::Sticker));
+        assert_eq!(classify_non_text(Some("com.apple.messages.URLBalloonProvider"), 0), None);
+        assert_eq!(classify_non_text(None, 0), None);
+    }
+
+    #[test]
+    fn test_non_text_messages_from_db() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+
+        let rows = [
+            ("S-1", "com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.Stickers.UserGenerated.MessagesExtension", "sticker.pluginPayloadAttachment"),
+            ("DT-1", "com.apple.DigitalTouchBalloonProvider", "touch.pluginPayloadAttachment"),
+            ("HW-1", "com.apple.Handwriting.HandwritingProvider", "hw.pluginPayloadAttachment"),
+        ];
+        for (guid, bundle, file) in rows {
+            let rowid = insert_message(&conn, guid, "\u{fffc}", None);
+            conn.execute(
+                "UPDATE message SET balloon_bundle_id = ?1, cache_has_attachments = 1 WHERE ROWID = ?2",
+                rusqlite::params![bundle, rowid],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO attachment (filename, mime_type, transfer_name, total_bytes) VALUES (?1, NULL, ?1, 10)",
+                [file],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO message_attachment_join (message_id, attachment_id) VALUES (?1, ?2)",
+                [rowid, conn.last_insert_rowid()],
+            )
+            .unwrap();
+        }
+        // A plain photo still comes through as Text
+        let photo = insert_message(&conn, "P-1", "\u{fffc}", None);
+        conn.execute("UPDATE message SET cache_has_attachments = 1 WHERE ROWID = ?1", [photo]).unwrap();
+        conn.execute(
+            "INSERT INTO attachment (filename, mime_type, transfer_name, total_bytes) VALUES ('/tmp/IMG_9.jpeg', 'image/jpeg', 'IMG_9.jpeg', 10)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO message_attachment_join (message_id, attachment_id) VALUES (?1, ?2)",
+            [photo, conn.last_insert_rowid()],
+        )
+        .unwrap();
+
+        let kinds: Vec<MessageKind> = reader.get_new_messages(0).unwrap().into_iter().map(|m| m.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                MessageKind::NonText { kind: NonTextKind::Sticker },
+                MessageKind::NonText { kind: NonTextKind::DigitalTouch },
+                MessageKind::NonText { kind: NonTextKind::Handwriting },
+                MessageKind::Text,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_service_parsed_from_db() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        conn.execute("INSERT INTO handle (id, service) VALUES ('+16175559876', 'SMS')", []).unwrap();
+
+        let rows = [
+            ("SV-1", Some("iMessage")),
+            ("SV-2", Some("SMS")),
+            ("SV-3", Some("RCS")),
+            ("SV-4", Some("Satellite")),
+            ("SV-5", None),
+        ];
+        for (guid, service) in rows {
+            let rowid = insert_message(&conn, guid, "hello", None);
+            conn.execute("UPDATE message SET service = ?1 WHERE ROWID = ?2", rusqlite::params![service, rowid])
+                .unwrap();
+        }
+        // No message.service: falls back to the handle's service
+        let from_sms_handle = insert_message(&conn, "SV-6", "hello", None);
+        conn.execute("UPDATE message SET handle_id = 2 WHERE ROWID = ?1", [from_sms_handle]).unwrap();
+
+        let services: Vec<MessageService> = reader.get_new_messages(0).unwrap().iter().map(|m| m.service).collect();
+        assert_eq!(
+            services,
+            vec![
+                MessageService::IMessage,
+                MessageService::Sms,
+                MessageService::Rcs,
+                MessageService::Unknown,
+                MessageService::IMessage,
+                MessageService::Sms,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_service_from_str() {
+        assert_eq!("imessage".parse::<MessageService>(), Ok(MessageService::IMessage));
+        assert_eq!("SMS".parse::<MessageService>(), Ok(MessageService::Sms));
+        assert_eq!("rcs".parse::<MessageService>(), Ok(MessageService::Rcs));
+        assert!("carrier-pigeon".parse::<MessageService>().is_err());
+        assert_eq!(MessageService::IMessage.to_string(), "iMessage");
+    }
+
+    #[test]
+    fn test_tapback_emoji() {
+        assert_eq!(TapbackKind::Like.emoji(), "\u{1f44d}");
+        assert_eq!(TapbackKind::Love.emoji(), "\u{2764}\u{fe0f}");
+    }
+
+    // Tests for chat_style race condition fix
+    #[test]
+    fn test_is_group_detection_style_43() {
+        // Style 43 = group chat
+        let chat_style: Option<i32> = Some(43);
+        let is_group = chat_style == Some(43);
+        assert!(is_group, "Style 43 should be detected as group");
+    }
+
+    #[test]
+    fn test_is_group_detection_style_45() {
+        // Style 45 = individual chat
+        let chat_style: Option<i32> = Some(45);
+        let is_group = chat_style == Some(43);
+        assert!(!is_group, "Style 45 should not be detected as group");
+    }
+
+    #[test]
+    fn test_is_group_detection_null_style() {
+        // NULL style (race condition case) should not be detected as group
+        let chat_style: Option<i32> = None;
+        let is_group = chat_style == Some(43);
+        assert!(!is_group, "NULL style should not be detected as group (triggers re-query)");
+    }
+
+    #[test]
+    fn test_delivered_and_read_dates() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        let sent = Utc.with_ymd_and_hms(2026, 3, 1, 18, 0, 0).unwrap();
+        let read = Utc.with_ymd_and_hms(2026, 3, 1, 18, 5, 30).unwrap();
+        let rowid = insert_message(&conn, "T-1", "dinner?", None);
+        conn.execute(
+            "UPDATE message SET date = ?1, date_delivered = ?1, date_read = ?2 WHERE ROWID = ?3",
+            rusqlite::params![datetime_to_macos(sent), datetime_to_macos(read), rowid],
+        )
+        .unwrap();
+        insert_message(&conn, "T-2", "not read yet", None);
+
+        let read_msg = reader.get_message_by_guid("T-1").unwrap().unwrap();
+        assert_eq!(read_msg.date_delivered, Some(sent));
+        assert_eq!(read_msg.date_read, Some(read));
+        let unread = reader.get_message_by_guid("T-2").unwrap().unwrap();
+        assert_eq!(unread.date_delivered, None);
+        assert_eq!(unread.date_read, None);
+    }
+
+    #[test]
+    fn test_subject() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        for (guid, subject) in [("S-1", Some("Quarterly report")), ("S-2", Some("  ")), ("S-3", None)] {
+            let rowid = insert_message(&conn, guid, "see attached", None);
+            conn.execute("UPDATE message SET subject = ?1 WHERE ROWID = ?2", rusqlite::params![subject, rowid])
+                .unwrap();
+        }
+
+        let messages = reader.get_new_messages(0).unwrap();
+        let subject = |guid: &str| messages.iter().find(|m| m.guid == guid).unwrap().subject.clone();
+        assert_eq!(subject("S-1").as_deref(), Some("Quarterly report"));
+        assert_eq!(subject("S-2"), None);
+        assert_eq!(subject("S-3"), None);
+    }
+
+    #[test]
+    fn test_spam_flag_and_lookup_by_rowid() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        let ham = insert_message(&conn, "J-1", "see you at 6", None);
+        let spam = insert_message(&conn, "J-2", "You won a prize! Claim now", None);
+        conn.execute("UPDATE message SET is_spam = 1 WHERE ROWID = ?1", [spam]).unwrap();
+
+        assert!(!reader.get_message_by_rowid(ham).unwrap().unwrap().is_spam);
+        let junk = reader.get_message_by_rowid(spam).unwrap().unwrap();
+        assert_eq!(junk.guid, "J-2");
+        assert!(junk.is_spam);
+        assert!(reader.get_message_by_rowid(spam + 100).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rowid_before() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        let base = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        let mut rowids = Vec::new();
+        for (i, minutes) in [0, 30, 60, 61, 90].into_iter().enumerate() {
+            let rowid = insert_message(&conn, &format!("B-{}", i), "hello", None);
+            conn.execute(
+                "UPDATE message SET date = ?1 WHERE ROWID = ?2",
+                rusqlite::params![datetime_to_macos(base + chrono::Duration::minutes(minutes)), rowid],
+            )
+            .unwrap();
+            rowids.push(rowid);
+        }
+        let max = reader.get_max_rowid().unwrap();
+
+        // A message exactly on the boundary is included
+        let floor = reader.rowid_before(base + chrono::Duration::minutes(60)).unwrap();
+        assert_eq!(floor, rowids[2] - 1);
+        assert_eq!(reader.count_since(floor).unwrap(), max - floor);
+
+        let floor = reader.rowid_before(base + chrono::Duration::minutes(45)).unwrap();
+        assert_eq!(floor, rowids[2] - 1);
+
+        // Nothing that recent: start from the top
+        let floor = reader.rowid_before(base + chrono::Duration::hours(5)).unwrap();
+        assert_eq!(floor, max);
+        assert_eq!(reader.count_since(floor).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_get_unreplied() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        let base = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        let at = |guid: &str, text: &str, minutes: i64, from_me: bool| {
+            let rowid = insert_message(&conn, guid, text, None);
+            conn.execute(
+                "UPDATE message SET date = ?1, is_from_me = ?2 WHERE ROWID = ?3",
+                rusqlite::params![datetime_to_macos(base + chrono::Duration::minutes(minutes)), from_me, rowid],
+            )
+            .unwrap();
+        };
+        at("U-1", "lunch?", 0, false);
+        at("U-2", "sure", 5, true);
+        at("U-3", "what time works?", 10, false);
+        at("U-4", "12:30?", 11, false);
+        // A tapback on our reply isn't something to answer
+        let tapback = insert_message(&conn, "U-5", "Liked \u{201c}sure\u{201d}", Some((2001, "p:0/U-2")));
+        conn.execute(
+            "UPDATE message SET date = ?1 WHERE ROWID = ?2",
+            rusqlite::params![datetime_to_macos(base + chrono::Duration::minutes(12)), tapback],
+        )
+        .unwrap();
+
+        // Newest inbound message since our last reply, once it's old enough
+        let three_hours_later = base + chrono::Duration::hours(3);
+        let msg = reader.get_unreplied("+16175551234", three_hours_later).unwrap().unwrap();
+        assert_eq!(msg.guid, "U-4");
+
+        // Too recent for the threshold: falls back to the older unreplied one
+        let msg = reader
+            .get_unreplied("+16175551234", base + chrono::Duration::minutes(10))
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.guid, "U-3");
+
+        // Nothing before our reply is unreplied
+        assert!(reader
+            .get_unreplied("+16175551234", base + chrono::Duration::minutes(7))
+            .unwrap()
+            .is_none());
+
+        // Replying clears it
+        at("U-6", "12:30 works", 20, true);
+        assert!(reader.get_unreplied("+16175551234", three_hours_later).unwrap().is_none());
+        assert!(reader.get_unreplied("chat-unknown", three_hours_later).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_classify_group_event() {
+        assert_eq!(
+            classify_group_event(2, 0, Some("Ski Trip 2025".to_string()), None),
+            Some(GroupEvent::Renamed {
+                name: Some("Ski Trip 2025".to_string())
+            })
+        );
+        assert_eq!(classify_group_event(2, 0, Some(" ".to_string()), None), Some(GroupEvent::Renamed { name: None }));
+        assert_eq!(
+            classify_group_event(1, 1, None, Some("+16175550000".to_string())),
+            Some(GroupEvent::ParticipantRemoved {
+                handle: "+16175550000".to_string()
+            })
+        );
+        assert_eq!(classify_group_event(3, 0, None, None), Some(GroupEvent::Left));
+        assert_eq!(classify_group_event(3, 1, None, None), Some(GroupEvent::PhotoChanged));
+        // Membership change without the other handle, and unrelated system rows
+        assert_eq!(classify_group_event(1, 0, None, None), None);
+        assert_eq!(classify_group_event(6, 0, None, None), None);
+    }
+
+    /// Insert a system row into a group chat (chat ROWID 2)
+    fn insert_group_event(conn: &Connection, guid: &str, item_type: i64, action: i64, title: Option<&str>, other: i64) {
+        conn.execute(
+            "INSERT INTO message (guid, text, handle_id, date, item_type, group_action_type, group_title, other_handle)
+             VALUES (?1, NULL, 1, (SELECT COUNT(*) FROM message), ?2, ?3, ?4, ?5)",
+            rusqlite::params![guid, item_type, action, title, other],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO chat_message_join (chat_id, message_id) VALUES (2, ?1)",
+            [conn.last_insert_rowid()],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_group_rename_and_add_from_db() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (reader, conn) = create_fixture_db(temp.path());
+        conn.execute_batch(
+            "INSERT INTO chat (chat_identifier, style, display_name) VALUES ('chat123456789', 43, 'Ski Trip 2025');
+             INSERT INTO handle (id, service) VALUES ('+16175550000', 'iMessage');",
+        )
+        .unwrap();
+        insert_group_event(&conn, "E-1", 2, 0, Some("Ski Trip 2025"), 0);
+        insert_group_event(&conn, "E-2", 1, 0, None, 2);
+        // Kept-audio and similar system rows are dropped
+        insert_group_event(&conn, "E-3", 5, 0, None, 0);
+
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].chat_id, "chat123456789");
+        assert_eq!(messages[0].text, "");
+        assert_eq!(
+            messages[0].kind,
+            MessageKind::GroupEvent {
+                event: GroupEvent::Renamed {
+                    name: Some("Ski Trip 2025".to_string())
+                }
+            }
+        );
+        assert_eq!(
+            messages[1].kind,
+            MessageKind::GroupEvent {
+                event: GroupEvent::ParticipantAdded {
+                    handle: "+16175550000".to_string()
+                }
+            }
+        );
+        let context = conversation_context(&messages[..1], |_| "Jane".to_string()).unwrap();
+        assert!(context.contains("Jane: (renamed the group to 'Ski Trip 2025')"));
+    }
+
+    #[test]
+    fn test_chat_join_backoff_bounded() {
+        // Short first wait, but never more than ~400ms total before deferring
+        assert_eq!(CHAT_JOIN_BACKOFF_MS[0], 25);
+        assert!(CHAT_JOIN_BACKOFF_MS.iter().sum::<u64>() <= 400);
+        assert!(CHAT_JOIN_BACKOFF_MS.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    /// Clock that records sleeps instead of sleeping, and can act on the Nth one
+    struct FakeClock {
+        start: Instant,
+        slept: Arc<Mutex<Vec<u64>>>,
+        on_sleep: Box<dyn Fn(usize) + Send + Sync>,
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.start + Duration::from_millis(self.slept.lock().unwrap().iter().sum())
+        }
+
+        fn sleep(&self, duration: Duration) {
+            let count = {
+                let mut slept = self.slept.lock().unwrap();
+                slept.push(duration.as_millis() as u64);
+                slept.len()
+            };
+            (self.on_sleep)(count);
+        }
+    }
+
+    fn fake_clock(reader: &mut MessagesReader, on_sleep: impl Fn(usize) + Send + Sync + 'static) -> Arc<Mutex<Vec<u64>>> {
+        let slept = Arc::new(Mutex::new(Vec::new()));
+        reader.clock = Box::new(FakeClock {
+            start: Instant::now(),
+            slept: slept.clone(),
+            on_sleep: Box::new(on_sleep),
+        });
+        slept
+    }
+
+    /// Insert a message into a new group chat, leaving out its chat_message_join row
+    fn insert_unjoined_group_message(conn: &Connection, guid: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO chat (chat_identifier, style, display_name) VALUES ('chat123456789', 43, 'Ski Trip')",
+            [],
+        )
+        .unwrap();
+        let rowid = insert_message(conn, guid, "who's driving?", None);
+        conn.execute("DELETE FROM chat_message_join WHERE message_id = ?1", [rowid])
+            .unwrap();
+        rowid
+    }
+
+    fn join_group(conn: &Connection, rowid: i64) {
+        conn.execute(
+            "INSERT INTO chat_message_join (chat_id, message_id)
+             SELECT ROWID, ?1 FROM chat WHERE chat_identifier = 'chat123456789'",
+            [rowid],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_chat_join_resolved_during_backoff() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut reader, conn) = create_fixture_db(temp.path());
+        // Like Messages.app, write in WAL mode so the reader's open statement doesn't block it
+        conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+        let rowid = insert_unjoined_group_message(&conn, "G-1");
+
+        // Join row lands while we're waiting out the second delay
+        let writer = Mutex::new(Connection::open(temp.path().join("chat.db")).unwrap());
+        let slept = fake_clock(&mut reader, move |count| {
+            if count == 2 {
+                join_group(&writer.lock().unwrap(), rowid);
+            }
+        });
+
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].chat_id, "chat123456789");
+        assert!(messages[0].is_group);
+        assert_eq!(*slept.lock().unwrap(), [25, 50]);
+        assert_eq!(
+            reader.race_stats(),
+            RaceStats {
+                requeried: 1,
+                resolved: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_chat_join_still_null_defers_message() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut reader, conn) = create_fixture_db(temp.path());
+        let before = insert_message(&conn, "D-1", "before", None);
+        let unjoined = insert_unjoined_group_message(&conn, "D-2");
+        insert_message(&conn, "D-3", "after", None);
+        let slept = fake_clock(&mut reader, |_| {});
+
+        // Only rows before the unresolved one come back; nothing past it is consumed
+        let messages = reader.get_new_messages(0).unwrap();
+        let rowids: Vec<i64> = messages.iter().map(|m| m.rowid).collect();
+        assert_eq!(rowids, [before]);
+        assert_eq!(slept.lock().unwrap().iter().sum::<u64>(), 375);
+        assert_eq!(reader.race_stats().deferred, 1);
+
+        // Next poll from the same floor picks it up with the right chat once the join exists
+        join_group(&conn, unjoined);
+        let messages = reader.get_new_messages(before).unwrap();
+        let guids: Vec<&str> = messages.iter().map(|m| m.guid.as_str()).collect();
+        assert_eq!(guids, ["D-2", "D-3"]);
+        assert_eq!(messages[0].chat_id, "chat123456789");
+        assert!(reader.deferrals.lock().unwrap().is_empty());
+    }
+
+    /// Our own messages wait for their chat like anyone's; in a group they have no handle
+    #[test]
+    fn test_outbound_chat_join_deferred() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut reader, conn) = create_fixture_db(temp.path());
+        let inbound = insert_unjoined_group_message(&conn, "G-1");
+        join_group(&conn, inbound);
+        let outbound = insert_message(&conn, "G-2", "I can drive", None);
+        conn.execute("DELETE FROM chat_message_join WHERE message_id = ?1", [outbound]).unwrap();
+        conn.execute("UPDATE message SET is_from_me = 1, handle_id = 0 WHERE ROWID = ?1", [outbound])
+            .unwrap();
+        insert_message(&conn, "D-1", "see you there", None);
+        fake_clock(&mut reader, |_| {});
+
+        let messages = reader.get_new_messages(0).unwrap();
+        let guids: Vec<&str> = messages.iter().map(|m| m.guid.as_str()).collect();
+        assert_eq!(guids, ["G-1"]);
+        assert_eq!(reader.race_stats().deferred, 1);
+
+        join_group(&conn, outbound);
+        let messages = reader.get_new_messages(inbound).unwrap();
+        let flags: Vec<(&str, bool, &str)> =
+            messages.iter().map(|m| (m.guid.as_str(), m.is_from_me, m.chat_id.as_str())).collect();
+        assert_eq!(flags, [("G-2", true, "chat123456789"), ("D-1", false, "+16175551234")]);
+        assert!(messages[0].sender.is_empty());
+    }
+
+    #[test]
+    fn test_chat_join_gives_up_after_max_deferrals() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (mut reader, conn) = create_fixture_db(temp.path());
+        insert_unjoined_group_message(&conn, "N-1");
+        let slept = fake_clock(&mut reader, |_| {});
+
+        for _ in 0..MAX_CHAT_JOIN_DEFERRALS {
+            assert!(reader.get_new_messages(0).unwrap().is_empty());
+        }
+        // Only the first poll waited on it
+        assert_eq!(slept.lock().unwrap().iter().sum::<u64>(), 375);
+        // Let through with 1:1 fallback rather than blocking the chat forever
+        let messages = reader.get_new_messages(0).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].chat_id, "+16175551234");
+
+        let stats = reader.race_stats();
+        assert_eq!(stats.requeried, 1);
+        assert_eq!(stats.deferred, MAX_CHAT_JOIN_DEFERRALS as u64);
+        assert_eq!(stats.gave_up, 1);
+    }
+}
@@ -0,0 +1,625 @@
+//! Decoder for the NeXTSTEP typedstream archives stored in `message.attributedBody`
+//!
+//! A typedstream is a flat sequence of type-encoding groups (`"@"`, `"iI"`, `"+"`, ...)
+//! followed by their values. Objects are written inline the first time they appear
+//! and by back-reference afterwards; the same goes for class names and type strings,
+//! which live in a separate shared-string table.
+//!
+//! An archived `NSAttributedString` holds its full text as one `NSString`, followed by
+//! `(index, length)` run headers each paired with an attribute `NSDictionary`.
+
+use crate::error::{Error, Result};
+use std::collections::HashSet;
+
+const TAG_INT16: u8 = 0x81;
+const TAG_INT32: u8 = 0x82;
+const TAG_FLOAT: u8 = 0x83;
+const TAG_NEW: u8 = 0x84;
+const TAG_NIL: u8 = 0x85;
+const TAG_END: u8 = 0x86;
+/// Back-references are written as small ints offset by this tag (0x92 -> index 0)
+const REFERENCE_BASE: i64 = -110;
+/// Objects and classes nested inline deeper than this are taken for a corrupt blob, not read to a stack overflow
+const MAX_NESTING: usize = 64;
+
+/// Text and attribute runs decoded from an attributedBody blob
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributedString {
+    pub text: String,
+    pub runs: Vec<AttributeRun>,
+}
+
+/// A span of the text (in UTF-16 units, as Foundation counts) and its attributes
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeRun {
+    pub length: usize,
+    pub attributes: Vec<(String, AttributeValue)>,
+}
+
+/// Attribute dictionary values we know how to interpret
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Number(i64),
+    /// Anything else (NSData, NSURL, ...), identified by class name
+    Other(String),
+}
+
+impl AttributedString {
+    /// First value of the named attribute across all runs
+    pub fn attribute(&self, key: &str) -> Option<&AttributeValue> {
+        self.runs
+            .iter()
+            .flat_map(|run| run.attributes.iter())
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Text covered by each run, paired with the run
+    pub fn run_texts(&self) -> Vec<(String, &AttributeRun)> {
+        let utf16: Vec<u16> = self.text.encode_utf16().collect();
+        let mut start = 0;
+        self.runs
+            .iter()
+            .map(|run| {
+                let end = (start + run.length).min(utf16.len());
+                let text = String::from_utf16_lossy(&utf16[start.min(end)..end]);
+                start = end;
+                (text, run)
+            })
+            .collect()
+    }
+}
+
+/// Decode an attributedBody blob into its text and attribute runs
+pub fn decode_attributed_string(data: &[u8]) -> Result<AttributedString> {
+    let archive = Decoder::new(data).decode()?;
+
+    let root = archive
+        .roots
+        .iter()
+        .find_map(|v| match v {
+            Value::Object(Some(idx)) if archive.is_kind_of(*idx, "NSAttributedString") => Some(*idx),
+            _ => None,
+        })
+        .ok_or_else(|| Error::Parse("typedstream has no NSAttributedString".to_string()))?;
+
+    let values = archive.object_values(root);
+    let mut values = values.iter();
+
+    // First value is the backing NSString (or NSMutableString)
+    let text = match values.next() {
+        Some(Value::Object(Some(idx))) => archive.string_content(*idx),
+        _ => None,
+    }
+    .ok_or_else(|| Error::Parse("attributed string has no text".to_string()))?;
+
+    // The rest are (index, length) headers each followed by an attribute dictionary
+    let mut runs = Vec::new();
+    let mut pending_length = None;
+    for value in values {
+        match value {
+            Value::Int(_) if pending_length.is_none() => pending_length = Some(None),
+            Value::Int(len) | Value::UInt(len) if pending_length == Some(None) => {
+                pending_length = Some(Some(*len as usize))
+            }
+            Value::Object(dict) => {
+                let length = pending_length.take().flatten().unwrap_or(0);
+                let attributes = dict.map(|idx| archive.dictionary(idx)).unwrap_or_default();
+                runs.push(AttributeRun { length, attributes });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(AttributedString { text, runs })
+}
+
+/// A decoded value
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(i64),
+    UInt(i64),
+    Float(f64),
+    /// '+' UTF-8 string
+    String(String),
+    /// '*' C string (shared)
+    CString(Option<String>),
+    /// Array of bytes ('[Nc]')
+    Bytes(Vec<u8>),
+    /// Index into the object table, None for nil
+    Object(Option<usize>),
+}
+
+/// Object-table entry: objects, classes, and C strings share one reference space
+#[derive(Debug, Clone)]
+enum Entry {
+    Placeholder,
+    CString(Option<String>),
+    Class {
+        name: String,
+        superclass: Option<usize>,
+    },
+    Object {
+        class: Option<usize>,
+        values: Vec<Value>,
+    },
+}
+
+struct Archive {
+    objects: Vec<Entry>,
+    roots: Vec<Value>,
+}
+
+impl Archive {
+    fn class_of(&self, idx: usize) -> Option<usize> {
+        match self.objects.get(idx)? {
+            Entry::Object { class, .. } => *class,
+            _ => None,
+        }
+    }
+
+    /// Whether the object's class, or any superclass, is `class_name`
+    fn is_kind_of(&self, idx: usize, class_name: &str) -> bool {
+        let mut class = self.class_of(idx);
+        // A chain longer than the table has looped back on itself
+        for _ in 0..=self.objects.len() {
+            let Some(Entry::Class { name, superclass }) = class.and_then(|c| self.objects.get(c)) else {
+                return false;
+            };
+            if name == class_name {
+                return true;
+            }
+            class = *superclass;
+        }
+        false
+    }
+
+    fn class_name(&self, idx: usize) -> String {
+        match self.class_of(idx).and_then(|c| self.objects.get(c)) {
+            Some(Entry::Class { name, .. }) => name.clone(),
+            _ => "unknown".to_string(),
+        }
+    }
+
+    fn object_values(&self, idx: usize) -> &[Value] {
+        match self.objects.get(idx) {
+            Some(Entry::Object { values, .. }) => values,
+            _ => &[],
+        }
+    }
+
+    /// Concatenated '+' contents of an NSString-like object
+    fn string_content(&self, idx: usize) -> Option<String> {
+        self.collect_string(idx, &mut HashSet::new())
+    }
+
+    /// `string_content`, reading each object at most once however the references loop
+    fn collect_string(&self, idx: usize, seen: &mut HashSet<usize>) -> Option<String> {
+        if !seen.insert(idx) {
+            return None;
+        }
+        let mut text = String::new();
+        let mut found = false;
+        for value in self.object_values(idx) {
+            match value {
+                Value::String(s) => {
+                    text.push_str(s);
+                    found = true;
+                }
+                // NSMutableString wraps its storage in a nested string object
+                Value::Object(Some(inner)) => {
+                    if let Some(s) = self.collect_string(*inner, seen) {
+                        text.push_str(&s);
+                        found = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        found.then_some(text)
+    }
+
+    fn number(&self, idx: usize) -> Option<i64> {
+        self.object_values(idx).iter().find_map(|v| match v {
+            Value::Int(n) | Value::UInt(n) => Some(*n),
+            Value::Float(f) => Some(*f as i64),
+            _ => None,
+        })
+    }
+
+    fn attribute_value(&self, idx: usize) -> AttributeValue {
+        if self.is_kind_of(idx, "NSString") {
+            if let Some(s) = self.string_content(idx) {
+                return AttributeValue::String(s);
+            }
+        }
+        if self.is_kind_of(idx, "NSNumber") {
+            if let Some(n) = self.number(idx) {
+                return AttributeValue::Number(n);
+            }
+        }
+        AttributeValue::Other(self.class_name(idx))
+    }
+
+    /// Key/value pairs of an NSDictionary: a count, then alternating key and value objects
+    fn dictionary(&self, idx: usize) -> Vec<(String, AttributeValue)> {
+        let objects: Vec<usize> = self
+            .object_values(idx)
+            .iter()
+            .filter_map(|v| match v {
+                Value::Object(Some(i)) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        objects
+            .chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .filter_map(|pair| {
+                let key = self.string_content(pair[0])?;
+                Some((key, self.attribute_value(pair[1])))
+            })
+            .collect()
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    strings: Vec<Vec<u8>>,
+    objects: Vec<Entry>,
+    /// Objects and classes being read inline inside one another
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            strings: Vec::new(),
+            objects: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn decode(mut self) -> Result<Archive> {
+        self.read_header()?;
+
+        let mut roots = Vec::new();
+        while self.pos < self.data.len() {
+            if self.peek()? == TAG_END {
+                self.pos += 1;
+                continue;
+            }
+            let types = self.read_type_encoding()?;
+            roots.extend(self.read_values(&types)?);
+        }
+
+        Ok(Archive {
+            objects: self.objects,
+            roots,
+        })
+    }
+
+    fn read_header(&mut self) -> Result<()> {
+        let version = self.next()?;
+        let signature = self.read_raw_string()?;
+        if version != 4 || signature != b"streamtyped" {
+            return Err(Error::Parse("not a typedstream".to_string()));
+        }
+        // System version
+        self.read_int()?;
+        Ok(())
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| Error::Parse(format!("typedstream truncated at {}", self.pos)))
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| Error::Parse(format!("typedstream truncated reading {} bytes", len)))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_int(&mut self) -> Result<i64> {
+        match self.next()? {
+            TAG_INT16 => {
+                let b = self.take(2)?;
+                Ok(i16::from_le_bytes([b[0], b[1]]) as i64)
+            }
+            TAG_INT32 => {
+                let b = self.take(4)?;
+                Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as i64)
+            }
+            b => Ok(b as i8 as i64),
+        }
+    }
+
+    fn read_uint(&mut self) -> Result<i64> {
+        match self.next()? {
+            TAG_INT16 => {
+                let b = self.take(2)?;
+                Ok(u16::from_le_bytes([b[0], b[1]]) as i64)
+            }
+            TAG_INT32 => {
+                let b = self.take(4)?;
+                Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as i64)
+            }
+            b => Ok(b as i64),
+        }
+    }
+
+    fn read_float(&mut self, double: bool) -> Result<f64> {
+        if self.peek()? != TAG_FLOAT {
+            return Ok(self.read_int()? as f64);
+        }
+        self.pos += 1;
+        if double {
+            let b = self.take(8)?;
+            Ok(f64::from_le_bytes(b.try_into().expect("8 bytes")))
+        } else {
+            let b = self.take(4)?;
+            Ok(f32::from_le_bytes(b.try_into().expect("4 bytes")) as f64)
+        }
+    }
+
+    /// Length-prefixed bytes (not shared)
+    fn read_raw_string(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_uint()?;
+        self.take(len as usize)
+    }
+
+    /// Decode a back-reference tag into a table index
+    fn read_reference(&mut self) -> Result<usize> {
+        let tag = self.read_int()?;
+        usize::try_from(tag - REFERENCE_BASE)
+            .map_err(|_| Error::Parse(format!("bad typedstream reference {}", tag)))
+    }
+
+    /// A string from the shared table: new (0x84), nil (0x85), or a back-reference
+    fn read_shared_string(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.peek()? {
+            TAG_NEW => {
+                self.pos += 1;
+                let bytes = self.read_raw_string()?.to_vec();
+                self.strings.push(bytes.clone());
+                Ok(Some(bytes))
+            }
+            TAG_NIL => {
+                self.pos += 1;
+                Ok(None)
+            }
+            _ => {
+                let idx = self.read_reference()?;
+                self.strings
+                    .get(idx)
+                    .cloned()
+                    .map(Some)
+                    .ok_or_else(|| Error::Parse(format!("dangling typedstream string reference {}", idx)))
+            }
+        }
+    }
+
+    fn read_type_encoding(&mut self) -> Result<Vec<u8>> {
+        self.read_shared_string()?
+            .ok_or_else(|| Error::Parse("nil type encoding".to_string()))
+    }
+
+    /// Read one value for each type in an encoding like "iI" or "[565c]"
+    fn read_values(&mut self, types: &[u8]) -> Result<Vec<Value>> {
+        let mut values = Vec::new();
+        let mut i = 0;
+        while i < types.len() {
+            let value = match types[i] {
+                b'@' => self.read_object()?,
+                b'+' => Value::String(String::from_utf8_lossy(self.read_raw_string()?).into_owned()),
+                b'*' => self.read_c_string()?,
+                b'c' | b'i' | b'l' | b'q' | b's' => Value::Int(self.read_int()?),
+                b'C' | b'I' | b'L' | b'Q' | b'S' => Value::UInt(self.read_uint()?),
+                b'f' => Value::Float(self.read_float(false)?),
+                b'd' => Value::Float(self.read_float(true)?),
+                b'[' => {
+                    let close = types[i..]
+                        .iter()
+                        .position(|&b| b == b']')
+                        .map(|p| i + p)
+                        .ok_or_else(|| Error::Parse("unterminated array type".to_string()))?;
+                    let spec = &types[i + 1..close];
+                    let count: usize = std::str::from_utf8(&spec[..spec.len().saturating_sub(1)])
+                        .ok()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or_else(|| Error::Parse("bad array type".to_string()))?;
+                    i = close;
+                    Value::Bytes(self.take(count)?.to_vec())
+                }
+                other => {
+                    return Err(Error::Parse(format!("unsupported typedstream type '{}'", other as char)));
+                }
+            };
+            values.push(value);
+            i += 1;
+        }
+        Ok(values)
+    }
+
+    /// A C string: new ones go into the object table, repeats are back-references to it
+    fn read_c_string(&mut self) -> Result<Value> {
+        match self.peek()? {
+            TAG_NIL => {
+                self.pos += 1;
+                Ok(Value::CString(None))
+            }
+            TAG_NEW => {
+                self.pos += 1;
+                let s = self
+                    .read_shared_string()?
+                    .map(|b| String::from_utf8_lossy(&b).into_owned());
+                self.objects.push(Entry::CString(s.clone()));
+                Ok(Value::CString(s))
+            }
+            _ => {
+                let idx = self.read_reference()?;
+                match self.objects.get(idx) {
+                    Some(Entry::CString(s)) => Ok(Value::CString(s.clone())),
+                    _ => Err(Error::Parse(format!("dangling typedstream C string reference {}", idx))),
+                }
+            }
+        }
+    }
+
+    fn read_object(&mut self) -> Result<Value> {
+        match self.peek()? {
+            TAG_NIL => {
+                self.pos += 1;
+                Ok(Value::Object(None))
+            }
+            TAG_NEW => {
+                self.pos += 1;
+                self.enter()?;
+                let idx = self.objects.len();
+                self.objects.push(Entry::Placeholder);
+                let class = self.read_class()?;
+
+                let mut values = Vec::new();
+                while self.peek()? != TAG_END {
+                    let types = self.read_type_encoding()?;
+                    values.extend(self.read_values(&types)?);
+                }
+                self.pos += 1;
+
+                self.objects[idx] = Entry::Object { class, values };
+                self.depth -= 1;
+                Ok(Value::Object(Some(idx)))
+            }
+            _ => Ok(Value::Object(Some(self.read_reference()?))),
+        }
+    }
+
+    /// A class and its superclass chain, ending in nil
+    fn read_class(&mut self) -> Result<Option<usize>> {
+        match self.peek()? {
+            TAG_NIL => {
+                self.pos += 1;
+                Ok(None)
+            }
+            TAG_NEW => {
+                self.pos += 1;
+                let name = self
+                    .read_shared_string()?
+                    .map(|b| String::from_utf8_lossy(&b).into_owned())
+                    .unwrap_or_default();
+                let _version = self.read_int()?;
+                self.enter()?;
+                let idx = self.objects.len();
+                self.objects.push(Entry::Class { name, superclass: None });
+                let superclass = self.read_class()?;
+                if let Entry::Class { superclass: slot, .. } = &mut self.objects[idx] {
+                    *slot = superclass;
+                }
+                self.depth -= 1;
+                Ok(Some(idx))
+            }
+            _ => Ok(Some(self.read_reference()?)),
+        }
+    }
+
+    /// Go one object or class deeper, failing past `MAX_NESTING`
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(Error::Parse(format!("typedstream nested more than {} deep", MAX_NESTING)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_non_typedstream() {
+        assert!(decode_attributed_string(b"bplist00").is_err());
+        assert!(decode_attributed_string(&[]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_stream_errors() {
+        // Header, "@" type, then an NSAttributedString object cut off mid-class
+        let mut data = vec![0x04, 0x0B];
+        data.extend_from_slice(b"streamtyped");
+        data.extend_from_slice(&[0x81, 0xE8, 0x03, 0x84, 0x01, b'@', 0x84, 0x84, 0x84, 0x12]);
+        data.extend_from_slice(b"NSAttributed");
+        assert!(matches!(decode_attributed_string(&data), Err(Error::Parse(_))));
+    }
+
+    /// Header, then `body` as the archive's contents
+    fn typedstream(body: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x04, 0x0B];
+        data.extend_from_slice(b"streamtyped");
+        data.extend_from_slice(&[0x81, 0xE8, 0x03]);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn test_class_that_is_its_own_superclass() {
+        // "@", then an object whose class (index 1) names itself as its superclass
+        let mut body = vec![0x84, 0x01, b'@', 0x84, 0x84, 0x84, 0x05];
+        body.extend_from_slice(b"NSFoo");
+        body.extend_from_slice(&[0x00, 0x93, 0x86]);
+        assert!(matches!(decode_attributed_string(&typedstream(&body)), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_strings_that_contain_each_other() {
+        // An NSAttributedString whose text is string 2, which holds string 4, which holds string 2
+        let mut body = vec![0x84, 0x01, b'@', 0x84, 0x84, 0x84, 0x12];
+        body.extend_from_slice(b"NSAttributedString");
+        body.extend_from_slice(&[0x00, 0x85, 0x92, 0x84, 0x84, 0x84, 0x08]);
+        body.extend_from_slice(b"NSString");
+        body.extend_from_slice(&[0x00, 0x85, 0x92, 0x84, 0x95, 0x92, 0x94, 0x86, 0x86, 0x86]);
+        assert!(matches!(decode_attributed_string(&typedstream(&body)), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_deep_nesting_errors() {
+        // Classless objects, each the only value of the one before
+        let mut body = vec![0x84, 0x01, b'@'];
+        for _ in 0..100_000 {
+            body.extend_from_slice(&[0x84, 0x85, 0x92]);
+        }
+        body.push(0x85);
+        body.extend(std::iter::repeat_n(0x86, 100_000));
+        assert!(matches!(decode_attributed_string(&typedstream(&body)), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_run_texts_count_utf16() {
+        let decoded = AttributedString {
+            text: "\u{1F355} pizza".to_string(),
+            runs: vec![
+                AttributeRun { length: 2, attributes: Vec::new() },
+                AttributeRun { length: 6, attributes: Vec::new() },
+            ],
+        };
+        let texts: Vec<String> = decoded.run_texts().into_iter().map(|(t, _)| t).collect();
+        assert_eq!(texts, ["\u{1F355}", " pizza"]);
+    }
+}
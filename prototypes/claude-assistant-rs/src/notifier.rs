@@ -0,0 +1,195 @@
+//! Texting people from the daemon
+//!
+//! Alerts to the admins and notices to a session's chat go out through
+//! `send-sms`. The daemon sends them through a `Notifier`, so tests can use
+//! `RecordingNotifier` and look at what would have been sent. It remembers
+//! what it sent with a `RememberingNotifier`, since its texts come back
+//! through chat.db looking like the owner's own replies.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::runner::{CommandRunner, SystemRunner};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Sends a text to a handle or chat
+pub trait Notifier: Send + Sync {
+    fn send(&self, to: &str, text: &str) -> Result<()>;
+}
+
+/// Sends through `send-sms <to> <text>`
+pub struct SmsNotifier {
+    send_sms: PathBuf,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl SmsNotifier {
+    pub fn new(config: &Config) -> Self {
+        Self::with_runner(config, Arc::new(SystemRunner))
+    }
+
+    /// Run `send-sms` through `runner` instead of for real
+    pub fn with_runner(config: &Config, runner: Arc<dyn CommandRunner>) -> Self {
+        Self { send_sms: config.send_sms.clone(), runner }
+    }
+}
+
+impl Notifier for SmsNotifier {
+    fn send(&self, to: &str, text: &str) -> Result<()> {
+        let output = self
+            .runner
+            .run(&self.send_sms, &[to.to_string(), text.to_string()])
+            .map_err(|e| Error::CommandFailed(format!("couldn't run send-sms: {}", e)))?;
+        if !output.status.success() {
+            return Err(Error::CommandFailed(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Sends through another notifier, remembering the last texts it sent
+pub struct RememberingNotifier {
+    notifier: Arc<dyn Notifier>,
+    capacity: usize,
+    /// (to, text), oldest first
+    sent: Mutex<VecDeque<(String, String)>>,
+}
+
+impl RememberingNotifier {
+    pub fn new(notifier: Arc<dyn Notifier>, capacity: usize) -> Self {
+        Self { notifier, capacity, sent: Mutex::new(VecDeque::new()) }
+    }
+
+    /// Whether `text` is one of the last texts sent to `to`, ignoring surrounding whitespace
+    pub fn sent_recently(&self, to: &str, text: &str) -> bool {
+        let text = text.trim();
+        self.sent.lock().unwrap_or_else(|e| e.into_inner()).iter().any(|(handle, sent)| handle == to && sent.trim() == text)
+    }
+}
+
+impl Notifier for RememberingNotifier {
+    fn send(&self, to: &str, text: &str) -> Result<()> {
+        self.notifier.send(to, text)?;
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        sent.push_back((to.to_string(), text.to_string()));
+        while sent.len() > self.capacity {
+            sent.pop_front();
+        }
+        Ok(())
+    }
+}
+
+/// Keeps what it's asked to send instead of sending it
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Default)]
+pub struct RecordingNotifier {
+    sent: Mutex<Vec<(String, String)>>,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl RecordingNotifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything sent so far, oldest first, as (to, text)
+    pub fn sent(&self) -> Vec<(String, String)> {
+        self.sent.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Texts sent to `to`
+    pub fn sent_to(&self, to: &str) -> Vec<String> {
+        self.sent().into_iter().filter(|(handle, _)| handle == to).map(|(_, text)| text).collect()
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl Notifier for RecordingNotifier {
+    fn send(&self, to: &str, text: &str) -> Result<()> {
+        self.sent.lock().unwrap_or_else(|e| e.into_inner()).push((to.to_string(), text.to_string()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::Path;
+    use std::process::{ExitStatus, Output};
+
+    /// send-sms as far as `SmsNotifier` can tell: records its arguments, then
+    /// exits with `code` and prints `stderr`, or can't be run at all
+    struct FakeSendSms {
+        calls: Mutex<Vec<(PathBuf, Vec<String>)>>,
+        result: Option<(i32, &'static str)>,
+    }
+
+    impl FakeSendSms {
+        fn new(result: Option<(i32, &'static str)>) -> Arc<Self> {
+            Arc::new(Self { calls: Mutex::new(Vec::new()), result })
+        }
+    }
+
+    impl CommandRunner for FakeSendSms {
+        fn run(&self, program: &Path, args: &[String]) -> std::io::Result<Output> {
+            self.calls.lock().unwrap().push((program.to_path_buf(), args.to_vec()));
+            let (code, stderr) = self.result.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+            Ok(Output {
+                status: ExitStatus::from_raw(code << 8),
+                stdout: Vec::new(),
+                stderr: stderr.as_bytes().to_vec(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_sms_notifier() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let send_sms = FakeSendSms::new(Some((0, "")));
+        SmsNotifier::with_runner(&config, send_sms.clone()).send("+16175551234", "hello there").unwrap();
+        assert_eq!(
+            *send_sms.calls.lock().unwrap(),
+            [(config.send_sms.clone(), vec!["+16175551234".to_string(), "hello there".to_string()])]
+        );
+
+        let err = SmsNotifier::with_runner(&config, FakeSendSms::new(Some((1, "not signed in\n"))))
+            .send("+16175551234", "hello")
+            .unwrap_err();
+        assert_eq!(err.to_string(), Error::CommandFailed("not signed in".to_string()).to_string());
+        assert!(SmsNotifier::with_runner(&config, FakeSendSms::new(None)).send("+16175551234", "hello").is_err());
+    }
+
+    #[test]
+    fn test_recording_notifier() {
+        let notifier = RecordingNotifier::new();
+        notifier.send("+16175551234", "one").unwrap();
+        notifier.send("jane@icloud.com", "two").unwrap();
+        notifier.send("+16175551234", "three").unwrap();
+        assert_eq!(notifier.sent().len(), 3);
+        assert_eq!(notifier.sent_to("+16175551234"), ["one", "three"]);
+    }
+
+    #[test]
+    fn test_remembering_notifier() {
+        let recorder = Arc::new(RecordingNotifier::new());
+        let notifier = RememberingNotifier::new(recorder.clone(), 2);
+        notifier.send("+16175551234", "one ").unwrap();
+        assert!(notifier.sent_recently("+16175551234", "one"));
+        assert!(!notifier.sent_recently("+16175550000", "one"));
+        notifier.send("+16175551234", "two").unwrap();
+        notifier.send("+16175551234", "three").unwrap();
+        assert!(!notifier.sent_recently("+16175551234", "one"));
+        assert!(notifier.sent_recently("+16175551234", "three"));
+        assert_eq!(recorder.sent().len(), 3);
+
+        // Nothing to remember if it never went
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let failing = RememberingNotifier::new(Arc::new(SmsNotifier::with_runner(&config, FakeSendSms::new(None))), 2);
+        assert!(failing.send("+16175551234", "lost").is_err());
+        assert!(!failing.sent_recently("+16175551234", "lost"));
+    }
+}
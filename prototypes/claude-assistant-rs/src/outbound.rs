@@ -0,0 +1,146 @@
+//! Outbound message tracking
+//!
+//! Messages sent from the owner's devices show up in chat.db as `is_from_me`.
+//! When a chat has a session, those replies are appended to the session's
+//! `conversation.log` (and optionally injected) so Claude knows the
+//! conversation moved on without it.
+
+use crate::error::Result;
+use crate::messages::Message;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Who sent an outbound message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboundAuthor {
+    /// Sent by hand from the owner's phone/Mac
+    Owner,
+    /// Sent by the Claude session itself via send-sms
+    Assistant,
+}
+
+/// Path of the per-session conversation log
+pub fn conversation_log_path(transcript_dir: &Path) -> PathBuf {
+    transcript_dir.join("conversation.log")
+}
+
+/// Append an outbound message to `<transcript_dir>/conversation.log`
+pub fn append_conversation_log(transcript_dir: &Path, msg: &Message, author: OutboundAuthor) -> Result<()> {
+    std::fs::create_dir_all(transcript_dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(conversation_log_path(transcript_dir))?;
+
+    let label = match author {
+        OutboundAuthor::Owner => "You",
+        OutboundAuthor::Assistant => "Claude",
+    };
+    let mut parts = vec![msg.body_text()];
+    parts.extend(
+        msg.attachments
+            .iter()
+            .map(|a| format!("[attachment: {} ({})]", a.name, a.mime_type)),
+    );
+    parts.retain(|p| !p.is_empty());
+    writeln!(
+        file,
+        "[{}] {}: {}",
+        msg.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        label,
+        parts.join(" ")
+    )?;
+    Ok(())
+}
+
+/// Low-priority note telling the session about a reply sent outside of it
+pub fn outbound_note(msg: &Message) -> String {
+    let text = msg.body_text();
+    let names: Vec<&str> = msg.attachments.iter().map(|a| a.name.as_str()).collect();
+    match (text.is_empty(), names.is_empty()) {
+        (_, true) => format!("[You replied directly: {}]", text),
+        (true, false) => format!("[You replied directly with attachments: {}]", names.join(", ")),
+        (false, false) => format!("[You replied directly: {} (attachments: {})]", text, names.join(", ")),
+    }
+}
+
+/// Whether outbound text already appears in the session's pane, i.e. Claude sent it
+///
+/// Whitespace and shell escaping are ignored so wrapped or quoted send-sms
+/// invocations still match.
+pub fn sent_by_session(pane: &str, text: &str) -> bool {
+    let needle = squash(text);
+    !needle.is_empty() && squash(pane).contains(&needle)
+}
+
+fn squash(s: &str) -> String {
+    s.chars().filter(|c| !c.is_whitespace() && *c != '\\').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::Attachment;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn outbound(text: &str) -> Message {
+        Message {
+            text: text.to_string(),
+            is_from_me: true,
+            chat_id: "+16175551234".to_string(),
+            timestamp: chrono::Utc.with_ymd_and_hms(2026, 3, 1, 18, 30, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_append_conversation_log() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().join("john-doe");
+
+        append_conversation_log(&dir, &outbound("on my way"), OutboundAuthor::Owner).unwrap();
+        let mut with_photo = outbound("");
+        with_photo.attachments.push(Attachment {
+            path: "/tmp/IMG_1.jpeg".to_string(),
+            mime_type: "image/jpeg".to_string(),
+            name: "IMG_1.jpeg".to_string(),
+            size: 10,
+        });
+        append_conversation_log(&dir, &with_photo, OutboundAuthor::Owner).unwrap();
+        append_conversation_log(&dir, &outbound("Sure, booked it"), OutboundAuthor::Assistant).unwrap();
+
+        let log = std::fs::read_to_string(conversation_log_path(&dir)).unwrap();
+        assert_eq!(
+            log,
+            "[2026-03-01 18:30:00] You: on my way\n\
+             [2026-03-01 18:30:00] You: [attachment: IMG_1.jpeg (image/jpeg)]\n\
+             [2026-03-01 18:30:00] Claude: Sure, booked it\n"
+        );
+    }
+
+    #[test]
+    fn test_outbound_note() {
+        assert_eq!(outbound_note(&outbound("on my way")), "[You replied directly: on my way]");
+
+        let mut photo = outbound("");
+        photo.attachments.push(Attachment {
+            path: "/tmp/IMG_1.jpeg".to_string(),
+            mime_type: "image/jpeg".to_string(),
+            name: "IMG_1.jpeg".to_string(),
+            size: 10,
+        });
+        assert_eq!(outbound_note(&photo), "[You replied directly with attachments: IMG_1.jpeg]");
+        photo.text = "look".to_string();
+        assert_eq!(outbound_note(&photo), "[You replied directly: look (attachments: IMG_1.jpeg)]");
+    }
+
+    #[test]
+    fn test_sent_by_session() {
+        let pane = "⏺ Bash(~/code/sms-cli/send-sms \"+16175551234\" \"Sure, I booked the table\n  for 7pm \\\"upstairs\\\"\")";
+        assert!(sent_by_session(pane, "Sure, I booked the table for 7pm \"upstairs\""));
+        assert!(!sent_by_session(pane, "on my way"));
+        assert!(!sent_by_session(pane, ""));
+    }
+}
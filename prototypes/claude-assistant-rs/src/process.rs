@@ -0,0 +1,177 @@
+//! Memory and CPU of a session's processes
+//!
+//! A session's pane runs Claude, and Claude runs its tools and MCP servers as
+//! children. `ps` lists every process with its parent; a session's usage is
+//! its pane's process and all of its descendants together, so a runaway node
+//! process is caught wherever it sits in the tree.
+
+use crate::runner::CommandRunner;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What `ps` is asked to print: every process, no header
+pub const PS_ARGS: [&str; 3] = ["-A", "-o", "pid=,ppid=,rss=,%cpu="];
+
+/// One line of `ps` output
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessRow {
+    pub pid: u32,
+    pub ppid: u32,
+    pub rss_kb: u64,
+    pub cpu_pct: f64,
+}
+
+/// Resident memory and CPU of a process tree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessUsage {
+    pub rss_mb: u64,
+    pub cpu_pct: f64,
+}
+
+impl ProcessUsage {
+    /// Whether either figure is past its limit; a limit of 0 isn't checked
+    pub fn over(&self, max_rss_mb: u64, max_cpu_pct: f64) -> bool {
+        (max_rss_mb > 0 && self.rss_mb > max_rss_mb) || (max_cpu_pct > 0.0 && self.cpu_pct > max_cpu_pct)
+    }
+}
+
+/// Rows of `ps -A -o pid=,ppid=,rss=,%cpu=` output, skipping any that don't parse
+pub fn parse_ps(output: &str) -> Vec<ProcessRow> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let row = ProcessRow {
+                pid: fields.next()?.parse().ok()?,
+                ppid: fields.next()?.parse().ok()?,
+                rss_kb: fields.next()?.parse().ok()?,
+                // Some locales print "1,5"
+                cpu_pct: fields.next()?.replace(',', ".").parse().ok()?,
+            };
+            Some(row)
+        })
+        .collect()
+}
+
+/// `root` and everything under it, parents before their children; empty if it isn't running
+pub fn tree_rows(rows: &[ProcessRow], root: u32) -> Vec<&ProcessRow> {
+    let mut children: HashMap<u32, Vec<&ProcessRow>> = HashMap::new();
+    for row in rows {
+        children.entry(row.ppid).or_default().push(row);
+    }
+    let mut stack: Vec<&ProcessRow> = rows.iter().filter(|row| row.pid == root).take(1).collect();
+    let mut tree = Vec::new();
+    while let Some(row) = stack.pop() {
+        tree.push(row);
+        // pid 0's parent is itself on some systems
+        stack.extend(children.get(&row.pid).into_iter().flatten().rev().filter(|child| child.pid != row.pid));
+    }
+    tree
+}
+
+/// Usage of `root` and everything under it, or None if it isn't running
+pub fn tree_usage(rows: &[ProcessRow], root: u32) -> Option<ProcessUsage> {
+    let tree = tree_rows(rows, root);
+    if tree.is_empty() {
+        return None;
+    }
+    let rss_kb: u64 = tree.iter().map(|row| row.rss_kb).sum();
+    let cpu_pct: f64 = tree.iter().map(|row| row.cpu_pct).sum();
+    Some(ProcessUsage { rss_mb: rss_kb / 1024, cpu_pct })
+}
+
+/// Every process, asking `ps`
+fn list_processes(runner: &dyn CommandRunner, ps: &Path) -> Option<Vec<ProcessRow>> {
+    let args: Vec<String> = PS_ARGS.iter().map(|arg| arg.to_string()).collect();
+    let output = runner.run(ps, &args).ok().filter(|output| output.status.success())?;
+    Some(parse_ps(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Usage of `root` and its descendants, asking `ps`
+pub fn process_usage(runner: &dyn CommandRunner, ps: &Path, root: u32) -> Option<ProcessUsage> {
+    tree_usage(&list_processes(runner, ps)?, root)
+}
+
+/// `root` and its descendants, asking `ps`, or None if it isn't running
+pub fn process_tree(runner: &dyn CommandRunner, ps: &Path, root: u32) -> Option<Vec<ProcessRow>> {
+    let tree: Vec<ProcessRow> = tree_rows(&list_processes(runner, ps)?, root).into_iter().cloned().collect();
+    (!tree.is_empty()).then_some(tree)
+}
+
+/// Rows as `ps` would print them, under a header `parse_ps` skips
+pub fn format_ps(rows: &[ProcessRow]) -> String {
+    let mut text = format!("{:>7} {:>7} {:>10} {:>6}\n", "PID", "PPID", "RSS", "%CPU");
+    for row in rows {
+        text.push_str(&format!("{:>7} {:>7} {:>10} {:>6.1}\n", row.pid, row.ppid, row.rss_kb, row.cpu_pct));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::SystemRunner;
+
+    /// launchd, a tmux server, and a session's shell running Claude, which runs an MCP server and a build
+    const PS_OUTPUT: &str = "    1     0  12000   0.3
+  410     1   8200   0.1
+  500   410   3100   0.0
+  501   500 6291456  97.5
+  502   501  81920   1.2
+  503   501 204800  55.0
+  600   410   3100   0.0
+  601   600 409600   2.0
+";
+
+    #[test]
+    fn test_parse_ps() {
+        let rows = parse_ps(PS_OUTPUT);
+        assert_eq!(rows.len(), 8);
+        assert_eq!(rows[3], ProcessRow { pid: 501, ppid: 500, rss_kb: 6291456, cpu_pct: 97.5 });
+        let rows = parse_ps("  PID  PPID   RSS  %CPU\n  42 1 2048 1,5\n\n  garbage\n  43 1 x 0.0\n");
+        assert_eq!(rows, [ProcessRow { pid: 42, ppid: 1, rss_kb: 2048, cpu_pct: 1.5 }]);
+    }
+
+    #[test]
+    fn test_tree_usage() {
+        let rows = parse_ps(PS_OUTPUT);
+        let usage = tree_usage(&rows, 500).unwrap();
+        // 3100 + 6291456 + 81920 + 204800 KB, and none of the other session's
+        assert_eq!(usage.rss_mb, 6427);
+        assert!((usage.cpu_pct - 153.7).abs() < 0.01);
+        assert_eq!(tree_usage(&rows, 601).unwrap(), ProcessUsage { rss_mb: 400, cpu_pct: 2.0 });
+        assert_eq!(tree_usage(&rows, 999), None);
+    }
+
+    #[test]
+    fn test_tree_rows() {
+        let rows = parse_ps(PS_OUTPUT);
+        let pids: Vec<u32> = tree_rows(&rows, 500).iter().map(|row| row.pid).collect();
+        assert_eq!(pids, [500, 501, 502, 503]);
+        assert!(tree_rows(&rows, 999).is_empty());
+        let tree: Vec<ProcessRow> = tree_rows(&rows, 600).into_iter().cloned().collect();
+        assert_eq!(parse_ps(&format_ps(&tree)), tree);
+    }
+
+    #[test]
+    fn test_usage_over() {
+        let usage = ProcessUsage { rss_mb: 6427, cpu_pct: 153.7 };
+        assert!(usage.over(6144, 0.0));
+        assert!(!usage.over(8192, 0.0));
+        assert!(usage.over(8192, 150.0));
+        assert!(!usage.over(0, 200.0));
+        assert!(!usage.over(0, 0.0));
+    }
+
+    #[test]
+    fn test_process_usage() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let ps = temp.path().join("ps");
+        std::fs::write(&ps, format!("#!/bin/sh\ncat <<'EOF'\n{}EOF\n", PS_OUTPUT)).unwrap();
+        std::fs::set_permissions(&ps, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        assert_eq!(process_usage(&SystemRunner, &ps, 600).unwrap().rss_mb, 403);
+        assert_eq!(process_usage(&SystemRunner, &temp.path().join("missing"), 600), None);
+        assert_eq!(process_tree(&SystemRunner, &ps, 600).unwrap().len(), 2);
+        assert_eq!(process_tree(&SystemRunner, &ps, 999), None);
+    }
+}
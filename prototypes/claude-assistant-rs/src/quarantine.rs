@@ -0,0 +1,239 @@
+//! Quarantine log for messages from unblessed or blocked senders
+//!
+//! Instead of dropping them, each one is appended as a JSON line to
+//! `state/quarantine.jsonl` so there's a record to review, and a specific
+//! message can be let through once with `quarantine --bless-once <rowid>`.
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::messages::Message;
+use crate::registry::write_atomic;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Characters of message text kept in an entry
+const PREVIEW_CHARS: usize = 200;
+
+/// One quarantined message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub timestamp: DateTime<Utc>,
+    pub rowid: i64,
+    pub sender: String,
+    pub chat_id: String,
+    pub preview: String,
+    /// Messages.app filed it as junk
+    #[serde(default)]
+    pub is_spam: bool,
+    /// Dropped because the sender is blocked, not just unblessed
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+impl QuarantineEntry {
+    pub fn from_message(msg: &Message) -> Self {
+        let mut preview = msg.body_text();
+        if preview.is_empty() && !msg.attachments.is_empty() {
+            let names: Vec<&str> = msg.attachments.iter().map(|a| a.name.as_str()).collect();
+            preview = format!("[attachments: {}]", names.join(", "));
+        }
+        Self {
+            timestamp: msg.timestamp,
+            rowid: msg.rowid,
+            sender: msg.sender.clone(),
+            chat_id: msg.chat_id.clone(),
+            preview: preview.chars().take(PREVIEW_CHARS).collect(),
+            is_spam: msg.is_spam,
+            blocked: false,
+        }
+    }
+}
+
+/// Append-only JSON lines log, trimmed to the newest `max_entries`
+///
+/// Lines in the file are counted once and then tracked, so an append only
+/// reads the log back when it's time to trim. Another process removing
+/// entries just makes the count high, which costs an early reread.
+pub struct Quarantine {
+    path: PathBuf,
+    max_entries: usize,
+    lines: Mutex<Option<usize>>,
+}
+
+impl Quarantine {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            path: config.quarantine_file.clone(),
+            max_entries: config.quarantine_max_entries,
+            lines: Mutex::new(None),
+        }
+    }
+
+    /// Record a message, dropping the oldest entries once over the cap
+    pub fn append(&self, msg: &Message) -> Result<()> {
+        self.append_entry(&QuarantineEntry::from_message(msg))
+    }
+
+    pub fn append_entry(&self, entry: &QuarantineEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        drop(file);
+
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        let count = match *lines {
+            Some(count) => count + 1,
+            None => fs::read_to_string(&self.path)?.lines().count(),
+        };
+        *lines = Some(count);
+        if count > self.max_entries {
+            let entries = self.entries()?;
+            let keep = &entries[entries.len().saturating_sub(self.max_entries)..];
+            self.rewrite(keep)?;
+            *lines = Some(keep.len());
+        }
+        Ok(())
+    }
+
+    /// All entries, oldest first (unparseable lines are skipped)
+    pub fn entries(&self) -> Result<Vec<QuarantineEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path)?;
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Newest `limit` entries, oldest first
+    pub fn recent(&self, limit: usize) -> Result<Vec<QuarantineEntry>> {
+        let entries = self.entries()?;
+        let skip = entries.len().saturating_sub(limit);
+        Ok(entries.into_iter().skip(skip).collect())
+    }
+
+    pub fn get(&self, rowid: i64) -> Result<Option<QuarantineEntry>> {
+        Ok(self.entries()?.into_iter().find(|e| e.rowid == rowid))
+    }
+
+    /// Remove an entry once it has been let through
+    pub fn remove(&self, rowid: i64) -> Result<Option<QuarantineEntry>> {
+        let mut entries = self.entries()?;
+        let index = match entries.iter().position(|e| e.rowid == rowid) {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let removed = entries.remove(index);
+        self.rewrite(&entries)?;
+        *self.lines.lock().unwrap_or_else(|e| e.into_inner()) = Some(entries.len());
+        Ok(Some(removed))
+    }
+
+    fn rewrite(&self, entries: &[QuarantineEntry]) -> Result<()> {
+        let mut lines = Vec::new();
+        for entry in entries {
+            writeln!(lines, "{}", serde_json::to_string(entry)?)?;
+        }
+        write_atomic(&self.path, &lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn message(rowid: i64, text: &str) -> Message {
+        Message {
+            rowid,
+            text: text.to_string(),
+            sender: "+16175550000".to_string(),
+            chat_id: "+16175550000".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_append_and_read_back() {
+        let temp = TempDir::new().unwrap();
+        let quarantine = Quarantine::new(&Config::for_test(temp.path()));
+        assert!(quarantine.entries().unwrap().is_empty());
+
+        let long = "x".repeat(500);
+        quarantine.append(&message(10, "Your tax documents are ready to sign")).unwrap();
+        quarantine
+            .append(&Message { is_spam: true, ..message(11, &long) })
+            .unwrap();
+
+        let entries = quarantine.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].rowid, 10);
+        assert_eq!(entries[0].sender, "+16175550000");
+        assert!(!entries[0].is_spam);
+        assert_eq!(entries[1].preview.chars().count(), PREVIEW_CHARS);
+        assert!(entries[1].is_spam);
+
+        // One JSON object per line
+        let raw = fs::read_to_string(temp.path().join("state/quarantine.jsonl")).unwrap();
+        assert_eq!(raw.lines().count(), 2);
+        assert!(raw.lines().all(|l| serde_json::from_str::<serde_json::Value>(l).is_ok()));
+    }
+
+    #[test]
+    fn test_retention_cap() {
+        let temp = TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.quarantine_max_entries = 3;
+        let quarantine = Quarantine::new(&config);
+
+        for rowid in 1..=5 {
+            quarantine.append(&message(rowid, "hello")).unwrap();
+        }
+
+        let rowids: Vec<i64> = quarantine.entries().unwrap().iter().map(|e| e.rowid).collect();
+        assert_eq!(rowids, vec![3, 4, 5]);
+        let recent: Vec<i64> = quarantine.recent(2).unwrap().iter().map(|e| e.rowid).collect();
+        assert_eq!(recent, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_retention_cap_after_remove_elsewhere() {
+        let temp = TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.quarantine_max_entries = 3;
+        let daemon = Quarantine::new(&config);
+        for rowid in 1..=3 {
+            daemon.append(&message(rowid, "hello")).unwrap();
+        }
+
+        // `quarantine --bless-once` runs in its own process
+        Quarantine::new(&config).remove(2).unwrap();
+        daemon.append(&message(4, "hello")).unwrap();
+        daemon.append(&message(5, "hello")).unwrap();
+
+        let rowids: Vec<i64> = daemon.entries().unwrap().iter().map(|e| e.rowid).collect();
+        assert_eq!(rowids, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_remove_after_bless() {
+        let temp = TempDir::new().unwrap();
+        let quarantine = Quarantine::new(&Config::for_test(temp.path()));
+        quarantine.append(&message(1, "first")).unwrap();
+        quarantine.append(&message(2, "second")).unwrap();
+
+        assert_eq!(quarantine.get(2).unwrap().unwrap().preview, "second");
+        assert_eq!(quarantine.remove(2).unwrap().unwrap().rowid, 2);
+        assert!(quarantine.get(2).unwrap().is_none());
+        assert!(quarantine.remove(2).unwrap().is_none());
+        assert_eq!(quarantine.entries().unwrap().len(), 1);
+    }
+}
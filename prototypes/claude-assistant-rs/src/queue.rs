@@ -0,0 +1,170 @@
+//! Holds prompts for sessions that are busy
+//!
+//! Typing into a session while Claude is still working mixes the new message
+//! into whatever it's doing. Prompts for a busy session wait here and are handed
+//! back, oldest first, once it goes idle or the oldest has waited `max_wait`.
+
+use crate::config::Config;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// A wrapped prompt waiting for its session
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedPrompt {
+    pub text: String,
+    /// Message the prompt came from, for naming a spilled oversized prompt
+    pub rowid: i64,
+    pub transcript_dir: PathBuf,
+    pub queued_at: Instant,
+}
+
+/// Per-session queues of prompts
+pub struct InjectionQueue {
+    pending: BTreeMap<String, VecDeque<QueuedPrompt>>,
+    max_wait: Duration,
+    coalesce: bool,
+}
+
+impl InjectionQueue {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            max_wait: Duration::from_secs(config.queue_max_wait_secs),
+            coalesce: config.coalesce_queued,
+        }
+    }
+
+    /// Hold a prompt until its session is ready
+    pub fn push(&mut self, session_name: &str, prompt: QueuedPrompt) {
+        self.pending.entry(session_name.to_string()).or_default().push_back(prompt);
+    }
+
+    /// Whether prompts are already waiting for this session, so new ones must wait behind them
+    pub fn is_waiting(&self, session_name: &str) -> bool {
+        self.pending.contains_key(session_name)
+    }
+
+    /// Sessions with prompts waiting
+    pub fn sessions(&self) -> Vec<String> {
+        self.pending.keys().cloned().collect()
+    }
+
+    /// Total prompts waiting
+    pub fn len(&self) -> usize {
+        self.pending.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Next prompt to inject into a session, if it's ready for one
+    ///
+    /// A busy session gets nothing until its oldest prompt has waited
+    /// `max_wait`. With coalescing, everything waiting comes back as one
+    /// prompt; otherwise just the oldest.
+    pub fn take_ready(&mut self, session_name: &str, busy: bool, now: Instant) -> Option<QueuedPrompt> {
+        let queue = self.pending.get_mut(session_name)?;
+        let oldest = queue.front()?;
+        if busy && now.saturating_duration_since(oldest.queued_at) < self.max_wait {
+            return None;
+        }
+
+        let prompt = if self.coalesce {
+            let mut prompts = queue.drain(..);
+            let mut merged = prompts.next()?;
+            for prompt in prompts {
+                merged.text.push_str("\n\n");
+                merged.text.push_str(&prompt.text);
+                merged.rowid = prompt.rowid;
+            }
+            merged
+        } else {
+            queue.pop_front()?
+        };
+        if queue.is_empty() {
+            self.pending.remove(session_name);
+        }
+        Some(prompt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(coalesce: bool) -> InjectionQueue {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.queue_max_wait_secs = 60;
+        config.coalesce_queued = coalesce;
+        InjectionQueue::new(&config)
+    }
+
+    fn prompt(text: &str, rowid: i64, queued_at: Instant) -> QueuedPrompt {
+        QueuedPrompt {
+            text: text.to_string(),
+            rowid,
+            transcript_dir: PathBuf::from("/tmp/jane-doe"),
+            queued_at,
+        }
+    }
+
+    #[test]
+    fn test_holds_while_busy() {
+        let start = Instant::now();
+        let mut queue = queue(false);
+        assert!(queue.take_ready("jane-doe", false, start).is_none());
+
+        queue.push("jane-doe", prompt("first", 1, start));
+        assert!(queue.is_waiting("jane-doe"));
+        assert!(!queue.is_waiting("john-doe"));
+        assert!(queue.take_ready("jane-doe", true, start + Duration::from_secs(30)).is_none());
+        assert_eq!(queue.len(), 1);
+
+        let taken = queue.take_ready("jane-doe", false, start + Duration::from_secs(30)).unwrap();
+        assert_eq!(taken.text, "first");
+        assert!(queue.is_empty());
+        assert!(!queue.is_waiting("jane-doe"));
+    }
+
+    #[test]
+    fn test_oldest_first_one_at_a_time() {
+        let start = Instant::now();
+        let mut queue = queue(false);
+        queue.push("jane-doe", prompt("first", 1, start));
+        queue.push("jane-doe", prompt("second", 2, start));
+        queue.push("john-doe", prompt("other", 3, start));
+        assert_eq!(queue.sessions(), vec!["jane-doe", "john-doe"]);
+
+        assert_eq!(queue.take_ready("jane-doe", false, start).unwrap().text, "first");
+        assert_eq!(queue.take_ready("jane-doe", false, start).unwrap().text, "second");
+        assert!(queue.take_ready("jane-doe", false, start).is_none());
+        assert_eq!(queue.sessions(), vec!["john-doe"]);
+    }
+
+    #[test]
+    fn test_coalesces_into_one_block() {
+        let start = Instant::now();
+        let mut queue = queue(true);
+        queue.push("jane-doe", prompt("first", 1, start));
+        queue.push("jane-doe", prompt("second", 2, start + Duration::from_secs(5)));
+
+        let taken = queue.take_ready("jane-doe", false, start + Duration::from_secs(10)).unwrap();
+        assert_eq!(taken.text, "first\n\nsecond");
+        assert_eq!(taken.rowid, 2);
+        assert_eq!(taken.queued_at, start);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_max_wait_overrides_busy() {
+        let start = Instant::now();
+        let mut queue = queue(false);
+        queue.push("jane-doe", prompt("first", 1, start));
+
+        assert!(queue.take_ready("jane-doe", true, start + Duration::from_secs(59)).is_none());
+        assert_eq!(queue.take_ready("jane-doe", true, start + Duration::from_secs(60)).unwrap().text, "first");
+    }
+}
@@ -1,23 +1,56 @@
 //! Session registry - persistent JSON storage for session metadata
+//!
+//! Each save first copies the registry it replaces to `sessions.json.1`,
+//! shifting older copies up to `.3`. If the registry won't parse at load, it's
+//! moved aside and the newest backup that does parse takes its place.
+//!
+//! The daemon and CLI commands like `inject-prompt` share the file, so every
+//! change reloads, edits, and saves it under an exclusive flock on
+//! `sessions.json.lock`, and loads take the lock shared.
+//!
+//! Something else can write the file too, a hand edit say. Each reload
+//! notices when the file changed since this process last read or wrote it,
+//! and keeps whichever copy of each entry has the later `updated_at`, the
+//! file's on a tie. With `registry_merge` off, this process's copy wins.
+//!
+//! Message counters and last message times, both ways, change with every message, too
+//! often to save each time. They're held back until the next change is saved,
+//! a `flush`, or the registry is dropped.
+//!
+//! `export` and `import` move the registry to another machine as a versioned
+//! JSON file.
 
 use crate::config::Config;
+use crate::contacts::normalize_chat_id;
 use crate::error::{Error, Result};
-use chrono::{DateTime, Utc};
+use crate::session::SessionManager;
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use tempfile::NamedTempFile;
 use std::io::Write;
+use tracing::{error, info, warn};
+
+/// Backups of the registry kept beside it, `.1` newest
+pub const REGISTRY_BACKUPS: usize = 3;
+
+/// Version of the `export` format; `import` refuses anything newer
+pub const REGISTRY_EXPORT_VERSION: u32 = 1;
+
+/// How often a blocked lock is tried again
+const LOCK_POLL: Duration = Duration::from_millis(10);
 
 /// Session metadata stored in registry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SessionData {
     pub chat_id: String,
     pub session_name: String,
     pub transcript_dir: String,
     #[serde(rename = "type")]
-    pub session_type: String, // "individual" or "group"
+    pub session_type: String, // "individual", "group", or "background"
     pub contact_name: Option<String>,
     pub display_name: Option<String>,
     pub tier: Option<String>,
@@ -26,58 +59,303 @@ pub struct SessionData {
     pub updated_at: DateTime<Utc>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_message_time: Option<DateTime<Utc>>,
+    /// Latest message to the chat, from us or from Claude
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_outbound_time: Option<DateTime<Utc>>,
+    /// Killed for being idle; the next message recreates the session
+    #[serde(default)]
+    pub archived: bool,
+    /// Failed too many restarts in a row; the health sweep leaves it alone until `restart-session`
+    #[serde(default)]
+    pub quarantined: bool,
+    /// Latest Claude conversation in the session, resumed when it restarts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claude_session_id: Option<String>,
+    #[serde(default)]
+    pub counters: SessionCounters,
+}
+
+/// How busy a session has been, for `status`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionCounters {
+    /// Messages from the chat the daemon handled
+    pub inbound: u64,
+    /// Times messages went into the session; queued messages can share one
+    pub injections: u64,
+    /// Restarts after failed health checks
+    pub restarts: u64,
+    /// Times the health check sent `/compact` to a session out of context
+    pub compactions: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_inbound: Option<DateTime<Utc>>,
+    /// Local day `inbound_today` counts for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<NaiveDate>,
+    pub inbound_today: u64,
+}
+
+impl SessionCounters {
+    /// Inbound messages on the local day `today`
+    pub fn inbound_on(&self, today: NaiveDate) -> u64 {
+        if self.day == Some(today) {
+            self.inbound_today
+        } else {
+            0
+        }
+    }
+
+    fn apply(&mut self, event: SessionEvent) {
+        match event {
+            SessionEvent::Inbound(at) => {
+                self.inbound += 1;
+                self.last_inbound = self.last_inbound.max(Some(at));
+                let day = at.with_timezone(&Local).date_naive();
+                if self.day != Some(day) {
+                    self.day = Some(day);
+                    self.inbound_today = 0;
+                }
+                self.inbound_today += 1;
+            }
+            SessionEvent::Injection => self.injections += 1,
+            SessionEvent::Restart => self.restarts += 1,
+            SessionEvent::Compaction => self.compactions += 1,
+        }
+    }
+}
+
+/// Something counted in `SessionCounters`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// A message from the chat, sent at the given time
+    Inbound(DateTime<Utc>),
+    Injection,
+    Restart,
+    Compaction,
+}
+
+/// The registry as `export` writes it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryExport {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    /// Home directory on the exporting machine, replaced in paths on import
+    pub home: String,
+    pub sessions: BTreeMap<String, SessionData>,
+}
+
+/// What `import` does with sessions already registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Keep them, taking an imported entry only if it was updated more recently
+    Merge,
+    /// Drop them all for the imported ones
+    Replace,
+}
+
+/// How an import went
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub updated: usize,
+    /// Imported entries older than the ones already here
+    pub kept: usize,
+    /// Entries dropped by a replace
+    pub removed: usize,
 }
 
 /// Persistent registry mapping chat_id to session metadata
 pub struct SessionRegistry {
     registry_path: PathBuf,
+    lock_path: PathBuf,
+    /// Left by a CLI command for the daemon, which removes it once it has flushed
+    flush_request_path: PathBuf,
+    lock_timeout: Duration,
     data: HashMap<String, SessionData>,
+    /// Key of the chat owning each session name, rebuilt whenever `data` changes
+    names: HashMap<String, String>,
+    /// Changes held back since the last save, replayed onto each reload until saved
+    pending: Vec<(String, Deferred)>,
+    /// The file as this registry last read or wrote it
+    stamp: Option<FileStamp>,
+    /// Sessions as of that read or write, without held back changes
+    saved: HashMap<String, SessionData>,
+    /// Whether changes something else made to the file are merged in, or written over
+    merge: bool,
+    #[cfg(test)]
+    saves: usize,
 }
 
 impl SessionRegistry {
     pub fn new(config: &Config) -> Self {
         let registry_path = config.registry_file.clone();
         Self {
+            lock_path: registry_path.with_extension("json.lock"),
+            flush_request_path: registry_path.with_extension("json.flush"),
             registry_path,
+            lock_timeout: Duration::from_millis(config.registry_lock_ms),
             data: HashMap::new(),
+            names: HashMap::new(),
+            pending: Vec::new(),
+            stamp: None,
+            saved: HashMap::new(),
+            merge: config.registry_merge,
+            #[cfg(test)]
+            saves: 0,
         }
     }
 
     /// Load registry from disk
+    ///
+    /// A registry that won't parse is moved aside and replaced by the newest
+    /// backup that does. Only when every backup is bad too does it start empty.
     pub fn load(&mut self) -> Result<usize> {
+        let (disk, stamp) = self.read()?;
+        self.adopt(disk, stamp);
+        Ok(self.data.len())
+    }
+
+    /// Reload if something changed the file since this registry last read or wrote it
+    ///
+    /// Returns whether it had. Anything to merge or held back is saved.
+    pub fn refresh(&mut self) -> Result<bool> {
+        if FileStamp::of(&self.registry_path) == self.stamp {
+            return Ok(false);
+        }
+        self.update(|_, _| Ok(None::<()>))?;
+        Ok(true)
+    }
+
+    fn read(&self) -> Result<(HashMap<String, SessionData>, Option<FileStamp>)> {
+        if !self.registry_path.exists() {
+            return Ok((HashMap::new(), None));
+        }
+        {
+            let _lock = self.lock(false)?;
+            if let Ok(data) = read_registry(&self.registry_path) {
+                return Ok((data, FileStamp::of(&self.registry_path)));
+            }
+        }
+        // Recovering rewrites the registry, so it needs the lock to itself
+        let _lock = self.lock(true)?;
+        self.read_locked()
+    }
+
+    /// `read`, for a caller holding the exclusive lock
+    fn read_locked(&self) -> Result<(HashMap<String, SessionData>, Option<FileStamp>)> {
+        let data = self.recover_locked()?;
+        Ok((data, FileStamp::of(&self.registry_path)))
+    }
+
+    /// The sessions in the file, restoring it from a backup if it won't parse
+    fn recover_locked(&self) -> Result<HashMap<String, SessionData>> {
         if !self.registry_path.exists() {
-            self.data = HashMap::new();
-            return Ok(0);
+            return Ok(HashMap::new());
         }
 
         let content = fs::read_to_string(&self.registry_path)?;
-        self.data = serde_json::from_str(&content)?;
-        Ok(self.data.len())
+        let problem = match serde_json::from_str(&content) {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        };
+        error!("Registry {} is corrupt: {}", self.registry_path.display(), problem);
+        let aside = self.registry_path.with_extension(format!("json.corrupt-{}", Utc::now().format("%Y%m%d-%H%M%S")));
+        fs::rename(&self.registry_path, &aside)?;
+        error!("Moved the corrupt registry to {}", aside.display());
+
+        for backup in self.backup_paths() {
+            match read_registry(&backup) {
+                Ok(data) => {
+                    fs::copy(&backup, &self.registry_path)?;
+                    warn!("Restored the registry from {} ({} sessions)", backup.display(), data.len());
+                    return Ok(data);
+                }
+                Err(e) if backup.exists() => warn!("Backup {} is no good either: {}", backup.display(), e),
+                Err(_) => {}
+            }
+        }
+        error!("No usable registry backup, starting with an empty registry");
+        Ok(HashMap::new())
     }
 
-    /// Save registry to disk atomically
-    pub fn save(&self) -> Result<()> {
-        // Ensure parent directory exists
-        if let Some(parent) = self.registry_path.parent() {
-            fs::create_dir_all(parent)?;
+    /// Make what was read from the file the registry, then replay held back changes
+    ///
+    /// If the file changed since this registry last read or wrote it, the
+    /// changes are merged in, or with `merge` off written over. Returns whether
+    /// the result differs from the file, which then needs saving.
+    fn adopt(&mut self, disk: HashMap<String, SessionData>, stamp: Option<FileStamp>) -> bool {
+        let changed = self.stamp.is_some() && stamp != self.stamp;
+        self.data = if !changed {
+            disk.clone()
+        } else if self.merge {
+            merge_external(&self.registry_path, &self.saved, disk.clone())
+        } else {
+            warn!("{} was changed outside this process, writing over it", self.registry_path.display());
+            self.saved.clone()
+        };
+        let differs = self.data != disk;
+        // Until it's saved, the file still counts as changed
+        if !differs {
+            self.stamp = stamp;
         }
+        self.saved = self.data.clone();
+        self.reindex();
+        self.replay_pending();
+        differs
+    }
+
+    /// The registry file and its backups, newest first
+    pub fn files(&self) -> Vec<PathBuf> {
+        std::iter::once(self.registry_path.clone()).chain(self.backup_paths()).collect()
+    }
 
-        // Write to temp file in same directory (for atomic rename)
-        let parent = self.registry_path.parent().unwrap_or(std::path::Path::new("."));
-        let mut temp = NamedTempFile::new_in(parent)?;
+    fn backup_paths(&self) -> Vec<PathBuf> {
+        (1..=REGISTRY_BACKUPS).map(|n| backup_path(&self.registry_path, n)).collect()
+    }
 
-        let json = serde_json::to_string_pretty(&self.data)?;
-        temp.write_all(json.as_bytes())?;
-        temp.as_file().sync_all()?;
+    /// Shift the backups up one and copy the current registry to `.1`
+    ///
+    /// A registry that doesn't parse isn't backed up, so a bad hand edit can't
+    /// push the good copies out.
+    fn rotate_backups(&self) -> Result<()> {
+        if read_registry(&self.registry_path).is_err() {
+            return Ok(());
+        }
+        let backups = self.backup_paths();
+        for n in (1..backups.len()).rev() {
+            if backups[n - 1].exists() {
+                fs::rename(&backups[n - 1], &backups[n])?;
+            }
+        }
+        fs::copy(&self.registry_path, &backups[0])?;
+        Ok(())
+    }
 
-        // Atomic rename
-        temp.persist(&self.registry_path)
-            .map_err(|e| Error::Io(e.error))?;
+    /// Save registry to disk atomically, for a caller holding the exclusive lock
+    fn save_locked(&mut self) -> Result<()> {
+        #[cfg(test)]
+        {
+            self.saves += 1;
+        }
+        // Ensure parent directory exists
+        if let Some(parent) = self.registry_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if let Err(e) = self.rotate_backups() {
+            warn!("Failed to back up the registry: {}", e);
+        }
+        write_json_atomic(&self.registry_path, &self.data)?;
 
+        self.stamp = FileStamp::of(&self.registry_path);
+        self.saved = self.data.clone();
         Ok(())
     }
 
     /// Register or update a session
+    ///
+    /// A "background" session is kept beside its chat's main session, under
+    /// `background_key(chat_id)`.
+    #[allow(clippy::too_many_arguments)]
     pub fn register(
         &mut self,
         chat_id: &str,
@@ -90,30 +368,37 @@ impl SessionRegistry {
         participants: Option<Vec<String>>,
     ) -> Result<SessionData> {
         let now = Utc::now();
-
-        let existing = self.data.get(chat_id);
-        let created_at = existing
-            .map(|e| e.created_at)
-            .unwrap_or(now);
-
-        let session_data = SessionData {
-            chat_id: chat_id.to_string(),
-            session_name: session_name.to_string(),
-            transcript_dir: transcript_dir.to_string(),
-            session_type: session_type.to_string(),
-            contact_name,
-            display_name,
-            tier,
-            participants,
-            created_at,
-            updated_at: now,
-            last_message_time: existing.and_then(|e| e.last_message_time),
+        let key = if session_type == "background" {
+            Self::background_key(chat_id)
+        } else {
+            chat_id.to_string()
         };
 
-        self.data.insert(chat_id.to_string(), session_data.clone());
-        self.save()?;
-
-        Ok(session_data)
+        let registered = self.update(|data, names| {
+            check_name_free(data, names, &key, session_name, session_type, contact_name.as_deref())?;
+            let existing = data.get(&key);
+            let session_data = SessionData {
+                chat_id: chat_id.to_string(),
+                session_name: session_name.to_string(),
+                transcript_dir: transcript_dir.to_string(),
+                session_type: session_type.to_string(),
+                contact_name,
+                display_name,
+                tier,
+                participants,
+                created_at: existing.map(|e| e.created_at).unwrap_or(now),
+                updated_at: now,
+                last_message_time: existing.and_then(|e| e.last_message_time),
+                last_outbound_time: existing.and_then(|e| e.last_outbound_time),
+                archived: false,
+                quarantined: existing.is_some_and(|e| e.quarantined),
+                claude_session_id: existing.and_then(|e| e.claude_session_id.clone()),
+                counters: existing.map(|e| e.counters.clone()).unwrap_or_default(),
+            };
+            data.insert(key, session_data.clone());
+            Ok(Some(session_data))
+        })?;
+        Ok(registered.expect("registering always saves"))
     }
 
     /// Get session data by chat_id
@@ -121,9 +406,55 @@ impl SessionRegistry {
         self.data.get(chat_id)
     }
 
+    /// Registry key of a chat's background session
+    pub fn background_key(chat_id: &str) -> String {
+        format!("{}-bg", chat_id)
+    }
+
+    /// Get a chat's background session, if it has one
+    pub fn get_background(&self, chat_id: &str) -> Option<&SessionData> {
+        self.data.get(&Self::background_key(chat_id))
+    }
+
     /// Get session data by session_name (reverse lookup)
+    ///
+    /// If several chats share the name, this is the chat that owns it: the one
+    /// registered first.
     pub fn get_by_session_name(&self, session_name: &str) -> Option<&SessionData> {
-        self.data.values().find(|d| d.session_name == session_name)
+        self.names.get(session_name).and_then(|key| self.data.get(key))
+    }
+
+    /// Rebuild the session name index from `data`
+    fn reindex(&mut self) {
+        self.names.clear();
+        for (key, session) in &self.data {
+            let owner = self.names.entry(session.session_name.clone()).or_insert_with(|| key.clone());
+            let current = &self.data[owner.as_str()];
+            if (session.created_at, &session.chat_id) < (current.created_at, &current.chat_id) {
+                *owner = key.clone();
+            }
+        }
+    }
+
+    /// Chat that owns a session name
+    pub fn session_owner(&self, session_name: &str) -> Option<&str> {
+        self.get_by_session_name(session_name).map(|d| d.chat_id.as_str())
+    }
+
+    /// Fail with `SessionNameTaken` if registering `session_name` under `key` would, as `register` does
+    pub fn check_name(&self, key: &str, session_name: &str, session_type: &str, contact_name: Option<&str>) -> Result<()> {
+        check_name_free(&self.data, &self.names, key, session_name, session_type, contact_name)
+    }
+
+    /// `session_name`, or if a different chat already owns it, the name with a short hash of `chat_id` appended
+    ///
+    /// Registering what this returns keeps `get_by_session_name` unique, and the
+    /// hash is stable, so the same chat gets the same name after a restart.
+    pub fn claim_session_name(&self, session_name: &str, chat_id: &str) -> String {
+        match self.session_owner(session_name) {
+            Some(owner) if owner != chat_id => format!("{}-{}", session_name, short_hash(chat_id)),
+            _ => session_name.to_string(),
+        }
     }
 
     /// Get all registered sessions
@@ -131,23 +462,347 @@ impl SessionRegistry {
         &self.data
     }
 
-    /// Update last message time
+    /// Update last message time, to be saved with the next change or `flush`
     pub fn update_last_message(&mut self, chat_id: &str) -> Result<()> {
-        if let Some(session) = self.data.get_mut(chat_id) {
-            session.last_message_time = Some(Utc::now());
-            session.updated_at = Utc::now();
-            self.save()?;
-        }
+        self.defer(chat_id, Deferred::LastMessage(Utc::now()));
         Ok(())
     }
 
+    /// Note a message we sent the chat at `at`, to be saved with the next change or `flush`
+    pub fn update_last_outbound(&mut self, chat_id: &str, at: DateTime<Utc>) {
+        self.defer(chat_id, Deferred::LastOutbound(at));
+    }
+
+    /// Replace a session's participant list, returning whether it changed
+    pub fn update_participants(&mut self, chat_id: &str, participants: Vec<String>) -> Result<bool> {
+        self.update_session(chat_id, |session| {
+            if session.participants.as_ref() == Some(&participants) {
+                return false;
+            }
+            session.participants = Some(participants);
+            true
+        })
+    }
+
+    /// Record a group's new display name; the session keeps its original name
+    ///
+    /// Returns whether anything changed.
+    pub fn update_display_name(&mut self, chat_id: &str, display_name: Option<String>) -> Result<bool> {
+        self.update_session(chat_id, |session| {
+            if session.display_name == display_name {
+                return false;
+            }
+            session.display_name = display_name;
+            true
+        })
+    }
+
+    /// Record a contact's new name; the session keeps its original name
+    ///
+    /// Returns whether anything changed.
+    pub fn update_contact_name(&mut self, chat_id: &str, contact_name: &str) -> Result<bool> {
+        self.update_session(chat_id, |session| {
+            if session.contact_name.as_deref() == Some(contact_name) {
+                return false;
+            }
+            session.contact_name = Some(contact_name.to_string());
+            true
+        })
+    }
+
+    /// Record the tier a session now runs at, returning whether it changed
+    pub fn update_tier(&mut self, chat_id: &str, tier: &str) -> Result<bool> {
+        self.update_session(chat_id, |session| {
+            if session.tier.as_deref() == Some(tier) {
+                return false;
+            }
+            session.tier = Some(tier.to_string());
+            true
+        })
+    }
+
+    /// Record the Claude conversation a session is on, returning whether it changed
+    pub fn update_claude_session(&mut self, chat_id: &str, conversation: &str) -> Result<bool> {
+        self.update_session(chat_id, |session| {
+            if session.claude_session_id.as_deref() == Some(conversation) {
+                return false;
+            }
+            session.claude_session_id = Some(conversation.to_string());
+            true
+        })
+    }
+
+    /// Forget a session's Claude conversation, so its next start is a new one; returns whether it had one
+    pub fn clear_claude_session(&mut self, chat_id: &str) -> Result<bool> {
+        self.update_session(chat_id, |session| session.claude_session_id.take().is_some())
+    }
+
+    /// Mark a session archived or live again, returning whether it changed
+    pub fn set_archived(&mut self, chat_id: &str, archived: bool) -> Result<bool> {
+        self.update_session(chat_id, |session| {
+            if session.archived == archived {
+                return false;
+            }
+            session.archived = archived;
+            true
+        })
+    }
+
+    /// Quarantine a session or let it be restarted again, returning whether it changed
+    pub fn set_quarantined(&mut self, chat_id: &str, quarantined: bool) -> Result<bool> {
+        self.update_session(chat_id, |session| {
+            if session.quarantined == quarantined {
+                return false;
+            }
+            session.quarantined = quarantined;
+            true
+        })
+    }
+
+    /// Everything registered, for `import` on another machine
+    pub fn export(&self, home: &Path) -> RegistryExport {
+        RegistryExport {
+            version: REGISTRY_EXPORT_VERSION,
+            exported_at: Utc::now(),
+            home: home.to_string_lossy().into_owned(),
+            sessions: self.data.iter().map(|(key, data)| (key.clone(), data.clone())).collect(),
+        }
+    }
+
+    /// Bring in an `export`, all of it or none
+    ///
+    /// Chat IDs are normalized, tiers checked against the configured ones, and
+    /// transcript dirs under the exporting machine's home moved under `home`.
+    /// Any bad entry, or a session name two people's chats would share, fails
+    /// the whole import before anything is written.
+    pub fn import(&mut self, config: &Config, export: RegistryExport, mode: ImportMode, home: &Path) -> Result<ImportSummary> {
+        if export.version > REGISTRY_EXPORT_VERSION {
+            return Err(Error::Config(format!(
+                "registry export is version {}, newer than this build reads ({})",
+                export.version, REGISTRY_EXPORT_VERSION
+            )));
+        }
+        let tiers = config.tier_names();
+        let mut imported: HashMap<String, SessionData> = HashMap::new();
+        for (key, mut session) in export.sessions {
+            if let Some(tier) = session.tier.as_deref() {
+                if !tiers.contains(&tier) && tier != "unknown" {
+                    return Err(Error::Config(format!("{}: unknown tier '{}'", key, tier)));
+                }
+            }
+            if session.chat_id.trim().is_empty() || session.session_name.trim().is_empty() {
+                return Err(Error::InvalidChatId(format!("{}: missing chat ID or session name", key)));
+            }
+            session.chat_id = normalize_chat_id(&session.chat_id, &config.default_region);
+            session.transcript_dir = rehome(&session.transcript_dir, &export.home, home);
+            let key = match session.session_type.as_str() {
+                "background" => Self::background_key(&session.chat_id),
+                _ => session.chat_id.clone(),
+            };
+            match imported.get(&key) {
+                Some(other) if other.updated_at >= session.updated_at => {}
+                _ => {
+                    imported.insert(key, session);
+                }
+            }
+        }
+
+        let summary = self.update(|data, _| {
+            let mut summary = ImportSummary::default();
+            let mut merged = match mode {
+                ImportMode::Merge => data.clone(),
+                ImportMode::Replace => {
+                    summary.removed = data.keys().filter(|key| !imported.contains_key(*key)).count();
+                    HashMap::new()
+                }
+            };
+            for (key, session) in imported {
+                match merged.get(&key).or_else(|| data.get(&key)) {
+                    Some(existing) if mode == ImportMode::Merge && existing.updated_at >= session.updated_at => {
+                        summary.kept += 1;
+                        continue;
+                    }
+                    Some(_) => summary.updated += 1,
+                    None => summary.added += 1,
+                }
+                merged.insert(key, session);
+            }
+            check_session_names(&merged)?;
+            *data = merged;
+            Ok(Some(summary))
+        })?;
+        Ok(summary.unwrap_or_default())
+    }
+
+    /// Remove sessions that aren't running and nobody has messaged for `older_than`, returning them
+    ///
+    /// A session never messaged goes by when it was last updated. With
+    /// `transcripts_gone`, only sessions whose transcript dir is gone too are
+    /// removed. Removed chats get a new entry with their next message.
+    pub fn prune(
+        &mut self,
+        session_mgr: &SessionManager,
+        older_than: Duration,
+        transcripts_gone: bool,
+    ) -> Result<Vec<SessionData>> {
+        let cutoff = prune_cutoff(older_than);
+        let pruned = self.update(|data, _| {
+            let keys: Vec<String> = data
+                .iter()
+                .filter(|(_, session)| is_prunable(session, session_mgr, cutoff, transcripts_gone))
+                .map(|(key, _)| key.clone())
+                .collect();
+            if keys.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(keys.iter().filter_map(|key| data.remove(key)).collect::<Vec<_>>()))
+        })?;
+        let mut pruned = pruned.unwrap_or_default();
+        pruned.sort_by(|a, b| a.session_name.cmp(&b.session_name));
+        Ok(pruned)
+    }
+
+    /// What `prune` would remove, without removing it
+    pub fn prunable(&self, session_mgr: &SessionManager, older_than: Duration, transcripts_gone: bool) -> Vec<&SessionData> {
+        let cutoff = prune_cutoff(older_than);
+        let mut prunable: Vec<&SessionData> = self
+            .data
+            .values()
+            .filter(|session| is_prunable(session, session_mgr, cutoff, transcripts_gone))
+            .collect();
+        prunable.sort_by(|a, b| a.session_name.cmp(&b.session_name));
+        prunable
+    }
+
     /// Remove a session from registry
     pub fn remove(&mut self, chat_id: &str) -> Result<Option<SessionData>> {
-        let removed = self.data.remove(chat_id);
-        if removed.is_some() {
-            self.save()?;
+        self.update(|data, _| Ok(data.remove(chat_id)))
+    }
+
+    /// Apply `change` to one session, if it's registered; see `update`
+    ///
+    /// `change` returns whether it changed anything, and only then is the
+    /// session's `updated_at` bumped and the registry saved.
+    fn update_session(&mut self, chat_id: &str, change: impl FnOnce(&mut SessionData) -> bool) -> Result<bool> {
+        let changed = self.update(|data, _| {
+            let Some(session) = data.get_mut(chat_id) else {
+                return Ok(None);
+            };
+            if !change(session) {
+                return Ok(None);
+            }
+            session.updated_at = Utc::now();
+            Ok(Some(()))
+        })?;
+        Ok(changed.is_some())
+    }
+
+    /// Reload the registry, apply `change`, and save it, all under the exclusive lock
+    ///
+    /// Going back to the file first keeps what other processes saved since this
+    /// registry last looked (see `adopt`), and changes held back are replayed onto it.
+    /// `change` gets the sessions and the name index, and returns None when it
+    /// left the registry alone; then nothing is written unless there were held
+    /// back changes to save. If it fails, nothing is written either.
+    fn update<T>(
+        &mut self,
+        change: impl FnOnce(&mut HashMap<String, SessionData>, &HashMap<String, String>) -> Result<Option<T>>,
+    ) -> Result<Option<T>> {
+        let _lock = self.lock(true)?;
+        let (disk, stamp) = self.read_locked()?;
+        let differs = self.adopt(disk, stamp);
+        let result = change(&mut self.data, &self.names)?;
+        if result.is_some() {
+            self.reindex();
+        }
+        if result.is_some() || !self.pending.is_empty() || differs {
+            self.save_locked()?;
+            self.pending.clear();
+        }
+        Ok(result)
+    }
+
+    /// Count an event for a session, to be saved with the next change or `flush`
+    pub fn count(&mut self, key: &str, event: SessionEvent) {
+        self.defer(key, Deferred::Count(event));
+    }
+
+    /// Whether there are changes not saved yet
+    pub fn is_dirty(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Save changes held back so far
+    pub fn flush(&mut self) -> Result<()> {
+        if self.is_dirty() {
+            self.update(|_, _| Ok(None::<()>))?;
+        }
+        Ok(())
+    }
+
+    /// Ask the daemon to save what it's holding back, and wait up to `lock_timeout` for it
+    ///
+    /// For a CLI command about to read the file. A daemon too busy to answer in
+    /// time leaves the command reading what was last saved.
+    pub fn request_flush(&self) -> Result<()> {
+        if let Some(parent) = self.flush_request_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        File::create(&self.flush_request_path)?;
+        let deadline = Instant::now() + self.lock_timeout;
+        while self.flush_request_path.exists() && Instant::now() < deadline {
+            std::thread::sleep(LOCK_POLL);
+        }
+        Ok(())
+    }
+
+    /// Flush if a CLI command has asked for it with `request_flush`; returns whether one had
+    pub fn flush_if_requested(&mut self) -> Result<bool> {
+        if !self.flush_request_path.exists() {
+            return Ok(false);
+        }
+        self.flush()?;
+        fs::remove_file(&self.flush_request_path)?;
+        Ok(true)
+    }
+
+    /// Make a change here now, and on disk with the next save
+    fn defer(&mut self, key: &str, change: Deferred) {
+        if let Some(session) = self.data.get_mut(key) {
+            change.apply(session);
+            self.pending.push((key.to_string(), change));
+        }
+    }
+
+    fn replay_pending(&mut self) {
+        for (key, change) in &self.pending {
+            if let Some(session) = self.data.get_mut(key) {
+                change.apply(session);
+            }
+        }
+    }
+
+    /// Take the registry's lock, shared or exclusive, waiting up to `lock_timeout` for it
+    ///
+    /// The lock is an flock on `sessions.json.lock`, held until the returned
+    /// file is dropped.
+    fn lock(&self, exclusive: bool) -> Result<File> {
+        if let Some(parent) = self.lock_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&self.lock_path)?;
+        let deadline = Instant::now() + self.lock_timeout;
+        loop {
+            let attempt = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+            match attempt {
+                Ok(()) => return Ok(file),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => std::thread::sleep(LOCK_POLL),
+                Err(TryLockError::WouldBlock) => {
+                    return Err(Error::Config("registry locked by another process".to_string()))
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
         }
-        Ok(removed)
     }
 
     /// Get number of registered sessions
@@ -161,72 +816,436 @@ impl SessionRegistry {
     }
 }
 
+/// When the registry file was last modified, and its size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileStamp {
+    /// None if the file isn't there
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self { modified: metadata.modified().ok()?, len: metadata.len() })
+    }
+}
+
+/// `ours` with the changes something else saved in `theirs`
+///
+/// Entries go by `updated_at`, `theirs` winning ties, since a hand edit
+/// rarely bumps it. Entries added or removed in `theirs` are added or removed.
+fn merge_external(
+    path: &Path,
+    ours: &HashMap<String, SessionData>,
+    theirs: HashMap<String, SessionData>,
+) -> HashMap<String, SessionData> {
+    let (mut took, mut kept, mut added) = (Vec::new(), Vec::new(), Vec::new());
+    let mut merged = HashMap::new();
+    for (key, session) in theirs {
+        match ours.get(&key) {
+            None => added.push(key.clone()),
+            Some(our) if our.updated_at > session.updated_at => {
+                kept.push(key.clone());
+                merged.insert(key, our.clone());
+                continue;
+            }
+            Some(our) if *our != session => took.push(key.clone()),
+            Some(_) => {}
+        }
+        merged.insert(key, session);
+    }
+    let mut removed: Vec<String> = ours.keys().filter(|key| !merged.contains_key(*key)).cloned().collect();
+
+    let mut merges = Vec::new();
+    for (what, keys) in [("took the file's", &mut took), ("kept our newer", &mut kept), ("added", &mut added), ("removed", &mut removed)] {
+        if !keys.is_empty() {
+            keys.sort();
+            merges.push(format!("{} {}", what, keys.join(", ")));
+        }
+    }
+    if merges.is_empty() {
+        merges.push("nothing to merge".to_string());
+    }
+    info!("{} was changed outside this process: {}", path.display(), merges.join("; "));
+    merged
+}
+
+/// A change to a session that waits for the next save
+#[derive(Debug, Clone, Copy)]
+enum Deferred {
+    Count(SessionEvent),
+    LastMessage(DateTime<Utc>),
+    LastOutbound(DateTime<Utc>),
+}
+
+impl Deferred {
+    fn apply(self, session: &mut SessionData) {
+        match self {
+            Deferred::Count(event) => session.counters.apply(event),
+            Deferred::LastMessage(at) => {
+                session.last_message_time = Some(at);
+                session.updated_at = at;
+            }
+            // Rows can come in out of order; the time never goes back
+            Deferred::LastOutbound(at) => session.last_outbound_time = session.last_outbound_time.max(Some(at)),
+        }
+    }
+}
+
+/// Whether a chat can share `owner`'s session name: another of the same person's handles can
+fn may_share(owner: &SessionData, session_type: &str, contact_name: Option<&str>) -> bool {
+    session_type != "group"
+        && owner.session_type == session_type
+        && owner.contact_name.is_some()
+        && owner.contact_name.as_deref() == contact_name
+}
+
+/// Fail if `session_name` is owned by a chat other than `key` that `may_share` wouldn't let it share with
+fn check_name_free(
+    data: &HashMap<String, SessionData>,
+    names: &HashMap<String, String>,
+    key: &str,
+    session_name: &str,
+    session_type: &str,
+    contact_name: Option<&str>,
+) -> Result<()> {
+    match names.get(session_name).filter(|owner| *owner != key).and_then(|owner| data.get(owner)) {
+        Some(owner) if !may_share(owner, session_type, contact_name) => {
+            Err(Error::SessionNameTaken(session_name.to_string(), owner.chat_id.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Fail if any session name is shared by chats that `may_share` wouldn't allow
+fn check_session_names(data: &HashMap<String, SessionData>) -> Result<()> {
+    let mut owners: HashMap<&str, &SessionData> = HashMap::new();
+    for session in data.values() {
+        let owner = owners.entry(&session.session_name).or_insert(session);
+        if (session.created_at, &session.chat_id) < (owner.created_at, &owner.chat_id) {
+            *owner = session;
+        }
+    }
+    for session in data.values() {
+        let owner = owners[session.session_name.as_str()];
+        if !std::ptr::eq(owner, session) && !may_share(owner, &session.session_type, session.contact_name.as_deref()) {
+            return Err(Error::SessionNameTaken(session.session_name.clone(), owner.chat_id.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// `path` with the `from` home directory swapped for `to`, or as it was if it's not under `from`
+fn rehome(path: &str, from: &str, to: &Path) -> String {
+    match Path::new(path).strip_prefix(from) {
+        Ok(rest) if !from.is_empty() => to.join(rest).to_string_lossy().into_owned(),
+        _ => path.to_string(),
+    }
+}
+
+fn prune_cutoff(older_than: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(older_than)
+        .ok()
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+fn is_prunable(session: &SessionData, session_mgr: &SessionManager, cutoff: DateTime<Utc>, transcripts_gone: bool) -> bool {
+    session.last_message_time.unwrap_or(session.updated_at) < cutoff
+        && !(transcripts_gone && Path::new(&session.transcript_dir).exists())
+        && !session_mgr.session_exists(&session.session_name)
+}
+
+/// Parse a registry file, or say what's wrong with it
+pub fn read_registry(path: &Path) -> Result<HashMap<String, SessionData>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Replace `path` with `value` as pretty JSON, so readers see the old file or the new one
+pub fn write_json_atomic<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    write_atomic(path, serde_json::to_string_pretty(value)?.as_bytes())
+}
+
+/// Replace `path` with `contents` via a synced temp file in the same directory
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let mut temp = NamedTempFile::new_in(parent)?;
+    temp.write_all(contents)?;
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|e| Error::Io(e.error))?;
+    Ok(())
+}
+
+/// `sessions.json.<n>`
+fn backup_path(registry_path: &Path, n: usize) -> PathBuf {
+    let mut name = registry_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", n));
+    registry_path.with_file_name(name)
+}
+
+/// Six hex digits of a 32-bit FNV-1a hash, the same on every run
+fn short_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0x811c_9dc5_u32, |hash, byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193));
+    format!("{:06x}", hash >> 8)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+    use std::collections::HashSet;
     use tempfile::TempDir;
 
     fn test_config(temp_dir: &TempDir) -> Config {
         Config::for_test(temp_dir.path())
     }
 
+    fn register(registry: &mut SessionRegistry, chat_id: &str, session_name: &str) {
+        registry
+            .register(chat_id, session_name, "/tmp/t", "individual", None, None, Some("family".to_string()), None)
+            .unwrap();
+    }
+
     #[test]
-    fn test_registry_create_and_load() {
+    fn test_write_json_atomic() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("state/nested/list.json");
+        write_json_atomic(&path, &["one", "two"]).unwrap();
+        write_json_atomic(&path, &["three"]).unwrap();
+
+        let saved: Vec<String> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, vec!["three"]);
+        // No temp files left beside it
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_registry_backup_rotation() {
         let temp_dir = TempDir::new().unwrap();
         let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        let files = registry.files();
+        assert_eq!(files[1], config.registry_file.with_file_name("sessions.json.1"));
+        assert_eq!(files.len(), 1 + REGISTRY_BACKUPS);
 
-        // Create registry and register a session
+        // The first save has nothing to back up
+        register(&mut registry, "+16175550001", "one");
+        assert!(!files[1].exists());
+        for (i, name) in ["two", "three", "four", "five"].iter().enumerate() {
+            register(&mut registry, &format!("+1617555000{}", i + 2), name);
+        }
+        // Each backup is the registry as it was one save earlier
+        for (n, len) in [(1, 4), (2, 3), (3, 2)] {
+            assert_eq!(read_registry(&files[n]).unwrap().len(), len);
+        }
+        assert!(!backup_path(&config.registry_file, 4).exists());
+    }
+
+    #[test]
+    fn test_registry_recovers_from_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
         let mut registry = SessionRegistry::new(&config);
-        registry
-            .register(
-                "+16175551234",
-                "test-user",
-                "/tmp/transcripts/test-user",
-                "individual",
-                Some("Test User".to_string()),
-                None,
-                Some("admin".to_string()),
-                None,
-            )
-            .unwrap();
+        register(&mut registry, "+16175550001", "one");
+        register(&mut registry, "+16175550002", "two");
 
-        assert_eq!(registry.len(), 1);
+        // A bad hand edit
+        fs::write(&config.registry_file, "{ \"+16175550001\": ").unwrap();
+        let mut reloaded = SessionRegistry::new(&config);
+        assert_eq!(reloaded.load().unwrap(), 1);
+        assert!(reloaded.get("+16175550001").is_some());
+        // Restored in place, with the bad one kept to look at
+        assert_eq!(read_registry(&config.registry_file).unwrap().len(), 1);
+        let state_dir = config.registry_file.parent().unwrap();
+        let aside: Vec<String> = fs::read_dir(state_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("sessions.json.corrupt-"))
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert_eq!(fs::read_to_string(state_dir.join(&aside[0])).unwrap(), "{ \"+16175550001\": ");
+    }
 
-        // Create new registry instance and load
-        let mut registry2 = SessionRegistry::new(&config);
-        let count = registry2.load().unwrap();
-        assert_eq!(count, 1);
+    /// A corrupt newest backup is skipped for an older one
+    #[test]
+    fn test_registry_skips_corrupt_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        register(&mut registry, "+16175550002", "two");
+        register(&mut registry, "+16175550003", "three");
 
-        let session = registry2.get("+16175551234").unwrap();
-        assert_eq!(session.session_name, "test-user");
-        assert_eq!(session.tier, Some("admin".to_string()));
+        fs::write(&config.registry_file, "not json").unwrap();
+        fs::write(backup_path(&config.registry_file, 1), "").unwrap();
+        let mut reloaded = SessionRegistry::new(&config);
+        assert_eq!(reloaded.load().unwrap(), 1);
     }
 
     #[test]
-    fn test_registry_update() {
+    fn test_registry_all_corrupt() {
         let temp_dir = TempDir::new().unwrap();
         let config = test_config(&temp_dir);
         let mut registry = SessionRegistry::new(&config);
+        fs::create_dir_all(config.registry_file.parent().unwrap()).unwrap();
+        for path in registry.files() {
+            fs::write(path, "[").unwrap();
+        }
+        assert_eq!(registry.load().unwrap(), 0);
+        assert!(registry.is_empty());
+        assert!(!config.registry_file.exists());
 
-        // Register
-        registry
-            .register(
-                "+16175551234",
-                "test-user",
-                "/tmp/test",
-                "individual",
-                Some("Test".to_string()),
-                None,
-                Some("admin".to_string()),
-                None,
-            )
-            .unwrap();
+        // Saving again doesn't back up a corrupt registry over the good copies
+        register(&mut registry, "+16175550001", "one");
+        fs::write(&config.registry_file, "[").unwrap();
+        register(&mut registry, "+16175550002", "two");
+        assert_eq!(fs::read_to_string(backup_path(&config.registry_file, 1)).unwrap(), "[");
+    }
 
-        let first_created = registry.get("+16175551234").unwrap().created_at;
+    /// The registry's lock, as another process holding it would
+    fn hold_lock(config: &Config, exclusive: bool) -> File {
+        SessionRegistry::new(config).lock(exclusive).unwrap()
+    }
 
-        // Update (same chat_id)
-        std::thread::sleep(std::time::Duration::from_millis(10));
-        registry
+    /// Writers on several threads, each with its own registry and lock file handle, lose nothing
+    #[test]
+    fn test_registry_concurrent_writers() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let config = config.clone();
+                std::thread::spawn(move || {
+                    let mut registry = SessionRegistry::new(&config);
+                    for j in 0..5 {
+                        register(&mut registry, &format!("+161755{:02}{:02}", i, j), &format!("writer-{}-{}", i, j));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let mut registry = SessionRegistry::new(&config);
+        assert_eq!(registry.load().unwrap(), 40);
+    }
+
+    /// A change starts from what another process saved, not from this registry's last look
+    #[test]
+    fn test_registry_update_keeps_other_writers_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut daemon = SessionRegistry::new(&config);
+        register(&mut daemon, "+16175550001", "one");
+        let mut cli = SessionRegistry::new(&config);
+        register(&mut cli, "+16175550002", "two");
+
+        assert!(daemon.update_tier("+16175550001", "admin").unwrap());
+        assert_eq!(daemon.len(), 2);
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved["+16175550001"].tier.as_deref(), Some("admin"));
+    }
+
+    #[test]
+    fn test_registry_lock_timeout() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = test_config(&temp_dir);
+        config.registry_lock_ms = 100;
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+
+        let held = hold_lock(&config, true);
+        let started = Instant::now();
+        let err = registry.update_tier("+16175550001", "admin").unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(matches!(err, Error::Config(ref m) if m == "registry locked by another process"));
+        assert!(registry.load().is_err());
+        drop(held);
+
+        // Readers share the lock, but keep writers out
+        let held = hold_lock(&config, false);
+        assert_eq!(registry.load().unwrap(), 1);
+        assert!(registry.remove("+16175550001").is_err());
+        drop(held);
+        assert!(registry.remove("+16175550001").unwrap().is_some());
+    }
+
+    /// A writer waits out a short hold rather than failing
+    #[test]
+    fn test_registry_waits_for_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let held = hold_lock(&config, true);
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(150));
+            drop(held);
+        });
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        holder.join().unwrap();
+        assert_eq!(read_registry(&config.registry_file).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_registry_create_and_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+
+        // Create registry and register a session
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register(
+                "+16175551234",
+                "test-user",
+                "/tmp/transcripts/test-user",
+                "individual",
+                Some("Test User".to_string()),
+                None,
+                Some("admin".to_string()),
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(registry.len(), 1);
+
+        // Create new registry instance and load
+        let mut registry2 = SessionRegistry::new(&config);
+        let count = registry2.load().unwrap();
+        assert_eq!(count, 1);
+
+        let session = registry2.get("+16175551234").unwrap();
+        assert_eq!(session.session_name, "test-user");
+        assert_eq!(session.tier, Some("admin".to_string()));
+    }
+
+    #[test]
+    fn test_registry_update() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+
+        // Register
+        registry
+            .register(
+                "+16175551234",
+                "test-user",
+                "/tmp/test",
+                "individual",
+                Some("Test".to_string()),
+                None,
+                Some("admin".to_string()),
+                None,
+            )
+            .unwrap();
+
+        let first_created = registry.get("+16175551234").unwrap().created_at;
+
+        // Update (same chat_id)
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        registry
             .register(
                 "+16175551234",
                 "test-user-updated",
@@ -273,6 +1292,32 @@ mod tests {
         assert!(not_found.is_none());
     }
 
+    #[test]
+    fn test_registry_session_owner() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+
+        // Both of one person's handles share a session; the first registered owns it
+        for chat_id in ["jane@example.com", "+16175551234"] {
+            registry
+                .register(chat_id, "jane-doe", "/tmp/test", "individual", Some("Jane Doe".to_string()), None, None, None)
+                .unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(registry.session_owner("jane-doe"), Some("jane@example.com"));
+        assert_eq!(registry.get_by_session_name("jane-doe").unwrap().chat_id, "jane@example.com");
+
+        // Re-registering keeps the original creation time, and so the owner
+        registry
+            .register("jane@example.com", "jane-doe", "/tmp/test", "individual", None, None, None, None)
+            .unwrap();
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.session_owner("jane-doe"), Some("jane@example.com"));
+        assert_eq!(reloaded.session_owner("nobody"), None);
+    }
+
     #[test]
     fn test_registry_remove() {
         let temp_dir = TempDir::new().unwrap();
@@ -331,6 +1376,481 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_registry_update_participants() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+
+        registry
+            .register(
+                "chat123456789",
+                "group-family",
+                "/tmp/group-family",
+                "group",
+                None,
+                Some("Family".to_string()),
+                Some("family".to_string()),
+                Some(vec!["John Doe".to_string(), "Jane Doe".to_string()]),
+            )
+            .unwrap();
+
+        let same = vec!["John Doe".to_string(), "Jane Doe".to_string()];
+        assert!(!registry.update_participants("chat123456789", same).unwrap());
+
+        let grown = vec!["John Doe".to_string(), "Jane Doe".to_string(), "Sam Roe".to_string()];
+        assert!(registry.update_participants("chat123456789", grown.clone()).unwrap());
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get("chat123456789").unwrap().participants, Some(grown));
+
+        assert!(!registry.update_participants("unknown", vec![]).unwrap());
+    }
+
+    #[test]
+    fn test_registry_update_display_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+
+        registry
+            .register(
+                "chat123456789",
+                "group-ski_trip",
+                "/tmp/group-ski_trip",
+                "group",
+                None,
+                Some("Ski Trip".to_string()),
+                Some("family".to_string()),
+                None,
+            )
+            .unwrap();
+
+        assert!(!registry.update_display_name("chat123456789", Some("Ski Trip".to_string())).unwrap());
+        assert!(registry.update_display_name("chat123456789", Some("Ski Trip 2025".to_string())).unwrap());
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        let session = reloaded.get("chat123456789").unwrap();
+        assert_eq!(session.display_name.as_deref(), Some("Ski Trip 2025"));
+        // The tmux session name is left alone
+        assert_eq!(session.session_name, "group-ski_trip");
+    }
+
+    #[test]
+    fn test_registry_update_tier() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+
+        registry
+            .register(
+                "+16175551234",
+                "john-doe",
+                "/tmp/john-doe",
+                "individual",
+                Some("John Doe".to_string()),
+                None,
+                Some("favorite".to_string()),
+                None,
+            )
+            .unwrap();
+
+        assert!(!registry.update_tier("+16175551234", "favorite").unwrap());
+        assert!(registry.update_tier("+16175551234", "family").unwrap());
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get("+16175551234").unwrap().tier.as_deref(), Some("family"));
+
+        assert!(!registry.update_tier("unknown", "family").unwrap());
+    }
+
+    #[test]
+    fn test_registry_update_contact_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+
+        registry
+            .register(
+                "+16175551234",
+                "john-doe",
+                "/tmp/john-doe",
+                "individual",
+                Some("John Doe".to_string()),
+                None,
+                Some("favorite".to_string()),
+                None,
+            )
+            .unwrap();
+
+        assert!(!registry.update_contact_name("+16175551234", "John Doe").unwrap());
+        assert!(registry.update_contact_name("+16175551234", "Johnny Doe").unwrap());
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        let data = reloaded.get("+16175551234").unwrap();
+        assert_eq!(data.contact_name.as_deref(), Some("Johnny Doe"));
+        assert_eq!(data.session_name, "john-doe");
+
+        assert!(!registry.update_contact_name("unknown", "Jane Doe").unwrap());
+    }
+
+    #[test]
+    fn test_registry_background_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+
+        registry
+            .register("+16175551234", "john-doe", "/tmp/john-doe", "individual", Some("John Doe".to_string()), None, None, None)
+            .unwrap();
+        registry
+            .register("+16175551234", "john-doe-bg", "/tmp/john-doe", "background", Some("John Doe".to_string()), None, None, None)
+            .unwrap();
+
+        // Kept beside the main session rather than replacing it
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get("+16175551234").unwrap().session_name, "john-doe");
+        let bg = registry.get_background("+16175551234").unwrap();
+        assert_eq!((bg.chat_id.as_str(), bg.session_name.as_str()), ("+16175551234", "john-doe-bg"));
+        assert_eq!(registry.session_owner("john-doe-bg"), Some("+16175551234"));
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get_background("+16175551234").unwrap().session_type, "background");
+        assert!(reloaded.get_background("+16175550000").is_none());
+    }
+
+    /// The name index agrees with a full scan after loads, re-registrations, and removals
+    #[test]
+    fn test_session_name_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        let consistent = |registry: &SessionRegistry| {
+            assert_eq!(registry.names.len(), registry.all().values().map(|d| &d.session_name).collect::<HashSet<_>>().len());
+            for (name, key) in &registry.names {
+                assert_eq!(&registry.all()[key].session_name, name);
+            }
+        };
+        register(&mut registry, "+16175550001", "one");
+        register(&mut registry, "+16175550002", "two");
+        consistent(&registry);
+        assert_eq!(registry.session_owner("two"), Some("+16175550002"));
+
+        // Registering over a chat under a new name frees the old one
+        register(&mut registry, "+16175550002", "two-again");
+        consistent(&registry);
+        assert!(registry.get_by_session_name("two").is_none());
+        assert_eq!(registry.session_owner("two-again"), Some("+16175550002"));
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        consistent(&reloaded);
+        assert_eq!(reloaded.session_owner("one"), Some("+16175550001"));
+
+        reloaded.remove("+16175550001").unwrap();
+        consistent(&reloaded);
+        assert!(reloaded.get_by_session_name("one").is_none());
+        // Another process's change shows up in the index with the next one here
+        register(&mut registry, "+16175550003", "three");
+        consistent(&registry);
+        assert!(registry.get_by_session_name("one").is_none());
+    }
+
+    /// A session name can't be taken by another person's chat, only by another of the same person's handles
+    #[test]
+    fn test_register_taken_session_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        let individual = |registry: &mut SessionRegistry, chat_id: &str, contact: &str| {
+            registry.register(chat_id, "jane-doe", "/tmp/t", "individual", Some(contact.to_string()), None, None, None)
+        };
+        individual(&mut registry, "+16175551234", "Jane Doe").unwrap();
+        individual(&mut registry, "jane@example.com", "Jane Doe").unwrap();
+
+        let err = individual(&mut registry, "+16175559999", "Jane Smith").unwrap_err();
+        assert!(matches!(err, Error::SessionNameTaken(ref name, ref owner) if name == "jane-doe" && owner == "+16175551234"));
+        let err = registry.register("chat123", "jane-doe", "/tmp/t", "group", None, None, None, None).unwrap_err();
+        assert!(matches!(err, Error::SessionNameTaken(..)));
+        assert!(registry.get("+16175559999").is_none());
+        assert_eq!(read_registry(&config.registry_file).unwrap().len(), 2);
+
+        // Once the owner is gone, the next handle owns the name
+        registry.remove("+16175551234").unwrap();
+        assert_eq!(registry.session_owner("jane-doe"), Some("jane@example.com"));
+    }
+
+    /// Move a chat's last message (or, if `messaged` is false, its last update) `days` back, as saved on disk
+    fn backdate(config: &Config, chat_id: &str, days: i64, messaged: bool) {
+        let mut data = read_registry(&config.registry_file).unwrap();
+        let session = data.get_mut(chat_id).unwrap();
+        let then = Utc::now() - chrono::Duration::days(days);
+        if messaged {
+            session.last_message_time = Some(then);
+        } else {
+            session.last_message_time = None;
+            session.updated_at = then;
+        }
+        fs::write(&config.registry_file, serde_json::to_string(&data).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_prune() {
+        use crate::runner::{FakeTmux, FAKE_READY_PANE};
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let fake = std::sync::Arc::new(FakeTmux::new());
+        let session_mgr = SessionManager::with_runner(&config, fake.clone());
+        let mut earlier = SessionRegistry::new(&config);
+        for (chat_id, name) in [("chat1", "old-group"), ("chat2", "running"), ("chat3", "recent"), ("chat4", "never-messaged"), ("chat5", "new")] {
+            register(&mut earlier, chat_id, name);
+        }
+        drop(earlier);
+        backdate(&config, "chat1", 120, true);
+        backdate(&config, "chat2", 120, true);
+        backdate(&config, "chat3", 10, true);
+        backdate(&config, "chat4", 200, false);
+        fake.add_session("running", FAKE_READY_PANE);
+        // old-group's transcripts are still around
+        let mut data = read_registry(&config.registry_file).unwrap();
+        data.get_mut("chat1").unwrap().transcript_dir = temp_dir.path().to_string_lossy().into_owned();
+        fs::write(&config.registry_file, serde_json::to_string(&data).unwrap()).unwrap();
+        let mut registry = SessionRegistry::new(&config);
+        registry.load().unwrap();
+
+        let ninety_days = Duration::from_secs(90 * 24 * 3600);
+        let names = |sessions: Vec<&SessionData>| sessions.iter().map(|d| d.session_name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(registry.prunable(&session_mgr, ninety_days, false)), ["never-messaged", "old-group"]);
+        assert_eq!(names(registry.prunable(&session_mgr, ninety_days, true)), ["never-messaged"]);
+
+        let pruned = registry.prune(&session_mgr, ninety_days, false).unwrap();
+        assert_eq!(names(pruned.iter().collect()), ["never-messaged", "old-group"]);
+        let saved = read_registry(&config.registry_file).unwrap();
+        let mut left: Vec<&str> = saved.keys().map(String::as_str).collect();
+        left.sort();
+        assert_eq!(left, ["chat2", "chat3", "chat5"]);
+        assert!(registry.get_by_session_name("old-group").is_none());
+
+        // Nothing left to prune, and nothing written
+        let modified = fs::metadata(&config.registry_file).unwrap().modified().unwrap();
+        assert!(registry.prune(&session_mgr, ninety_days, false).unwrap().is_empty());
+        assert_eq!(fs::metadata(&config.registry_file).unwrap().modified().unwrap(), modified);
+        assert_eq!(registry.prune(&session_mgr, Duration::from_secs(86400), false).unwrap().len(), 1);
+    }
+
+    /// An export from another machine, whose home was /Users/old
+    fn old_machine_export() -> RegistryExport {
+        let temp_dir = TempDir::new().unwrap();
+        let mut old = SessionRegistry::new(&Config::for_test(temp_dir.path()));
+        for (chat_id, name) in [("(617) 555-0001", "one"), ("+16175550002", "two")] {
+            old.register(chat_id, name, &format!("/Users/old/transcripts/{}", name), "individual", None, None, Some("family".to_string()), None)
+                .unwrap();
+        }
+        old.register("ABCDEF0123456789ABCDEF", "book-club", "/srv/shared/book-club", "group", None, None, None, None).unwrap();
+        let export = old.export(Path::new("/Users/old"));
+        assert_eq!(export.version, REGISTRY_EXPORT_VERSION);
+        export
+    }
+
+    #[test]
+    fn test_import_rewrites_paths_and_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let export = old_machine_export();
+        let json = serde_json::to_string(&export).unwrap();
+
+        let mut registry = SessionRegistry::new(&config);
+        let summary = registry
+            .import(&config, serde_json::from_str(&json).unwrap(), ImportMode::Merge, Path::new("/Users/new"))
+            .unwrap();
+        assert_eq!(summary, ImportSummary { added: 3, ..ImportSummary::default() });
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved["+16175550001"].transcript_dir, "/Users/new/transcripts/one");
+        assert_eq!(saved["+16175550001"].chat_id, "+16175550001");
+        assert_eq!(saved["abcdef0123456789abcdef"].transcript_dir, "/srv/shared/book-club");
+        assert_eq!(registry.session_owner("book-club"), Some("abcdef0123456789abcdef"));
+    }
+
+    #[test]
+    fn test_import_merge_keeps_newer() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut export = old_machine_export();
+        let mut registry = SessionRegistry::new(&config);
+        // Registered here after the export was made
+        register(&mut registry, "+16175550002", "two");
+        registry.update_tier("+16175550002", "admin").unwrap();
+        register(&mut registry, "+16175550009", "nine");
+        // and one the export has a newer copy of
+        export.sessions.get_mut("ABCDEF0123456789ABCDEF").unwrap().updated_at = Utc::now() + chrono::Duration::hours(1);
+        register(&mut registry, "abcdef0123456789abcdef", "book-club");
+
+        let summary = registry.import(&config, export, ImportMode::Merge, Path::new("/Users/new")).unwrap();
+        assert_eq!(summary, ImportSummary { added: 1, updated: 1, kept: 1, removed: 0 });
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved.len(), 4);
+        assert_eq!(saved["+16175550002"].tier.as_deref(), Some("admin"));
+        assert_eq!(saved["abcdef0123456789abcdef"].session_type, "group");
+    }
+
+    #[test]
+    fn test_import_replace() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let export = old_machine_export();
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550002", "two");
+        register(&mut registry, "+16175550009", "nine");
+
+        let summary = registry.import(&config, export, ImportMode::Replace, Path::new("/Users/new")).unwrap();
+        assert_eq!(summary, ImportSummary { added: 2, updated: 1, kept: 0, removed: 1 });
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved.len(), 3);
+        assert!(!saved.contains_key("+16175550009"));
+        assert_eq!(saved["+16175550002"].transcript_dir, "/Users/new/transcripts/two");
+    }
+
+    /// A bad entry or a newer format fails the whole import, leaving the registry as it was
+    #[test]
+    fn test_import_all_or_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550009", "nine");
+        let before = fs::read_to_string(&config.registry_file).unwrap();
+        let home = Path::new("/Users/new");
+
+        let mut export = old_machine_export();
+        export.sessions.get_mut("+16175550002").unwrap().tier = Some("vip".to_string());
+        assert!(matches!(registry.import(&config, export, ImportMode::Replace, home), Err(Error::Config(_))));
+
+        let mut export = old_machine_export();
+        export.version = REGISTRY_EXPORT_VERSION + 1;
+        assert!(registry.import(&config, export, ImportMode::Merge, home).is_err());
+
+        // Someone else's chat already has the name here
+        let mut export = old_machine_export();
+        export.sessions.get_mut("+16175550002").unwrap().session_name = "nine".to_string();
+        assert!(matches!(registry.import(&config, export, ImportMode::Merge, home), Err(Error::SessionNameTaken(..))));
+
+        assert_eq!(fs::read_to_string(&config.registry_file).unwrap(), before);
+    }
+
+    #[test]
+    fn test_rehome() {
+        let home = Path::new("/Users/new");
+        assert_eq!(rehome("/Users/old/t/jane", "/Users/old", home), "/Users/new/t/jane");
+        assert_eq!(rehome("/Users/older/t/jane", "/Users/old", home), "/Users/older/t/jane");
+        assert_eq!(rehome("/tmp/t", "", home), "/tmp/t");
+    }
+
+    #[test]
+    fn test_claim_session_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        assert_eq!(registry.claim_session_name("group-family", "chat111"), "group-family");
+        registry
+            .register("chat111", "group-family", "/tmp/group-family", "group", None, None, None, None)
+            .unwrap();
+        assert_eq!(registry.claim_session_name("group-family", "chat111"), "group-family");
+
+        // Another group with the same name gets its own session
+        let other = registry.claim_session_name("group-family", "chat222");
+        assert_eq!(other, format!("group-family-{}", short_hash("chat222")));
+        assert_ne!(short_hash("chat222"), short_hash("chat333"));
+        registry
+            .register("chat222", &other, "/tmp/other", "group", None, None, None, None)
+            .unwrap();
+
+        // Both keep their names after a restart, and each name has one owner
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get("chat222").unwrap().session_name, other);
+        assert_eq!(reloaded.claim_session_name("group-family", "chat222"), other);
+        assert_eq!(reloaded.session_owner("group-family"), Some("chat111"));
+        assert_eq!(reloaded.session_owner(&other), Some("chat222"));
+    }
+
+    #[test]
+    fn test_registry_claude_session_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        registry
+            .register("+16175551234", "john-doe", "/tmp/john-doe", "individual", None, None, None, None)
+            .unwrap();
+        assert!(registry.get("+16175551234").unwrap().claude_session_id.is_none());
+
+        assert!(registry.update_claude_session("+16175551234", "0b7c-42").unwrap());
+        assert!(!registry.update_claude_session("+16175551234", "0b7c-42").unwrap());
+        assert!(!registry.update_claude_session("unknown", "0b7c-42").unwrap());
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get("+16175551234").unwrap().claude_session_id.as_deref(), Some("0b7c-42"));
+
+        // Recreating the session keeps it to resume later
+        reloaded
+            .register("+16175551234", "john-doe", "/tmp/john-doe", "individual", None, None, None, None)
+            .unwrap();
+        assert_eq!(reloaded.get("+16175551234").unwrap().claude_session_id.as_deref(), Some("0b7c-42"));
+
+        assert!(reloaded.clear_claude_session("+16175551234").unwrap());
+        assert!(!reloaded.clear_claude_session("+16175551234").unwrap());
+        assert!(read_registry(&config.registry_file).unwrap()["+16175551234"].claude_session_id.is_none());
+        // and isn't written at all while unset
+        assert!(!fs::read_to_string(&config.registry_file).unwrap().contains("claude_session_id"));
+    }
+
+    #[test]
+    fn test_registry_archive_and_revive() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        let register = |registry: &mut SessionRegistry| {
+            registry
+                .register("+16175551234", "john-doe", "/tmp/john-doe", "individual", None, None, None, None)
+                .unwrap();
+        };
+        register(&mut registry);
+
+        assert!(registry.set_archived("+16175551234", true).unwrap());
+        assert!(!registry.set_archived("+16175551234", true).unwrap());
+        assert!(!registry.set_archived("unknown", true).unwrap());
+
+        let mut reloaded = SessionRegistry::new(&config);
+        reloaded.load().unwrap();
+        assert!(reloaded.get("+16175551234").unwrap().archived);
+
+        // Recreating the session brings the entry back
+        register(&mut reloaded);
+        assert!(!reloaded.get("+16175551234").unwrap().archived);
+    }
+
+    #[test]
+    fn test_registry_quarantined() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175551234", "john-doe");
+
+        assert!(registry.set_quarantined("+16175551234", true).unwrap());
+        assert!(!registry.set_quarantined("+16175551234", true).unwrap());
+        assert!(!registry.set_quarantined("unknown", true).unwrap());
+        // A message recreating the session doesn't lift it
+        register(&mut registry, "+16175551234", "john-doe");
+        assert!(read_registry(&config.registry_file).unwrap()["+16175551234"].quarantined);
+
+        assert!(registry.set_quarantined("+16175551234", false).unwrap());
+        assert!(!registry.get("+16175551234").unwrap().quarantined);
+    }
+
     #[test]
     fn test_registry_last_message_time() {
         let temp_dir = TempDir::new().unwrap();
@@ -375,6 +1895,11 @@ mod tests {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             last_message_time: None,
+            last_outbound_time: None,
+            archived: false,
+            quarantined: false,
+            claude_session_id: None,
+            counters: SessionCounters::default(),
         };
 
         let json = serde_json::to_string(&session).unwrap();
@@ -386,4 +1911,271 @@ mod tests {
         assert_eq!(parsed.chat_id, session.chat_id);
         assert_eq!(parsed.session_name, session.session_name);
     }
+
+    /// Registries saved before counters load with them at zero
+    #[test]
+    fn test_counters_default() {
+        let json = r#"{"chat_id": "+16175551234", "session_name": "jane-doe", "transcript_dir": "/tmp/t",
+            "type": "individual", "contact_name": null, "display_name": null, "tier": "family",
+            "participants": null, "created_at": "2025-01-01T00:00:00Z", "updated_at": "2025-01-01T00:00:00Z"}"#;
+        let parsed: SessionData = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.counters, SessionCounters::default());
+
+        assert_eq!(parsed.last_outbound_time, None);
+
+        let json = json.replace("}", r#", "counters": {"inbound": 7, "last_inbound": "2025-01-02T00:00:00Z"}}"#);
+        let parsed: SessionData = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.counters.inbound, 7);
+        assert_eq!(parsed.counters.restarts, 0);
+        assert_eq!(parsed.counters.last_inbound, Some("2025-01-02T00:00:00Z".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_counters_apply() {
+        let mut counters = SessionCounters::default();
+        let morning = Local.with_ymd_and_hms(2025, 3, 4, 9, 0, 0).unwrap().with_timezone(&Utc);
+        let evening = Local.with_ymd_and_hms(2025, 3, 4, 21, 0, 0).unwrap().with_timezone(&Utc);
+        let next_day = Local.with_ymd_and_hms(2025, 3, 5, 8, 0, 0).unwrap().with_timezone(&Utc);
+        let today = evening.with_timezone(&Local).date_naive();
+
+        counters.apply(SessionEvent::Inbound(morning));
+        counters.apply(SessionEvent::Inbound(evening));
+        counters.apply(SessionEvent::Injection);
+        assert_eq!(counters.inbound_on(today), 2);
+        // Out of order messages don't move the times back
+        counters.apply(SessionEvent::Inbound(morning));
+        assert_eq!(counters.last_inbound, Some(evening));
+
+        counters.apply(SessionEvent::Inbound(next_day));
+        counters.apply(SessionEvent::Restart);
+        counters.apply(SessionEvent::Compaction);
+        assert_eq!(counters.inbound_on(next_day.with_timezone(&Local).date_naive()), 1);
+        assert_eq!(counters.inbound_on(today), 0);
+        assert_eq!((counters.inbound, counters.injections, counters.restarts, counters.compactions), (4, 1, 1, 1));
+    }
+
+    /// Write the registry file as something other than a `SessionRegistry` would
+    fn edit_externally(config: &Config, edit: impl FnOnce(&mut HashMap<String, SessionData>)) {
+        let mut data = read_registry(&config.registry_file).unwrap();
+        edit(&mut data);
+        fs::write(&config.registry_file, serde_json::to_string_pretty(&data).unwrap()).unwrap();
+    }
+
+    /// A hand edit made while the registry is loaded survives its next save
+    #[test]
+    fn test_external_edit_merged() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        register(&mut registry, "+16175550002", "two");
+        register(&mut registry, "+16175550003", "three");
+        registry.update_last_message("+16175550001").unwrap();
+
+        let added = registry.get("+16175550002").unwrap().clone();
+        edit_externally(&config, |data| {
+            data.get_mut("+16175550001").unwrap().tier = Some("favorite".to_string());
+            data.remove("+16175550003");
+            let mut four = added;
+            four.chat_id = "+16175550004".to_string();
+            four.session_name = "four".to_string();
+            data.insert(four.chat_id.clone(), four);
+        });
+        register(&mut registry, "+16175550005", "five");
+
+        let saved = read_registry(&config.registry_file).unwrap();
+        let mut keys: Vec<&String> = saved.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["+16175550001", "+16175550002", "+16175550004", "+16175550005"]);
+        // The edit, with the last message time held back before it
+        assert_eq!(saved["+16175550001"].tier.as_deref(), Some("favorite"));
+        assert!(saved["+16175550001"].last_message_time.is_some());
+        assert_eq!(registry.get("+16175550001").unwrap().tier.as_deref(), Some("favorite"));
+        assert_eq!(registry.get_by_session_name("four").unwrap().chat_id, "+16175550004");
+        assert!(registry.get("+16175550003").is_none());
+    }
+
+    /// An older copy of an entry written back doesn't undo a newer change
+    #[test]
+    fn test_external_stale_entry_kept_ours() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        let stale = read_registry(&config.registry_file).unwrap();
+        registry.update_tier("+16175550001", "favorite").unwrap();
+
+        fs::write(&config.registry_file, serde_json::to_string_pretty(&stale).unwrap()).unwrap();
+        assert!(registry.refresh().unwrap());
+        assert!(!registry.refresh().unwrap());
+        assert_eq!(registry.get("+16175550001").unwrap().tier.as_deref(), Some("favorite"));
+        // and the file is put right
+        assert_eq!(read_registry(&config.registry_file).unwrap()["+16175550001"].tier.as_deref(), Some("favorite"));
+    }
+
+    #[test]
+    fn test_refresh_picks_up_external_edit() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        assert!(!registry.refresh().unwrap());
+        let saves = registry.saves;
+
+        edit_externally(&config, |data| data.get_mut("+16175550001").unwrap().contact_name = Some("Jane Doe".to_string()));
+        assert!(registry.refresh().unwrap());
+        assert_eq!(registry.get("+16175550001").unwrap().contact_name.as_deref(), Some("Jane Doe"));
+        // Nothing of ours to add, so the edit isn't rewritten
+        assert_eq!(registry.saves, saves);
+        assert!(!registry.refresh().unwrap());
+    }
+
+    /// With merging off, this process's copy wins
+    #[test]
+    fn test_external_edit_written_over_without_merge() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = test_config(&temp_dir);
+        config.registry_merge = false;
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+
+        edit_externally(&config, |data| {
+            data.get_mut("+16175550001").unwrap().tier = Some("favorite".to_string());
+            data.remove("+16175550001").map(|one| data.insert("+16175550009".to_string(), one));
+        });
+        assert!(registry.refresh().unwrap());
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved.keys().collect::<Vec<_>>(), ["+16175550001"]);
+        assert_eq!(saved["+16175550001"].tier.as_deref(), Some("family"));
+        assert_eq!(registry.get("+16175550001").unwrap().tier.as_deref(), Some("family"));
+    }
+
+    #[test]
+    fn test_update_last_outbound() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        let earlier: DateTime<Utc> = "2025-01-02T09:00:00Z".parse().unwrap();
+        let later: DateTime<Utc> = "2025-01-02T10:00:00Z".parse().unwrap();
+
+        registry.update_last_outbound("+16175550001", later);
+        registry.update_last_outbound("+16175550001", earlier);
+        assert_eq!(registry.get("+16175550001").unwrap().last_outbound_time, Some(later));
+        // Not a message from the chat
+        assert!(registry.get("+16175550001").unwrap().last_message_time.is_none());
+
+        // Re-registering keeps it
+        register(&mut registry, "+16175550001", "one");
+        assert_eq!(read_registry(&config.registry_file).unwrap()["+16175550001"].last_outbound_time, Some(later));
+    }
+
+    /// A burst of messages is one save, whenever the flush comes
+    #[test]
+    fn test_hot_path_changes_coalesce() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        register(&mut registry, "+16175550002", "two");
+        let saves = registry.saves;
+
+        for _ in 0..50 {
+            registry.update_last_message("+16175550001").unwrap();
+            registry.count("+16175550001", SessionEvent::Inbound(Utc::now()));
+            registry.update_last_message("+16175550002").unwrap();
+        }
+        assert!(registry.is_dirty());
+        assert_eq!(registry.saves, saves);
+        assert!(read_registry(&config.registry_file).unwrap()["+16175550001"].last_message_time.is_none());
+
+        registry.flush().unwrap();
+        registry.flush().unwrap();
+        assert_eq!(registry.saves, saves + 1);
+        assert!(!registry.is_dirty());
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved["+16175550001"].last_message_time, registry.get("+16175550001").unwrap().last_message_time);
+        assert!(saved["+16175550002"].last_message_time.is_some());
+        assert_eq!(saved["+16175550001"].counters.inbound, 50);
+
+        // Unregistered chats have nothing to hold back
+        registry.update_last_message("+16175559999").unwrap();
+        assert!(!registry.is_dirty());
+    }
+
+    /// Held back changes survive another process's save, and are saved by a flush
+    #[test]
+    fn test_held_back_changes_not_lost() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut daemon = SessionRegistry::new(&config);
+        register(&mut daemon, "+16175550001", "one");
+        daemon.update_last_message("+16175550001").unwrap();
+        let sent = daemon.get("+16175550001").unwrap().last_message_time;
+
+        let mut cli = SessionRegistry::new(&config);
+        register(&mut cli, "+16175550002", "two");
+        // The daemon's next change saves both
+        register(&mut daemon, "+16175550003", "three");
+        let saved = read_registry(&config.registry_file).unwrap();
+        assert_eq!(saved.len(), 3);
+        assert_eq!(saved["+16175550001"].last_message_time, sent);
+
+        daemon.update_last_message("+16175550003").unwrap();
+        daemon.flush().unwrap();
+        assert!(read_registry(&config.registry_file).unwrap()["+16175550003"].last_message_time.is_some());
+    }
+
+    /// A CLI command's flush request has the daemon save what it holds back
+    #[test]
+    fn test_flush_on_request() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut daemon = SessionRegistry::new(&config);
+        register(&mut daemon, "+16175550001", "one");
+        daemon.update_last_message("+16175550001").unwrap();
+        assert!(!daemon.flush_if_requested().unwrap());
+        assert!(daemon.is_dirty());
+
+        let cli = SessionRegistry::new(&config);
+        let waiting = std::thread::spawn(move || cli.request_flush().unwrap());
+        while !daemon.flush_if_requested().unwrap() {
+            std::thread::sleep(LOCK_POLL);
+        }
+        waiting.join().unwrap();
+        assert!(!daemon.is_dirty());
+        assert!(read_registry(&config.registry_file).unwrap()["+16175550001"].last_message_time.is_some());
+        assert!(!config.registry_file.with_extension("json.flush").exists());
+    }
+
+    /// Counts wait for the next save instead of saving one each
+    #[test]
+    fn test_count_and_flush() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let mut registry = SessionRegistry::new(&config);
+        register(&mut registry, "+16175550001", "one");
+        register(&mut registry, "+16175550002", "two");
+        let saved = |chat_id: &str| read_registry(&config.registry_file).unwrap()[chat_id].counters.clone();
+
+        registry.count("+16175550001", SessionEvent::Inbound(Utc::now()));
+        registry.count("+16175550001", SessionEvent::Injection);
+        registry.count("+16175559999", SessionEvent::Injection);
+        assert_eq!(registry.get("+16175550001").unwrap().counters.inbound, 1);
+        assert_eq!(saved("+16175550001").inbound, 0);
+
+        // Reloading keeps unsaved counts, and the next change saves them
+        registry.load().unwrap();
+        assert_eq!(registry.get("+16175550001").unwrap().counters.injections, 1);
+        registry.update_tier("+16175550002", "admin").unwrap();
+        assert_eq!(saved("+16175550001").injections, 1);
+
+        // Counted once, however many saves follow
+        registry.count("+16175550002", SessionEvent::Restart);
+        registry.flush().unwrap();
+        registry.flush().unwrap();
+        registry.update_tier("+16175550002", "family").unwrap();
+        assert_eq!(saved("+16175550002").restarts, 1);
+        assert_eq!(saved("+16175550001").inbound, 1);
+    }
 }
@@ -1,38 +1,185 @@
 //! Reminder polling using cron expressions
 //!
 //! Evaluates cron schedules from contact notes to determine when to inject reminders.
+//!
+//! When each reminder last fired is saved to `reminders_fired.json`, so a
+//! daemon restart doesn't fire them all again. It's kept by chat and schedule
+//! rather than by position in the notes, so reordering them or adding one
+//! above another leaves the rest as they were.
+//!
+//! Besides those in contact notes, reminders can be added from the command
+//! line with `reminders add`. Those are kept in `reminders.json`, the daemon's
+//! own file, and picked up at the next check after it changes.
 
+use crate::config::Config;
+use crate::contacts::Contact;
 use crate::error::{Error, Result};
+use crate::registry::write_json_atomic;
+use crate::registry::SessionRegistry;
 use chrono::{DateTime, Utc};
 use cron::Schedule;
 use regex::Regex;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::SystemTime;
 
-/// A parsed reminder from contact notes
+/// A parsed reminder from contact notes or the reminders file
 #[derive(Debug, Clone)]
 pub struct Reminder {
     pub cron_expr: String,
     pub schedule: Schedule,
     pub prompt: String,
+    /// Its position among its chat's in the reminders file; None if it's from contact notes
+    pub added: Option<usize>,
+}
+
+impl Reminder {
+    /// A reminder firing on a 5-field cron schedule, or 6 with seconds first
+    pub fn parse(cron_expr: &str, prompt: &str) -> Result<Self> {
+        let cron_expr = cron_expr.trim();
+        // Cron crate needs 6 fields (sec min hour dom month dow)
+        // If user gives 5 fields, prepend "0" for seconds
+        let full_cron = if cron_expr.split_whitespace().count() == 5 {
+            format!("0 {}", cron_expr)
+        } else {
+            cron_expr.to_string()
+        };
+        let schedule = Schedule::from_str(&full_cron)
+            .map_err(|e| Error::Parse(format!("invalid cron expression '{}': {}", cron_expr, e)))?;
+        Ok(Self { cron_expr: cron_expr.to_string(), schedule, prompt: prompt.trim().to_string(), added: None })
+    }
+}
+
+/// A reminder added with `reminders add`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredReminder {
+    pub cron: String,
+    pub prompt: String,
+}
+
+/// Reminders added from the command line, by chat, in the order they were added
+pub struct ReminderStore {
+    path: PathBuf,
+    reminders: BTreeMap<String, Vec<StoredReminder>>,
+    modified: Option<SystemTime>,
+}
+
+impl ReminderStore {
+    /// Load `reminders_file`; a missing file is no reminders
+    pub fn load(config: &Config) -> Result<Self> {
+        let path = config.reminders_file.clone();
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let reminders = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self { path, reminders, modified })
+    }
+
+    /// Save atomically
+    pub fn save(&self) -> Result<()> {
+        write_json_atomic(&self.path, &self.reminders)
+    }
+
+    /// Add a reminder, returning its position among the chat's; the schedule has to parse
+    pub fn add(&mut self, chat_id: &str, cron_expr: &str, prompt: &str) -> Result<usize> {
+        let reminder = Reminder::parse(cron_expr, prompt)?;
+        let chat = self.reminders.entry(chat_id.to_string()).or_default();
+        chat.push(StoredReminder { cron: reminder.cron_expr, prompt: reminder.prompt });
+        Ok(chat.len() - 1)
+    }
+
+    /// Remove the chat's reminder at `position`, returning it
+    pub fn remove(&mut self, chat_id: &str, position: usize) -> Option<StoredReminder> {
+        let chat = self.reminders.get_mut(chat_id).filter(|chat| position < chat.len())?;
+        let removed = chat.remove(position);
+        if chat.is_empty() {
+            self.reminders.remove(chat_id);
+        }
+        Some(removed)
+    }
+
+    /// Every added reminder, by chat
+    pub fn reminders(&self) -> &BTreeMap<String, Vec<StoredReminder>> {
+        &self.reminders
+    }
+
+    /// When the file was last written, None if it doesn't exist
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
 }
 
 /// Manages reminder schedules for contacts
 pub struct ReminderManager {
     /// Map of chat_id -> Vec<Reminder>
     reminders: HashMap<String, Vec<Reminder>>,
-    /// Last fire time per reminder (chat_id + index)
+    /// Last fire time per reminder, by `fired_key`
     last_fired: HashMap<String, DateTime<Utc>>,
+    /// Where `last_fired` is saved, if anywhere
+    fired_file: Option<PathBuf>,
+    /// Whether `last_fired` changed since it was last saved
+    dirty: bool,
+}
+
+/// The key a reminder's last fire time is kept under: its chat and a hash of its schedule
+///
+/// FNV-1a, as std's hasher may change between Rust releases and the keys are saved.
+fn fired_key(chat_id: &str, cron_expr: &str) -> String {
+    let hash = cron_expr.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{}:{:016x}", chat_id, hash)
 }
 
 impl ReminderManager {
+    /// Reminders kept in memory only
     pub fn new() -> Self {
         Self {
             reminders: HashMap::new(),
             last_fired: HashMap::new(),
+            fired_file: None,
+            dirty: false,
         }
     }
 
+    /// Reminders whose fire times are saved to `reminders_fired_file`
+    pub fn with_config(config: &Config) -> Self {
+        Self { fired_file: Some(config.reminders_fired_file.clone()), ..Self::new() }
+    }
+
+    /// Load saved fire times; a missing file is nothing fired yet
+    pub fn load(&mut self) -> Result<()> {
+        let Some(path) = self.fired_file.as_ref().filter(|path| path.exists()) else {
+            return Ok(());
+        };
+        let saved: HashMap<String, DateTime<Utc>> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        self.last_fired = saved;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Whether there are fire times not saved yet
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Save fire times atomically, if any changed since the last save
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(path) = self.fired_file.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        // Sorted, so the file reads the same from one save to the next
+        let sorted: BTreeMap<&String, &DateTime<Utc>> = self.last_fired.iter().collect();
+        write_json_atomic(path, &sorted)?;
+        self.dirty = false;
+        Ok(())
+    }
+
     /// Parse reminders from contact notes
     /// Format: REMINDER: <cron> | <prompt>
     /// Example: REMINDER: 0 9 * * * | Good morning! Time to check your tasks.
@@ -41,28 +188,12 @@ impl ReminderManager {
         let mut reminders = Vec::new();
 
         for cap in pattern.captures_iter(notes) {
-            let cron_expr = cap.get(1).map(|m| m.as_str().trim()).unwrap_or("");
-            let prompt = cap.get(2).map(|m| m.as_str().trim()).unwrap_or("");
-
-            // Cron crate needs 6 fields (sec min hour dom month dow)
-            // If user gives 5 fields, prepend "0" for seconds
-            let full_cron = if cron_expr.split_whitespace().count() == 5 {
-                format!("0 {}", cron_expr)
-            } else {
-                cron_expr.to_string()
-            };
-
-            match Schedule::from_str(&full_cron) {
-                Ok(schedule) => {
-                    reminders.push(Reminder {
-                        cron_expr: cron_expr.to_string(),
-                        schedule,
-                        prompt: prompt.to_string(),
-                    });
-                }
-                Err(e) => {
-                    tracing::warn!("Invalid cron expression '{}': {}", cron_expr, e);
-                }
+            let cron_expr = cap.get(1).map(|m| m.as_str()).unwrap_or("");
+            let prompt = cap.get(2).map(|m| m.as_str()).unwrap_or("");
+
+            match Reminder::parse(cron_expr, prompt) {
+                Ok(reminder) => reminders.push(reminder),
+                Err(e) => tracing::warn!("Skipping reminder: {}", e),
             }
         }
 
@@ -84,45 +215,143 @@ impl ReminderManager {
         self.reminders.remove(chat_id);
         // Clean up last_fired entries
         let prefix = format!("{}:", chat_id);
+        let before = self.last_fired.len();
         self.last_fired.retain(|k, _| !k.starts_with(&prefix));
+        self.dirty |= self.last_fired.len() != before;
+    }
+
+    /// Replace registered reminders with those in the given contacts' notes and `added`
+    ///
+    /// A contact's chat is its registered individual session, else its phone or
+    /// email. A chat's added reminders come after those from notes. New reminders count from `now` instead of firing straight away for
+    /// occurrences that passed before they were registered. A reminder whose
+    /// schedule is unchanged keeps when it last fired, including one loaded
+    /// from before a restart. Returns the number of chats with reminders.
+    pub fn sync_contacts(
+        &mut self,
+        contacts: &[Contact],
+        added: &BTreeMap<String, Vec<StoredReminder>>,
+        registry: &SessionRegistry,
+        now: DateTime<Utc>,
+    ) -> usize {
+        let mut wanted: HashMap<String, Vec<Reminder>> = HashMap::new();
+        for contact in contacts {
+            let Some(notes) = contact.notes.as_deref() else { continue };
+            let reminders = Self::parse_reminders(notes);
+            if reminders.is_empty() {
+                continue;
+            }
+            let chat_id = registry
+                .all()
+                .values()
+                .find(|d| d.session_type == "individual" && d.contact_name.as_deref() == Some(contact.name.as_str()))
+                .map(|d| d.chat_id.clone())
+                .or_else(|| contact.phone.clone())
+                .or_else(|| contact.email.clone());
+            if let Some(chat_id) = chat_id {
+                wanted.insert(chat_id, reminders);
+            }
+        }
+        for (chat_id, stored) in added {
+            for (position, stored) in stored.iter().enumerate() {
+                match Reminder::parse(&stored.cron, &stored.prompt) {
+                    Ok(reminder) => {
+                        wanted.entry(chat_id.clone()).or_default().push(Reminder { added: Some(position), ..reminder })
+                    }
+                    Err(e) => tracing::warn!("Skipping added reminder for {}: {}", chat_id, e),
+                }
+            }
+        }
+
+        let gone: Vec<String> = self.reminders.keys().filter(|k| !wanted.contains_key(*k)).cloned().collect();
+        for chat_id in gone {
+            self.unregister(&chat_id);
+        }
+
+        for (chat_id, reminders) in wanted {
+            let unchanged = self.reminders.get(&chat_id).is_some_and(|old| {
+                old.len() == reminders.len()
+                    && old
+                        .iter()
+                        .zip(&reminders)
+                        .all(|(a, b)| a.cron_expr == b.cron_expr && a.prompt == b.prompt && a.added == b.added)
+            });
+            if unchanged {
+                continue;
+            }
+            let keys: HashSet<String> = reminders.iter().map(|r| fired_key(&chat_id, &r.cron_expr)).collect();
+            let prefix = format!("{}:", chat_id);
+            self.last_fired.retain(|k, _| !k.starts_with(&prefix) || keys.contains(k));
+            for key in keys {
+                self.last_fired.entry(key).or_insert(now);
+            }
+            self.dirty = true;
+            self.reminders.insert(chat_id, reminders);
+        }
+
+        self.reminders.len()
     }
 
     /// Check for due reminders and return (chat_id, prompt) pairs
+    ///
+    /// Only the reminders returned are marked fired; a contact's other
+    /// reminders keep their own schedules.
     pub fn check_due(&mut self, now: DateTime<Utc>) -> Vec<(String, String)> {
         let mut due = Vec::new();
+        let mut fired = Vec::new();
 
         for (chat_id, reminders) in &self.reminders {
-            for (idx, reminder) in reminders.iter().enumerate() {
-                let key = format!("{}:{}", chat_id, idx);
-
-                // Get last fire time or epoch
-                let last = self
-                    .last_fired
-                    .get(&key)
-                    .copied()
-                    .unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap());
-
+            for reminder in reminders {
                 // Check if there's a scheduled time between last and now
-                if let Some(next) = reminder.schedule.after(&last).next() {
-                    if next <= now {
-                        due.push((chat_id.clone(), reminder.prompt.clone()));
-                        // Update last_fired through mutable reference after loop
-                    }
+                if self.next_fire(chat_id, reminder).is_some_and(|next| next <= now) {
+                    due.push((chat_id.clone(), reminder.prompt.clone()));
+                    fired.push(fired_key(chat_id, &reminder.cron_expr));
                 }
             }
         }
 
-        // Update last_fired for due reminders
-        for (chat_id, _) in &due {
-            for (idx, _) in self.reminders.get(chat_id).unwrap().iter().enumerate() {
-                let key = format!("{}:{}", chat_id, idx);
-                self.last_fired.insert(key, now);
-            }
+        self.dirty |= !fired.is_empty();
+        for key in fired {
+            self.last_fired.insert(key, now);
         }
 
         due
     }
 
+    /// When a chat's reminder is next due: its first occurrence after it last fired
+    ///
+    /// That's already past for one that's overdue, which fires at the next
+    /// check. None if its schedule has no more occurrences.
+    pub fn next_fire(&self, chat_id: &str, reminder: &Reminder) -> Option<DateTime<Utc>> {
+        // Never fired counts from the epoch
+        let last = self
+            .last_fired
+            .get(&fired_key(chat_id, &reminder.cron_expr))
+            .copied()
+            .unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap());
+        reminder.schedule.after(&last).next()
+    }
+
+    /// Every reminder with when it's next due, soonest first, then those never due again
+    pub fn upcoming(&self) -> Vec<Upcoming<'_>> {
+        let mut upcoming: Vec<Upcoming<'_>> = self
+            .reminders
+            .iter()
+            .flat_map(|(chat_id, reminders)| {
+                reminders.iter().enumerate().map(move |(index, reminder)| Upcoming {
+                    chat_id,
+                    index,
+                    reminder,
+                    next: self.next_fire(chat_id, reminder),
+                })
+            })
+            .collect();
+        upcoming.sort_by(|a, b| {
+            (a.next.is_none(), a.next, a.chat_id, a.index).cmp(&(b.next.is_none(), b.next, b.chat_id, b.index))
+        });
+        upcoming
+    }
+
     /// Get all registered reminders
     pub fn all(&self) -> &HashMap<String, Vec<Reminder>> {
         &self.reminders
@@ -144,6 +373,16 @@ impl ReminderManager {
     }
 }
 
+/// A registered reminder and when it's next due
+#[derive(Debug)]
+pub struct Upcoming<'a> {
+    pub chat_id: &'a str,
+    /// Its position among its chat's reminders, from 0
+    pub index: usize,
+    pub reminder: &'a Reminder,
+    pub next: Option<DateTime<Utc>>,
+}
+
 impl Default for ReminderManager {
     fn default() -> Self {
         Self::new()
@@ -271,6 +510,232 @@ More notes here.
         assert_eq!(due.len(), 2);
     }
 
+    /// A contact's 9am reminder firing leaves their 6pm one alone, and neither fires twice
+    #[test]
+    fn test_check_due_reminders_keep_own_schedules() {
+        let mut manager = ReminderManager::new();
+        manager.register("+16175551234", "REMINDER: 0 9 * * * | Morning\nREMINDER: 0 18 * * * | Evening");
+        let at = |day: u32, hour: u32, min: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, min, 0).unwrap();
+        let prompts = |due: Vec<(String, String)>| due.into_iter().map(|(_, prompt)| prompt).collect::<Vec<_>>();
+
+        // Both catch up on first check, then wait for their own times
+        let mut caught_up = prompts(manager.check_due(at(15, 8, 0)));
+        caught_up.sort();
+        assert_eq!(caught_up, ["Evening", "Morning"]);
+
+        // Checked every half hour from 8am to midnight, two days running
+        let mut fired = Vec::new();
+        for day in [15, 16] {
+            for half_hour in 16..48 {
+                let now = at(day, half_hour / 2, half_hour % 2 * 30);
+                fired.extend(prompts(manager.check_due(now)).into_iter().map(|prompt| format!("{} {}", now.hour(), prompt)));
+            }
+        }
+        assert_eq!(fired, ["9 Morning", "18 Evening", "9 Morning", "18 Evening"]);
+
+        // The morning one firing stamps only itself
+        manager.check_due(at(17, 9, 0));
+        assert_eq!(manager.last_fired[&fired_key("+16175551234", "0 9 * * *")], at(17, 9, 0));
+        assert_eq!(manager.last_fired[&fired_key("+16175551234", "0 18 * * *")], at(16, 18, 0));
+        // And the evening one still fires when a check comes late
+        assert_eq!(prompts(manager.check_due(at(17, 23, 0))), ["Evening"]);
+        assert!(manager.check_due(at(17, 23, 30)).is_empty());
+    }
+
+    fn contact(name: &str, phone: &str, notes: Option<&str>) -> Contact {
+        Contact {
+            name: name.to_string(),
+            phone: Some(phone.to_string()),
+            email: None,
+            tier: "family".to_string(),
+            notes: notes.map(str::to_string),
+            system_prompt: None,
+            allowed_tools: None,
+            alias: None,
+            workdir: None,
+            id: None,
+        }
+    }
+
+    #[test]
+    fn test_sync_contacts() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut registry = SessionRegistry::new(&crate::config::Config::for_test(temp.path()));
+        registry
+            .register("jane@example.com", "jane-doe", "/t/jane-doe", "individual", Some("Jane Doe".to_string()), None, None, None)
+            .unwrap();
+        let mut manager = ReminderManager::new();
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 15, 10, 0, 30).unwrap();
+
+        let contacts = vec![
+            contact("John Doe", "+16175551234", Some("REMINDER: * * * * * | Stretch")),
+            contact("Jane Doe", "+16175559876", Some("Likes tea\nREMINDER: 0 9 * * * | Morning")),
+            contact("No Notes", "+16175550000", None),
+        ];
+        assert_eq!(manager.sync_contacts(&contacts, &BTreeMap::new(), &registry, t0), 2);
+        assert!(manager.has_reminders("+16175551234"));
+        // Registered session wins over the phone number
+        assert!(manager.has_reminders("jane@example.com"));
+
+        // Nothing fires for occurrences before the sync
+        assert!(manager.check_due(t0).is_empty());
+        let t1 = Utc.with_ymd_and_hms(2024, 1, 15, 10, 1, 0).unwrap();
+        assert_eq!(manager.check_due(t1), vec![("+16175551234".to_string(), "Stretch".to_string())]);
+
+        // Unchanged notes keep their schedule; edited and removed ones are updated
+        let contacts = vec![
+            contact("John Doe", "+16175551234", Some("REMINDER: * * * * * | Stretch")),
+            contact("Jane Doe", "+16175559876", Some("Likes tea")),
+        ];
+        assert_eq!(manager.sync_contacts(&contacts, &BTreeMap::new(), &registry, t1), 1);
+        assert!(!manager.has_reminders("jane@example.com"));
+        assert!(manager.check_due(t1).is_empty());
+    }
+
+    /// A restart in the evening doesn't fire the morning's reminder again
+    #[test]
+    fn test_fire_times_survive_restart() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = crate::config::Config::for_test(temp.path());
+        let registry = SessionRegistry::new(&config);
+        let contacts = vec![contact("John Doe", "+16175551234", Some("REMINDER: 0 9 * * * | Good morning"))];
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+
+        let mut before = ReminderManager::with_config(&config);
+        before.load().unwrap();
+        before.sync_contacts(&contacts, &BTreeMap::new(), &registry, at(15, 8));
+        assert_eq!(before.check_due(at(15, 9)).len(), 1);
+        assert!(before.is_dirty());
+        before.flush().unwrap();
+        assert!(!before.is_dirty());
+
+        let mut after = ReminderManager::with_config(&config);
+        after.load().unwrap();
+        after.sync_contacts(&contacts, &BTreeMap::new(), &registry, at(15, 23));
+        assert!(after.check_due(at(15, 23)).is_empty());
+        assert_eq!(after.check_due(at(16, 9)).len(), 1);
+
+        // Kept in memory only, nothing is saved
+        let mut memory = ReminderManager::new();
+        memory.sync_contacts(&contacts, &BTreeMap::new(), &registry, at(15, 8));
+        memory.check_due(at(15, 9));
+        memory.flush().unwrap();
+        fs::remove_file(&config.reminders_fired_file).unwrap();
+        memory.flush().unwrap();
+        assert!(!config.reminders_fired_file.exists());
+
+        fs::write(&config.reminders_fired_file, "{").unwrap();
+        assert!(ReminderManager::with_config(&config).load().is_err());
+    }
+
+    /// Adding a reminder above another doesn't reset the other's fire time
+    #[test]
+    fn test_fire_times_keyed_by_schedule() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let registry = SessionRegistry::new(&crate::config::Config::for_test(temp.path()));
+        let mut manager = ReminderManager::new();
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        let evening = contact("John Doe", "+16175551234", Some("REMINDER: 0 18 * * * | Evening"));
+        manager.sync_contacts(&[evening], &BTreeMap::new(), &registry, at(15, 8));
+        assert_eq!(manager.check_due(at(15, 18)).len(), 1);
+
+        let both = contact("John Doe", "+16175551234", Some("REMINDER: 0 9 * * * | Morning\nREMINDER: 0 18 * * * | Evening"));
+        manager.sync_contacts(&[both], &BTreeMap::new(), &registry, at(15, 19));
+        assert_eq!(manager.last_fired[&fired_key("+16175551234", "0 18 * * *")], at(15, 18));
+        assert_eq!(manager.last_fired[&fired_key("+16175551234", "0 9 * * *")], at(15, 19));
+        assert!(manager.check_due(at(15, 20)).is_empty());
+        assert_eq!(manager.check_due(at(16, 9)), vec![("+16175551234".to_string(), "Morning".to_string())]);
+
+        // A schedule taken out of the notes takes its fire time with it
+        let morning = contact("John Doe", "+16175551234", Some("REMINDER: 0 9 * * * | Morning"));
+        manager.sync_contacts(&[morning], &BTreeMap::new(), &registry, at(16, 10));
+        assert_eq!(manager.last_fired.len(), 1);
+        assert_ne!(fired_key("+16175551234", "0 9 * * *"), fired_key("+16175551234", "0 18 * * *"));
+    }
+
+    #[test]
+    fn test_store_add_and_remove() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = crate::config::Config::for_test(temp.path());
+        let mut store = ReminderStore::load(&config).unwrap();
+        assert!(store.reminders().is_empty() && store.modified().is_none());
+        assert_eq!(store.add("+16175551234", " 0 9 * * * ", " Morning ").unwrap(), 0);
+        assert_eq!(store.add("+16175551234", "0 18 * * *", "Evening").unwrap(), 1);
+        assert_eq!(store.add("jane@example.com", "0 0 1 * *", "Rent").unwrap(), 0);
+        assert!(store.add("+16175551234", "0 25 * * *", "Never").is_err());
+        store.save().unwrap();
+
+        let mut loaded = ReminderStore::load(&config).unwrap();
+        assert!(loaded.modified().is_some());
+        let john = &loaded.reminders()["+16175551234"];
+        assert_eq!(john[0], StoredReminder { cron: "0 9 * * *".to_string(), prompt: "Morning".to_string() });
+        assert_eq!(john.len(), 2);
+        assert_eq!(loaded.remove("+16175551234", 0).unwrap().prompt, "Morning");
+        assert_eq!(loaded.remove("+16175551234", 1), None);
+        assert_eq!(loaded.remove("+16175559999", 0), None);
+        assert_eq!(loaded.remove("jane@example.com", 0).unwrap().prompt, "Rent");
+        assert_eq!(loaded.reminders().keys().collect::<Vec<_>>(), ["+16175551234"]);
+
+        fs::write(&config.reminders_file, "[").unwrap();
+        assert!(ReminderStore::load(&config).is_err());
+    }
+
+    /// Added reminders follow the notes' ones, and fire on their own schedules
+    #[test]
+    fn test_sync_added_reminders() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let registry = SessionRegistry::new(&crate::config::Config::for_test(temp.path()));
+        let mut manager = ReminderManager::new();
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        let contacts = vec![contact("John Doe", "+16175551234", Some("REMINDER: 0 9 * * * | Morning"))];
+        let stored = |cron: &str, prompt: &str| StoredReminder { cron: cron.to_string(), prompt: prompt.to_string() };
+        let mut added = BTreeMap::new();
+        added.insert("+16175551234".to_string(), vec![stored("bad cron", "Skipped"), stored("0 18 * * *", "Evening")]);
+        added.insert("+16175552222".to_string(), vec![stored("0 12 * * *", "Lunch")]);
+
+        assert_eq!(manager.sync_contacts(&contacts, &added, &registry, at(15, 10)), 2);
+        let john: Vec<(&str, Option<usize>)> =
+            manager.get("+16175551234").unwrap().iter().map(|r| (r.prompt.as_str(), r.added)).collect();
+        assert_eq!(john, [("Morning", None), ("Evening", Some(1))]);
+        assert_eq!(manager.check_due(at(15, 12)), vec![("+16175552222".to_string(), "Lunch".to_string())]);
+
+        // Removing one is picked up at the next sync
+        added.remove("+16175552222");
+        assert_eq!(manager.sync_contacts(&contacts, &added, &registry, at(15, 13)), 1);
+        assert_eq!(manager.check_due(at(15, 18)), vec![("+16175551234".to_string(), "Evening".to_string())]);
+    }
+
+    #[test]
+    fn test_next_fire() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let registry = SessionRegistry::new(&crate::config::Config::for_test(temp.path()));
+        let mut manager = ReminderManager::new();
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        let notes = "REMINDER: 0 18 * * * | Evening\nREMINDER: 0 9 * * * | Morning\nREMINDER: 0 0 9 1 1 * 2023 | Long gone";
+        let contacts = vec![contact("John Doe", "+16175551234", Some(notes)), contact("Jane Doe", "+16175559876", Some("REMINDER: 0 12 * * * | Lunch"))];
+        manager.sync_contacts(&contacts, &BTreeMap::new(), &registry, at(15, 10));
+
+        let upcoming: Vec<(&str, usize, Option<DateTime<Utc>>)> =
+            manager.upcoming().iter().map(|u| (u.reminder.prompt.as_str(), u.index, u.next)).collect();
+        assert_eq!(
+            upcoming,
+            [("Lunch", 0, Some(at(15, 12))), ("Evening", 0, Some(at(15, 18))), ("Morning", 1, Some(at(16, 9))), ("Long gone", 2, None)]
+        );
+
+        // What's due by a check is what check_due fires, a missed lunch included
+        let evening = manager.get("+16175551234").unwrap()[0].clone();
+        let lunch = manager.get("+16175559876").unwrap()[0].clone();
+        let due_by = |manager: &ReminderManager, now| manager.upcoming().iter().filter(|u| u.next.is_some_and(|next| next <= now)).count();
+        assert_eq!(due_by(&manager, at(15, 18)), 2);
+        assert_eq!(manager.check_due(at(15, 18)).len(), 2);
+        // Then each counts from when it fired
+        assert_eq!(manager.next_fire("+16175551234", &evening), Some(at(16, 18)));
+        assert_eq!(manager.next_fire("+16175559876", &lunch), Some(at(16, 12)));
+        assert_eq!(due_by(&manager, at(15, 23)), 0);
+        // Never synced, so counted from the epoch
+        assert_eq!(manager.next_fire("+16175550000", &lunch), Some(Utc.with_ymd_and_hms(1970, 1, 1, 12, 0, 0).unwrap()));
+    }
+
     #[test]
     fn test_count() {
         let mut manager = ReminderManager::new();
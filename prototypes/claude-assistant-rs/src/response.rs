@@ -0,0 +1,143 @@
+//! Reading Claude's reply to an injected prompt out of its pane
+//!
+//! A snapshot of the pane is taken before injecting. The pane is then polled
+//! until Claude is idle and the output has stopped changing, and whatever is
+//! new relative to the snapshot is the reply.
+
+use crate::health::is_busy_content;
+use std::time::{Duration, Instant};
+
+/// Lines of the pane after `before` that weren't in it, with the input box and footer left off
+///
+/// The pane may have scrolled in between, so `before` is lined up against
+/// `after` at whichever offset matches the most lines. The lines both end with
+/// (Claude's input box) are dropped.
+pub fn pane_diff(before: &str, after: &str) -> String {
+    let before: Vec<&str> = trim_blank_tail(before.lines().collect());
+    let after: Vec<&str> = trim_blank_tail(after.lines().collect());
+
+    // Longest run of `after`'s first lines found in `before`, and where
+    let (offset, common) = (0..before.len())
+        .map(|offset| {
+            let common = before[offset..].iter().zip(&after).take_while(|(b, a)| b == a).count();
+            (offset, common)
+        })
+        .max_by_key(|&(offset, common)| (common, std::cmp::Reverse(offset)))
+        .unwrap_or((0, 0));
+
+    // What the old tail and the new tail share is the input box, not the reply
+    let old_tail = &before[(offset + common).min(before.len())..];
+    let new_tail = &after[common..];
+    let footer = old_tail.iter().rev().zip(new_tail.iter().rev()).take_while(|(b, a)| b == a).count();
+
+    let new = trim_blank_tail(new_tail[..new_tail.len() - footer].to_vec());
+    let start = new.iter().position(|line| !line.trim().is_empty()).unwrap_or(new.len());
+    new[start..].join("\n")
+}
+
+fn trim_blank_tail(mut lines: Vec<&str>) -> Vec<&str> {
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Decides when a pane being polled has settled
+///
+/// Settled means it has changed from the snapshot, Claude isn't busy, and the
+/// content has been the same for `quiet`.
+pub struct Settle {
+    snapshot: String,
+    last: Option<String>,
+    unchanged_since: Instant,
+    quiet: Duration,
+}
+
+impl Settle {
+    pub fn new(snapshot: &str, quiet: Duration, now: Instant) -> Self {
+        Self {
+            snapshot: snapshot.to_string(),
+            last: None,
+            unchanged_since: now,
+            quiet,
+        }
+    }
+
+    /// Record a capture, returning whether the pane has settled
+    pub fn observe(&mut self, pane: &str, now: Instant) -> bool {
+        if self.last.as_deref() != Some(pane) {
+            self.last = Some(pane.to_string());
+            self.unchanged_since = now;
+        }
+        pane != self.snapshot
+            && !is_busy_content(pane)
+            && now.saturating_duration_since(self.unchanged_since) >= self.quiet
+    }
+
+    /// The latest capture, or the snapshot if nothing has been seen yet
+    pub fn latest(&self) -> &str {
+        self.last.as_deref().unwrap_or(&self.snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE: &str = "\
+● Earlier answer
+  more of it
+
+╭──────────────────╮
+│ >                │
+╰──────────────────╯
+  ? for shortcuts
+";
+
+    const AFTER: &str = "\
+● Earlier answer
+  more of it
+
+> what's the capital of France?
+
+● Paris.
+
+╭──────────────────╮
+│ >                │
+╰──────────────────╯
+  ? for shortcuts
+
+";
+
+    #[test]
+    fn test_pane_diff() {
+        assert_eq!(pane_diff(BEFORE, AFTER), "> what's the capital of France?\n\n● Paris.");
+        assert_eq!(pane_diff(BEFORE, BEFORE), "");
+        assert_eq!(pane_diff("", "● Hi\n"), "● Hi");
+    }
+
+    /// Scrolled so the snapshot's first line is gone
+    #[test]
+    fn test_pane_diff_after_scroll() {
+        let scrolled = AFTER.lines().skip(1).collect::<Vec<_>>().join("\n");
+        assert_eq!(pane_diff(BEFORE, &scrolled), "> what's the capital of France?\n\n● Paris.");
+    }
+
+    #[test]
+    fn test_settle() {
+        let start = Instant::now();
+        let quiet = Duration::from_secs(2);
+        let busy = AFTER.replace("● Paris.", "✻ Thinking… (esc to interrupt)");
+        let mut settle = Settle::new(BEFORE, quiet, start);
+
+        // Nothing has happened yet
+        assert!(!settle.observe(BEFORE, start + Duration::from_secs(5)));
+        assert!(!settle.observe(&busy, start + Duration::from_secs(6)));
+        assert!(!settle.observe(&busy, start + Duration::from_secs(9)));
+        // Idle, but not for long enough
+        assert!(!settle.observe(AFTER, start + Duration::from_secs(10)));
+        assert!(!settle.observe(AFTER, start + Duration::from_secs(11)));
+        assert!(settle.observe(AFTER, start + Duration::from_secs(12)));
+        assert_eq!(settle.latest(), AFTER);
+    }
+}
@@ -0,0 +1,378 @@
+//! `FakeTmux`, an in-memory tmux for tests
+
+use super::CommandRunner;
+use crate::process::PS_ARGS;
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{ExitStatus, Output};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What a new session's pane shows: Claude's input box, ready for a message
+pub const FAKE_READY_PANE: &str = "╭──────────────────╮\n│ >                │\n╰──────────────────╯\n  ? for shortcuts\n";
+
+/// Version `FakeTmux` answers `claude --version` with
+pub const FAKE_CLAUDE_VERSION: &str = "1.0.58 (Claude Code)";
+
+/// An in-memory tmux server
+///
+/// Sessions appear with `new-session` and go with `kill-session`; each has a
+/// pane whose content tests set, and text typed with `send-keys -l` lands
+/// above its input box. A subcommand can be scripted to fail, or a session to
+/// hang, start slowly, or exit at once. Every call is recorded, without the
+/// `-L` socket arguments. It answers `ps` too, with the process table tests set.
+#[derive(Default)]
+pub struct FakeTmux {
+    state: Mutex<FakeState>,
+    /// Sessions whose next call blocks until told to go on
+    hung: Mutex<HashMap<String, Receiver<()>>>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    panes: HashMap<String, FakePane>,
+    /// What new sessions show, if not `FAKE_READY_PANE`
+    startup_pane: Option<String>,
+    failures: HashMap<String, String>,
+    calls: Vec<Vec<String>>,
+    /// What `ps` prints
+    processes: String,
+    /// `new-session` commands with one of these in them start a Claude that exits at once
+    exits_on_start: Vec<String>,
+    /// How long `new-session` takes, by session
+    start_delays: HashMap<String, Duration>,
+}
+
+#[derive(Clone, Default)]
+struct FakePane {
+    content: String,
+    title: String,
+    piped: bool,
+    /// Whether typed text is lost instead of showing
+    deaf: bool,
+    /// What the pane shows once something is typed into it
+    answer: Option<String>,
+    /// Process the pane runs, as `list-panes` reports it
+    pid: Option<u32>,
+    /// Unix seconds
+    created: u64,
+}
+
+impl FakePane {
+    fn new(content: String) -> Self {
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        Self { content, created, ..Self::default() }
+    }
+}
+
+impl FakeTmux {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a session showing `content`, as if it had been created earlier
+    pub fn add_session(&self, session_name: &str, content: &str) {
+        self.state().panes.insert(session_name.to_string(), FakePane::new(content.to_string()));
+    }
+
+    /// Make sessions created from now on show `content` instead of a ready input box
+    pub fn set_startup_pane(&self, content: &str) {
+        self.state().startup_pane = Some(content.to_string());
+    }
+
+    /// Change what a session's pane shows
+    pub fn set_pane(&self, session_name: &str, content: &str) {
+        if let Some(pane) = self.state().panes.get_mut(session_name) {
+            pane.content = content.to_string();
+        }
+    }
+
+    /// Lose whatever is typed into a session from now on, as a paste Claude never took
+    pub fn drop_typing(&self, session_name: &str) {
+        if let Some(pane) = self.state().panes.get_mut(session_name) {
+            pane.deaf = true;
+        }
+    }
+
+    /// Make the pane show `content` once anything is typed into the session, as Claude answering
+    pub fn answer_typing(&self, session_name: &str, content: &str) {
+        if let Some(pane) = self.state().panes.get_mut(session_name) {
+            pane.answer = Some(content.to_string());
+        }
+    }
+
+    /// Make sessions whose command contains `fragment` end as soon as they start, as a Claude that exits
+    pub fn exit_on_start(&self, fragment: &str) {
+        self.state().exits_on_start.push(fragment.to_string());
+    }
+
+    /// Make creating a session take `delay`, as a slow tmux would
+    pub fn delay_start(&self, session_name: &str, delay: Duration) {
+        self.state().start_delays.insert(session_name.to_string(), delay);
+    }
+
+    /// Give a session's pane a process id, to find it in `set_processes`
+    pub fn set_pid(&self, session_name: &str, pid: u32) {
+        if let Some(pane) = self.state().panes.get_mut(session_name) {
+            pane.pid = Some(pid);
+        }
+    }
+
+    /// Make `ps` print `rows`, as `pid ppid rss_kb cpu` lines
+    pub fn set_processes(&self, rows: &str) {
+        self.state().processes = rows.to_string();
+    }
+
+    /// End a session as if its process had exited
+    pub fn end_session(&self, session_name: &str) {
+        self.state().panes.remove(session_name);
+    }
+
+    /// Make the next call about a session block until the returned sender sends or is dropped, as a wedged tmux would
+    pub fn hang(&self, session_name: &str) -> Sender<()> {
+        let (release, wait) = mpsc::channel();
+        self.hung.lock().unwrap_or_else(|e| e.into_inner()).insert(session_name.to_string(), wait);
+        release
+    }
+
+    /// Make every later call to `subcommand` fail with `stderr`
+    pub fn fail(&self, subcommand: &str, stderr: &str) {
+        self.state().failures.insert(subcommand.to_string(), stderr.to_string());
+    }
+
+    /// Names of the running sessions, sorted
+    pub fn sessions(&self) -> Vec<String> {
+        let mut sessions: Vec<String> = self.state().panes.keys().cloned().collect();
+        sessions.sort();
+        sessions
+    }
+
+    pub fn pane(&self, session_name: &str) -> Option<String> {
+        self.state().panes.get(session_name).map(|p| p.content.clone())
+    }
+
+    pub fn title(&self, session_name: &str) -> Option<String> {
+        self.state().panes.get(session_name).map(|p| p.title.clone())
+    }
+
+    /// Every call so far
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.state().calls.clone()
+    }
+
+    /// Calls of one subcommand, e.g. "send-keys"
+    pub fn calls_to(&self, subcommand: &str) -> Vec<Vec<String>> {
+        self.calls().into_iter().filter(|call| call.first().is_some_and(|c| c == subcommand)).collect()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CommandRunner for FakeTmux {
+    fn run(&self, program: &Path, args: &[String]) -> io::Result<Output> {
+        if args == ["--version"] {
+            return Ok(output(true, FAKE_CLAUDE_VERSION, ""));
+        }
+        if args == PS_ARGS {
+            return Ok(output(true, &self.state().processes, ""));
+        }
+        let args = match args {
+            [flag, _socket, rest @ ..] if flag == "-L" => rest,
+            _ => args,
+        };
+        let hung = args.iter().position(|a| a == "-t").and_then(|i| {
+            let target = args.get(i + 1)?.trim_start_matches('=').trim_end_matches(':');
+            self.hung.lock().unwrap_or_else(|e| e.into_inner()).remove(target)
+        });
+        if let Some(wait) = hung {
+            let _ = wait.recv();
+        }
+        if args.first().is_some_and(|a| a == "new-session") {
+            let name = args.iter().position(|a| a == "-s").and_then(|i| args.get(i + 1));
+            let delay = name.and_then(|name| self.state().start_delays.get(name).copied());
+            if let Some(delay) = delay {
+                std::thread::sleep(delay);
+            }
+        }
+        let mut state = self.state();
+        state.calls.push(args.to_vec());
+
+        let Some(subcommand) = args.first() else {
+            return Ok(output(false, "", "usage: tmux command"));
+        };
+        if let Some(stderr) = state.failures.get(subcommand) {
+            return Ok(output(false, "", stderr));
+        }
+        let flag = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1));
+        // "=name" and "=name:" target the session exactly; send-keys uses the bare name
+        let target = flag("-t").map(|t| t.trim_start_matches('=').trim_end_matches(':').to_string());
+        let missing = |name: &str| Ok(output(false, "", &format!("can't find session: {}", name)));
+
+        match subcommand.as_str() {
+            "new-session" => {
+                let Some(name) = flag("-s").cloned() else {
+                    return Ok(output(false, "", "no session name"));
+                };
+                if state.panes.contains_key(&name) {
+                    return Ok(output(false, "", &format!("duplicate session: {}", name)));
+                }
+                if state.exits_on_start.iter().any(|fragment| args.iter().any(|a| a.contains(fragment.as_str()))) {
+                    return Ok(output(true, "", ""));
+                }
+                let content = state.startup_pane.clone().unwrap_or_else(|| FAKE_READY_PANE.to_string());
+                state.panes.insert(name, FakePane::new(content));
+                Ok(output(true, "", ""))
+            }
+            "list-sessions" => {
+                if state.panes.is_empty() {
+                    return Ok(output(false, "", &format!("no server running on {}", program.display())));
+                }
+                let mut names: Vec<&String> = state.panes.keys().collect();
+                names.sort();
+                let listed: String = names.iter().map(|name| format!("{}\n", name)).collect();
+                Ok(output(true, &listed, ""))
+            }
+            _ => {
+                let name = target.unwrap_or_default();
+                let Some(pane) = state.panes.get_mut(&name) else {
+                    return missing(&name);
+                };
+                match subcommand.as_str() {
+                    "kill-session" => {
+                        state.panes.remove(&name);
+                    }
+                    "capture-pane" => return Ok(output(true, &pane.content, "")),
+                    "list-panes" => {
+                        let pid = pane.pid.map(|pid| format!("{}\n", pid)).unwrap_or_default();
+                        return Ok(output(true, &pid, ""));
+                    }
+                    "send-keys" if flag("-l").is_some() && !pane.deaf => {
+                        let text = args.last().map(String::as_str).unwrap_or_default();
+                        pane.content = match pane.answer.take() {
+                            Some(answer) => answer,
+                            None => typed_into(&pane.content, text),
+                        };
+                    }
+                    "display-message" => {
+                        let shown = match args.last().map(String::as_str) {
+                            Some("#{pane_pipe}") => if pane.piped { "1" } else { "0" }.to_string(),
+                            Some("#{pane_title}") => pane.title.clone(),
+                            Some("#{session_created}") => pane.created.to_string(),
+                            _ => String::new(),
+                        };
+                        return Ok(output(true, &format!("{}\n", shown), ""));
+                    }
+                    "pipe-pane" => pane.piped = true,
+                    "select-pane" => {
+                        if let Some(title) = flag("-T") {
+                            pane.title = title.clone();
+                        }
+                    }
+                    _ => {}
+                }
+                Ok(output(true, "", ""))
+            }
+        }
+    }
+}
+
+/// A pane after `text` is typed: it shows above the input box, as Claude echoes a prompt
+fn typed_into(content: &str, text: &str) -> String {
+    let typed = format!("> {}\n", text);
+    match content.find('╭') {
+        Some(i) => format!("{}{}{}", &content[..i], typed, &content[i..]),
+        None => format!("{}{}", content, typed),
+    }
+}
+
+fn output(success: bool, stdout: &str, stderr: &str) -> Output {
+    Output {
+        status: ExitStatus::from_raw(if success { 0 } else { 1 << 8 }),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: stderr.as_bytes().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmux(fake: &FakeTmux, args: &[&str]) -> Output {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        fake.run(Path::new("tmux"), &args).unwrap()
+    }
+
+    #[test]
+    fn test_fake_tmux_sessions() {
+        let fake = FakeTmux::new();
+        assert!(!tmux(&fake, &["list-sessions", "-F", "#{session_name}"]).status.success());
+        assert!(tmux(&fake, &["-L", "test", "new-session", "-d", "-s", "jane-doe", "claude"]).status.success());
+        assert!(!tmux(&fake, &["new-session", "-d", "-s", "jane-doe", "claude"]).status.success());
+        assert!(tmux(&fake, &["has-session", "-t", "=jane-doe"]).status.success());
+        assert_eq!(tmux(&fake, &["list-sessions", "-F", "#{session_name}"]).stdout, b"jane-doe\n");
+
+        assert!(tmux(&fake, &["kill-session", "-t", "=jane-doe"]).status.success());
+        assert!(!tmux(&fake, &["has-session", "-t", "=jane-doe"]).status.success());
+        // Recorded without the socket
+        assert_eq!(fake.calls()[1][..2], ["new-session", "-d"]);
+        assert_eq!(fake.calls_to("has-session").len(), 2);
+    }
+
+    #[test]
+    fn test_fake_tmux_panes() {
+        let fake = FakeTmux::new();
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+        tmux(&fake, &["send-keys", "-t", "jane-doe", "-l", "--", "hello"]);
+        let pane = fake.pane("jane-doe").unwrap();
+        assert!(pane.starts_with("> hello\n╭"));
+        assert_eq!(tmux(&fake, &["capture-pane", "-t", "=jane-doe", "-p"]).stdout, pane.as_bytes());
+
+        assert_eq!(tmux(&fake, &["display-message", "-p", "-t", "=jane-doe:", "#{pane_pipe}"]).stdout, b"0\n");
+        tmux(&fake, &["pipe-pane", "-t", "=jane-doe:", "-o", "cat >> log"]);
+        assert_eq!(tmux(&fake, &["display-message", "-p", "-t", "=jane-doe:", "#{pane_pipe}"]).stdout, b"1\n");
+        tmux(&fake, &["select-pane", "-t", "=jane-doe:", "-T", "Jane Doe"]);
+        assert_eq!(fake.title("jane-doe").as_deref(), Some("Jane Doe"));
+        let created = tmux(&fake, &["display-message", "-p", "-t", "=jane-doe:", "#{session_created}"]).stdout;
+        assert!(String::from_utf8(created).unwrap().trim().parse::<u64>().unwrap() > 1_700_000_000);
+
+        fake.set_pid("jane-doe", 500);
+        assert_eq!(tmux(&fake, &["list-panes", "-t", "=jane-doe:", "-F", "#{pane_pid}"]).stdout, b"500\n");
+        fake.set_processes("  500 410 3100 0.0\n");
+        let ps = fake.run(Path::new("/bin/ps"), &PS_ARGS.map(String::from)).unwrap();
+        assert_eq!(ps.stdout, b"  500 410 3100 0.0\n");
+    }
+
+    #[test]
+    fn test_fake_tmux_failures() {
+        let fake = FakeTmux::new();
+        fake.add_session("jane-doe", "");
+        fake.fail("send-keys", "server exited");
+        let output = tmux(&fake, &["send-keys", "-t", "jane-doe", "Enter"]);
+        assert!(!output.status.success());
+        assert_eq!(output.stderr, b"server exited");
+        assert!(!tmux(&fake, &["capture-pane", "-t", "=john-doe", "-p"]).status.success());
+    }
+
+    #[test]
+    fn test_fake_tmux_scripted_sessions() {
+        let fake = FakeTmux::new();
+        fake.exit_on_start("--resume gone");
+        assert!(tmux(&fake, &["new-session", "-d", "-s", "jane-doe", "claude --resume gone"]).status.success());
+        assert!(fake.sessions().is_empty());
+
+        fake.delay_start("jane-doe", Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        tmux(&fake, &["new-session", "-d", "-s", "jane-doe", "claude"]);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        fake.set_pane("jane-doe", "esc to interrupt\n");
+        fake.answer_typing("jane-doe", FAKE_READY_PANE);
+        tmux(&fake, &["send-keys", "-t", "jane-doe", "-l", "--", "wrap up"]);
+        assert_eq!(fake.pane("jane-doe").as_deref(), Some(FAKE_READY_PANE));
+    }
+}
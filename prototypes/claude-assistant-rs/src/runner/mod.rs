@@ -0,0 +1,100 @@
+//! Running tmux and claude
+//!
+//! `SessionManager` runs every external command through a `CommandRunner`.
+//! The daemon uses `SystemRunner`; tests use `FakeTmux`, an in-memory tmux
+//! that records what it was asked to do, so session logic can be tested
+//! without a tmux server or a real Claude. The fake is built for this crate's
+//! tests, and for others with the `test-support` feature.
+
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+#[cfg(any(test, feature = "test-support"))]
+mod fake;
+#[cfg(any(test, feature = "test-support"))]
+pub use fake::{FakeTmux, FAKE_CLAUDE_VERSION, FAKE_READY_PANE};
+
+/// Runs a program to completion, capturing its output
+pub trait CommandRunner: Send + Sync {
+    fn run(&self, program: &Path, args: &[String]) -> io::Result<Output>;
+
+    /// Like `run`, but a program still going after `timeout` is killed and a `TimedOut` error returned
+    fn run_timeout(&self, program: &Path, args: &[String], timeout: Duration) -> io::Result<Output> {
+        let _ = timeout;
+        self.run(program, args)
+    }
+}
+
+/// Runs commands for real
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &Path, args: &[String]) -> io::Result<Output> {
+        Command::new(program).args(args).output()
+    }
+
+    fn run_timeout(&self, program: &Path, args: &[String], timeout: Duration) -> io::Result<Output> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Read as it runs, so a full pipe can't stall it
+        let (stdout, stderr) = (read_all(child.stdout.take()), read_all(child.stderr.take()));
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} didn't finish within {:?}", program.display(), timeout),
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        };
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+/// Everything a child's pipe gives until it closes, read on its own thread
+fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_runner_timeout() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let output = SystemRunner.run_timeout(Path::new("/bin/sh"), &args(&["-c", "echo out; echo err >&2"]), Duration::from_secs(5));
+        let output = output.unwrap();
+        assert!(output.status.success());
+        assert_eq!((output.stdout.as_slice(), output.stderr.as_slice()), (&b"out\n"[..], &b"err\n"[..]));
+
+        // Killed rather than waited on
+        let start = Instant::now();
+        let hung = SystemRunner.run_timeout(Path::new("/bin/sleep"), &args(&["10"]), Duration::from_millis(100));
+        assert_eq!(hung.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
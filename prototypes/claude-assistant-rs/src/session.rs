@@ -2,50 +2,410 @@
 //!
 //! Create, kill, and interact with tmux sessions running Claude.
 
-use crate::config::Config;
+use crate::claude_cli::{detect_flags, ClaudeFlags};
+use crate::config::{Config, TierConfig, QUARANTINE_TIER};
+use crate::contacts::Contact;
 use crate::error::{Error, Result};
-use crate::health::{check_session_content, HealthStatus, UnhealthyReason};
+use crate::health::{is_busy_content, is_ready_content, HealthCheckOptions, HealthPatterns, HealthStatus, UnhealthyReason};
+use crate::response::{pane_diff, Settle};
+use crate::process::{process_tree, process_usage, ProcessRow, ProcessUsage};
+use crate::registry::write_json_atomic;
+use crate::runner::{CommandRunner, SystemRunner};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
-use std::time::Duration;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Pause before reading back an injection, so Claude has drawn it
+const VERIFY_DELAY: Duration = Duration::from_millis(300);
+
+/// Pane lines searched for an injection
+const VERIFY_LINES: u32 = 50;
+
+/// File in a transcript dir saying who its session is for
+pub const SESSION_INFO_FILE: &str = "session-info.json";
+
+/// Subdirectory of a transcript dir holding scrollback saved from killed sessions
+pub const PANE_ARCHIVE_DIR: &str = "pane-archives";
+
+/// Subdirectory of the logs dir holding everything each session has printed
+pub const SESSION_LOG_DIR: &str = "sessions";
+
+/// Most bytes typed by one `send-keys`; much more and its argv can fail with E2BIG
+const SEND_CHUNK_BYTES: usize = 4 * 1024;
+
+/// Pause between chunks of a long text, so tmux keeps them in order
+const SEND_CHUNK_DELAY: Duration = Duration::from_millis(20);
+
+/// Pane lines compared when waiting for a response
+const RESPONSE_PANE_LINES: u32 = 2000;
+
+/// How often the pane is read while waiting for a response
+const RESPONSE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the pane must sit unchanged for a response to count as finished
+const RESPONSE_QUIET: Duration = Duration::from_secs(2);
+
+/// Sent to a busy Claude before its session is killed
+const WRAP_UP_MESSAGE: &str = "Please finish your current step and stop. This session is about to be shut down.";
+
+/// How often a session being wrapped up is checked for still being busy
+const WRAP_UP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a starting session's pane is checked for Claude's input box
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Where a dry run says what it would have done
+pub type DryRunOutput = Arc<Mutex<dyn Write + Send>>;
+
+/// Who a session is for, exported into its environment and saved as `session-info.json`
+///
+/// Skills read this instead of parsing the SMS wrapper.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub chat_id: String,
+    pub tier: String,
+    /// Contact the session is with, or who started it for a group
+    pub contact: String,
+    pub created_at: DateTime<Utc>,
+    /// Where Claude runs, if not the transcript dir
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workdir: Option<PathBuf>,
+    /// Name of the group, for a group session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl SessionInfo {
+    pub fn new(chat_id: &str, tier: &str, contact: &str) -> Self {
+        Self {
+            chat_id: chat_id.to_string(),
+            tier: tier.to_string(),
+            contact: contact.to_string(),
+            created_at: Utc::now(),
+            workdir: None,
+            group: None,
+        }
+    }
+
+    /// What the session's window and pane are titled: the group, or else the contact
+    pub fn title(&self) -> &str {
+        self.group.as_deref().unwrap_or(&self.contact)
+    }
+
+    /// Read `session-info.json` from a transcript dir
+    pub fn load(transcript_dir: &Path) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(transcript_dir.join(SESSION_INFO_FILE)).ok()?).ok()
+    }
+
+    /// Environment variables set in the session
+    pub fn env(&self) -> [(&'static str, &str); 3] {
+        [
+            ("CLAUDE_ASSISTANT_CHAT_ID", &self.chat_id),
+            ("CLAUDE_ASSISTANT_TIER", &self.tier),
+            ("CLAUDE_ASSISTANT_CONTACT", &self.contact),
+        ]
+    }
+
+    /// Write `session-info.json` into a transcript dir
+    pub fn save(&self, transcript_dir: &Path) -> Result<()> {
+        write_json_atomic(&transcript_dir.join(SESSION_INFO_FILE), self)
+    }
+}
+
+/// How to stop a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KillMode {
+    /// Kill it at once
+    Now,
+    /// Ask a busy Claude to wrap up first, waiting up to `kill_grace_secs`
+    Graceful,
+}
+
+impl KillMode {
+    /// How to stop an unhealthy session: a crashed or hung Claude can't wrap up, so gets no grace
+    pub fn for_unhealthy(reason: &UnhealthyReason) -> Self {
+        match reason {
+            UnhealthyReason::FatalError(_) | UnhealthyReason::Stuck => KillMode::Now,
+            _ => KillMode::Graceful,
+        }
+    }
+}
+
+/// Whether to ask Claude to wrap up before killing; only a busy Claude has anything to finish
+fn needs_wrap_up(mode: KillMode, grace: Duration, busy: bool) -> bool {
+    mode == KillMode::Graceful && !grace.is_zero() && busy
+}
 
 /// Manager for tmux sessions
 pub struct SessionManager {
+    runner: Arc<dyn CommandRunner>,
     tmux: std::path::PathBuf,
+    socket: String,
     claude: std::path::PathBuf,
     transcripts_dir: std::path::PathBuf,
+    ps: PathBuf,
+    /// A tmux call taking longer than this is killed
+    tmux_timeout: Duration,
+    /// How the installed claude spells its flags, asked the first time a session starts
+    claude_flags: OnceLock<ClaudeFlags>,
+    max_inject_bytes: usize,
+    tiers: Vec<TierConfig>,
+    health_patterns: HealthPatterns,
+    ready_timeout: Duration,
+    verify_injections: bool,
+    pane_archive_keep: usize,
+    kill_grace: Duration,
+    logs_dir: PathBuf,
+    session_log_max_bytes: u64,
+    session_log_keep: usize,
+    session_cache_ttl: Duration,
+    session_cache: Mutex<SessionCache>,
+    /// Set for a dry run: sessions are only read, and changes are described here instead
+    dry_run: Option<DryRunOutput>,
+}
+
+/// What tmux recently said about which sessions exist
+///
+/// Every create and kill forgets the session and bumps `generation`, and an
+/// answer is only kept if no create or kill happened while tmux was being
+/// asked, so a session is never reported as still there after being killed.
+#[derive(Default)]
+struct SessionCache {
+    exists: HashMap<String, (Instant, bool)>,
+    listed: Option<(Instant, Vec<String>)>,
+    generation: u64,
+}
+
+impl SessionCache {
+    fn exists(&self, session_name: &str, ttl: Duration, now: Instant) -> Option<bool> {
+        let fresh = |at: &Instant| now.saturating_duration_since(*at) < ttl;
+        match self.exists.get(session_name) {
+            Some((at, exists)) if fresh(at) => Some(*exists),
+            _ => match &self.listed {
+                Some((at, sessions)) if fresh(at) => Some(sessions.iter().any(|s| s == session_name)),
+                _ => None,
+            },
+        }
+    }
+
+    fn listed(&self, ttl: Duration, now: Instant) -> Option<Vec<String>> {
+        match &self.listed {
+            Some((at, sessions)) if now.saturating_duration_since(*at) < ttl => Some(sessions.clone()),
+            _ => None,
+        }
+    }
+
+    fn forget(&mut self, session_name: &str) {
+        self.exists.remove(session_name);
+        self.listed = None;
+        self.generation += 1;
+    }
 }
 
 impl SessionManager {
     pub fn new(config: &Config) -> Self {
+        Self::with_runner(config, Arc::new(SystemRunner))
+    }
+
+    /// A manager that runs tmux and claude through `runner`
+    pub fn with_runner(config: &Config, runner: Arc<dyn CommandRunner>) -> Self {
         Self {
+            runner,
             tmux: config.tmux.clone(),
+            socket: config.tmux_socket_name.clone(),
             claude: config.claude.clone(),
             transcripts_dir: config.transcripts_dir.clone(),
+            ps: config.ps.clone(),
+            tmux_timeout: Duration::from_secs(config.health_check_timeout_secs),
+            claude_flags: OnceLock::new(),
+            max_inject_bytes: config.max_inject_bytes,
+            tiers: config.tiers.clone(),
+            health_patterns: config.health_patterns.clone(),
+            ready_timeout: Duration::from_secs(config.session_ready_timeout_secs),
+            verify_injections: config.verify_injections,
+            pane_archive_keep: config.pane_archive_keep,
+            kill_grace: Duration::from_secs(config.kill_grace_secs),
+            logs_dir: config.logs_dir.clone(),
+            session_log_max_bytes: config.session_log_max_bytes,
+            session_log_keep: config.session_log_keep,
+            session_cache_ttl: Duration::from_millis(config.session_cache_ms),
+            session_cache: Mutex::new(SessionCache::default()),
+            dry_run: config.dry_run.then(|| Arc::new(Mutex::new(std::io::stdout())) as DryRunOutput),
+        }
+    }
+
+    /// Make this a dry run, describing what it would do to `out`
+    pub fn dry_run_into(mut self, out: DryRunOutput) -> Self {
+        self.dry_run = Some(out);
+        self
+    }
+
+    /// Describe an action a dry run skips, returning whether this is a dry run
+    fn dry_run(&self, what: impl FnOnce() -> String) -> bool {
+        let Some(out) = &self.dry_run else {
+            return false;
+        };
+        let mut out = out.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(out, "{}", what()) {
+            warn!("Failed to write the dry run: {}", e);
+        }
+        true
+    }
+
+    /// Run tmux on the daemon's server
+    fn tmux<I, S>(&self, args: I) -> std::io::Result<Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut all = Vec::new();
+        if !self.socket.is_empty() {
+            all.extend(["-L".to_string(), self.socket.clone()]);
+        }
+        all.extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+        // A hung tmux, as after the machine wakes, mustn't hang the daemon too
+        if self.tmux_timeout.is_zero() {
+            return self.runner.run(&self.tmux, &all);
+        }
+        self.runner.run_timeout(&self.tmux, &all, self.tmux_timeout)
+    }
+
+    /// Shell command for attaching to a session by hand
+    pub fn attach_hint(&self, session_name: &str) -> String {
+        self.tmux_line(&["attach".to_string(), "-t".to_string(), format!("={}", session_name)])
+    }
+
+    /// The shell command that runs tmux with `args` on the daemon's server
+    fn tmux_line(&self, args: &[String]) -> String {
+        let mut line = vec![self.tmux.to_string_lossy().into_owned()];
+        if !self.socket.is_empty() {
+            line.extend(["-L".to_string(), self.socket.clone()]);
         }
+        line.extend(args.iter().cloned());
+        line.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" ")
     }
 
     /// Check if a tmux session exists (exact match)
+    ///
+    /// tmux's answer is reused for `session_cache_ms`, unless the session has
+    /// been created or killed since.
     pub fn session_exists(&self, session_name: &str) -> bool {
-        let result = Command::new(&self.tmux)
-            .args(["has-session", "-t", &format!("={}", session_name)])
-            .output();
+        let generation = {
+            let cache = self.cache();
+            if let Some(exists) = cache.exists(session_name, self.session_cache_ttl, Instant::now()) {
+                return exists;
+            }
+            cache.generation
+        };
+
+        let result = self.tmux(["has-session", "-t", &format!("={}", session_name)]);
+        let exists = matches!(result, Ok(o) if o.status.success());
+
+        let mut cache = self.cache();
+        if cache.generation == generation {
+            cache.exists.insert(session_name.to_string(), (Instant::now(), exists));
+        }
+        exists
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, SessionCache> {
+        // The cache is only ever left consistent, so a panic elsewhere doesn't spoil it
+        self.session_cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stop trusting what tmux said about a session, after creating or killing it
+    fn forget_session(&self, session_name: &str) {
+        self.cache().forget(session_name);
+    }
 
-        matches!(result, Ok(o) if o.status.success())
+    /// Where a session's transcripts go, unless the registry says otherwise
+    pub fn transcript_dir(&self, session_name: &str) -> PathBuf {
+        self.transcripts_dir.join(session_name)
     }
 
     /// Create a new tmux session with Claude
+    ///
+    /// `contact` is the person a 1:1 session is with; their own system prompt and
+    /// allowed tools adjust the tier's.
     pub fn create_session(
         &self,
         session_name: &str,
         transcript_dir: &std::path::Path,
-        tier: &str,
+        info: &SessionInfo,
+        contact: Option<&Contact>,
+    ) -> Result<()> {
+        self.start_session(session_name, transcript_dir, info, contact, None)
+    }
+
+    /// Create a session that picks up a previous Claude conversation
+    ///
+    /// If Claude can't resume it (the session dies or comes up unhealthy), a
+    /// fresh session is started instead. Returns whether the conversation was
+    /// resumed.
+    pub fn resume_session(
+        &self,
+        session_name: &str,
+        transcript_dir: &Path,
+        info: &SessionInfo,
+        contact: Option<&Contact>,
+        conversation: Option<&str>,
+    ) -> Result<bool> {
+        let Some(conversation) = conversation else {
+            self.create_session(session_name, transcript_dir, info, contact)?;
+            return Ok(false);
+        };
+        let problem = match self.start_session(session_name, transcript_dir, info, contact, Some(conversation)) {
+            Ok(()) => match self.check_health(session_name) {
+                HealthStatus::Healthy => return Ok(true),
+                HealthStatus::Unhealthy(reason) => reason.to_string(),
+            },
+            Err(e) => e.to_string(),
+        };
+        warn!("Couldn't resume {} in {} ({}), starting fresh", conversation, session_name, problem);
+        self.kill_session(session_name)?;
+        self.create_session(session_name, transcript_dir, info, contact)?;
+        Ok(false)
+    }
+
+    fn start_session(
+        &self,
+        session_name: &str,
+        transcript_dir: &Path,
+        info: &SessionInfo,
+        contact: Option<&Contact>,
+        resume: Option<&str>,
     ) -> Result<()> {
         if self.session_exists(session_name) {
             return Ok(()); // Already exists
         }
 
+        let tier = self.tier_config(&info.tier);
+        // A quarantined sender's contact card can't widen what the session may do
+        let contact = contact.filter(|_| tier.name != QUARANTINE_TIER);
+        if self.dry_run.is_some() {
+            let workdir = session_workdir(&tier, contact, transcript_dir);
+            let args = new_session_args(session_name, info, &self.command_in(&workdir, &tier, contact, resume));
+            self.dry_run(|| format!("Would create session {} with:\n{}", session_name, self.tmux_line(&args)));
+            return Ok(());
+        }
+
         // Ensure transcript directory exists
         std::fs::create_dir_all(transcript_dir)?;
+        let workdir = session_workdir(&tier, contact, transcript_dir);
+        let info = SessionInfo {
+            created_at: Utc::now(),
+            workdir: (workdir != transcript_dir).then(|| workdir.clone()),
+            ..info.clone()
+        };
+        info.save(transcript_dir)?;
 
         // Symlink .claude so skills are available
         let claude_symlink = transcript_dir.join(".claude");
@@ -55,49 +415,10 @@ impl SessionManager {
             }
         }
 
-        // Build claude command based on tier
-        let claude_cmd = match tier {
-            "admin" | "wife" => {
-                format!(
-                    "cd {} && {} --dangerously-skip-permissions",
-                    transcript_dir.display(),
-                    self.claude.display()
-                )
-            }
-            "family" => {
-                let prompt = "You are chatting with a FAMILY tier user. Read ~/.claude/skills/sms-assistant/family-rules.md FIRST.";
-                format!(
-                    "cd {} && {} --dangerously-skip-permissions --append-system-prompt \"{}\"",
-                    transcript_dir.display(),
-                    self.claude.display(),
-                    prompt
-                )
-            }
-            _ => {
-                // Restricted for favorites
-                let allowed = "Read,WebSearch,WebFetch,Grep,Glob,Bash(osascript:*)";
-                let prompt = "You are chatting with a FAVORITES tier user with LIMITED privileges.";
-                format!(
-                    "cd {} && {} --dangerously-skip-permissions --allowedTools \"{}\" --append-system-prompt \"{}\"",
-                    transcript_dir.display(),
-                    self.claude.display(),
-                    allowed,
-                    prompt
-                )
-            }
-        };
+        let claude_cmd = self.command_in(&workdir, &tier, contact, resume);
 
-        let output = Command::new(&self.tmux)
-            .args([
-                "new-session",
-                "-d",
-                "-s",
-                session_name,
-                "/bin/bash",
-                "-lc",
-                &claude_cmd,
-            ])
-            .output()?;
+        let output = self.tmux(new_session_args(session_name, &info, &claude_cmd))?;
+        self.forget_session(session_name);
 
         if !output.status.success() {
             return Err(Error::Tmux(format!(
@@ -107,22 +428,82 @@ impl SessionManager {
             )));
         }
 
-        // Wait for session to start
-        std::thread::sleep(Duration::from_secs(2));
+        if let Err(e) = self.ensure_output_log(session_name) {
+            warn!("Failed to log the output of {}: {}", session_name, e);
+        }
+        if let Err(e) = self.set_title(session_name, info.title()) {
+            warn!("Failed to title {}: {}", session_name, e);
+        }
+        self.wait_until_ready(session_name)
+    }
 
-        Ok(())
+    /// Wait for Claude's input box so the first message isn't typed into a loading screen
+    fn wait_until_ready(&self, session_name: &str) -> Result<()> {
+        let deadline = Instant::now() + self.ready_timeout;
+        loop {
+            match self.capture_pane(session_name, 50) {
+                Ok(content) if is_ready_content(&content) => return Ok(()),
+                Ok(_) => {}
+                // Claude exited and took the session with it
+                Err(Error::SessionNotFound(_)) => break,
+                Err(_) => {}
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+            std::thread::sleep(READY_POLL_INTERVAL);
+        }
+        Err(Error::Tmux(format!("session {} failed to become ready", session_name)))
+    }
+
+    /// Shell command for `bash -lc` that starts Claude in the session's workdir
+    ///
+    /// Tiers without a definition get `TierConfig::restricted`. Every value is
+    /// quoted, so spaces, apostrophes, and `$` in the dir or prompt stay literal.
+    pub fn claude_command(&self, transcript_dir: &Path, tier: &str, contact: Option<&Contact>, resume: Option<&str>) -> String {
+        let tier = self.tier_config(tier);
+        let contact = contact.filter(|_| tier.name != QUARANTINE_TIER);
+        let workdir = session_workdir(&tier, contact, transcript_dir);
+        self.command_in(&workdir, &tier, contact, resume)
+    }
+
+    fn command_in(&self, workdir: &Path, tier: &TierConfig, contact: Option<&Contact>, resume: Option<&str>) -> String {
+        let args: Vec<String> = build_claude_command(&self.claude, self.claude_flags(), tier, contact, resume)
+            .iter()
+            .map(|arg| shell_quote(arg).into_owned())
+            .collect();
+        format!("cd {} && {}", shell_quote(&workdir.to_string_lossy()), args.join(" "))
+    }
+
+    /// Flags for the installed claude, probed once per manager
+    pub fn claude_flags(&self) -> &ClaudeFlags {
+        self.claude_flags.get_or_init(|| detect_flags(self.runner.as_ref(), &self.claude))
+    }
+
+    fn tier_config(&self, tier: &str) -> TierConfig {
+        if tier == QUARANTINE_TIER {
+            return TierConfig::quarantine();
+        }
+        self.tiers
+            .iter()
+            .find(|t| t.name == tier)
+            .cloned()
+            .unwrap_or_else(TierConfig::restricted)
     }
 
     /// Kill a tmux session
     pub fn kill_session(&self, session_name: &str) -> Result<()> {
-        let output = Command::new(&self.tmux)
-            .args(["kill-session", "-t", &format!("={}", session_name)])
-            .output()?;
+        if self.dry_run(|| format!("Would kill session {}", session_name)) {
+            return Ok(());
+        }
+        let output = self.tmux(["kill-session", "-t", &format!("={}", session_name)]);
+        self.forget_session(session_name);
+        let output = output?;
 
         if !output.status.success() {
             // Session might not exist, that's OK
             let stderr = String::from_utf8_lossy(&output.stderr).to_lowercase();
-            if !stderr.contains("no server running")
+            if !is_no_server(&stderr)
                 && !stderr.contains("session not found")
                 && !stderr.contains("can't find session")
                 && !stderr.contains("no current session")
@@ -138,54 +519,300 @@ impl SessionManager {
         Ok(())
     }
 
-    /// Inject text into a tmux session
-    pub fn inject_text(&self, session_name: &str, text: &str) -> Result<()> {
+    /// Save a session's whole scrollback under `<transcript_dir>/pane-archives/`
+    ///
+    /// Files are named `<timestamp>-<session>.txt`, and only the newest
+    /// `pane_archive_keep` in the directory are kept.
+    pub fn archive_pane(&self, session_name: &str, transcript_dir: &Path) -> Result<PathBuf> {
         if !self.session_exists(session_name) {
             return Err(Error::SessionNotFound(session_name.to_string()));
         }
 
-        // Send keys with literal flag
-        let output = Command::new(&self.tmux)
-            .args(["send-keys", "-t", session_name, "-l", "--", text])
-            .output()?;
+        let scrollback = self.capture_scrollback(session_name)?;
+        let dir = transcript_dir.join(PANE_ARCHIVE_DIR);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}-{}.txt", Utc::now().format("%Y%m%d-%H%M%S%.3f"), session_name));
+        fs::write(&path, scrollback)?;
+        prune_pane_archives(&dir, self.pane_archive_keep)?;
+        Ok(path)
+    }
+
+    /// A session's whole scrollback
+    pub fn capture_scrollback(&self, session_name: &str) -> Result<String> {
+        let output = self.tmux(["capture-pane", "-t", &format!("={}", session_name), "-p", "-S", "-"])?;
+        if !output.status.success() {
+            return Err(Error::Tmux(format!(
+                "Failed to capture scrollback: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Where a session's output is logged
+    pub fn session_log_path(&self, session_name: &str) -> PathBuf {
+        session_log_path(&self.logs_dir, session_name)
+    }
+
+    /// When tmux created a session, to the second
+    pub fn session_started(&self, session_name: &str) -> Option<SystemTime> {
+        let output = self
+            .tmux(["display-message", "-p", "-t", &pane_target(session_name), "#{session_created}"])
+            .ok()
+            .filter(|output| output.status.success())?;
+        let secs: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+        Some(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// Process id of the program running in a session's pane
+    pub fn session_pid(&self, session_name: &str) -> Option<u32> {
+        let output = self
+            .tmux(["list-panes", "-t", &pane_target(session_name), "-F", "#{pane_pid}"])
+            .ok()
+            .filter(|output| output.status.success())?;
+        String::from_utf8_lossy(&output.stdout).lines().next()?.trim().parse().ok()
+    }
+
+    /// Memory and CPU of a session's Claude and every process under it
+    pub fn session_usage(&self, session_name: &str) -> Option<ProcessUsage> {
+        process_usage(self.runner.as_ref(), &self.ps, self.session_pid(session_name)?)
+    }
+
+    /// A session's Claude and every process under it
+    pub fn session_processes(&self, session_name: &str) -> Option<Vec<ProcessRow>> {
+        process_tree(self.runner.as_ref(), &self.ps, self.session_pid(session_name)?)
+    }
+
+    /// Pipe everything a session prints into its log, unless that's already set up
+    ///
+    /// Returns whether a pipe was started.
+    pub fn ensure_output_log(&self, session_name: &str) -> Result<bool> {
+        let output = self.tmux(["display-message", "-p", "-t", &pane_target(session_name), "#{pane_pipe}"])?;
+        if !output.status.success() {
+            return Err(Error::Tmux(format!(
+                "Failed to check the pipe of {}: {}",
+                session_name,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        if String::from_utf8_lossy(&output.stdout).trim() == "1" {
+            return Ok(false);
+        }
 
+        let path = self.session_log_path(session_name);
+        fs::create_dir_all(self.logs_dir.join(SESSION_LOG_DIR))?;
+        let output = self.tmux(pipe_pane_args(session_name, &path))?;
         if !output.status.success() {
             return Err(Error::Tmux(format!(
-                "Failed to send keys: {}",
+                "Failed to pipe the output of {}: {}",
+                session_name,
                 String::from_utf8_lossy(&output.stderr)
             )));
         }
+        Ok(true)
+    }
+
+    /// Name a session's window and pane after who it's for, so it's recognisable when attached
+    pub fn set_title(&self, session_name: &str, title: &str) -> Result<()> {
+        for args in title_args(session_name, title) {
+            let output = self.tmux(&args)?;
+            if !output.status.success() {
+                return Err(Error::Tmux(format!(
+                    "Failed to {} {}: {}",
+                    args[0],
+                    session_name,
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rotate every session log that has grown too big, returning how many were
+    pub fn rotate_session_logs(&self) -> Result<usize> {
+        let dir = self.logs_dir.join(SESSION_LOG_DIR);
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let mut rotated = 0;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "log")
+                && rotate_log(&path, self.session_log_max_bytes, self.session_log_keep)?
+            {
+                rotated += 1;
+            }
+        }
+        Ok(rotated)
+    }
+
+    /// Kill a session, archiving its scrollback first if it's running
+    ///
+    /// Gracefully, a busy Claude is first asked to finish its current step,
+    /// so it isn't cut off halfway through writing a file.
+    pub fn archive_and_kill(&self, session_name: &str, transcript_dir: &Path, mode: KillMode) -> Result<()> {
+        if self.dry_run(|| format!("Would archive and kill session {}", session_name)) {
+            return Ok(());
+        }
+        if self.session_exists(session_name) {
+            if needs_wrap_up(mode, self.kill_grace, self.is_busy(session_name)) {
+                self.wrap_up(session_name);
+            }
+            if let Err(e) = self.archive_pane(session_name, transcript_dir) {
+                warn!("Failed to archive the pane of {}: {}", session_name, e);
+            }
+        }
+        self.kill_session(session_name)
+    }
+
+    /// Ask Claude to stop, and wait until it has or the grace period is up
+    fn wrap_up(&self, session_name: &str) {
+        if let Err(e) = self.inject_text(session_name, WRAP_UP_MESSAGE) {
+            warn!("Failed to ask {} to wrap up: {}", session_name, e);
+            return;
+        }
+        let start = Instant::now();
+        while self.is_busy(session_name) {
+            if start.elapsed() >= self.kill_grace {
+                warn!("Session {} still busy after {}s, killing it anyway", session_name, self.kill_grace.as_secs());
+                return;
+            }
+            std::thread::sleep(WRAP_UP_POLL_INTERVAL);
+        }
+    }
+
+    /// Inject text into a tmux session
+    ///
+    /// Text over `max_inject_bytes` is saved to the session's inbox and
+    /// replaced with a pointer to it, as in `inject_message`.
+    pub fn inject_text(&self, session_name: &str, text: &str) -> Result<()> {
+        if self.dry_run(|| format!("Would inject into {}:\n{}", session_name, text)) {
+            return Ok(());
+        }
+        if !self.session_exists(session_name) {
+            return Err(Error::SessionNotFound(session_name.to_string()));
+        }
+        let path = self.transcript_dir(session_name).join("inbox").join(format!("note-{}.txt", Utc::now().timestamp_millis()));
+        let text = spill_oversized(text, self.max_inject_bytes, &path)?;
+        self.deliver(session_name, &text)
+    }
+
+    /// Type text into an existing session, as is
+    ///
+    /// With `verify_injections`, the pane is read back to check the text
+    /// arrived, against a capture from just before it was sent; if it didn't,
+    /// it's sent once more before giving up with `Error::InjectionNotConfirmed`.
+    fn deliver(&self, session_name: &str, text: &str) -> Result<()> {
+        let before = if self.verify_injections {
+            self.capture_pane(session_name, VERIFY_LINES).unwrap_or_default()
+        } else {
+            String::new()
+        };
+        self.send_text(session_name, text)?;
+        if !self.verify_injections {
+            return Ok(());
+        }
+        for attempt in 0..2 {
+            std::thread::sleep(VERIFY_DELAY);
+            let pane = self.capture_pane(session_name, VERIFY_LINES).unwrap_or_default();
+            if injection_visible(&before, &pane, text) {
+                return Ok(());
+            }
+            if attempt == 0 {
+                warn!("Injection into {} not seen in the pane, sending again", session_name);
+                self.send_text(session_name, text)?;
+            }
+        }
+        Err(Error::InjectionNotConfirmed(session_name.to_string()))
+    }
+
+    /// Type text into a session and submit it
+    ///
+    /// Long text is typed in chunks of at most `SEND_CHUNK_BYTES`, and only
+    /// submitted once every chunk is in.
+    fn send_text(&self, session_name: &str, text: &str) -> Result<()> {
+        let chunks = chunk_text(text, SEND_CHUNK_BYTES);
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i > 0 {
+                std::thread::sleep(SEND_CHUNK_DELAY);
+            }
+            // Send keys with literal flag
+            let output = self.tmux(["send-keys", "-t", session_name, "-l", "--", chunk])
+                .map_err(|e| Error::Tmux(format!("Failed to send chunk {} of {}: {}", i + 1, chunks.len(), e)))?;
+
+            if !output.status.success() {
+                return Err(Error::Tmux(format!(
+                    "Failed to send chunk {} of {}: {}",
+                    i + 1,
+                    chunks.len(),
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+        }
 
         // Wait for paste to complete
         std::thread::sleep(Duration::from_millis(500));
 
         // Send Enter to submit
-        Command::new(&self.tmux)
-            .args(["send-keys", "-t", session_name, "Enter"])
-            .output()?;
-        Command::new(&self.tmux)
-            .args(["send-keys", "-t", session_name, "Enter"])
-            .output()?;
+        self.tmux(["send-keys", "-t", session_name, "Enter"])?;
+        self.tmux(["send-keys", "-t", session_name, "Enter"])?;
 
         Ok(())
     }
 
+    /// Stop whatever Claude is doing by pressing Escape, then optionally send a message
+    pub fn interrupt(&self, session_name: &str, follow_up: Option<&str>) -> Result<()> {
+        if !self.session_exists(session_name) {
+            return Err(Error::SessionNotFound(session_name.to_string()));
+        }
+
+        let output = self.tmux(["send-keys", "-t", session_name, "Escape"])?;
+        if !output.status.success() {
+            return Err(Error::Tmux(format!(
+                "Failed to send Escape: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        match follow_up {
+            Some(text) if !text.trim().is_empty() => {
+                // Give Claude a moment to drop what it was doing
+                std::thread::sleep(Duration::from_millis(500));
+                self.inject_text(session_name, text)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Inject a message, saving it to `<transcript_dir>/inbox/<rowid>.txt` first if it's too big to paste
+    ///
+    /// `send-keys -l` truncates very long input and ties up the pane while it types,
+    /// so oversized messages are replaced with a pointer Claude can Read.
+    pub fn inject_message(&self, session_name: &str, text: &str, transcript_dir: &Path, rowid: i64) -> Result<()> {
+        if self.dry_run(|| format!("Would inject into {}:\n{}", session_name, text)) {
+            return Ok(());
+        }
+        if !self.session_exists(session_name) {
+            return Err(Error::SessionNotFound(session_name.to_string()));
+        }
+        let text = spill_oversized(text, self.max_inject_bytes, &inbox_path(transcript_dir, rowid))?;
+        self.deliver(session_name, &text)
+    }
+
     /// Capture pane content from a tmux session
     pub fn capture_pane(&self, session_name: &str, lines: u32) -> Result<String> {
         if !self.session_exists(session_name) {
             return Err(Error::SessionNotFound(session_name.to_string()));
         }
 
-        let output = Command::new(&self.tmux)
-            .args([
+        let output = self.tmux([
                 "capture-pane",
                 "-t",
                 &format!("={}", session_name),
                 "-p",
                 "-S",
                 &format!("-{}", lines),
-            ])
-            .output()?;
+            ])?;
 
         if !output.status.success() {
             return Err(Error::Tmux(format!(
@@ -200,25 +827,87 @@ impl SessionManager {
     /// Check session health
     pub fn check_health(&self, session_name: &str) -> HealthStatus {
         if !self.session_exists(session_name) {
+            if matches!(self.server_running(), Ok(false)) {
+                return HealthStatus::Unhealthy(UnhealthyReason::NoServer);
+            }
             return HealthStatus::Unhealthy(UnhealthyReason::SessionMissing);
         }
 
         match self.capture_pane(session_name, 30) {
-            Ok(content) => check_session_content(&content),
+            Ok(content) => self.health_patterns.check_with_context(&content, &HealthCheckOptions::default()),
             Err(_) => HealthStatus::Unhealthy(UnhealthyReason::SessionMissing),
         }
     }
 
-    /// List all tmux sessions
+    /// Whether Claude is in the middle of something, so typing now would interleave
+    pub fn is_busy(&self, session_name: &str) -> bool {
+        self.capture_pane(session_name, 30)
+            .map(|content| is_busy_content(&content))
+            .unwrap_or(false)
+    }
+
+    /// The pane as it is before injecting a prompt whose response will be waited for
+    pub fn response_snapshot(&self, session_name: &str) -> Result<String> {
+        self.capture_pane(session_name, RESPONSE_PANE_LINES)
+    }
+
+    /// Wait for Claude to finish responding, returning what's new since `snapshot`
+    ///
+    /// The flag says whether it finished; on timeout the output so far is returned.
+    pub fn wait_for_response(&self, session_name: &str, snapshot: &str, timeout: Duration) -> Result<(String, bool)> {
+        let start = Instant::now();
+        let mut settle = Settle::new(snapshot, RESPONSE_QUIET, start);
+        let finished = loop {
+            std::thread::sleep(RESPONSE_POLL_INTERVAL);
+            let pane = self.capture_pane(session_name, RESPONSE_PANE_LINES)?;
+            let now = Instant::now();
+            if settle.observe(&pane, now) {
+                break true;
+            }
+            if now.duration_since(start) >= timeout {
+                break false;
+            }
+        };
+        Ok((pane_diff(snapshot, settle.latest()), finished))
+    }
+
+    /// List all tmux sessions, reusing the last list for `session_cache_ms`
     pub fn list_sessions(&self) -> Result<Vec<String>> {
-        let output = Command::new(&self.tmux)
-            .args(["list-sessions", "-F", "#{session_name}"])
-            .output()?;
+        let generation = {
+            let cache = self.cache();
+            if let Some(sessions) = cache.listed(self.session_cache_ttl, Instant::now()) {
+                return Ok(sessions);
+            }
+            cache.generation
+        };
+        let sessions = self.list_sessions_uncached()?;
+        let mut cache = self.cache();
+        if cache.generation == generation {
+            cache.listed = Some((Instant::now(), sessions.clone()));
+        }
+        Ok(sessions)
+    }
+
+    fn list_sessions_uncached(&self) -> Result<Vec<String>> {
+        Ok(self.list_server()?.unwrap_or_default())
+    }
+
+    /// Whether the daemon's tmux server is up
+    ///
+    /// Asked when a session is missing: with no server every session is, and
+    /// that's one problem rather than one per session.
+    pub fn server_running(&self) -> Result<bool> {
+        Ok(self.list_server()?.is_some())
+    }
+
+    /// The server's sessions, or None if it isn't running
+    fn list_server(&self) -> Result<Option<Vec<String>>> {
+        let output = self.tmux(["list-sessions", "-F", "#{session_name}"])?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            if stderr.contains("no server running") {
-                return Ok(Vec::new());
+            if is_no_server(&stderr) {
+                return Ok(None);
             }
             return Err(Error::Tmux(format!("Failed to list sessions: {}", stderr)));
         }
@@ -229,12 +918,18 @@ impl SessionManager {
             .filter(|s| !s.is_empty())
             .collect();
 
-        Ok(sessions)
+        Ok(Some(sessions))
     }
 
     /// Generate session name from contact name
+    ///
+    /// tmux rewrites '.' and ':' in session names, so those become '_' here;
+    /// otherwise email-derived names would never match an existing session.
     pub fn session_name_for_contact(contact_name: &str) -> String {
-        contact_name.to_lowercase().replace(' ', "-")
+        contact_name
+            .to_lowercase()
+            .replace(' ', "-")
+            .replace(['.', ':'], "_")
     }
 
     /// Generate session name for a group chat
@@ -252,32 +947,1071 @@ impl SessionManager {
         }
     }
 
-    /// Restart a session (kill and recreate)
+    /// Restart a session (kill and recreate), resuming `conversation` if given
+    ///
+    /// Returns whether the conversation was resumed.
     pub fn restart_session(
         &self,
         session_name: &str,
         transcript_dir: &std::path::Path,
-        tier: &str,
-    ) -> Result<()> {
+        info: &SessionInfo,
+        contact: Option<&Contact>,
+        conversation: Option<&str>,
+        mode: KillMode,
+    ) -> Result<bool> {
         // Kill existing
-        self.kill_session(session_name)?;
+        self.archive_and_kill(session_name, transcript_dir, mode)?;
         std::thread::sleep(Duration::from_secs(2));
 
         // Recreate
-        self.create_session(session_name, transcript_dir, tier)?;
+        self.resume_session(session_name, transcript_dir, info, contact, conversation)
+    }
+}
 
-        Ok(())
+/// `tmux new-session` arguments running `command` in a detached session with the info in its environment
+fn new_session_args(session_name: &str, info: &SessionInfo, command: &str) -> Vec<String> {
+    let mut args: Vec<String> = ["new-session", "-d", "-s", session_name].map(String::from).into();
+    for (name, value) in info.env() {
+        args.push("-e".to_string());
+        args.push(format!("{}={}", name, value));
     }
+    args.extend(["/bin/bash", "-lc", command].map(String::from));
+    args
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Delete all but the newest `keep` archives in a pane archive dir, returning how many went
+fn prune_pane_archives(dir: &Path, keep: usize) -> Result<usize> {
+    let mut archives: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    // Names start with a timestamp, so they sort oldest first
+    archives.sort();
+    let excess = archives.len().saturating_sub(keep);
+    for path in &archives[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(excess)
+}
 
-    #[test]
-    fn test_session_name_for_contact() {
-        assert_eq!(
-            SessionManager::session_name_for_contact("Jane Doe"),
+/// Whether tmux's stderr says its server isn't running, rather than that one session is missing
+///
+/// "no server running on <socket>" once the server has exited; "error
+/// connecting to <socket>" when its socket dir doesn't exist yet, as just
+/// after a reboot.
+pub fn is_no_server(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    stderr.contains("no server running") || stderr.contains("error connecting to")
+}
+
+/// Where a session's output is logged under `logs_dir`
+pub fn session_log_path(logs_dir: &Path, session_name: &str) -> PathBuf {
+    logs_dir.join(SESSION_LOG_DIR).join(format!("{}.log", session_name))
+}
+
+/// The active pane of exactly `session_name`, for commands that take a pane rather than a session
+fn pane_target(session_name: &str) -> String {
+    format!("={}:", session_name)
+}
+
+/// tmux args naming a session's window and titling its pane
+///
+/// A renamed window stops being renamed automatically, and unlike the pane
+/// title, Claude can't change it, so it's what the monitor reads back.
+fn title_args(session_name: &str, title: &str) -> [Vec<String>; 2] {
+    let target = pane_target(session_name);
+    [
+        vec!["rename-window".to_string(), "-t".to_string(), target.clone(), title.to_string()],
+        vec!["select-pane".to_string(), "-t".to_string(), target, "-T".to_string(), title.to_string()],
+    ]
+}
+
+/// tmux args appending a session's output to `log`; `-o` leaves an existing pipe alone
+fn pipe_pane_args(session_name: &str, log: &Path) -> Vec<String> {
+    vec![
+        "pipe-pane".to_string(),
+        "-t".to_string(),
+        pane_target(session_name),
+        "-o".to_string(),
+        format!("cat >> {}", shell_quote(&log.to_string_lossy())),
+    ]
+}
+
+/// Rotate `path` to `path.1` (shifting older ones up to `path.<keep>`) once it's over `max_bytes`
+///
+/// The log is copied and truncated rather than renamed, since the pipe's `cat`
+/// keeps it open and appends to it. Returns whether it was rotated.
+fn rotate_log(path: &Path, max_bytes: u64, keep: usize) -> Result<bool> {
+    let size = match fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(_) => return Ok(false),
+    };
+    if size <= max_bytes {
+        return Ok(false);
+    }
+
+    let numbered = |n: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+    if keep > 0 {
+        let _ = fs::remove_file(numbered(keep));
+        for n in (1..keep).rev() {
+            if numbered(n).exists() {
+                fs::rename(numbered(n), numbered(n + 1))?;
+            }
+        }
+        fs::copy(path, numbered(1))?;
+    }
+    fs::OpenOptions::new().write(true).open(path)?.set_len(0)?;
+    Ok(true)
+}
+
+/// A tmux command on the server for `socket`, or the default server if it's empty
+pub fn tmux_command(tmux: &Path, socket: &str) -> Command {
+    let mut command = Command::new(tmux);
+    if !socket.is_empty() {
+        command.args(["-L", socket]);
+    }
+    command
+}
+
+/// Whether a pane shows an injected text it didn't `before` it was sent
+///
+/// Some line of the text, or Claude's placeholder for a collapsed multi-line
+/// paste, has to show more often than it did. Lines every message has, like
+/// an SMS's header, are already on the pane from earlier messages, so they
+/// only count when there's a new copy of them.
+fn injection_visible(before: &str, pane: &str, text: &str) -> bool {
+    let more = |snippet: &str| !snippet.is_empty() && pane.matches(snippet).count() > before.matches(snippet).count();
+    more("[Pasted text")
+        || text.lines().map(str::trim).any(|line| more(&line.chars().take(30).collect::<String>()))
+}
+
+/// ID of the most recent Claude conversation started in a transcript dir
+///
+/// Claude keeps each conversation as `<id>.jsonl` under
+/// `.claude/projects/<dir with every non-alphanumeric replaced by ->/`.
+pub fn latest_conversation(transcript_dir: &Path) -> Option<String> {
+    conversation_since(transcript_dir, UNIX_EPOCH)
+}
+
+/// `latest_conversation`, but only one written to since `since`
+///
+/// With a session's start time, this is the conversation the session is on,
+/// rather than one an earlier session left behind.
+pub fn conversation_since(transcript_dir: &Path, since: SystemTime) -> Option<String> {
+    // Claude files conversations by the directory it runs in
+    let workdir = SessionInfo::load(transcript_dir)
+        .and_then(|info| info.workdir)
+        .unwrap_or_else(|| transcript_dir.to_path_buf());
+    let project: String = workdir
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let entries = std::fs::read_dir(transcript_dir.join(".claude/projects").join(project)).ok()?;
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .filter_map(|path| Some((std::fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .filter(|(modified, _)| *modified >= since)
+        .max()
+        .and_then(|(_, path)| Some(path.file_stem()?.to_string_lossy().into_owned()))
+}
+
+/// Where a session runs: the contact's workdir, else the tier's, else the transcript dir
+///
+/// `~/` is the home dir. A workdir that isn't a directory is skipped with a
+/// warning rather than failing the session.
+pub fn session_workdir(tier: &TierConfig, contact: Option<&Contact>, transcript_dir: &Path) -> PathBuf {
+    let configured = contact
+        .and_then(|c| c.workdir.as_deref())
+        .or(tier.workdir.as_deref())
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty());
+    let Some(dir) = configured else {
+        return transcript_dir.to_path_buf();
+    };
+    let path = match (dir.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(dir),
+    };
+    if path.is_dir() {
+        path
+    } else {
+        warn!("Workdir {} doesn't exist, running in {} instead", path.display(), transcript_dir.display());
+        transcript_dir.to_path_buf()
+    }
+}
+
+/// Arguments that start claude with the tier's flags, the program first
+///
+/// A contact's system prompt is appended to the tier's in the same
+/// `--append-system-prompt`. Their allowed tools replace the tier's list, but
+/// only for tiers that have one: a tier without a list isn't restricted, so
+/// there's nothing to replace. Blank contact settings count as unset. With
+/// `resume`, Claude picks up that conversation instead of starting a new one.
+/// Flags are spelled as `flags` says; a prompt the CLI can't take is left off.
+pub fn build_claude_command(
+    claude: &Path,
+    flags: &ClaudeFlags,
+    tier: &TierConfig,
+    contact: Option<&Contact>,
+    resume: Option<&str>,
+) -> Vec<String> {
+    let custom = |field: fn(&Contact) -> Option<&String>| {
+        contact.and_then(field).map(|s| s.trim()).filter(|s| !s.is_empty())
+    };
+
+    let mut args = vec![claude.to_string_lossy().into_owned()];
+    if tier.skip_permissions {
+        args.push(flags.skip_permissions.to_string());
+    }
+    if let Some(tier_tools) = &tier.allowed_tools {
+        let tools = custom(|c| c.allowed_tools.as_ref()).unwrap_or(tier_tools);
+        args.push(flags.allowed_tools.to_string());
+        args.push(tools.to_string());
+    }
+    let prompt = match (tier.system_prompt.as_deref(), custom(|c| c.system_prompt.as_ref())) {
+        (Some(tier_prompt), Some(custom)) => Some(format!("{}\n\n{}", tier_prompt, custom)),
+        (tier_prompt, custom) => tier_prompt.or(custom).map(str::to_string),
+    };
+    match (prompt, flags.append_system_prompt) {
+        (Some(prompt), Some(flag)) => {
+            args.push(flag.to_string());
+            args.push(prompt);
+        }
+        (Some(_), None) => warn!("This Claude CLI can't take a system prompt, starting without the tier's"),
+        (None, _) => {}
+    }
+    if let Some(conversation) = resume {
+        args.push(flags.resume.to_string());
+        args.push(conversation.to_string());
+    }
+    args
+}
+
+/// Bytes of an oversized message quoted in the pointer that replaces it
+const SPILL_PREVIEW_BYTES: usize = 300;
+
+/// Path an oversized message is saved to
+pub fn inbox_path(transcript_dir: &Path, rowid: i64) -> PathBuf {
+    transcript_dir.join("inbox").join(format!("{}.txt", rowid))
+}
+
+/// Return `text` unchanged if it fits in `max_bytes`; otherwise write it to `path`
+/// and return a short wrapper pointing at the file
+pub fn spill_oversized<'a>(text: &'a str, max_bytes: usize, path: &Path) -> Result<Cow<'a, str>> {
+    if text.len() <= max_bytes {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, text)?;
+
+    let preview = truncate_at_char_boundary(text.trim_start(), SPILL_PREVIEW_BYTES);
+    Ok(Cow::Owned(format!(
+        "---LONG MESSAGE ({} KB)---\nThe full message, including who sent it and how to reply, was saved to {}. Use the Read tool on that file before responding.\nIt begins: {}\u{2026}\n---END LONG MESSAGE---",
+        text.len().div_ceil(1024),
+        path.display(),
+        preview.replace('\n', " ")
+    )))
+}
+
+/// `text` split into pieces of at most `max_bytes`, never inside a char
+///
+/// A char wider than `max_bytes` gets a piece to itself. Empty text has no pieces.
+fn chunk_text(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut chunk = truncate_at_char_boundary(rest, max_bytes);
+        if chunk.is_empty() {
+            chunk = &rest[..rest.chars().next().map_or(rest.len(), char::len_utf8)];
+        }
+        chunks.push(chunk);
+        rest = &rest[chunk.len()..];
+    }
+    chunks
+}
+
+/// Longest prefix of `s` that is at most `max_bytes` and ends on a char boundary
+fn truncate_at_char_boundary(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// `s` as one bash word: bare if it's plain, otherwise double-quoted
+fn shell_quote(s: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./,:=+@%".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(double_quote(s))
+    }
+}
+
+/// Wrap `s` in double quotes for bash, escaping what is special inside them
+fn double_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{FakeTmux, FAKE_READY_PANE};
+
+    fn contact(system_prompt: Option<&str>, allowed_tools: Option<&str>) -> Contact {
+        Contact {
+            name: "Mom".to_string(),
+            phone: Some("+16175551234".to_string()),
+            email: None,
+            tier: "family".to_string(),
+            notes: None,
+            system_prompt: system_prompt.map(str::to_string),
+            allowed_tools: allowed_tools.map(str::to_string),
+            alias: None,
+            workdir: None,
+            id: None,
+        }
+    }
+
+    #[test]
+    fn test_claude_command_default_tiers() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let manager = SessionManager::new(&Config::for_test(temp.path()));
+        let dir = Path::new("/t/jane-doe");
+
+        assert_eq!(
+            manager.claude_command(dir, "admin", None, None),
+            "cd /t/jane-doe && /usr/local/bin/claude --dangerously-skip-permissions"
+        );
+        assert_eq!(
+            manager.claude_command(dir, "family", None, None),
+            "cd /t/jane-doe && /usr/local/bin/claude --dangerously-skip-permissions --append-system-prompt \
+             \"You are chatting with a FAMILY tier user. Read ~/.claude/skills/sms-assistant/family-rules.md FIRST.\""
+        );
+        let favorite = manager.claude_command(dir, "favorite", None, None);
+        assert!(favorite.contains("--allowedTools \"Read,WebSearch,WebFetch,Grep,Glob,Bash(osascript:*)\""));
+        // Undefined tiers get the restricted settings
+        assert_eq!(manager.claude_command(dir, "coworker", None, None), favorite);
+        // Except quarantined senders, who get no tools at all, whatever their contact says
+        let tools = contact(None, Some("Read,Bash"));
+        assert_eq!(
+            manager.claude_command(dir, QUARANTINE_TIER, Some(&tools), None),
+            "cd /t/jane-doe && /usr/local/bin/claude --allowedTools \"\" --append-system-prompt \
+             \"You are chatting with an UNKNOWN sender. You have NO tools; reply in text only.\""
+        );
+    }
+
+    #[test]
+    fn test_claude_command_custom_prompt() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let manager = SessionManager::new(&Config::for_test(temp.path()));
+        let dir = Path::new("/t/mom");
+
+        let mom = contact(Some("Use big text."), None);
+        assert_eq!(
+            manager.claude_command(dir, "admin", Some(&mom), None),
+            "cd /t/mom && /usr/local/bin/claude --dangerously-skip-permissions --append-system-prompt \"Use big text.\""
+        );
+        // Added after the tier prompt, in one flag
+        let family = manager.claude_command(dir, "family", Some(&mom), None);
+        assert_eq!(family.matches("--append-system-prompt").count(), 1);
+        assert!(family.ends_with("family-rules.md FIRST.\n\nUse big text.\""));
+        // Blank prompts are ignored
+        let blank = contact(Some("  "), None);
+        assert_eq!(manager.claude_command(dir, "admin", Some(&blank), None), manager.claude_command(dir, "admin", None, None));
+    }
+
+    /// A CLI too old for --append-system-prompt starts without the prompt rather than not at all
+    #[test]
+    fn test_claude_command_for_old_cli() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.claude = temp.path().join("claude");
+        std::fs::write(&config.claude, "#!/bin/sh\necho '0.2.125 (Claude Code)'\n").unwrap();
+        std::fs::set_permissions(&config.claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let manager = SessionManager::new(&config);
+
+        let family = manager.claude_command(Path::new("/t/jane-doe"), "family", None, None);
+        assert!(family.ends_with("claude --dangerously-skip-permissions"), "{}", family);
+        let favorite = manager.claude_command(Path::new("/t/jane-doe"), "favorite", None, Some("0b7c-42"));
+        assert!(favorite.contains("--allowedTools"));
+        assert!(favorite.ends_with("--resume 0b7c-42"));
+    }
+
+    #[test]
+    fn test_build_claude_command_allowed_tools() {
+        let claude = Path::new("/usr/local/bin/claude");
+        let restricted = TierConfig {
+            name: "family".to_string(),
+            allowed_tools: Some("Read,Grep".to_string()),
+            system_prompt: None,
+            skip_permissions: false,
+            workdir: None,
+        };
+        let admin = TierConfig {
+            name: "admin".to_string(),
+            allowed_tools: None,
+            system_prompt: None,
+            skip_permissions: true,
+            workdir: None,
+        };
+
+        // Tier default
+        assert_eq!(
+            build_claude_command(claude, &ClaudeFlags::CURRENT, &restricted, None, None),
+            vec!["/usr/local/bin/claude", "--allowedTools", "Read,Grep"]
+        );
+        assert_eq!(
+            build_claude_command(claude, &ClaudeFlags::CURRENT, &restricted, Some(&contact(None, None)), None),
+            build_claude_command(claude, &ClaudeFlags::CURRENT, &restricted, None, None)
+        );
+        // Contact override replaces the tier's list
+        assert_eq!(
+            build_claude_command(
+                claude,
+                &ClaudeFlags::CURRENT,
+                &restricted,
+                Some(&contact(None, Some("Read,Grep,Bash"))),
+                None
+            ),
+            vec!["/usr/local/bin/claude", "--allowedTools", "Read,Grep,Bash"]
+        );
+        // Empty override inherits the tier's list rather than allowing nothing
+        assert_eq!(
+            build_claude_command(claude, &ClaudeFlags::CURRENT, &restricted, Some(&contact(None, Some(" "))), None),
+            build_claude_command(claude, &ClaudeFlags::CURRENT, &restricted, None, None)
+        );
+        // A tier without a list ignores overrides entirely
+        assert_eq!(
+            build_claude_command(claude, &ClaudeFlags::CURRENT, &admin, Some(&contact(None, Some("Read"))), None),
+            vec!["/usr/local/bin/claude", "--dangerously-skip-permissions"]
+        );
+    }
+
+    #[test]
+    fn test_build_claude_command_resume() {
+        let claude = Path::new("/usr/local/bin/claude");
+        let args = build_claude_command(claude, &ClaudeFlags::CURRENT, &TierConfig::restricted(), None, Some("0b7c-42"));
+        assert_eq!(args[args.len() - 2..], ["--resume", "0b7c-42"]);
+        assert!(!build_claude_command(claude, &ClaudeFlags::CURRENT, &TierConfig::restricted(), None, None).contains(&"--resume".to_string()));
+    }
+
+    #[test]
+    fn test_latest_conversation() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path().join("transcripts/jane.doe");
+        assert_eq!(latest_conversation(&dir), None);
+
+        let project: String =
+            dir.to_string_lossy().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect();
+        let projects = dir.join(".claude/projects").join(project);
+        std::fs::create_dir_all(&projects).unwrap();
+        let now = std::time::SystemTime::now();
+        for (name, age) in [("older.jsonl", 60), ("newest.jsonl", 0), ("notes.txt", 0)] {
+            let file = std::fs::File::create(projects.join(name)).unwrap();
+            file.set_modified(now - Duration::from_secs(age)).unwrap();
+        }
+        assert_eq!(latest_conversation(&dir).as_deref(), Some("newest"));
+        assert_eq!(conversation_since(&dir, now - Duration::from_secs(30)).as_deref(), Some("newest"));
+        assert_eq!(conversation_since(&dir, now + Duration::from_secs(30)), None);
+
+        // A session with its own workdir has its conversations filed under that
+        let workdir = temp.path().join("code");
+        let project: String =
+            workdir.to_string_lossy().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect();
+        std::fs::create_dir_all(dir.join(".claude/projects").join(&project)).unwrap();
+        std::fs::File::create(dir.join(".claude/projects").join(&project).join("in-code.jsonl")).unwrap();
+        let mut info = SessionInfo::new("+16175551234", "admin", "Jane Doe");
+        info.workdir = Some(workdir);
+        info.save(&dir).unwrap();
+        assert_eq!(latest_conversation(&dir).as_deref(), Some("in-code"));
+    }
+
+    /// The contact's workdir beats the tier's, which beats the transcript dir; missing ones are skipped
+    #[test]
+    fn test_session_workdir() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let transcripts = temp.path().join("transcripts/jane-doe");
+        let code = temp.path().join("code");
+        let family = temp.path().join("family-stuff");
+        fs::create_dir_all(&code).unwrap();
+        fs::create_dir_all(&family).unwrap();
+        let mut tier = TierConfig::restricted();
+        let mut jane = contact(None, None);
+
+        assert_eq!(session_workdir(&tier, Some(&jane), &transcripts), transcripts);
+        tier.workdir = Some(code.display().to_string());
+        assert_eq!(session_workdir(&tier, Some(&jane), &transcripts), code);
+        assert_eq!(session_workdir(&tier, None, &transcripts), code);
+        jane.workdir = Some(family.display().to_string());
+        assert_eq!(session_workdir(&tier, Some(&jane), &transcripts), family);
+
+        jane.workdir = Some(temp.path().join("gone").display().to_string());
+        assert_eq!(session_workdir(&tier, Some(&jane), &transcripts), transcripts);
+
+        tier.workdir = Some("~/".to_string());
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(session_workdir(&tier, None, &transcripts), home);
+        }
+    }
+
+    #[test]
+    fn test_claude_command_runs_in_workdir() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        let code = temp.path().join("code");
+        fs::create_dir_all(&code).unwrap();
+        config.tiers[0].workdir = Some(code.display().to_string());
+        let manager = SessionManager::new(&config);
+        let transcripts = temp.path().join("transcripts/jane-doe");
+
+        let command = manager.claude_command(&transcripts, &config.tiers[0].name, None, None);
+        assert!(command.starts_with(&format!("cd {} && ", code.display())));
+        let command = manager.claude_command(&transcripts, "family", None, None);
+        assert!(command.starts_with(&format!("cd {} && ", transcripts.display())));
+    }
+
+    /// Resuming a conversation Claude can't find falls back to a fresh session
+    #[test]
+    fn test_resume_falls_back_to_fresh() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        // Claude exits at once, taking the session with it, when asked for "gone"
+        fake.exit_on_start("--resume gone");
+        let dir = temp.path().join("transcripts/jane-doe");
+
+        assert!(manager.resume_session("jane-doe", &dir, &SessionInfo::new("+16175551234", "family", "Jane Doe"), None, Some("abc-123")).unwrap());
+        manager.kill_session("jane-doe").unwrap();
+        assert!(!manager.resume_session("jane-doe", &dir, &SessionInfo::new("+16175551234", "family", "Jane Doe"), None, Some("gone")).unwrap());
+        assert!(manager.session_exists("jane-doe"));
+        assert!(!manager.resume_session("bob", &dir, &SessionInfo::new("+16175551234", "family", "Jane Doe"), None, None).unwrap());
+
+        assert_eq!(fake.calls_to("new-session").len(), 4);
+    }
+
+    #[test]
+    fn test_injection_visible() {
+        let pane = "Welcome to Claude\n> Hey, are you free for dinner on Friday night? Let me know\n  ? for shortcuts\n";
+        assert!(injection_visible("Welcome to Claude\n", pane, "\n  Hey, are you free for dinner on Friday night? Let me know soon\n"));
+        assert!(!injection_visible("Welcome to Claude\n", pane, "Where did you park the car?"));
+        // Already there before it was sent
+        assert!(!injection_visible(pane, pane, "Hey, are you free for dinner on Friday night? Let me know soon"));
+        assert!(injection_visible("", "> [Pasted text #1 +40 lines]\n", "line one\nline two"));
+        assert!(!injection_visible("> [Pasted text #1 +40 lines]\n", "> [Pasted text #1 +40 lines]\n", "line one\nline two"));
+    }
+
+    const EARLIER_SMS: &str = "\n---SMS FROM Jane Doe (family) via iMessage---\nChat ID: +16175551234\nAre you free Friday?\n---END SMS---\n";
+
+    /// The same header as a message already on the pane doesn't make a lost one look delivered
+    #[test]
+    fn test_inject_lost_after_earlier_message() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.verify_injections = true;
+        let (manager, fake) = fake_manager_with(&config);
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+        manager.inject_text("jane-doe", EARLIER_SMS).unwrap();
+
+        let next = EARLIER_SMS.replace("Are you free Friday?", "What time works?");
+        fake.drop_typing("jane-doe");
+        let result = manager.inject_text("jane-doe", &next);
+        assert!(matches!(result, Err(Error::InjectionNotConfirmed(ref name)) if name == "jane-doe"));
+        assert_eq!(fake.calls_to("send-keys").iter().filter(|call| call.contains(&next)).count(), 2);
+
+        // The very same text again is confirmed by its new copy
+        let (manager, fake) = fake_manager_with(&config);
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+        manager.inject_text("jane-doe", EARLIER_SMS).unwrap();
+        manager.inject_text("jane-doe", EARLIER_SMS).unwrap();
+        assert_eq!(fake.calls_to("send-keys").iter().filter(|call| call.contains(&EARLIER_SMS.to_string())).count(), 2);
+    }
+
+    /// A manager whose session shows what was typed, unless `lose` drops it
+    fn verifying_manager(temp: &Path, lose: bool) -> (SessionManager, Arc<FakeTmux>) {
+        let mut config = Config::for_test(temp);
+        config.verify_injections = true;
+        let (manager, fake) = fake_manager_with(&config);
+        fake.add_session("jane-doe", "");
+        if lose {
+            fake.drop_typing("jane-doe");
+        }
+        (manager, fake)
+    }
+
+    /// Text typed with `send-keys -l`, one entry per call
+    fn typed(fake: &FakeTmux) -> Vec<String> {
+        fake.calls_to("send-keys").into_iter().filter(|call| call.contains(&"-l".to_string())).filter_map(|call| call.last().cloned()).collect()
+    }
+
+    /// Any oversized injection, not just an incoming message, goes through the inbox
+    #[test]
+    fn test_inject_text_spills_oversized() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = verifying_manager(temp.path(), false);
+
+        let note = format!("[Group update: {}]", "x".repeat(9 * 1024));
+        manager.inject_text("jane-doe", &note).unwrap();
+        let typed = typed(&fake).concat();
+        assert!(typed.starts_with("---LONG MESSAGE (10 KB)---"));
+
+        let inbox: Vec<_> = std::fs::read_dir(manager.transcript_dir("jane-doe").join("inbox")).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(inbox.len(), 1);
+        assert!(typed.contains(&inbox[0].display().to_string()));
+        assert_eq!(std::fs::read_to_string(&inbox[0]).unwrap(), note);
+    }
+
+    #[test]
+    fn test_inject_confirmed() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = verifying_manager(temp.path(), false);
+
+        manager.inject_text("jane-doe", "Are you free Friday?").unwrap();
+        assert_eq!(typed(&fake), ["Are you free Friday?"]);
+        assert!(!fake.calls_to("capture-pane").is_empty());
+    }
+
+    /// A lost injection is retried once, then reported
+    #[test]
+    fn test_inject_not_confirmed() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = verifying_manager(temp.path(), true);
+
+        let result = manager.inject_text("jane-doe", "Are you free Friday?");
+        assert!(matches!(result, Err(Error::InjectionNotConfirmed(ref name)) if name == "jane-doe"));
+        assert_eq!(typed(&fake), ["Are you free Friday?", "Are you free Friday?"]);
+        // Once before sending, then after each try
+        assert_eq!(fake.calls_to("capture-pane").len(), 3);
+    }
+
+    #[test]
+    fn test_kill_mode() {
+        assert_eq!(KillMode::for_unhealthy(&UnhealthyReason::FatalError("panic".into())), KillMode::Now);
+        assert_eq!(KillMode::for_unhealthy(&UnhealthyReason::Stuck), KillMode::Now);
+        assert_eq!(KillMode::for_unhealthy(&UnhealthyReason::ApiErrorsPersistent), KillMode::Graceful);
+
+        let grace = Duration::from_secs(30);
+        assert!(needs_wrap_up(KillMode::Graceful, grace, true));
+        // Idle, so nothing is half-done
+        assert!(!needs_wrap_up(KillMode::Graceful, grace, false));
+        assert!(!needs_wrap_up(KillMode::Now, grace, true));
+        assert!(!needs_wrap_up(KillMode::Graceful, Duration::ZERO, true));
+    }
+
+    /// A manager whose session is busy until something is typed into it
+    fn busy_manager(temp: &Path) -> (SessionManager, Arc<FakeTmux>) {
+        let mut config = Config::for_test(temp);
+        config.kill_grace_secs = 5;
+        let (manager, fake) = fake_manager_with(&config);
+        fake.add_session("jane-doe", "esc to interrupt\n");
+        fake.answer_typing("jane-doe", FAKE_READY_PANE);
+        (manager, fake)
+    }
+
+    /// Index of the first call that has `arg`
+    fn call_with(fake: &FakeTmux, arg: &str) -> Option<usize> {
+        fake.calls().iter().position(|call| call.iter().any(|a| a == arg))
+    }
+
+    #[test]
+    fn test_graceful_kill_asks_busy_claude_to_wrap_up() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = busy_manager(temp.path());
+
+        manager.archive_and_kill("jane-doe", temp.path(), KillMode::Graceful).unwrap();
+        let asked = call_with(&fake, WRAP_UP_MESSAGE).expect("never asked to wrap up");
+        let killed = call_with(&fake, "kill-session").expect("never killed");
+        assert!(asked < killed);
+    }
+
+    #[test]
+    fn test_kill_now_skips_wrap_up() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = busy_manager(temp.path());
+
+        manager.archive_and_kill("jane-doe", temp.path(), KillMode::Now).unwrap();
+        assert_eq!(call_with(&fake, WRAP_UP_MESSAGE), None);
+        assert!(fake.sessions().is_empty());
+    }
+
+    /// A manager whose `session_exists` answers are cached for `cache_ms`
+    fn counting_manager(temp: &Path, cache_ms: u64) -> (SessionManager, Arc<FakeTmux>) {
+        let mut config = Config::for_test(temp);
+        config.session_cache_ms = cache_ms;
+        fake_manager_with(&config)
+    }
+
+    #[test]
+    fn test_session_exists_is_cached() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = counting_manager(temp.path(), 60_000);
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+
+        for _ in 0..3 {
+            assert!(manager.session_exists("jane-doe"));
+            assert!(!manager.session_exists("john-doe"));
+        }
+        assert_eq!(fake.calls_to("has-session").len(), 2);
+
+        for _ in 0..2 {
+            assert_eq!(manager.list_sessions().unwrap(), ["jane-doe"]);
+        }
+        assert_eq!(fake.calls_to("list-sessions").len(), 1);
+        // Answered from the list
+        assert!(!manager.session_exists("pat-smith"));
+        assert_eq!(fake.calls_to("has-session").len(), 2);
+
+        // Never still there after a kill
+        manager.kill_session("jane-doe").unwrap();
+        assert!(!manager.session_exists("jane-doe"));
+        assert_eq!(fake.calls_to("has-session").len(), 3);
+        assert!(manager.list_sessions().unwrap().is_empty());
+        assert_eq!(fake.calls_to("list-sessions").len(), 2);
+    }
+
+    #[test]
+    fn test_session_cache_off() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = counting_manager(temp.path(), 0);
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+
+        for _ in 0..3 {
+            assert!(manager.session_exists("jane-doe"));
+            manager.list_sessions().unwrap();
+        }
+        assert_eq!(fake.calls_to("has-session").len(), 3);
+        assert_eq!(fake.calls_to("list-sessions").len(), 3);
+    }
+
+    #[test]
+    fn test_session_cache_expiry_and_forget() {
+        let ttl = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut cache = SessionCache::default();
+        cache.exists.insert("jane-doe".to_string(), (start, true));
+        cache.listed = Some((start, vec!["john-doe".to_string()]));
+
+        assert_eq!(cache.exists("jane-doe", ttl, start), Some(true));
+        assert_eq!(cache.exists("john-doe", ttl, start), Some(true));
+        assert_eq!(cache.exists("pat-smith", ttl, start), Some(false));
+        assert_eq!(cache.exists("jane-doe", ttl, start + ttl), None);
+        assert_eq!(cache.listed(ttl, start + ttl), None);
+
+        cache.forget("jane-doe");
+        assert_eq!(cache.generation, 1);
+        assert_eq!(cache.exists("jane-doe", ttl, start), None);
+        assert_eq!(cache.listed(ttl, start), None);
+    }
+
+    /// The dir, tools, and prompt reach claude intact through `bash -lc`, whatever they contain
+    #[test]
+    fn test_claude_command_survives_shell() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        let args_file = temp.path().join("args.txt");
+        config.claude = temp.path().join("claude");
+        std::fs::write(
+            &config.claude,
+            format!(
+                "#!/bin/sh\n{{ printf '%s\\0' \"$PWD\"; for arg in \"$@\"; do printf '%s\\0' \"$arg\"; done; }} > '{}'\n",
+                args_file.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&config.claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let manager = SessionManager::new(&config);
+
+        let hostile = [
+            r#"Call her "Mom", never 'Mother'."#,
+            "Never run `rm -rf ~` or $(anything) like that.",
+            "Costs are $5, not ${HOME} or $1; use \\n literally.",
+            "Big text.\nNo jargon!\nNever run shell commands; && || > < * ? ~ # %",
+        ];
+        for (i, text) in hostile.iter().enumerate() {
+            // Apostrophes and spaces, as in a group named "Dave's Crew"
+            let dir = temp.path().join(format!("Dave's Crew {}", i)).join(text.replace('/', "_").replace('\n', " "));
+            std::fs::create_dir_all(&dir).unwrap();
+            let tools = format!("Read,Bash({}:*)", text);
+            let person = contact(Some(text), Some(&tools));
+
+            let cmd = manager.claude_command(&dir, "favorite", Some(&person), None);
+            let status = Command::new("/bin/bash").args(["-c", &cmd]).status().unwrap();
+            assert!(status.success(), "{}", cmd);
+
+            let args = std::fs::read(&args_file).unwrap();
+            let args: Vec<&str> = args
+                .split(|b| *b == 0)
+                .filter(|a| !a.is_empty())
+                .map(|a| std::str::from_utf8(a).unwrap())
+                .collect();
+            let expected =
+                build_claude_command(&config.claude, manager.claude_flags(), &TierConfig::restricted(), Some(&person), None);
+            assert_eq!(Path::new(args[0]), dir.as_path());
+            assert_eq!(args[1..], expected[1..]);
+            assert!(args.contains(&tools.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_new_session_args() {
+        let info = SessionInfo::new("+16175551234", "family", "Jane Doe");
+        assert_eq!(
+            new_session_args("jane-doe", &info, "cd /tmp && claude"),
+            [
+                "new-session",
+                "-d",
+                "-s",
+                "jane-doe",
+                "-e",
+                "CLAUDE_ASSISTANT_CHAT_ID=+16175551234",
+                "-e",
+                "CLAUDE_ASSISTANT_TIER=family",
+                "-e",
+                "CLAUDE_ASSISTANT_CONTACT=Jane Doe",
+                "/bin/bash",
+                "-lc",
+                "cd /tmp && claude",
+            ]
+        );
+    }
+
+    /// Every start, including a restart at a new tier, rewrites session-info.json
+    #[test]
+    fn test_session_info_written_on_start() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, _fake) = fake_manager(temp.path());
+        let dir = temp.path().join("transcripts/jane-doe");
+        let read = || -> SessionInfo { serde_json::from_str(&fs::read_to_string(dir.join(SESSION_INFO_FILE)).unwrap()).unwrap() };
+
+        let before = Utc::now();
+        manager.create_session("jane-doe", &dir, &SessionInfo::new("+16175551234", "family", "Jane Doe"), None).unwrap();
+        let info = read();
+        assert_eq!((info.chat_id.as_str(), info.tier.as_str(), info.contact.as_str()), ("+16175551234", "family", "Jane Doe"));
+        assert!(info.created_at >= before);
+
+        manager
+            .restart_session("jane-doe", &dir, &SessionInfo::new("+16175551234", "favorite", "Jane Doe"), None, None, KillMode::Now)
+            .unwrap();
+        let restarted = read();
+        assert_eq!(restarted.tier, "favorite");
+        assert!(restarted.created_at >= info.created_at);
+    }
+
+    #[test]
+    fn test_pipe_pane_args() {
+        let log = session_log_path(Path::new("/logs/Dave's Crew"), "jane-doe");
+        assert_eq!(log, Path::new("/logs/Dave's Crew/sessions/jane-doe.log"));
+        assert_eq!(
+            pipe_pane_args("jane-doe", &log),
+            ["pipe-pane", "-t", "=jane-doe:", "-o", r#"cat >> "/logs/Dave's Crew/sessions/jane-doe.log""#]
+        );
+    }
+
+    #[test]
+    fn test_title_args() {
+        assert_eq!(
+            title_args("jane-doe", "Jane O'Doe"),
+            [
+                vec!["rename-window", "-t", "=jane-doe:", "Jane O'Doe"],
+                vec!["select-pane", "-t", "=jane-doe:", "-T", "Jane O'Doe"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_session_info_title() {
+        let mut info = SessionInfo::new("+16175551234", "family", "Jane Doe");
+        assert_eq!(info.title(), "Jane Doe");
+        info.group = Some("Book Club".to_string());
+        assert_eq!(info.title(), "Book Club");
+    }
+
+    #[test]
+    fn test_rotate_log() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let log = temp.path().join("jane-doe.log");
+        let rotated = |n: usize| temp.path().join(format!("jane-doe.log.{}", n));
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+
+        // Missing or small enough
+        assert!(!rotate_log(&log, 10, 2).unwrap());
+        fs::write(&log, "0123456789").unwrap();
+        assert!(!rotate_log(&log, 10, 2).unwrap());
+
+        fs::write(&log, "first run!!").unwrap();
+        assert!(rotate_log(&log, 10, 2).unwrap());
+        assert_eq!(read(&log), "");
+        assert_eq!(read(&rotated(1)), "first run!!");
+
+        fs::write(&log, "second run!").unwrap();
+        assert!(rotate_log(&log, 10, 2).unwrap());
+        fs::write(&log, "third run!!").unwrap();
+        assert!(rotate_log(&log, 10, 2).unwrap());
+        assert_eq!(read(&rotated(1)), "third run!!");
+        assert_eq!(read(&rotated(2)), "second run!");
+        // Only `keep` are kept
+        assert!(!rotated(3).exists());
+    }
+
+    /// A session whose output is already piped doesn't get a second pipe
+    #[test]
+    fn test_ensure_output_log() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let (manager, fake) = fake_manager(temp.path());
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+
+        assert!(manager.ensure_output_log("jane-doe").unwrap());
+        assert!(!manager.ensure_output_log("jane-doe").unwrap());
+        assert_eq!(fake.calls_to("pipe-pane").len(), 1);
+        assert!(config.logs_dir.join(SESSION_LOG_DIR).is_dir());
+    }
+
+    #[test]
+    fn test_chunk_text() {
+        assert!(chunk_text("", 4).is_empty());
+        // Exactly one chunk's worth, and one byte over
+        assert_eq!(chunk_text("abcd", 4), ["abcd"]);
+        assert_eq!(chunk_text("abcde", 4), ["abcd", "e"]);
+        assert_eq!(chunk_text("abcdefgh", 4), ["abcd", "efgh"]);
+        // "é" is two bytes and would straddle the limit
+        assert_eq!(chunk_text("abcé", 4), ["abc", "é"]);
+        assert_eq!(chunk_text("ab😀cd", 4), ["ab", "😀", "cd"]);
+        // Wider than a chunk
+        assert_eq!(chunk_text("😀😀", 2), ["😀", "😀"]);
+
+        let long = "héllo wörld ".repeat(2000);
+        let chunks = chunk_text(&long, SEND_CHUNK_BYTES);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= SEND_CHUNK_BYTES));
+        assert_eq!(chunks.concat(), long);
+    }
+
+    /// A long prompt goes in as several `send-keys`, then is submitted once
+    #[test]
+    fn test_send_text_in_chunks() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config { verify_injections: true, max_inject_bytes: SEND_CHUNK_BYTES * 4, ..Config::for_test(temp.path()) };
+        let (manager, fake) = fake_manager_with(&config);
+        fake.add_session("jane-doe", "");
+
+        let text = "x".repeat(SEND_CHUNK_BYTES * 2 + 10);
+        manager.inject_text("jane-doe", &text).unwrap();
+        assert_eq!(typed(&fake).len(), 3);
+        let keys = fake.calls_to("send-keys");
+        let enters: Vec<usize> = keys.iter().enumerate().filter(|(_, call)| call.last().is_some_and(|a| a == "Enter")).map(|(i, _)| i).collect();
+        assert_eq!(enters.len(), 2);
+        let last_chunk = keys.iter().rposition(|call| call.contains(&"-l".to_string())).unwrap();
+        assert!(last_chunk < enters[0]);
+        let pane = fake.pane("jane-doe").unwrap();
+        assert_eq!(pane.lines().map(|line| line.trim_start_matches("> ")).collect::<String>(), text);
+    }
+
+    #[test]
+    fn test_prune_pane_archives() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path();
+        for name in ["20260101-090000.000-jane-doe.txt", "20260102-090000.000-jane-doe-bg.txt", "20260103-090000.000-jane-doe.txt"] {
+            fs::write(dir.join(name), "pane").unwrap();
+        }
+        fs::write(dir.join("notes.md"), "kept").unwrap();
+
+        assert_eq!(prune_pane_archives(dir, 5).unwrap(), 0);
+        assert_eq!(prune_pane_archives(dir, 2).unwrap(), 1);
+        let mut left: Vec<_> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        left.sort();
+        assert_eq!(left, ["20260102-090000.000-jane-doe-bg.txt", "20260103-090000.000-jane-doe.txt", "notes.md"]);
+
+        assert_eq!(prune_pane_archives(dir, 0).unwrap(), 2);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_tmux_command() {
+        let command = tmux_command(Path::new("/usr/bin/tmux"), "claude-assistant");
+        assert_eq!(command.get_program(), "/usr/bin/tmux");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["-L", "claude-assistant"]);
+        assert_eq!(tmux_command(Path::new("/usr/bin/tmux"), "").get_args().count(), 0);
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.tmux_socket_name = "claude-assistant".to_string();
+        let runner = Arc::new(Recorder::default());
+        let manager = SessionManager::with_runner(&config, runner.clone());
+        manager.session_exists("jane-doe");
+        let calls = runner.0.lock().unwrap();
+        assert_eq!(calls[0].0, config.tmux);
+        assert_eq!(calls[0].1, ["-L", "claude-assistant", "has-session", "-t", "=jane-doe"]);
+    }
+
+    /// Records what it's asked to run, and runs nothing
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(PathBuf, Vec<String>)>>);
+
+    impl CommandRunner for Recorder {
+        fn run(&self, program: &Path, args: &[String]) -> std::io::Result<Output> {
+            self.0.lock().unwrap().push((program.to_path_buf(), args.to_vec()));
+            Err(std::io::Error::other("not run"))
+        }
+    }
+
+    #[test]
+    fn test_attach_hint() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.tmux = PathBuf::from("/opt/homebrew/bin/tmux");
+        assert_eq!(SessionManager::new(&config).attach_hint("jane-doe"), "/opt/homebrew/bin/tmux attach -t =jane-doe");
+        config.tmux_socket_name = "claude-assistant".to_string();
+        assert_eq!(
+            SessionManager::new(&config).attach_hint("jane-doe"),
+            "/opt/homebrew/bin/tmux -L claude-assistant attach -t =jane-doe"
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/usr/local/bin/claude"), "/usr/local/bin/claude");
+        assert_eq!(shell_quote("Read,Grep"), "Read,Grep");
+        assert_eq!(shell_quote("Dave's Crew"), "\"Dave's Crew\"");
+        assert_eq!(shell_quote("Bash(ls:*)"), "\"Bash(ls:*)\"");
+        assert_eq!(shell_quote(""), "\"\"");
+    }
+
+    #[test]
+    fn test_double_quote() {
+        assert_eq!(double_quote("plain"), "\"plain\"");
+        assert_eq!(double_quote(r#"say "hi" to $USER `now` \o/"#), r#""say \"hi\" to \$USER \`now\` \\o/""#);
+    }
+
+    #[test]
+    fn test_session_name_for_contact() {
+        assert_eq!(
+            SessionManager::session_name_for_contact("Jane Doe"),
             "jane-doe"
         );
         assert_eq!(
@@ -290,6 +2024,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_session_name_for_email_contact() {
+        assert_eq!(
+            SessionManager::session_name_for_contact("Jane.Doe@iCloud.com"),
+            "jane_doe@icloud_com"
+        );
+    }
+
+    #[test]
+    fn test_spill_threshold() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let fits = "a".repeat(100);
+        assert!(matches!(spill_oversized(&fits, 100, &inbox_path(temp.path(), 7)).unwrap(), Cow::Borrowed(_)));
+        assert!(!inbox_path(temp.path(), 7).exists());
+
+        let over = "a".repeat(101);
+        let injected = spill_oversized(&over, 100, &inbox_path(temp.path(), 7)).unwrap();
+        assert!(injected.len() < over.len() + 400);
+        assert!(injected.contains("---LONG MESSAGE (1 KB)---"));
+        assert!(injected.contains(&inbox_path(temp.path(), 7).display().to_string()));
+        assert_eq!(std::fs::read_to_string(inbox_path(temp.path(), 7)).unwrap(), over);
+    }
+
+    #[test]
+    fn test_spill_file_naming() {
+        let temp = tempfile::TempDir::new().unwrap();
+        assert_eq!(inbox_path(temp.path(), 4242), temp.path().join("inbox/4242.txt"));
+
+        let wall = "word ".repeat(8 * 1024);
+        spill_oversized(&wall, 1024, &inbox_path(temp.path(), 4242)).unwrap();
+        assert!(temp.path().join("inbox/4242.txt").exists());
+    }
+
+    #[test]
+    fn test_spill_preview_respects_utf8() {
+        let temp = tempfile::TempDir::new().unwrap();
+        // 4-byte emoji never line up with the preview cut
+        let wall = format!("x{}", "\u{1F600}".repeat(2000));
+        let injected = spill_oversized(&wall, 1000, &inbox_path(temp.path(), 1)).unwrap();
+        let preview = injected.split("It begins: ").nth(1).unwrap();
+        assert!(preview.starts_with('x'));
+        assert_eq!(std::fs::read_to_string(inbox_path(temp.path(), 1)).unwrap(), wall);
+
+        assert_eq!(truncate_at_char_boundary("h\u{e9}llo", 2), "h");
+        assert_eq!(truncate_at_char_boundary("h\u{e9}llo", 3), "h\u{e9}");
+        assert_eq!(truncate_at_char_boundary("hi", 10), "hi");
+    }
+
     #[test]
     fn test_session_name_for_group() {
         assert_eq!(
@@ -330,7 +2112,7 @@ mod tests {
 
         // Create session
         manager
-            .create_session(test_session, temp_dir.path(), "admin")
+            .create_session(test_session, temp_dir.path(), &SessionInfo::new("+16175550001", "admin", "Test"), None)
             .unwrap();
         assert!(manager.session_exists(test_session));
 
@@ -360,7 +2142,7 @@ mod tests {
         // Setup
         let _ = manager.kill_session(test_session);
         manager
-            .create_session(test_session, temp_dir.path(), "admin")
+            .create_session(test_session, temp_dir.path(), &SessionInfo::new("+16175550001", "admin", "Test"), None)
             .unwrap();
         std::thread::sleep(Duration::from_secs(2));
 
@@ -371,6 +2153,409 @@ mod tests {
         manager.kill_session(test_session).unwrap();
     }
 
+    #[test]
+    #[ignore]
+    fn test_archive_pane() {
+        let config = Config::default();
+        let manager = SessionManager::new(&config);
+        let test_session = "test-archive-session";
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let _ = manager.kill_session(test_session);
+        tmux_command(&config.tmux, &config.tmux_socket_name)
+            .args(["new-session", "-d", "-s", test_session, "seq 1 5000; sleep 30"])
+            .status()
+            .unwrap();
+        std::thread::sleep(Duration::from_secs(1));
+
+        // The whole history, not just what's on screen
+        let path = manager.archive_pane(test_session, temp_dir.path()).unwrap();
+        assert!(path.starts_with(temp_dir.path().join(PANE_ARCHIVE_DIR)));
+        let pane = fs::read_to_string(&path).unwrap();
+        assert!(pane.lines().any(|line| line == "1"));
+        assert!(pane.lines().any(|line| line == "5000"));
+
+        manager.archive_and_kill(test_session, temp_dir.path(), KillMode::Now).unwrap();
+        assert!(!manager.session_exists(test_session));
+        assert_eq!(fs::read_dir(temp_dir.path().join(PANE_ARCHIVE_DIR)).unwrap().count(), 2);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_output_log() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config { logs_dir: temp_dir.path().to_path_buf(), ..Config::default() };
+        let manager = SessionManager::new(&config);
+        let test_session = "test-output-log-session";
+
+        let _ = manager.kill_session(test_session);
+        tmux_command(&config.tmux, &config.tmux_socket_name)
+            .args(["new-session", "-d", "-s", test_session, "sleep 1; echo hello from the pane; sleep 30"])
+            .status()
+            .unwrap();
+
+        assert!(manager.ensure_output_log(test_session).unwrap());
+        // Already piped
+        assert!(!manager.ensure_output_log(test_session).unwrap());
+        std::thread::sleep(Duration::from_secs(2));
+        let log = fs::read_to_string(manager.session_log_path(test_session)).unwrap();
+        assert!(log.contains("hello from the pane"));
+
+        manager.kill_session(test_session).unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn test_set_title() {
+        let config = Config::default();
+        let manager = SessionManager::new(&config);
+        let test_session = "test-title-session";
+
+        let _ = manager.kill_session(test_session);
+        tmux_command(&config.tmux, &config.tmux_socket_name)
+            .args(["new-session", "-d", "-s", test_session, "sleep 30"])
+            .status()
+            .unwrap();
+
+        manager.set_title(test_session, "Jane Doe").unwrap();
+        let output = tmux_command(&config.tmux, &config.tmux_socket_name)
+            .args(["display-message", "-p", "-t", &pane_target(test_session), "#{window_name}|#{pane_title}"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Jane Doe|Jane Doe");
+
+        manager.kill_session(test_session).unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn test_session_usage() {
+        let config = Config::default();
+        let manager = SessionManager::new(&config);
+        let test_session = "test-usage-session";
+
+        let _ = manager.kill_session(test_session);
+        tmux_command(&config.tmux, &config.tmux_socket_name)
+            .args(["new-session", "-d", "-s", test_session, "sleep 30 & sleep 30"])
+            .status()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+
+        assert!(manager.session_pid(test_session).is_some());
+        let usage = manager.session_usage(test_session).unwrap();
+        assert!(usage.rss_mb < 100);
+
+        manager.kill_session(test_session).unwrap();
+        assert_eq!(manager.session_pid(test_session), None);
+        assert_eq!(manager.session_usage(test_session), None);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_is_busy() {
+        let config = Config::default();
+        let manager = SessionManager::new(&config);
+        let test_session = "test-busy-session";
+        let start = |script: &str| {
+            let _ = manager.kill_session(test_session);
+            tmux_command(&config.tmux, &config.tmux_socket_name)
+                .args(["new-session", "-d", "-s", test_session, script])
+                .status()
+                .unwrap();
+            std::thread::sleep(Duration::from_secs(1));
+        };
+
+        start("printf '> hi\\n\\n✻ Pondering… (3s · esc to interrupt)\\n'; sleep 30");
+        assert!(manager.is_busy(test_session));
+
+        start("printf '> hi\\n\\n● Hello!\\n\\n> '; sleep 30");
+        assert!(!manager.is_busy(test_session));
+
+        manager.kill_session(test_session).unwrap();
+        assert!(!manager.is_busy(test_session));
+    }
+
+    #[test]
+    fn test_create_session_times_out_if_never_ready() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::for_test(temp.path());
+        config.session_ready_timeout_secs = 1;
+        let fake = Arc::new(FakeTmux::new());
+        fake.set_startup_pane("Do you trust the files in this folder?\n");
+
+        let start = Instant::now();
+        let manager = SessionManager::with_runner(&config, fake);
+        let result = manager.create_session("jane-doe", &temp.path().join("t"), &SessionInfo::new("+16175551234", "family", "Jane Doe"), None);
+        assert!(matches!(result, Err(Error::Tmux(ref e)) if e.contains("failed to become ready")), "{:?}", result);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_create_session_waits_until_ready() {
+        let config = Config::default();
+        let manager = SessionManager::new(&config);
+        let test_session = "test-ready-session";
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let _ = manager.kill_session(test_session);
+        manager.create_session(test_session, temp_dir.path(), &SessionInfo::new("+16175550001", "admin", "Test"), None).unwrap();
+        assert!(is_ready_content(&manager.capture_pane(test_session, 50).unwrap()));
+
+        manager.kill_session(test_session).unwrap();
+    }
+
+    // The integration tests above, against FakeTmux
+
+    fn fake_manager(temp: &Path) -> (SessionManager, Arc<FakeTmux>) {
+        fake_manager_with(&Config::for_test(temp))
+    }
+
+    /// A manager for `config` on a fresh `FakeTmux`
+    fn fake_manager_with(config: &Config) -> (SessionManager, Arc<FakeTmux>) {
+        let fake = Arc::new(FakeTmux::new());
+        (SessionManager::with_runner(config, fake.clone()), fake)
+    }
+
+    #[test]
+    fn test_fake_session_lifecycle() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        let info = SessionInfo::new("+16175550001", "admin", "Test");
+
+        manager.create_session("test-rust-session", temp.path(), &info, None).unwrap();
+        assert!(manager.session_exists("test-rust-session"));
+        assert_eq!(manager.list_sessions().unwrap(), ["test-rust-session"]);
+        assert_eq!(manager.capture_pane("test-rust-session", 10).unwrap(), FAKE_READY_PANE);
+        let created = &fake.calls_to("new-session")[0];
+        assert!(created.contains(&"CLAUDE_ASSISTANT_CHAT_ID=+16175550001".to_string()));
+        assert!(created.last().unwrap().ends_with("claude --dangerously-skip-permissions"));
+        assert_eq!(fake.title("test-rust-session").as_deref(), Some("Test"));
+        assert_eq!(fake.calls_to("pipe-pane").len(), 1);
+
+        manager.kill_session("test-rust-session").unwrap();
+        assert!(!manager.session_exists("test-rust-session"));
+        assert!(manager.list_sessions().unwrap().is_empty());
+        // Already gone is fine
+        manager.kill_session("test-rust-session").unwrap();
+    }
+
+    #[test]
+    fn test_is_no_server() {
+        assert!(is_no_server("no server running on /tmp/tmux-501/default\n"));
+        assert!(is_no_server("error connecting to /private/tmp/tmux-501/default (No such file or directory)\n"));
+        assert!(!is_no_server("can't find session: jane-doe\n"));
+        assert!(!is_no_server("server exited unexpectedly\n"));
+    }
+
+    /// A session missing on its own is restarted on its own; all of them missing with the server is one problem
+    #[test]
+    fn test_fake_no_server() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+        assert_eq!(manager.check_health("john-doe"), HealthStatus::Unhealthy(UnhealthyReason::SessionMissing));
+        assert!(manager.server_running().unwrap());
+
+        // The last session going takes the server with it
+        fake.end_session("jane-doe");
+        // A new manager, so nothing is answered from its cache
+        let manager = SessionManager::with_runner(&Config::for_test(temp.path()), fake.clone());
+        assert!(!manager.server_running().unwrap());
+        assert_eq!(manager.check_health("jane-doe"), HealthStatus::Unhealthy(UnhealthyReason::NoServer));
+        assert!(manager.list_sessions().unwrap().is_empty());
+
+        // Its socket dir not there yet, just after a reboot
+        fake.fail("list-sessions", "error connecting to /private/tmp/tmux-501/default (No such file or directory)");
+        assert!(!manager.server_running().unwrap());
+        fake.fail("list-sessions", "lost server");
+        assert!(manager.server_running().is_err());
+        assert_eq!(manager.check_health("jane-doe"), HealthStatus::Unhealthy(UnhealthyReason::SessionMissing));
+    }
+
+    #[test]
+    fn test_fake_inject_text() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config { verify_injections: true, ..Config::for_test(temp.path()) };
+        let (manager, fake) = fake_manager_with(&config);
+        fake.add_session("test-inject-session", FAKE_READY_PANE);
+
+        manager.inject_text("test-inject-session", "echo hello").unwrap();
+        let sent: Vec<String> = fake.calls_to("send-keys").iter().map(|call| call[3..].join(" ")).collect();
+        assert_eq!(sent, ["-l -- echo hello", "Enter", "Enter"]);
+        assert!(fake.pane("test-inject-session").unwrap().starts_with("> echo hello\n"));
+
+        assert!(matches!(manager.inject_text("gone", "hi"), Err(Error::SessionNotFound(_))));
+        fake.fail("send-keys", "server exited");
+        let failed = manager.inject_text("test-inject-session", "again");
+        assert!(matches!(failed, Err(Error::Tmux(ref e)) if e.contains("server exited")), "{:?}", failed);
+    }
+
+    #[test]
+    fn test_fake_archive_pane() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        let history: String = (1..=5000).map(|n| format!("{}\n", n)).collect();
+        fake.add_session("test-archive-session", &history);
+
+        let path = manager.archive_pane("test-archive-session", temp.path()).unwrap();
+        assert!(path.starts_with(temp.path().join(PANE_ARCHIVE_DIR)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), history);
+        // The whole history, not just what's on screen
+        assert_eq!(fake.calls_to("capture-pane")[0][3..], ["-p", "-S", "-"]);
+
+        // Archives are named to the millisecond
+        std::thread::sleep(Duration::from_millis(5));
+        manager.archive_and_kill("test-archive-session", temp.path(), KillMode::Now).unwrap();
+        assert!(fake.sessions().is_empty());
+        assert_eq!(fs::read_dir(temp.path().join(PANE_ARCHIVE_DIR)).unwrap().count(), 2);
+        assert!(matches!(manager.archive_pane("test-archive-session", temp.path()), Err(Error::SessionNotFound(_))));
+    }
+
+    #[test]
+    fn test_fake_output_log() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        fake.add_session("test-output-log-session", "");
+
+        assert!(manager.ensure_output_log("test-output-log-session").unwrap());
+        // Already piped
+        assert!(!manager.ensure_output_log("test-output-log-session").unwrap());
+        let piped = fake.calls_to("pipe-pane");
+        assert_eq!(piped.len(), 1);
+        assert!(piped[0][4].contains(&manager.session_log_path("test-output-log-session").display().to_string()));
+        assert!(manager.ensure_output_log("gone").is_err());
+    }
+
+    #[test]
+    fn test_fake_set_title() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        fake.add_session("test-title-session", "");
+
+        manager.set_title("test-title-session", "Jane Doe").unwrap();
+        assert_eq!(fake.title("test-title-session").as_deref(), Some("Jane Doe"));
+        assert_eq!(fake.calls_to("rename-window")[0], title_args("test-title-session", "Jane Doe")[0]);
+    }
+
+    #[test]
+    fn test_fake_is_busy() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+
+        fake.add_session("test-busy-session", "> hi\n\n✻ Pondering… (3s · esc to interrupt)\n");
+        assert!(manager.is_busy("test-busy-session"));
+        fake.set_pane("test-busy-session", "> hi\n\n● Hello!\n\n> ");
+        assert!(!manager.is_busy("test-busy-session"));
+        fake.end_session("test-busy-session");
+        assert!(!manager.is_busy("test-busy-session"));
+    }
+
+    #[test]
+    fn test_fake_create_session_waits_until_ready() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        fake.set_startup_pane("Loading…");
+        let ready_later = {
+            let fake = Arc::clone(&fake);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(500));
+                fake.set_pane("test-ready-session", FAKE_READY_PANE);
+            })
+        };
+
+        let start = Instant::now();
+        manager
+            .create_session("test-ready-session", temp.path(), &SessionInfo::new("+16175550001", "admin", "Test"), None)
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        assert!(is_ready_content(&manager.capture_pane("test-ready-session", 50).unwrap()));
+        ready_later.join().unwrap();
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let fake = Arc::new(FakeTmux::new());
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let config = Config { tmux_socket_name: "claude-assistant".to_string(), ..Config::for_test(temp.path()) };
+        let manager = SessionManager::with_runner(&config, fake.clone()).dry_run_into(out.clone());
+        let transcript_dir = temp.path().join("jane-doe");
+        fake.add_session("john-doe", FAKE_READY_PANE);
+
+        let info = SessionInfo::new("+16175551234", "admin", "Jane Doe");
+        manager.create_session("jane-doe", &transcript_dir, &info, None).unwrap();
+        manager.inject_message("jane-doe", "hello\nthere", &transcript_dir, 7).unwrap();
+        manager.archive_and_kill("john-doe", &transcript_dir, KillMode::Graceful).unwrap();
+        // Already running, so there's nothing to create
+        manager.create_session("john-doe", &transcript_dir, &info, None).unwrap();
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "Would create session jane-doe with:\n/opt/homebrew/bin/tmux -L claude-assistant new-session -d -s jane-doe \
+                 -e CLAUDE_ASSISTANT_CHAT_ID=+16175551234 -e CLAUDE_ASSISTANT_TIER=admin -e \"CLAUDE_ASSISTANT_CONTACT=Jane Doe\" \
+                 /bin/bash -lc \"cd {} && /usr/local/bin/claude --dangerously-skip-permissions\"\n\
+                 Would inject into jane-doe:\nhello\nthere\n\
+                 Would archive and kill session john-doe\n",
+                transcript_dir.display()
+            )
+        );
+        assert!(fake.calls().iter().all(|call| call[0] == "has-session"));
+        assert_eq!(fake.sessions(), ["john-doe"]);
+        assert!(!transcript_dir.exists());
+    }
+
+    /// A crashed session is restarted on its conversation, or fresh if that comes up broken too
+    #[test]
+    fn test_fake_restart_session() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (manager, fake) = fake_manager(temp.path());
+        fake.add_session("jane-doe", "Claude session crashed");
+        let info = SessionInfo::new("+16175551234", "family", "Jane Doe");
+
+        let resumed = manager
+            .restart_session("jane-doe", temp.path(), &info, None, Some("conv-1"), KillMode::Now)
+            .unwrap();
+        assert!(resumed);
+        assert_eq!(fake.sessions(), ["jane-doe"]);
+        assert!(fake.calls_to("new-session")[0].last().unwrap().ends_with("--resume conv-1"));
+        assert_eq!(fs::read_dir(temp.path().join(PANE_ARCHIVE_DIR)).unwrap().count(), 1);
+
+        // Resuming comes up broken, so it starts over
+        fake.set_startup_pane(&format!("{}Claude session crashed\n$ ", FAKE_READY_PANE));
+        let resumed = manager
+            .restart_session("jane-doe", temp.path(), &info, None, Some("conv-1"), KillMode::Now)
+            .unwrap();
+        assert!(!resumed);
+        let created = fake.calls_to("new-session");
+        assert_eq!(created.len(), 3);
+        assert!(!created[2].last().unwrap().contains("--resume"));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_resume_real_conversation() {
+        let config = Config::default();
+        let manager = SessionManager::new(&config);
+        let test_session = "test-resume-session";
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let _ = manager.kill_session(test_session);
+        manager.create_session(test_session, temp_dir.path(), &SessionInfo::new("+16175550001", "admin", "Test"), None).unwrap();
+        manager.inject_text(test_session, "Remember the word pineapple.").unwrap();
+        std::thread::sleep(Duration::from_secs(20));
+
+        let conversation = latest_conversation(temp_dir.path()).expect("no conversation saved");
+        let resumed = manager
+            .restart_session(test_session, temp_dir.path(), &SessionInfo::new("+16175550001", "admin", "Test"), None, Some(&conversation), KillMode::Now)
+            .unwrap();
+        assert!(resumed);
+
+        manager.kill_session(test_session).unwrap();
+    }
+
     #[test]
     fn test_kill_nonexistent_session() {
         let config = Config::default();
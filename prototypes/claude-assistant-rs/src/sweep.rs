@@ -0,0 +1,146 @@
+//! Health sweeps off the poll loop
+//!
+//! Checking a session takes a few tmux calls, and checking a dozen of them one
+//! after another held up polling for messages; a single tmux call that hung,
+//! as happens just after the machine wakes, held it up until tmux came back.
+//! Sessions are now checked on a few threads of their own, and the loop takes
+//! in what they found as it arrives, acting on one session at a time.
+
+use crate::health::HealthStatus;
+use crate::process::ProcessUsage;
+use crate::session::SessionManager;
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tracing::error;
+
+/// Sessions checked at once
+pub const SWEEP_THREADS: usize = 4;
+
+/// Lines of a session's pane a check looks at
+const PROBE_LINES: u32 = 30;
+
+/// What checking a session found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub status: HealthStatus,
+    /// The bottom of its pane, empty if it couldn't be captured
+    pub pane: String,
+    /// Memory and CPU of its processes, if asked for and it's otherwise healthy
+    pub usage: Option<ProcessUsage>,
+    /// When it started, if it's healthy
+    pub started: Option<SystemTime>,
+}
+
+/// Check a session's health, its pane, and if `check_usage` what it's using
+pub fn probe(session_mgr: &SessionManager, session_name: &str, check_usage: bool) -> Probe {
+    let status = session_mgr.check_health(session_name);
+    let healthy = status == HealthStatus::Healthy;
+    Probe {
+        pane: session_mgr.capture_pane(session_name, PROBE_LINES).unwrap_or_default(),
+        usage: if healthy && check_usage { session_mgr.session_usage(session_name) } else { None },
+        started: if healthy { session_mgr.session_started(session_name) } else { None },
+        status,
+    }
+}
+
+/// Check `sessions` on up to `SWEEP_THREADS` threads, sending each finding as soon as it's in
+///
+/// A check that never finishes only holds up its own thread; the others
+/// carry on through the rest. The threads stop once every session is
+/// checked, or early if the receiver is dropped.
+pub fn spawn_sweep(session_mgr: Arc<SessionManager>, sessions: Vec<String>, check_usage: bool) -> Receiver<(String, Probe)> {
+    let (sender, receiver) = mpsc::channel();
+    let threads = sessions.len().min(SWEEP_THREADS);
+    let todo = Arc::new(Mutex::new(VecDeque::from(sessions)));
+    for n in 0..threads {
+        let (session_mgr, todo, sender) = (Arc::clone(&session_mgr), Arc::clone(&todo), sender.clone());
+        let spawned = std::thread::Builder::new().name(format!("health-{}", n)).spawn(move || loop {
+            let Some(session_name) = todo.lock().unwrap_or_else(|e| e.into_inner()).pop_front() else {
+                return;
+            };
+            match catch_unwind(AssertUnwindSafe(|| probe(&session_mgr, &session_name, check_usage))) {
+                Ok(found) => {
+                    if sender.send((session_name, found)).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => error!("Health check of {} panicked", session_name),
+            }
+        });
+        if let Err(e) = spawned {
+            error!("Failed to start a health check thread: {}", e);
+        }
+    }
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::health::UnhealthyReason;
+    use crate::runner::{FakeTmux, FAKE_READY_PANE};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_probe() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let fake = Arc::new(FakeTmux::new());
+        fake.add_session("jane-doe", FAKE_READY_PANE);
+        fake.add_session("john-doe", "Claude session crashed\n$ ");
+        let session_mgr = SessionManager::with_runner(&config, fake.clone());
+
+        let jane = probe(&session_mgr, "jane-doe", false);
+        assert_eq!((jane.status, jane.pane.as_str()), (HealthStatus::Healthy, FAKE_READY_PANE));
+        assert!(jane.started.is_some());
+        let john = probe(&session_mgr, "john-doe", true);
+        assert!(matches!(john.status, HealthStatus::Unhealthy(_)));
+        assert_eq!((john.usage, john.started), (None, None));
+        let gone = probe(&session_mgr, "jim-doe", false);
+        assert_eq!(gone.status, HealthStatus::Unhealthy(UnhealthyReason::SessionMissing));
+        assert_eq!(gone.pane, "");
+    }
+
+    /// One session's check hanging holds up only that session
+    #[test]
+    fn test_sweep_carries_on_past_blocked_check() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::for_test(temp.path());
+        let fake = Arc::new(FakeTmux::new());
+        let sessions: Vec<String> = ["al-admin", "bo-brown", "cy-chan", "di-diaz", "ed-evans", "fay-fox"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        for session in &sessions {
+            fake.add_session(session, FAKE_READY_PANE);
+        }
+        let release = fake.hang("al-admin");
+        let session_mgr = Arc::new(SessionManager::with_runner(&config, fake.clone()));
+
+        let start = Instant::now();
+        let results = spawn_sweep(session_mgr, sessions, false);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        let mut checked: Vec<String> = (0..5).map(|_| results.recv_timeout(Duration::from_secs(5)).unwrap().0).collect();
+        checked.sort();
+        assert_eq!(checked, ["bo-brown", "cy-chan", "di-diaz", "ed-evans", "fay-fox"]);
+        assert!(results.recv_timeout(Duration::from_millis(100)).is_err());
+
+        // Comes in once tmux answers
+        release.send(()).unwrap();
+        let (session, found) = results.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!((session.as_str(), found.status), ("al-admin", HealthStatus::Healthy));
+        // Then the threads are done
+        assert!(matches!(results.recv_timeout(Duration::from_secs(5)), Err(mpsc::RecvTimeoutError::Disconnected)));
+    }
+
+    #[test]
+    fn test_empty_sweep() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let session_mgr = Arc::new(SessionManager::with_runner(&Config::for_test(temp.path()), Arc::new(FakeTmux::new())));
+        assert!(spawn_sweep(session_mgr, Vec::new(), true).recv().is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! One worker thread per active session
+//!
+//! Starting a session takes seconds, and the poll loop used to do it inline,
+//! holding up every other chat. Work for a session now runs on that session's
+//! own thread, in the order it was submitted, while other sessions' work runs
+//! alongside it. Idle workers are retired by the loop, and `drain` finishes
+//! everything before shutdown.
+
+use crate::config::Config;
+use crate::error::Result;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+/// A unit of work for one session
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A worker with nothing to do for this long is retired
+const WORKER_IDLE: Duration = Duration::from_secs(600);
+
+struct Worker {
+    sender: Sender<Job>,
+    handle: JoinHandle<()>,
+    /// Jobs submitted and not yet finished
+    pending: Arc<AtomicUsize>,
+    last_submit: Instant,
+}
+
+/// Per-session job queues, each run by its own thread
+pub struct SessionWorkers {
+    workers: HashMap<String, Worker>,
+    /// Run jobs on the caller's thread instead, as the loop did before workers
+    inline: bool,
+}
+
+impl SessionWorkers {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            workers: HashMap::new(),
+            inline: !config.parallel_sessions,
+        }
+    }
+
+    /// Queue a job behind any already submitted for the same session
+    ///
+    /// Fails, dropping the job, if the session has no worker and one can't be started.
+    pub fn submit(&mut self, session_name: &str, job: Job) -> Result<()> {
+        if self.inline {
+            run(job);
+            return Ok(());
+        }
+        let worker = match self.workers.entry(session_name.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(spawn(session_name)?),
+        };
+        worker.pending.fetch_add(1, Ordering::SeqCst);
+        worker.last_submit = Instant::now();
+        // Jobs can't take their thread down with them, so the receiver outlives the sender
+        if worker.sender.send(job).is_err() {
+            worker.pending.fetch_sub(1, Ordering::SeqCst);
+            error!("Worker for {} is gone, dropping a job", session_name);
+        }
+        Ok(())
+    }
+
+    /// Whether a session has jobs queued or running
+    pub fn is_busy(&self, session_name: &str) -> bool {
+        self.workers
+            .get(session_name)
+            .is_some_and(|w| w.pending.load(Ordering::SeqCst) > 0)
+    }
+
+    /// Workers with threads running
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Stop the threads of sessions that have had nothing to do for a while
+    pub fn retire_idle(&mut self, now: Instant) {
+        self.retire_idle_after(now, WORKER_IDLE);
+    }
+
+    fn retire_idle_after(&mut self, now: Instant, idle: Duration) {
+        let idle: Vec<String> = self
+            .workers
+            .iter()
+            .filter(|(_, w)| w.pending.load(Ordering::SeqCst) == 0)
+            .filter(|(_, w)| now.saturating_duration_since(w.last_submit) >= idle)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in idle {
+            if let Some(worker) = self.workers.remove(&name) {
+                stop(worker);
+            }
+        }
+    }
+
+    /// Finish every submitted job and stop all the threads
+    pub fn drain(&mut self) {
+        for (_, worker) in self.workers.drain() {
+            stop(worker);
+        }
+    }
+}
+
+impl Drop for SessionWorkers {
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
+
+fn spawn(session_name: &str) -> Result<Worker> {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let pending = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&pending);
+    let handle = std::thread::Builder::new()
+        .name(format!("session-{}", session_name))
+        .spawn(move || {
+            // Ends once the sender is dropped and the queue is empty
+            for job in receiver {
+                run(job);
+                counter.fetch_sub(1, Ordering::SeqCst);
+            }
+        })?;
+    Ok(Worker {
+        sender,
+        handle,
+        pending,
+        last_submit: Instant::now(),
+    })
+}
+
+/// Run a job, containing any panic so one bad job doesn't stop the session's queue
+fn run(job: Job) {
+    if catch_unwind(AssertUnwindSafe(job)).is_err() {
+        error!("A session job panicked");
+    }
+}
+
+/// Let a worker finish its queue, then wait for its thread
+fn stop(worker: Worker) {
+    drop(worker.sender);
+    if worker.handle.join().is_err() {
+        error!("A session worker thread panicked");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+    use std::sync::Mutex;
+
+    fn parallel() -> SessionWorkers {
+        let mut config = Config::for_test(&std::env::temp_dir());
+        config.parallel_sessions = true;
+        SessionWorkers::new(&config)
+    }
+
+    /// A job that notes its start and end
+    fn job(log: &Arc<Mutex<Vec<String>>>, name: &str) -> Job {
+        let log = Arc::clone(log);
+        let name = name.to_string();
+        Box::new(move || {
+            log.lock().unwrap().push(format!("start {}", name));
+            log.lock().unwrap().push(format!("end {}", name));
+        })
+    }
+
+    /// Signals from a gated job, and the way to let it finish
+    struct Gate {
+        started: Receiver<()>,
+        release: Sender<()>,
+    }
+
+    /// A job that notes its start and end, and in between waits to be let go
+    fn gated(log: &Arc<Mutex<Vec<String>>>, name: &str) -> (Job, Gate) {
+        let (log, name) = (Arc::clone(log), name.to_string());
+        let (started_tx, started) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            log.lock().unwrap().push(format!("start {}", name));
+            started_tx.send(()).unwrap();
+            released.recv().unwrap();
+            log.lock().unwrap().push(format!("end {}", name));
+        });
+        (job, Gate { started, release })
+    }
+
+    /// Wait for a session's queue to run dry
+    fn wait_idle(workers: &SessionWorkers, session_name: &str) {
+        while workers.is_busy(session_name) {
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_jobs_for_a_session_run_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut workers = parallel();
+
+        let (create, gate) = gated(&log, "create");
+        workers.submit("jane-doe", create).unwrap();
+        workers.submit("jane-doe", job(&log, "first")).unwrap();
+        workers.submit("jane-doe", job(&log, "second")).unwrap();
+        gate.started.recv().unwrap();
+        assert!(workers.is_busy("jane-doe"));
+        assert_eq!(*log.lock().unwrap(), ["start create"]);
+        gate.release.send(()).unwrap();
+        workers.drain();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["start create", "end create", "start first", "end first", "start second", "end second"]
+        );
+        assert!(workers.is_empty());
+    }
+
+    /// A slow session doesn't hold up another
+    #[test]
+    fn test_sessions_run_in_parallel() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut workers = parallel();
+
+        let (slow, gate) = gated(&log, "slow");
+        workers.submit("jane-doe", slow).unwrap();
+        gate.started.recv().unwrap();
+        workers.submit("john-doe", job(&log, "quick")).unwrap();
+        wait_idle(&workers, "john-doe");
+        assert!(workers.is_busy("jane-doe"));
+        gate.release.send(()).unwrap();
+        workers.drain();
+
+        assert_eq!(*log.lock().unwrap(), ["start slow", "start quick", "end quick", "end slow"]);
+    }
+
+    #[test]
+    fn test_panicking_job_keeps_the_queue_going() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut workers = parallel();
+
+        workers.submit("jane-doe", Box::new(|| panic!("boom"))).unwrap();
+        workers.submit("jane-doe", job(&log, "after")).unwrap();
+        workers.drain();
+        assert_eq!(*log.lock().unwrap(), ["start after", "end after"]);
+    }
+
+    #[test]
+    fn test_retire_idle() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut workers = parallel();
+
+        workers.submit("jane-doe", job(&log, "quick")).unwrap();
+        let (slow, gate) = gated(&log, "slow");
+        workers.submit("john-doe", slow).unwrap();
+        gate.started.recv().unwrap();
+        wait_idle(&workers, "jane-doe");
+        // john-doe is still working, so only jane-doe goes
+        workers.retire_idle_after(Instant::now(), Duration::ZERO);
+        assert_eq!(workers.len(), 1);
+        assert!(workers.is_busy("john-doe"));
+
+        // A retired session gets a new worker when there's more to do
+        workers.submit("jane-doe", job(&log, "again")).unwrap();
+        gate.release.send(()).unwrap();
+        workers.drain();
+        assert!(log.lock().unwrap().contains(&"end again".to_string()));
+    }
+
+    /// Without parallel sessions, jobs run before `submit` returns
+    #[test]
+    fn test_inline() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut workers = SessionWorkers::new(&Config::for_test(&std::env::temp_dir()));
+
+        workers.submit("jane-doe", job(&log, "now")).unwrap();
+        assert_eq!(*log.lock().unwrap(), ["start now", "end now"]);
+        assert!(workers.is_empty());
+    }
+}
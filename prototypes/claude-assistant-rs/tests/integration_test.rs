@@ -58,17 +58,17 @@ fn test_registry_workflow() {
 #[test]
 fn test_phone_normalization_comprehensive() {
     // Standard formats
-    assert_eq!(normalize_phone("+16175551234"), "+16175551234");
-    assert_eq!(normalize_phone("6175551234"), "+16175551234");
-    assert_eq!(normalize_phone("16175551234"), "+16175551234");
+    assert_eq!(normalize_phone("+16175551234", "US"), "+16175551234");
+    assert_eq!(normalize_phone("6175551234", "US"), "+16175551234");
+    assert_eq!(normalize_phone("16175551234", "US"), "+16175551234");
 
     // With formatting
-    assert_eq!(normalize_phone("(617) 555-1234"), "+16175551234");
-    assert_eq!(normalize_phone("617.555.1234"), "+16175551234");
-    assert_eq!(normalize_phone("+1 (617) 555-1234"), "+16175551234");
+    assert_eq!(normalize_phone("(617) 555-1234", "US"), "+16175551234");
+    assert_eq!(normalize_phone("617.555.1234", "US"), "+16175551234");
+    assert_eq!(normalize_phone("+1 (617) 555-1234", "US"), "+16175551234");
 
     // International
-    assert_eq!(normalize_phone("+447911123456"), "+447911123456");
+    assert_eq!(normalize_phone("+447911123456", "US"), "+447911123456");
 }
 
 /// Test health check patterns
@@ -135,6 +135,49 @@ More notes here.
     assert_eq!(reminders[2].prompt, "Monthly report due");
 }
 
+/// Reminders in a contact's notes are registered from the contacts source and fire
+#[test]
+fn test_reminders_from_contact_notes() {
+    use chrono::{Duration, Utc};
+    use claude_assistant_rs::contacts::{fake_contacts_cli, ContactSource, ContactsManager};
+
+    let temp = TempDir::new().unwrap();
+    let config = Config::for_test(temp.path());
+    let contacts_json = fake_contacts_cli(
+        &config,
+        r#"[
+            {"name": "John Doe", "phone": "617-555-1234", "tier": "admin",
+             "notes": "Prefers texts\nREMINDER: * * * * * | Take your meds"},
+            {"name": "Stranger", "phone": "617-555-0000", "tier": "unknown",
+             "notes": "REMINDER: * * * * * | Never sent"}
+        ]"#,
+    );
+
+    let mut contacts = ContactsManager::new(&config);
+    let registry = SessionRegistry::new(&config);
+    let mut manager = ReminderManager::new();
+
+    let now = Utc::now();
+    let blessed = contacts.list_blessed().unwrap();
+    assert_eq!(manager.sync_contacts(&blessed, &Default::default(), &registry, now), 1);
+
+    let due = manager.check_due(now + Duration::minutes(1));
+    assert_eq!(due, vec![("+16175551234".to_string(), "Take your meds".to_string())]);
+
+    // Edited notes take effect on the next refresh
+    std::fs::write(
+        &contacts_json,
+        r#"[{"name": "John Doe", "phone": "617-555-1234", "tier": "admin",
+             "notes": "REMINDER: * * * * * | Drink water"}]"#,
+    )
+    .unwrap();
+    contacts.refresh().unwrap();
+    let blessed = contacts.list_blessed().unwrap();
+    manager.sync_contacts(&blessed, &Default::default(), &registry, now + Duration::minutes(1));
+    let due = manager.check_due(now + Duration::minutes(2));
+    assert_eq!(due, vec![("+16175551234".to_string(), "Drink water".to_string())]);
+}
+
 /// Test session name generation
 #[test]
 fn test_session_name_generation() {
@@ -245,16 +288,63 @@ fn test_config_paths() {
 /// Test blessed tier checking
 #[test]
 fn test_blessed_tiers() {
-    use claude_assistant_rs::contacts::ContactsManager;
+    use claude_assistant_rs::contacts::{ContactSource, ContactsManager};
 
-    assert!(ContactsManager::is_blessed_tier("admin"));
-    assert!(ContactsManager::is_blessed_tier("wife"));
-    assert!(ContactsManager::is_blessed_tier("family"));
-    assert!(ContactsManager::is_blessed_tier("favorite"));
+    let temp = TempDir::new().unwrap();
+    let contacts = ContactsManager::new(&Config::for_test(temp.path()));
 
-    assert!(!ContactsManager::is_blessed_tier("unknown"));
-    assert!(!ContactsManager::is_blessed_tier(""));
-    assert!(!ContactsManager::is_blessed_tier("ADMIN")); // case-sensitive
+    assert!(contacts.is_blessed_tier("admin"));
+    assert!(contacts.is_blessed_tier("wife"));
+    assert!(contacts.is_blessed_tier("family"));
+    assert!(contacts.is_blessed_tier("favorite"));
+
+    assert!(!contacts.is_blessed_tier("unknown"));
+    assert!(!contacts.is_blessed_tier(""));
+    assert!(!contacts.is_blessed_tier("ADMIN")); // case-sensitive
+}
+
+/// A tier defined in the tiers file is blessed and shapes the session's claude command
+#[test]
+fn test_custom_tier_end_to_end() {
+    use claude_assistant_rs::contacts::{fake_contacts_cli, ContactSource, ContactsManager};
+
+    let temp = TempDir::new().unwrap();
+    let mut config = Config::for_test(temp.path());
+    std::fs::create_dir_all(temp.path().join("config")).unwrap();
+    std::fs::write(
+        &config.tiers_file,
+        r#"[
+            {"name": "admin", "skip_permissions": true},
+            {"name": "coworker", "allowed_tools": "Read,WebSearch",
+             "system_prompt": "You are chatting with a coworker. Keep it professional."}
+        ]"#,
+    )
+    .unwrap();
+    config.load_tiers().unwrap();
+
+    fake_contacts_cli(
+        &config,
+        r#"[{"name": "Pat Lee", "phone": "617-555-1234", "tier": "coworker"},
+            {"name": "Aunt May", "phone": "617-555-9876", "tier": "family"}]"#,
+    );
+
+    let mut contacts = ContactsManager::new(&config);
+    let pat = contacts.lookup_identifier("+16175551234").unwrap().unwrap();
+    assert!(contacts.is_blessed_tier(&pat.tier));
+    // No longer configured, so no longer blessed
+    let may = contacts.lookup_identifier("+16175559876").unwrap().unwrap();
+    assert!(!contacts.is_blessed_tier(&may.tier));
+
+    let transcript_dir = temp.path().join("transcripts/pat-lee");
+    let cmd = SessionManager::new(&config).claude_command(&transcript_dir, &pat.tier, None, None);
+    assert_eq!(
+        cmd,
+        format!(
+            "cd {} && /usr/local/bin/claude --allowedTools Read,WebSearch \
+             --append-system-prompt \"You are chatting with a coworker. Keep it professional.\"",
+            transcript_dir.display()
+        )
+    );
 }
 
 /// Test registry group session handling